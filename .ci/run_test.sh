@@ -10,15 +10,21 @@ if cargo fmt --version >/dev/null 2>&1; then
 fi
 
 if cargo clippy --version >/dev/null 2>&1; then
-    cargo clippy --all --all-targets
+    cargo clippy --all --all-targets -- -D warnings
 
-    cargo clippy -p tsukuyomi --all-features --all-targets
-    cargo clippy -p tsukuyomi-session --all-features --all-targets
+    cargo clippy -p tsukuyomi --all-features --all-targets -- -D warnings
+    cargo clippy -p tsukuyomi-session --all-features --all-targets -- -D warnings
 fi
 
 cargo test --all
 
-cargo test -p tsukuyomi --all-features
+# The optional features outside of `full` depend on crates requiring a newer compiler
+# than the minimum supported Rust version.
+if [[ "${RUST_TOOLCHAIN:-}" == "1.31.0" ]]; then
+    cargo test -p tsukuyomi --features full
+else
+    cargo test -p tsukuyomi --all-features
+fi
 cargo test -p tsukuyomi --no-default-features
 
 cargo test -p tsukuyomi-session --all-features
@@ -7,11 +7,15 @@ use {
     tsukuyomi_server::Server,
 };
 
-fn main() -> tsukuyomi_server::Result<()> {
-    let app = App::create(
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(
         path!("/") //
             .to(endpoint::reply("Hello, world!\n")),
-    )?;
+    )
+}
+
+fn main() -> tsukuyomi_server::Result<()> {
+    let app = app()?;
 
     let addr: SocketAddr = "127.0.0.1:4000".parse()?;
     println!("Listening on http://{}", addr);
@@ -19,3 +23,14 @@ fn main() -> tsukuyomi_server::Result<()> {
         .bind(addr) //
         .run()
 }
+
+#[cfg(test)]
+mod tests {
+    use tsukuyomi::test::conformance::Conformance;
+
+    #[test]
+    fn conformance() {
+        let app = super::app().unwrap();
+        Conformance::new().run(&app).assert();
+    }
+}
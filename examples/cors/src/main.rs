@@ -2,7 +2,11 @@ use {
     serde::{Deserialize, Serialize},
     tsukuyomi::{
         config::prelude::*, //
-        extractor,
+        extractor::{
+            self,
+            validate::{Validate, ValidationErrors},
+            ExtractorExt,
+        },
         output::IntoResponse,
         App,
     },
@@ -19,7 +23,17 @@ struct UserInfo {
     confirm_password: String,
 }
 
-fn main() -> tsukuyomi_server::Result<()> {
+impl Validate for UserInfo {
+    fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        if self.password != self.confirm_password {
+            errors.add("confirm_password", "not matched to password");
+        }
+        errors.into_result()
+    }
+}
+
+fn app() -> tsukuyomi_server::Result<App> {
     let cors = CORS::builder()
         .allow_origin("http://127.0.0.1:5000")?
         .allow_methods(vec!["GET", "POST"])?
@@ -31,19 +45,30 @@ fn main() -> tsukuyomi_server::Result<()> {
         path!("*").to(cors.clone()), // handle OPTIONS *
         path!("/user/info") //
             .to(endpoint::post() //
-                .extract(extractor::body::json())
-                .call(|info: UserInfo| -> tsukuyomi::Result<_> {
-                    if info.password != info.confirm_password {
-                        return Err(tsukuyomi::error::bad_request(
-                            "the field confirm_password is not matched to password.",
-                        ));
-                    }
-                    Ok(info)
-                },))
+                .extract(extractor::body::json().validate())
+                .call(|info: UserInfo| info))
             .modify(cors), // <-- handle CORS simple/preflight request to `/user/info`
     ])?;
 
-    Server::new(app)
+    Ok(app)
+}
+
+fn main() -> tsukuyomi_server::Result<()> {
+    Server::new(app()?)
         .bind(std::net::SocketAddr::from(([127, 0, 0, 1], 4000)))
         .run()
 }
+
+#[cfg(test)]
+mod tests {
+    use tsukuyomi::test::conformance::Conformance;
+
+    #[test]
+    fn conformance() {
+        let app = super::app().unwrap();
+        Conformance::new()
+            .origin("http://127.0.0.1:5000")
+            .run(&app)
+            .assert();
+    }
+}
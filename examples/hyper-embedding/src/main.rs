@@ -0,0 +1,30 @@
+//! Serving a Tsukuyomi application by hyper directly, without `tsukuyomi-server`.
+
+use {
+    futures::Future,
+    std::net::SocketAddr,
+    tsukuyomi::{
+        config::prelude::*, //
+        App,
+    },
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(
+        path!("/") //
+            .to(endpoint::reply("Hello from hyper!\n")),
+    )
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let app = app().map_err(|e| e.to_string())?;
+
+    let addr: SocketAddr = "127.0.0.1:4000".parse()?;
+    let server = hyper::Server::bind(&addr)
+        .serve(app.into_make_service())
+        .map_err(|e| eprintln!("server error: {}", e));
+
+    println!("Listening on http://{}", addr);
+    hyper::rt::run(server);
+    Ok(())
+}
@@ -15,17 +15,9 @@ fn main() -> tsukuyomi_server::Result<()> {
     // A GraphQL schema.
     let schema = Arc::new(crate::schema::create_schema());
 
-    // Extractor which creates a GraphQL context from the request.
-    let fetch_graphql_context = {
-        let database = Arc::new(RwLock::new(Database::default()));
-        tsukuyomi::extractor::ready(move |_| -> tsukuyomi::Result<_> {
-            Ok((Context {
-                database: database.clone(),
-            },))
-        })
-    };
-
     let app = App::create(chain![
+        // registers the database shared among the handlers.
+        state(RwLock::new(Database::default())),
         // renders the source of GraphiQL.
         path!("/") //
             .to(endpoint::get() //
@@ -34,11 +26,13 @@ fn main() -> tsukuyomi_server::Result<()> {
         path!("/graphql")
             .to(endpoint::allow_only("GET, POST")?
                 .extract(tsukuyomi_juniper::request()) // <-- parses the incoming GraphQL request.
-                .extract(fetch_graphql_context) // <-- fetches a GraphQL context.
-                .call(move |request: GraphQLRequest, context: Context| {
-                    // creates a `Responder` that executes a GraphQL request with the specified schema and context.
-                    request.execute(schema.clone(), context)
-                }))
+                .extract(tsukuyomi::extractor::state()) // <-- fetches the shared database.
+                .call(
+                    move |request: GraphQLRequest, database: Arc<RwLock<Database>>| {
+                        // creates a `Responder` that executes a GraphQL request with the specified schema and context.
+                        request.execute(schema.clone(), Context { database })
+                    }
+                ))
             .modify(capture_errors()) // <-- modifies all errors that this route throws into GraphQL errors.
     ])?;
 
@@ -2,12 +2,18 @@ use {
     std::path::PathBuf,
     tsukuyomi::{
         config::prelude::*, //
+        fs::{NamedFile, SafePath},
         App,
     },
     tsukuyomi_server::Server,
 };
 
-fn main() -> tsukuyomi_server::Result<()> {
+fn app() -> tsukuyomi::app::Result<App> {
+    // the resolver of the paths under the static directory, which rejects the paths
+    // escaping from the directory.
+    let static_dir = SafePath::new(concat!(env!("CARGO_MANIFEST_DIR"), "/static"))
+        .map_err(tsukuyomi::config::Error::custom)?;
+
     App::create(chain![
         // a route that matches the root path.
         path!("/") //
@@ -45,15 +51,28 @@ fn main() -> tsukuyomi_server::Result<()> {
         path!("/static/*path") //
             .to({
                 endpoint::get() //
-                    .call(|path: PathBuf| {
+                    .call_async(move |path: PathBuf| {
                         // returns a `Future` which will return a `Responder`.
-                        tsukuyomi::fs::NamedFile::open(path)
+                        static_dir.join(path).map(NamedFile::open)
                     })
             }),
         // A route that matches any path.
         path!("*") //
             .to(endpoint::reply("default route"))
     ])
-    .map(Server::new)?
-    .run()
+}
+
+fn main() -> tsukuyomi_server::Result<()> {
+    app().map(Server::new)?.run()
+}
+
+#[cfg(test)]
+mod tests {
+    use tsukuyomi::test::conformance::Conformance;
+
+    #[test]
+    fn conformance() {
+        let app = super::app().unwrap();
+        Conformance::new().run(&app).assert();
+    }
 }
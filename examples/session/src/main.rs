@@ -1,9 +1,10 @@
 use {
     std::sync::Arc,
     tsukuyomi::{
-        config::prelude::*, //
+        config::{prelude::*, Scope},
         extractor,
-        output::{html, redirect, IntoResponse},
+        output::{html, redirect},
+        util::Either,
         App,
     },
     tsukuyomi_server::Server,
@@ -14,76 +15,81 @@ use {
     },
 };
 
-#[derive(IntoResponse)]
-enum Either<L, R> {
-    Left(L),
-    Right(R),
-}
-
 fn main() -> tsukuyomi_server::Result<()> {
     let backend = CookieBackend::plain();
     let session = Arc::new(session(backend));
 
-    App::create(chain![
-        path!("/") //
-            .to(endpoint::get() //
-                .extract(session.clone())
-                .call_async(|session: Session<_>| -> tsukuyomi::Result<_> {
-                    let username = session.get::<String>("username")?;
-                    let output = if let Some(username) = username {
-                        Either::Right(html(format!(
-                            "Hello, {}! <br />\n\
-                             <form method=\"post\" action=\"/logout\">\n\
-                             <input type=\"submit\" value=\"Log out\" />\n\
-                             </form>\
-                             ",
-                            username
-                        )))
-                    } else {
-                        Either::Left(redirect::to("/login"))
-                    };
-                    Ok(session.finish(output))
-                })),
-        path!("/login") //
-            .to(chain![
-                endpoint::get() //
+    App::create(|scope: &mut Scope<'_, _, _>| {
+        let index = scope.route_ref("index");
+        let login = scope.route_ref("login");
+        chain![
+            path!("/") //
+                .to(endpoint::get() //
                     .extract(session.clone())
-                    .call(|session: Session<_>| {
-                        let output = if session.contains("username") {
-                            Either::Left(redirect::to("/"))
+                    .call_async(move |session: Session<_>| -> tsukuyomi::Result<_> {
+                        let username = session.get::<String>("username")?;
+                        let output = if let Some(username) = username {
+                            Either::Right(html(format!(
+                                "Hello, {}! <br />\n\
+                                 <form method=\"post\" action=\"/logout\">\n\
+                                 <input type=\"submit\" value=\"Log out\" />\n\
+                                 </form>\
+                                 ",
+                                html::escape(&username)
+                            )))
                         } else {
-                            Either::Right(html(
-                                "login form\n\
-                                 <form method=\"post\">\n\
-                                 <input type=\"text\" name=\"username\">\n\
-                                 <input type=\"submit\">\n\
-                                 </form>",
-                            ))
+                            Either::Left(redirect::to_route(&login, ()))
                         };
-                        session.finish(output)
-                    }),
-                endpoint::post()
-                    .extract(session.clone())
-                    .extract(extractor::body::urlencoded())
-                    .call_async({
-                        #[derive(Debug, serde::Deserialize)]
-                        struct Form {
-                            username: String,
-                        }
-                        |mut session: Session<_>, form: Form| -> tsukuyomi::Result<_> {
-                            session.set("username", form.username)?;
-                            Ok(session.finish(redirect::to("/")))
-                        }
-                    }),
-            ]),
-        path!("/logout") //
-            .to(endpoint::get()
-                .extract(session)
-                .call(|mut session: Session<_>| {
-                    session.remove("username");
-                    session.finish(redirect::to("/"))
-                }))
-    ])
+                        Ok(session.finish(output))
+                    }))
+                .name("index"),
+            path!("/login") //
+                .to(chain![
+                    endpoint::get() //
+                        .extract(session.clone())
+                        .call({
+                            let index = index.clone();
+                            move |session: Session<_>| {
+                                let output = if session.contains("username") {
+                                    Either::Left(redirect::to_route(&index, ()))
+                                } else {
+                                    Either::Right(html(
+                                        "login form\n\
+                                         <form method=\"post\">\n\
+                                         <input type=\"text\" name=\"username\">\n\
+                                         <input type=\"submit\">\n\
+                                         </form>",
+                                    ))
+                                };
+                                session.finish(output)
+                            }
+                        }),
+                    endpoint::post()
+                        .extract(session.clone())
+                        .extract(extractor::body::form())
+                        .call_async({
+                            #[derive(Debug, serde::Deserialize)]
+                            struct Form {
+                                username: String,
+                            }
+                            let index = index.clone();
+                            move |mut session: Session<_>, form: Form| -> tsukuyomi::Result<_> {
+                                session.set("username", form.username)?;
+                                Ok(session.finish(redirect::to_route(&index, ())))
+                            }
+                        }),
+                ])
+                .name("login"),
+            path!("/logout") //
+                .to(endpoint::get()
+                    .extract(session)
+                    .call(move |mut session: Session<_>| {
+                        session.remove("username");
+                        session.finish(redirect::to_route(&index, ()))
+                    }))
+        ]
+        .configure(scope)
+    })
     .map(Server::new)?
     .run()
 }
@@ -39,7 +39,7 @@ mod support_tera {
         tsukuyomi::{
             error::Error,
             future::{Poll, TryFuture},
-            handler::{AllowedMethods, Handler, ModifyHandler},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
             input::Input,
         },
     };
@@ -95,6 +95,10 @@ mod support_tera {
             self.inner.allowed_methods()
         }
 
+        fn metadata(&self) -> &Metadata {
+            self.inner.metadata()
+        }
+
         fn handle(&self) -> Self::Handle {
             WithTeraHandle {
                 inner: self.inner.handle(),
@@ -0,0 +1,36 @@
+//! An example of resumable uploads.
+//!
+//! ```console
+//! $ curl -i -X POST -H 'upload-length: 11' http://127.0.0.1:4000/uploads
+//! $ curl -i -X PATCH -H 'upload-offset: 0' --data-binary 'hello' http://127.0.0.1:4000/uploads/<id>
+//! $ curl -I http://127.0.0.1:4000/uploads/<id>
+//! $ curl -i -X PUT -H 'content-range: bytes 5-10/11' --data-binary ' world' http://127.0.0.1:4000/uploads/<id>
+//! ```
+
+use {
+    std::io::Read,
+    tsukuyomi::{
+        config::prelude::*, //
+        contrib::upload::{Completed, FsStore, Uploads},
+        App,
+    },
+    tsukuyomi_server::Server,
+};
+
+fn main() -> tsukuyomi_server::Result<()> {
+    let store = FsStore::new(std::env::temp_dir().join("example-upload"))?;
+
+    App::create(
+        mount("/uploads").with(
+            Uploads::new(store, |mut upload: Completed| {
+                let mut content = vec![];
+                upload.read_to_end(&mut content)?;
+                println!("completed: id = {}, {} bytes", upload.id(), content.len());
+                Ok(())
+            })
+            .max_size(64 * 1024 * 1024),
+        ),
+    )
+    .map(Server::new)?
+    .run()
+}
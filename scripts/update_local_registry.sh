@@ -8,6 +8,13 @@ echo "[regenerate Cargo.lock...]"
 cd
 (set -x; cargo generate-lockfile --manifest-path=$MANIFEST_DIR/Cargo.toml)
 
+echo "[pin the dependencies to the versions buildable with the minimum supported Rust version...]"
+pin() {
+    (set -x; cargo update --manifest-path=$MANIFEST_DIR/Cargo.toml -p "$1" --precise "$2")
+}
+pin xml-rs 0.8.0
+pin thiserror 1.0.20
+
 echo "[remove the old files in the local registry...]"
 rm -f $MANIFEST_DIR/.registry-index/*.crate
 rm -rf $MANIFEST_DIR/.registry-index/index/
@@ -17,10 +17,11 @@ use {
         Request, Response,
     },
     mime_guess::get_mime_type_str,
+    serde::Serialize,
     tsukuyomi::{
         error::internal_server_error,
         handler::{Handler, ModifyHandler},
-        output::preset::Preset,
+        output::{negotiate::Negotiate, preset::Preset},
     },
 };
 
@@ -83,6 +84,26 @@ where
     Ok(response)
 }
 
+/// Creates a `Responder` that returns either the rendered template or JSON,
+/// according to `Accept` of the request.
+///
+/// The rendered template is sent with the media type guessed from the extension
+/// of the template, or `text/html` if it cannot be guessed.  See
+/// `tsukuyomi::output::negotiate` for the details of negotiation.
+pub fn negotiate<T>(ctx: T) -> Negotiate<T>
+where
+    T: Template + Serialize + Send + 'static,
+{
+    let content_type = ctx
+        .extension()
+        .and_then(get_mime_type_str)
+        .and_then(|s| s.parse().ok())
+        .unwrap_or(mime::TEXT_HTML_UTF_8);
+    tsukuyomi::output::negotiate(ctx).render(content_type, |ctx| {
+        ctx.render().map_err(internal_server_error)
+    })
+}
+
 /// Creates a `ModifyHandler` that renders the outputs of handlers as Askama template.
 pub fn renderer() -> Renderer {
     Renderer::default()
@@ -111,7 +132,7 @@ mod renderer {
         tsukuyomi::{
             error::Error,
             future::{Poll, TryFuture},
-            handler::{AllowedMethods, Handler},
+            handler::{AllowedMethods, Handler, Metadata},
             input::Input,
         },
     };
@@ -134,6 +155,10 @@ mod renderer {
             self.inner.allowed_methods()
         }
 
+        fn metadata(&self) -> &Metadata {
+            self.inner.metadata()
+        }
+
         fn handle(&self) -> Self::Handle {
             RenderedHandle(self.inner.handle())
         }
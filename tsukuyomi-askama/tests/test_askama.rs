@@ -60,3 +60,35 @@ fn test_template_with_modifier() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn test_negotiate() -> tsukuyomi_server::Result<()> {
+    #[derive(Template, serde::Serialize)]
+    #[template(source = "Hello, {{ name }}.", ext = "html")]
+    struct Index {
+        name: &'static str,
+    }
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get() //
+                .call(|| tsukuyomi_askama::negotiate(Index { name: "Alice" }))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(http::Request::get("/").header("accept", "text/html"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("content-type")?, "text/html");
+    assert_eq!(response.header("vary")?, "accept");
+    assert_eq!(response.body().to_utf8()?, "Hello, Alice.");
+
+    let response = server.perform(http::Request::get("/").header("accept", "application/json"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("content-type")?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"{"name":"Alice"}"#);
+
+    let response = server.perform(http::Request::get("/").header("accept", "image/png"))?;
+    assert_eq!(response.status(), 406);
+
+    Ok(())
+}
@@ -0,0 +1,517 @@
+//! Response compression for Tsukuyomi.
+//!
+//! `Compression` is a `ModifyHandler` that encodes the response bodies with gzip or
+//! Brotli, according to `Accept-Encoding` sent by the client.  The bodies are
+//! compressed chunk by chunk, so the streaming responses are never buffered.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, App};
+//! use tsukuyomi_compress::Compression;
+//!
+//! let app = App::create(
+//!     path!("/")
+//!         .to(endpoint::call(|| "Hello, world!\n".repeat(1000)))
+//!         .modify(Compression::new().min_size(1024)),
+//! );
+//! # app.unwrap();
+//! ```
+
+#![doc(html_root_url = "https://docs.rs/tsukuyomi-compress/0.1.0")]
+#![deny(
+    missing_docs,
+    missing_debug_implementations,
+    nonstandard_style,
+    rust_2018_idioms,
+    rust_2018_compatibility,
+    unused
+)]
+#![forbid(clippy::unimplemented)]
+
+use {
+    bytes::Bytes,
+    http::{
+        header::{
+            HeaderMap, //
+            HeaderValue,
+            ACCEPT_ENCODING,
+            CONTENT_ENCODING,
+            CONTENT_LENGTH,
+            CONTENT_TYPE,
+            ETAG,
+            VARY,
+        },
+        Method, Request, Response, StatusCode,
+    },
+    hyper::body::Payload,
+    mime::Mime,
+    std::{io::Write, sync::Arc},
+    tsukuyomi::output::{is_transformable, transform::ChunkTransformer, ETag, ResponseBody},
+};
+
+const DEFAULT_MIN_SIZE: u64 = 1024;
+const DEFAULT_GZIP_LEVEL: u32 = 6;
+// The qualities above 5 are too slow for on-the-fly compression.
+const DEFAULT_BROTLI_QUALITY: u32 = 5;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+const BROTLI_LGWIN: u32 = 22;
+
+/// A `ModifyHandler` that compresses the response bodies.
+///
+/// A response is compressed only if all of the following conditions are met:
+///
+/// * The request method is not `HEAD`, and the status code is neither `204 No Content`,
+///   `206 Partial Content` nor `304 Not Modified`.
+/// * The response is transformable (see `tsukuyomi::output::is_transformable`)
+///   and does not have `Content-Encoding`.
+/// * The media type in `Content-Type` is in the allowlist.  By default, the list
+///   contains `text/*`, `application/json`, `application/javascript`,
+///   `application/xml`, `image/svg+xml` and the types with the suffix `+json` or
+///   `+xml`, so that the already-compressed types such as images are skipped.
+/// * The length of the body, taken from `Content-Length` or the body itself, is not
+///   less than the minimum size, or is unknown as in the streaming responses.
+///
+/// The eligible responses get `Vary: Accept-Encoding` even if the client does not
+/// accept any of the supported encodings, so that the caches do not mix up the
+/// representations.  The compressed responses have `Content-Encoding`, and
+/// `Content-Length` is removed from them.  Their strong `ETag` is replaced with the
+/// weak one of the same tag, since the compressed bytes differ from the original.
+#[derive(Debug, Clone)]
+pub struct Compression {
+    config: Arc<Config>,
+}
+
+#[derive(Debug, Clone)]
+struct Config {
+    gzip: Option<u32>,
+    brotli: Option<u32>,
+    min_size: u64,
+    content_types: Vec<Mime>,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            config: Arc::new(Config {
+                gzip: Some(DEFAULT_GZIP_LEVEL),
+                brotli: Some(DEFAULT_BROTLI_QUALITY),
+                min_size: DEFAULT_MIN_SIZE,
+                content_types: vec![
+                    mime::TEXT_STAR,
+                    mime::APPLICATION_JSON,
+                    mime::APPLICATION_JAVASCRIPT,
+                    "application/xml".parse().expect("should be a valid mime"),
+                    mime::IMAGE_SVG,
+                ],
+            }),
+        }
+    }
+}
+
+impl Compression {
+    /// Creates a `Compression` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the compression level of gzip, from `0` to `9`, or disables gzip if `None`.
+    ///
+    /// The default value is `Some(6)`.
+    pub fn gzip(mut self, level: Option<u32>) -> Self {
+        Arc::make_mut(&mut self.config).gzip = level.map(|level| level.min(9));
+        self
+    }
+
+    /// Sets the quality of Brotli, from `0` to `11`, or disables Brotli if `None`.
+    ///
+    /// The default value is `Some(5)`.
+    pub fn brotli(mut self, quality: Option<u32>) -> Self {
+        Arc::make_mut(&mut self.config).brotli = quality.map(|quality| quality.min(11));
+        self
+    }
+
+    /// Sets the minimum value of `Content-Length` to be compressed.
+    ///
+    /// The default value is `1024`.
+    pub fn min_size(mut self, min_size: u64) -> Self {
+        Arc::make_mut(&mut self.config).min_size = min_size;
+        self
+    }
+
+    /// Adds a media type to the allowlist.
+    ///
+    /// The subtype `*` matches all subtypes, and the parameters are ignored.
+    pub fn content_type(mut self, content_type: Mime) -> Self {
+        Arc::make_mut(&mut self.config)
+            .content_types
+            .push(content_type);
+        self
+    }
+
+    /// Replaces the allowlist of media types with the specified ones.
+    pub fn content_types(mut self, content_types: impl IntoIterator<Item = Mime>) -> Self {
+        Arc::make_mut(&mut self.config).content_types = content_types.into_iter().collect();
+        self
+    }
+
+    /// Compresses the response according to `Accept-Encoding` in the request,
+    /// if it is eligible.
+    ///
+    /// This method is called by the handler created by `ModifyHandler::modify`.
+    pub fn compress(
+        &self,
+        request: &Request<()>,
+        response: Response<ResponseBody>,
+    ) -> Response<ResponseBody> {
+        if !self.is_eligible(request, &response) {
+            return response;
+        }
+
+        let (mut parts, body) = response.into_parts();
+        append_vary(&mut parts.headers);
+
+        let encoding = match self.negotiate(request.headers()) {
+            Some(encoding) => encoding,
+            None => return Response::from_parts(parts, body),
+        };
+        parts.headers.remove(CONTENT_LENGTH);
+        weaken_etag(&mut parts.headers);
+        parts.headers.insert(
+            CONTENT_ENCODING,
+            HeaderValue::from_static(encoding.as_str()),
+        );
+        let body = match encoding {
+            Encoding::Gzip(level) => body.map_chunks(Encoder::gzip(level)),
+            Encoding::Brotli(quality) => body.map_chunks(Encoder::brotli(quality)),
+        };
+        Response::from_parts(parts, body)
+    }
+
+    fn is_eligible(&self, request: &Request<()>, response: &Response<ResponseBody>) -> bool {
+        if request.method() == Method::HEAD {
+            return false;
+        }
+        match response.status() {
+            StatusCode::NO_CONTENT | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED => {
+                return false
+            }
+            status if status.is_informational() => return false,
+            _ => {}
+        }
+        if !is_transformable(response) || response.headers().contains_key(CONTENT_ENCODING) {
+            return false;
+        }
+
+        let content_type = match response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse::<Mime>().ok())
+        {
+            Some(content_type) => content_type,
+            None => return false,
+        };
+        if !self.is_allowed(&content_type) {
+            return false;
+        }
+
+        let content_length = response
+            .headers()
+            .get(CONTENT_LENGTH)
+            .and_then(|h| h.to_str().ok())
+            .and_then(|s| s.parse::<u64>().ok())
+            .or_else(|| response.body().content_length());
+        match content_length {
+            Some(len) => len >= self.config.min_size,
+            None => true,
+        }
+    }
+
+    fn is_allowed(&self, content_type: &Mime) -> bool {
+        if let Some(suffix) = content_type.suffix() {
+            if suffix == mime::JSON || suffix == mime::XML {
+                return true;
+            }
+        }
+        self.config.content_types.iter().any(|allowed| {
+            allowed.type_() == content_type.type_()
+                && (allowed.subtype() == mime::STAR || allowed.subtype() == content_type.subtype())
+        })
+    }
+
+    /// Selects the encoding with the highest quality value.  Brotli is preferred
+    /// when the values are the same.
+    fn negotiate(&self, headers: &HeaderMap) -> Option<Encoding> {
+        let mut br = None;
+        let mut gzip = None;
+        let mut star = None;
+        for item in headers
+            .get_all(ACCEPT_ENCODING)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|s| s.split(','))
+        {
+            let mut params = item.split(';');
+            let coding = params.next().unwrap_or("").trim().to_ascii_lowercase();
+            let q = params
+                .filter_map(|param| {
+                    let param = param.trim();
+                    if param.starts_with("q=") || param.starts_with("Q=") {
+                        param[2..].trim().parse::<f32>().ok()
+                    } else {
+                        None
+                    }
+                })
+                .next()
+                .unwrap_or(1.0);
+            match &*coding {
+                "br" => br = Some(q),
+                "gzip" | "x-gzip" => gzip = Some(q),
+                "*" => star = Some(q),
+                _ => {}
+            }
+        }
+
+        let br = self
+            .config
+            .brotli
+            .map(|quality| (br.or(star).unwrap_or(0.0), Encoding::Brotli(quality)));
+        let gzip = self
+            .config
+            .gzip
+            .map(|level| (gzip.or(star).unwrap_or(0.0), Encoding::Gzip(level)));
+        br.into_iter()
+            .chain(gzip)
+            .filter(|&(q, _)| q > 0.0)
+            .fold(
+                None,
+                |selected: Option<(f32, Encoding)>, (q, encoding)| match selected {
+                    Some((selected_q, _)) if selected_q >= q => selected,
+                    _ => Some((q, encoding)),
+                },
+            )
+            .map(|(_, encoding)| encoding)
+    }
+}
+
+fn append_vary(headers: &mut HeaderMap) {
+    let varied = headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|h| h.to_str().ok())
+        .flat_map(|s| s.split(','))
+        .any(|field| {
+            let field = field.trim();
+            field == "*" || field.eq_ignore_ascii_case("accept-encoding")
+        });
+    if !varied {
+        headers.append(VARY, HeaderValue::from_static("accept-encoding"));
+    }
+}
+
+/// Weakens the entity tag of the response, since the compressed representation is
+/// not byte-for-byte identical to the one identified by the strong tag.
+///
+/// The conditional requests with `If-None-Match` still match the weakened tag,
+/// because they use the weak comparison.
+fn weaken_etag(headers: &mut HeaderMap) {
+    let etag = match headers
+        .get(ETAG)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.parse::<ETag>().ok())
+    {
+        Some(ref etag) if !etag.is_weak() => ETag::weak(etag.tag()),
+        _ => return,
+    };
+    headers.insert(
+        ETAG,
+        etag.to_string()
+            .parse()
+            .expect("the entity tag should be a valid header value"),
+    );
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Encoding {
+    Gzip(u32),
+    Brotli(u32),
+}
+
+impl Encoding {
+    fn as_str(self) -> &'static str {
+        match self {
+            Encoding::Gzip(..) => "gzip",
+            Encoding::Brotli(..) => "br",
+        }
+    }
+}
+
+/// A `ChunkTransformer` that flushes the compressed data at the end of each chunk,
+/// so that the client receives the data as soon as it is produced.
+enum Encoder {
+    Gzip(Option<flate2::write::GzEncoder<Vec<u8>>>),
+    Brotli(Option<Box<brotli::CompressorWriter<Vec<u8>>>>),
+}
+
+impl Encoder {
+    fn gzip(level: u32) -> Self {
+        Encoder::Gzip(Some(flate2::write::GzEncoder::new(
+            vec![],
+            flate2::Compression::new(level),
+        )))
+    }
+
+    fn brotli(quality: u32) -> Self {
+        Encoder::Brotli(Some(Box::new(brotli::CompressorWriter::new(
+            vec![],
+            BROTLI_BUFFER_SIZE,
+            quality,
+            BROTLI_LGWIN,
+        ))))
+    }
+}
+
+// Writing into `Vec<u8>` never fails, and so do the encoders.
+const INFALLIBLE: &str = "the encoder should not fail when writing into Vec<u8>";
+
+impl ChunkTransformer for Encoder {
+    fn transform(&mut self, chunk: Bytes) -> Bytes {
+        if chunk.is_empty() {
+            return chunk;
+        }
+        match self {
+            Encoder::Gzip(Some(encoder)) => {
+                encoder.write_all(&chunk).expect(INFALLIBLE);
+                encoder.flush().expect(INFALLIBLE);
+                encoder.get_mut().split_off(0).into()
+            }
+            Encoder::Brotli(Some(encoder)) => {
+                encoder.write_all(&chunk).expect(INFALLIBLE);
+                encoder.flush().expect(INFALLIBLE);
+                encoder.get_mut().split_off(0).into()
+            }
+            _ => Bytes::new(),
+        }
+    }
+
+    fn finish(&mut self) -> Bytes {
+        match self {
+            Encoder::Gzip(encoder) => match encoder.take() {
+                Some(encoder) => encoder.finish().expect(INFALLIBLE).into(),
+                None => Bytes::new(),
+            },
+            Encoder::Brotli(encoder) => match encoder.take() {
+                Some(encoder) => encoder.into_inner().into(),
+                None => Bytes::new(),
+            },
+        }
+    }
+}
+
+mod impl_modify_handler_for_compression {
+    use {
+        super::Compression,
+        http::Response,
+        tsukuyomi::{
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+            input::Input,
+            output::{IntoResponse, ResponseBody},
+            responder::Responder,
+        },
+    };
+
+    impl<H> ModifyHandler<H> for Compression
+    where
+        H: Handler,
+        H::Output: Responder,
+    {
+        type Output = Response<ResponseBody>;
+        type Handler = CompressionHandler<H>;
+
+        fn modify(&self, handler: H) -> Self::Handler {
+            CompressionHandler {
+                handler,
+                compression: self.clone(),
+            }
+        }
+    }
+
+    #[derive(Debug)]
+    pub struct CompressionHandler<H> {
+        handler: H,
+        compression: Compression,
+    }
+
+    impl<H> Handler for CompressionHandler<H>
+    where
+        H: Handler,
+        H::Output: Responder,
+    {
+        type Output = Response<ResponseBody>;
+        type Error = Error;
+        type Handle = CompressionHandle<H::Handle>;
+
+        fn allowed_methods(&self) -> Option<&AllowedMethods> {
+            self.handler.allowed_methods()
+        }
+
+        fn metadata(&self) -> &Metadata {
+            self.handler.metadata()
+        }
+
+        fn handle(&self) -> Self::Handle {
+            CompressionHandle {
+                state: State::First(self.handler.handle()),
+                compression: self.compression.clone(),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct CompressionHandle<H>
+    where
+        H: TryFuture,
+        H::Ok: Responder,
+    {
+        state: State<H, <H::Ok as Responder>::Respond>,
+        compression: Compression,
+    }
+
+    enum State<A, B> {
+        First(A),
+        Second(B),
+    }
+
+    impl<H> TryFuture for CompressionHandle<H>
+    where
+        H: TryFuture,
+        H::Ok: Responder,
+    {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let response = loop {
+                self.state = match self.state {
+                    State::First(ref mut handle) => {
+                        let output =
+                            futures::try_ready!(handle.poll_ready(input).map_err(Into::into));
+                        State::Second(output.respond())
+                    }
+                    State::Second(ref mut respond) => {
+                        break futures::try_ready!(respond.poll_ready(input).map_err(Into::into))
+                            .respond_to(input)
+                            .map_err(Into::into)?
+                            .map(Into::into);
+                    }
+                };
+            };
+            Ok(Async::Ready(
+                self.compression.compress(input.request, response),
+            ))
+        }
+    }
+}
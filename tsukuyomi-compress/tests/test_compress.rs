@@ -0,0 +1,268 @@
+use {
+    http::{
+        header::{
+            ACCEPT_ENCODING, //
+            CACHE_CONTROL,
+            CONTENT_ENCODING,
+            CONTENT_LENGTH,
+            CONTENT_TYPE,
+            ETAG,
+            VARY,
+        },
+        Request, Response,
+    },
+    std::io::Read,
+    tsukuyomi::{
+        config::prelude::*, //
+        output::{self, json, ResponseBody},
+        App,
+    },
+    tsukuyomi_compress::Compression,
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn test_version_sync() {
+    version_sync::assert_html_root_url_updated!("src/lib.rs");
+}
+
+fn large_payload() -> serde_json::Value {
+    let items: Vec<_> = (0..2000)
+        .map(|i| {
+            serde_json::json!({
+                "id": i,
+                "name": format!("item-{}", i),
+                "tags": ["compression", "tsukuyomi"],
+            })
+        })
+        .collect();
+    serde_json::Value::Array(items)
+}
+
+fn gunzip(data: &[u8]) -> Vec<u8> {
+    let mut decoded = vec![];
+    flate2::read::GzDecoder::new(data)
+        .read_to_end(&mut decoded)
+        .expect("invalid gzip stream");
+    decoded
+}
+
+fn unbrotli(data: &[u8]) -> Vec<u8> {
+    let mut decoded = vec![];
+    brotli::Decompressor::new(data, 4096)
+        .read_to_end(&mut decoded)
+        .expect("invalid brotli stream");
+    decoded
+}
+
+#[test]
+fn compress_large_json() -> tsukuyomi_server::Result<()> {
+    let payload = large_payload();
+    let original = serde_json::to_vec(&payload)?;
+    assert!(original.len() >= 100 * 1024);
+
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(move || json(payload.clone())))
+            .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+    assert_eq!(response.header(VARY)?, "accept-encoding");
+    assert!(!response.headers().contains_key(CONTENT_LENGTH));
+    let body = response.body().to_bytes();
+    assert!(body.len() < original.len());
+    assert_eq!(gunzip(&body), original);
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "gzip, br"))?;
+    assert_eq!(response.header(CONTENT_ENCODING)?, "br");
+    let body = response.body().to_bytes();
+    assert!(body.len() < original.len());
+    assert_eq!(unbrotli(&body), original);
+
+    Ok(())
+}
+
+#[test]
+fn compress_streaming_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(|| {
+                let chunks = (0..100).map(|i| format!("line {}: {}\n", i, "x".repeat(100)));
+                let mut response =
+                    Response::new(ResponseBody::wrap_stream(futures::stream::iter_ok::<
+                        _,
+                        std::io::Error,
+                    >(chunks)));
+                response
+                    .headers_mut()
+                    .insert(CONTENT_TYPE, "text/plain".parse().unwrap());
+                response
+            }))
+            .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+    assert!(response.body().chunks().len() > 1);
+    let expected: String = (0..100)
+        .map(|i| format!("line {}: {}\n", i, "x".repeat(100)))
+        .collect();
+    assert_eq!(gunzip(&response.body().to_bytes()), expected.as_bytes());
+
+    Ok(())
+}
+
+#[test]
+fn negotiate_encoding() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(|| "a".repeat(4096)))
+            .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/"))?;
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(response.header(VARY)?, "accept-encoding");
+    assert_eq!(response.body().to_bytes().len(), 4096);
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "br;q=0.5, gzip"))?;
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "*;q=0.8, br;q=0"))?;
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "identity"))?;
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+
+    Ok(())
+}
+
+#[test]
+fn weaken_etag() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        chain![
+            path!("/strong").to(endpoint::call(|| {
+                Response::builder()
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(ETAG, "\"xyzzy\"")
+                    .body("a".repeat(4096))
+                    .unwrap()
+            })),
+            path!("/weak").to(endpoint::call(|| {
+                Response::builder()
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(ETAG, "W/\"xyzzy\"")
+                    .body("a".repeat(4096))
+                    .unwrap()
+            })),
+        ]
+        .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/strong").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+    assert_eq!(response.header(ETAG)?, "W/\"xyzzy\"");
+
+    let response = server.perform(Request::get("/weak").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.header(ETAG)?, "W/\"xyzzy\"");
+
+    // the identity representation keeps the strong validator.
+    let response = server.perform(Request::get("/strong"))?;
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(response.header(ETAG)?, "\"xyzzy\"");
+
+    Ok(())
+}
+
+#[test]
+fn skip_ineligible_responses() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        chain![
+            path!("/small").to(endpoint::call(|| "a".repeat(100))),
+            path!("/png").to(endpoint::call(|| {
+                Response::builder()
+                    .header(CONTENT_TYPE, "image/png")
+                    .body(vec![0u8; 4096])
+                    .unwrap()
+            })),
+            path!("/encoded").to(endpoint::call(|| {
+                Response::builder()
+                    .header(CONTENT_TYPE, "text/plain")
+                    .header(CONTENT_ENCODING, "deflate")
+                    .body(vec![0u8; 4096])
+                    .unwrap()
+            })),
+        ]
+        .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/small").header(ACCEPT_ENCODING, "gzip"))?;
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert!(!response.headers().contains_key(VARY));
+    assert_eq!(response.header(CONTENT_LENGTH)?, "100");
+
+    let response = server.perform(Request::get("/png").header(ACCEPT_ENCODING, "gzip"))?;
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(response.body().to_bytes().len(), 4096);
+
+    let response = server.perform(Request::get("/encoded").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.header(CONTENT_ENCODING)?, "deflate");
+    assert_eq!(response.body().to_bytes().len(), 4096);
+
+    Ok(())
+}
+
+#[test]
+fn pass_through_no_transform() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(|| output::no_transform("a".repeat(4096))))
+            .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "gzip, br"))?;
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert!(!response.headers().contains_key(VARY));
+    assert_eq!(response.header(CACHE_CONTROL)?, "no-transform");
+    assert_eq!(response.header(CONTENT_LENGTH)?, "4096");
+    assert_eq!(response.body().to_utf8()?, "a".repeat(4096));
+
+    Ok(())
+}
+
+#[test]
+fn compress_envelope() -> tsukuyomi_server::Result<()> {
+    #[derive(serde::Serialize, tsukuyomi::IntoResponse)]
+    #[response(preset = "tsukuyomi::output::preset::Envelope<tsukuyomi::output::preset::Json>")]
+    struct Items(serde_json::Value);
+
+    let payload = large_payload();
+    let expected = serde_json::to_vec(&serde_json::json!({
+        "data": payload,
+        "meta": {},
+    }))?;
+
+    let app = App::create(
+        path!("/")
+            .to(endpoint::call(move || Items(payload.clone())))
+            .modify(Compression::new()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the presets wrapped by `Compression` are also given the `Input`.
+    let response = server.perform(Request::get("/").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+    assert_eq!(gunzip(&response.body().to_bytes()), expected);
+
+    Ok(())
+}
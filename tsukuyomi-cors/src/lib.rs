@@ -26,6 +26,7 @@ use {
             ACCESS_CONTROL_REQUEST_HEADERS,
             ACCESS_CONTROL_REQUEST_METHOD,
             ORIGIN,
+            VARY,
         },
         HttpTryFrom, Method, Request, Response, StatusCode, Uri,
     },
@@ -224,6 +225,15 @@ impl CORS {
     pub fn builder() -> Builder {
         Builder::new()
     }
+
+    /// Validates the specified value of `Origin` with the allowed origins of this configuration.
+    ///
+    /// If no origin is explicitly allowed, any valid origin is accepted.
+    /// This method is useful for applying the same origin policy to the requests
+    /// not handled by `CORS` itself, such as WebSocket handshakes.
+    pub fn validate_origin(&self, origin: &HeaderValue) -> Result<(), CORSError> {
+        self.inner.check_origin(origin)
+    }
 }
 
 mod impl_endpoint_for_cors {
@@ -289,7 +299,7 @@ mod impl_modify_handler_for_cors {
         tsukuyomi::{
             error::Error,
             future::{Async, Poll, TryFuture},
-            handler::{AllowedMethods, Handler, ModifyHandler},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
             input::Input,
         },
     };
@@ -337,6 +347,10 @@ mod impl_modify_handler_for_cors {
             self.allowed_methods.as_ref()
         }
 
+        fn metadata(&self) -> &Metadata {
+            self.handler.metadata()
+        }
+
         #[inline]
         fn handle(&self) -> Self::Handle {
             CORSHandle {
@@ -388,6 +402,16 @@ impl Inner {
             None => return Ok(None),
         };
 
+        self.check_origin(origin)?;
+
+        if self.origins.is_some() || self.allow_credentials {
+            Ok(Some(AllowedOrigin::Some(origin.clone())))
+        } else {
+            Ok(Some(AllowedOrigin::Any))
+        }
+    }
+
+    fn check_origin(&self, origin: &HeaderValue) -> Result<(), CORSError> {
         let parsed_origin = {
             let h_str = origin.to_str().map_err(|_| CORSErrorKind::InvalidOrigin)?;
             let origin_uri: Uri = h_str.parse().map_err(|_| CORSErrorKind::InvalidOrigin)?;
@@ -407,14 +431,9 @@ impl Inner {
             if !origins.contains(&parsed_origin) {
                 return Err(CORSErrorKind::DisallowedOrigin.into());
             }
-            return Ok(Some(AllowedOrigin::Some(origin.clone())));
         }
 
-        if self.allow_credentials {
-            Ok(Some(AllowedOrigin::Some(origin.clone())))
-        } else {
-            Ok(Some(AllowedOrigin::Any))
-        }
+        Ok(())
     }
 
     fn validate_request_method<T>(
@@ -478,6 +497,11 @@ impl Inner {
 
         let mut response = Response::default();
         *response.status_mut() = StatusCode::NO_CONTENT;
+        if origin.varies() {
+            response
+                .headers_mut()
+                .insert(VARY, HeaderValue::from_static("Origin"));
+        }
         response
             .headers_mut()
             .insert(ACCESS_CONTROL_ALLOW_ORIGIN, origin.into());
@@ -513,6 +537,9 @@ impl Inner {
             return Err(CORSErrorKind::DisallowedRequestMethod.into());
         }
 
+        if origin.varies() {
+            hdrs.append(VARY, HeaderValue::from_static("Origin"));
+        }
         hdrs.append(ACCESS_CONTROL_ALLOW_ORIGIN, origin.into());
 
         if self.allow_credentials {
@@ -530,6 +557,8 @@ impl Inner {
             Some(origin) => origin,
             None => return Ok(None), // do nothing
         };
+        // The effective method, which may be overridden by `MethodOverride`,
+        // is validated so that the override cannot bypass the allowed methods.
         if input.request.method() == Method::OPTIONS {
             self.process_preflight_request(input.request, origin)
                 .map(Some)
@@ -549,6 +578,16 @@ enum AllowedOrigin {
     Any,
 }
 
+impl AllowedOrigin {
+    /// Returns whether the response depends on the value of `Origin`.
+    fn varies(&self) -> bool {
+        match self {
+            AllowedOrigin::Some(..) => true,
+            AllowedOrigin::Any => false,
+        }
+    }
+}
+
 impl Into<HeaderValue> for AllowedOrigin {
     fn into(self) -> HeaderValue {
         match self {
@@ -11,6 +11,7 @@ use {
             COOKIE,
             HOST,
             ORIGIN,
+            VARY,
         },
         Method, Request,
     },
@@ -83,6 +84,7 @@ fn simple_request_with_allow_origin() -> tsukuyomi_server::Result<()> {
         response.header(ACCESS_CONTROL_ALLOW_ORIGIN)?,
         "http://example.com"
     );
+    assert_eq!(response.header(VARY)?, "Origin");
 
     // disallowed origin
     let response = server.perform(
@@ -437,3 +439,53 @@ fn as_scope_modifier() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn preflight_with_method_override() -> tsukuyomi_server::Result<()> {
+    const X_HTTP_METHOD_OVERRIDE: &str = "x-http-method-override";
+
+    let cors = CORS::builder()
+        .allow_origin("http://example.com")?
+        .allow_methods(vec![Method::GET, Method::POST])?
+        .allow_header(X_HTTP_METHOD_OVERRIDE)?
+        .build();
+
+    let app = App::create(chain![
+        tsukuyomi::config::MethodOverride::new(),
+        path!("/posts/:id")
+            .to(endpoint::delete().call(|id: u32| format!("deleted {}", id)))
+            .modify(cors),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the preflight request is never overridden.
+    let response = server.perform(
+        Request::options("/posts/1")
+            .header(HOST, "localhost")
+            .header(ORIGIN, "http://example.com")
+            .header(ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(ACCESS_CONTROL_REQUEST_HEADERS, X_HTTP_METHOD_OVERRIDE)
+            .header(X_HTTP_METHOD_OVERRIDE, "DELETE"),
+    )?;
+    assert_eq!(response.status(), 204);
+    assert_eq!(
+        response.header(ACCESS_CONTROL_ALLOW_ORIGIN)?,
+        "http://example.com"
+    );
+    assert_headers!(
+        response.header(ACCESS_CONTROL_ALLOW_HEADERS)?,
+        [X_HTTP_METHOD_OVERRIDE.parse().unwrap()]
+    );
+
+    // the actual request is validated with the overridden method, which is not allowed.
+    let response = server.perform(
+        Request::post("/posts/1")
+            .header(HOST, "localhost")
+            .header(ORIGIN, "http://example.com")
+            .header(X_HTTP_METHOD_OVERRIDE, "DELETE"),
+    )?;
+    assert_eq!(response.status(), 403);
+    assert!(!response.headers().contains_key(ACCESS_CONTROL_ALLOW_ORIGIN));
+
+    Ok(())
+}
@@ -5,7 +5,7 @@ use {
     tsukuyomi::{
         error::{Error, HttpError}, //
         future::{Poll, TryFuture},
-        handler::{AllowedMethods, Handler, ModifyHandler},
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
         input::Input,
         output::ResponseBody,
     },
@@ -13,7 +13,6 @@ use {
 
 #[derive(Debug)]
 pub enum GraphQLParseError {
-    InvalidRequestMethod,
     MissingQuery,
     MissingMime,
     InvalidMime,
@@ -25,7 +24,6 @@ pub enum GraphQLParseError {
 impl fmt::Display for GraphQLParseError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            GraphQLParseError::InvalidRequestMethod => f.write_str("the request method is invalid"),
             GraphQLParseError::MissingQuery => f.write_str("missing query"),
             GraphQLParseError::MissingMime => f.write_str("missing content-type"),
             GraphQLParseError::InvalidMime => f.write_str("the content type is invalid."),
@@ -128,6 +126,10 @@ where
         self.inner.allowed_methods()
     }
 
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
     fn handle(&self) -> Self::Handle {
         GraphQLHandle {
             inner: self.inner.handle(),
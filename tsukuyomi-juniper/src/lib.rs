@@ -13,12 +13,14 @@
 
 mod error;
 mod graphiql;
+mod persisted;
 mod request;
 
 pub use crate::{
     error::{capture_errors, CaptureErrors},
     graphiql::graphiql_source,
-    request::{request, GraphQLRequest, GraphQLResponse},
+    persisted::{LruQueryCache, QueryCache},
+    request::{request, request_with_cache, GraphQLRequest, GraphQLResponse},
 };
 
 use {
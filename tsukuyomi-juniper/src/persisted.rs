@@ -0,0 +1,208 @@
+use {
+    http::StatusCode,
+    serde_json::json,
+    sha2::{Digest, Sha256},
+    std::{
+        collections::{BTreeMap, HashMap},
+        fmt,
+        sync::{Arc, Mutex},
+    },
+};
+
+/// A trait representing the storage of persisted queries, keyed by the SHA-256 hash of query.
+///
+/// The hashes passed to the cache are always formatted in lowercase hexadecimal.
+pub trait QueryCache: Send + Sync + 'static {
+    /// Returns the query associated with the specified hash, if exists.
+    fn get(&self, hash: &str) -> Option<String>;
+
+    /// Stores the query with its hash.
+    fn insert(&self, hash: String, query: String);
+}
+
+impl<T> QueryCache for Arc<T>
+where
+    T: QueryCache,
+{
+    fn get(&self, hash: &str) -> Option<String> {
+        (**self).get(hash)
+    }
+
+    fn insert(&self, hash: String, query: String) {
+        (**self).insert(hash, query)
+    }
+}
+
+/// An in-memory `QueryCache` which evicts the least recently used query when
+/// the number of entries exceeds its capacity.
+#[derive(Debug)]
+pub struct LruQueryCache {
+    capacity: usize,
+    inner: Mutex<LruInner>,
+}
+
+#[derive(Debug, Default)]
+struct LruInner {
+    entries: HashMap<String, (String, u64)>,
+    recency: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl LruInner {
+    fn touch(&mut self, hash: &str) -> u64 {
+        self.tick += 1;
+        let tick = self.tick;
+        if let Some(&mut (_, ref mut last_used)) = self.entries.get_mut(hash) {
+            self.recency.remove(last_used);
+            *last_used = tick;
+            self.recency.insert(tick, hash.to_owned());
+        }
+        tick
+    }
+}
+
+impl LruQueryCache {
+    /// Creates a new `LruQueryCache` with the specified capacity.
+    ///
+    /// # Panics
+    /// This function will panic if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must be greater than zero");
+        Self {
+            capacity,
+            inner: Mutex::new(LruInner::default()),
+        }
+    }
+
+    /// Returns the number of queries stored in this cache.
+    pub fn len(&self) -> usize {
+        self.inner.lock().unwrap().entries.len()
+    }
+
+    /// Returns whether this cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl QueryCache for LruQueryCache {
+    fn get(&self, hash: &str) -> Option<String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.touch(hash);
+        inner.entries.get(hash).map(|(query, _)| query.clone())
+    }
+
+    fn insert(&self, hash: String, query: String) {
+        let mut inner = self.inner.lock().unwrap();
+        if inner.entries.contains_key(&hash) {
+            inner.touch(&hash);
+            return;
+        }
+
+        if inner.entries.len() >= self.capacity {
+            let oldest = inner.recency.keys().next().cloned();
+            if let Some(evicted) = oldest.and_then(|tick| inner.recency.remove(&tick)) {
+                inner.entries.remove(&evicted);
+            }
+        }
+
+        let tick = inner.touch(&hash);
+        inner.recency.insert(tick, hash.clone());
+        inner.entries.insert(hash, (query, tick));
+    }
+}
+
+/// The field `extensions.persistedQuery` in a GraphQL request.
+#[derive(Debug, serde::Deserialize)]
+pub(crate) struct PersistedQuery {
+    version: u32,
+    #[serde(rename = "sha256Hash")]
+    sha256_hash: String,
+}
+
+/// The errors that occur when resolving the persisted query of a request.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum PersistedQueryError {
+    NotSupported,
+    NotFound,
+    UnsupportedVersion,
+    HashMismatch,
+    MissingQuery,
+}
+
+impl fmt::Display for PersistedQueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PersistedQueryError::NotSupported => "PersistedQueryNotSupported",
+            PersistedQueryError::NotFound => "PersistedQueryNotFound",
+            PersistedQueryError::UnsupportedVersion => "unsupported persisted query version",
+            PersistedQueryError::HashMismatch => "provided sha does not match query",
+            PersistedQueryError::MissingQuery => "missing query",
+        })
+    }
+}
+
+impl PersistedQueryError {
+    /// Returns the status code of the response.
+    ///
+    /// The errors that the clients recover from by resending the full query are
+    /// returned with `200 OK`, as Apollo clients expect.
+    pub(crate) fn status(self) -> StatusCode {
+        match self {
+            PersistedQueryError::NotSupported | PersistedQueryError::NotFound => StatusCode::OK,
+            _ => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    pub(crate) fn to_json(self) -> serde_json::Value {
+        let code = match self {
+            PersistedQueryError::NotSupported => "PERSISTED_QUERY_NOT_SUPPORTED",
+            PersistedQueryError::NotFound => "PERSISTED_QUERY_NOT_FOUND",
+            _ => "BAD_REQUEST",
+        };
+        json!({
+            "errors": [
+                {
+                    "message": self.to_string(),
+                    "extensions": { "code": code },
+                }
+            ],
+        })
+    }
+}
+
+/// Determines the query string of a request, using the cache if the request
+/// contains a persisted query.
+pub(crate) fn resolve_query(
+    query: Option<String>,
+    persisted: Option<PersistedQuery>,
+    cache: Option<&dyn QueryCache>,
+) -> Result<String, PersistedQueryError> {
+    let persisted = match persisted {
+        Some(persisted) => persisted,
+        None => return query.ok_or(PersistedQueryError::MissingQuery),
+    };
+    let cache = cache.ok_or(PersistedQueryError::NotSupported)?;
+    if persisted.version != 1 {
+        return Err(PersistedQueryError::UnsupportedVersion);
+    }
+
+    let hash = persisted.sha256_hash.to_ascii_lowercase();
+    match query {
+        Some(query) => {
+            if sha256_hex(&query) != hash {
+                return Err(PersistedQueryError::HashMismatch);
+            }
+            cache.insert(hash, query.clone());
+            Ok(query)
+        }
+        None => cache.get(&hash).ok_or(PersistedQueryError::NotFound),
+    }
+}
+
+fn sha256_hex(query: &str) -> String {
+    Sha256::digest(query.as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
@@ -1,10 +1,15 @@
 use {
-    crate::{error::GraphQLParseError, Schema},
+    crate::{
+        error::GraphQLParseError,
+        persisted::{PersistedQuery, PersistedQueryError, QueryCache},
+        Schema,
+    },
     futures::{stream::Concat2, Future, Stream},
     http::{Method, Response, StatusCode},
     juniper::{DefaultScalarValue, InputValue, ScalarRefValue, ScalarValue},
     percent_encoding::percent_decode,
-    serde::Deserialize,
+    serde::{Deserialize, Deserializer},
+    std::sync::Arc,
     tsukuyomi::{
         error::Error,
         extractor::Extractor,
@@ -15,11 +20,73 @@ use {
 };
 
 /// Create an `Extractor` that parses the incoming request as GraphQL query.
+///
+/// The requests using the automatic persisted queries are answered with
+/// the error `PersistedQueryNotSupported`. Use `request_with_cache` to enable them.
 pub fn request<S>() -> impl Extractor<
     Output = (GraphQLRequest<S>,), //
     Error = Error,
     Extract = impl TryFuture<Ok = (GraphQLRequest<S>,), Error = Error> + Send + 'static,
 >
+where
+    S: ScalarValue + Send + 'static,
+    for<'a> &'a S: ScalarRefValue<'a>,
+{
+    self::request_impl(None)
+}
+
+/// Create an `Extractor` that parses the incoming request as GraphQL query,
+/// with the support for the automatic persisted queries (APQ).
+///
+/// If a request contains the field `extensions.persistedQuery`, its query is looked up
+/// from `cache` by the SHA-256 hash, and the error `PersistedQueryNotFound` is returned
+/// if it is missing. When both the hash and the query text are provided, the query is
+/// stored into `cache` after verifying that the hash matches. In the batch requests,
+/// the persisted queries are resolved for each element.
+pub fn request_with_cache<S, C>(
+    cache: C,
+) -> impl Extractor<
+    Output = (GraphQLRequest<S>,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (GraphQLRequest<S>,), Error = Error> + Send + 'static,
+>
+where
+    S: ScalarValue + Send + 'static,
+    for<'a> &'a S: ScalarRefValue<'a>,
+    C: QueryCache,
+{
+    self::request_impl(Some(Arc::new(cache)))
+}
+
+fn request_impl<S>(
+    cache: Option<Arc<dyn QueryCache>>,
+) -> impl Extractor<
+    Output = (GraphQLRequest<S>,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (GraphQLRequest<S>,), Error = Error> + Send + 'static,
+>
+where
+    S: ScalarValue + Send + 'static,
+    for<'a> &'a S: ScalarRefValue<'a>,
+{
+    tsukuyomi::extractor::method_switch()
+        .on(Method::GET, {
+            let cache = cache.clone();
+            tsukuyomi::extractor::ready(move |input| {
+                parse_query_request(input)
+                    .map(|request| (GraphQLRequest::resolve(request, cache.as_ref()),))
+            })
+        })
+        .on(Method::POST, body_request(cache))
+}
+
+fn body_request<S>(
+    cache: Option<Arc<dyn QueryCache>>,
+) -> impl Extractor<
+    Output = (GraphQLRequest<S>,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (GraphQLRequest<S>,), Error = Error> + Send + 'static,
+>
 where
     S: ScalarValue + Send + 'static,
     for<'a> &'a S: ScalarRefValue<'a>,
@@ -37,35 +104,28 @@ where
         Receive(Concat2<RequestBody>, RequestKind),
     }
 
-    tsukuyomi::extractor::extract(|| {
+    tsukuyomi::extractor::extract(move || {
+        let cache = cache.clone();
         let mut state = State::Init;
         tsukuyomi::future::poll_fn(move |input| loop {
             state = match state {
                 State::Init => {
-                    if input.request.method() == Method::GET {
-                        return parse_query_request(input).map(|request| Async::Ready((request,)));
-                    } else if input.request.method() == Method::POST {
-                        let kind = match tsukuyomi::input::header::parse::<ContentType>(input) {
-                            Ok(Some(mime)) if *mime == mime::APPLICATION_JSON => RequestKind::Json,
-                            Ok(Some(mime)) if *mime == "application/graphql" => {
-                                RequestKind::GraphQL
-                            }
-                            Ok(Some(..)) => return Err(GraphQLParseError::InvalidMime.into()),
-                            Ok(None) => return Err(GraphQLParseError::MissingMime.into()),
-                            Err(err) => return Err(err),
-                        };
-
-                        let read_all = RequestBody::take_from(input.locals)
-                            .ok_or_else(|| {
-                                tsukuyomi::error::internal_server_error(
-                                    "the payload has already stolen by another extractor",
-                                )
-                            })?
-                            .concat2();
-                        State::Receive(read_all, kind)
-                    } else {
-                        return Err(GraphQLParseError::InvalidRequestMethod.into());
-                    }
+                    let kind = match tsukuyomi::input::header::parse::<ContentType>(input) {
+                        Ok(Some(mime)) if *mime == mime::APPLICATION_JSON => RequestKind::Json,
+                        Ok(Some(mime)) if *mime == "application/graphql" => RequestKind::GraphQL,
+                        Ok(Some(..)) => return Err(GraphQLParseError::InvalidMime.into()),
+                        Ok(None) => return Err(GraphQLParseError::MissingMime.into()),
+                        Err(err) => return Err(err),
+                    };
+
+                    let read_all = RequestBody::take_from(input.locals)
+                        .ok_or_else(|| {
+                            tsukuyomi::error::internal_server_error(
+                                "the payload has already stolen by another extractor",
+                            )
+                        })?
+                        .concat2();
+                    State::Receive(read_all, kind)
                 }
                 State::Receive(ref mut read_all, kind) => {
                     let data = futures::try_ready!(read_all.poll());
@@ -73,6 +133,7 @@ where
                         RequestKind::Json => {
                             let request = serde_json::from_slice(&*data)
                                 .map_err(GraphQLParseError::ParseJson)?;
+                            let request = GraphQLRequest::resolve(request, cache.as_ref());
                             return Ok(Async::Ready((request,)));
                         }
                         RequestKind::GraphQL => {
@@ -89,7 +150,7 @@ where
     })
 }
 
-fn parse_query_request<S>(input: &mut Input<'_>) -> tsukuyomi::Result<GraphQLRequest<S>>
+fn parse_query_request<S>(input: &mut Input<'_>) -> tsukuyomi::Result<RawRequestKind<S>>
 where
     S: ScalarValue,
     for<'a> &'a S: ScalarRefValue<'a>,
@@ -102,32 +163,43 @@ where
     parse_query_str(query_str).map_err(Into::into)
 }
 
-fn parse_query_str<S>(s: &str) -> Result<GraphQLRequest<S>, GraphQLParseError>
+fn parse_query_str<S>(s: &str) -> Result<RawRequestKind<S>, GraphQLParseError>
 where
     S: ScalarValue,
     for<'a> &'a S: ScalarRefValue<'a>,
 {
     #[derive(Debug, serde::Deserialize)]
     struct ParsedQuery {
-        query: String,
+        query: Option<String>,
         operation_name: Option<String>,
         variables: Option<String>,
+        extensions: Option<String>,
     }
     let parsed: ParsedQuery =
         serde_urlencoded::from_str(s).map_err(GraphQLParseError::ParseQuery)?;
 
-    let query = percent_decode(parsed.query.as_ref())
-        .decode_utf8()
-        .map_err(GraphQLParseError::DecodeUtf8)?
-        .into_owned();
-
-    let operation_name = parsed.operation_name.map_or(Ok(None), |s| {
+    let decode = |s: String| {
         percent_decode(s.as_ref())
             .decode_utf8()
             .map_err(GraphQLParseError::DecodeUtf8)
             .map(|s| s.into_owned())
             .map(Some)
-    })?;
+    };
+
+    let query = parsed.query.map_or(Ok(None), decode)?;
+    let operation_name = parsed.operation_name.map_or(Ok(None), decode)?;
+
+    let extensions = parsed
+        .extensions
+        .map_or(Ok(None), |s| -> Result<_, GraphQLParseError> {
+            let decoded = percent_decode(s.as_ref())
+                .decode_utf8()
+                .map_err(GraphQLParseError::DecodeUtf8)?;
+            let extensions = serde_json::from_str(&decoded)
+                .map(Some)
+                .map_err(GraphQLParseError::ParseJson)?;
+            Ok(extensions)
+        })?;
 
     let variables = parsed
         .variables
@@ -141,36 +213,117 @@ where
             Ok(variables)
         })?;
 
-    Ok(GraphQLRequest::single(query, operation_name, variables))
+    Ok(RawRequestKind::Single(RawRequest {
+        query,
+        operation_name,
+        variables,
+        extensions,
+    }))
 }
 
-/// The type representing a GraphQL request from the client.
-#[derive(Debug, serde::Deserialize)]
+/// The unresolved form of a GraphQL request, which may omit the query text
+/// when the persisted query is used.
+#[derive(Debug, Deserialize)]
 #[serde(bound = "InputValue<S>: Deserialize<'de>")]
-pub struct GraphQLRequest<S: ScalarValue = DefaultScalarValue>(GraphQLRequestKind<S>);
+struct RawRequest<S: ScalarValue> {
+    query: Option<String>,
+    #[serde(rename = "operationName")]
+    operation_name: Option<String>,
+    variables: Option<InputValue<S>>,
+    extensions: Option<RequestExtensions>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RequestExtensions {
+    #[serde(rename = "persistedQuery")]
+    persisted_query: Option<PersistedQuery>,
+}
 
 #[derive(Debug, Deserialize)]
 #[serde(untagged, bound = "InputValue<S>: Deserialize<'de>")]
+enum RawRequestKind<S: ScalarValue> {
+    Single(RawRequest<S>),
+    Batch(Vec<RawRequest<S>>),
+}
+
+impl<S> RawRequest<S>
+where
+    S: ScalarValue,
+{
+    fn resolve(self, cache: Option<&Arc<dyn QueryCache>>) -> Operation<S> {
+        let Self {
+            query,
+            operation_name,
+            variables,
+            extensions,
+        } = self;
+        let persisted = extensions.and_then(|ext| ext.persisted_query);
+        let query = crate::persisted::resolve_query(query, persisted, cache.map(|c| &**c))?;
+        Ok(juniper::http::GraphQLRequest::new(
+            query,
+            operation_name,
+            variables,
+        ))
+    }
+}
+
+/// The type representing a GraphQL request from the client.
+#[derive(Debug)]
+pub struct GraphQLRequest<S: ScalarValue = DefaultScalarValue>(GraphQLRequestKind<S>);
+
+#[derive(Debug)]
 enum GraphQLRequestKind<S: ScalarValue> {
-    Single(juniper::http::GraphQLRequest<S>),
-    Batch(Vec<juniper::http::GraphQLRequest<S>>),
+    Single(Operation<S>),
+    Batch(Vec<Operation<S>>),
+}
+
+type Operation<S> = Result<juniper::http::GraphQLRequest<S>, PersistedQueryError>;
+
+impl<'de, S> Deserialize<'de> for GraphQLRequest<S>
+where
+    S: ScalarValue,
+    InputValue<S>: Deserialize<'de>,
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        RawRequestKind::deserialize(deserializer).map(|raw| GraphQLRequest::resolve(raw, None))
+    }
 }
 
 impl<S> GraphQLRequest<S>
 where
     S: ScalarValue,
-    for<'a> &'a S: ScalarRefValue<'a>,
 {
     fn single(
         query: String,
         operation_name: Option<String>,
         variables: Option<InputValue<S>>,
     ) -> Self {
-        GraphQLRequest(GraphQLRequestKind::Single(
+        GraphQLRequest(GraphQLRequestKind::Single(Ok(
             juniper::http::GraphQLRequest::new(query, operation_name, variables),
-        ))
+        )))
     }
 
+    fn resolve(raw: RawRequestKind<S>, cache: Option<&Arc<dyn QueryCache>>) -> Self {
+        GraphQLRequest(match raw {
+            RawRequestKind::Single(request) => GraphQLRequestKind::Single(request.resolve(cache)),
+            RawRequestKind::Batch(requests) => GraphQLRequestKind::Batch(
+                requests
+                    .into_iter()
+                    .map(|request| request.resolve(cache))
+                    .collect(),
+            ),
+        })
+    }
+}
+
+impl<S> GraphQLRequest<S>
+where
+    S: ScalarValue,
+    for<'a> &'a S: ScalarRefValue<'a>,
+{
     /// Creates a `Responder` that executes this request using the specified schema and context.
     pub fn execute<T, CtxT>(self, schema: T, context: CtxT) -> GraphQLResponse<T, CtxT, S>
     where
@@ -214,7 +367,7 @@ where
         let handle = tsukuyomi_server::rt::spawn_fn(move || -> tsukuyomi::Result<_> {
             use self::GraphQLRequestKind::*;
             match request.0 {
-                Single(request) => {
+                Single(Ok(request)) => {
                     let response = request.execute(schema.as_root_node(), context.as_ref());
                     let status = if response.is_ok() {
                         StatusCode::OK
@@ -229,12 +382,34 @@ where
                         .body(body)
                         .expect("should be a valid response"))
                 }
+                Single(Err(err)) => {
+                    let body = serde_json::to_vec(&err.to_json())
+                        .map_err(tsukuyomi::error::internal_server_error)?;
+                    Ok(Response::builder()
+                        .status(err.status())
+                        .header("content-type", "application/json")
+                        .body(body)
+                        .expect("should be a valid response"))
+                }
                 Batch(requests) => {
-                    let responses: Vec<_> = requests
+                    let mut all_ok = true;
+                    let responses = requests
                         .iter()
-                        .map(|request| request.execute(schema.as_root_node(), context.as_ref()))
-                        .collect();
-                    let status = if responses.iter().all(|response| response.is_ok()) {
+                        .map(|request| match request {
+                            Ok(request) => {
+                                let response =
+                                    request.execute(schema.as_root_node(), context.as_ref());
+                                all_ok &= response.is_ok();
+                                serde_json::to_value(&response)
+                            }
+                            Err(err) => {
+                                all_ok &= err.status() == StatusCode::OK;
+                                Ok(err.to_json())
+                            }
+                        })
+                        .collect::<Result<Vec<_>, _>>()
+                        .map_err(tsukuyomi::error::internal_server_error)?;
+                    let status = if all_ok {
                         StatusCode::OK
                     } else {
                         StatusCode::BAD_REQUEST
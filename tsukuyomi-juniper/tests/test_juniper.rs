@@ -101,3 +101,197 @@ fn make_test_response(response: &Response<TestOutput>) -> http_tests::TestRespon
         body: Some(body),
     }
 }
+
+const HERO_QUERY: &str = "{ hero { name } }";
+const HERO_QUERY_HASH: &str = "aae585680c3470e4947255eafbd1eafe87d1c3f129259cf15e404d1bb7f1e8f4";
+
+fn persisted_query_server(
+    cache: Arc<tsukuyomi_juniper::LruQueryCache>,
+) -> tsukuyomi_server::Result<TestServer<tsukuyomi::app::App>> {
+    let database = Arc::new(Database::new());
+    let schema = Arc::new(RootNode::new(
+        Database::new(),
+        EmptyMutation::<Database>::new(),
+    ));
+
+    let app = App::create({
+        path!("/")
+            .to(endpoint::allow_only("GET, POST")?
+                .extract(tsukuyomi_juniper::request_with_cache(cache))
+                .extract(tsukuyomi::extractor::value(schema))
+                .call(move |request: GraphQLRequest, schema: Arc<_>| {
+                    let database = database.clone();
+                    request.execute(schema, database)
+                }))
+            .modify(tsukuyomi_juniper::capture_errors())
+    })?;
+
+    tsukuyomi_server::test::server(app)
+}
+
+fn post_json(
+    server: &mut TestServer<tsukuyomi::app::App>,
+    body: serde_json::Value,
+) -> (u16, serde_json::Value) {
+    let response = server
+        .perform(
+            Request::post("/")
+                .header("content-type", "application/json")
+                .body(body.to_string()),
+        )
+        .unwrap();
+    let body = serde_json::from_slice(&response.body().to_bytes()).unwrap();
+    (response.status().as_u16(), body)
+}
+
+#[test]
+fn persisted_query() -> tsukuyomi_server::Result<()> {
+    let cache = Arc::new(tsukuyomi_juniper::LruQueryCache::new(16));
+    let mut server = persisted_query_server(cache.clone())?;
+
+    let extensions = serde_json::json!({
+        "persistedQuery": { "version": 1, "sha256Hash": HERO_QUERY_HASH },
+    });
+
+    // miss
+    let (status, body) = post_json(
+        &mut server,
+        serde_json::json!({ "extensions": extensions.clone() }),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["errors"][0]["message"], "PersistedQueryNotFound");
+    assert_eq!(
+        body["errors"][0]["extensions"]["code"],
+        "PERSISTED_QUERY_NOT_FOUND"
+    );
+    assert!(cache.is_empty());
+
+    // register
+    let (status, body) = post_json(
+        &mut server,
+        serde_json::json!({ "query": HERO_QUERY, "extensions": extensions.clone() }),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["hero"]["name"], "R2-D2");
+    assert_eq!(cache.len(), 1);
+
+    // hit
+    let (status, body) = post_json(&mut server, serde_json::json!({ "extensions": extensions }));
+    assert_eq!(status, 200);
+    assert_eq!(body["data"]["hero"]["name"], "R2-D2");
+
+    // hit via GET
+    let response = server.perform(Request::get(format!(
+        "/?extensions={}",
+        custom_url_encode(&format!(
+            r#"{{"persistedQuery":{{"version":1,"sha256Hash":"{}"}}}}"#,
+            HERO_QUERY_HASH
+        ))
+    )))?;
+    assert_eq!(response.status(), 200);
+    let body: serde_json::Value = serde_json::from_slice(&response.body().to_bytes()).unwrap();
+    assert_eq!(body["data"]["hero"]["name"], "R2-D2");
+
+    Ok(())
+}
+
+#[test]
+fn persisted_query_hash_mismatch() -> tsukuyomi_server::Result<()> {
+    let cache = Arc::new(tsukuyomi_juniper::LruQueryCache::new(16));
+    let mut server = persisted_query_server(cache.clone())?;
+
+    let (status, body) = post_json(
+        &mut server,
+        serde_json::json!({
+            "query": "{ hero { id } }",
+            "extensions": {
+                "persistedQuery": { "version": 1, "sha256Hash": HERO_QUERY_HASH },
+            },
+        }),
+    );
+    assert_eq!(status, 400);
+    assert_eq!(body["errors"][0]["extensions"]["code"], "BAD_REQUEST");
+    assert!(cache.is_empty());
+
+    Ok(())
+}
+
+#[test]
+fn persisted_query_batch() -> tsukuyomi_server::Result<()> {
+    let cache = Arc::new(tsukuyomi_juniper::LruQueryCache::new(16));
+    let mut server = persisted_query_server(cache.clone())?;
+
+    let persisted = serde_json::json!({
+        "extensions": {
+            "persistedQuery": { "version": 1, "sha256Hash": HERO_QUERY_HASH },
+        },
+    });
+    let (status, body) = post_json(
+        &mut server,
+        serde_json::json!([persisted.clone(), { "query": "{ hero { id } }" }]),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body[0]["errors"][0]["message"], "PersistedQueryNotFound");
+    assert_eq!(body[1]["data"]["hero"]["id"], "2001");
+
+    let (status, body) = post_json(
+        &mut server,
+        serde_json::json!([
+            { "query": HERO_QUERY, "extensions": persisted["extensions"].clone() },
+            persisted,
+        ]),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body[0]["data"]["hero"]["name"], "R2-D2");
+    assert_eq!(body[1]["data"]["hero"]["name"], "R2-D2");
+
+    Ok(())
+}
+
+#[test]
+fn persisted_query_not_supported() -> tsukuyomi_server::Result<()> {
+    let schema = Arc::new(RootNode::new(
+        Database::new(),
+        EmptyMutation::<Database>::new(),
+    ));
+    let app = App::create({
+        path!("/")
+            .to(endpoint::post()
+                .extract(tsukuyomi_juniper::request())
+                .extract(tsukuyomi::extractor::value(schema))
+                .call(|request: GraphQLRequest, schema: Arc<_>| {
+                    request.execute(schema, Arc::new(Database::new()))
+                }))
+            .modify(tsukuyomi_juniper::capture_errors())
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let (status, body) = post_json(
+        &mut server,
+        serde_json::json!({
+            "extensions": {
+                "persistedQuery": { "version": 1, "sha256Hash": HERO_QUERY_HASH },
+            },
+        }),
+    );
+    assert_eq!(status, 200);
+    assert_eq!(body["errors"][0]["message"], "PersistedQueryNotSupported");
+
+    Ok(())
+}
+
+#[test]
+fn lru_query_cache_evicts_least_recently_used() {
+    use tsukuyomi_juniper::{LruQueryCache, QueryCache};
+
+    let cache = LruQueryCache::new(2);
+    cache.insert("a".into(), "query a".into());
+    cache.insert("b".into(), "query b".into());
+    assert_eq!(cache.get("a"), Some("query a".into()));
+
+    cache.insert("c".into(), "query c".into());
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.get("b"), None);
+    assert_eq!(cache.get("a"), Some("query a".into()));
+    assert_eq!(cache.get("c"), Some("query c".into()));
+}
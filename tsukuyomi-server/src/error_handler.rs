@@ -0,0 +1,275 @@
+//! The handling of errors occurred while accepting and serving the connections.
+
+use {
+    crate::{handle::ServerHandle, CritError},
+    futures::{task::AtomicTask, Async, Future, Poll, Stream},
+    std::{
+        error::Error as StdError,
+        fmt, io,
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+    tokio::timer::Delay,
+};
+
+/// The initial delay before accepting again after the resources are exhausted, in milliseconds.
+const INITIAL_BACKOFF_MS: u64 = 10;
+
+/// The maximum delay before accepting again after the resources are exhausted, in milliseconds.
+const MAX_BACKOFF_MS: u64 = 1000;
+
+/// The number of consecutive failures of accepting after which the server backs off,
+/// even if the errors are not known to be caused by the exhaustion of resources.
+const MAX_CONSECUTIVE_ERRORS: usize = 8;
+
+type ConnectionErrorHook = dyn Fn(&(dyn StdError + 'static)) + Send + Sync + 'static;
+
+/// An error occurred in `Server`, categorized by where it occurred.
+#[derive(Debug, Clone, Copy)]
+pub enum ServerError<'a> {
+    /// The listener failed to accept a connection (e.g. too many open files).
+    Accept(&'a (dyn StdError + 'static)),
+
+    /// The acceptor failed to establish the connection (e.g. the TLS handshake).
+    Handshake(&'a (dyn StdError + 'static)),
+
+    /// The connection violated HTTP, or was closed unexpectedly.
+    Protocol(&'a (dyn StdError + 'static)),
+
+    /// The service could not be created or became unavailable.
+    Service(&'a (dyn StdError + 'static)),
+}
+
+impl<'a> ServerError<'a> {
+    /// Returns the underlying error.
+    pub fn error(&self) -> &'a (dyn StdError + 'static) {
+        match *self {
+            ServerError::Accept(err)
+            | ServerError::Handshake(err)
+            | ServerError::Protocol(err)
+            | ServerError::Service(err) => err,
+        }
+    }
+
+    /// Returns whether the error is caused by the exhaustion of file descriptors
+    /// (`EMFILE` or `ENFILE`) or memory (`ENOBUFS` or `ENOMEM`) while accepting
+    /// a connection.
+    ///
+    /// The server waits with exponential backoff before accepting again after these
+    /// errors, so that the accept loop does not spin until the resources are released.
+    pub fn is_resource_exhausted(&self) -> bool {
+        match *self {
+            ServerError::Accept(err) => {
+                match err
+                    .downcast_ref::<io::Error>()
+                    .and_then(io::Error::raw_os_error)
+                {
+                    Some(code) => is_resource_exhausted(code),
+                    None => false,
+                }
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_resource_exhausted(code: i32) -> bool {
+    match code {
+        libc::EMFILE | libc::ENFILE | libc::ENOBUFS | libc::ENOMEM => true,
+        _ => false,
+    }
+}
+
+#[cfg(not(unix))]
+fn is_resource_exhausted(_: i32) -> bool {
+    false
+}
+
+impl<'a> fmt::Display for ServerError<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            ServerError::Accept(err) => write!(f, "transport error: {}", err),
+            ServerError::Handshake(err) => write!(f, "acceptor error: {}", err),
+            ServerError::Protocol(err) => write!(f, "HTTP protocol error: {}", err),
+            ServerError::Service(err) => write!(f, "service error: {}", err),
+        }
+    }
+}
+
+/// The action taken by `Server` after an error is handled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorAction {
+    /// Continues serving.
+    Continue,
+
+    /// Stops accepting the new connections on all listeners.
+    ///
+    /// The connections already accepted are served until they are closed, and then
+    /// `Server::run` returns.
+    Shutdown,
+}
+
+/// A trait for handling the errors occurred in `Server`.
+///
+/// The handler is called on the task of the accept loop or the connection, and
+/// hence it must not block.  This trait is implemented for the functions of the
+/// form `Fn(&ServerError<'_>) -> ErrorAction`.
+pub trait ServerErrorHandler: Send + Sync + 'static {
+    /// Handles the error, and returns the action to be taken by the server.
+    fn handle_error(&self, error: &ServerError<'_>) -> ErrorAction;
+}
+
+impl<F> ServerErrorHandler for F
+where
+    F: Fn(&ServerError<'_>) -> ErrorAction + Send + Sync + 'static,
+{
+    fn handle_error(&self, error: &ServerError<'_>) -> ErrorAction {
+        (*self)(error)
+    }
+}
+
+/// The default error handler of `Server`, which logs the errors and continues.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DefaultErrorHandler;
+
+impl ServerErrorHandler for DefaultErrorHandler {
+    fn handle_error(&self, error: &ServerError<'_>) -> ErrorAction {
+        log::error!("{}", error);
+        ErrorAction::Continue
+    }
+}
+
+/// Passes the errors to the hook of connection errors and the handler, and carries
+/// out the action returned from the handler.
+#[derive(Clone)]
+pub(crate) struct ErrorReporter {
+    handler: Arc<dyn ServerErrorHandler>,
+    on_connection_error: Option<Arc<ConnectionErrorHook>>,
+    handle: ServerHandle,
+}
+
+impl fmt::Debug for ErrorReporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorReporter")
+            .field("on_connection_error", &self.on_connection_error.is_some())
+            .field("handle", &self.handle)
+            .finish()
+    }
+}
+
+impl ErrorReporter {
+    pub(crate) fn new(handle: ServerHandle) -> Self {
+        Self {
+            handler: Arc::new(DefaultErrorHandler),
+            on_connection_error: None,
+            handle,
+        }
+    }
+
+    pub(crate) fn set_handler(&mut self, handler: impl ServerErrorHandler) {
+        self.handler = Arc::new(handler);
+    }
+
+    pub(crate) fn add_on_connection_error(
+        &mut self,
+        hook: impl Fn(&(dyn StdError + 'static)) + Send + Sync + 'static,
+    ) {
+        self.on_connection_error = Some(match self.on_connection_error.take() {
+            Some(prev) => Arc::new(move |err: &(dyn StdError + 'static)| {
+                prev(err);
+                hook(err);
+            }),
+            None => Arc::new(hook),
+        });
+    }
+
+    pub(crate) fn report(&self, error: ServerError<'_>) {
+        match (error, &self.on_connection_error) {
+            (ServerError::Accept(..), _) | (_, None) => {}
+            (error, Some(hook)) => hook(error.error()),
+        }
+        if self.handler.handle_error(&error) == ErrorAction::Shutdown {
+            log::info!("the shutdown of server is requested by the error handler");
+            self.handle.shutdown();
+        }
+    }
+
+    /// Wraps the stream of incoming connections so that the accept errors do not
+    /// stop the server, unless the handler requests the shutdown.
+    pub(crate) fn supervise<S>(&self, incoming: S) -> Supervised<S> {
+        Supervised {
+            incoming,
+            reporter: self.clone(),
+            task: self.handle.accept_task(),
+            backoff: None,
+            next_backoff: Duration::from_millis(INITIAL_BACKOFF_MS),
+            consecutive_errors: 0,
+        }
+    }
+}
+
+/// A `Stream` of incoming connections which reports the accept errors, and ends when
+/// the shutdown is requested.
+#[allow(missing_debug_implementations)]
+pub(crate) struct Supervised<S> {
+    incoming: S,
+    reporter: ErrorReporter,
+    task: Arc<AtomicTask>,
+    backoff: Option<Delay>,
+    next_backoff: Duration,
+    consecutive_errors: usize,
+}
+
+impl<S> Stream for Supervised<S>
+where
+    S: Stream<Error = CritError>,
+{
+    type Item = S::Item;
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.task.register();
+        loop {
+            if self.reporter.handle.is_shutdown() {
+                return Ok(Async::Ready(None));
+            }
+
+            if let Some(ref mut backoff) = self.backoff {
+                match backoff.poll() {
+                    Ok(Async::NotReady) => return Ok(Async::NotReady),
+                    Ok(Async::Ready(())) => {}
+                    Err(err) => log::error!("timer error: {}", err),
+                }
+            }
+            self.backoff = None;
+
+            match self.incoming.poll() {
+                Ok(Async::Ready(Some(conn))) => {
+                    self.next_backoff = Duration::from_millis(INITIAL_BACKOFF_MS);
+                    self.consecutive_errors = 0;
+                    return Ok(Async::Ready(Some(conn)));
+                }
+                Ok(Async::Ready(None)) => return Ok(Async::Ready(None)),
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Err(err) => {
+                    let error = ServerError::Accept(&*err);
+                    self.reporter.report(error);
+                    // the transient errors (e.g. the connection aborted by the peer)
+                    // are retried immediately, unless they persist.
+                    self.consecutive_errors += 1;
+                    if error.is_resource_exhausted()
+                        || self.consecutive_errors >= MAX_CONSECUTIVE_ERRORS
+                    {
+                        log::debug!("accepting again after {:?}", self.next_backoff);
+                        self.backoff = Some(Delay::new(Instant::now() + self.next_backoff));
+                        self.next_backoff = std::cmp::min(
+                            self.next_backoff * 2,
+                            Duration::from_millis(MAX_BACKOFF_MS),
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,76 @@
+//! The handle shared between `Server` and the outside of it.
+
+use {
+    futures::task::AtomicTask,
+    std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+/// A handle to control the state of `Server` from the outside.
+///
+/// The value is obtained by `Server::handle` before running the server.  The clones
+/// share the state, and hence they can be moved into the application or the
+/// background tasks.
+#[derive(Debug, Clone)]
+pub struct ServerHandle {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    ready: AtomicBool,
+    shutdown: AtomicBool,
+    accept_tasks: Mutex<Vec<Arc<AtomicTask>>>,
+}
+
+impl Default for ServerHandle {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                ready: AtomicBool::new(true),
+                shutdown: AtomicBool::new(false),
+                accept_tasks: Mutex::new(vec![]),
+            }),
+        }
+    }
+}
+
+impl ServerHandle {
+    /// Sets whether the server is ready to receive the traffic.
+    ///
+    /// While the server is not ready, the health check endpoint is answered with
+    /// `503 Service Unavailable` (e.g. during warmup or drain).  The other requests
+    /// are still passed to the service.  The server is ready by default.
+    pub fn set_ready(&self, ready: bool) {
+        self.inner.ready.store(ready, Ordering::SeqCst);
+    }
+
+    /// Returns whether the server is ready to receive the traffic.
+    pub fn is_ready(&self) -> bool {
+        self.inner.ready.load(Ordering::SeqCst)
+    }
+
+    /// Returns whether the shutdown of server has been requested.
+    pub fn is_shutdown(&self) -> bool {
+        self.inner.shutdown.load(Ordering::SeqCst)
+    }
+
+    /// Stops accepting the new connections on all listeners.
+    ///
+    /// The connections already accepted are served until they are closed.
+    pub(crate) fn shutdown(&self) {
+        self.inner.shutdown.store(true, Ordering::SeqCst);
+        for task in &*self.inner.accept_tasks.lock().unwrap() {
+            task.notify();
+        }
+    }
+
+    /// Registers the task which is notified when the shutdown is requested.
+    pub(crate) fn accept_task(&self) -> Arc<AtomicTask> {
+        let task = Arc::new(AtomicTask::new());
+        self.inner.accept_tasks.lock().unwrap().push(task.clone());
+        task
+    }
+}
@@ -0,0 +1,46 @@
+//! The health check endpoint answered by the server itself.
+
+use {
+    crate::handle::ServerHandle,
+    http::{
+        header::{HeaderValue, ALLOW},
+        Method, Request, Response, StatusCode,
+    },
+    std::sync::Arc,
+};
+
+/// The health check endpoint of a server.
+#[derive(Debug, Clone)]
+pub(crate) struct HealthCheck {
+    path: Arc<str>,
+    handle: ServerHandle,
+}
+
+impl HealthCheck {
+    pub(crate) fn new(path: &str, handle: ServerHandle) -> Self {
+        Self {
+            path: path.into(),
+            handle,
+        }
+    }
+
+    /// Returns the response reporting the status of the server, if the request is
+    /// sent to the endpoint.
+    pub(crate) fn check<T>(&self, request: &Request<T>) -> Option<Response<()>> {
+        if request.uri().path() != &*self.path {
+            return None;
+        }
+        let mut response = Response::new(());
+        *response.status_mut() = match *request.method() {
+            Method::GET | Method::HEAD if self.handle.is_ready() => StatusCode::OK,
+            Method::GET | Method::HEAD => StatusCode::SERVICE_UNAVAILABLE,
+            _ => {
+                response
+                    .headers_mut()
+                    .insert(ALLOW, HeaderValue::from_static("GET, HEAD"));
+                StatusCode::METHOD_NOT_ALLOWED
+            }
+        };
+        Some(response)
+    }
+}
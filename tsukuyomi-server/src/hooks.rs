@@ -0,0 +1,502 @@
+//! Hooks called by `Server` at the points of request lifecycle, and the access logger
+//! built on top of them.
+
+use {
+    crate::{io::PeerAddr, proxy::ProxiedAddr},
+    bytes::Buf,
+    futures::{Async, Future, Poll},
+    http::{header::HeaderMap, Method, Request, Response, StatusCode, Uri, Version},
+    hyper::body::Payload,
+    std::{
+        fmt,
+        net::SocketAddr,
+        sync::Arc,
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+    tsukuyomi_service::Truncation,
+};
+
+type RequestHook = dyn Fn(&Request<hyper::Body>) + Send + Sync + 'static;
+type ResponseHook = dyn Fn(&ResponseInfo) + Send + Sync + 'static;
+
+/// The set of hooks registered to `Server`.
+///
+/// The unset hooks cost nothing but the check of `None`.
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    on_request: Option<Arc<RequestHook>>,
+    on_response: Option<Arc<ResponseHook>>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Hooks")
+            .field("on_request", &self.on_request.is_some())
+            .field("on_response", &self.on_response.is_some())
+            .finish()
+    }
+}
+
+impl Hooks {
+    pub(crate) fn add_on_request(
+        &mut self,
+        hook: impl Fn(&Request<hyper::Body>) + Send + Sync + 'static,
+    ) {
+        self.on_request = Some(match self.on_request.take() {
+            Some(prev) => Arc::new(move |request: &Request<hyper::Body>| {
+                prev(request);
+                hook(request);
+            }),
+            None => Arc::new(hook),
+        });
+    }
+
+    pub(crate) fn add_on_response(&mut self, hook: impl Fn(&ResponseInfo) + Send + Sync + 'static) {
+        self.on_response = Some(match self.on_response.take() {
+            Some(prev) => Arc::new(move |info: &ResponseInfo| {
+                prev(info);
+                hook(info);
+            }),
+            None => Arc::new(hook),
+        });
+    }
+
+    /// Calls the hook of incoming request, and starts tracking the request if its
+    /// response is to be observed.
+    pub(crate) fn start(&self, request: &Request<hyper::Body>) -> Option<Tracking> {
+        if let Some(ref hook) = self.on_request {
+            hook(request);
+        }
+        let hook = self.on_response.clone()?;
+        Some(Tracking {
+            hook,
+            info: ResponseInfo {
+                method: request.method().clone(),
+                uri: request.uri().clone(),
+                version: request.version(),
+                remote_addr: remote_addr(request),
+                received_at: SystemTime::now(),
+                status: StatusCode::OK,
+                latency: Duration::from_secs(0),
+                bytes_written: 0,
+                completed: false,
+            },
+            start: Instant::now(),
+            truncation: None,
+        })
+    }
+}
+
+fn remote_addr(request: &Request<hyper::Body>) -> Option<SocketAddr> {
+    let extensions = request.extensions();
+    extensions
+        .get::<ProxiedAddr>()
+        .map(ProxiedAddr::source)
+        .or_else(|| extensions.get::<PeerAddr>().map(PeerAddr::addr))
+}
+
+/// The metadata of an exchange of request and response, passed to the hook
+/// registered by `Server::on_response`.
+#[derive(Debug, Clone)]
+pub struct ResponseInfo {
+    method: Method,
+    uri: Uri,
+    version: Version,
+    remote_addr: Option<SocketAddr>,
+    received_at: SystemTime,
+    status: StatusCode,
+    latency: Duration,
+    bytes_written: u64,
+    completed: bool,
+}
+
+impl ResponseInfo {
+    /// Returns the method of request.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the URI of request.
+    pub fn uri(&self) -> &Uri {
+        &self.uri
+    }
+
+    /// Returns the HTTP version of request.
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns the address of client, if known.
+    ///
+    /// The address reported by the PROXY protocol (see `proxy::ProxiedAddr`) takes
+    /// precedence over the address of the peer of connection (see `PeerAddr`).
+    pub fn remote_addr(&self) -> Option<SocketAddr> {
+        self.remote_addr
+    }
+
+    /// Returns the time when the request was received.
+    pub fn received_at(&self) -> SystemTime {
+        self.received_at
+    }
+
+    /// Returns the status code of response.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the duration from receiving the request until the response body has
+    /// been sent (or aborted).
+    pub fn latency(&self) -> Duration {
+        self.latency
+    }
+
+    /// Returns the number of bytes of the response body passed to the connection.
+    ///
+    /// The header of response is not included.
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    /// Returns whether the whole of response body has been sent.
+    ///
+    /// The value is `false` if the client closes the connection before the body
+    /// has been sent, or if the body has been truncated by an error (see
+    /// `tsukuyomi_service::Truncation`).
+    pub fn completed(&self) -> bool {
+        self.completed
+    }
+}
+
+/// The in-flight `ResponseInfo`, completed when the response body is dropped.
+#[allow(missing_debug_implementations)]
+pub(crate) struct Tracking {
+    hook: Arc<ResponseHook>,
+    info: ResponseInfo,
+    start: Instant,
+    truncation: Option<Truncation>,
+}
+
+/// A `Future` that passes the tracking of request to the body of its response.
+#[allow(missing_debug_implementations)]
+pub(crate) struct HookedResponse<F> {
+    inner: F,
+    tracking: Option<Tracking>,
+}
+
+impl<F> HookedResponse<F> {
+    pub(crate) fn new(inner: F, tracking: Option<Tracking>) -> Self {
+        Self { inner, tracking }
+    }
+}
+
+impl<F, Bd> Future for HookedResponse<F>
+where
+    F: Future<Item = Response<Bd>>,
+    Bd: Payload,
+{
+    type Item = Response<HookedBody<Bd>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = futures::try_ready!(self.inner.poll());
+        let mut tracking = self.tracking.take();
+        if let Some(ref mut tracking) = tracking {
+            tracking.info.status = response.status();
+            // hyper does not poll the body which reports the end of stream in advance.
+            tracking.info.completed = response.body().is_end_stream();
+            tracking.truncation = response.extensions().get::<Truncation>().cloned();
+        }
+        Ok(Async::Ready(
+            response.map(|inner| HookedBody { inner, tracking }),
+        ))
+    }
+}
+
+/// The body of response that counts the bytes passed to the connection, and calls
+/// the hook when it is dropped.
+#[allow(missing_debug_implementations)]
+pub(crate) struct HookedBody<Bd> {
+    inner: Bd,
+    tracking: Option<Tracking>,
+}
+
+impl<Bd> Drop for HookedBody<Bd> {
+    fn drop(&mut self) {
+        if let Some(mut tracking) = self.tracking.take() {
+            tracking.info.latency = tracking.start.elapsed();
+            if tracking
+                .truncation
+                .as_ref()
+                .map_or(false, Truncation::is_set)
+            {
+                tracking.info.completed = false;
+            }
+            (tracking.hook)(&tracking.info);
+        }
+    }
+}
+
+impl<Bd> Payload for HookedBody<Bd>
+where
+    Bd: Payload,
+{
+    type Data = Bd::Data;
+    type Error = Bd::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        let data = futures::try_ready!(self.inner.poll_data());
+        if let Some(ref mut tracking) = self.tracking {
+            match data {
+                Some(ref data) => tracking.info.bytes_written += data.remaining() as u64,
+                None => tracking.info.completed = true,
+            }
+        }
+        Ok(Async::Ready(data))
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        self.inner.poll_trailers()
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        self.inner.content_length()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Common,
+    Json,
+}
+
+/// An access logger which writes a line per response into the `log` crate.
+///
+/// The logger is registered by `Server::access_log`, on top of `Server::on_response`.
+/// The lines are written at the level `Info`, with the target `"tsukuyomi_server::access"`
+/// unless changed by `AccessLog::target`.
+#[derive(Debug, Clone)]
+pub struct AccessLog {
+    format: Format,
+    target: &'static str,
+}
+
+impl AccessLog {
+    /// Creates an `AccessLog` in the Common Log Format.
+    ///
+    /// The client address is written as `-` if unknown, and the size of response
+    /// body is written as `-` if no bytes are sent.
+    pub fn common() -> Self {
+        Self {
+            format: Format::Common,
+            target: "tsukuyomi_server::access",
+        }
+    }
+
+    /// Creates an `AccessLog` which writes a JSON object per line.
+    ///
+    /// The object has the fields `remote_addr`, `time`, `method`, `uri`, `version`,
+    /// `status`, `bytes`, `latency_ms` and `completed`.
+    pub fn json() -> Self {
+        Self {
+            format: Format::Json,
+            target: "tsukuyomi_server::access",
+        }
+    }
+
+    /// Sets the target of log records.
+    pub fn target(self, target: &'static str) -> Self {
+        Self { target, ..self }
+    }
+
+    /// Formats a line of log from the specified `ResponseInfo`.
+    pub fn format(&self, info: &ResponseInfo) -> String {
+        let (year, month, day, hour, min, sec) = civil_time(info.received_at);
+        let remote_addr = info.remote_addr.map(|addr| addr.ip().to_string());
+        match self.format {
+            Format::Common => format!(
+                "{} - - [{:02}/{}/{}:{:02}:{:02}:{:02} +0000] \"{} {} {:?}\" {} {}",
+                remote_addr.as_ref().map_or("-", |addr| &**addr),
+                day,
+                MONTHS[month as usize - 1],
+                year,
+                hour,
+                min,
+                sec,
+                info.method,
+                info.uri,
+                info.version,
+                info.status.as_u16(),
+                match info.bytes_written {
+                    0 => "-".into(),
+                    n => n.to_string(),
+                },
+            ),
+            Format::Json => format!(
+                "{{\"remote_addr\":{},\"time\":\"{}-{:02}-{:02}T{:02}:{:02}:{:02}Z\",\"method\":{},\"uri\":{},\"version\":\"{:?}\",\"status\":{},\"bytes\":{},\"latency_ms\":{:.3},\"completed\":{}}}",
+                match remote_addr {
+                    Some(ref addr) => json_string(addr),
+                    None => "null".into(),
+                },
+                year,
+                month,
+                day,
+                hour,
+                min,
+                sec,
+                json_string(info.method.as_str()),
+                json_string(&info.uri.to_string()),
+                info.version,
+                info.status.as_u16(),
+                info.bytes_written,
+                info.latency.as_secs() as f64 * 1e3 + f64::from(info.latency.subsec_nanos()) / 1e6,
+                info.completed,
+            ),
+        }
+    }
+
+    pub(crate) fn log(&self, info: &ResponseInfo) {
+        log::info!(target: self.target, "{}", self.format(info));
+    }
+}
+
+const MONTHS: [&str; 12] = [
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+/// Converts the time into the date and time in UTC.
+fn civil_time(time: SystemTime) -> (i64, u32, u32, u32, u32, u32) {
+    let secs = time
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    // `secs` is not negative, as are the intermediate values below.
+    let (days, rem) = (secs / 86400, secs % 86400);
+
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z % 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (
+        year,
+        month,
+        day,
+        (rem / 3600) as u32,
+        (rem % 3600 / 60) as u32,
+        (rem % 60) as u32,
+    )
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len() + 2);
+    escaped.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn info() -> ResponseInfo {
+        ResponseInfo {
+            method: Method::GET,
+            uri: "/index.html?q=1".parse().unwrap(),
+            version: Version::HTTP_11,
+            remote_addr: Some("192.0.2.1:54321".parse().unwrap()),
+            received_at: UNIX_EPOCH + Duration::from_secs(971_182_536),
+            status: StatusCode::OK,
+            latency: Duration::from_micros(1500),
+            bytes_written: 2326,
+            completed: true,
+        }
+    }
+
+    #[test]
+    fn common_log_format() {
+        assert_eq!(
+            AccessLog::common().format(&info()),
+            "192.0.2.1 - - [10/Oct/2000:12:55:36 +0000] \"GET /index.html?q=1 HTTP/1.1\" 200 2326"
+        );
+
+        let info = ResponseInfo {
+            remote_addr: None,
+            bytes_written: 0,
+            status: StatusCode::NOT_MODIFIED,
+            ..info()
+        };
+        assert_eq!(
+            AccessLog::common().format(&info),
+            "- - - [10/Oct/2000:12:55:36 +0000] \"GET /index.html?q=1 HTTP/1.1\" 304 -"
+        );
+    }
+
+    #[test]
+    fn json_format() {
+        assert_eq!(
+            AccessLog::json().format(&info()),
+            "{\"remote_addr\":\"192.0.2.1\",\"time\":\"2000-10-10T12:55:36Z\",\"method\":\"GET\",\
+             \"uri\":\"/index.html?q=1\",\"version\":\"HTTP/1.1\",\"status\":200,\
+             \"bytes\":2326,\"latency_ms\":1.500,\"completed\":true}"
+        );
+    }
+
+    #[test]
+    fn truncated_body_is_not_completed() {
+        let reported = Arc::new(std::sync::Mutex::new(None));
+        let mut hooks = Hooks::default();
+        hooks.add_on_response({
+            let reported = reported.clone();
+            move |info| *reported.lock().unwrap() = Some(info.completed())
+        });
+
+        let truncation = Truncation::new();
+        let mut response = Response::new(hyper::Body::from("chunk"));
+        response.extensions_mut().insert(truncation.clone());
+
+        let tracking = hooks.start(&Request::new(hyper::Body::empty()));
+        let mut response = HookedResponse::new(futures::future::ok::<_, ()>(response), tracking);
+        let mut body = match response.poll() {
+            Ok(Async::Ready(response)) => response.into_body(),
+            _ => panic!("the response should be ready"),
+        };
+        while let Ok(Async::Ready(Some(..))) = body.poll_data() {}
+        truncation.set();
+        drop(body);
+
+        assert_eq!(*reported.lock().unwrap(), Some(false));
+    }
+
+    #[test]
+    fn civil_time_leap_year() {
+        // 2024-02-29T23:59:59Z
+        assert_eq!(
+            civil_time(UNIX_EPOCH + Duration::from_secs(1_709_251_199)),
+            (2024, 2, 29, 23, 59, 59)
+        );
+        assert_eq!(civil_time(UNIX_EPOCH), (1970, 1, 1, 0, 0, 0));
+    }
+
+    #[test]
+    fn json_string_escapes() {
+        assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+    }
+}
@@ -0,0 +1,59 @@
+//! The configuration of HTTP/2 connections.
+
+use hyper::server::conn::Http;
+
+/// The HTTP/2-specific configuration passed to the connections of `Server`.
+///
+/// The values left unset keep the defaults of the underlying HTTP/2 implementation.
+/// Note that the keep-alive pings are not supported by the current version of `hyper`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Http2Config {
+    initial_stream_window_size: Option<u32>,
+    initial_connection_window_size: Option<u32>,
+    max_concurrent_streams: Option<u32>,
+}
+
+impl Http2Config {
+    /// Creates an `Http2Config` with the default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the initial window size of the stream-level flow control.
+    ///
+    /// The default value is 65,535.
+    pub fn initial_stream_window_size(self, size: u32) -> Self {
+        Self {
+            initial_stream_window_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Sets the initial window size of the connection-level flow control.
+    ///
+    /// The default value is 65,535.
+    pub fn initial_connection_window_size(self, size: u32) -> Self {
+        Self {
+            initial_connection_window_size: Some(size),
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of streams which the client can open concurrently
+    /// on a connection.
+    ///
+    /// By default, the number is unlimited.
+    pub fn max_concurrent_streams(self, max: u32) -> Self {
+        Self {
+            max_concurrent_streams: Some(max),
+            ..self
+        }
+    }
+
+    pub(crate) fn apply(&self, protocol: &mut Http) {
+        protocol
+            .http2_initial_stream_window_size(self.initial_stream_window_size)
+            .http2_initial_connection_window_size(self.initial_connection_window_size)
+            .http2_max_concurrent_streams(self.max_concurrent_streams);
+    }
+}
@@ -1,9 +1,13 @@
 use {
-    crate::CritError,
-    futures::{Future, IntoFuture, Stream},
+    crate::{socket::TcpConfig, CritError},
+    futures::{stream::Fuse, Async, Future, IntoFuture, Poll, Stream},
+    std::{fmt, io, net::SocketAddr, sync::Arc},
     tokio::io::{AsyncRead, AsyncWrite},
 };
 
+#[doc(no_inline)]
+pub use tsukuyomi_service::PeerCertificates;
+
 /// A trait that represents the low-level I/O.
 pub trait Listener {
     type Conn: AsyncRead + AsyncWrite;
@@ -12,19 +16,432 @@ pub trait Listener {
 
     /// Creates a `Stream` of asynchronous I/Os.
     fn listen(self) -> Result<Self::Incoming, Self::Error>;
+
+    /// Creates a `Stream` of asynchronous I/Os, applying the socket-level configuration
+    /// set by `Server::tcp`.
+    ///
+    /// By default, the configuration is ignored and `listen` is called.
+    fn listen_with(self, config: &TcpConfig) -> Result<Self::Incoming, Self::Error>
+    where
+        Self: Sized,
+    {
+        let _ = config;
+        self.listen()
+    }
+
+    /// Returns the address on which this listener accepts the connections, if known.
+    ///
+    /// The value is used in the error messages and is exposed to the services
+    /// through `ListenerInfo`.
+    fn local_addr(&self) -> Option<String> {
+        None
+    }
+
+    /// Returns the address of the peer of an accepted I/O, if known.
+    ///
+    /// The value is attached to the connection as `PeerAddr`.  By default, nothing
+    /// is returned.
+    fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+        let _ = conn;
+        None
+    }
+}
+
+/// The address of the peer of the connection, reported by the listener.
+///
+/// The server stores this value in the extension map of each request received on
+/// the connection.  Note that this is the address of the proxy server if the
+/// connection is relayed by a proxy (see `proxy::ProxiedAddr`).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PeerAddr(SocketAddr);
+
+impl PeerAddr {
+    /// Returns the address of the peer.
+    pub fn addr(&self) -> SocketAddr {
+        self.0
+    }
+}
+
+/// The information about the listener which accepted the connection.
+///
+/// The server stores this value in the extension map of each request, so that the
+/// services can branch on the listener (e.g. `request.extensions().get::<ListenerInfo>()`).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ListenerInfo {
+    index: usize,
+    local_addr: Option<String>,
+}
+
+impl ListenerInfo {
+    /// Returns the position of the listener in the order of `Server::bind`, starting at zero.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the address of the listener, if known.
+    pub fn local_addr(&self) -> Option<&str> {
+        self.local_addr.as_ref().map(|addr| &**addr)
+    }
+}
+
+/// A trait that represents the set of listeners bound to `Server`.
+///
+/// This trait is implemented for the `Listener`s and for the listeners combined
+/// by `Server::add_listener`.
+pub trait Bindings {
+    type Conn: AsyncRead + AsyncWrite;
+    type Incoming: Stream<Item = (Self::Conn, Arc<ListenerInfo>), Error = CritError>;
+
+    /// Starts listening on all listeners with the specified configuration, numbering
+    /// them from `*index`.
+    ///
+    /// This method fails on the first listener which cannot be started, with an error
+    /// that names its address.
+    fn listen_all(
+        self,
+        index: &mut usize,
+        config: &TcpConfig,
+    ) -> Result<Self::Incoming, failure::Error>;
+
+    /// Returns the address of the peer of an accepted I/O, if known.
+    fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr>;
+}
+
+impl<L> Bindings for L
+where
+    L: Listener,
+{
+    type Conn = L::Conn;
+    type Incoming = Tagged<L::Incoming>;
+
+    fn listen_all(
+        self,
+        index: &mut usize,
+        config: &TcpConfig,
+    ) -> Result<Self::Incoming, failure::Error> {
+        let info = Arc::new(ListenerInfo {
+            index: *index,
+            local_addr: self.local_addr(),
+        });
+        *index += 1;
+        let inner = self.listen_with(config).map_err(|err| {
+            let addr = match info.local_addr {
+                Some(ref addr) => addr.clone(),
+                None => format!("the listener #{}", info.index),
+            };
+            failure::format_err!("failed to listen on {}: {}", addr, err.into())
+        })?;
+        Ok(Tagged { inner, info })
+    }
+
+    #[inline]
+    fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+        L::peer_addr(conn)
+    }
+}
+
+/// The set of listeners bound to `Server` by `Server::add_listener`.
+#[derive(Debug)]
+pub struct Bound<B, L> {
+    bound: B,
+    listener: L,
+}
+
+impl<B, L> Bound<B, L> {
+    pub(crate) fn new(bound: B, listener: L) -> Self {
+        Self { bound, listener }
+    }
+}
+
+impl<B, L> Bindings for Bound<B, L>
+where
+    B: Bindings,
+    L: Listener,
+{
+    type Conn = Either<B::Conn, L::Conn>;
+    type Incoming = Merged<B::Incoming, Tagged<L::Incoming>>;
+
+    fn listen_all(
+        self,
+        index: &mut usize,
+        config: &TcpConfig,
+    ) -> Result<Self::Incoming, failure::Error> {
+        let first = self.bound.listen_all(index, config)?;
+        let second = self.listener.listen_all(index, config)?;
+        Ok(Merged {
+            first: first.fuse(),
+            second: second.fuse(),
+            first_polled: false,
+        })
+    }
+
+    fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+        match conn {
+            Either::Left(conn) => B::peer_addr(conn),
+            Either::Right(conn) => L::peer_addr(conn),
+        }
+    }
+}
+
+/// A `Stream` that adds the information about the listener to the incoming I/Os.
+#[derive(Debug)]
+pub struct Tagged<S> {
+    inner: S,
+    info: Arc<ListenerInfo>,
+}
+
+impl<S> Stream for Tagged<S>
+where
+    S: Stream,
+    S::Error: Into<CritError>,
+{
+    type Item = (S::Item, Arc<ListenerInfo>);
+    type Error = CritError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let conn = futures::try_ready!(self.inner.poll().map_err(Into::into));
+        Ok(Async::Ready(conn.map(|conn| (conn, self.info.clone()))))
+    }
+}
+
+/// A `Stream` that merges the incoming I/Os from two sets of listeners.
+///
+/// The streams are polled alternately, so that either of them does not starve the other.
+#[derive(Debug)]
+pub struct Merged<S1, S2> {
+    first: Fuse<S1>,
+    second: Fuse<S2>,
+    first_polled: bool,
+}
+
+impl<S1, S2, A, B> Stream for Merged<S1, S2>
+where
+    S1: Stream<Item = (A, Arc<ListenerInfo>), Error = CritError>,
+    S2: Stream<Item = (B, Arc<ListenerInfo>), Error = CritError>,
+{
+    type Item = (Either<A, B>, Arc<ListenerInfo>);
+    type Error = CritError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        self.first_polled = !self.first_polled;
+        if self.first_polled {
+            if let Some(item) = poll_first(&mut self.first)? {
+                return Ok(Async::Ready(Some(item)));
+            }
+            if let Some(item) = poll_second(&mut self.second)? {
+                return Ok(Async::Ready(Some(item)));
+            }
+        } else {
+            if let Some(item) = poll_second(&mut self.second)? {
+                return Ok(Async::Ready(Some(item)));
+            }
+            if let Some(item) = poll_first(&mut self.first)? {
+                return Ok(Async::Ready(Some(item)));
+            }
+        }
+        if self.first.is_done() && self.second.is_done() {
+            Ok(Async::Ready(None))
+        } else {
+            Ok(Async::NotReady)
+        }
+    }
+}
+
+type Polled<A, B> = Result<Option<(Either<A, B>, Arc<ListenerInfo>)>, CritError>;
+
+fn poll_first<S, A, B>(stream: &mut Fuse<S>) -> Polled<A, B>
+where
+    S: Stream<Item = (A, Arc<ListenerInfo>), Error = CritError>,
+{
+    match stream.poll()? {
+        Async::Ready(Some((conn, info))) => Ok(Some((Either::Left(conn), info))),
+        _ => Ok(None),
+    }
+}
+
+fn poll_second<S, A, B>(stream: &mut Fuse<S>) -> Polled<A, B>
+where
+    S: Stream<Item = (B, Arc<ListenerInfo>), Error = CritError>,
+{
+    match stream.poll()? {
+        Async::Ready(Some((conn, info))) => Ok(Some((Either::Right(conn), info))),
+        _ => Ok(None),
+    }
+}
+
+/// An asynchronous I/O accepted by either of two sets of listeners.
+#[derive(Debug)]
+pub enum Either<A, B> {
+    Left(A),
+    Right(B),
+}
+
+impl<A, B> io::Read for Either<A, B>
+where
+    A: io::Read,
+    B: io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Either::Left(a) => a.read(buf),
+            Either::Right(b) => b.read(buf),
+        }
+    }
+}
+
+impl<A, B> io::Write for Either<A, B>
+where
+    A: io::Write,
+    B: io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Either::Left(a) => a.write(buf),
+            Either::Right(b) => b.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Either::Left(a) => a.flush(),
+            Either::Right(b) => b.flush(),
+        }
+    }
+}
+
+impl<A, B> AsyncRead for Either<A, B>
+where
+    A: AsyncRead,
+    B: AsyncRead,
+{
+}
+
+impl<A, B> AsyncWrite for Either<A, B>
+where
+    A: AsyncWrite,
+    B: AsyncWrite,
+{
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        match self {
+            Either::Left(a) => a.shutdown(),
+            Either::Right(b) => b.shutdown(),
+        }
+    }
 }
 
 /// A trait that represents the conversion of asynchronous I/Os.
 ///
-/// Typically, the implementors of this trait establish a TLS session.
+/// Typically, the implementors of this trait establish a TLS session, or strip
+/// the header prepended by the proxy server.
 pub trait Acceptor<T> {
     type Conn: AsyncRead + AsyncWrite;
     type Error;
     type Accept: Future<Item = Self::Conn, Error = Self::Error>;
 
     fn accept(&self, io: T) -> Self::Accept;
+
+    /// Attaches the metadata of the accepted connection, e.g. the server name sent via SNI.
+    ///
+    /// The attached values are inserted into the extension map of each request received
+    /// on the connection.  By default, nothing is attached.
+    fn metadata(conn: &Self::Conn, metadata: &mut ConnectionMetadata) {
+        let _ = (conn, metadata);
+    }
+}
+
+type InsertFn = Box<dyn Fn(&mut http::Extensions) + Send + Sync>;
+
+/// The typed values attached to a connection by `Acceptor::metadata`.
+#[derive(Default)]
+pub struct ConnectionMetadata {
+    entries: Vec<InsertFn>,
+}
+
+impl fmt::Debug for ConnectionMetadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ConnectionMetadata")
+            .field("len", &self.entries.len())
+            .finish()
+    }
+}
+
+impl ConnectionMetadata {
+    /// Attaches a value to the connection.
+    ///
+    /// A copy of the value is inserted into the extension map of each request, and
+    /// hence the services can retrieve it by `request.extensions().get::<T>()`.
+    pub fn insert<T>(&mut self, value: T)
+    where
+        T: Clone + Send + Sync + 'static,
+    {
+        self.entries.push(Box::new(move |extensions| {
+            extensions.insert(value.clone());
+        }));
+    }
+
+    /// Returns whether no value is attached.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub(crate) fn apply(&self, extensions: &mut http::Extensions) {
+        for entry in &self.entries {
+            entry(extensions);
+        }
+    }
+}
+
+/// Returns the function to retrieve the address of peer from the I/Os accepted by the listeners.
+pub(crate) fn peer_addr_fn<B>(_: &B) -> fn(&B::Conn) -> Option<SocketAddr>
+where
+    B: Bindings,
+{
+    B::peer_addr
+}
+
+/// Accepts the I/O with the specified `Acceptor`, and collects the metadata of the
+/// accepted connection.
+pub(crate) fn accept<A, T>(acceptor: &A, io: T, peer_addr: Option<SocketAddr>) -> Accepted<A, T>
+where
+    A: Acceptor<T>,
+{
+    Accepted {
+        future: acceptor.accept(io),
+        peer_addr,
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub(crate) struct Accepted<A, T>
+where
+    A: Acceptor<T>,
+{
+    future: A::Accept,
+    peer_addr: Option<SocketAddr>,
+}
+
+impl<A, T> Future for Accepted<A, T>
+where
+    A: Acceptor<T>,
+{
+    type Item = (A::Conn, ConnectionMetadata);
+    type Error = A::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let conn = futures::try_ready!(self.future.poll());
+        let mut metadata = ConnectionMetadata::default();
+        if let Some(addr) = self.peer_addr {
+            metadata.insert(PeerAddr(addr));
+        }
+        A::metadata(&conn, &mut metadata);
+        Ok(Async::Ready((conn, metadata)))
+    }
 }
 
+/// The name of server sent by the client via SNI.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerName(pub String);
+
 impl<F, T, R> Acceptor<T> for F
 where
     F: Fn(T) -> R,
@@ -41,6 +458,7 @@ where
     }
 }
 
+/// The identity acceptor, which returns the incoming I/Os directly.
 impl<T> Acceptor<T> for ()
 where
     T: AsyncRead + AsyncWrite,
@@ -58,9 +476,10 @@ where
 mod tcp {
     use {
         super::Listener,
+        crate::socket::{TcpConfig, TcpIncoming},
         std::{io, net::SocketAddr},
         tokio::{
-            net::{tcp::Incoming, TcpListener, TcpStream},
+            net::{TcpListener, TcpStream},
             reactor::Handle,
         },
     };
@@ -68,45 +487,95 @@ mod tcp {
     impl Listener for SocketAddr {
         type Conn = TcpStream;
         type Error = io::Error;
-        type Incoming = Incoming;
+        type Incoming = TcpIncoming;
 
         #[inline]
         fn listen(self) -> io::Result<Self::Incoming> {
             (&self).listen()
         }
+
+        #[inline]
+        fn listen_with(self, config: &TcpConfig) -> io::Result<Self::Incoming> {
+            (&self).listen_with(config)
+        }
+
+        fn local_addr(&self) -> Option<String> {
+            Some(self.to_string())
+        }
+
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
+        }
     }
 
     impl<'a> Listener for &'a SocketAddr {
         type Conn = TcpStream;
         type Error = io::Error;
-        type Incoming = Incoming;
+        type Incoming = TcpIncoming;
 
         #[inline]
         fn listen(self) -> io::Result<Self::Incoming> {
-            Ok(TcpListener::bind(self)?.incoming())
+            self.listen_with(&TcpConfig::default())
+        }
+
+        fn listen_with(self, config: &TcpConfig) -> io::Result<Self::Incoming> {
+            let listener = TcpListener::from_std(config.bind(self)?, &Handle::default())?;
+            Ok(config.incoming(listener.incoming()))
+        }
+
+        fn local_addr(&self) -> Option<String> {
+            Some(self.to_string())
+        }
+
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
         }
     }
 
     impl Listener for std::net::TcpListener {
         type Conn = TcpStream;
         type Error = io::Error;
-        type Incoming = Incoming;
+        type Incoming = TcpIncoming;
 
         #[inline]
         fn listen(self) -> io::Result<Self::Incoming> {
+            self.listen_with(&TcpConfig::default())
+        }
+
+        fn listen_with(self, config: &TcpConfig) -> io::Result<Self::Incoming> {
             let listener = TcpListener::from_std(self, &Handle::current())?;
-            Ok(listener.incoming())
+            Ok(config.incoming(listener.incoming()))
+        }
+
+        fn local_addr(&self) -> Option<String> {
+            self.local_addr().ok().map(|addr| addr.to_string())
+        }
+
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
         }
     }
 
     impl Listener for TcpListener {
         type Conn = TcpStream;
         type Error = io::Error;
-        type Incoming = Incoming;
+        type Incoming = TcpIncoming;
 
         #[inline]
         fn listen(self) -> io::Result<Self::Incoming> {
-            Ok(self.incoming())
+            self.listen_with(&TcpConfig::default())
+        }
+
+        fn listen_with(self, config: &TcpConfig) -> io::Result<Self::Incoming> {
+            Ok(config.incoming(self.incoming()))
+        }
+
+        fn local_addr(&self) -> Option<String> {
+            self.local_addr().ok().map(|addr| addr.to_string())
+        }
+
+        fn peer_addr(conn: &Self::Conn) -> Option<SocketAddr> {
+            conn.peer_addr().ok()
         }
     }
 }
@@ -134,6 +603,10 @@ mod uds {
         fn listen(self) -> io::Result<Self::Incoming> {
             (&self).listen()
         }
+
+        fn local_addr(&self) -> Option<String> {
+            Some(self.display().to_string())
+        }
     }
 
     impl<'a> Listener for &'a PathBuf {
@@ -145,6 +618,10 @@ mod uds {
         fn listen(self) -> io::Result<Self::Incoming> {
             <&'a std::path::Path>::listen(&*self)
         }
+
+        fn local_addr(&self) -> Option<String> {
+            Some(self.display().to_string())
+        }
     }
 
     impl<'a> Listener for &'a Path {
@@ -156,6 +633,10 @@ mod uds {
         fn listen(self) -> io::Result<Self::Incoming> {
             Ok(UnixListener::bind(self)?.incoming())
         }
+
+        fn local_addr(&self) -> Option<String> {
+            Some(self.display().to_string())
+        }
     }
 
     impl Listener for UnixListener {
@@ -167,6 +648,11 @@ mod uds {
         fn listen(self) -> io::Result<Self::Incoming> {
             Ok(self.incoming())
         }
+
+        fn local_addr(&self) -> Option<String> {
+            let addr = self.local_addr().ok()?;
+            Some(addr.as_pathname()?.display().to_string())
+        }
     }
 
     impl Listener for std::os::unix::net::UnixListener {
@@ -178,6 +664,11 @@ mod uds {
         fn listen(self) -> io::Result<Self::Incoming> {
             Ok(UnixListener::from_std(self, &Handle::current())?.incoming())
         }
+
+        fn local_addr(&self) -> Option<String> {
+            let addr = self.local_addr().ok()?;
+            Some(addr.as_pathname()?.display().to_string())
+        }
     }
 }
 
@@ -207,12 +698,14 @@ mod navite_tls {
 #[cfg(feature = "use-rustls")]
 mod rustls {
     use {
-        super::Acceptor,
-        rustls::ServerSession,
+        super::{Acceptor, ConnectionMetadata, PeerCertificates, ServerName},
+        rustls::{ServerSession, Session},
         tokio::io::{AsyncRead, AsyncWrite},
         tokio_rustls::{Accept, TlsAcceptor, TlsStream},
     };
 
+    /// Attaches `ServerName` and `PeerCertificates` to the connection, if available.
+
     impl<T> Acceptor<T> for TlsAcceptor
     where
         T: AsyncRead + AsyncWrite,
@@ -225,6 +718,18 @@ mod rustls {
         fn accept(&self, io: T) -> Self::Accept {
             self.accept(io)
         }
+
+        fn metadata(conn: &Self::Conn, metadata: &mut ConnectionMetadata) {
+            let (_, session) = conn.get_ref();
+            if let Some(server_name) = session.get_sni_hostname() {
+                metadata.insert(ServerName(server_name.to_owned()));
+            }
+            if let Some(certs) = session.get_peer_certificates() {
+                metadata.insert(PeerCertificates::new(
+                    certs.into_iter().map(|cert| cert.0).collect(),
+                ));
+            }
+        }
     }
 }
 
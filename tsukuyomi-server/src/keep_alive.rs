@@ -0,0 +1,185 @@
+//! The persistence of HTTP/1 connections.
+
+use {
+    futures::{Async, Future, Poll},
+    http::{
+        header::{HeaderValue, CONNECTION},
+        Request, Response, Version,
+    },
+};
+
+/// The keep-alive settings of `Server`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KeepAlive {
+    pub(crate) enabled: bool,
+    pub(crate) max_requests: Option<usize>,
+}
+
+impl Default for KeepAlive {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            max_requests: None,
+        }
+    }
+}
+
+impl KeepAlive {
+    /// Checks whether the settings are consistent with the other configuration
+    /// of the server.
+    pub(crate) fn validate(&self, http2_only: bool) -> Result<(), failure::Error> {
+        if self.max_requests == Some(0) {
+            failure::bail!("the maximum number of requests per connection must be positive");
+        }
+        if !self.enabled && self.max_requests.is_some() {
+            failure::bail!(
+                "the maximum number of requests per connection cannot be set \
+                 when keep-alive is disabled"
+            );
+        }
+        if http2_only {
+            if !self.enabled {
+                failure::bail!("keep-alive cannot be disabled on the HTTP/2-only server");
+            }
+            if self.max_requests.is_some() {
+                failure::bail!(
+                    "the maximum number of requests per connection cannot be set \
+                     on the HTTP/2-only server"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn counter(&self) -> RequestCounter {
+        RequestCounter {
+            // hyper closes the connection without notifying the client if keep-alive
+            // is disabled, so every response is marked as the last one.
+            remaining: if self.enabled {
+                self.max_requests
+            } else {
+                Some(1)
+            },
+        }
+    }
+}
+
+/// The number of requests remaining on an HTTP/1 connection.
+#[derive(Debug, Default)]
+pub(crate) struct RequestCounter {
+    remaining: Option<usize>,
+}
+
+impl RequestCounter {
+    /// Counts the request, and returns whether the connection should be closed
+    /// after responding to it.
+    ///
+    /// The streams of HTTP/2 are not counted, since they are multiplexed onto
+    /// a connection rather than sent one after another.
+    pub(crate) fn count<T>(&mut self, request: &Request<T>) -> bool {
+        if request.version() == Version::HTTP_2 {
+            return false;
+        }
+        match self.remaining {
+            Some(ref mut remaining) => {
+                *remaining = remaining.saturating_sub(1);
+                *remaining == 0
+            }
+            None => false,
+        }
+    }
+}
+
+/// A `Future` that marks its response as the last one on the connection.
+///
+/// hyper closes the HTTP/1 connection after sending a response which contains
+/// `Connection: close`.
+#[allow(missing_debug_implementations)]
+pub(crate) struct ClosingResponse<F> {
+    inner: F,
+    close: bool,
+}
+
+impl<F> ClosingResponse<F> {
+    pub(crate) fn new(inner: F, close: bool) -> Self {
+        Self { inner, close }
+    }
+}
+
+impl<F, Bd> Future for ClosingResponse<F>
+where
+    F: Future<Item = Response<Bd>>,
+{
+    type Item = Response<Bd>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mut response = futures::try_ready!(self.inner.poll());
+        if self.close {
+            response
+                .headers_mut()
+                .insert(CONNECTION, HeaderValue::from_static("close"));
+        }
+        Ok(Async::Ready(response))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counter_closes_on_last_request() {
+        let mut counter = KeepAlive {
+            enabled: true,
+            max_requests: Some(2),
+        }
+        .counter();
+        let request = Request::new(());
+        assert!(!counter.count(&request));
+        assert!(counter.count(&request));
+    }
+
+    #[test]
+    fn counter_ignores_http2_streams() {
+        let mut counter = KeepAlive {
+            enabled: true,
+            max_requests: Some(1),
+        }
+        .counter();
+        let mut request = Request::new(());
+        *request.version_mut() = Version::HTTP_2;
+        assert!(!counter.count(&request));
+        assert!(!counter.count(&request));
+    }
+
+    #[test]
+    fn validate_conflicts() {
+        let disabled = KeepAlive {
+            enabled: false,
+            max_requests: None,
+        };
+        assert!(disabled.validate(false).is_ok());
+        assert!(disabled.validate(true).is_err());
+
+        let limited = KeepAlive {
+            enabled: true,
+            max_requests: Some(100),
+        };
+        assert!(limited.validate(false).is_ok());
+        assert!(limited.validate(true).is_err());
+
+        assert!(KeepAlive {
+            enabled: false,
+            max_requests: Some(100),
+        }
+        .validate(false)
+        .is_err());
+        assert!(KeepAlive {
+            enabled: true,
+            max_requests: Some(0),
+        }
+        .validate(false)
+        .is_err());
+    }
+}
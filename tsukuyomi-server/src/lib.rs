@@ -14,23 +14,56 @@
 #![forbid(clippy::unimplemented)]
 
 mod error;
+mod error_handler;
+mod handle;
+mod health;
+mod hooks;
+mod http2;
 mod io;
+mod keep_alive;
+mod limit;
+pub mod proxy;
+pub mod redirect;
 pub mod rt;
+pub mod shard;
+mod socket;
 pub mod test;
+mod timeout;
 
 pub use crate::{
     error::{Error, Result},
-    io::{Acceptor, Listener},
+    error_handler::{DefaultErrorHandler, ErrorAction, ServerError, ServerErrorHandler},
+    handle::ServerHandle,
+    hooks::{AccessLog, ResponseInfo},
+    http2::Http2Config,
+    io::{
+        Acceptor, Bindings, Bound, ConnectionMetadata, Either, Listener, ListenerInfo, PeerAddr,
+        PeerCertificates, ServerName,
+    },
+    limit::{ConcurrencyLimit, Metrics},
+    shard::shard,
+    socket::{TcpConfig, TcpIncoming},
 };
 
 use {
+    crate::{
+        error_handler::ErrorReporter,
+        health::HealthCheck,
+        hooks::{HookedBody, HookedResponse, Hooks},
+        keep_alive::{ClosingResponse, KeepAlive, RequestCounter},
+        limit::{LimitedResponse, Limiter, Limits},
+        redirect::RedirectHttp,
+        timeout::{
+            ConnTimer, Reply, TimedConnection, TimedHandshake, TimedIo, TimedResponse, Timeouts,
+        },
+    },
     futures::{Future, Poll, Stream},
     http::{Request, Response},
     hyper::{
         body::{Body, Payload},
         server::conn::Http,
     },
-    std::{marker::PhantomData, net::SocketAddr, rc::Rc, sync::Arc},
+    std::{marker::PhantomData, net::SocketAddr, rc::Rc, sync::Arc, time::Duration},
     tsukuyomi_service::{MakeServiceRef, Service},
 };
 
@@ -43,35 +76,100 @@ pub struct Server<S, L = SocketAddr, A = (), R = tokio::runtime::Runtime> {
     listener: L,
     acceptor: A,
     protocol: Http,
+    http2_only: bool,
+    keep_alive: KeepAlive,
+    tcp: TcpConfig,
+    timeouts: Timeouts,
+    limits: Limits,
+    hooks: Hooks,
+    health_endpoint: Option<String>,
+    handle: ServerHandle,
+    errors: ErrorReporter,
+    redirect: Option<(SocketAddr, RedirectHttp)>,
     runtime: Option<R>,
 }
 
 impl<S> Server<S> {
     /// Create a new `Server` with the specified `NewService` and default configuration.
     pub fn new(make_service: S) -> Self {
+        let handle = ServerHandle::default();
         Self {
             make_service,
             listener: ([127, 0, 0, 1], 4000).into(),
             acceptor: (),
             protocol: Http::new(),
+            http2_only: false,
+            keep_alive: KeepAlive::default(),
+            tcp: TcpConfig::default(),
+            timeouts: Timeouts::default(),
+            limits: Limits::default(),
+            hooks: Hooks::default(),
+            health_endpoint: None,
+            errors: ErrorReporter::new(handle.clone()),
+            handle,
+            redirect: None,
             runtime: None,
         }
     }
+
+    /// Creates a new `Server` serving the connections from a listener created outside
+    /// of the server.
+    ///
+    /// This is useful for the sockets inherited from the parent process, e.g. by
+    /// the socket activation of systemd.  Additional listeners can be added by
+    /// `add_listener`.
+    pub fn from_listener(
+        make_service: S,
+        listener: std::net::TcpListener,
+    ) -> Server<S, std::net::TcpListener> {
+        Self::new(make_service).bind(listener)
+    }
 }
 
 impl<S, L, A, R> Server<S, L, A, R> {
     /// Sets the transport used by the server.
     ///
     /// By default, a TCP transport with the listener address `"127.0.0.1:4000"` is set.
+    /// Calling this method replaces all listeners set so far.
     pub fn bind<L2>(self, listener: L2) -> Server<S, L2, A, R>
     where
         L2: Listener,
     {
+        self.map_listener(|_| listener)
+    }
+
+    /// Adds a transport used by the server, in addition to the current ones.
+    ///
+    /// The listeners (e.g. IPv4 and IPv6, or TCP and Unix domain socket) are served
+    /// concurrently, and the services can identify the listener that accepted the
+    /// connection by `ListenerInfo` in the extension map of request.
+    ///
+    /// All listeners start listening when the server runs, and it fails immediately
+    /// if any of them cannot be started.
+    pub fn add_listener<L2>(self, listener: L2) -> Server<S, Bound<L, L2>, A, R>
+    where
+        L: Bindings,
+        L2: Listener,
+    {
+        self.map_listener(|current| Bound::new(current, listener))
+    }
+
+    fn map_listener<L2>(self, f: impl FnOnce(L) -> L2) -> Server<S, L2, A, R> {
         Server {
             make_service: self.make_service,
-            listener,
+            listener: f(self.listener),
             acceptor: self.acceptor,
             protocol: self.protocol,
+            http2_only: self.http2_only,
+            keep_alive: self.keep_alive,
+            tcp: self.tcp,
+            timeouts: self.timeouts,
+            limits: self.limits,
+            hooks: self.hooks,
+            health_endpoint: self.health_endpoint,
+            handle: self.handle,
+            errors: self.errors,
+            redirect: self.redirect,
             runtime: self.runtime,
         }
     }
@@ -82,7 +180,7 @@ impl<S, L, A, R> Server<S, L, A, R> {
     /// I/Os directly.
     pub fn acceptor<A2>(self, acceptor: A2) -> Server<S, L, A2, R>
     where
-        L: Listener,
+        L: Bindings,
         A2: Acceptor<L::Conn>,
     {
         Server {
@@ -90,6 +188,16 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener: self.listener,
             acceptor,
             protocol: self.protocol,
+            http2_only: self.http2_only,
+            keep_alive: self.keep_alive,
+            tcp: self.tcp,
+            timeouts: self.timeouts,
+            limits: self.limits,
+            hooks: self.hooks,
+            health_endpoint: self.health_endpoint,
+            handle: self.handle,
+            errors: self.errors,
+            redirect: self.redirect,
             runtime: self.runtime,
         }
     }
@@ -101,6 +209,224 @@ impl<S, L, A, R> Server<S, L, A, R> {
         Self { protocol, ..self }
     }
 
+    /// Sets the HTTP/2-specific configuration to this server.
+    ///
+    /// The values are applied to the current HTTP-level configuration, and hence
+    /// they are overwritten by the subsequent call of `protocol`.
+    pub fn http2(mut self, config: Http2Config) -> Self {
+        config.apply(&mut self.protocol);
+        self
+    }
+
+    /// Sets whether to accept only the connections using HTTP/2.
+    ///
+    /// By default, the server accepts both HTTP/1.x and HTTP/2.  HTTP/2 without TLS
+    /// (h2c) is accepted with prior knowledge, i.e. when the client starts the
+    /// connection with the HTTP/2 connection preface, which is suitable for the
+    /// internal traffic behind a trusted load balancer.  The upgrade from HTTP/1.1
+    /// via `Upgrade: h2c` is not supported.
+    pub fn http2_only(mut self, enabled: bool) -> Self {
+        self.protocol.http2_only(enabled);
+        self.http2_only = enabled;
+        self
+    }
+
+    /// Sets the time limit for receiving the header of a request.
+    ///
+    /// The limit is counted from the start of connection, including the handshake of
+    /// the acceptor (e.g. TLS or PROXY protocol), or from the first byte of the
+    /// subsequent request on the persistent connection.  The connection is closed
+    /// if the limit is exceeded, in order to protect the server from the clients that
+    /// send the header slowly.  The default value is 30 seconds, and `None` disables it.
+    pub fn header_read_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.header_read = timeout;
+        self
+    }
+
+    /// Sets the time limit for the service to return the response after the header of
+    /// request has been received.
+    ///
+    /// If the limit is exceeded, the request is replied with `408 Request Timeout` and
+    /// the connection is closed.  The limit does not apply to the streaming of response
+    /// body, nor the connections upgraded to another protocol (e.g. WebSocket).
+    /// The default value is 60 seconds, and `None` disables it.
+    pub fn request_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.request = timeout;
+        self
+    }
+
+    /// Sets the time limit for the persistent connection to wait for the next request.
+    ///
+    /// The idle connection is closed if the limit is exceeded.  The default value is
+    /// 75 seconds, and `None` disables it.
+    pub fn keep_alive_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeouts.keep_alive = timeout;
+        self
+    }
+
+    /// Sets whether to keep the HTTP/1 connections alive after responding.
+    ///
+    /// If disabled, each response is sent with `Connection: close` and the connection
+    /// is closed after it.  The setting does not apply to HTTP/2, and hence disabling
+    /// it on the HTTP/2-only server is rejected when the server starts.  The default
+    /// value is `true`.
+    pub fn keep_alive(mut self, enabled: bool) -> Self {
+        self.protocol.keep_alive(enabled);
+        self.keep_alive.enabled = enabled;
+        self
+    }
+
+    /// Sets the maximum number of requests served on an HTTP/1 connection.
+    ///
+    /// The response to the last request is sent with `Connection: close`, and then
+    /// the connection is closed.  This is useful for spreading the long-lived clients
+    /// over the instances behind a load balancer.  The server fails to start if the
+    /// value is zero, or if it is combined with `keep_alive(false)` or `http2_only(true)`.
+    /// By default, the number is unlimited.
+    pub fn max_requests_per_connection(mut self, max: usize) -> Self {
+        self.keep_alive.max_requests = Some(max);
+        self
+    }
+
+    /// Sets the socket-level configuration of the TCP listeners.
+    ///
+    /// The configuration applies to all TCP listeners bound to this server,
+    /// including the one started by `redirect_http`.
+    pub fn tcp(self, config: TcpConfig) -> Self {
+        Self {
+            tcp: config,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of connections open at the same time.
+    ///
+    /// While the number of connections reaches the limit, the server stops accepting
+    /// the new connections and leaves them in the backlog of listeners until some of
+    /// the open connections are closed.  By default, the number is unlimited.
+    pub fn max_connections(mut self, max: usize) -> Self {
+        self.limits.max_connections = Some(max);
+        self
+    }
+
+    /// Sets the limit of requests processed by the service concurrently.
+    ///
+    /// The limit is checked when the service is polled for readiness, and the
+    /// requests exceeding it are replied with `503 Service Unavailable` without
+    /// calling the service.  By default, the number is unlimited.
+    pub fn concurrency_limit(mut self, limit: ConcurrencyLimit) -> Self {
+        self.limits.concurrency = Some(limit);
+        self
+    }
+
+    /// Returns the gauges of the connections and requests processed by this server.
+    ///
+    /// The returned value shares the gauges with the server, and hence it can be used
+    /// to observe the server while running (e.g. from another thread).
+    pub fn metrics(&self) -> Metrics {
+        self.limits.metrics.clone()
+    }
+
+    /// Sets the handler of the errors occurred while accepting and serving the connections.
+    ///
+    /// The handler receives the errors categorized by `ServerError`, and decides whether
+    /// the server continues or shuts down.  The failures of accepting a connection do not
+    /// stop the server by themselves, and the server backs off before accepting again if
+    /// the resources are exhausted or the failures persist.  The handler also applies to the service started
+    /// by `redirect_http`.  By default, `DefaultErrorHandler` is set, which logs the errors
+    /// and continues.
+    pub fn error_handler(mut self, handler: impl ServerErrorHandler) -> Self {
+        self.errors.set_handler(handler);
+        self
+    }
+
+    /// Sets the path of the health check endpoint answered by the server itself.
+    ///
+    /// The `GET` and `HEAD` requests to the path are replied with `200 OK`, or with
+    /// `503 Service Unavailable` while the server is marked as not ready by
+    /// `ServerHandle::set_ready`.  The endpoint is answered before the request is
+    /// passed to the service, and hence it is not affected by the middlewares of
+    /// the application (e.g. authentication), the concurrency limit nor the hooks.
+    /// This is suitable for the liveness and readiness probes of an orchestrator.
+    pub fn health_endpoint(self, path: impl Into<String>) -> Self {
+        Self {
+            health_endpoint: Some(path.into()),
+            ..self
+        }
+    }
+
+    /// Returns a handle to control the state of this server.
+    ///
+    /// The returned value shares the state with the server, and hence it can be used
+    /// to flip the readiness while running (e.g. from the application or another thread).
+    pub fn handle(&self) -> ServerHandle {
+        self.handle.clone()
+    }
+
+    /// Registers a hook called when the header of request has been received, before
+    /// the request is passed to the service.
+    ///
+    /// The hooks must not block, since they are called on the task of connection.
+    /// If this method is called multiple times, the hooks are called in the order
+    /// of registration.
+    pub fn on_request(
+        mut self,
+        hook: impl Fn(&Request<hyper::Body>) + Send + Sync + 'static,
+    ) -> Self {
+        self.hooks.add_on_request(hook);
+        self
+    }
+
+    /// Registers a hook called when the response has been sent, or aborted.
+    ///
+    /// Unlike the modifiers of handlers, the hook observes the responses generated
+    /// by the server itself (e.g. `503 Service Unavailable` by `concurrency_limit`),
+    /// and the number of bytes of response body streamed after the service returns
+    /// the response.
+    ///
+    /// The hook is called when the response body is dropped, and hence the responses
+    /// to the requests failed in the service (i.e. the connection is closed without
+    /// sending any response) are not observed.
+    pub fn on_response(mut self, hook: impl Fn(&ResponseInfo) + Send + Sync + 'static) -> Self {
+        self.hooks.add_on_response(hook);
+        self
+    }
+
+    /// Registers a hook called when a connection fails, e.g. due to the errors of
+    /// acceptor, service or HTTP protocol.
+    ///
+    /// The hook is called just before the error is passed to the handler set by
+    /// `error_handler`, which logs the error by default.  The failures of accepting
+    /// a connection are only passed to the handler.
+    pub fn on_connection_error(
+        mut self,
+        hook: impl Fn(&(dyn std::error::Error + 'static)) + Send + Sync + 'static,
+    ) -> Self {
+        self.errors.add_on_connection_error(hook);
+        self
+    }
+
+    /// Registers an access logger on top of the hook of `on_response`.
+    pub fn access_log(self, log: AccessLog) -> Self {
+        self.on_response(move |info| log.log(info))
+    }
+
+    /// Starts the service redirecting to HTTPS on the specified address, alongside
+    /// the listeners of this server.
+    ///
+    /// All requests on the address are answered by `RedirectService` without reaching
+    /// the service of this server.  The timeouts and hooks of this server also apply
+    /// to the redirect service, but the limits do not.
+    pub fn redirect_http(mut self, addr: SocketAddr, config: RedirectHttp) -> Self {
+        self.redirect = Some((addr, config));
+        self
+    }
+
+    fn health_check(&self) -> Option<HealthCheck> {
+        let path = self.health_endpoint.as_ref()?;
+        Some(HealthCheck::new(path, self.handle.clone()))
+    }
+
     /// Sets the instance of runtime to the specified `runtime`.
     pub fn runtime<R2>(self, runtime: R2) -> Server<S, L, A, R2> {
         Server {
@@ -108,6 +434,16 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener: self.listener,
             acceptor: self.acceptor,
             protocol: self.protocol,
+            http2_only: self.http2_only,
+            keep_alive: self.keep_alive,
+            tcp: self.tcp,
+            timeouts: self.timeouts,
+            limits: self.limits,
+            hooks: self.hooks,
+            health_endpoint: self.health_endpoint,
+            handle: self.handle,
+            errors: self.errors,
+            redirect: self.redirect,
             runtime: Some(runtime),
         }
     }
@@ -121,6 +457,16 @@ impl<S, L, A, R> Server<S, L, A, R> {
             listener: self.listener,
             acceptor: self.acceptor,
             protocol: self.protocol,
+            http2_only: self.http2_only,
+            keep_alive: self.keep_alive,
+            tcp: self.tcp,
+            timeouts: self.timeouts,
+            limits: self.limits,
+            hooks: self.hooks,
+            health_endpoint: self.health_endpoint,
+            handle: self.handle,
+            errors: self.errors,
+            redirect: self.redirect,
             runtime: None,
         }
     }
@@ -133,45 +479,95 @@ macro_rules! serve {
         listener: $listener:expr,
         acceptor: $acceptor:expr,
         protocol: $protocol:expr,
+        keep_alive: $keep_alive:expr,
+        tcp: $tcp:expr,
+        timeouts: $timeouts:expr,
+        limits: $limits:expr,
+        hooks: $hooks:expr,
+        errors: $errors:expr,
+        health: $health:expr,
         spawn: $spawn:expr,
     ) => {{
         let make_service = $make_service;
         let listener = $listener;
         let acceptor = $acceptor;
         let protocol = $protocol;
+        let keep_alive: KeepAlive = $keep_alive;
+        let tcp: TcpConfig = $tcp;
+        let timeouts = $timeouts;
+        let limits = $limits;
+        let hooks = $hooks;
+        let errors: ErrorReporter = $errors;
+        let health: Option<HealthCheck> = $health;
         let spawn = $spawn;
 
-        let incoming = listener
-            .listen()
-            .map_err(|err| failure::Error::from_boxed_compat(err.into()))?;
-        incoming
-            .map_err(|e| log::error!("transport error: {}", e.into()))
-            .for_each(move |io| {
-                let accept = acceptor
-                    .accept(io)
-                    .map_err(|e| log::error!("acceptor error: {}", e.into()));
-
-                let protocol = protocol.clone();
-                let make_service = make_service.clone();
-                let task = accept.and_then(move |io| {
-                    let service = make_service
-                        .make_service_ref(&io)
-                        .map_err(|e| log::error!("make_service error: {}", e.into()));
-                    service
-                        .and_then(|service| {
-                            ReadyService(Some(service), PhantomData)
-                                .map_err(|e| log::error!("service error: {}", e.into()))
-                        })
-                        .and_then(move |service| {
-                            protocol
-                                .serve_connection(io, LiftedHttpService { service })
-                                .with_upgrades()
-                                .map_err(|e| log::error!("HTTP protocol error: {}", e))
-                        })
+        let peer_addr = crate::io::peer_addr_fn(&listener);
+        let incoming = limits.throttle(errors.supervise(listener.listen_all(&mut 0, &tcp)?));
+        incoming.for_each(move |((io, listener), connection)| {
+            let hooks = hooks.clone();
+            let errors = errors.clone();
+            // the handshake shares the deadline with reading the header of the first request.
+            let timer = ConnTimer::new(timeouts);
+            let peer_addr = peer_addr(&io);
+            let accept = TimedHandshake::new(crate::io::accept(&acceptor, io, peer_addr), &timer)
+                .map_err({
+                    let errors = errors.clone();
+                    move |e| {
+                        let e: crate::CritError = e.into();
+                        errors.report(ServerError::Handshake(&*e));
+                    }
+                });
+
+            let protocol = protocol.clone();
+            let make_service = make_service.clone();
+            let limiter = limits.limiter();
+            let health = health.clone();
+            let task = accept.and_then(move |(io, metadata)| {
+                let service = make_service.make_service_ref(&io).map_err({
+                    let errors = errors.clone();
+                    move |e| {
+                        let e: crate::CritError = e.into();
+                        errors.report(ServerError::Service(&*e));
+                    }
                 });
-                spawn(task);
-                Ok(())
-            })
+                service
+                    .and_then({
+                        let errors = errors.clone();
+                        move |service| {
+                            ReadyService(Some(service), PhantomData).map_err(move |e| {
+                                let e: crate::CritError = e.into();
+                                errors.report(ServerError::Service(&*e));
+                            })
+                        }
+                    })
+                    .and_then(move |service| {
+                        let conn = protocol
+                            .serve_connection(
+                                TimedIo::new(io, timer.clone()),
+                                LiftedHttpService {
+                                    service,
+                                    listener: Some(listener),
+                                    metadata,
+                                    timer: Some(timer.clone()),
+                                    limiter: Some(limiter),
+                                    overloaded: false,
+                                    hooks,
+                                    requests: keep_alive.counter(),
+                                    health,
+                                },
+                            )
+                            .with_upgrades();
+                        TimedConnection::new(conn, timer)
+                            .map_err(move |e| errors.report(ServerError::Protocol(&e)))
+                    })
+                    .then(move |result| {
+                        drop(connection);
+                        result
+                    })
+            });
+            spawn(task);
+            Ok(())
+        })
     }};
 }
 
@@ -187,19 +583,69 @@ where
     S::Service: Send + 'static,
     <S::Service as Service<Request<hyper::Body>>>::Future: Send + 'static,
     Bd: Payload,
-    T: Listener,
+    T: Bindings + 'static,
     T::Incoming: Send + 'static,
     A: Acceptor<T::Conn> + Send + 'static,
     A::Conn: Send + 'static,
     A::Error: Into<crate::CritError>,
     A::Accept: Send + 'static,
 {
-    pub fn run(self) -> crate::Result<()> {
-        let mut runtime = match self.runtime {
+    /// Runs the server on the multi-threaded runtime until all tasks are completed.
+    ///
+    /// If no runtime is set by `Server::runtime`, a new instance of runtime is created
+    /// with the default configuration.
+    pub fn run(mut self) -> crate::Result<()> {
+        let mut runtime = match self.runtime.take() {
             Some(rt) => rt,
             None => tokio::runtime::Runtime::new()?,
         };
 
+        self.run_on(&mut runtime)?;
+        runtime.shutdown_on_idle().wait().unwrap();
+
+        Ok(())
+    }
+
+    /// Spawns the server onto the existing runtime, and returns immediately.
+    ///
+    /// The server is executed alongside the other tasks spawned onto the runtime,
+    /// until the runtime shuts down.  The runtime set by `Server::runtime` is not used.
+    pub fn run_on(self, runtime: &mut tokio::runtime::Runtime) -> crate::Result<()> {
+        runtime.spawn(self.into_future()?);
+        Ok(())
+    }
+
+    /// Converts the server into a `Future` that accepts and serves the incoming
+    /// connections, without spawning it.
+    ///
+    /// The listeners are bound when this method is called.  The returned future must
+    /// be executed within the context of a Tokio runtime, since the connections are
+    /// spawned onto the default executor.  The runtime set by `Server::runtime` is
+    /// not used.
+    pub fn into_future(self) -> crate::Result<impl Future<Item = (), Error = ()> + Send + 'static> {
+        self.keep_alive.validate(self.http2_only)?;
+        let health = self.health_check();
+
+        let redirect = match self.redirect {
+            Some((addr, config)) => Some(serve! {
+                make_service: Arc::new(config),
+                listener: addr,
+                acceptor: (),
+                protocol: Arc::new(
+                    Http::new().with_executor(tokio::executor::DefaultExecutor::current())
+                ),
+                keep_alive: KeepAlive::default(),
+                tcp: self.tcp,
+                timeouts: self.timeouts,
+                limits: Limits::default(),
+                hooks: self.hooks.clone(),
+                errors: self.errors.clone(),
+                health: None,
+                spawn: |future| crate::rt::spawn(future),
+            }),
+            None => None,
+        };
+
         let serve = serve! {
             make_service: Arc::new(self.make_service),
             listener: self.listener,
@@ -207,13 +653,17 @@ where
             protocol: Arc::new(
                 self.protocol.with_executor(tokio::executor::DefaultExecutor::current())
             ),
+            keep_alive: self.keep_alive,
+            tcp: self.tcp,
+            timeouts: self.timeouts,
+            limits: self.limits,
+            hooks: self.hooks,
+            errors: self.errors,
+            health: health,
             spawn: |future| crate::rt::spawn(future),
         };
 
-        runtime.spawn(serve);
-        runtime.shutdown_on_idle().wait().unwrap();
-
-        Ok(())
+        Ok(serve.join(redirect).map(|_| ()))
     }
 }
 
@@ -226,19 +676,71 @@ where
     S::Service: 'static,
     <S::Service as Service<Request<hyper::Body>>>::Future: 'static,
     Bd: Payload,
-    T: Listener,
+    T: Bindings + 'static,
     T::Incoming: 'static,
     A: Acceptor<T::Conn> + 'static,
     A::Conn: Send + 'static,
     A::Error: Into<crate::CritError>,
     A::Accept: 'static,
 {
-    pub fn run(self) -> crate::Result<()> {
-        let mut runtime = match self.runtime {
+    /// Runs the server on the current thread until all tasks are completed.
+    ///
+    /// If no runtime is set by `Server::runtime`, a new instance of runtime is created.
+    pub fn run(mut self) -> crate::Result<()> {
+        let mut runtime = match self.runtime.take() {
             Some(rt) => rt,
             None => tokio::runtime::current_thread::Runtime::new()?,
         };
 
+        self.run_on(&mut runtime)?;
+        runtime.run()?;
+
+        Ok(())
+    }
+
+    /// Spawns the server onto the existing single-threaded runtime, and returns immediately.
+    ///
+    /// The server is executed alongside the other tasks spawned onto the runtime,
+    /// the next time the runtime is driven (e.g. by `Runtime::run`).  The runtime set
+    /// by `Server::runtime` is not used.
+    pub fn run_on(
+        self,
+        runtime: &mut tokio::runtime::current_thread::Runtime,
+    ) -> crate::Result<()> {
+        runtime.spawn(self.into_future()?);
+        Ok(())
+    }
+
+    /// Converts the server into a `Future` that accepts and serves the incoming
+    /// connections, without spawning it.
+    ///
+    /// The returned future is not `Send`, and must be executed on a single-threaded
+    /// runtime since the connections are spawned onto the current thread.  The runtime
+    /// set by `Server::runtime` is not used.
+    pub fn into_future(self) -> crate::Result<impl Future<Item = (), Error = ()> + 'static> {
+        self.keep_alive.validate(self.http2_only)?;
+        let health = self.health_check();
+
+        let redirect = match self.redirect {
+            Some((addr, config)) => Some(serve! {
+                make_service: Rc::new(config),
+                listener: addr,
+                acceptor: (),
+                protocol: Rc::new(
+                    Http::new().with_executor(tokio::runtime::current_thread::TaskExecutor::current())
+                ),
+                keep_alive: KeepAlive::default(),
+                tcp: self.tcp,
+                timeouts: self.timeouts,
+                limits: Limits::default(),
+                hooks: self.hooks.clone(),
+                errors: self.errors.clone(),
+                health: None,
+                spawn: |future| tokio::runtime::current_thread::spawn(future),
+            }),
+            None => None,
+        };
+
         let serve = serve! {
             make_service: Rc::new(self.make_service),
             listener: self.listener,
@@ -246,19 +748,111 @@ where
             protocol: Rc::new(
                 self.protocol.with_executor(tokio::runtime::current_thread::TaskExecutor::current())
             ),
+            keep_alive: self.keep_alive,
+            tcp: self.tcp,
+            timeouts: self.timeouts,
+            limits: self.limits,
+            hooks: self.hooks,
+            errors: self.errors,
+            health: health,
             spawn: |future| tokio::runtime::current_thread::spawn(future),
         };
 
-        let _ = runtime.block_on(serve);
-        runtime.run()?;
+        Ok(serve.join(redirect).map(|_| ()))
+    }
+}
 
-        Ok(())
+impl<S, T, A, R, Bd> Server<S, T, A, R>
+where
+    S: MakeServiceRef<A::Conn, Request<hyper::Body>, Response = Response<Bd>> + 'static,
+    S::Error: Into<crate::CritError>,
+    S::MakeError: Into<crate::CritError>,
+    S::Future: 'static,
+    S::Service: 'static,
+    <S::Service as Service<Request<hyper::Body>>>::Future: 'static,
+    Bd: Payload,
+    T: Bindings + 'static,
+    T::Incoming: 'static,
+    A: Acceptor<T::Conn> + 'static,
+    A::Conn: Send + 'static,
+    A::Error: Into<crate::CritError>,
+    A::Accept: 'static,
+{
+    /// Runs the server on a new single-threaded runtime until all tasks are completed.
+    ///
+    /// This is a shorthand for `self.current_thread().run()`.
+    ///
+    /// Since all tasks are executed on the current thread, the following values are
+    /// *not* required to be `Send` or `Sync` (e.g. `tsukuyomi::app::LocalApp` can be
+    /// used as the service):
+    ///
+    /// * the factory of services, and the services created by it,
+    /// * the futures returned from the factory and the services,
+    /// * the listeners and the acceptor.
+    ///
+    /// The connections returned from the acceptor are still required to be `Send`,
+    /// because they may be moved into the upgraded connections (e.g. WebSocket).
+    ///
+    /// There is no blocking pool on the single-threaded runtime.  The blocking
+    /// sections created by `rt::blocking` are executed inline on the current thread,
+    /// stalling the other connections while they run.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// # use {http::{Request, Response}, hyper::Body, std::rc::Rc, tokio::net::TcpStream};
+    /// # use tsukuyomi_service::{make_service_ref, service_fn};
+    /// # use tsukuyomi_server::Server;
+    /// # fn main() -> tsukuyomi_server::Result<()> {
+    /// // `Rc` is not `Send`.
+    /// let message = Rc::new("Hello");
+    ///
+    /// let make_service = make_service_ref(move |_: &TcpStream| {
+    ///     let message = message.clone();
+    ///     Ok::<_, std::io::Error>(service_fn(move |_: Request<Body>| {
+    ///         Ok::<_, std::io::Error>(Response::new(Body::from(*message)))
+    ///     }))
+    /// });
+    ///
+    /// Server::new(make_service).run_single_threaded()
+    /// # }
+    /// ```
+    ///
+    /// The same service cannot be run on the multi-threaded runtime:
+    ///
+    /// ```compile_fail
+    /// # use {http::{Request, Response}, hyper::Body, std::rc::Rc, tokio::net::TcpStream};
+    /// # use tsukuyomi_service::{make_service_ref, service_fn};
+    /// # use tsukuyomi_server::Server;
+    /// # fn main() -> tsukuyomi_server::Result<()> {
+    /// let message = Rc::new("Hello");
+    ///
+    /// let make_service = make_service_ref(move |_: &TcpStream| {
+    ///     let message = message.clone();
+    ///     Ok::<_, std::io::Error>(service_fn(move |_: Request<Body>| {
+    ///         Ok::<_, std::io::Error>(Response::new(Body::from(*message)))
+    ///     }))
+    /// });
+    ///
+    /// Server::new(make_service).run()
+    /// # }
+    /// ```
+    pub fn run_single_threaded(self) -> crate::Result<()> {
+        self.current_thread().run()
     }
 }
 
 #[allow(missing_debug_implementations)]
-struct LiftedHttpService<S> {
-    service: S,
+pub(crate) struct LiftedHttpService<S> {
+    pub(crate) service: S,
+    pub(crate) listener: Option<Arc<ListenerInfo>>,
+    pub(crate) metadata: ConnectionMetadata,
+    pub(crate) timer: Option<ConnTimer>,
+    pub(crate) limiter: Option<Limiter>,
+    pub(crate) overloaded: bool,
+    pub(crate) hooks: Hooks,
+    pub(crate) requests: RequestCounter,
+    pub(crate) health: Option<HealthCheck>,
 }
 
 impl<S, Bd> hyper::service::Service for LiftedHttpService<S>
@@ -268,13 +862,55 @@ where
     S::Error: Into<crate::CritError>,
 {
     type ReqBody = Body;
-    type ResBody = Bd;
+    type ResBody = HookedBody<crate::timeout::TimedBody<Bd>>;
     type Error = S::Error;
-    type Future = S::Future;
+    type Future = HookedResponse<ClosingResponse<TimedResponse<LimitedResponse<S::Future>>>>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        // the requests are shed without calling the service while the number of
+        // in-flight requests reaches the limit, rather than waiting for the service.
+        self.overloaded = self.limiter.as_ref().map_or(false, Limiter::is_full);
+        if self.overloaded {
+            return Ok(futures::Async::Ready(()));
+        }
+        self.service.poll_ready()
+    }
 
     #[inline]
-    fn call(&mut self, request: Request<Body>) -> Self::Future {
-        self.service.call(request)
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        if let Some(ref listener) = self.listener {
+            request.extensions_mut().insert((**listener).clone());
+        }
+        self.metadata.apply(request.extensions_mut());
+        let close = self.requests.count(&request);
+        let overloaded = std::mem::replace(&mut self.overloaded, false);
+        let health = self
+            .health
+            .as_ref()
+            .and_then(|health| health.check(&request));
+        let (reply, tracking) = match health {
+            // the health checks are not reported to the hooks, e.g. the access log.
+            Some(response) => (Reply::Server(Some(response)), None),
+            None => {
+                let tracking = self.hooks.start(&request);
+                let reply = match self.limiter {
+                    Some(ref limiter) => match Some(limiter)
+                        .filter(|_| !overloaded)
+                        .and_then(Limiter::try_acquire)
+                    {
+                        Some(in_flight) => Reply::Service(LimitedResponse::new(
+                            self.service.call(request),
+                            Some(in_flight),
+                        )),
+                        None => Reply::Server(Some(limiter.reject())),
+                    },
+                    None => Reply::Service(LimitedResponse::new(self.service.call(request), None)),
+                };
+                (reply, tracking)
+            }
+        };
+        let response = TimedResponse::new(reply, self.timer.clone());
+        HookedResponse::new(ClosingResponse::new(response, close), tracking)
     }
 }
 
@@ -0,0 +1,264 @@
+//! Limits of the connections and requests processed by `Server` concurrently.
+
+use {
+    futures::{task::AtomicTask, Async, Future, Poll, Stream},
+    http::{
+        header::{HeaderValue, RETRY_AFTER},
+        Response, StatusCode,
+    },
+    std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        },
+        time::Duration,
+    },
+};
+
+/// The limit of the requests processed by the service concurrently.
+///
+/// The requests exceeding the limit are rejected with `503 Service Unavailable`
+/// without calling the service, so that the server sheds the load instead of
+/// queueing the requests indefinitely.
+#[derive(Debug, Clone, Copy)]
+pub struct ConcurrencyLimit {
+    max: usize,
+    retry_after: Option<Duration>,
+}
+
+impl ConcurrencyLimit {
+    /// Creates a `ConcurrencyLimit` which allows at most `max` requests to be processed
+    /// at the same time.
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            retry_after: None,
+        }
+    }
+
+    /// Sets the duration sent by `Retry-After` with the rejected requests.
+    ///
+    /// The value is rounded up to seconds.  By default, `Retry-After` is not sent.
+    pub fn retry_after(self, retry_after: Duration) -> Self {
+        Self {
+            retry_after: Some(retry_after),
+            ..self
+        }
+    }
+}
+
+/// The gauges of the connections and requests being processed by `Server`.
+///
+/// The value is obtained by `Server::metrics` before running the server, and it
+/// continues to observe the server after `Server::run` is called.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    inner: Arc<Gauges>,
+}
+
+#[derive(Debug, Default)]
+struct Gauges {
+    connections: AtomicUsize,
+    in_flight_requests: AtomicUsize,
+    acceptor: AtomicTask,
+}
+
+impl Metrics {
+    /// Returns the number of connections currently open.
+    pub fn connections(&self) -> usize {
+        self.inner.connections.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of requests currently processed by the service.
+    ///
+    /// The request is counted until the service returns the header of its response.
+    pub fn in_flight_requests(&self) -> usize {
+        self.inner.in_flight_requests.load(Ordering::SeqCst)
+    }
+}
+
+/// The limits configured on `Server`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Limits {
+    pub(crate) max_connections: Option<usize>,
+    pub(crate) concurrency: Option<ConcurrencyLimit>,
+    pub(crate) metrics: Metrics,
+}
+
+impl Limits {
+    /// Wraps the stream of incoming connections so that it stops accepting the new
+    /// connections while the number of open connections reaches the limit.
+    pub(crate) fn throttle<S: Stream>(&self, incoming: S) -> Throttled<S> {
+        Throttled {
+            incoming,
+            max: self.max_connections,
+            metrics: self.metrics.clone(),
+        }
+    }
+
+    pub(crate) fn limiter(&self) -> Limiter {
+        Limiter {
+            concurrency: self.concurrency,
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+/// A `Stream` of incoming connections that applies backpressure to the listener.
+///
+/// The connections waiting to be accepted remain in the backlog of listener.
+#[allow(missing_debug_implementations)]
+pub(crate) struct Throttled<S> {
+    incoming: S,
+    max: Option<usize>,
+    metrics: Metrics,
+}
+
+impl<S> Stream for Throttled<S>
+where
+    S: Stream,
+{
+    type Item = (S::Item, Connection);
+    type Error = S::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        if let Some(max) = self.max {
+            if self.metrics.connections() >= max {
+                self.metrics.inner.acceptor.register();
+                // check again, since a connection may have been closed before registering.
+                if self.metrics.connections() >= max {
+                    return Ok(Async::NotReady);
+                }
+            }
+        }
+
+        let item = futures::try_ready!(self.incoming.poll());
+        Ok(Async::Ready(item.map(|item| {
+            self.metrics
+                .inner
+                .connections
+                .fetch_add(1, Ordering::SeqCst);
+            (
+                item,
+                Connection {
+                    metrics: self.metrics.clone(),
+                },
+            )
+        })))
+    }
+}
+
+/// A guard that counts the connection as open until dropped.
+#[derive(Debug)]
+pub(crate) struct Connection {
+    metrics: Metrics,
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.metrics
+            .inner
+            .connections
+            .fetch_sub(1, Ordering::SeqCst);
+        self.metrics.inner.acceptor.notify();
+    }
+}
+
+/// The counter of in-flight requests shared by the connections.
+#[derive(Debug, Clone)]
+pub(crate) struct Limiter {
+    concurrency: Option<ConcurrencyLimit>,
+    metrics: Metrics,
+}
+
+impl Limiter {
+    /// Returns whether the number of in-flight requests reaches the limit.
+    pub(crate) fn is_full(&self) -> bool {
+        self.concurrency.map_or(false, |concurrency| {
+            self.metrics.in_flight_requests() >= concurrency.max
+        })
+    }
+
+    /// Counts a request as in-flight, or returns `None` if the number of in-flight
+    /// requests reaches the limit.
+    pub(crate) fn try_acquire(&self) -> Option<InFlight> {
+        let in_flight_requests = &self.metrics.inner.in_flight_requests;
+        let mut current = in_flight_requests.load(Ordering::SeqCst);
+        loop {
+            if let Some(ref concurrency) = self.concurrency {
+                if current >= concurrency.max {
+                    return None;
+                }
+            }
+            match in_flight_requests.compare_exchange(
+                current,
+                current + 1,
+                Ordering::SeqCst,
+                Ordering::SeqCst,
+            ) {
+                Ok(..) => break,
+                Err(actual) => current = actual,
+            }
+        }
+        Some(InFlight {
+            metrics: self.metrics.clone(),
+        })
+    }
+
+    /// Creates the response which rejects the request exceeding the limit.
+    pub(crate) fn reject(&self) -> Response<()> {
+        log::debug!("the request is rejected due to the concurrency limit");
+        let mut response = Response::new(());
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+        if let Some(retry_after) = self.concurrency.and_then(|c| c.retry_after) {
+            let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+            response
+                .headers_mut()
+                .insert(RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
+    }
+}
+
+/// A guard that counts the request as in-flight until dropped.
+#[derive(Debug)]
+pub(crate) struct InFlight {
+    metrics: Metrics,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.metrics
+            .inner
+            .in_flight_requests
+            .fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+/// A `Future` that counts the request as in-flight until the service returns
+/// the response.
+#[allow(missing_debug_implementations)]
+pub(crate) struct LimitedResponse<F> {
+    inner: F,
+    in_flight: Option<InFlight>,
+}
+
+impl<F> LimitedResponse<F> {
+    pub(crate) fn new(inner: F, in_flight: Option<InFlight>) -> Self {
+        Self { inner, in_flight }
+    }
+}
+
+impl<F> Future for LimitedResponse<F>
+where
+    F: Future,
+{
+    type Item = F::Item;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = futures::try_ready!(self.inner.poll());
+        self.in_flight.take();
+        Ok(Async::Ready(response))
+    }
+}
@@ -0,0 +1,527 @@
+//! Support for the PROXY protocol used by the load balancers such as HAProxy and ELB.
+//!
+//! The load balancer with the PROXY protocol enabled prepends a header to each
+//! connection, which tells the address of the original client.  `ProxyProtocol`
+//! strips the header (version 1 or 2) before the inner acceptor and hyper see the
+//! connection, and attaches the addresses to it as `ProxiedAddr`.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use tsukuyomi_server::{proxy::ProxyProtocol, Server};
+//!
+//! Server::new(app)
+//!     .acceptor(ProxyProtocol::new(tls_acceptor)) // or `ProxyProtocol::new(())`
+//!     .run()?;
+//! ```
+//!
+//! The connections without a valid header are closed, and hence this acceptor must
+//! be enabled only if all of the clients are the load balancers speaking the protocol.
+
+use {
+    crate::io::{Acceptor, ConnectionMetadata},
+    futures::{Async, Future, Poll},
+    std::{
+        io,
+        net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+        sync::Arc,
+    },
+    tokio::io::{AsyncRead, AsyncWrite},
+};
+
+type BoxedError = Box<dyn std::error::Error + Send + Sync + 'static>;
+
+/// The signature of the PROXY protocol version 1.
+const V1_SIGNATURE: &[u8] = b"PROXY ";
+
+/// The maximum length of the version 1 header, including the trailing CRLF.
+const V1_MAX_LEN: usize = 107;
+
+/// The signature of the PROXY protocol version 2.
+const V2_SIGNATURE: &[u8] = b"\r\n\r\n\0\r\nQUIT\n";
+
+/// The length of the fixed part of the version 2 header.
+const V2_HEADER_LEN: usize = 16;
+
+/// The addresses of the original connection reported by the PROXY protocol header.
+///
+/// The value is attached to the connection by `ProxyProtocol`, and hence it can be
+/// retrieved from the extension map of each request.  It is not attached if the proxy
+/// does not report the addresses (e.g. `PROXY UNKNOWN`, or the health checks by the
+/// proxy itself).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ProxiedAddr {
+    source: SocketAddr,
+    destination: SocketAddr,
+}
+
+impl ProxiedAddr {
+    /// Returns the address of the original client.
+    pub fn source(&self) -> SocketAddr {
+        self.source
+    }
+
+    /// Returns the address to which the original client connected.
+    pub fn destination(&self) -> SocketAddr {
+        self.destination
+    }
+}
+
+/// An `Acceptor` that strips the PROXY protocol header, and then passes the remaining
+/// stream to the inner acceptor.
+#[derive(Debug)]
+pub struct ProxyProtocol<A> {
+    inner: Arc<A>,
+}
+
+impl<A> ProxyProtocol<A> {
+    /// Creates a `ProxyProtocol` with the specified inner acceptor.
+    ///
+    /// Use `()` as the inner acceptor if the connection is not encrypted.
+    pub fn new(inner: A) -> Self {
+        Self {
+            inner: Arc::new(inner),
+        }
+    }
+}
+
+impl<T, A> Acceptor<T> for ProxyProtocol<A>
+where
+    T: AsyncRead + AsyncWrite,
+    A: Acceptor<ProxiedIo<T>>,
+    A::Error: Into<BoxedError>,
+{
+    type Conn = ProxiedConn<A::Conn>;
+    type Error = BoxedError;
+    type Accept = Accept<T, A>;
+
+    fn accept(&self, io: T) -> Self::Accept {
+        Accept {
+            state: State::Header {
+                io: Some(io),
+                buf: Vec::new(),
+            },
+            acceptor: self.inner.clone(),
+        }
+    }
+
+    fn metadata(conn: &Self::Conn, metadata: &mut ConnectionMetadata) {
+        if let Some(addr) = conn.addr {
+            metadata.insert(addr);
+        }
+        A::metadata(&conn.conn, metadata);
+    }
+}
+
+/// The `Future` returned by `ProxyProtocol::accept`.
+#[allow(missing_debug_implementations)]
+pub struct Accept<T, A: Acceptor<ProxiedIo<T>>> {
+    state: State<T, A::Accept>,
+    acceptor: Arc<A>,
+}
+
+enum State<T, F> {
+    Header {
+        io: Option<T>,
+        buf: Vec<u8>,
+    },
+    Inner {
+        accept: F,
+        addr: Option<ProxiedAddr>,
+    },
+}
+
+impl<T, A> Future for Accept<T, A>
+where
+    T: AsyncRead + AsyncWrite,
+    A: Acceptor<ProxiedIo<T>>,
+    A::Error: Into<BoxedError>,
+{
+    type Item = ProxiedConn<A::Conn>;
+    type Error = BoxedError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Header {
+                    ref mut io,
+                    ref mut buf,
+                } => match parse(buf).map_err(malformed)? {
+                    Some((addr, len)) => {
+                        let io = ProxiedIo {
+                            io: io.take().expect("the future has already been polled"),
+                            buf: buf.split_off(len),
+                            pos: 0,
+                        };
+                        State::Inner {
+                            accept: self.acceptor.accept(io),
+                            addr,
+                        }
+                    }
+                    None => {
+                        let mut chunk = [0; 256];
+                        let io = io.as_mut().expect("the future has already been polled");
+                        let n = futures::try_ready!(io.poll_read(&mut chunk));
+                        if n == 0 {
+                            return Err(malformed("the connection is closed before the header"));
+                        }
+                        buf.extend_from_slice(&chunk[..n]);
+                        continue;
+                    }
+                },
+                State::Inner {
+                    ref mut accept,
+                    addr,
+                } => {
+                    let conn = futures::try_ready!(accept.poll().map_err(Into::into));
+                    return Ok(Async::Ready(ProxiedConn { conn, addr }));
+                }
+            };
+        }
+    }
+}
+
+fn malformed(reason: &str) -> BoxedError {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed PROXY protocol header: {}", reason),
+    )
+    .into()
+}
+
+/// The I/O passed to the inner acceptor, which starts with the bytes received after
+/// the PROXY protocol header.
+#[derive(Debug)]
+pub struct ProxiedIo<T> {
+    io: T,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<T> ProxiedIo<T> {
+    /// Returns a reference to the underlying I/O.
+    pub fn get_ref(&self) -> &T {
+        &self.io
+    }
+}
+
+impl<T: io::Read> io::Read for ProxiedIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos < self.buf.len() {
+            let n = std::cmp::min(buf.len(), self.buf.len() - self.pos);
+            buf[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+            self.pos += n;
+            return Ok(n);
+        }
+        self.io.read(buf)
+    }
+}
+
+impl<T: io::Write> io::Write for ProxiedIo<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for ProxiedIo<T> {}
+
+impl<T: AsyncWrite> AsyncWrite for ProxiedIo<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+/// The connection accepted by `ProxyProtocol`.
+#[derive(Debug)]
+pub struct ProxiedConn<C> {
+    conn: C,
+    addr: Option<ProxiedAddr>,
+}
+
+impl<C> ProxiedConn<C> {
+    /// Returns a reference to the connection accepted by the inner acceptor.
+    pub fn get_ref(&self) -> &C {
+        &self.conn
+    }
+
+    /// Returns the addresses reported by the PROXY protocol header, if available.
+    pub fn proxied_addr(&self) -> Option<&ProxiedAddr> {
+        self.addr.as_ref()
+    }
+}
+
+impl<C: io::Read> io::Read for ProxiedConn<C> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.conn.read(buf)
+    }
+}
+
+impl<C: io::Write> io::Write for ProxiedConn<C> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.conn.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.conn.flush()
+    }
+}
+
+impl<C: AsyncRead> AsyncRead for ProxiedConn<C> {}
+
+impl<C: AsyncWrite> AsyncWrite for ProxiedConn<C> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.conn.shutdown()
+    }
+}
+
+type Parsed = Option<(Option<ProxiedAddr>, usize)>;
+
+/// Parses the PROXY protocol header at the beginning of `buf`.
+///
+/// The returned value contains the reported addresses and the length of the header,
+/// or is `None` if more bytes are required.
+fn parse(buf: &[u8]) -> Result<Parsed, &'static str> {
+    if buf.starts_with(V1_SIGNATURE) {
+        parse_v1(buf)
+    } else if buf.starts_with(V2_SIGNATURE) {
+        parse_v2(buf)
+    } else if V1_SIGNATURE.starts_with(buf) || V2_SIGNATURE.starts_with(buf) {
+        Ok(None)
+    } else {
+        Err("missing the signature")
+    }
+}
+
+fn parse_v1(buf: &[u8]) -> Result<Parsed, &'static str> {
+    let end = match buf.windows(2).position(|w| w == b"\r\n") {
+        Some(end) if end + 2 <= V1_MAX_LEN => end,
+        None if buf.len() < V1_MAX_LEN => return Ok(None),
+        _ => return Err("the v1 header is too long"),
+    };
+    let line = std::str::from_utf8(&buf[..end]).map_err(|_| "the v1 header is not ASCII")?;
+
+    let mut fields = line.split(' ').skip(1);
+    let addr = match fields.next() {
+        // the remaining fields are ignored.
+        Some("UNKNOWN") => None,
+        Some(protocol @ "TCP4") | Some(protocol @ "TCP6") => {
+            let mut next = || fields.next().ok_or("missing fields in the v1 header");
+            let source: IpAddr = next()?.parse().map_err(|_| "invalid source address")?;
+            let destination: IpAddr = next()?.parse().map_err(|_| "invalid destination address")?;
+            let source_port: u16 = next()?.parse().map_err(|_| "invalid source port")?;
+            let destination_port: u16 = next()?.parse().map_err(|_| "invalid destination port")?;
+            if fields.next().is_some() {
+                return Err("extra fields in the v1 header");
+            }
+            if source.is_ipv4() != (protocol == "TCP4")
+                || destination.is_ipv4() != (protocol == "TCP4")
+            {
+                return Err("the address family mismatches the protocol");
+            }
+            Some(ProxiedAddr {
+                source: SocketAddr::new(source, source_port),
+                destination: SocketAddr::new(destination, destination_port),
+            })
+        }
+        _ => return Err("unknown protocol in the v1 header"),
+    };
+
+    Ok(Some((addr, end + 2)))
+}
+
+fn parse_v2(buf: &[u8]) -> Result<Parsed, &'static str> {
+    if buf.len() < V2_HEADER_LEN {
+        return Ok(None);
+    }
+    let version_command = buf[12];
+    let family = buf[13];
+    let len = V2_HEADER_LEN + usize::from(port(&buf[14..16]));
+    if version_command >> 4 != 2 {
+        return Err("unsupported version in the v2 header");
+    }
+    if buf.len() < len {
+        return Ok(None);
+    }
+    let block = &buf[V2_HEADER_LEN..len];
+
+    let addr = match version_command & 0x0f {
+        // LOCAL: the connection established by the proxy itself.
+        0x0 => None,
+        // PROXY
+        0x1 => match family {
+            // TCP or UDP over IPv4
+            0x11 | 0x12 => {
+                if block.len() < 12 {
+                    return Err("the v2 address block is truncated");
+                }
+                let ip = |b: &[u8]| IpAddr::from(Ipv4Addr::new(b[0], b[1], b[2], b[3]));
+                Some(ProxiedAddr {
+                    source: SocketAddr::new(ip(&block[0..4]), port(&block[8..10])),
+                    destination: SocketAddr::new(ip(&block[4..8]), port(&block[10..12])),
+                })
+            }
+            // TCP or UDP over IPv6
+            0x21 | 0x22 => {
+                if block.len() < 36 {
+                    return Err("the v2 address block is truncated");
+                }
+                let ip = |b: &[u8]| {
+                    let mut octets = [0; 16];
+                    octets.copy_from_slice(b);
+                    IpAddr::from(Ipv6Addr::from(octets))
+                };
+                Some(ProxiedAddr {
+                    source: SocketAddr::new(ip(&block[0..16]), port(&block[32..34])),
+                    destination: SocketAddr::new(ip(&block[16..32]), port(&block[34..36])),
+                })
+            }
+            // UNSPEC or the Unix domain sockets
+            _ => None,
+        },
+        _ => return Err("unknown command in the v2 header"),
+    };
+
+    Ok(Some((addr, len)))
+}
+
+/// Reads a 16-bit unsigned integer in network byte order.
+fn port(b: &[u8]) -> u16 {
+    u16::from(b[0]) << 8 | u16::from(b[1])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(source: &str, destination: &str) -> Option<ProxiedAddr> {
+        Some(ProxiedAddr {
+            source: source.parse().unwrap(),
+            destination: destination.parse().unwrap(),
+        })
+    }
+
+    #[test]
+    fn v1_examples() {
+        let header = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\nGET / HTTP/1.1\r\n";
+        assert_eq!(
+            parse(header),
+            Ok(Some((addr("192.168.0.1:56324", "192.168.0.11:443"), 47)))
+        );
+
+        let header = b"PROXY TCP4 255.255.255.255 255.255.255.255 65535 65535\r\n";
+        assert_eq!(
+            parse(header),
+            Ok(Some((
+                addr("255.255.255.255:65535", "255.255.255.255:65535"),
+                header.len()
+            )))
+        );
+
+        let header = b"PROXY TCP6 ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff \
+                       ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff 65535 65535\r\n";
+        let max = "[ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff]:65535";
+        assert_eq!(parse(header), Ok(Some((addr(max, max), header.len()))));
+
+        assert_eq!(parse(b"PROXY UNKNOWN\r\n"), Ok(Some((None, 15))));
+        let header = b"PROXY UNKNOWN ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff \
+                       ffff:ffff:ffff:ffff:ffff:ffff:ffff:ffff 65535 65535\r\n";
+        assert_eq!(header.len(), V1_MAX_LEN);
+        assert_eq!(parse(header), Ok(Some((None, V1_MAX_LEN))));
+    }
+
+    #[test]
+    fn v1_malformed() {
+        for header in &[
+            &b"PROXY TCP4 192.168.0.1 192.168.0.11 56324\r\n"[..],
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443 80\r\n",
+            b"PROXY TCP4 ::1 ::1 56324 443\r\n",
+            b"PROXY TCP4 192.168.0.1 192.168.0.11 65536 443\r\n",
+            b"PROXY UDP4 192.168.0.1 192.168.0.11 56324 443\r\n",
+            b"PROXY\r\n",
+            b"GET / HTTP/1.1\r\n",
+        ] {
+            assert!(
+                parse(header).is_err(),
+                "{}",
+                String::from_utf8_lossy(header)
+            );
+        }
+
+        let mut header = b"PROXY UNKNOWN ".to_vec();
+        header.resize(V1_MAX_LEN + 1, b'f');
+        assert!(parse(&header).is_err());
+    }
+
+    #[test]
+    fn v1_truncated() {
+        let header = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n";
+        for len in 0..header.len() {
+            assert_eq!(parse(&header[..len]), Ok(None), "{}", len);
+        }
+    }
+
+    fn v2(command: u8, family: u8, block: &[u8]) -> Vec<u8> {
+        let mut header = V2_SIGNATURE.to_vec();
+        header.push(0x20 | command);
+        header.push(family);
+        header.extend_from_slice(&(block.len() as u16).to_be_bytes());
+        header.extend_from_slice(block);
+        header
+    }
+
+    #[test]
+    fn v2_examples() {
+        let block = [192, 168, 0, 1, 192, 168, 0, 11, 0xdc, 0x04, 0x01, 0xbb];
+        let mut header = v2(0x1, 0x11, &block);
+        header.extend_from_slice(b"GET / HTTP/1.1\r\n");
+        assert_eq!(
+            parse(&header),
+            Ok(Some((addr("192.168.0.1:56324", "192.168.0.11:443"), 28)))
+        );
+
+        let mut block = vec![0; 36];
+        block[15] = 1;
+        block[16] = 0x20;
+        block[17] = 0x01;
+        block[31] = 2;
+        block[32..].copy_from_slice(&[0xdc, 0x04, 0x01, 0xbb]);
+        let header = v2(0x1, 0x21, &block);
+        assert_eq!(
+            parse(&header),
+            Ok(Some((addr("[::1]:56324", "[2001::2]:443"), 52)))
+        );
+
+        // the TLVs after the addresses are skipped.
+        let mut block = block.clone();
+        block.extend_from_slice(&[0x04, 0x00, 0x01, 0x00]);
+        let header = v2(0x1, 0x21, &block);
+        assert_eq!(parse(&header).unwrap().unwrap().1, header.len());
+
+        assert_eq!(parse(&v2(0x0, 0x00, &[])), Ok(Some((None, 16))));
+        assert_eq!(parse(&v2(0x1, 0x00, &[])), Ok(Some((None, 16))));
+        assert_eq!(parse(&v2(0x1, 0x31, &[0; 216])), Ok(Some((None, 232))));
+    }
+
+    #[test]
+    fn v2_malformed() {
+        assert!(parse(&v2(0x1, 0x11, &[192, 168, 0, 1])).is_err());
+        assert!(parse(&v2(0x1, 0x21, &[0; 12])).is_err());
+        assert!(parse(&v2(0x2, 0x11, &[0; 12])).is_err());
+
+        let mut header = v2(0x1, 0x11, &[0; 12]);
+        header[12] = 0x11;
+        assert!(parse(&header).is_err());
+    }
+
+    #[test]
+    fn v2_truncated() {
+        let header = v2(0x1, 0x11, &[192, 168, 0, 1, 192, 168, 0, 11, 0, 80, 1, 187]);
+        for len in 0..header.len() {
+            assert_eq!(parse(&header[..len]), Ok(None), "{}", len);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+//! The companion service which redirects the plaintext HTTP requests to HTTPS.
+//!
+//! The service is usually started by `Server::redirect_http` on an additional address
+//! (e.g. port 80) alongside the TLS listeners, without duplicating the application.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use tsukuyomi_server::{redirect::RedirectHttp, Server};
+//!
+//! Server::new(app)
+//!     .bind(tls_listener)
+//!     .acceptor(tls_acceptor)
+//!     .redirect_http(
+//!         ([0, 0, 0, 0], 80).into(),
+//!         RedirectHttp::new().canonical_host("www.example.com"),
+//!     )
+//!     .run()?;
+//! ```
+
+use {
+    futures::future::{self, FutureResult},
+    http::{
+        header::{HeaderValue, CONTENT_TYPE, HOST, LOCATION},
+        uri::Authority,
+        Method, Request, Response, StatusCode,
+    },
+    hyper::Body,
+    std::{error::Error as StdError, fmt, sync::Arc},
+    tsukuyomi_service::{MakeService, Service},
+};
+
+/// The path prefix of the HTTP-01 challenges of ACME.
+const ACME_CHALLENGE_PREFIX: &str = "/.well-known/acme-challenge/";
+
+type AcmeChallenge = dyn Fn(&str) -> Option<String> + Send + Sync + 'static;
+
+/// The configuration of the service which redirects the requests to HTTPS.
+///
+/// The value is also a factory of `RedirectService`, and hence it can be tested
+/// by `test::server` without binding any ports.
+#[derive(Clone)]
+pub struct RedirectHttp {
+    canonical_host: Option<String>,
+    https_port: u16,
+    preserve_method: bool,
+    acme_challenge: Option<Arc<AcmeChallenge>>,
+}
+
+impl fmt::Debug for RedirectHttp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RedirectHttp")
+            .field("canonical_host", &self.canonical_host)
+            .field("https_port", &self.https_port)
+            .field("preserve_method", &self.preserve_method)
+            .field("acme_challenge", &self.acme_challenge.is_some())
+            .finish()
+    }
+}
+
+impl Default for RedirectHttp {
+    fn default() -> Self {
+        Self {
+            canonical_host: None,
+            https_port: 443,
+            preserve_method: false,
+            acme_challenge: None,
+        }
+    }
+}
+
+impl RedirectHttp {
+    /// Creates a `RedirectHttp` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the host name used in the redirect location.
+    ///
+    /// By default, the host name is taken from the request, which makes the service
+    /// redirect to any host the client asks for.  Setting the canonical host is
+    /// recommended if the server is reachable by the unknown host names.
+    pub fn canonical_host(self, host: impl Into<String>) -> Self {
+        Self {
+            canonical_host: Some(host.into()),
+            ..self
+        }
+    }
+
+    /// Sets the port number of HTTPS used in the redirect location.
+    ///
+    /// The default value is 443, which is omitted from the location.
+    pub fn https_port(self, port: u16) -> Self {
+        Self {
+            https_port: port,
+            ..self
+        }
+    }
+
+    /// Sets whether to redirect the requests other than `GET` and `HEAD` with
+    /// `308 Permanent Redirect` so that the clients preserve the method and body.
+    ///
+    /// By default, all requests are redirected with `301 Moved Permanently`.
+    /// Enabling this is compatible with the requirements of the HSTS preload list.
+    pub fn preserve_method(self, enabled: bool) -> Self {
+        Self {
+            preserve_method: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the function to answer the HTTP-01 challenges of ACME.
+    ///
+    /// The requests to `/.well-known/acme-challenge/{token}` are answered with the
+    /// key authorization returned from the function, or `404 Not Found` if it
+    /// returns `None`, instead of being redirected.
+    pub fn acme_challenge(
+        self,
+        f: impl Fn(&str) -> Option<String> + Send + Sync + 'static,
+    ) -> Self {
+        Self {
+            acme_challenge: Some(Arc::new(f)),
+            ..self
+        }
+    }
+
+    fn respond(&self, request: &Request<Body>) -> Response<Body> {
+        if let Some(ref acme_challenge) = self.acme_challenge {
+            let path = request.uri().path();
+            if path.starts_with(ACME_CHALLENGE_PREFIX) {
+                let token = &path[ACME_CHALLENGE_PREFIX.len()..];
+                return match acme_challenge(token) {
+                    Some(key_authorization) => {
+                        let mut response = Response::new(Body::from(key_authorization));
+                        response.headers_mut().insert(
+                            CONTENT_TYPE,
+                            HeaderValue::from_static("text/plain; charset=utf-8"),
+                        );
+                        response
+                    }
+                    None => status(StatusCode::NOT_FOUND),
+                };
+            }
+        }
+
+        let host = match self.canonical_host {
+            Some(ref host) => host.clone(),
+            None => match request_host(request) {
+                Some(host) => host,
+                None => return status(StatusCode::BAD_REQUEST),
+            },
+        };
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map_or("/", |path_and_query| path_and_query.as_str());
+        let location = match self.https_port {
+            443 => format!("https://{}{}", host, path_and_query),
+            port => format!("https://{}:{}{}", host, port, path_and_query),
+        };
+        let location = match HeaderValue::from_str(&location) {
+            Ok(location) => location,
+            Err(..) => return status(StatusCode::BAD_REQUEST),
+        };
+
+        let mut response = match *request.method() {
+            Method::GET | Method::HEAD => status(StatusCode::MOVED_PERMANENTLY),
+            _ if self.preserve_method => status(StatusCode::PERMANENT_REDIRECT),
+            _ => status(StatusCode::MOVED_PERMANENTLY),
+        };
+        response.headers_mut().insert(LOCATION, location);
+        response
+    }
+}
+
+/// Returns the host name requested by the client, without the port number.
+fn request_host(request: &Request<Body>) -> Option<String> {
+    let authority = match request.headers().get(HOST) {
+        Some(host) => host.to_str().ok()?.parse::<Authority>().ok()?,
+        None => request.uri().authority_part()?.clone(),
+    };
+    // the user information is not a part of the host.
+    if authority.as_str().contains('@') || authority.host().is_empty() {
+        return None;
+    }
+    Some(authority.host().to_owned())
+}
+
+fn status(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// The error type of `RedirectHttp` and `RedirectService`, which is never returned.
+#[derive(Debug)]
+pub enum Never {}
+
+impl fmt::Display for Never {
+    fn fmt(&self, _: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {}
+    }
+}
+
+impl StdError for Never {
+    fn description(&self) -> &str {
+        match *self {}
+    }
+}
+
+impl<Ctx> MakeService<Ctx, Request<Body>> for RedirectHttp {
+    type Response = Response<Body>;
+    type Error = Never;
+    type Service = RedirectService;
+    type MakeError = Never;
+    type Future = FutureResult<RedirectService, Never>;
+
+    fn make_service(&self, _: Ctx) -> Self::Future {
+        future::ok(RedirectService {
+            config: self.clone(),
+        })
+    }
+}
+
+/// A `Service` which redirects the requests to HTTPS, created by `RedirectHttp`.
+#[derive(Debug)]
+pub struct RedirectService {
+    config: RedirectHttp,
+}
+
+impl Service<Request<Body>> for RedirectService {
+    type Response = Response<Body>;
+    type Error = Never;
+    type Future = FutureResult<Response<Body>, Never>;
+
+    fn poll_ready(&mut self) -> futures::Poll<(), Self::Error> {
+        Ok(futures::Async::Ready(()))
+    }
+
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        future::ok(self.config.respond(&request))
+    }
+}
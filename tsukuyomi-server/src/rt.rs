@@ -36,14 +36,25 @@ where
 /// and then enters a blocking section after other tasks are moved to another thread.
 /// See [the documentation of `tokio_threadpool::blocking`][blocking] for details.
 ///
+/// If the future is polled outside of the thread pool (e.g. on the single-threaded
+/// runtime), the function is executed inline on the current thread instead, since
+/// there is no thread to which the other tasks can be moved.  Therefore, the returned
+/// future does not fail with `BlockingError`.
+///
 /// [blocking]: https://docs.rs/tokio-threadpool/0.1/tokio_threadpool/fn.blocking.html
 pub fn blocking<T>(op: impl FnOnce() -> T) -> impl Future<Item = T, Error = BlockingError> {
     let mut op = Some(op);
     futures::future::poll_fn(move || {
-        poll_blocking(|| {
+        match poll_blocking(|| {
             let op = op.take().expect("The future has already been polled");
             op()
-        })
+        }) {
+            Err(..) => {
+                let op = op.take().expect("The future has already been polled");
+                Ok(Async::Ready(op()))
+            }
+            polled => polled,
+        }
     })
 }
 
@@ -0,0 +1,617 @@
+//! A server-level router which dispatches the requests to multiple services by path prefix.
+//!
+//! The dispatch is performed before the routing of each service, and hence the services
+//! may be built with different frameworks or different versions of Tsukuyomi.
+//!
+//! # Example
+//!
+//! ```ignore
+//! use tsukuyomi_server::{shard::shard, Server};
+//!
+//! let service = shard()
+//!     .mount_stripped("/v2", new_app) // receives `/v2/users` as `/users`
+//!     .mount("/legacy", legacy_app)   // receives `/legacy/users` as it is
+//!     .fallback(old_app);
+//!
+//! Server::new(service).run()?;
+//! ```
+
+use {
+    crate::CritError,
+    bytes::Buf,
+    futures::{Async, Future, Poll},
+    http::{header::HeaderMap, Request, Response, StatusCode, Uri},
+    hyper::body::{Body, Payload},
+    tsukuyomi_service::{MakeService, Service},
+};
+
+/// Creates an empty `Shard`.
+///
+/// The requests which do not match any mounted prefix are responded with
+/// `404 Not Found` unless the fallback service is specified.
+pub fn shard() -> Shard<(), NotFound> {
+    Shard {
+        mounts: (),
+        fallback: NotFound(()),
+    }
+}
+
+/// A factory of services which dispatches the requests by path prefix.
+///
+/// The request is dispatched to the service mounted at the longest prefix matching
+/// the request path, and to the fallback service if no prefix matches.  A prefix
+/// matches only at the segment boundary, i.e. `/v2` matches `/v2` and `/v2/users`
+/// but not `/v20`.
+#[derive(Debug)]
+pub struct Shard<M, F> {
+    mounts: M,
+    fallback: F,
+}
+
+impl<M, F> Shard<M, F>
+where
+    M: Mounts,
+{
+    /// Mounts a service factory at the specified prefix, without modifying the request path.
+    ///
+    /// # Panics
+    /// This method panics if the prefix does not start with `/` or it has already been mounted.
+    pub fn mount<S>(self, prefix: &str, make_service: S) -> Shard<Mount<M, S>, F> {
+        self.mount_inner(prefix, make_service, false)
+    }
+
+    /// Mounts a service factory at the specified prefix, removing the prefix from
+    /// the request path before dispatching.
+    ///
+    /// The path is rewritten to `/` if it becomes empty.
+    ///
+    /// # Panics
+    /// This method panics if the prefix does not start with `/` or it has already been mounted.
+    pub fn mount_stripped<S>(self, prefix: &str, make_service: S) -> Shard<Mount<M, S>, F> {
+        self.mount_inner(prefix, make_service, true)
+    }
+
+    fn mount_inner<S>(self, prefix: &str, make_service: S, strip: bool) -> Shard<Mount<M, S>, F> {
+        assert!(
+            prefix.starts_with('/'),
+            "the prefix must start with a slash: {:?}",
+            prefix
+        );
+        let prefix = prefix.trim_end_matches('/').to_owned();
+        assert!(
+            !self.mounts.contains(&prefix),
+            "the prefix has already been mounted: {:?}",
+            prefix
+        );
+        Shard {
+            mounts: Mount {
+                prefix,
+                strip,
+                make_service,
+                id: self.mounts.count(),
+                rest: self.mounts,
+            },
+            fallback: self.fallback,
+        }
+    }
+
+    /// Sets the service factory used for the requests which match no prefix.
+    pub fn fallback<F2>(self, fallback: F2) -> Shard<M, F2> {
+        Shard {
+            mounts: self.mounts,
+            fallback,
+        }
+    }
+}
+
+impl<M, F, Ctx, Bd, FBd> MakeService<Ctx, Request<Bd>> for Shard<M, F>
+where
+    M: MakeMounts<Ctx, Bd>,
+    F: MakeService<Ctx, Request<Bd>, Response = Response<FBd>>,
+    F::Error: Into<CritError>,
+    F::MakeError: Into<CritError>,
+    FBd: Payload,
+    Ctx: Clone,
+{
+    type Response = Response<EitherBody<M::Body, FBd>>;
+    type Error = CritError;
+    type Service = ShardService<M::Service, F::Service>;
+    type MakeError = CritError;
+    type Future = MakeShard<M::Future, F::Future>;
+
+    fn make_service(&self, ctx: Ctx) -> Self::Future {
+        MakeShard {
+            mounts: MaybeDone::Pending(self.mounts.make_mounts(ctx.clone())),
+            fallback: MaybeDone::Pending(self.fallback.make_service(ctx)),
+        }
+    }
+}
+
+/// A `Service` created by `Shard`.
+#[derive(Debug)]
+pub struct ShardService<M, F> {
+    mounts: M,
+    fallback: F,
+}
+
+impl<M, F, Bd, FBd> Service<Request<Bd>> for ShardService<M, F>
+where
+    M: MountsService<Bd>,
+    F: Service<Request<Bd>, Response = Response<FBd>>,
+    F::Error: Into<CritError>,
+    FBd: Payload,
+{
+    type Response = Response<EitherBody<M::Body, FBd>>;
+    type Error = CritError;
+    type Future = EitherFuture<M::Future, F::Future>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        let mounts = self.mounts.poll_ready()?;
+        let fallback = self.fallback.poll_ready().map_err(Into::into)?;
+        match (mounts, fallback) {
+            (Async::Ready(()), Async::Ready(())) => Ok(Async::Ready(())),
+            _ => Ok(Async::NotReady),
+        }
+    }
+
+    fn call(&mut self, mut request: Request<Bd>) -> Self::Future {
+        let mut matched = None;
+        self.mounts.find(request.uri().path(), &mut matched);
+        match matched {
+            Some(Matched { id, len, strip }) => {
+                if strip {
+                    strip_prefix(&mut request, len);
+                }
+                EitherFuture::Left(self.mounts.call(id, request))
+            }
+            None => EitherFuture::Right(self.fallback.call(request)),
+        }
+    }
+}
+
+fn strip_prefix<Bd>(request: &mut Request<Bd>, len: usize) {
+    let path = match &request.uri().path()[len..] {
+        "" => "/",
+        path => path,
+    };
+    let path_and_query = match request.uri().query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_owned(),
+    };
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = Some(
+        path_and_query
+            .parse()
+            .expect("the stripped path should be valid"),
+    );
+    *request.uri_mut() = Uri::from_parts(parts).expect("the stripped URI should be valid");
+}
+
+/// A service factory mounted onto `Shard`.
+#[derive(Debug)]
+pub struct Mount<P, S> {
+    prefix: String,
+    strip: bool,
+    make_service: S,
+    rest: P,
+    id: usize,
+}
+
+/// The default fallback of `Shard` which returns `404 Not Found`.
+#[derive(Debug, Clone, Copy)]
+pub struct NotFound(());
+
+impl<Ctx, Bd> MakeService<Ctx, Request<Bd>> for NotFound {
+    type Response = Response<Body>;
+    type Error = std::io::Error;
+    type Service = NotFound;
+    type MakeError = std::io::Error;
+    type Future = futures::future::FutureResult<Self::Service, Self::MakeError>;
+
+    fn make_service(&self, _: Ctx) -> Self::Future {
+        futures::future::ok(NotFound(()))
+    }
+}
+
+impl<Bd> Service<Request<Bd>> for NotFound {
+    type Response = Response<Body>;
+    type Error = std::io::Error;
+    type Future = futures::future::FutureResult<Self::Response, Self::Error>;
+
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _: Request<Bd>) -> Self::Future {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        futures::future::ok(response)
+    }
+}
+
+// ==== the list of mounted services ====
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct Matched {
+    id: usize,
+    len: usize,
+    strip: bool,
+}
+
+/// A trait representing the list of mounted service factories.
+#[doc(hidden)]
+pub trait Mounts {
+    fn count(&self) -> usize;
+    fn contains(&self, prefix: &str) -> bool;
+}
+
+impl Mounts for () {
+    fn count(&self) -> usize {
+        0
+    }
+
+    fn contains(&self, _: &str) -> bool {
+        false
+    }
+}
+
+impl<P, S> Mounts for Mount<P, S>
+where
+    P: Mounts,
+{
+    fn count(&self) -> usize {
+        self.id + 1
+    }
+
+    fn contains(&self, prefix: &str) -> bool {
+        self.prefix == prefix || self.rest.contains(prefix)
+    }
+}
+
+#[doc(hidden)]
+pub trait MakeMounts<Ctx, Bd>: Mounts {
+    type Body: Payload;
+    type Service: MountsService<Bd, Body = Self::Body>;
+    type Future: Future<Item = Self::Service, Error = CritError>;
+
+    fn make_mounts(&self, ctx: Ctx) -> Self::Future;
+}
+
+impl<Ctx, Bd> MakeMounts<Ctx, Bd> for () {
+    type Body = Body;
+    type Service = ();
+    type Future = futures::future::FutureResult<(), CritError>;
+
+    fn make_mounts(&self, _: Ctx) -> Self::Future {
+        futures::future::ok(())
+    }
+}
+
+impl<P, S, Ctx, Bd, SBd> MakeMounts<Ctx, Bd> for Mount<P, S>
+where
+    P: MakeMounts<Ctx, Bd>,
+    S: MakeService<Ctx, Request<Bd>, Response = Response<SBd>>,
+    S::Error: Into<CritError>,
+    S::MakeError: Into<CritError>,
+    SBd: Payload,
+    Ctx: Clone,
+{
+    type Body = EitherBody<SBd, P::Body>;
+    type Service = MountService<P::Service, S::Service>;
+    type Future = MakeMount<P::Future, S::Future>;
+
+    fn make_mounts(&self, ctx: Ctx) -> Self::Future {
+        MakeMount {
+            rest: MaybeDone::Pending(self.rest.make_mounts(ctx.clone())),
+            service: MaybeDone::Pending(self.make_service.make_service(ctx)),
+            prefix: Some(self.prefix.clone()),
+            strip: self.strip,
+            id: self.id,
+        }
+    }
+}
+
+#[doc(hidden)]
+pub trait MountsService<Bd> {
+    type Body: Payload;
+    type Future: Future<Item = Response<Self::Body>, Error = CritError>;
+
+    fn find(&self, path: &str, matched: &mut Option<Matched>);
+    fn poll_ready(&mut self) -> Poll<(), CritError>;
+    fn call(&mut self, id: usize, request: Request<Bd>) -> Self::Future;
+}
+
+impl<Bd> MountsService<Bd> for () {
+    type Body = Body;
+    type Future = futures::future::FutureResult<Response<Body>, CritError>;
+
+    fn find(&self, _: &str, _: &mut Option<Matched>) {}
+
+    fn poll_ready(&mut self) -> Poll<(), CritError> {
+        Ok(Async::Ready(()))
+    }
+
+    fn call(&mut self, _: usize, _: Request<Bd>) -> Self::Future {
+        unreachable!("no service is mounted")
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct MountService<P, S> {
+    prefix: String,
+    strip: bool,
+    service: S,
+    rest: P,
+    id: usize,
+}
+
+impl<P, S, Bd, SBd> MountsService<Bd> for MountService<P, S>
+where
+    P: MountsService<Bd>,
+    S: Service<Request<Bd>, Response = Response<SBd>>,
+    S::Error: Into<CritError>,
+    SBd: Payload,
+{
+    type Body = EitherBody<SBd, P::Body>;
+    type Future = EitherFuture<S::Future, P::Future>;
+
+    fn find(&self, path: &str, matched: &mut Option<Matched>) {
+        let is_match = path.starts_with(&*self.prefix)
+            && (path.len() == self.prefix.len() || path[self.prefix.len()..].starts_with('/'));
+        let is_longer = match matched {
+            Some(ref matched) => matched.len < self.prefix.len(),
+            None => true,
+        };
+        if is_match && is_longer {
+            *matched = Some(Matched {
+                id: self.id,
+                len: self.prefix.len(),
+                strip: self.strip,
+            });
+        }
+        self.rest.find(path, matched);
+    }
+
+    fn poll_ready(&mut self) -> Poll<(), CritError> {
+        let service = self.service.poll_ready().map_err(Into::into)?;
+        let rest = self.rest.poll_ready()?;
+        match (service, rest) {
+            (Async::Ready(()), Async::Ready(())) => Ok(Async::Ready(())),
+            _ => Ok(Async::NotReady),
+        }
+    }
+
+    fn call(&mut self, id: usize, request: Request<Bd>) -> Self::Future {
+        if id == self.id {
+            EitherFuture::Left(self.service.call(request))
+        } else {
+            EitherFuture::Right(self.rest.call(id, request))
+        }
+    }
+}
+
+// ==== futures ====
+
+#[derive(Debug)]
+enum MaybeDone<F: Future> {
+    Pending(F),
+    Done(F::Item),
+    Gone,
+}
+
+impl<F> MaybeDone<F>
+where
+    F: Future,
+    F::Error: Into<CritError>,
+{
+    fn poll_done(&mut self) -> Poll<(), CritError> {
+        let item = match self {
+            MaybeDone::Pending(ref mut future) => {
+                futures::try_ready!(future.poll().map_err(Into::into))
+            }
+            MaybeDone::Done(..) => return Ok(Async::Ready(())),
+            MaybeDone::Gone => panic!("the future has already been polled"),
+        };
+        *self = MaybeDone::Done(item);
+        Ok(Async::Ready(()))
+    }
+
+    fn take(&mut self) -> F::Item {
+        match std::mem::replace(self, MaybeDone::Gone) {
+            MaybeDone::Done(item) => item,
+            _ => panic!("the future has not completed yet"),
+        }
+    }
+}
+
+/// A `Future` that creates a `ShardService`.
+#[allow(missing_debug_implementations)]
+pub struct MakeShard<M: Future, F: Future> {
+    mounts: MaybeDone<M>,
+    fallback: MaybeDone<F>,
+}
+
+impl<M, F> Future for MakeShard<M, F>
+where
+    M: Future<Error = CritError>,
+    F: Future,
+    F::Error: Into<CritError>,
+{
+    type Item = ShardService<M::Item, F::Item>;
+    type Error = CritError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let mounts = self.mounts.poll_done()?;
+        let fallback = self.fallback.poll_done()?;
+        if mounts.is_not_ready() || fallback.is_not_ready() {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(ShardService {
+            mounts: self.mounts.take(),
+            fallback: self.fallback.take(),
+        }))
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct MakeMount<P: Future, S: Future> {
+    rest: MaybeDone<P>,
+    service: MaybeDone<S>,
+    prefix: Option<String>,
+    strip: bool,
+    id: usize,
+}
+
+impl<P, S> Future for MakeMount<P, S>
+where
+    P: Future<Error = CritError>,
+    S: Future,
+    S::Error: Into<CritError>,
+{
+    type Item = MountService<P::Item, S::Item>;
+    type Error = CritError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let rest = self.rest.poll_done()?;
+        let service = self.service.poll_done()?;
+        if rest.is_not_ready() || service.is_not_ready() {
+            return Ok(Async::NotReady);
+        }
+        Ok(Async::Ready(MountService {
+            prefix: self
+                .prefix
+                .take()
+                .expect("the future has already been polled"),
+            strip: self.strip,
+            service: self.service.take(),
+            rest: self.rest.take(),
+            id: self.id,
+        }))
+    }
+}
+
+/// A `Future` that returns the response from either of two services.
+#[derive(Debug)]
+pub enum EitherFuture<L, R> {
+    #[doc(hidden)]
+    Left(L),
+    #[doc(hidden)]
+    Right(R),
+}
+
+impl<L, R, LBd, RBd> Future for EitherFuture<L, R>
+where
+    L: Future<Item = Response<LBd>>,
+    L::Error: Into<CritError>,
+    R: Future<Item = Response<RBd>>,
+    R::Error: Into<CritError>,
+{
+    type Item = Response<EitherBody<LBd, RBd>>;
+    type Error = CritError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        match self {
+            EitherFuture::Left(ref mut future) => future
+                .poll()
+                .map(|x| x.map(|response| response.map(EitherBody::Left)))
+                .map_err(Into::into),
+            EitherFuture::Right(ref mut future) => future
+                .poll()
+                .map(|x| x.map(|response| response.map(EitherBody::Right)))
+                .map_err(Into::into),
+        }
+    }
+}
+
+// ==== body ====
+
+/// The message body of the responses returned from `ShardService`.
+#[derive(Debug)]
+pub enum EitherBody<L, R> {
+    #[doc(hidden)]
+    Left(L),
+    #[doc(hidden)]
+    Right(R),
+}
+
+impl<L, R> Payload for EitherBody<L, R>
+where
+    L: Payload,
+    R: Payload,
+{
+    type Data = EitherBuf<L::Data, R::Data>;
+    type Error = CritError;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self {
+            EitherBody::Left(ref mut body) => body
+                .poll_data()
+                .map(|x| x.map(|data| data.map(EitherBuf::Left)))
+                .map_err(Into::into),
+            EitherBody::Right(ref mut body) => body
+                .poll_data()
+                .map(|x| x.map(|data| data.map(EitherBuf::Right)))
+                .map_err(Into::into),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match self {
+            EitherBody::Left(ref mut body) => body.poll_trailers().map_err(Into::into),
+            EitherBody::Right(ref mut body) => body.poll_trailers().map_err(Into::into),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self {
+            EitherBody::Left(ref body) => body.is_end_stream(),
+            EitherBody::Right(ref body) => body.is_end_stream(),
+        }
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        match self {
+            EitherBody::Left(ref body) => body.content_length(),
+            EitherBody::Right(ref body) => body.content_length(),
+        }
+    }
+}
+
+/// A chunk of `EitherBody`.
+#[derive(Debug)]
+pub enum EitherBuf<L, R> {
+    #[doc(hidden)]
+    Left(L),
+    #[doc(hidden)]
+    Right(R),
+}
+
+impl<L, R> Buf for EitherBuf<L, R>
+where
+    L: Buf,
+    R: Buf,
+{
+    fn remaining(&self) -> usize {
+        match self {
+            EitherBuf::Left(ref buf) => buf.remaining(),
+            EitherBuf::Right(ref buf) => buf.remaining(),
+        }
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match self {
+            EitherBuf::Left(ref buf) => buf.bytes(),
+            EitherBuf::Right(ref buf) => buf.bytes(),
+        }
+    }
+
+    fn advance(&mut self, cnt: usize) {
+        match self {
+            EitherBuf::Left(ref mut buf) => buf.advance(cnt),
+            EitherBuf::Right(ref mut buf) => buf.advance(cnt),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+//! The socket-level configuration of TCP listeners.
+
+use {
+    futures::{Async, Poll, Stream},
+    net2::TcpBuilder,
+    std::{io, net::SocketAddr},
+    tokio::net::{tcp::Incoming, TcpStream},
+};
+
+/// The socket-level configuration of the TCP listeners bound to `Server`.
+///
+/// The options applied when creating the socket (`reuse_address`, `reuse_port` and
+/// `backlog`) only take effect on the listeners bound by the server from a
+/// `SocketAddr`, since the listeners created outside of the server are already
+/// listening.  The options of the accepted connections (`nodelay`) apply to all
+/// TCP listeners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TcpConfig {
+    nodelay: Option<bool>,
+    reuse_address: bool,
+    reuse_port: bool,
+    backlog: i32,
+}
+
+impl Default for TcpConfig {
+    fn default() -> Self {
+        Self {
+            nodelay: None,
+            // mirrors the behavior of `TcpListener::bind` in Tokio.
+            reuse_address: cfg!(unix),
+            reuse_port: false,
+            backlog: 1024,
+        }
+    }
+}
+
+impl TcpConfig {
+    /// Creates a `TcpConfig` with the default values.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the value of `TCP_NODELAY` on the accepted connections.
+    ///
+    /// By default, the value is left to the operating system, which usually
+    /// enables Nagle's algorithm.
+    pub fn nodelay(self, enabled: bool) -> Self {
+        Self {
+            nodelay: Some(enabled),
+            ..self
+        }
+    }
+
+    /// Sets the value of `SO_REUSEADDR` on the listening sockets.
+    ///
+    /// The default value is `true` on Unix platforms, and `false` otherwise.
+    pub fn reuse_address(self, enabled: bool) -> Self {
+        Self {
+            reuse_address: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the value of `SO_REUSEPORT` on the listening sockets.
+    ///
+    /// Enabling this allows multiple processes to listen on the same address,
+    /// with the incoming connections distributed by the kernel.  The option is
+    /// only available on Unix platforms, and binding fails on the other platforms
+    /// if it is enabled.  The default value is `false`.
+    pub fn reuse_port(self, enabled: bool) -> Self {
+        Self {
+            reuse_port: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the maximum length of the queue of pending connections.
+    ///
+    /// The value may be silently truncated by the operating system.
+    /// The default value is 1024.
+    pub fn backlog(self, backlog: i32) -> Self {
+        Self { backlog, ..self }
+    }
+
+    /// Creates a listening socket bound to the specified address.
+    pub(crate) fn bind(&self, addr: &SocketAddr) -> io::Result<std::net::TcpListener> {
+        let builder = match addr {
+            SocketAddr::V4(..) => TcpBuilder::new_v4()?,
+            SocketAddr::V6(..) => TcpBuilder::new_v6()?,
+        };
+        builder.reuse_address(self.reuse_address)?;
+        if self.reuse_port {
+            set_reuse_port(&builder)?;
+        }
+        builder.bind(addr)?;
+        builder.listen(self.backlog)
+    }
+
+    pub(crate) fn incoming(&self, incoming: Incoming) -> TcpIncoming {
+        TcpIncoming {
+            inner: incoming,
+            nodelay: self.nodelay,
+        }
+    }
+}
+
+#[cfg(unix)]
+fn set_reuse_port(builder: &TcpBuilder) -> io::Result<()> {
+    use net2::unix::UnixTcpBuilderExt;
+    builder.reuse_port(true).map(|_| ())
+}
+
+#[cfg(not(unix))]
+fn set_reuse_port(_: &TcpBuilder) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Other,
+        "SO_REUSEPORT is not supported on this platform",
+    ))
+}
+
+/// A `Stream` of the connections accepted by a TCP listener, with the socket options
+/// of `TcpConfig` applied.
+#[derive(Debug)]
+pub struct TcpIncoming {
+    inner: Incoming,
+    nodelay: Option<bool>,
+}
+
+impl Stream for TcpIncoming {
+    type Item = TcpStream;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let stream = match futures::try_ready!(self.inner.poll()) {
+            Some(stream) => stream,
+            None => return Ok(Async::Ready(None)),
+        };
+        if let Some(nodelay) = self.nodelay {
+            // the failure does not affect the other connections.
+            if let Err(err) = stream.set_nodelay(nodelay) {
+                log::debug!("failed to set TCP_NODELAY: {}", err);
+            }
+        }
+        Ok(Async::Ready(Some(stream)))
+    }
+}
@@ -2,11 +2,13 @@
 
 mod input;
 mod output;
+mod raw;
 mod server;
 
 pub use self::{
     input::{Input, IntoRequestBody},
     output::Output,
+    raw::RawOutput,
     server::{Server, Session},
 };
 
@@ -70,11 +70,8 @@ impl<Bd: Payload> Receive<Bd> {
                     ..
                 } => {
                     debug_assert!(end_of_chunks);
-                    self.state = ReceiveState::Ready(Output {
-                        chunks,
-                        trailers,
-                        content_length: self.content_length,
-                    });
+                    self.state =
+                        ReceiveState::Ready(Output::new(chunks, trailers, self.content_length));
                     return Ok(Async::Ready(()));
                 }
                 ReceiveState::Ready(..) | ReceiveState::Gone => {
@@ -102,6 +99,18 @@ pub struct Output {
 
 #[allow(missing_docs)]
 impl Output {
+    pub(super) fn new(
+        chunks: Vec<Bytes>,
+        trailers: Option<HeaderMap>,
+        content_length: Option<u64>,
+    ) -> Self {
+        Self {
+            chunks,
+            trailers,
+            content_length,
+        }
+    }
+
     pub fn chunks(&self) -> &Vec<Bytes> {
         &self.chunks
     }
@@ -0,0 +1,247 @@
+use {
+    super::output::Output,
+    bytes::Bytes,
+    futures::{Async, Poll},
+    http::{header::HeaderMap, Response, StatusCode, Version},
+    std::{
+        io::{self, Cursor, Read, Write},
+        sync::{Arc, Mutex},
+    },
+    tokio::io::{AsyncRead, AsyncWrite},
+};
+
+/// An in-memory transport which reads the prepared bytes and records the written bytes.
+#[derive(Debug)]
+pub(super) struct MemoryIo {
+    input: Cursor<Bytes>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl MemoryIo {
+    pub(super) fn new(input: Bytes) -> (Self, Arc<Mutex<Vec<u8>>>) {
+        let output = Arc::new(Mutex::new(vec![]));
+        let io = Self {
+            input: Cursor::new(input),
+            output: output.clone(),
+        };
+        (io, output)
+    }
+}
+
+impl Read for MemoryIo {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.input.read(buf)
+    }
+}
+
+impl AsyncRead for MemoryIo {}
+
+impl Write for MemoryIo {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.output
+            .lock()
+            .expect("the output buffer should not be poisoned")
+            .extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsyncWrite for MemoryIo {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        Ok(Async::Ready(()))
+    }
+}
+
+/// A type representing the bytes written by the server on a raw connection,
+/// along with the responses parsed from them.
+#[derive(Debug)]
+pub struct RawOutput {
+    raw: Bytes,
+    responses: Vec<Response<Output>>,
+    connection_error: Option<hyper::Error>,
+}
+
+#[allow(missing_docs)]
+impl RawOutput {
+    pub(super) fn new(raw: Vec<u8>, connection_error: Option<hyper::Error>) -> Self {
+        let raw = Bytes::from(raw);
+        let responses = parse_responses(&raw);
+        Self {
+            raw,
+            responses,
+            connection_error,
+        }
+    }
+
+    /// Returns the bytes written by the server, as they are.
+    pub fn raw(&self) -> &Bytes {
+        &self.raw
+    }
+
+    /// Returns the responses parsed from the written bytes, including the interim
+    /// (`1xx`) responses.
+    ///
+    /// The parsing stops at the first message that is incomplete or malformed.
+    /// Since the length of the responses to `HEAD` requests cannot be determined
+    /// from the response alone, they must be inspected with `raw` instead.
+    pub fn responses(&self) -> &[Response<Output>] {
+        &self.responses
+    }
+
+    /// Returns the last parsed response, which is usually the final response.
+    pub fn response(&self) -> Option<&Response<Output>> {
+        self.responses.last()
+    }
+
+    /// Returns the error which has terminated the connection, if any.
+    pub fn connection_error(&self) -> Option<&hyper::Error> {
+        self.connection_error.as_ref()
+    }
+
+    pub fn to_utf8(&self) -> Result<&str, std::str::Utf8Error> {
+        std::str::from_utf8(&self.raw)
+    }
+}
+
+fn parse_responses(mut data: &[u8]) -> Vec<Response<Output>> {
+    let mut responses = vec![];
+    while !data.is_empty() {
+        match parse_response(data) {
+            Some((response, rest)) => {
+                responses.push(response);
+                data = rest;
+            }
+            None => break,
+        }
+    }
+    responses
+}
+
+fn parse_response(data: &[u8]) -> Option<(Response<Output>, &[u8])> {
+    let mut headers = [httparse::EMPTY_HEADER; 64];
+    let mut parsed = httparse::Response::new(&mut headers);
+    let head_len = match parsed.parse(data).ok()? {
+        httparse::Status::Complete(len) => len,
+        httparse::Status::Partial => return None,
+    };
+
+    let mut response = Response::new(());
+    *response.status_mut() = StatusCode::from_u16(parsed.code?).ok()?;
+    *response.version_mut() = match parsed.version? {
+        0 => Version::HTTP_10,
+        _ => Version::HTTP_11,
+    };
+    for header in parsed.headers.iter() {
+        response.headers_mut().append(
+            http::header::HeaderName::from_bytes(header.name.as_bytes()).ok()?,
+            http::header::HeaderValue::from_bytes(header.value).ok()?,
+        );
+    }
+    let data = &data[head_len..];
+
+    let status = response.status();
+    let is_chunked = response
+        .headers()
+        .get_all(http::header::TRANSFER_ENCODING)
+        .iter()
+        .any(|value| value.as_bytes().eq_ignore_ascii_case(b"chunked"));
+    let content_length = response
+        .headers()
+        .get(http::header::CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok()?.parse::<u64>().ok());
+
+    let (output, rest) = if status.is_informational()
+        || status == StatusCode::NO_CONTENT
+        || status == StatusCode::NOT_MODIFIED
+    {
+        (Output::new(vec![], None, Some(0)), data)
+    } else if is_chunked {
+        let (chunks, trailers, rest) = parse_chunked(data)?;
+        (Output::new(chunks, trailers, None), rest)
+    } else if let Some(len) = content_length {
+        if (data.len() as u64) < len {
+            return None;
+        }
+        let (body, rest) = data.split_at(len as usize);
+        let chunks = if body.is_empty() {
+            vec![]
+        } else {
+            vec![Bytes::from(body)]
+        };
+        (Output::new(chunks, None, Some(len)), rest)
+    } else {
+        (
+            Output::new(vec![Bytes::from(data)], None, None),
+            &data[data.len()..],
+        )
+    };
+
+    Some((response.map(|()| output), rest))
+}
+
+#[allow(clippy::type_complexity)]
+fn parse_chunked(mut data: &[u8]) -> Option<(Vec<Bytes>, Option<HeaderMap>, &[u8])> {
+    let mut chunks = vec![];
+    loop {
+        let line_end = find_crlf(data)?;
+        let size_line = std::str::from_utf8(&data[..line_end]).ok()?;
+        let size_str = size_line.split(';').next()?.trim();
+        let size = usize::from_str_radix(size_str, 16).ok()?;
+        data = &data[line_end + 2..];
+
+        if size == 0 {
+            break;
+        }
+        if data.len() < size + 2 || &data[size..size + 2] != b"\r\n" {
+            return None;
+        }
+        chunks.push(Bytes::from(&data[..size]));
+        data = &data[size + 2..];
+    }
+
+    let mut trailers = HeaderMap::new();
+    loop {
+        let line_end = find_crlf(data)?;
+        let line = &data[..line_end];
+        data = &data[line_end + 2..];
+        if line.is_empty() {
+            break;
+        }
+        let colon = line.iter().position(|&b| b == b':')?;
+        trailers.append(
+            http::header::HeaderName::from_bytes(&line[..colon]).ok()?,
+            http::header::HeaderValue::from_bytes(trim(&line[colon + 1..])).ok()?,
+        );
+    }
+    let trailers = if trailers.is_empty() {
+        None
+    } else {
+        Some(trailers)
+    };
+
+    Some((chunks, trailers, data))
+}
+
+fn find_crlf(data: &[u8]) -> Option<usize> {
+    data.windows(2).position(|window| window == b"\r\n")
+}
+
+fn trim(mut s: &[u8]) -> &[u8] {
+    while let Some((&b, rest)) = s.split_first() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        s = rest;
+    }
+    while let Some((&b, rest)) = s.split_last() {
+        if b != b' ' && b != b'\t' {
+            break;
+        }
+        s = rest;
+    }
+    s
+}
@@ -4,15 +4,17 @@ use {
     super::{
         input::Input,
         output::{Output, Receive},
+        raw::{MemoryIo, RawOutput},
     },
-    crate::CritError,
+    crate::{CritError, LiftedHttpService},
+    bytes::Bytes,
     cookie::Cookie,
     futures::{Future, Poll},
     http::{
         header::{COOKIE, SET_COOKIE},
         Request, Response,
     },
-    hyper::body::Payload,
+    hyper::{body::Payload, server::conn::Http},
     std::{collections::HashMap, mem},
     tsukuyomi_service::{MakeService, Service},
 };
@@ -159,6 +161,46 @@ mod threadpool {
             let mut session = self.new_session()?;
             session.perform(input)
         }
+
+        /// Feeds the raw bytes into a new HTTP/1.x connection and collects the bytes
+        /// written by the server.
+        ///
+        /// The input is parsed by hyper in the same way as the bytes received over
+        /// the network, and hence this method can be used to test the wire-level
+        /// behavior which cannot be expressed with `http::Request`.  The connection
+        /// is closed after all of the input has been consumed.
+        pub fn perform_raw(&mut self, input: impl Into<Bytes>) -> crate::Result<RawOutput>
+        where
+            <S::Service as Service<Request<hyper::Body>>>::Future: Send + 'static,
+        {
+            let service = block_on(
+                &mut self.runtime,
+                self.make_service.make_service(()).map_err(Into::into),
+            )
+            .map_err(failure::Error::from_boxed_compat)?;
+
+            let (io, output) = MemoryIo::new(input.into());
+            let mut protocol = Http::new();
+            protocol.http1_only(true);
+            let conn = protocol.serve_connection(
+                io,
+                LiftedHttpService {
+                    service,
+                    listener: None,
+                    metadata: Default::default(),
+                    timer: None,
+                    limiter: None,
+                    overloaded: false,
+                    hooks: Default::default(),
+                    requests: Default::default(),
+                    health: None,
+                },
+            );
+            let result = block_on(&mut self.runtime, conn);
+
+            let raw = output.lock().unwrap().clone();
+            Ok(RawOutput::new(raw, result.err()))
+        }
     }
 
     impl<'a, S, Bd> Session<'a, S, Runtime>
@@ -211,6 +253,45 @@ mod current_thread {
             let mut session = self.new_session()?;
             session.perform(input)
         }
+
+        /// Feeds the raw bytes into a new HTTP/1.x connection and collects the bytes
+        /// written by the server.
+        ///
+        /// See the documentation of the multi-threaded version for details.
+        pub fn perform_raw(&mut self, input: impl Into<Bytes>) -> crate::Result<RawOutput>
+        where
+            S::Service: 'static,
+            <S::Service as Service<Request<hyper::Body>>>::Future: 'static,
+        {
+            let service = self
+                .runtime
+                .block_on(self.make_service.make_service(()))
+                .map_err(|err| failure::Error::from_boxed_compat(err.into()))?;
+
+            let (io, output) = MemoryIo::new(input.into());
+            let mut protocol = Http::new();
+            protocol.http1_only(true);
+            let conn = protocol
+                .with_executor(tokio::runtime::current_thread::TaskExecutor::current())
+                .serve_connection(
+                    io,
+                    LiftedHttpService {
+                        service,
+                        listener: None,
+                        metadata: Default::default(),
+                        timer: None,
+                        limiter: None,
+                        overloaded: false,
+                        hooks: Default::default(),
+                        requests: Default::default(),
+                        health: None,
+                    },
+                );
+            let result = self.runtime.block_on(conn);
+
+            let raw = output.lock().unwrap().clone();
+            Ok(RawOutput::new(raw, result.err()))
+        }
     }
 
     impl<'a, S, Bd> Session<'a, S, Runtime>
@@ -0,0 +1,392 @@
+//! Timeouts of the connections and requests served by `Server`.
+
+use {
+    futures::{Async, Future, Poll},
+    http::{header::HeaderMap, Response, StatusCode},
+    hyper::body::Payload,
+    std::{
+        io,
+        sync::{Arc, Mutex},
+        time::{Duration, Instant},
+    },
+    tokio::{
+        io::{AsyncRead, AsyncWrite},
+        timer::Delay,
+    },
+};
+
+/// The configuration of timeouts applied by `Server`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Timeouts {
+    pub(crate) header_read: Option<Duration>,
+    pub(crate) request: Option<Duration>,
+    pub(crate) keep_alive: Option<Duration>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            header_read: Some(Duration::from_secs(30)),
+            request: Some(Duration::from_secs(60)),
+            keep_alive: Some(Duration::from_secs(75)),
+        }
+    }
+}
+
+/// The phase of connection, which determines the deadline of connection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Waiting for the header of request to be received.
+    Head,
+    /// Some requests are being processed.
+    Busy,
+    /// Waiting for the next request on the persistent connection.
+    Idle,
+}
+
+#[derive(Debug)]
+struct State {
+    phase: Phase,
+    deadline: Option<Instant>,
+    /// The number of requests being processed, which may be more than one on HTTP/2.
+    requests: usize,
+}
+
+/// The timer shared by the I/O, the service and the response bodies of a connection.
+#[derive(Debug, Clone)]
+pub(crate) struct ConnTimer {
+    timeouts: Timeouts,
+    state: Arc<Mutex<State>>,
+}
+
+impl ConnTimer {
+    pub(crate) fn new(timeouts: Timeouts) -> Self {
+        Self {
+            timeouts,
+            state: Arc::new(Mutex::new(State {
+                phase: Phase::Head,
+                deadline: deadline(timeouts.header_read),
+                requests: 0,
+            })),
+        }
+    }
+
+    /// Called when some bytes are received from the client.
+    fn on_read(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.phase == Phase::Idle {
+            state.phase = Phase::Head;
+            state.deadline = deadline(self.timeouts.header_read);
+        }
+    }
+
+    /// Called when the header of request has been received.
+    fn on_request(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.phase = Phase::Busy;
+        state.deadline = None;
+        state.requests += 1;
+    }
+
+    /// Called when the response has been sent.
+    fn on_response_end(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.requests = state.requests.saturating_sub(1);
+        if state.phase == Phase::Busy && state.requests == 0 {
+            state.phase = Phase::Idle;
+            state.deadline = deadline(self.timeouts.keep_alive);
+        }
+    }
+
+    fn deadline(&self) -> Option<Instant> {
+        self.state.lock().unwrap().deadline
+    }
+}
+
+fn deadline(timeout: Option<Duration>) -> Option<Instant> {
+    timeout.map(|timeout| Instant::now() + timeout)
+}
+
+/// A `Future` that fails if the handshake of connection (e.g. TLS or PROXY protocol)
+/// does not complete by the deadline of receiving the header of the first request.
+#[allow(missing_debug_implementations)]
+pub(crate) struct TimedHandshake<F> {
+    inner: F,
+    delay: Option<Delay>,
+}
+
+impl<F> TimedHandshake<F> {
+    pub(crate) fn new(inner: F, timer: &ConnTimer) -> Self {
+        Self {
+            inner,
+            delay: timer.deadline().map(Delay::new),
+        }
+    }
+}
+
+impl<F> Future for TimedHandshake<F>
+where
+    F: Future,
+    F::Error: Into<crate::CritError>,
+{
+    type Item = F::Item;
+    type Error = crate::CritError;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(item) = self.inner.poll().map_err(Into::into)? {
+            return Ok(Async::Ready(item));
+        }
+
+        match self.delay.as_mut().map(Future::poll) {
+            Some(Ok(Async::Ready(()))) => Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "the handshake of connection is timed out",
+            )
+            .into()),
+            Some(Err(err)) => {
+                log::error!("timer error: {}", err);
+                self.delay = None;
+                Ok(Async::NotReady)
+            }
+            _ => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// An I/O that notifies the timer of the received bytes.
+#[derive(Debug)]
+pub(crate) struct TimedIo<T> {
+    io: T,
+    timer: ConnTimer,
+}
+
+impl<T> TimedIo<T> {
+    pub(crate) fn new(io: T, timer: ConnTimer) -> Self {
+        Self { io, timer }
+    }
+}
+
+impl<T: io::Read> io::Read for TimedIo<T> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.io.read(buf)?;
+        if n > 0 {
+            self.timer.on_read();
+        }
+        Ok(n)
+    }
+}
+
+impl<T: io::Write> io::Write for TimedIo<T> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.io.write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.io.flush()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for TimedIo<T> {}
+
+impl<T: AsyncWrite> AsyncWrite for TimedIo<T> {
+    fn shutdown(&mut self) -> Poll<(), io::Error> {
+        self.io.shutdown()
+    }
+}
+
+/// A `Future` that drives the connection until it completes or the deadline of the
+/// current phase elapses.
+///
+/// The connection upgraded to another protocol (e.g. WebSocket) completes this future,
+/// and thus the timeouts never apply to it.
+#[allow(missing_debug_implementations)]
+pub(crate) struct TimedConnection<F> {
+    conn: F,
+    timer: ConnTimer,
+    delay: Option<Delay>,
+}
+
+impl<F> TimedConnection<F> {
+    pub(crate) fn new(conn: F, timer: ConnTimer) -> Self {
+        Self {
+            conn,
+            timer,
+            delay: None,
+        }
+    }
+}
+
+impl<F> Future for TimedConnection<F>
+where
+    F: Future<Item = ()>,
+{
+    type Item = ();
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        if let Async::Ready(()) = self.conn.poll()? {
+            return Ok(Async::Ready(()));
+        }
+
+        // the deadline is checked after polling the connection, since it may be
+        // changed by the I/O, the service and the response bodies.
+        let deadline = match self.timer.deadline() {
+            Some(deadline) => deadline,
+            None => {
+                self.delay = None;
+                return Ok(Async::NotReady);
+            }
+        };
+        let delay = self.delay.get_or_insert_with(|| Delay::new(deadline));
+        if delay.deadline() != deadline {
+            delay.reset(deadline);
+        }
+        match delay.poll() {
+            Ok(Async::Ready(())) => {
+                log::debug!("the connection is closed due to timeout");
+                Ok(Async::Ready(()))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                log::error!("timer error: {}", err);
+                self.delay = None;
+                Ok(Async::NotReady)
+            }
+        }
+    }
+}
+
+/// The source of the response to a request.
+#[allow(missing_debug_implementations)]
+pub(crate) enum Reply<F> {
+    /// The response is returned from the service.
+    Service(F),
+    /// The response without the body is returned by the server itself, e.g. the
+    /// health check or the rejection due to the concurrency limit.
+    Server(Option<Response<()>>),
+}
+
+/// A `Future` that returns the response from the service, or `408 Request Timeout`
+/// if the service does not return the response within the time limit.
+#[allow(missing_debug_implementations)]
+pub(crate) struct TimedResponse<F> {
+    inner: Reply<F>,
+    timer: Option<ConnTimer>,
+    delay: Option<Delay>,
+}
+
+impl<F> TimedResponse<F> {
+    pub(crate) fn new(inner: Reply<F>, timer: Option<ConnTimer>) -> Self {
+        let delay = timer.as_ref().and_then(|timer| {
+            timer.on_request();
+            deadline(timer.timeouts.request).map(Delay::new)
+        });
+        Self {
+            inner,
+            timer,
+            delay,
+        }
+    }
+}
+
+impl<F, Bd> Future for TimedResponse<F>
+where
+    F: Future<Item = Response<Bd>>,
+{
+    type Item = Response<TimedBody<Bd>>;
+    type Error = F::Error;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        let response = match self.inner {
+            Reply::Service(ref mut inner) => match inner.poll()? {
+                Async::Ready(response) => Some(response.map(Some)),
+                Async::NotReady => None,
+            },
+            Reply::Server(ref mut response) => Some(
+                response
+                    .take()
+                    .expect("the future has already been polled")
+                    .map(|()| None),
+            ),
+        };
+        if let Some(response) = response {
+            let timer = self.timer.take();
+            return Ok(Async::Ready(
+                response.map(|inner| TimedBody { inner, timer }),
+            ));
+        }
+
+        match self.delay.as_mut().map(Future::poll) {
+            Some(Ok(Async::Ready(()))) => {
+                log::debug!("the request is timed out");
+                let mut response = Response::new(TimedBody {
+                    inner: None,
+                    timer: self.timer.take(),
+                });
+                *response.status_mut() = StatusCode::REQUEST_TIMEOUT;
+                response.headers_mut().insert(
+                    http::header::CONNECTION,
+                    http::header::HeaderValue::from_static("close"),
+                );
+                Ok(Async::Ready(response))
+            }
+            Some(Err(err)) => {
+                log::error!("timer error: {}", err);
+                self.delay = None;
+                Ok(Async::NotReady)
+            }
+            _ => Ok(Async::NotReady),
+        }
+    }
+}
+
+/// The body of response that notifies the timer when it has been sent.
+#[allow(missing_debug_implementations)]
+pub(crate) struct TimedBody<Bd> {
+    inner: Option<Bd>,
+    timer: Option<ConnTimer>,
+}
+
+impl<Bd> Drop for TimedBody<Bd> {
+    fn drop(&mut self) {
+        if let Some(timer) = self.timer.take() {
+            timer.on_response_end();
+        }
+    }
+}
+
+impl<Bd> Payload for TimedBody<Bd>
+where
+    Bd: Payload,
+{
+    type Data = Bd::Data;
+    type Error = Bd::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        match self.inner {
+            Some(ref mut inner) => inner.poll_data(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match self.inner {
+            Some(ref mut inner) => inner.poll_trailers(),
+            None => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.inner {
+            Some(ref inner) => inner.is_end_stream(),
+            None => true,
+        }
+    }
+
+    fn content_length(&self) -> Option<u64> {
+        match self.inner {
+            Some(ref inner) => inner.content_length(),
+            None => Some(0),
+        }
+    }
+}
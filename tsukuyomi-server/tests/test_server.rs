@@ -2,3 +2,1715 @@
 fn test_version_sync() {
     version_sync::assert_html_root_url_updated!("src/lib.rs");
 }
+
+mod bind {
+    use {
+        futures::{future::FutureResult, Async, Poll},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+        },
+        tsukuyomi_server::{ListenerInfo, Server},
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    /// A service that replies the information about the listener which accepted
+    /// the connection.
+    struct Echo;
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Echo {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Echo;
+        type MakeError = std::io::Error;
+        type Future = FutureResult<Echo, std::io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            futures::future::ok(Echo)
+        }
+    }
+
+    impl Service<Request<Body>> for Echo {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = FutureResult<Response<Body>, std::io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            let body = match request.extensions().get::<ListenerInfo>() {
+                Some(info) => format!("{} {}", info.index(), info.local_addr().unwrap()),
+                None => "none".into(),
+            };
+            futures::future::ok(Response::new(Body::from(body)))
+        }
+    }
+
+    fn get(addr: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn multiple_listeners() {
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").unwrap();
+        let first_addr = first.local_addr().unwrap();
+        let second_addr = second.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            Server::from_listener(Echo, first)
+                .add_listener(second)
+                .run()
+                .unwrap();
+        });
+
+        let response = get(first_addr);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(
+            response.ends_with(&format!("0 {}", first_addr)),
+            "{}",
+            response
+        );
+
+        let response = get(second_addr);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(
+            response.ends_with(&format!("1 {}", second_addr)),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn bind_error_names_address() {
+        let occupied = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = occupied.local_addr().unwrap();
+
+        let err = Server::new(Echo)
+            .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .add_listener(addr)
+            .run()
+            .unwrap_err();
+        assert!(err.to_string().contains(&addr.to_string()), "{}", err);
+    }
+
+    #[test]
+    fn bind_error_stops_other_listeners() {
+        let occupied = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = occupied.local_addr().unwrap();
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let first_addr = first.local_addr().unwrap();
+
+        // the server fails before serving the listener which has been started.
+        let err = Server::from_listener(Echo, first)
+            .add_listener(addr)
+            .run()
+            .unwrap_err();
+        assert!(err.to_string().contains(&addr.to_string()), "{}", err);
+        assert!(TcpStream::connect(first_addr).is_err());
+    }
+
+    #[test]
+    fn bind_replaces_listeners() {
+        let first = TcpListener::bind("127.0.0.1:0").unwrap();
+        let second = TcpListener::bind("127.0.0.1:0").unwrap();
+        let first_addr = first.local_addr().unwrap();
+        let second_addr = second.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            Server::from_listener(Echo, first)
+                .bind(second)
+                .run()
+                .unwrap();
+        });
+
+        let response = get(second_addr);
+        assert!(
+            response.ends_with(&format!("0 {}", second_addr)),
+            "{}",
+            response
+        );
+        assert!(TcpStream::connect(first_addr).is_err());
+    }
+}
+
+mod timeout {
+    use {
+        futures::{future, Async, Future, Poll},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            time::{Duration, Instant},
+        },
+        tsukuyomi_server::Server,
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    /// A service that never replies to the requests to `/stall`.
+    struct Stall;
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Stall {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Stall;
+        type MakeError = std::io::Error;
+        type Future = future::FutureResult<Stall, std::io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            future::ok(Stall)
+        }
+    }
+
+    impl Service<Request<Body>> for Stall {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = Box<dyn Future<Item = Response<Body>, Error = std::io::Error> + Send>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            if request.uri().path() == "/stall" {
+                Box::new(future::empty())
+            } else {
+                Box::new(future::ok(Response::new(Body::from("hello"))))
+            }
+        }
+    }
+
+    fn spawn_server(
+        configure: impl FnOnce(Server<Stall>) -> Server<Stall> + Send + 'static,
+    ) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            configure(Server::new(Stall)).bind(listener).run().unwrap();
+        });
+        addr
+    }
+
+    fn connect(addr: SocketAddr) -> TcpStream {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+    }
+
+    #[test]
+    fn slow_header_closes_connection() {
+        let addr =
+            spawn_server(|server| server.header_read_timeout(Some(Duration::from_millis(1500))));
+
+        let mut stream = connect(addr);
+        let mut writer = stream.try_clone().unwrap();
+        std::thread::spawn(move || {
+            for byte in b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n".iter() {
+                if writer.write_all(&[*byte]).is_err() {
+                    break;
+                }
+                std::thread::sleep(Duration::from_secs(1));
+            }
+        });
+
+        let start = Instant::now();
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response);
+        assert!(
+            response.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&response)
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn stalled_handshake_closes_connection() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            Server::new(Stall)
+                .bind(listener)
+                // waits for the 4-byte preamble before serving the connection.
+                .acceptor(|io: tokio::net::TcpStream| {
+                    tokio::io::read_exact(io, [0u8; 4]).map(|(io, _)| io)
+                })
+                .header_read_timeout(Some(Duration::from_millis(500)))
+                .run()
+                .unwrap();
+        });
+
+        let mut stream = connect(addr);
+        stream.write_all(b"PR").unwrap();
+        let start = Instant::now();
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response);
+        assert!(
+            response.is_empty(),
+            "{}",
+            String::from_utf8_lossy(&response)
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[test]
+    fn request_timeout_replies_408() {
+        let addr = spawn_server(|server| server.request_timeout(Some(Duration::from_millis(500))));
+
+        let mut stream = connect(addr);
+        stream
+            .write_all(b"GET /stall HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(
+            response.starts_with("HTTP/1.1 408 Request Timeout"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn idle_connection_is_closed() {
+        let addr =
+            spawn_server(|server| server.keep_alive_timeout(Some(Duration::from_millis(500))));
+
+        let mut stream = connect(addr);
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let start = Instant::now();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("hello"), "{}", response);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+}
+
+mod limit {
+    use {
+        futures::{future, sync::oneshot, Async, Future, Poll},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            sync::{Arc, Mutex},
+            time::{Duration, Instant},
+        },
+        tsukuyomi_server::{ConcurrencyLimit, Metrics, Server},
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    /// A service that holds the requests to `/block` until they are released.
+    #[derive(Clone, Default)]
+    struct Gate {
+        waiters: Arc<Mutex<Vec<oneshot::Sender<()>>>>,
+    }
+
+    impl Gate {
+        fn release(&self) {
+            for waiter in self.waiters.lock().unwrap().drain(..) {
+                let _ = waiter.send(());
+            }
+        }
+    }
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Gate {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Gate;
+        type MakeError = std::io::Error;
+        type Future = future::FutureResult<Gate, std::io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            future::ok(self.clone())
+        }
+    }
+
+    impl Service<Request<Body>> for Gate {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = Box<dyn Future<Item = Response<Body>, Error = std::io::Error> + Send>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            let response = || Response::new(Body::from("hello"));
+            if request.uri().path() == "/block" {
+                let (tx, rx) = oneshot::channel();
+                self.waiters.lock().unwrap().push(tx);
+                Box::new(rx.then(move |_| Ok(response())))
+            } else {
+                Box::new(future::ok(response()))
+            }
+        }
+    }
+
+    fn spawn_server(
+        configure: impl FnOnce(Server<Gate>) -> Server<Gate>,
+    ) -> (SocketAddr, Gate, Metrics) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let gate = Gate::default();
+        let server = configure(Server::new(gate.clone())).bind(listener);
+        let metrics = server.metrics();
+        std::thread::spawn(move || server.run().unwrap());
+        (addr, gate, metrics)
+    }
+
+    fn send(addr: SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        write!(
+            stream,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        )
+        .unwrap();
+        stream
+    }
+
+    fn read_response(mut stream: TcpStream) -> String {
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn wait_until(mut cond: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !cond() {
+            assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn concurrency_limit_sheds_load() {
+        let (addr, gate, metrics) = spawn_server(|server| {
+            server.concurrency_limit(
+                ConcurrencyLimit::new(1).retry_after(Duration::from_millis(1500)),
+            )
+        });
+
+        let blocked = send(addr, "/block");
+        wait_until(|| metrics.in_flight_requests() == 1);
+
+        let response = read_response(send(addr, "/"));
+        assert!(
+            response.starts_with("HTTP/1.1 503 Service Unavailable"),
+            "{}",
+            response
+        );
+        assert!(
+            response.to_lowercase().contains("retry-after: 2\r\n"),
+            "{}",
+            response
+        );
+        assert_eq!(metrics.in_flight_requests(), 1);
+
+        gate.release();
+        let response = read_response(blocked);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        wait_until(|| metrics.in_flight_requests() == 0);
+
+        let response = read_response(send(addr, "/"));
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    }
+
+    #[test]
+    fn max_connections_defers_accepting() {
+        let (addr, gate, metrics) = spawn_server(|server| server.max_connections(1));
+
+        let blocked = send(addr, "/block");
+        wait_until(|| metrics.in_flight_requests() == 1);
+
+        let mut deferred = send(addr, "/");
+        deferred
+            .set_read_timeout(Some(Duration::from_millis(500)))
+            .unwrap();
+        assert!(deferred.read(&mut [0; 1]).is_err());
+        assert_eq!(metrics.connections(), 1);
+
+        gate.release();
+        let response = read_response(blocked);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        deferred
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let response = read_response(deferred);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        wait_until(|| metrics.connections() == 0);
+    }
+}
+
+mod http2 {
+    use {
+        futures::{future, Async, Future, Poll, Stream},
+        hyper::{Body, Client, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            time::Duration,
+        },
+        tsukuyomi_server::{Http2Config, Server},
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    /// A service that replies the HTTP version of request.
+    struct Version;
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Version {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Version;
+        type MakeError = std::io::Error;
+        type Future = future::FutureResult<Version, std::io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            future::ok(Version)
+        }
+    }
+
+    impl Service<Request<Body>> for Version {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = future::FutureResult<Response<Body>, std::io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            let body = format!("{:?}", request.version());
+            future::ok(Response::new(Body::from(body)))
+        }
+    }
+
+    fn spawn_server(configure: impl FnOnce(Server<Version>) -> Server<Version>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = configure(Server::new(Version)).bind(listener);
+        std::thread::spawn(move || server.run().unwrap());
+        addr
+    }
+
+    fn get_h2c(addr: SocketAddr) -> Vec<String> {
+        let client = Client::builder().http2_only(true).build_http::<Body>();
+        let requests = (0..3).map(move |_| {
+            client
+                .get(format!("http://{}/", addr).parse().unwrap())
+                .and_then(|response| {
+                    assert_eq!(response.status(), 200);
+                    response.into_body().concat2()
+                })
+                .map(|body| String::from_utf8(body.to_vec()).unwrap())
+        });
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(future::join_all(requests)).unwrap()
+    }
+
+    #[test]
+    fn h2c_prior_knowledge() {
+        let addr = spawn_server(|server| {
+            server.http2(
+                Http2Config::new()
+                    .initial_stream_window_size(1024 * 1024)
+                    .initial_connection_window_size(4 * 1024 * 1024)
+                    .max_concurrent_streams(16),
+            )
+        });
+        assert_eq!(get_h2c(addr), vec!["HTTP/2.0"; 3]);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.ends_with("HTTP/1.1"), "{}", response);
+    }
+
+    #[test]
+    fn http2_only() {
+        let addr = spawn_server(|server| server.http2_only(true));
+        assert_eq!(get_h2c(addr), vec!["HTTP/2.0"; 3]);
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response);
+        assert!(
+            !response.starts_with(b"HTTP/1.1"),
+            "{}",
+            String::from_utf8_lossy(&response)
+        );
+    }
+}
+
+mod acceptor {
+    use {
+        futures::{future, Async, Future, Poll},
+        hyper::{
+            header::{CONNECTION, UPGRADE},
+            Body, Request, Response, StatusCode,
+        },
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            time::Duration,
+        },
+        tokio::{io::AsyncRead, net::TcpStream as AsyncTcpStream},
+        tsukuyomi_server::{Acceptor, ConnectionMetadata, Server},
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    #[derive(Debug, Clone, PartialEq)]
+    struct ClientAddr(SocketAddr);
+
+    /// An acceptor that attaches the address of client to the connection.
+    struct WithClientAddr;
+
+    impl Acceptor<AsyncTcpStream> for WithClientAddr {
+        type Conn = AsyncTcpStream;
+        type Error = std::io::Error;
+        type Accept = future::FutureResult<AsyncTcpStream, std::io::Error>;
+
+        fn accept(&self, io: AsyncTcpStream) -> Self::Accept {
+            future::ok(io)
+        }
+
+        fn metadata(conn: &Self::Conn, metadata: &mut ConnectionMetadata) {
+            if let Ok(addr) = conn.peer_addr() {
+                metadata.insert(ClientAddr(addr));
+            }
+        }
+    }
+
+    /// A service that replies the address of client, or echoes the bytes on the
+    /// upgraded connection.
+    struct Echo;
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Echo {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Echo;
+        type MakeError = std::io::Error;
+        type Future = future::FutureResult<Echo, std::io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            future::ok(Echo)
+        }
+    }
+
+    impl Service<Request<Body>> for Echo {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = future::FutureResult<Response<Body>, std::io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            if request.uri().path() == "/echo" {
+                tokio::spawn(
+                    request
+                        .into_body()
+                        .on_upgrade()
+                        .map_err(|e| panic!("failed to upgrade: {}", e))
+                        .and_then(|io| {
+                            let (reader, writer) = io.split();
+                            tokio::io::copy(reader, writer)
+                                .map(|_| ())
+                                .map_err(|e| panic!("echo error: {}", e))
+                        }),
+                );
+                let mut response = Response::new(Body::empty());
+                *response.status_mut() = StatusCode::SWITCHING_PROTOCOLS;
+                response
+                    .headers_mut()
+                    .insert(UPGRADE, "echo".parse().unwrap());
+                response
+                    .headers_mut()
+                    .insert(CONNECTION, "upgrade".parse().unwrap());
+                return future::ok(response);
+            }
+
+            let body = match request.extensions().get::<ClientAddr>() {
+                Some(addr) => addr.0.to_string(),
+                None => "none".into(),
+            };
+            future::ok(Response::new(Body::from(body)))
+        }
+    }
+
+    fn spawn_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            Server::from_listener(Echo, listener)
+                .acceptor(WithClientAddr)
+                .run()
+                .unwrap()
+        });
+        addr
+    }
+
+    fn connect(addr: SocketAddr) -> TcpStream {
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream
+    }
+
+    #[test]
+    fn metadata_is_inserted_into_requests() {
+        let addr = spawn_server();
+
+        let mut stream = connect(addr);
+        let client_addr = stream.local_addr().unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with(&client_addr.to_string()), "{}", response);
+    }
+
+    #[test]
+    fn upgrade_through_acceptor() {
+        let addr = spawn_server();
+
+        let mut stream = connect(addr);
+        stream
+            .write_all(
+                b"GET /echo HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\n",
+            )
+            .unwrap();
+        let mut head = vec![];
+        while !head.ends_with(b"\r\n\r\n") {
+            let mut byte = [0; 1];
+            stream.read_exact(&mut byte).unwrap();
+            head.push(byte[0]);
+        }
+        let head = String::from_utf8(head).unwrap();
+        assert!(
+            head.starts_with("HTTP/1.1 101 Switching Protocols"),
+            "{}",
+            head
+        );
+
+        stream.write_all(b"ping").unwrap();
+        let mut echo = [0; 4];
+        stream.read_exact(&mut echo).unwrap();
+        assert_eq!(&echo, b"ping");
+    }
+}
+
+mod proxy {
+    use {
+        futures::{future, Async, Poll},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            time::Duration,
+        },
+        tsukuyomi_server::{
+            proxy::{ProxiedAddr, ProxyProtocol},
+            Server,
+        },
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    /// A service that replies the address of original client.
+    struct Source;
+
+    impl<'a, T> MakeService<&'a T, Request<Body>> for Source {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Source;
+        type MakeError = std::io::Error;
+        type Future = future::FutureResult<Source, std::io::Error>;
+
+        fn make_service(&self, _: &'a T) -> Self::Future {
+            future::ok(Source)
+        }
+    }
+
+    impl Service<Request<Body>> for Source {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = future::FutureResult<Response<Body>, std::io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, request: Request<Body>) -> Self::Future {
+            let body = match request.extensions().get::<ProxiedAddr>() {
+                Some(addr) => addr.source().to_string(),
+                None => "none".into(),
+            };
+            future::ok(Response::new(Body::from(body)))
+        }
+    }
+
+    fn spawn_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            Server::from_listener(Source, listener)
+                .acceptor(ProxyProtocol::new(()))
+                .run()
+                .unwrap()
+        });
+        addr
+    }
+
+    fn send(addr: SocketAddr, input: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        stream.write_all(input).unwrap();
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response);
+        String::from_utf8(response).unwrap()
+    }
+
+    const REQUEST: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+    #[test]
+    fn v1_header() {
+        let addr = spawn_server();
+        let mut input = b"PROXY TCP4 192.168.0.1 192.168.0.11 56324 443\r\n".to_vec();
+        input.extend_from_slice(REQUEST);
+        let response = send(addr, &input);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("192.168.0.1:56324"), "{}", response);
+
+        let mut input = b"PROXY UNKNOWN\r\n".to_vec();
+        input.extend_from_slice(REQUEST);
+        let response = send(addr, &input);
+        assert!(response.ends_with("none"), "{}", response);
+    }
+
+    #[test]
+    fn v2_header() {
+        let addr = spawn_server();
+        let mut input = b"\r\n\r\n\0\r\nQUIT\n\x21\x11\x00\x0c".to_vec();
+        input.extend_from_slice(&[192, 168, 0, 1, 192, 168, 0, 11, 0xdc, 0x04, 0x01, 0xbb]);
+        input.extend_from_slice(REQUEST);
+        let response = send(addr, &input);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("192.168.0.1:56324"), "{}", response);
+    }
+
+    #[test]
+    fn missing_header_closes_connection() {
+        let addr = spawn_server();
+        assert_eq!(send(addr, REQUEST), "");
+    }
+}
+
+mod runtime {
+    use {
+        futures::{sync::oneshot, Future},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            rc::Rc,
+        },
+        tokio::net::TcpStream as Conn,
+        tsukuyomi_server::{rt, Server},
+        tsukuyomi_service::{make_service_ref, service_fn},
+    };
+
+    fn get(addr: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn run_on_existing_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, std::io::Error>(service_fn(|_: Request<Body>| {
+                Ok::<_, std::io::Error>(Response::new(Body::from("served")))
+            }))
+        });
+
+        let mut runtime = tokio::runtime::Runtime::new().unwrap();
+        Server::new(make_service)
+            .bind(listener)
+            .run_on(&mut runtime)
+            .unwrap();
+
+        // another task is executed alongside the server.
+        let (tx, rx) = oneshot::channel();
+        runtime.spawn(futures::future::lazy(move || {
+            let _ = tx.send("other task");
+            Ok(())
+        }));
+        assert_eq!(rx.wait().unwrap(), "other task");
+
+        let response = get(addr);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("served"), "{}", response);
+
+        runtime.shutdown_now().wait().unwrap();
+    }
+
+    #[test]
+    fn into_future_on_current_thread_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let message = Rc::new("local");
+            let make_service = make_service_ref(move |_: &Conn| {
+                let message = message.clone();
+                Ok::<_, std::io::Error>(service_fn(move |_: Request<Body>| {
+                    Ok::<_, std::io::Error>(Response::new(Body::from(*message)))
+                }))
+            });
+            let serve = Server::new(make_service)
+                .bind(listener)
+                .current_thread()
+                .into_future()
+                .unwrap();
+            let mut runtime = tokio::runtime::current_thread::Runtime::new().unwrap();
+            runtime.spawn(serve);
+            runtime.run().unwrap();
+        });
+
+        let response = get(addr);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("local"), "{}", response);
+    }
+
+    #[test]
+    fn blocking_section_on_single_threaded_runtime() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        std::thread::spawn(move || {
+            let make_service = make_service_ref(|_: &Conn| {
+                Ok::<_, std::io::Error>(service_fn(|_: Request<Body>| {
+                    // executed inline, since there is no blocking pool.
+                    rt::blocking(|| Response::new(Body::from("blocking")))
+                }))
+            });
+            Server::new(make_service)
+                .bind(listener)
+                .run_single_threaded()
+                .unwrap();
+        });
+
+        let response = get(addr);
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert!(response.ends_with("blocking"), "{}", response);
+    }
+}
+
+mod hooks {
+    use {
+        futures::stream,
+        hyper::{Body, Request, Response, StatusCode},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            sync::{mpsc, Arc, Mutex},
+            time::Duration,
+        },
+        tokio::net::TcpStream as Conn,
+        tsukuyomi_server::{ErrorAction, ResponseInfo, Server, ServerError},
+        tsukuyomi_service::{make_service_ref, service_fn},
+    };
+
+    fn send(addr: SocketAddr, input: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(input).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn request_and_response_hooks() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let requests = Arc::new(Mutex::new(vec![]));
+        let (tx, rx) = mpsc::channel::<ResponseInfo>();
+        let tx = Mutex::new(tx);
+
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, std::io::Error>(service_fn(|request: Request<Body>| {
+                // the body is streamed after the service returns the response.
+                let chunks = match request.uri().path() {
+                    "/stream" => vec!["Hello", ", ", "world"],
+                    _ => vec![],
+                };
+                let body = Body::wrap_stream(stream::iter_ok::<_, std::io::Error>(chunks));
+                let mut response = Response::new(body);
+                if request.uri().path() != "/stream" {
+                    *response.status_mut() = StatusCode::NOT_FOUND;
+                }
+                Ok::<_, std::io::Error>(response)
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(listener)
+            .on_request({
+                let requests = requests.clone();
+                move |request| {
+                    requests
+                        .lock()
+                        .unwrap()
+                        .push(request.uri().path().to_owned())
+                }
+            })
+            .on_response(move |info| tx.lock().unwrap().send(info.clone()).unwrap());
+        std::thread::spawn(move || server.run().unwrap());
+
+        let response = send(
+            addr,
+            b"GET /stream HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        let info = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(info.method(), "GET");
+        assert_eq!(info.uri(), "/stream");
+        assert_eq!(info.status(), StatusCode::OK);
+        assert_eq!(info.bytes_written(), 12);
+        assert!(info.completed());
+        // the address of client is taken from the peer of connection.
+        assert_eq!(
+            info.remote_addr().map(|addr| addr.ip()),
+            Some([127, 0, 0, 1].into())
+        );
+
+        let response = send(
+            addr,
+            b"HEAD /missing HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 404"), "{}", response);
+
+        let info = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(info.method(), "HEAD");
+        assert_eq!(info.status(), StatusCode::NOT_FOUND);
+        assert_eq!(info.bytes_written(), 0);
+
+        assert_eq!(*requests.lock().unwrap(), vec!["/stream", "/missing"]);
+    }
+
+    #[test]
+    fn connection_error_hook() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, std::io::Error>(service_fn(|_: Request<Body>| {
+                Ok::<_, std::io::Error>(Response::new(Body::empty()))
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(listener)
+            .on_connection_error({
+                let tx = Mutex::new(tx.lock().unwrap().clone());
+                move |err| tx.lock().unwrap().send(format!("hook: {}", err)).unwrap()
+            })
+            .error_handler(move |err: &ServerError<'_>| {
+                tx.lock()
+                    .unwrap()
+                    .send(format!("handler: {}", err.error()))
+                    .unwrap();
+                ErrorAction::Continue
+            });
+        std::thread::spawn(move || server.run().unwrap());
+
+        let _ = send(addr, b"NOT AN HTTP REQUEST\r\n\r\n");
+        // the hook is called once, just before the error is passed to the handler.
+        let hook = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        let handler = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(hook.starts_with("hook: "), "{}", hook);
+        assert_eq!(hook["hook: ".len()..], handler["handler: ".len()..]);
+        assert!(rx.recv_timeout(Duration::from_millis(100)).is_err());
+    }
+}
+
+mod redirect {
+    use {
+        http::{header::LOCATION, Request, StatusCode},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            time::Duration,
+        },
+        tsukuyomi_server::{redirect::RedirectHttp, test::ResponseExt, Server},
+        tsukuyomi_service::{make_service_ref, service_fn},
+    };
+
+    #[test]
+    fn redirect_to_https() -> tsukuyomi_server::Result<()> {
+        let mut server = tsukuyomi_server::test::server(RedirectHttp::new())?;
+
+        let response = server.perform(
+            Request::get("/path/to?q=1")
+                .header("host", "example.com:8080")
+                .body(()),
+        )?;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.header(LOCATION)?,
+            "https://example.com/path/to?q=1"
+        );
+
+        // the method is not preserved by default.
+        let response = server.perform(Request::post("/").header("host", "example.com").body(()))?;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+
+        // missing or malformed host.
+        let response = server.perform(Request::get("/").body(()))?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let response = server.perform(
+            Request::get("/")
+                .header("host", "user@example.com")
+                .body(()),
+        )?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+        Ok(())
+    }
+
+    #[test]
+    fn canonical_host_and_preserve_method() -> tsukuyomi_server::Result<()> {
+        let mut server = tsukuyomi_server::test::server(
+            RedirectHttp::new()
+                .canonical_host("www.example.com")
+                .https_port(8443)
+                .preserve_method(true),
+        )?;
+
+        let response =
+            server.perform(Request::get("/a").header("host", "evil.example").body(()))?;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(response.header(LOCATION)?, "https://www.example.com:8443/a");
+
+        let response = server.perform(Request::post("/form").body(()))?;
+        assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+        assert_eq!(
+            response.header(LOCATION)?,
+            "https://www.example.com:8443/form"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn acme_challenge() -> tsukuyomi_server::Result<()> {
+        let mut server =
+            tsukuyomi_server::test::server(RedirectHttp::new().acme_challenge(|token| {
+                if token == "token123" {
+                    Some("token123.thumbprint".into())
+                } else {
+                    None
+                }
+            }))?;
+
+        let response = server.perform("/.well-known/acme-challenge/token123")?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().to_utf8()?, "token123.thumbprint");
+
+        let response = server.perform("/.well-known/acme-challenge/unknown")?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        Ok(())
+    }
+
+    #[test]
+    fn served_alongside_the_server() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let redirect_addr: SocketAddr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        let make_service = make_service_ref(|_: &tokio::net::TcpStream| {
+            Ok::<_, std::io::Error>(service_fn(|_: Request<hyper::Body>| {
+                Ok::<_, std::io::Error>(http::Response::new(hyper::Body::from("app")))
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(listener)
+            .redirect_http(redirect_addr, RedirectHttp::new());
+        std::thread::spawn(move || server.run().unwrap());
+
+        let get = |addr: SocketAddr| {
+            let mut stream = loop {
+                match TcpStream::connect(addr) {
+                    Ok(stream) => break stream,
+                    Err(..) => std::thread::sleep(Duration::from_millis(10)),
+                }
+            };
+            stream
+                .write_all(b"GET /x HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).unwrap();
+            response
+        };
+
+        let response = get(addr);
+        assert!(response.ends_with("app"), "{}", response);
+
+        let response = get(redirect_addr);
+        assert!(
+            response.starts_with("HTTP/1.1 301 Moved Permanently"),
+            "{}",
+            response
+        );
+        assert!(
+            response
+                .to_lowercase()
+                .contains("location: https://localhost/x\r\n"),
+            "{}",
+            response
+        );
+    }
+}
+
+mod connection {
+    use {
+        futures::{future, Async, Poll},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+        },
+        tokio::net::TcpStream as Conn,
+        tsukuyomi_server::{Server, TcpConfig},
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    /// A service that replies the value of `TCP_NODELAY` on the connection.
+    struct Nodelay(bool);
+
+    impl<'a> MakeService<&'a Conn, Request<Body>> for Nodelay {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Service = Nodelay;
+        type MakeError = std::io::Error;
+        type Future = future::FutureResult<Nodelay, std::io::Error>;
+
+        fn make_service(&self, conn: &'a Conn) -> Self::Future {
+            future::result(conn.nodelay().map(Nodelay))
+        }
+    }
+
+    impl Service<Request<Body>> for Nodelay {
+        type Response = Response<Body>;
+        type Error = std::io::Error;
+        type Future = future::FutureResult<Response<Body>, std::io::Error>;
+
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            Ok(Async::Ready(()))
+        }
+
+        fn call(&mut self, _: Request<Body>) -> Self::Future {
+            future::ok(Response::new(Body::from(format!("nodelay={}", self.0))))
+        }
+    }
+
+    fn spawn_server(configure: impl FnOnce(Server<Nodelay>) -> Server<Nodelay>) -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = configure(Server::new(Nodelay(false))).bind(listener);
+        std::thread::spawn(move || server.run().unwrap());
+        addr
+    }
+
+    fn send(addr: SocketAddr, input: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(input).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    const GET: &[u8] = b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n";
+
+    #[test]
+    fn nodelay_on_accepted_connections() {
+        let request = b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n";
+
+        let addr = spawn_server(|server| server.tcp(TcpConfig::new().nodelay(true)));
+        let response = send(addr, request);
+        assert!(response.ends_with("nodelay=true"), "{}", response);
+
+        let addr = spawn_server(|server| server.tcp(TcpConfig::new().nodelay(false)));
+        let response = send(addr, request);
+        assert!(response.ends_with("nodelay=false"), "{}", response);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reuse_port_on_bound_address() {
+        let addr = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+        let config = TcpConfig::new().reuse_port(true).backlog(16);
+
+        // the listeners are bound when converting the server into a future.
+        let first = Server::new(Nodelay(false))
+            .bind(addr)
+            .tcp(config)
+            .into_future();
+        assert!(first.is_ok());
+        let second = Server::new(Nodelay(false))
+            .bind(addr)
+            .tcp(config)
+            .into_future();
+        assert!(second.is_ok());
+
+        let third = Server::new(Nodelay(false)).bind(addr).into_future();
+        assert!(third.is_err());
+    }
+
+    #[test]
+    fn keep_alive_disabled() {
+        let addr = spawn_server(|server| server.keep_alive(false));
+
+        // the second request is not answered.
+        let input = [GET, GET].concat();
+        let response = send(addr, &input);
+        assert_eq!(
+            response.matches("HTTP/1.1 200 OK").count(),
+            1,
+            "{}",
+            response
+        );
+        assert!(
+            response.to_lowercase().contains("connection: close\r\n"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn max_requests_per_connection() {
+        let addr = spawn_server(|server| server.max_requests_per_connection(2));
+
+        let input = [GET, GET, GET].concat();
+        let response = send(addr, &input);
+        assert_eq!(
+            response.matches("HTTP/1.1 200 OK").count(),
+            2,
+            "{}",
+            response
+        );
+        let (first, second) = response.split_at(response.rfind("HTTP/1.1").unwrap());
+        assert!(
+            !first.to_lowercase().contains("connection: close\r\n"),
+            "{}",
+            response
+        );
+        assert!(
+            second.to_lowercase().contains("connection: close\r\n"),
+            "{}",
+            response
+        );
+    }
+
+    #[test]
+    fn conflicting_settings() {
+        let message = |server: Server<Nodelay>| {
+            server
+                .bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+                .into_future()
+                .err()
+                .expect("the server should not start")
+                .to_string()
+        };
+
+        let err = message(
+            Server::new(Nodelay(false))
+                .http2_only(true)
+                .keep_alive(false),
+        );
+        assert!(err.contains("HTTP/2-only"), "{}", err);
+
+        let err = message(
+            Server::new(Nodelay(false))
+                .max_requests_per_connection(10)
+                .http2_only(true),
+        );
+        assert!(err.contains("HTTP/2-only"), "{}", err);
+
+        let err = message(
+            Server::new(Nodelay(false))
+                .keep_alive(false)
+                .max_requests_per_connection(10),
+        );
+        assert!(err.contains("keep-alive is disabled"), "{}", err);
+
+        let err = message(Server::new(Nodelay(false)).max_requests_per_connection(0));
+        assert!(err.contains("must be positive"), "{}", err);
+    }
+}
+
+mod health {
+    use {
+        hyper::{Body, Request, Response, StatusCode},
+        std::{
+            io::{Read, Write},
+            net::{SocketAddr, TcpListener, TcpStream},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+        },
+        tokio::net::TcpStream as Conn,
+        tsukuyomi_server::Server,
+        tsukuyomi_service::{make_service_ref, service_fn},
+    };
+
+    fn send(addr: SocketAddr, input: &[u8]) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(input).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let request = format!(
+            "GET {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            path
+        );
+        send(addr, request.as_bytes())
+    }
+
+    #[test]
+    fn health_endpoint_bypasses_service() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        // the service behaves like an authentication middleware rejecting everything.
+        let calls = Arc::new(AtomicUsize::new(0));
+        let make_service = make_service_ref({
+            let calls = calls.clone();
+            move |_: &Conn| {
+                let calls = calls.clone();
+                Ok::<_, std::io::Error>(service_fn(move |_: Request<Body>| {
+                    calls.fetch_add(1, Ordering::SeqCst);
+                    let mut response = Response::new(Body::empty());
+                    *response.status_mut() = StatusCode::UNAUTHORIZED;
+                    Ok::<_, std::io::Error>(response)
+                }))
+            }
+        });
+        let server = Server::new(make_service)
+            .bind(listener)
+            .health_endpoint("/healthz");
+        let handle = server.handle();
+        std::thread::spawn(move || server.run().unwrap());
+
+        let response = get(addr, "/healthz");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        let response = get(addr, "/healthz/");
+        assert!(response.starts_with("HTTP/1.1 401"), "{}", response);
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // the readiness is shared with the clones of handle.
+        handle.clone().set_ready(false);
+        assert!(!handle.is_ready());
+        let response = get(addr, "/healthz");
+        assert!(response.starts_with("HTTP/1.1 503"), "{}", response);
+
+        handle.set_ready(true);
+        let response = get(addr, "/healthz");
+        assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+
+        let response = send(
+            addr,
+            b"POST /healthz HTTP/1.1\r\nHost: localhost\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+        assert!(
+            response.to_lowercase().contains("allow: get, head\r\n"),
+            "{}",
+            response
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+}
+
+mod error_handler {
+    use {
+        futures::{Poll, Stream},
+        hyper::{Body, Request, Response},
+        std::{
+            io::{self, Read, Write},
+            net::{SocketAddr, TcpStream},
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                mpsc, Arc, Mutex,
+            },
+            time::Duration,
+        },
+        tokio::{
+            net::{tcp::Incoming, TcpListener, TcpStream as Conn},
+            reactor::Handle,
+        },
+        tsukuyomi_server::{ErrorAction, Listener, Server, ServerError},
+        tsukuyomi_service::{make_service_ref, service_fn},
+    };
+
+    /// A listener that fails with the specified error before accepting the connections.
+    #[cfg(unix)]
+    struct Exhausted {
+        listener: std::net::TcpListener,
+        failures: usize,
+        errno: i32,
+    }
+
+    #[cfg(unix)]
+    struct ExhaustedIncoming {
+        incoming: Incoming,
+        failures: usize,
+        errno: i32,
+    }
+
+    #[cfg(unix)]
+    impl Listener for Exhausted {
+        type Conn = Conn;
+        type Error = io::Error;
+        type Incoming = ExhaustedIncoming;
+
+        fn listen(self) -> io::Result<Self::Incoming> {
+            let listener = TcpListener::from_std(self.listener, &Handle::default())?;
+            Ok(ExhaustedIncoming {
+                incoming: listener.incoming(),
+                failures: self.failures,
+                errno: self.errno,
+            })
+        }
+    }
+
+    #[cfg(unix)]
+    impl Stream for ExhaustedIncoming {
+        type Item = Conn;
+        type Error = io::Error;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            if self.failures > 0 {
+                self.failures -= 1;
+                futures::task::current().notify();
+                return Err(io::Error::from_raw_os_error(self.errno));
+            }
+            self.incoming.poll()
+        }
+    }
+
+    fn get(addr: SocketAddr) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .unwrap();
+        let mut response = String::new();
+        let _ = stream.read_to_string(&mut response);
+        response
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn accept_errors_do_not_stop_server() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let errors = Arc::new(Mutex::new(vec![]));
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, io::Error>(service_fn(|_: Request<Body>| {
+                Ok::<_, io::Error>(Response::new(Body::from("hello")))
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(Exhausted {
+                listener,
+                failures: 3,
+                errno: libc::EMFILE,
+            })
+            .error_handler({
+                let errors = errors.clone();
+                move |err: &ServerError<'_>| {
+                    match err {
+                        ServerError::Accept(..) => {
+                            errors.lock().unwrap().push(err.is_resource_exhausted())
+                        }
+                        err => panic!("unexpected error: {}", err),
+                    }
+                    ErrorAction::Continue
+                }
+            });
+        std::thread::spawn(move || server.run().unwrap());
+
+        let response = get(addr);
+        assert!(response.ends_with("hello"), "{}", response);
+        assert_eq!(*errors.lock().unwrap(), vec![true, true, true]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn persistent_accept_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let errors = Arc::new(Mutex::new(vec![]));
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, io::Error>(service_fn(|_: Request<Body>| {
+                Ok::<_, io::Error>(Response::new(Body::from("hello")))
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(Exhausted {
+                listener,
+                failures: 20,
+                errno: libc::ECONNABORTED,
+            })
+            .error_handler({
+                let errors = errors.clone();
+                move |err: &ServerError<'_>| {
+                    errors.lock().unwrap().push(err.is_resource_exhausted());
+                    ErrorAction::Continue
+                }
+            });
+        std::thread::spawn(move || server.run().unwrap());
+
+        let response = get(addr);
+        assert!(response.ends_with("hello"), "{}", response);
+        assert_eq!(*errors.lock().unwrap(), vec![false; 20]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn memory_exhaustion_is_resource_exhausted() {
+        let err = io::Error::from_raw_os_error(libc::ENOBUFS);
+        assert!(ServerError::Accept(&err).is_resource_exhausted());
+        let err = io::Error::from_raw_os_error(libc::ENOMEM);
+        assert!(ServerError::Accept(&err).is_resource_exhausted());
+        let err = io::Error::from_raw_os_error(libc::ECONNABORTED);
+        assert!(!ServerError::Accept(&err).is_resource_exhausted());
+    }
+
+    #[test]
+    fn handshake_errors_are_reported() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let tx = Mutex::new(tx);
+        let attempts = AtomicUsize::new(0);
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, io::Error>(service_fn(|_: Request<Body>| {
+                Ok::<_, io::Error>(Response::new(Body::from("hello")))
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(listener)
+            .acceptor(move |io: Conn| {
+                if attempts.fetch_add(1, Ordering::SeqCst) == 0 {
+                    Err(io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        "handshake failed",
+                    ))
+                } else {
+                    Ok(io)
+                }
+            })
+            .error_handler(move |err: &ServerError<'_>| {
+                tx.lock().unwrap().send(err.to_string()).unwrap();
+                ErrorAction::Continue
+            });
+        std::thread::spawn(move || server.run().unwrap());
+
+        // the first connection is closed without any response.
+        let response = get(addr);
+        assert_eq!(response, "");
+        let err = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(err, "acceptor error: handshake failed");
+
+        let response = get(addr);
+        assert!(response.ends_with("hello"), "{}", response);
+    }
+
+    #[test]
+    fn shutdown_requested_by_handler() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let make_service = make_service_ref(|_: &Conn| {
+            Ok::<_, io::Error>(service_fn(|_: Request<Body>| {
+                Ok::<_, io::Error>(Response::new(Body::from("hello")))
+            }))
+        });
+        let server = Server::new(make_service)
+            .bind(listener)
+            .acceptor(|_: Conn| Err::<Conn, _>(io::Error::new(io::ErrorKind::InvalidData, "fatal")))
+            .error_handler(|err: &ServerError<'_>| match err {
+                ServerError::Handshake(..) => ErrorAction::Shutdown,
+                _ => ErrorAction::Continue,
+            });
+        let handle = server.handle();
+        let (tx, rx) = mpsc::channel();
+        std::thread::spawn(move || tx.send(server.run().is_ok()).unwrap());
+
+        let _ = get(addr);
+        assert_eq!(rx.recv_timeout(Duration::from_secs(5)), Ok(true));
+        assert!(handle.is_shutdown());
+
+        // the listener has been closed.
+        assert!(TcpStream::connect(addr).is_err());
+    }
+}
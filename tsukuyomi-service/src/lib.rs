@@ -13,7 +13,13 @@
 )]
 #![forbid(clippy::unimplemented)]
 
-use futures::{Async, Future, IntoFuture, Poll};
+use {
+    futures::{Async, Future, IntoFuture, Poll},
+    std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 
 #[doc(no_inline)]
 pub use tower_service::Service;
@@ -350,3 +356,58 @@ where
 
     ModifyServiceRefFn(f)
 }
+
+/// The DER-encoded certificate chain presented by the peer of a connection,
+/// starting with its own certificate.
+///
+/// The value is created once per connection by the transport layer, such as the
+/// TLS acceptor of the server, and a clone of it is inserted into the extension map
+/// of every request received on that connection.  The clones share the chain.
+#[derive(Debug, Clone)]
+pub struct PeerCertificates {
+    chain: Arc<Vec<Vec<u8>>>,
+}
+
+impl PeerCertificates {
+    /// Creates a `PeerCertificates` from a list of DER-encoded certificates,
+    /// starting with the end-entity certificate.
+    pub fn new(chain: Vec<Vec<u8>>) -> Self {
+        Self {
+            chain: Arc::new(chain),
+        }
+    }
+
+    /// Returns the list of DER-encoded certificates.
+    pub fn chain(&self) -> &[Vec<u8>] {
+        &self.chain[..]
+    }
+}
+
+/// A flag set when the transmission of a response body has been terminated by an
+/// error after the response head was sent.
+///
+/// The value is created by the application for a streaming response body, and a clone
+/// of it is inserted into the extension map of the response, so that the server can
+/// tell the truncated responses (e.g. in the access log) even if the protocol does not
+/// report the error to the server.  The clones share the flag.
+#[derive(Debug, Clone, Default)]
+pub struct Truncation {
+    truncated: Arc<AtomicBool>,
+}
+
+impl Truncation {
+    /// Creates a `Truncation` which is not set.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Marks the response body as truncated.
+    pub fn set(&self) {
+        self.truncated.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the response body has been truncated.
+    pub fn is_set(&self) -> bool {
+        self.truncated.load(Ordering::SeqCst)
+    }
+}
@@ -201,13 +201,13 @@ impl TryFuture for ReadSession {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct CookieSession {
     inner: Inner,
     backend: CookieBackend,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum Inner {
     Empty,
     Some(HashMap<String, String>),
@@ -0,0 +1,294 @@
+use {
+    crate::{Backend, RawSession, SessionStore},
+    cookie::Cookie,
+    futures::future::FutureResult,
+    rand::{rngs::OsRng, RngCore},
+    std::{
+        borrow::Cow,
+        collections::HashMap,
+        fmt,
+        sync::{Arc, Mutex},
+        time::{Duration, SystemTime},
+    },
+    tsukuyomi::{
+        error::{Error, Result},
+        future::{Poll, TryFuture},
+        input::Input,
+    },
+};
+
+/// A `Backend` that stores the session data in the process memory.
+///
+/// The clones share the same storage.  Each session expires after the lifetime
+/// specified by `ttl` has elapsed since its last modification.  The expired
+/// sessions are no longer visible to the handlers, but they are kept in memory
+/// until removed by `SessionStore::purge_expired`.
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    inner: Arc<MemoryBackendInner>,
+}
+
+impl Default for MemoryBackend {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(MemoryBackendInner {
+                sessions: Mutex::new(HashMap::new()),
+                cookie_name: "session-id".into(),
+                ttl: Duration::from_secs(60 * 60),
+            }),
+        }
+    }
+}
+
+impl MemoryBackend {
+    /// Create a new `MemoryBackend` with the default configuration.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn inner_mut(&mut self) -> &mut MemoryBackendInner {
+        Arc::get_mut(&mut self.inner).expect("the value has already been shared")
+    }
+
+    /// Sets the name of Cookie entry for storing the session ID.
+    ///
+    /// The default value is `"session-id"`.
+    pub fn cookie_name(mut self, name: impl Into<Cow<'static, str>>) -> Self {
+        self.inner_mut().cookie_name = name.into();
+        self
+    }
+
+    /// Sets the lifetime of sessions.
+    ///
+    /// The default value is one hour.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.inner_mut().ttl = ttl;
+        self
+    }
+
+    /// Returns the number of stored sessions, including the expired ones.
+    pub fn len(&self) -> usize {
+        self.inner.lock().len()
+    }
+
+    /// Returns `true` if no session is stored.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+struct MemoryBackendInner {
+    sessions: Mutex<HashMap<String, Entry>>,
+    cookie_name: Cow<'static, str>,
+    ttl: Duration,
+}
+
+struct Entry {
+    data: HashMap<String, String>,
+    expires_at: SystemTime,
+}
+
+#[cfg_attr(tarpaulin, skip)]
+impl fmt::Debug for MemoryBackendInner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryBackendInner")
+            .field("cookie_name", &self.cookie_name)
+            .field("ttl", &self.ttl)
+            .finish()
+    }
+}
+
+impl MemoryBackendInner {
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.sessions
+            .lock()
+            .expect("the session storage should not be poisoned")
+    }
+
+    fn get_session_id(&self, input: &mut Input<'_>) -> Result<Option<String>> {
+        Ok(input
+            .cookies
+            .jar()?
+            .get(&self.cookie_name)
+            .map(|cookie| cookie.value().to_owned()))
+    }
+
+    fn read(&self, input: &mut Input<'_>) -> Result<(Inner, Option<String>)> {
+        let session_id = match self.get_session_id(input)? {
+            Some(session_id) => session_id,
+            None => return Ok((Inner::Empty, None)),
+        };
+        match self.lock().get(&session_id) {
+            Some(entry) if entry.expires_at > SystemTime::now() => {
+                Ok((Inner::Some(entry.data.clone()), Some(session_id)))
+            }
+            _ => Ok((Inner::Empty, None)),
+        }
+    }
+
+    fn write(&self, input: &mut Input<'_>, inner: Inner, session_id: Option<String>) -> Result<()> {
+        match inner {
+            Inner::Empty => {}
+            Inner::Some(data) => {
+                let session_id = match session_id {
+                    Some(session_id) => session_id,
+                    None => generate_session_id()?,
+                };
+                input
+                    .cookies
+                    .jar()?
+                    .add(Cookie::new(self.cookie_name.clone(), session_id.clone()));
+                let entry = Entry {
+                    data,
+                    expires_at: SystemTime::now() + self.ttl,
+                };
+                self.lock().insert(session_id, entry);
+            }
+            Inner::Clear => {
+                if let Some(session_id) = session_id {
+                    input
+                        .cookies
+                        .jar()?
+                        .remove(Cookie::named(self.cookie_name.clone()));
+                    self.lock().remove(&session_id);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Generates a session ID of 128 random bits from the OS, in hexadecimal.
+fn generate_session_id() -> Result<String> {
+    let mut rng = OsRng::new().map_err(tsukuyomi::error::internal_server_error)?;
+    let mut bytes = [0u8; 16];
+    rng.try_fill_bytes(&mut bytes)
+        .map_err(tsukuyomi::error::internal_server_error)?;
+    Ok(bytes.iter().map(|b| format!("{:02x}", b)).collect())
+}
+
+impl Backend for MemoryBackend {
+    type Session = MemorySession;
+    type ReadError = Error;
+    type ReadSession = ReadSession;
+
+    fn read(&self) -> Self::ReadSession {
+        ReadSession(Some(self.clone()))
+    }
+}
+
+impl SessionStore for MemoryBackend {
+    type PurgeError = Error;
+    type PurgeExpired = FutureResult<usize, Error>;
+
+    fn purge_expired(&self, before: SystemTime) -> Self::PurgeExpired {
+        let mut sessions = self.inner.lock();
+        let len = sessions.len();
+        sessions.retain(|_, entry| entry.expires_at >= before);
+        futures::future::ok(len - sessions.len())
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct ReadSession(Option<MemoryBackend>);
+
+impl TryFuture for ReadSession {
+    type Ok = MemorySession;
+    type Error = Error;
+
+    #[inline]
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let backend = self.0.take().expect("the future has already been polled");
+        backend.inner.read(input).map(|(inner, session_id)| {
+            MemorySession {
+                inner,
+                backend,
+                session_id,
+            }
+            .into()
+        })
+    }
+}
+
+#[allow(missing_debug_implementations)]
+#[derive(Clone)]
+pub struct MemorySession {
+    inner: Inner,
+    backend: MemoryBackend,
+    session_id: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Inner {
+    Empty,
+    Some(HashMap<String, String>),
+    Clear,
+}
+
+impl RawSession for MemorySession {
+    type WriteSession = WriteSession;
+    type WriteError = Error;
+
+    fn get(&self, name: &str) -> Option<&str> {
+        match self.inner {
+            Inner::Some(ref map) => map.get(name).map(|s| &**s),
+            _ => None,
+        }
+    }
+
+    fn set(&mut self, name: &str, value: String) {
+        match self.inner {
+            Inner::Empty => {}
+            Inner::Some(ref mut map) => {
+                map.insert(name.to_owned(), value);
+                return;
+            }
+            Inner::Clear => return,
+        }
+
+        match std::mem::replace(&mut self.inner, Inner::Empty) {
+            Inner::Empty => {
+                self.inner = Inner::Some({
+                    let mut map = HashMap::new();
+                    map.insert(name.to_owned(), value);
+                    map
+                });
+            }
+            Inner::Some(..) | Inner::Clear => unreachable!(),
+        }
+    }
+
+    fn remove(&mut self, name: &str) {
+        if let Inner::Some(ref mut map) = self.inner {
+            map.remove(name);
+        }
+    }
+
+    fn clear(&mut self) {
+        self.inner = Inner::Clear;
+    }
+
+    fn write(self) -> Self::WriteSession {
+        WriteSession(Some(self))
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct WriteSession(Option<MemorySession>);
+
+impl TryFuture for WriteSession {
+    type Ok = ();
+    type Error = Error;
+
+    #[inline]
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let session = self.0.take().expect("the future has already been polled");
+        session
+            .backend
+            .inner
+            .write(input, session.inner, session.session_id)
+            .map(Into::into)
+    }
+}
@@ -1,8 +1,9 @@
 //! The definition of session backends
 
 mod cookie;
+mod memory;
 mod redis;
 
-pub use self::cookie::CookieBackend;
 #[cfg(feature = "use-redis")]
 pub use self::redis::RedisBackend;
+pub use self::{cookie::CookieBackend, memory::MemoryBackend};
@@ -1,11 +1,12 @@
 #![cfg(feature = "use-redis")]
 
 use {
-    crate::{Backend, RawSession},
+    crate::{Backend, RawSession, SessionStore},
     cookie::Cookie,
+    futures::future::FutureResult,
     futures::try_ready,
     redis::{r#async::Connection, Client, RedisFuture},
-    std::time::Duration,
+    std::time::{Duration, SystemTime},
     std::{borrow::Cow, collections::HashMap, mem, sync::Arc},
     tsukuyomi::{
         error::{Error, Result},
@@ -16,6 +17,10 @@ use {
 };
 
 /// A `Backend` using Redis.
+///
+/// The expiration of sessions is delegated to Redis by `SETEX` (see `timeout`),
+/// so the implementation of `SessionStore::purge_expired` does nothing and always
+/// returns zero.
 #[derive(Debug, Clone)]
 pub struct RedisBackend {
     inner: Arc<RedisBackendInner>,
@@ -103,6 +108,15 @@ impl Backend for RedisBackend {
     }
 }
 
+impl SessionStore for RedisBackend {
+    type PurgeError = Error;
+    type PurgeExpired = FutureResult<usize, Error>;
+
+    fn purge_expired(&self, _: SystemTime) -> Self::PurgeExpired {
+        futures::future::ok(0)
+    }
+}
+
 #[allow(missing_debug_implementations)]
 pub struct RedisSession {
     inner: Inner,
@@ -0,0 +1,250 @@
+//! Garbage collection of the expired sessions.
+//!
+//! The backends that keep the session data on the server side accumulate the
+//! sessions whose clients never come back.  `Collector` is a background task
+//! that periodically removes them through `SessionStore::purge_expired`.
+//!
+//! # Example
+//!
+//! ```
+//! # use std::time::Duration;
+//! use tsukuyomi_session::{backend::MemoryBackend, gc};
+//!
+//! let backend = MemoryBackend::new();
+//! let collector = gc::collector(backend.clone())
+//!     .interval(Duration::from_secs(5 * 60))
+//!     .jitter(Duration::from_secs(30));
+//! let metrics = collector.metrics();
+//! let handle = collector.handle();
+//!
+//! // spawn the collector onto the runtime that drives the server, e.g.
+//! // `tsukuyomi_server::rt::spawn(collector)`, and stop it on shutdown.
+//! # drop(collector);
+//! handle.stop();
+//! # assert_eq!(metrics.runs(), 0);
+//! ```
+
+use {
+    crate::SessionStore,
+    futures::{task::AtomicTask, Async, Future, Poll},
+    std::{
+        fmt,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc,
+        },
+        time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+    },
+    tokio_timer::Delay,
+};
+
+/// Creates a `Collector` that purges the expired sessions in the specified store.
+pub fn collector<S>(store: S) -> Collector<S>
+where
+    S: SessionStore,
+{
+    Collector {
+        store,
+        interval: Duration::from_secs(10 * 60),
+        jitter: Duration::from_secs(60),
+        metrics: Metrics::default(),
+        handle: Handle::default(),
+        rng: seed(),
+        state: None,
+    }
+}
+
+/// A `Future` that periodically purges the expired sessions.
+///
+/// The value of this type is created by `collector`.  It runs until stopped by
+/// `Handle::stop`, and must be spawned onto the runtime where the timer is available.
+/// A failed sweep is counted in the metrics and retried at the next tick.
+pub struct Collector<S: SessionStore> {
+    store: S,
+    interval: Duration,
+    jitter: Duration,
+    metrics: Metrics,
+    handle: Handle,
+    rng: u64,
+    state: Option<State<S::PurgeExpired>>,
+}
+
+enum State<F> {
+    Waiting(Delay),
+    Purging(F),
+}
+
+impl<S> fmt::Debug for Collector<S>
+where
+    S: SessionStore + fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Collector")
+            .field("store", &self.store)
+            .field("interval", &self.interval)
+            .field("jitter", &self.jitter)
+            .field("metrics", &self.metrics)
+            .finish()
+    }
+}
+
+impl<S> Collector<S>
+where
+    S: SessionStore,
+{
+    /// Sets the interval between the sweeps.
+    ///
+    /// The default value is 10 minutes.
+    ///
+    /// # Panics
+    /// This method panics if `interval` is zero.
+    pub fn interval(self, interval: Duration) -> Self {
+        assert!(
+            interval > Duration::from_secs(0),
+            "the interval must be positive"
+        );
+        Self { interval, ..self }
+    }
+
+    /// Sets the upper bound of the random delay added to each interval, so that
+    /// the server instances sharing a store do not sweep it at the same time.
+    ///
+    /// The default value is 1 minute.
+    pub fn jitter(self, jitter: Duration) -> Self {
+        Self { jitter, ..self }
+    }
+
+    /// Returns a handle to the metrics of this collector.
+    pub fn metrics(&self) -> Metrics {
+        self.metrics.clone()
+    }
+
+    /// Returns a handle for stopping this collector.
+    pub fn handle(&self) -> Handle {
+        self.handle.clone()
+    }
+
+    fn next_delay(&mut self) -> Delay {
+        // xorshift64; the quality of randomness does not matter here.
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        let jitter_ms = self.jitter.as_secs() * 1000 + u64::from(self.jitter.subsec_millis());
+        let jitter = match jitter_ms {
+            0 => Duration::from_secs(0),
+            n => Duration::from_millis(self.rng % (n + 1)),
+        };
+        Delay::new(Instant::now() + self.interval + jitter)
+    }
+}
+
+fn seed() -> u64 {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_else(|_| Duration::from_secs(0));
+    (now.as_secs() ^ u64::from(now.subsec_nanos())) | 1
+}
+
+impl<S> Future for Collector<S>
+where
+    S: SessionStore,
+{
+    type Item = ();
+    type Error = ();
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        self.handle.inner.task.register();
+        if self.handle.is_stopped() {
+            // The sweep in progress, if any, is abandoned.
+            self.state = None;
+            return Ok(Async::Ready(()));
+        }
+
+        loop {
+            let state = match self.state {
+                None => State::Waiting(self.next_delay()),
+                Some(State::Waiting(ref mut delay)) => {
+                    // The timer has gone away, so the collector cannot run any more.
+                    futures::try_ready!(delay.poll().map_err(|_| ()));
+                    State::Purging(self.store.purge_expired(SystemTime::now()))
+                }
+                Some(State::Purging(ref mut purge)) => {
+                    match purge.poll() {
+                        Ok(Async::Ready(purged)) => self.metrics.record(Some(purged)),
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(..) => self.metrics.record(None),
+                    }
+                    State::Waiting(self.next_delay())
+                }
+            };
+            self.state = Some(state);
+        }
+    }
+}
+
+/// A handle for stopping a `Collector`.
+#[derive(Debug, Clone, Default)]
+pub struct Handle {
+    inner: Arc<StopSignal>,
+}
+
+#[derive(Debug, Default)]
+struct StopSignal {
+    stopped: AtomicBool,
+    task: AtomicTask,
+}
+
+impl Handle {
+    /// Stops the collector, which then completes without waiting for the next tick.
+    pub fn stop(&self) {
+        self.inner.stopped.store(true, Ordering::SeqCst);
+        self.inner.task.notify();
+    }
+
+    /// Returns `true` if the collector has been stopped.
+    pub fn is_stopped(&self) -> bool {
+        self.inner.stopped.load(Ordering::SeqCst)
+    }
+}
+
+/// A handle to the counters of a `Collector`.
+#[derive(Debug, Clone, Default)]
+pub struct Metrics {
+    counters: Arc<Counters>,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    runs: AtomicUsize,
+    purged: AtomicUsize,
+    failures: AtomicUsize,
+}
+
+impl Metrics {
+    /// Returns the number of completed sweeps, including the failed ones.
+    pub fn runs(&self) -> usize {
+        self.counters.runs.load(Ordering::SeqCst)
+    }
+
+    /// Returns the total number of purged sessions.
+    pub fn purged(&self) -> usize {
+        self.counters.purged.load(Ordering::SeqCst)
+    }
+
+    /// Returns the number of failed sweeps.
+    pub fn failures(&self) -> usize {
+        self.counters.failures.load(Ordering::SeqCst)
+    }
+
+    fn record(&self, purged: Option<usize>) {
+        match purged {
+            Some(purged) => {
+                self.counters.purged.fetch_add(purged, Ordering::SeqCst);
+            }
+            None => {
+                self.counters.failures.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+        self.counters.runs.fetch_add(1, Ordering::SeqCst);
+    }
+}
@@ -12,10 +12,13 @@
 #![forbid(clippy::unimplemented)]
 
 pub mod backend;
+pub mod gc;
 mod util;
 
 use {
+    futures::Future,
     serde::{de::DeserializeOwned, ser::Serialize},
+    std::time::SystemTime,
     tsukuyomi::{
         error::Error, //
         extractor::Extractor,
@@ -79,6 +82,33 @@ where
     }
 }
 
+/// A trait representing the backends that keep the session data on the server side.
+///
+/// The stored sessions are removed periodically by the task created with
+/// `gc::collector`.
+pub trait SessionStore {
+    /// The type of errors which will occur when purging the expired sessions.
+    type PurgeError: Into<Error>;
+    /// The type of `Future` that will return the number of purged sessions.
+    type PurgeExpired: Future<Item = usize, Error = Self::PurgeError>;
+
+    /// Creates a `Future` to remove the sessions that expired before the specified time.
+    fn purge_expired(&self, before: SystemTime) -> Self::PurgeExpired;
+}
+
+impl<S> SessionStore for std::sync::Arc<S>
+where
+    S: SessionStore,
+{
+    type PurgeError = S::PurgeError;
+    type PurgeExpired = S::PurgeExpired;
+
+    #[inline]
+    fn purge_expired(&self, before: SystemTime) -> Self::PurgeExpired {
+        (**self).purge_expired(before)
+    }
+}
+
 /// A trait that abstracts the management of session data during request handling.
 pub trait RawSession {
     /// The error type during writing modification to the backend.
@@ -103,6 +133,13 @@ pub trait RawSession {
 }
 
 /// Create an `Extractor` which returns a `Session`.
+///
+/// The session is read from the backend every time the extractor runs.  In order to
+/// read it at most once per request (e.g. when the extractor is shared with `Arc`
+/// between a modifier and an endpoint), wrap it with `ExtractorExt::cached`, which
+/// is available if the session of the backend implements `Clone` (as with the cookie
+/// and in-memory backends).  Note that the cached values are independent copies,
+/// and hence the modification should be made and finished through only one of them.
 pub fn session<B>(
     backend: B,
 ) -> impl Extractor<
@@ -150,7 +187,7 @@ mod impl_extractor {
 }
 
 /// An interface of session values.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Session<S: RawSession> {
     raw: S,
 }
@@ -1,10 +1,14 @@
 use {
+    futures::Future,
     http::Request,
+    std::time::{Duration, Instant, SystemTime},
     tsukuyomi::{config::prelude::*, App},
     tsukuyomi_session::{
-        backend::CookieBackend, //
+        backend::{CookieBackend, MemoryBackend}, //
+        gc,
         session,
         Session,
+        SessionStore,
     },
 };
 
@@ -75,3 +79,173 @@ fn smoketest() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn cached_session() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::extractor::ExtractorExt;
+
+    let backend = CookieBackend::plain().cookie_name("session");
+    let session = std::sync::Arc::new(session(backend).cached());
+
+    let app = App::create(chain![
+        path!("/login").to(endpoint::put() //
+            .extract(session.clone())
+            .call_async(|mut session: Session<_>| -> tsukuyomi::Result<_> {
+                session.set("username", "alice")?;
+                Ok(session.finish("logged in"))
+            })),
+        path!("/whoami").to(endpoint::get()
+            .extract(session.clone())
+            .extract(session)
+            .call_async(
+                |first: Session<_>, second: Session<_>| -> tsukuyomi::Result<_> {
+                    let first: Option<String> = first.get("username")?;
+                    let second: Option<String> = second.get("username")?;
+                    assert_eq!(first, second);
+                    Ok(format!("{:?}", first))
+                }
+            )),
+    ])?;
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let mut session = server.new_session()?.save_cookies(true);
+
+    session.perform(Request::put("/login"))?;
+    let response = session.perform("/whoami")?;
+    assert_eq!(response.body().to_utf8()?, "Some(\"alice\")");
+
+    Ok(())
+}
+
+#[test]
+fn finish_with_cookie() -> tsukuyomi_server::Result<()> {
+    use {cookie::Cookie, tsukuyomi::responder::ResponderExt};
+
+    let backend = CookieBackend::plain().cookie_name("session");
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::put().extract(session(backend)).call_async(
+                |mut session: Session<_>| -> tsukuyomi::Result<_> {
+                    session.set("counter", 1)?;
+                    Ok(session
+                        .finish("done")
+                        .with_cookie(Cookie::new("theme", "dark")))
+                },
+            )),
+    )?;
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let mut session = server.new_session()?.save_cookies(true);
+
+    let response = session.perform(Request::put("/"))?;
+    assert_eq!(response.headers().get_all("set-cookie").iter().count(), 2);
+    assert!(session.cookie("session").is_some());
+    assert_eq!(session.cookie("theme"), Some("dark"));
+
+    Ok(())
+}
+
+fn counter_app(backend: MemoryBackend) -> tsukuyomi::app::Result<App> {
+    let session = std::sync::Arc::new(session(backend));
+    App::create(path!("/counter").to(chain![
+            endpoint::get() //
+                .extract(session.clone())
+                .call_async(|session: Session<_>| -> tsukuyomi::Result<_> {
+                    let counter: Option<i64> = session.get("counter")?;
+                    Ok(session.finish(format!("{:?}", counter)))
+                }),
+            endpoint::put() //
+                .extract(session)
+                .call_async(|mut session: Session<_>| -> tsukuyomi::Result<_> {
+                    let counter: i64 = session.get("counter")?.unwrap_or_default();
+                    session.set("counter", counter + 1)?;
+                    Ok(session.finish(format!("{}", counter)))
+                }),
+        ]))
+}
+
+#[test]
+fn memory_backend() -> tsukuyomi_server::Result<()> {
+    let backend = MemoryBackend::new().cookie_name("session");
+    let mut server = tsukuyomi_server::test::server(counter_app(backend.clone())?)?;
+    let mut session = server.new_session()?.save_cookies(true);
+
+    assert_eq!(session.perform("/counter")?.body().to_utf8()?, "None");
+    assert!(backend.is_empty());
+
+    session.perform(Request::put("/counter"))?;
+    session.perform(Request::put("/counter"))?;
+    assert!(session.cookie("session").is_some());
+    assert_eq!(session.perform("/counter")?.body().to_utf8()?, "Some(2)");
+    assert_eq!(backend.len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn purge_expired_removes_only_expired_sessions() -> tsukuyomi_server::Result<()> {
+    let ttl = Duration::from_secs(60);
+    let backend = MemoryBackend::new().ttl(ttl);
+    let mut server = tsukuyomi_server::test::server(counter_app(backend.clone())?)?;
+
+    let old_cookie = {
+        let mut session = server.new_session()?.save_cookies(true);
+        session.perform(Request::put("/counter"))?;
+        format!("session-id={}", session.cookie("session-id").unwrap())
+    };
+    let threshold = SystemTime::now() + ttl;
+    std::thread::sleep(Duration::from_millis(10));
+    let new_cookie = {
+        let mut session = server.new_session()?.save_cookies(true);
+        session.perform(Request::put("/counter"))?;
+        format!("session-id={}", session.cookie("session-id").unwrap())
+    };
+    assert_eq!(backend.len(), 2);
+
+    // the old session has expired at `threshold`, while the new one has not.
+    assert_eq!(backend.purge_expired(threshold).wait().ok(), Some(1));
+    assert_eq!(backend.len(), 1);
+
+    let response = server.perform(Request::get("/counter").header("cookie", old_cookie))?;
+    assert_eq!(response.body().to_utf8()?, "None");
+    let response = server.perform(Request::get("/counter").header("cookie", new_cookie))?;
+    assert_eq!(response.body().to_utf8()?, "Some(1)");
+
+    assert_eq!(backend.purge_expired(threshold).wait().ok(), Some(0));
+
+    Ok(())
+}
+
+#[test]
+fn collector_purges_periodically() -> tsukuyomi_server::Result<()> {
+    let backend = MemoryBackend::new().ttl(Duration::from_secs(0));
+    let mut server = tsukuyomi_server::test::server(counter_app(backend.clone())?)?;
+    for _ in 0..3 {
+        server.new_session()?.perform(Request::put("/counter"))?;
+    }
+    assert_eq!(backend.len(), 3);
+
+    let collector = gc::collector(backend.clone())
+        .interval(Duration::from_millis(10))
+        .jitter(Duration::from_millis(5));
+    let metrics = collector.metrics();
+    let handle = collector.handle();
+
+    let mut runtime = tokio::runtime::Runtime::new()?;
+    runtime.spawn(collector);
+    let deadline = Instant::now() + Duration::from_secs(5);
+    while metrics.runs() < 2 && Instant::now() < deadline {
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    assert!(metrics.runs() >= 2);
+    assert_eq!(metrics.purged(), 3);
+    assert_eq!(metrics.failures(), 0);
+    assert!(backend.is_empty());
+
+    // the runtime becomes idle after the collector is stopped.
+    handle.stop();
+    runtime.shutdown_on_idle().wait().unwrap();
+
+    Ok(())
+}
@@ -14,8 +14,10 @@
 
 use {
     futures::IntoFuture,
-    http::Response,
+    http::{header::HeaderValue, Response},
+    std::{fmt, sync::Arc},
     tsukuyomi::{error::Error, input::body::UpgradedIo, responder::Responder},
+    tsukuyomi_cors::CORS,
 };
 
 #[doc(no_inline)]
@@ -29,6 +31,7 @@ pub type WebSocketStream = tokio_tungstenite::WebSocketStream<UpgradedIo>;
 pub struct Ws<F> {
     on_upgrade: F,
     config: Option<WebSocketConfig>,
+    origin_policy: Option<OriginPolicy>,
 }
 
 impl<F, R> Ws<F>
@@ -42,6 +45,7 @@ where
         Self {
             on_upgrade,
             config: None,
+            origin_policy: None,
         }
     }
 
@@ -52,6 +56,111 @@ where
             ..self
         }
     }
+
+    /// Sets the policy for validating the value of `Origin` in the handshake request.
+    ///
+    /// The handshake requests whose origin is rejected by the policy are
+    /// responded with `403 Forbidden` without upgrading the connection.
+    pub fn verify_origin(self, policy: OriginPolicy) -> Self {
+        Self {
+            origin_policy: Some(policy),
+            ..self
+        }
+    }
+}
+
+/// The policy for validating the header field `Origin` in WebSocket handshake requests.
+///
+/// Since the browsers do not apply the same-origin policy to WebSocket connections,
+/// the server should check the origin of handshake requests by itself in order to
+/// prevent cross-site WebSocket hijacking.
+#[derive(Clone)]
+pub struct OriginPolicy {
+    kind: OriginPolicyKind,
+    require_origin: bool,
+}
+
+#[derive(Clone)]
+enum OriginPolicyKind {
+    AllowList(Arc<Vec<String>>),
+    Predicate(Arc<dyn Fn(&str) -> bool + Send + Sync + 'static>),
+    Cors(CORS),
+}
+
+impl fmt::Debug for OriginPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            OriginPolicyKind::AllowList(ref origins) => format!("AllowList({:?})", origins),
+            OriginPolicyKind::Predicate(..) => "Predicate(..)".into(),
+            OriginPolicyKind::Cors(..) => "CORS(..)".into(),
+        };
+        f.debug_struct("OriginPolicy")
+            .field("kind", &kind)
+            .field("require_origin", &self.require_origin)
+            .finish()
+    }
+}
+
+impl OriginPolicy {
+    fn new(kind: OriginPolicyKind) -> Self {
+        Self {
+            kind,
+            require_origin: false,
+        }
+    }
+
+    /// Creates an `OriginPolicy` that accepts only the specified origins.
+    ///
+    /// The origins are compared with the value of `Origin` case-insensitively,
+    /// and must be serialized in the form `<scheme>://<host>[:<port>]`.
+    pub fn allow_list<T>(origins: impl IntoIterator<Item = T>) -> Self
+    where
+        T: Into<String>,
+    {
+        Self::new(OriginPolicyKind::AllowList(Arc::new(
+            origins.into_iter().map(Into::into).collect(),
+        )))
+    }
+
+    /// Creates an `OriginPolicy` that accepts the origins for which the specified predicate returns `true`.
+    pub fn predicate<F>(f: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        Self::new(OriginPolicyKind::Predicate(Arc::new(f)))
+    }
+
+    /// Creates an `OriginPolicy` that reuses the allowed origins of the specified `CORS`.
+    ///
+    /// If no origin is explicitly allowed by the `CORS`, any valid origin is accepted.
+    pub fn cors(cors: &CORS) -> Self {
+        Self::new(OriginPolicyKind::Cors(cors.clone()))
+    }
+
+    /// Sets whether to reject the handshake requests without `Origin`.
+    ///
+    /// The default value is `false`, since the clients other than browsers
+    /// usually do not send the header field.
+    pub fn require_origin(self, enabled: bool) -> Self {
+        Self {
+            require_origin: enabled,
+            ..self
+        }
+    }
+
+    fn is_allowed(&self, origin: &HeaderValue) -> bool {
+        let origin_str = match origin.to_str() {
+            Ok(s) => s,
+            Err(..) => return false,
+        };
+        match self.kind {
+            OriginPolicyKind::AllowList(ref origins) => origins
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(origin_str)),
+            OriginPolicyKind::Predicate(ref f) => f(origin_str),
+            OriginPolicyKind::Cors(ref cors) => cors.validate_origin(origin).is_ok(),
+        }
+    }
 }
 
 impl<F, R> Responder for Ws<F>
@@ -71,17 +180,18 @@ where
 
 mod imp {
     use {
-        super::{WebSocketStream, Ws},
+        super::{OriginPolicy, WebSocketStream, Ws},
         futures::{Future, IntoFuture},
         http::{
             header::{
                 CONNECTION, //
+                ORIGIN,
                 SEC_WEBSOCKET_ACCEPT,
                 SEC_WEBSOCKET_KEY,
                 SEC_WEBSOCKET_VERSION,
                 UPGRADE,
             },
-            Request, Response, StatusCode,
+            Request, Response, StatusCode, Version,
         },
         sha1::{Digest, Sha1},
         tsukuyomi::{
@@ -110,11 +220,18 @@ mod imp {
         type Error = tsukuyomi::Error;
 
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-            let Ws { on_upgrade, config } =
-                self.0.take().expect("the future has already been polled");
+            let Ws {
+                on_upgrade,
+                config,
+                origin_policy,
+            } = self.0.take().expect("the future has already been polled");
 
             let accept_hash = handshake(input)?;
 
+            if let Some(ref policy) = origin_policy {
+                verify_origin(input, policy)?;
+            }
+
             let body = RequestBody::take_from(input.locals) //
                 .ok_or_else(|| {
                     tsukuyomi::error::internal_server_error(
@@ -147,6 +264,12 @@ mod imp {
 
     #[derive(Debug, failure::Fail)]
     enum HandshakeError {
+        #[fail(
+            display = "The WebSocket upgrade is not supported over {:?} (requires HTTP/1.1)",
+            version
+        )]
+        UnsupportedVersion { version: Version },
+
         #[fail(display = "The header is missing: `{}'", name)]
         MissingHeader { name: &'static str },
 
@@ -158,20 +281,38 @@ mod imp {
 
         #[fail(display = "The value of `Sec-WebSocket-Version` must be equal to '13'")]
         InvalidSecWebSocketVersion,
+
+        #[fail(display = "The origin of handshake request is missing")]
+        MissingOrigin,
+
+        #[fail(display = "The origin of handshake request is not allowed")]
+        DisallowedOrigin,
     }
 
     impl HttpError for HandshakeError {
         type Body = String;
 
         fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+            let status = match self {
+                HandshakeError::MissingOrigin | HandshakeError::DisallowedOrigin => {
+                    StatusCode::FORBIDDEN
+                }
+                _ => StatusCode::BAD_REQUEST,
+            };
             Response::builder()
-                .status(StatusCode::BAD_REQUEST)
+                .status(status)
                 .body(self.to_string())
                 .expect("should be a valid response")
         }
     }
 
     fn handshake(input: &mut Input<'_>) -> Result<String, HandshakeError> {
+        // The upgrade mechanism does not exist in HTTP/2 and HTTP/1.0.
+        match input.request.version() {
+            Version::HTTP_11 => {}
+            version => Err(HandshakeError::UnsupportedVersion { version })?,
+        }
+
         match input.request.headers().get(UPGRADE) {
             Some(h) if h.as_bytes().eq_ignore_ascii_case(b"websocket") => (),
             Some(..) => Err(HandshakeError::InvalidHeader { name: "Upgrade" })?,
@@ -216,4 +357,13 @@ mod imp {
 
         Ok(accept_hash)
     }
+
+    fn verify_origin(input: &mut Input<'_>, policy: &OriginPolicy) -> Result<(), HandshakeError> {
+        match input.request.headers().get(ORIGIN) {
+            Some(origin) if policy.is_allowed(origin) => Ok(()),
+            Some(..) => Err(HandshakeError::DisallowedOrigin),
+            None if policy.require_origin => Err(HandshakeError::MissingOrigin),
+            None => Ok(()),
+        }
+    }
 }
@@ -8,19 +8,22 @@ use {
         header::{
             CONNECTION, //
             HOST,
+            ORIGIN,
             SEC_WEBSOCKET_ACCEPT,
             SEC_WEBSOCKET_KEY,
             SEC_WEBSOCKET_VERSION,
             UPGRADE,
         },
-        Request,
+        request::Builder,
+        Request, Version,
     },
     tsukuyomi::{
         config::prelude::*, //
         App,
     },
+    tsukuyomi_cors::CORS,
     tsukuyomi_server::test::ResponseExt,
-    tsukuyomi_tungstenite::Ws,
+    tsukuyomi_tungstenite::{OriginPolicy, Ws},
 };
 
 #[test]
@@ -55,4 +58,107 @@ fn test_handshake() -> tsukuyomi_server::Result<()> {
     Ok(())
 }
 
+#[test]
+fn test_handshake_over_http2() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/ws") //
+            .to(endpoint::get().reply(Ws::new(|_| Ok(())))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(handshake_request(None).version(Version::HTTP_2))?;
+    assert_eq!(response.status(), 400);
+    assert!(!response.headers().contains_key(SEC_WEBSOCKET_ACCEPT));
+
+    Ok(())
+}
+
+fn handshake_request(origin: Option<&str>) -> Builder {
+    let mut request = Request::get("/ws");
+    request
+        .header(HOST, "localhost:4000")
+        .header(CONNECTION, "upgrade")
+        .header(UPGRADE, "websocket")
+        .header(SEC_WEBSOCKET_VERSION, "13")
+        .header(SEC_WEBSOCKET_KEY, "dGhlIHNhbXBsZSBub25jZQ==");
+    if let Some(origin) = origin {
+        request.header(ORIGIN, origin);
+    }
+    request
+}
+
+#[test]
+fn test_verify_origin_allow_list() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/ws") //
+            .to(endpoint::get().reply(
+                Ws::new(|_| Ok(())) //
+                    .verify_origin(OriginPolicy::allow_list(vec!["http://example.com"])),
+            )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(handshake_request(Some("http://example.com")))?;
+    assert_eq!(response.status(), 101);
+
+    let response = server.perform(handshake_request(Some("http://evil.example.com")))?;
+    assert_eq!(response.status(), 403);
+
+    let response = server.perform(handshake_request(None))?;
+    assert_eq!(response.status(), 101);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_origin_require_origin() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/ws") //
+            .to(endpoint::get().reply(
+                Ws::new(|_| Ok(())) //
+                    .verify_origin(
+                        OriginPolicy::predicate(|origin| origin.ends_with(".example.com"))
+                            .require_origin(true),
+                    ),
+            )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(handshake_request(Some("https://www.example.com")))?;
+    assert_eq!(response.status(), 101);
+
+    let response = server.perform(handshake_request(Some("https://example.org")))?;
+    assert_eq!(response.status(), 403);
+
+    let response = server.perform(handshake_request(None))?;
+    assert_eq!(response.status(), 403);
+
+    Ok(())
+}
+
+#[test]
+fn test_verify_origin_with_cors() -> tsukuyomi_server::Result<()> {
+    let cors = CORS::builder().allow_origin("https://example.com")?.build();
+
+    let app = App::create(
+        path!("/ws") //
+            .to(endpoint::get().reply(
+                Ws::new(|_| Ok(())) //
+                    .verify_origin(OriginPolicy::cors(&cors)),
+            )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(handshake_request(Some("https://example.com")))?;
+    assert_eq!(response.status(), 101);
+
+    let response = server.perform(handshake_request(Some("https://example.org")))?;
+    assert_eq!(response.status(), 403);
+
+    let response = server.perform(handshake_request(Some("not-a-valid-origin")))?;
+    assert_eq!(response.status(), 403);
+
+    Ok(())
+}
+
 // TODO: add check whether the task to handle upgraded connection is spawned
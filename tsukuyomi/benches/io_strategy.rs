@@ -0,0 +1,164 @@
+//! Compares the strategies of file system operations, by serving the static files
+//! to 1000 concurrent requests.
+//!
+//! ```shell
+//! $ cargo bench -p tsukuyomi --bench io_strategy
+//! ```
+
+use {
+    futures01::{future, sync::oneshot, Async, Future},
+    http::Request,
+    hyper::body::Payload,
+    std::{
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    },
+    tsukuyomi::{
+        config::prelude::*,
+        fs::{IoStrategy, OpenConfig, Staticfiles},
+        App,
+    },
+    tsukuyomi_service::Service,
+};
+
+const CONCURRENCY: usize = 1000;
+const NUM_FILES: usize = 100;
+const FILE_SIZE: usize = 64 * 1024;
+const ROUNDS: u32 = 5;
+
+fn main() -> tsukuyomi_server::Result<()> {
+    let root = setup()?;
+
+    let blocking = app(&root, IoStrategy::Blocking)?;
+    let background = app(&root, IoStrategy::Background)?;
+
+    report(
+        "Blocking (thread pool)",
+        run(&mut tokio::runtime::Runtime::new()?, &blocking),
+    );
+    report(
+        "Background (thread pool)",
+        run(&mut tokio::runtime::Runtime::new()?, &background),
+    );
+    report(
+        "Background (current thread)",
+        run(
+            &mut tokio::runtime::current_thread::Runtime::new()?,
+            &background,
+        ),
+    );
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+fn setup() -> std::io::Result<PathBuf> {
+    let root = std::env::temp_dir().join(format!("tsukuyomi-bench-fs-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    for i in 0..NUM_FILES {
+        std::fs::write(root.join(format!("{}.bin", i)), vec![i as u8; FILE_SIZE])?;
+    }
+    Ok(root)
+}
+
+fn app(root: &Path, io: IoStrategy) -> tsukuyomi::app::Result<App> {
+    App::create(
+        mount("/files").with(Staticfiles::new(root).open_config(OpenConfig {
+            io,
+            ..Default::default()
+        })),
+    )
+}
+
+/// The runtimes on which the requests are executed.
+trait BlockOn {
+    fn block_on<F>(&mut self, future: F) -> Result<F::Item, F::Error>
+    where
+        F: Future + Send + 'static,
+        F::Item: Send + 'static,
+        F::Error: Send + 'static;
+}
+
+impl BlockOn for tokio::runtime::Runtime {
+    fn block_on<F>(&mut self, future: F) -> Result<F::Item, F::Error>
+    where
+        F: Future + Send + 'static,
+        F::Item: Send + 'static,
+        F::Error: Send + 'static,
+    {
+        tokio::runtime::Runtime::block_on(self, future)
+    }
+}
+
+impl BlockOn for tokio::runtime::current_thread::Runtime {
+    fn block_on<F>(&mut self, future: F) -> Result<F::Item, F::Error>
+    where
+        F: Future + Send + 'static,
+        F::Item: Send + 'static,
+        F::Error: Send + 'static,
+    {
+        tokio::runtime::current_thread::Runtime::block_on(self, future)
+    }
+}
+
+/// Performs `CONCURRENCY` requests at once for `ROUNDS` times, and returns the
+/// average duration of a round.
+fn run(runtime: &mut impl BlockOn, app: &App) -> Duration {
+    let mut total = Duration::from_secs(0);
+    for _ in 0..ROUNDS {
+        let service = app.clone().into_service();
+        let start = Instant::now();
+        let received = runtime
+            .block_on(future::lazy(move || {
+                // Each request is spawned as a separate task, as the server does.
+                let responses = (0..CONCURRENCY).map(move |i| {
+                    let (tx, rx) = oneshot::channel();
+                    tokio::spawn(request(service.clone(), i).then(|result| {
+                        let _ = tx.send(result);
+                        Ok(())
+                    }));
+                    rx.map_err(|_| "the task has been canceled".to_string())
+                        .and_then(|result| result)
+                });
+                future::join_all(responses)
+            }))
+            .expect("failed to perform the requests");
+        total += start.elapsed();
+
+        assert!(received.iter().all(|&len| len == FILE_SIZE));
+    }
+    total / ROUNDS
+}
+
+fn request<S>(mut service: S, i: usize) -> impl Future<Item = usize, Error = String>
+where
+    S: Service<Request<hyper::Body>, Response = http::Response<tsukuyomi::output::ResponseBody>>,
+    S::Error: std::fmt::Debug,
+{
+    let request = Request::get(format!("/files/{}.bin", i % NUM_FILES))
+        .body(hyper::Body::empty())
+        .unwrap();
+    service
+        .call(request)
+        .map_err(|err| format!("{:?}", err))
+        .and_then(|response| {
+            let mut body = response.into_body();
+            let mut len = 0;
+            future::poll_fn(move || loop {
+                match body.poll_data().map_err(|err| err.to_string())? {
+                    Async::Ready(Some(chunk)) => len += chunk.len(),
+                    Async::Ready(None) => return Ok(Async::Ready(len)),
+                    Async::NotReady => return Ok(Async::NotReady),
+                }
+            })
+        })
+}
+
+fn report(name: &str, elapsed: Duration) {
+    let per_sec =
+        CONCURRENCY as f64 / (elapsed.as_secs() as f64 + f64::from(elapsed.subsec_nanos()) / 1e9);
+    println!(
+        "{:<30} {:>10.2?} / {} requests ({:.0} req/s)",
+        name, elapsed, CONCURRENCY, per_sec
+    );
+}
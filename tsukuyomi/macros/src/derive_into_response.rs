@@ -30,7 +30,7 @@ enum InputKind {
     Struct(Target),
     Enum(Vec<Variant>),
     ExplicitWithFnPath(syn::Path, Span),
-    UsePreset(syn::Path),
+    UsePreset(syn::Path, bool),
 }
 
 #[derive(Debug)]
@@ -93,6 +93,7 @@ mod parsing {
 
             let mut explicit_path: Option<ExplicitKind> = None;
             let mut bounds: Option<Vec<syn::WherePredicate>> = None;
+            let mut pretty: Option<Span> = None;
 
             for attr in &input.attrs {
                 let m = attr.parse_meta()?;
@@ -111,6 +112,17 @@ mod parsing {
                 };
 
                 for nm_item in meta_list.nested {
+                    if let syn::NestedMeta::Meta(syn::Meta::Word(ref ident)) = nm_item {
+                        match ident.to_string().as_ref() {
+                            "pretty" => pretty = Some(ident.span()),
+                            s => {
+                                return Err(parse_error_at(
+                                    ident,
+                                    format!("unsupported flag: '{}'", s),
+                                ))
+                            }
+                        }
+                    }
                     if let syn::NestedMeta::Meta(syn::Meta::NameValue(ref pair)) = nm_item {
                         match pair.ident.to_string().as_ref() {
                             "with" => {
@@ -148,9 +160,19 @@ mod parsing {
                 }
             }
 
+            match (pretty, &explicit_path) {
+                (Some(..), Some(ExplicitKind::Preset(..))) | (None, _) => {}
+                (Some(span), _) => {
+                    return Err(parse::Error::new(
+                        span,
+                        "the flag 'pretty' requires the parameter 'preset'",
+                    ))
+                }
+            }
+
             let kind = match explicit_path {
                 Some(ExplicitKind::Fn(path, span)) => InputKind::ExplicitWithFnPath(path, span),
-                Some(ExplicitKind::Preset(path)) => InputKind::UsePreset(path),
+                Some(ExplicitKind::Preset(path)) => InputKind::UsePreset(path, pretty.is_some()),
                 None => match input.data {
                     syn::Data::Struct(data) => {
                         let field = match data.fields {
@@ -248,6 +270,7 @@ impl<'a> Context<'a> {
         let Self_ = self.ident;
         let IntoResponse: syn::Path = syn::parse_quote!(tsukuyomi::output::internal::IntoResponse);
         let Request: syn::Path = syn::parse_quote!(tsukuyomi::output::internal::Request);
+        let Input: syn::Path = syn::parse_quote!(tsukuyomi::output::internal::Input);
         let Response: syn::Path = syn::parse_quote!(tsukuyomi::output::internal::Response);
         let Preset: syn::Path = syn::parse_quote!(tsukuyomi::output::internal::Preset);
 
@@ -267,6 +290,8 @@ impl<'a> Context<'a> {
 
         // The path of types drawn at the position of the associated type.
         let (Body, Error, body): (syn::Type, syn::Type, TokenStream);
+        // The body of `respond_to`, if it needs to be forwarded.
+        let mut respond_to: Option<TokenStream> = None;
         match &self.kind {
             InputKind::ExplicitWithFnPath(path, span) => {
                 Body = syn::parse_quote!(tsukuyomi::output::internal::ResponseBody);
@@ -281,7 +306,7 @@ impl<'a> Context<'a> {
                 )
             }
 
-            InputKind::UsePreset(path) => {
+            InputKind::UsePreset(path, pretty) => {
                 where_clause
                     .get_or_insert_with(|| syn::WhereClause {
                         where_token: Default::default(),
@@ -292,7 +317,16 @@ impl<'a> Context<'a> {
 
                 Body = syn::parse_quote!(<#path as #Preset<Self>>::Body);
                 Error = syn::parse_quote!(<#path as #Preset<Self>>::Error);
-                body = quote!(< #path as #Preset<Self> >::into_response(self, request));
+                body = if *pretty {
+                    quote!(< #path as #Preset<Self> >::into_response_pretty(self, request))
+                } else {
+                    quote!(< #path as #Preset<Self> >::into_response(self, request))
+                };
+                respond_to = Some(if *pretty {
+                    quote!(< #path as #Preset<Self> >::respond_to_pretty(self, input))
+                } else {
+                    quote!(< #path as #Preset<Self> >::respond_to(self, input))
+                });
             }
 
             InputKind::Struct(target) => match target {
@@ -316,6 +350,9 @@ impl<'a> Context<'a> {
                     body = quote!(match self {
                         #Self_(__arg_0) => #IntoResponse::into_response(__arg_0, request),
                     });
+                    respond_to = Some(quote!(match self {
+                        #Self_(__arg_0) => #IntoResponse::respond_to(__arg_0, input),
+                    }));
                 }
 
                 Target::NamedField(Some(field)) => {
@@ -333,68 +370,85 @@ impl<'a> Context<'a> {
                     body = quote!(match self {
                         #Self_ { #field_ident: __arg_0, } => #IntoResponse::into_response(__arg_0, request),
                     });
+                    respond_to = Some(quote!(match self {
+                        #Self_ { #field_ident: __arg_0, } => #IntoResponse::respond_to(__arg_0, input),
+                    }));
                 }
             },
 
             InputKind::Enum(variants) => {
-                let variants = variants.into_iter().map(|variant| {
-                    let Variant = &variant.ident;
-                    match &variant.target {
-                        Target::Unit => quote!(
-                            #Self_ :: #Variant => #IntoResponse::into_response((), request)
-                                .map(|response| response.map(Into::into))
-                                .map_err(Into::into)
-                        ),
-
-                        Target::UnnamedField(None) => {
-                            quote!(#Self_ :: #Variant () => #IntoResponse::into_response((), request)
-                                .map(|response| response.map(Into::into))
-                                .map_err(Into::into))
-                        }
-                        Target::UnnamedField(Some(field)) => {
-                            let bounded_ty = &field.ty;
-                            where_clause
-                                .get_or_insert_with(|| syn::WhereClause {
-                                    where_token: Default::default(),
-                                    predicates: Default::default(),
-                                })
-                                .predicates
-                                .push(syn::parse_quote!(#bounded_ty: #IntoResponse));
-                            quote!(#Self_ :: #Variant (__arg_0) => #IntoResponse::into_response(__arg_0, request)
-                                .map(|response| response.map(Into::into))
-                                .map_err(Into::into))
+                // the pattern of each variant and the value passed to `IntoResponse`.
+                let arms: Vec<(TokenStream, TokenStream)> = variants
+                    .iter()
+                    .map(|variant| {
+                        let Variant = &variant.ident;
+                        match &variant.target {
+                            Target::Unit => (quote!(#Self_ :: #Variant), quote!(())),
+                            Target::UnnamedField(None) => {
+                                (quote!(#Self_ :: #Variant ()), quote!(()))
+                            }
+                            Target::UnnamedField(Some(field)) => {
+                                let bounded_ty = &field.ty;
+                                where_clause
+                                    .get_or_insert_with(|| syn::WhereClause {
+                                        where_token: Default::default(),
+                                        predicates: Default::default(),
+                                    })
+                                    .predicates
+                                    .push(syn::parse_quote!(#bounded_ty: #IntoResponse));
+                                (quote!(#Self_ :: #Variant (__arg_0)), quote!(__arg_0))
+                            }
+                            Target::NamedField(None) => (quote!(#Self_ :: #Variant {}), quote!(())),
+                            Target::NamedField(Some(field)) => {
+                                let bounded_ty = &field.ty;
+                                where_clause
+                                    .get_or_insert_with(|| syn::WhereClause {
+                                        where_token: Default::default(),
+                                        predicates: Default::default(),
+                                    })
+                                    .predicates
+                                    .push(syn::parse_quote!(#bounded_ty: #IntoResponse));
+                                let field = &field.ident;
+                                (
+                                    quote!(#Self_ :: #Variant { #field: __arg_0, }),
+                                    quote!(__arg_0),
+                                )
+                            }
                         }
+                    })
+                    .collect();
 
-                        Target::NamedField(None) => {
-                            quote!(#Self_ :: #Variant {} => #IntoResponse::into_response((), request)
-                                .map(|response| response.map(Into::into))
-                                .map_err(Into::into))
-                        }
-                        Target::NamedField(Some(field)) => {
-                            let bounded_ty = &field.ty;
-                            where_clause
-                                .get_or_insert_with(|| syn::WhereClause {
-                                    where_token: Default::default(),
-                                    predicates: Default::default(),
-                                })
-                                .predicates
-                                .push(syn::parse_quote!(#bounded_ty: #IntoResponse));
-                            let field = &field.ident;
-                            quote!(#Self_ :: #Variant { #field: __arg_0, } => #IntoResponse::into_response(__arg_0, request)
-                                .map(|response| response.map(Into::into))
-                                .map_err(Into::into))
-                        }
-                    }
+                let into_response_arms = arms.iter().map(|(pat, arg)| {
+                    quote!(#pat => #IntoResponse::into_response(#arg, request)
+                        .map(|response| response.map(Into::into))
+                        .map_err(Into::into))
+                });
+                let respond_to_arms = arms.iter().map(|(pat, arg)| {
+                    quote!(#pat => #IntoResponse::respond_to(#arg, input)
+                        .map(|response| response.map(Into::into))
+                        .map_err(Into::into))
                 });
 
                 Body = syn::parse_quote!(tsukuyomi::output::internal::ResponseBody);
                 Error = syn::parse_quote!(tsukuyomi::output::internal::Error);
                 body = quote!(match self {
-                    #( #variants, )*
+                    #( #into_response_arms, )*
                 });
+                respond_to = Some(quote!(match self {
+                    #( #respond_to_arms, )*
+                }));
             }
         };
 
+        let respond_to = respond_to.map(|body| {
+            quote!(
+                #[inline]
+                fn respond_to(self, input: &mut #Input<'_>) -> Result<#Response<Self::Body>, Self::Error> {
+                    #body
+                }
+            )
+        });
+
         // appends the trailing comma if not exist.
         if let Some(where_clause) = &mut where_clause {
             if !where_clause.predicates.empty_or_trailing() {
@@ -413,6 +467,8 @@ impl<'a> Context<'a> {
                 fn into_response(self, request: &#Request<()>) -> Result<#Response<Self::Body>, Self::Error> {
                     #body
                 }
+
+                #respond_to
             }
         )
     }
@@ -501,6 +557,20 @@ mod tests {
                             tsukuyomi::output::internal::IntoResponse::into_response(__arg_0, request),
                     }
                 }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    match self {
+                        A(__arg_0) =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to(__arg_0, input),
+                    }
+                }
             }
         },
     }
@@ -557,6 +627,20 @@ mod tests {
                             tsukuyomi::output::internal::IntoResponse::into_response(__arg_0, request),
                     }
                 }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    match self {
+                        A { b: __arg_0, } =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to(__arg_0, input),
+                    }
+                }
             }
         },
     }
@@ -636,6 +720,38 @@ mod tests {
                                 .map_err(Into::into),
                     }
                 }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    match self {
+                        Either::A(__arg_0) =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to(__arg_0, input)
+                                .map(|response| response.map(Into::into))
+                                .map_err(Into::into),
+                        Either::B { b: __arg_0, } =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to(__arg_0, input)
+                                .map(|response| response.map(Into::into))
+                                .map_err(Into::into),
+                        Either::C =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to((), input)
+                                .map(|response| response.map(Into::into))
+                                .map_err(Into::into),
+                        Either::D() =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to((), input)
+                                .map(|response| response.map(Into::into))
+                                .map_err(Into::into),
+                        Either::E {} =>
+                            tsukuyomi::output::internal::IntoResponse::respond_to((), input)
+                                .map(|response| response.map(Into::into))
+                                .map_err(Into::into),
+                    }
+                }
             }
         },
     }
@@ -741,6 +857,59 @@ mod tests {
                 > {
                     <my::Preset as tsukuyomi::output::internal::Preset<Self> >::into_response(self, request)
                 }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    <my::Preset as tsukuyomi::output::internal::Preset<Self> >::respond_to(self, input)
+                }
+            }
+        },
+    }
+
+    t! {
+        name: explicit_preset_pretty,
+        source: {
+            #[response(preset = "my::Preset", pretty)]
+            struct A {
+                x: X,
+                y: Y,
+            }
+        },
+        expected: {
+            impl tsukuyomi::output::internal::IntoResponse for A
+            where
+                my::Preset: tsukuyomi::output::internal::Preset<Self>,
+            {
+                type Body = <my::Preset as tsukuyomi::output::internal::Preset<Self> >::Body;
+                type Error = <my::Preset as tsukuyomi::output::internal::Preset<Self> >::Error;
+
+                #[inline]
+                fn into_response(
+                    self,
+                    request: &tsukuyomi::output::internal::Request<()>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    <my::Preset as tsukuyomi::output::internal::Preset<Self> >::into_response_pretty(self, request)
+                }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    <my::Preset as tsukuyomi::output::internal::Preset<Self> >::respond_to_pretty(self, input)
+                }
             }
         },
     }
@@ -778,6 +947,59 @@ mod tests {
                 > {
                     <my::Preset as tsukuyomi::output::internal::Preset<Self> >::into_response(self, request)
                 }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    <my::Preset as tsukuyomi::output::internal::Preset<Self> >::respond_to(self, input)
+                }
+            }
+        },
+    }
+
+    t! {
+        name: explicit_preset_with_generic_arguments,
+        source: {
+            #[response(preset = "my::Envelope<my::Json, my::Meta>")]
+            struct A {
+                x: X,
+                y: Y,
+            }
+        },
+        expected: {
+            impl tsukuyomi::output::internal::IntoResponse for A
+            where
+                my::Envelope<my::Json, my::Meta>: tsukuyomi::output::internal::Preset<Self>,
+            {
+                type Body = <my::Envelope<my::Json, my::Meta> as tsukuyomi::output::internal::Preset<Self> >::Body;
+                type Error = <my::Envelope<my::Json, my::Meta> as tsukuyomi::output::internal::Preset<Self> >::Error;
+
+                #[inline]
+                fn into_response(
+                    self,
+                    request: &tsukuyomi::output::internal::Request<()>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    <my::Envelope<my::Json, my::Meta> as tsukuyomi::output::internal::Preset<Self> >::into_response(self, request)
+                }
+
+                #[inline]
+                fn respond_to(
+                    self,
+                    input: &mut tsukuyomi::output::internal::Input<'_>
+                ) -> Result<
+                    tsukuyomi::output::internal::Response<Self::Body>,
+                    Self::Error
+                > {
+                    <my::Envelope<my::Json, my::Meta> as tsukuyomi::output::internal::Preset<Self> >::respond_to(self, input)
+                }
             }
         },
     }
@@ -848,4 +1070,13 @@ mod tests {
         },
         error: "the parameter 'with' or 'preset' has already been provided",
     }
+
+    t! {
+        name: failcase_pretty_without_preset,
+        source: {
+            #[response(pretty)]
+            struct A(B);
+        },
+        error: "the flag 'pretty' requires the parameter 'preset'",
+    }
 }
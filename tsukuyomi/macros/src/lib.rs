@@ -52,6 +52,22 @@ use proc_macro::TokenStream;
 /// # fn main() {}
 /// ```
 ///
+/// The flag `#[response(pretty)]` makes the derived implementation call
+/// `Preset::into_response_pretty` instead of `Preset::into_response`.
+/// The preset `Json` uses it to pretty-print the output:
+///
+/// ```
+/// # use tsukuyomi::IntoResponse;
+/// # use serde::Serialize;
+/// #[derive(Debug, Serialize, IntoResponse)]
+/// #[response(preset = "tsukuyomi::output::preset::Json", pretty)]
+/// struct Post {
+///     title: String,
+///     text: String,
+/// }
+/// # fn main() {}
+/// ```
+///
 /// # Notes
 /// 1. When `preset = ".."` is omitted for struct, a field in the specified
 ///    struct is chosen and the the implementation of `IntoResponse` for its
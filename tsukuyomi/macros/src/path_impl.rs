@@ -36,6 +36,7 @@ pub fn path_impl(input: TokenStream) -> parse::Result<TokenStream> {
 #[derive(Debug, Copy, Clone, PartialEq)]
 enum Param<'a> {
     Single(&'a str),
+    Optional(&'a str),
     CatchAll(&'a str),
 }
 
@@ -59,7 +60,53 @@ fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
     let mut names = HashSet::new();
 
     while let Some(segment) = iter.next() {
+        let is_optional = params.last().map_or(false, |param| match param {
+            Param::Optional(..) => true,
+            _ => false,
+        });
+        let follows_catch_all = params.last().map_or(false, |param| match param {
+            Param::CatchAll(..) => true,
+            _ => false,
+        });
+        if segment.is_empty() {
+            // the empty segment is allowed only at the end, as the trailing slash.
+            if iter.peek().is_some() {
+                return spanned_err(span, "a segment must not be empty");
+            }
+            if is_optional {
+                return spanned_err(
+                    span,
+                    "the optional parameters cannot be followed by a trailing slash",
+                );
+            }
+            break;
+        }
         match segment.split_at(1) {
+            (":", _) | ("*", _) if follows_catch_all => {
+                return spanned_err(
+                    span,
+                    "the catch-all parameter can be followed only by static segments",
+                );
+            }
+            (":", name) if name.ends_with('?') => {
+                let name = &name[..name.len() - 1];
+                if !names.insert(name) {
+                    return spanned_err(
+                        span,
+                        format!("detected duplicate parameter name: '{}'", name),
+                    );
+                }
+                params.push(Param::Optional(name));
+            }
+            _ if is_optional => {
+                return spanned_err(
+                    span,
+                    format!(
+                        "the segment '{}' follows an optional parameter, but is not optional",
+                        segment
+                    ),
+                );
+            }
             (":", name) => {
                 if !names.insert(name) {
                     return spanned_err(
@@ -69,6 +116,9 @@ fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
                 }
                 params.push(Param::Single(name));
             }
+            ("*", name) if name.ends_with('?') => {
+                return spanned_err(span, "the catch-all parameter cannot be optional");
+            }
             ("*", name) => {
                 if !names.insert(name) {
                     return spanned_err(
@@ -77,20 +127,11 @@ fn parse_literal(path: &str, span: Span) -> parse::Result<Vec<Param<'_>>> {
                     );
                 }
                 params.push(Param::CatchAll(name));
-                break;
-            }
-            _ => {
-                if segment.is_empty() && iter.peek().is_some() {
-                    return spanned_err(span, "a segment must not be empty");
-                }
             }
+            _ => {}
         }
     }
 
-    if iter.next().is_some() {
-        return spanned_err(span, "the catch-all parameter must be at the end of path");
-    }
-
     Ok(params)
 }
 
@@ -131,6 +172,18 @@ impl<'a> ToTokens for PathImplOutput<'a> {
             .collect();
         let type_idents = &type_idents[..];
 
+        // the optional parameters are extracted as `Option<T>`.
+        let output_types: Vec<_> = self
+            .params
+            .iter()
+            .zip(type_idents)
+            .map(|(param, ty)| match param {
+                Param::Optional(..) => quote!(Option<#ty>),
+                _ => quote!(#ty),
+            })
+            .collect();
+        let output_types = &output_types[..];
+
         let where_clause = {
             let bounds = type_idents
                 .iter()
@@ -141,6 +194,18 @@ impl<'a> ToTokens for PathImplOutput<'a> {
 
         let extract = self.params.iter().zip(type_idents).map(|(param, ty)| {
             let extract_raw = match param {
+                Param::Optional(name) => {
+                    return quote!(
+                        let #ty = match params.name(#name) {
+                            Some(raw) => Some(
+                                <#ty as #FromPercentEncoded>::from_percent_encoded(
+                                    unsafe { #PercentEncoded::new_unchecked(raw) }
+                                ).map_err(Into::into)?
+                            ),
+                            None => None,
+                        };
+                    );
+                }
                 Param::Single(name) => quote!(params.name(#name).expect("missing parameter")),
                 Param::CatchAll(..) => {
                     quote!(params.catch_all().expect("missing catch-all parameter"))
@@ -154,18 +219,18 @@ impl<'a> ToTokens for PathImplOutput<'a> {
         });
 
         tokens.append_all(quote! {
-            fn call<#(#type_idents),*>() -> #Path<impl #PathExtractor<Output = (#(#type_idents,)*)>>
+            fn call<#(#type_idents),*>() -> #Path<impl #PathExtractor<Output = (#(#output_types,)*)>>
             #where_clause
             {
                 #[allow(missing_debug_implementations)]
                 struct __Extractor<#(#type_idents),*> {
-                    _marker: std::marker::PhantomData<fn() -> (#(#type_idents,)*)>,
+                    _marker: std::marker::PhantomData<fn() -> (#(#output_types,)*)>,
                 }
 
                 impl<#(#type_idents),*> #PathExtractor for __Extractor<#(#type_idents),*>
                 #where_clause
                 {
-                    type Output = (#(#type_idents,)*);
+                    type Output = (#(#output_types,)*);
 
                     #[allow(nonstandard_style)]
                     fn extract(params: Option<&#Params<'_>>)
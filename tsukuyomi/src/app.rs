@@ -1,37 +1,85 @@
 //! Components for constructing HTTP applications.
 
+mod analysis;
 pub mod config;
+mod embed;
+mod host;
+mod info;
+mod method_override;
+mod mount;
+mod normalize;
+mod observe;
+mod preflight;
 mod recognizer;
+mod rewrite;
+mod routes;
 mod scope;
 mod service;
+mod state;
 
 #[cfg(test)]
 mod tests;
 
-pub(crate) use self::recognizer::Captures;
 pub use self::{
+    analysis::{Reason, UnreachableRoute},
     config::{Error, Result},
+    embed::{HyperService, MakeHyperService},
+    info::{RouteInfo, RouteParam},
+    preflight::{Authorize, Layer, Outcome, Preflight},
     service::AppService,
 };
+pub(crate) use self::{
+    host::Subdomain,
+    method_override::OriginalMethod,
+    mount::Mounted,
+    preflight::{PreflightTarget, Recorded},
+    recognizer::Captures,
+    rewrite::RawPath,
+    routes::RouteNames,
+    state::{RouteMetadata, ScopeConfigs, StateMap},
+};
 
 use {
     self::{
         config::Concurrency,
+        host::HostPattern,
+        observe::ErrorObservers,
+        preflight::Evaluation,
         recognizer::{RecognizeError, Recognizer},
         scope::{Scope, ScopeId, Scopes},
     },
-    crate::{input::body::RequestBody, uri::Uri, util::Never},
-    http::Request,
-    std::{fmt, sync::Arc},
+    crate::{
+        config::{
+            path::Location, DisallowedMethod, MethodOverride, MountPoint, PathNormalization,
+            PreflightLimits, TrailingSlash,
+        },
+        handler::{AllowedMethods, Metadata},
+        input::body::RequestBody,
+        uri::Uri,
+        util::Never,
+    },
+    http::{Method, Request},
+    std::{borrow::Cow, fmt, sync::Arc},
     tsukuyomi_service::{MakeService, Service},
 };
 
 /// The main type representing an HTTP application.
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct AppBase<C: Concurrency = self::config::ThreadSafe> {
     inner: Arc<AppInner<C>>,
 }
 
+impl<C> Clone for AppBase<C>
+where
+    C: Concurrency,
+{
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
 impl<C> AppBase<C>
 where
     C: Concurrency,
@@ -46,6 +94,85 @@ where
             modify_service,
         }
     }
+
+    /// Converts itself into a `Service`, for embedding the application into the other
+    /// servers or wrapping it with Tower middlewares.
+    ///
+    /// The returned value implements `tower_service::Service<http::Request<hyper::Body>>`,
+    /// and can be cloned in order to serve multiple connections.
+    pub fn into_service(self) -> AppService<C> {
+        AppService { inner: self.inner }
+    }
+
+    /// Converts itself into a `MakeService` of hyper, for serving the application by
+    /// `hyper::Server` without `tsukuyomi-server`.
+    ///
+    /// The protocol upgrades (e.g. WebSocket) are available as long as the connection
+    /// supports them, which is the case with `hyper::Server`.  When serving the
+    /// connections manually by `hyper::server::conn::Http::serve_connection`, the
+    /// connection must be configured by `with_upgrades`, or the upgraded I/O is never
+    /// obtained by the handlers.
+    ///
+    /// The blocking sections used by the handlers (e.g. `fs::IoStrategy::Blocking`)
+    /// require the thread pool of Tokio, such as the default runtime used by
+    /// `hyper::rt::run`.  On the other executors (e.g. the single-threaded runtime),
+    /// these handlers fail with `500 Internal Server Error`, unless they are configured
+    /// to use `fs::IoStrategy::Background`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// # use tsukuyomi::{config::prelude::*, App};
+    /// # use tsukuyomi::vendor::futures::Future;
+    /// # fn main() -> tsukuyomi::app::Result<()> {
+    /// let app = App::create(path!("/").to(endpoint::reply("Hello")))?;
+    ///
+    /// let server = hyper::Server::bind(&([127, 0, 0, 1], 4000).into())
+    ///     .serve(app.into_make_service())
+    ///     .map_err(|e| eprintln!("server error: {}", e));
+    /// hyper::rt::run(server);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn into_make_service(self) -> MakeHyperService<C> {
+        MakeHyperService {
+            service: self.into_service(),
+        }
+    }
+
+    /// Returns the registrations detected as unreachable when building the application.
+    ///
+    /// The detected registrations are also logged as warnings.  Use `App::create_strict`
+    /// in order to reject them as an error.
+    pub fn unreachable_routes(&self) -> &[UnreachableRoute] {
+        &self.inner.unreachable_routes
+    }
+
+    /// Returns the information of the registered routes, followed by the fallbacks.
+    ///
+    /// The routes are listed in the order of registration.  The routes shadowed by
+    /// the preflight endpoint are not included.
+    pub fn routes(&self) -> Vec<RouteInfo> {
+        self::info::collect_routes(&self.inner)
+    }
+
+    /// Evaluates the specified request without executing the endpoint.
+    ///
+    /// The request is routed and then passed through the modifiers applied to the
+    /// matched route, and the returned future resolves to whether the request would
+    /// reach the endpoint.  The request body is not available during the evaluation.
+    ///
+    /// Only the routes marked by `Route::preflight` are evaluated, and the handlers of
+    /// the other routes are never called; the requests to them are resolved to
+    /// `Outcome::NotEvaluated`.  The requests that fall back to the default handlers
+    /// are regarded as not found.
+    pub fn authorize(&self, request: Request<()>) -> Authorize<C> {
+        Authorize {
+            inner: self.inner.clone(),
+            request,
+            evaluation: Evaluation::new(),
+        }
+    }
 }
 
 impl<C, Ctx, Bd> MakeService<Ctx, Request<Bd>> for AppBase<C>
@@ -101,15 +228,131 @@ pub type LocalApp = AppBase<self::config::CurrentThread>;
 
 #[derive(Debug)]
 struct AppInner<C: Concurrency> {
-    recognizer: Recognizer<Arc<Endpoint<C>>>,
+    recognizer: Recognizer<Vec<Arc<Endpoint<C>>>>,
     scopes: Scopes<ScopeData<C>>,
+    preflight_endpoint: Option<(Uri, PreflightLimits)>,
+    trailing_slash: TrailingSlash,
+    method_override: Option<MethodOverride>,
+    path_normalization: Option<PathNormalization>,
+    mount_point: Option<MountPoint>,
+    error_observers: ErrorObservers,
+    route_names: RouteNames,
+    unreachable_routes: Vec<UnreachableRoute>,
 }
 
 impl<C: Concurrency> AppInner<C> {
+    /// Returns whether the request is handled by the preflight endpoint, which
+    /// accepts only `POST`.
+    fn is_preflight_endpoint(&self, method: &Method, path: &str) -> bool {
+        match self.preflight_endpoint {
+            Some((ref uri, _)) => *method == Method::POST && uri.as_str() == path,
+            None => false,
+        }
+    }
+
+    /// Returns whether all requests to the endpoint are handled by the preflight
+    /// endpoint instead.
+    fn is_shadowed_by_preflight_endpoint(&self, endpoint: &Endpoint<C>) -> bool {
+        self.is_preflight_endpoint(&Method::POST, endpoint.uri.as_str())
+            && endpoint
+                .allowed_methods
+                .as_ref()
+                .map_or(false, |allowed_methods| {
+                    allowed_methods.iter().all(|method| *method == Method::POST)
+                })
+    }
+
     fn scope(&self, id: ScopeId) -> &Scope<ScopeData<C>> {
         &self.scopes[id]
     }
 
+    /// Returns an iterator over the registered endpoints, in the order of insertion.
+    fn endpoints(&self) -> impl Iterator<Item = &Arc<Endpoint<C>>> + '_ {
+        self.recognizer
+            .iter()
+            .flat_map(|endpoints| endpoints.iter())
+    }
+
+    /// Returns whether the scope accepts the requests to the specified host.
+    fn matches_host(&self, id: ScopeId, host: Option<&str>) -> bool {
+        match self.scope(id).data.host {
+            Some(ref pattern) => host.map_or(false, |host| pattern.matches(host)),
+            None => true,
+        }
+    }
+
+    /// Selects the endpoint that accepts the requests to the specified host and method.
+    ///
+    /// The endpoints constrained to the host take precedence over the unconstrained ones.
+    /// If none of the endpoints for the host allows the method, the first one is returned
+    /// so that the request is replied with `405 Method Not Allowed`.
+    fn select_endpoint<'a>(
+        &self,
+        endpoints: &'a [Arc<Endpoint<C>>],
+        host: Option<&str>,
+        method: &Method,
+    ) -> Option<&'a Arc<Endpoint<C>>> {
+        let constrained = endpoints.iter().any(|endpoint| {
+            self.scope(endpoint.scope).data.host.is_some()
+                && self.matches_host(endpoint.scope, host)
+        });
+        let mut candidates = endpoints
+            .iter()
+            .filter(|endpoint| {
+                self.scope(endpoint.scope).data.host.is_some() == constrained
+                    && self.matches_host(endpoint.scope, host)
+            })
+            .peekable();
+        let first = *candidates.peek()?;
+        Some(
+            candidates
+                .find(|endpoint| endpoint.allows(method))
+                .unwrap_or(first),
+        )
+    }
+
+    /// Returns the methods allowed by all endpoints registered with the same path and
+    /// host as the specified one.
+    fn resource_allowed_methods(&self, endpoint: &Endpoint<C>) -> Option<AllowedMethods> {
+        let host = &self.scope(endpoint.scope).data.host;
+        self.recognizer
+            .get_by_path(endpoint.uri.as_str())?
+            .iter()
+            .filter(|other| self.scope(other.scope).data.host == *host)
+            .map(|other| other.allowed_methods.as_ref())
+            .try_fold(
+                std::iter::empty().collect::<AllowedMethods>(),
+                |mut methods, allowed_methods| {
+                    methods.extend(allowed_methods?.iter().cloned());
+                    Some(methods)
+                },
+            )
+    }
+
+    /// Returns whether the request with a method not allowed by the endpoint should be
+    /// handled as if no route matched, according to the scope configuration `DisallowedMethod`.
+    fn hides_disallowed_method(&self, endpoint: &Endpoint<C>, method: &Method) -> bool {
+        !endpoint.allows(method)
+            && endpoint.fallback.is_none()
+            && self
+                .scope(endpoint.scope)
+                .data
+                .configs
+                .find::<DisallowedMethod>()
+                .map_or(false, |config| *config == DisallowedMethod::NotFound)
+    }
+
+    /// Returns the nearest scope from `start` (including itself) that accepts the
+    /// requests to the specified host.
+    fn host_scope(&self, start: ScopeId, host: Option<&str>) -> Option<&Scope<ScopeData<C>>> {
+        let scope = self.scope(start);
+        Some(scope.id())
+            .into_iter()
+            .chain(scope.ancestors().iter().rev().cloned())
+            .find(|&id| self.matches_host(id, host))
+            .map(|id| self.scope(id))
+    }
+
     /// Infers the scope where the input path belongs from the extracted candidates.
     fn infer_scope<'a>(
         &self,
@@ -145,50 +388,168 @@ impl<C: Concurrency> AppInner<C> {
         self.scope(node_id)
     }
 
+    /// Finds the default handler for the request that matches no route, with the scope
+    /// where the request belongs.
+    ///
+    /// The request belongs to the deepest scope whose prefix contains the path, and
+    /// the nearest fallback is searched from that scope toward the root.  The scopes
+    /// constrained to the host of request take precedence, so that the scope catches
+    /// all requests to the host.  The fallback of a scope is never selected for the
+    /// paths outside of its prefix, even if they partially match the routes in it.
+    fn find_fallback(
+        &self,
+        start: ScopeId,
+        host: Option<&str>,
+        path: &str,
+    ) -> Option<(&Scope<ScopeData<C>>, &C::Handler)> {
+        let inferred = self.host_scope(start, host)?;
+        let rank = |scope: &Scope<ScopeData<C>>| {
+            (
+                scope.data.host.is_some(),
+                scope.ancestors().len(),
+                scope.data.default_handler.is_some(),
+            )
+        };
+        let deepest = self
+            .scopes
+            .ids()
+            .into_iter()
+            .map(|id| self.scope(id))
+            .filter(|scope| {
+                self.matches_host(scope.id(), host) && has_prefix(path, scope.data.prefix.as_str())
+            })
+            .fold(
+                None,
+                |deepest: Option<&Scope<ScopeData<C>>>, scope| match deepest {
+                    Some(deepest) if rank(deepest) >= rank(scope) => Some(deepest),
+                    _ => Some(scope),
+                },
+            );
+        let scope = deepest.unwrap_or(inferred);
+        self.find_default_handler(scope.id())
+            .map(|fallback| (scope, fallback))
+    }
+
     fn find_default_handler(&self, start: ScopeId) -> Option<&C::Handler> {
         let scope = self.scope(start);
         if let Some(ref f) = scope.data.default_handler {
-            return Some(f);
+            return Some(&**f);
         }
         scope
             .ancestors()
             .into_iter()
             .rev()
-            .filter_map(|&id| self.scope(id).data.default_handler.as_ref())
+            .filter_map(|&id| self.scope(id).data.default_handler.as_ref().map(|h| &**h))
             .next()
     }
 
+    /// Finds the endpoint matching the request to the specified host and path.
+    ///
+    /// If no endpoint matches, the scope where the request belongs is returned instead.
+    /// The endpoints and scopes constrained to another host are skipped, so that the
+    /// request continues to the other scopes.  The endpoint that hides the disallowed
+    /// method by `DisallowedMethod::NotFound` is also treated as unmatched.
     fn find_endpoint(
         &self,
+        host: Option<&str>,
+        method: &Method,
         path: &str,
         captures: &mut Option<Captures>,
     ) -> std::result::Result<&Arc<Endpoint<C>>, &Scope<ScopeData<C>>> {
         match self.recognizer.recognize(path, captures) {
-            Ok(endpoint) => Ok(endpoint),
+            Ok(endpoints) => match self.select_endpoint(endpoints, host, method) {
+                Some(endpoint) if self.hides_disallowed_method(endpoint, method) => {
+                    Err(self.scope(endpoint.scope))
+                }
+                Some(endpoint) => Ok(endpoint),
+                None => Err(self.scope(ScopeId::root())),
+            },
             Err(RecognizeError::NotMatched) => Err(self.scope(ScopeId::root())),
             Err(RecognizeError::PartiallyMatched(candidates)) => Err(self.infer_scope(
                 path,
                 candidates
                     .iter()
-                    .filter_map(|i| self.recognizer.get(i).map(|e| &**e)),
+                    .filter_map(|i| self.recognizer.get(i))
+                    .flat_map(|endpoints| endpoints.iter())
+                    .filter(|endpoint| self.matches_host(endpoint.scope, host))
+                    .map(|endpoint| &**endpoint),
             )),
         }
     }
+
+    /// Finds the endpoint registered with the path that differs from the input path
+    /// only in the trailing slash, according to the policy `TrailingSlash`.
+    ///
+    /// The alternate path matched by a wildcard route is ignored, so that the slash
+    /// in the wildcard segment is never added or removed.
+    fn find_alternate_endpoint(
+        &self,
+        host: Option<&str>,
+        method: &Method,
+        path: &str,
+        captures: &mut Option<Captures>,
+    ) -> Option<(&Arc<Endpoint<C>>, String)> {
+        if self.trailing_slash == TrailingSlash::Strict || !path.starts_with('/') {
+            return None;
+        }
+        let alternate = match path {
+            "/" => return None,
+            path if path.ends_with('/') => path[..path.len() - 1].to_owned(),
+            path => format!("{}/", path),
+        };
+
+        let mut alternate_captures = None;
+        let endpoints = self
+            .recognizer
+            .recognize(&alternate, &mut alternate_captures)
+            .ok()?;
+        let endpoint = self.select_endpoint(endpoints, host, method)?;
+        if self.hides_disallowed_method(endpoint, method) {
+            return None;
+        }
+        if alternate_captures
+            .as_ref()
+            .map_or(false, |captures| captures.wildcard().is_some())
+        {
+            return None;
+        }
+        *captures = alternate_captures;
+        Some((endpoint, alternate))
+    }
+}
+
+/// Returns whether the path is located under the prefix of scope.
+fn has_prefix(path: &str, prefix: &str) -> bool {
+    path.starts_with(prefix)
+        && (prefix.ends_with('/')
+            || path.len() == prefix.len()
+            || path.as_bytes()[prefix.len()] == b'/')
 }
 
 struct ScopeData<C: Concurrency> {
     prefix: Uri,
-    default_handler: Option<C::Handler>,
+    host: Option<HostPattern>,
+    default_handler: Option<Arc<C::Handler>>,
+    overridden_fallbacks: usize,
+    /// The name of configuration that owns the default handler, if it is not replaceable.
+    exclusive_fallback: Option<&'static str>,
+    states: StateMap,
+    configs: ScopeConfigs,
 }
 
 impl<C: Concurrency> fmt::Debug for ScopeData<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.debug_struct("ScopeData")
             .field("prefix", &self.prefix)
+            .field("host", &self.host)
             .field(
                 "default_handler",
                 &self.default_handler.as_ref().map(|_| "<default handler>"),
             )
+            .field("overridden_fallbacks", &self.overridden_fallbacks)
+            .field("exclusive_fallback", &self.exclusive_fallback)
+            .field("states", &self.states)
+            .field("configs", &self.configs)
             .finish()
     }
 }
@@ -198,7 +559,51 @@ struct Endpoint<C: Concurrency> {
     scope: ScopeId,
     ancestors: Vec<ScopeId>,
     uri: Uri,
-    handler: C::Handler,
+    /// The registered path with the optional parameters, from which `uri` is expanded.
+    pattern: Option<Arc<str>>,
+    name: Option<Cow<'static, str>>,
+    location: Option<Location>,
+    allowed_methods: Option<AllowedMethods>,
+    metadata: Metadata,
+    handler: Arc<C::Handler>,
+    fallback: Option<Arc<C::Handler>>,
+}
+
+impl<C: Concurrency> Endpoint<C> {
+    /// Returns whether the handler is known to allow the specified method.
+    fn allows(&self, method: &Method) -> bool {
+        self.allowed_methods
+            .as_ref()
+            .map_or(true, |allowed_methods| allowed_methods.contains(method))
+    }
+
+    /// Returns the handler to be called for the request with the specified method.
+    ///
+    /// The fallback of endpoint is selected instead of the handler, only if the method
+    /// is known not to be allowed by the handler.
+    fn handler_for(&self, method: &Method) -> &C::Handler {
+        match self.fallback {
+            Some(ref fallback) if !self.allows(method) => fallback,
+            _ => &self.handler,
+        }
+    }
+
+    /// Returns the description of this route used in the error messages, with the scope
+    /// and the location where it is registered.
+    fn describe(&self, scopes: &Scopes<ScopeData<C>>) -> String {
+        let mut notes = vec![];
+        if let Some(ref pattern) = self.pattern {
+            notes.push(format!("expanded from `{}`", pattern));
+        }
+        notes.push(match scopes[self.scope].data.prefix.as_str() {
+            _ if self.scope == ScopeId::root() => "in the root scope".to_owned(),
+            prefix => format!("in the scope `{}`", prefix),
+        });
+        if let Some(ref location) = self.location {
+            notes.push(format!("at {}", location));
+        }
+        format!("`{}` ({})", self.uri, notes.join(", "))
+    }
 }
 
 impl<C: Concurrency> fmt::Debug for Endpoint<C> {
@@ -207,6 +612,12 @@ impl<C: Concurrency> fmt::Debug for Endpoint<C> {
             .field("scope", &self.scope)
             .field("ancestors", &self.ancestors)
             .field("uri", &self.uri)
+            .field("pattern", &self.pattern)
+            .field("name", &self.name)
+            .field("location", &self.location)
+            .field("allowed_methods", &self.allowed_methods)
+            .field("metadata", &self.metadata)
+            .field("fallback", &self.fallback.is_some())
             .finish()
     }
 }
@@ -0,0 +1,127 @@
+//! Detection of the registrations that can never handle any request.
+
+use {
+    super::{config::Concurrency, AppInner},
+    std::fmt,
+};
+
+/// A registration detected as unreachable when building the application.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnreachableRoute {
+    route: String,
+    reason: Reason,
+}
+
+impl UnreachableRoute {
+    /// Returns the path of the unreachable registration.
+    ///
+    /// The fallbacks registered by `path!("*")` are represented with the prefix of
+    /// the scope followed by `*`.
+    pub fn route(&self) -> &str {
+        &self.route
+    }
+
+    /// Returns the reason why the registration is unreachable.
+    pub fn reason(&self) -> &Reason {
+        &self.reason
+    }
+}
+
+impl fmt::Display for UnreachableRoute {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the route `{}` is unreachable: ", self.route)?;
+        match self.reason {
+            Reason::Shadowed { ref by } => write!(f, "shadowed by `{}`", by),
+            Reason::NoAllowedMethods => f.write_str("the handler allows no HTTP method"),
+        }
+    }
+}
+
+/// The reason why a registration is unreachable.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reason {
+    /// The requests are always dispatched to another registration.
+    Shadowed {
+        /// The path of the registration that shadows the unreachable one.
+        by: String,
+    },
+
+    /// The handler of route allows no HTTP method, and hence declines all requests.
+    NoAllowedMethods,
+}
+
+/// Collects the unreachable registrations, in the order of routes and then fallbacks.
+pub(super) fn find_unreachable_routes<C: Concurrency>(
+    inner: &AppInner<C>,
+) -> Vec<UnreachableRoute> {
+    let mut unreachable = vec![];
+
+    for endpoint in inner.endpoints() {
+        if inner.is_shadowed_by_preflight_endpoint(endpoint) {
+            unreachable.push(UnreachableRoute {
+                route: endpoint.uri.as_str().into(),
+                reason: Reason::Shadowed {
+                    by: format!("{} (preflight endpoint)", endpoint.uri.as_str()),
+                },
+            });
+            continue;
+        }
+
+        if let Some(ref allowed_methods) = endpoint.allowed_methods {
+            if allowed_methods.iter().next().is_none() {
+                unreachable.push(UnreachableRoute {
+                    route: endpoint.uri.as_str().into(),
+                    reason: Reason::NoAllowedMethods,
+                });
+            }
+        }
+    }
+
+    let ids = inner.scopes.ids();
+    for (i, &id) in ids.iter().enumerate() {
+        let data = &inner.scope(id).data;
+        let route = format!("{}*", data.prefix.as_str());
+
+        for _ in 0..data.overridden_fallbacks {
+            unreachable.push(UnreachableRoute {
+                route: route.clone(),
+                reason: Reason::Shadowed {
+                    by: format!("{} (registered later in the same scope)", route),
+                },
+            });
+        }
+
+        // the fallback is never selected if another scope with the same prefix and host
+        // takes precedence, by being nested more deeply or registered earlier.
+        let scope = inner.scope(id);
+        if data.default_handler.is_none() {
+            continue;
+        }
+        if let Some(&other) = ids.iter().enumerate().find_map(|(j, other_id)| {
+            let other = inner.scope(*other_id);
+            let precedes = other.ancestors().len() > scope.ancestors().len()
+                || (other.ancestors().len() == scope.ancestors().len() && j < i);
+            if precedes
+                && other.data.default_handler.is_some()
+                && other.data.prefix == data.prefix
+                && other.data.host == data.host
+            {
+                Some(other_id)
+            } else {
+                None
+            }
+        }) {
+            unreachable.push(UnreachableRoute {
+                route,
+                reason: Reason::Shadowed {
+                    by: format!(
+                        "{}* (registered in another scope)",
+                        inner.scope(other).data.prefix.as_str()
+                    ),
+                },
+            });
+        }
+    }
+
+    unreachable
+}
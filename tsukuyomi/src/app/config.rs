@@ -1,15 +1,28 @@
 use {
     super::{
-        recognizer::Recognizer,
+        analysis::find_unreachable_routes,
+        host::HostPattern,
+        observe::ErrorObservers,
+        preflight::PreflightTarget,
+        recognizer::{Conflict, Recognizer},
         scope::{ScopeId, Scopes},
-        AppBase, AppInner, Endpoint, ScopeData, Uri,
+        AppBase, AppInner, Endpoint, RouteNames, ScopeConfigs, ScopeData, StateMap, Uri,
     },
     crate::{
-        handler::{Handler, ModifyHandler},
+        config::{
+            path::Location, MethodOverride, MountPoint, PathNormalization, PreflightLimits,
+            TrailingSlash,
+        },
+        error::{Error as HandlerError, ErrorEvent},
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+        input::Input,
+        output::{redirect::RouteRef, ResponseBody},
         util::{Chain, Never},
     },
     failure::Fail,
-    std::{marker::PhantomData, rc::Rc, sync::Arc},
+    http::Response,
+    std::{borrow::Cow, collections::HashMap, fmt, marker::PhantomData, rc::Rc, sync::Arc},
 };
 
 /// A type alias of `Result<T, E>` whose error type is restricted to `AppError`.
@@ -97,10 +110,9 @@ mod thread_safe {
         }
     }
 
-    type BoxedHandle =
-        dyn FnMut(&mut Input<'_>) -> Poll<Response<ResponseBody>, crate::error::Error>
-            + Send
-            + 'static;
+    type BoxedHandle = dyn FnMut(&mut Input<'_>) -> Poll<Response<ResponseBody>, crate::error::Error>
+        + Send
+        + 'static;
 
     pub struct BoxedHandler(Box<dyn Fn() -> Box<BoxedHandle> + Send + Sync + 'static>);
 
@@ -139,7 +151,7 @@ mod thread_safe {
                                 futures01::try_ready!(respond
                                     .poll_ready(input)
                                     .map_err(Into::into))
-                                .into_response(input.request)
+                                .respond_to(input)
                                 .map_err(Into::into)?
                                 .map(Into::into),
                             ));
@@ -227,7 +239,7 @@ mod current_thread {
                                 futures01::try_ready!(respond
                                     .poll_ready(input)
                                     .map_err(Into::into))
-                                .into_response(input.request)
+                                .respond_to(input)
                                 .map_err(Into::into)?
                                 .map(Into::into),
                             ));
@@ -244,33 +256,348 @@ where
     T: Concurrency,
 {
     /// Creates a new `App` from the provided configuration.
+    ///
+    /// The registrations that can never handle any request (e.g. a fallback overridden
+    /// by another one in the same scope) are logged as warnings, and can be obtained
+    /// by `unreachable_routes`.
     pub fn create(config: impl Config<(), T>) -> Result<Self> {
+        Self::create_inner(config, false)
+    }
+
+    /// Creates a new `App` from the provided configuration, rejecting the unreachable
+    /// registrations as an error.
+    pub fn create_strict(config: impl Config<(), T>) -> Result<Self> {
+        Self::create_inner(config, true)
+    }
+
+    fn create_inner(config: impl Config<(), T>, strict: bool) -> Result<Self> {
         let mut recognizer = Recognizer::default();
         let mut scopes = Scopes::new(ScopeData {
             prefix: Uri::root(),
             default_handler: None,
+            overridden_fallbacks: 0,
+            exclusive_fallback: None,
+            states: StateMap::default(),
+            configs: ScopeConfigs::default(),
+            host: None,
         });
+        let mut tags = Tags::default();
+        let mut preflight_endpoint = None;
+        let mut trailing_slash = TrailingSlash::default();
+        let mut method_override = None;
+        let mut path_normalization = None;
+        let mut mount_point = None;
+        let mut error_observers = ErrorObservers::default();
+        let mut route_names = RouteNames::default();
         config
             .configure(&mut Scope {
                 recognizer: &mut recognizer,
                 scopes: &mut scopes,
+                tags: &mut tags,
+                preflight_endpoint: &mut preflight_endpoint,
+                trailing_slash: &mut trailing_slash,
+                method_override: &mut method_override,
+                path_normalization: &mut path_normalization,
+                mount_point: &mut mount_point,
+                error_observers: &mut error_observers,
+                route_names: &mut route_names,
                 scope_id: ScopeId::root(),
                 modifier: &(),
                 _marker: PhantomData,
             })
             .map_err(Into::into)?;
+        route_names.validate().map_err(Error::custom)?;
+
+        // propagate the states, configuration values and hosts registered in the ancestor scopes.
+        for id in scopes.ids() {
+            if let Some(&parent) = scopes[id].ancestors().last() {
+                let parent_states = scopes[parent].data.states.clone();
+                scopes[id].data.states.inherit(&parent_states);
+                let parent_configs = scopes[parent].data.configs.clone();
+                scopes[id].data.configs.inherit(&parent_configs);
+                if let Some(parent_host) = scopes[parent].data.host.clone() {
+                    if let Some(ref host) = scopes[id].data.host {
+                        return Err(Error::custom(failure::format_err!(
+                            "the scope constrained to the host `{}` cannot be nested in the one for `{}`",
+                            host,
+                            parent_host
+                        )));
+                    }
+                    scopes[id].data.host = Some(parent_host);
+                }
+            }
+        }
+
+        // expose the effective configuration values through the metadata of the routes.
+        for endpoints in recognizer.iter_mut() {
+            for endpoint in endpoints {
+                let endpoint =
+                    Arc::get_mut(endpoint).expect("the endpoint should not be shared yet");
+                endpoint
+                    .metadata
+                    .set_configs(&scopes[endpoint.scope].data.configs);
+            }
+        }
+
+        // the routes registered with the same path and host are allowed only if
+        // they declare the disjoint sets of methods.
+        for endpoints in recognizer.iter() {
+            for (i, endpoint) in endpoints.iter().enumerate() {
+                let host = &scopes[endpoint.scope].data.host;
+                if let Some(other) = endpoints[..i].iter().find(|other| {
+                    scopes[other.scope].data.host == *host
+                        && overlaps(&endpoint.allowed_methods, &other.allowed_methods)
+                }) {
+                    return Err(Error::custom(match host {
+                        Some(host) => failure::format_err!(
+                            "the route {} conflicts with the route {} for the host `{}`: both routes accept the same methods",
+                            endpoint.describe(&scopes),
+                            other.describe(&scopes),
+                            host
+                        ),
+                        None => failure::format_err!(
+                            "the route {} conflicts with the route {}: both routes accept the same methods",
+                            endpoint.describe(&scopes),
+                            other.describe(&scopes)
+                        ),
+                    }));
+                }
+            }
+        }
+
+        // the empty segment is left for the alternate path without the trailing slash.
+        recognizer.reject_empty_params(trailing_slash != TrailingSlash::Strict);
+
+        let mut inner = AppInner {
+            recognizer,
+            scopes,
+            preflight_endpoint,
+            trailing_slash,
+            method_override,
+            path_normalization,
+            mount_point,
+            error_observers,
+            route_names,
+            unreachable_routes: vec![],
+        };
+        inner.unreachable_routes = find_unreachable_routes(&inner);
+        if strict && !inner.unreachable_routes.is_empty() {
+            let messages: Vec<_> = inner
+                .unreachable_routes
+                .iter()
+                .map(ToString::to_string)
+                .collect();
+            return Err(Error::custom(failure::format_err!(
+                "detected unreachable routes: {}",
+                messages.join("; ")
+            )));
+        }
+        for unreachable in &inner.unreachable_routes {
+            log::warn!("{}", unreachable);
+        }
 
         Ok(Self {
-            inner: Arc::new(AppInner { recognizer, scopes }),
+            inner: Arc::new(inner),
         })
     }
 }
 
+/// Concatenates the prefix of scope and the path with the optional parameters,
+/// in the same way as `Uri::join`.
+fn join_pattern(prefix: &Uri, path: &str) -> Arc<str> {
+    match prefix.as_str() {
+        "/" => path.into(),
+        prefix => format!("{}{}", prefix.trim_end_matches('/'), path).into(),
+    }
+}
+
+/// Returns whether two routes may receive the requests with the same method.
+fn overlaps(a: &Option<AllowedMethods>, b: &Option<AllowedMethods>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => a.iter().any(|method| b.contains(method)),
+        _ => true,
+    }
+}
+
+/// A type-erased handler passed to the modifiers bound to tags.
+///
+/// The output of the route is converted into `Response<ResponseBody>` before it is
+/// passed to the tag modifiers, so that the modifiers bound to the different tags
+/// can be applied to a route in any combination.
+pub struct TaggedHandler<T: Concurrency> {
+    inner: T::Handler,
+    allowed_methods: Option<AllowedMethods>,
+    metadata: Metadata,
+}
+
+impl<T: Concurrency> fmt::Debug for TaggedHandler<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TaggedHandler")
+            .field("allowed_methods", &self.allowed_methods)
+            .field("metadata", &self.metadata)
+            .finish()
+    }
+}
+
+impl<T: Concurrency> TaggedHandler<T> {
+    fn new<H>(handler: H) -> Self
+    where
+        H: Handler + Into<T::Handler>,
+    {
+        Self {
+            allowed_methods: handler.allowed_methods().cloned(),
+            metadata: handler.metadata().clone(),
+            inner: handler.into(),
+        }
+    }
+}
+
+impl<T: Concurrency> Handler for TaggedHandler<T> {
+    type Output = Response<ResponseBody>;
+    type Error = HandlerError;
+    type Handle = TaggedHandle<T>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.allowed_methods.as_ref()
+    }
+
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    fn handle(&self) -> Self::Handle {
+        TaggedHandle(T::handle(&self.inner))
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct TaggedHandle<T: Concurrency>(T::Handle);
+
+impl<T: Concurrency> TryFuture for TaggedHandle<T> {
+    type Ok = Response<ResponseBody>;
+    type Error = HandlerError;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        T::poll_ready(&mut self.0, input)
+    }
+}
+
+/// A type-erased modifier bound to a tag or to a scope by `with_outer`.
+struct TagModifier<T: Concurrency> {
+    modify: Box<dyn Fn(TaggedHandler<T>) -> TaggedHandler<T>>,
+    participates_in_preflight: bool,
+}
+
+impl<T: Concurrency> TagModifier<T> {
+    fn new<M>(modifier: M) -> Self
+    where
+        M: ModifyHandler<TaggedHandler<T>> + 'static,
+        M::Handler: Into<T::Handler>,
+    {
+        Self {
+            participates_in_preflight: modifier.participates_in_preflight(),
+            modify: Box::new(move |handler| TaggedHandler::new(modifier.modify(handler))),
+        }
+    }
+}
+
+/// The type-erased modifiers bound to tags at the root scope, or to scopes by `with_outer`.
+struct Tags<T: Concurrency> {
+    modifiers: HashMap<Cow<'static, str>, Vec<TagModifier<T>>>,
+    outer: HashMap<ScopeId, Vec<TagModifier<T>>>,
+}
+
+impl<T: Concurrency> Default for Tags<T> {
+    fn default() -> Self {
+        Self {
+            modifiers: HashMap::new(),
+            outer: HashMap::new(),
+        }
+    }
+}
+
+impl<T: Concurrency> fmt::Debug for Tags<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.modifiers.keys()).finish()
+    }
+}
+
+impl<T: Concurrency> Tags<T> {
+    /// Applies the modifiers bound to the specified tags, and then the outer modifiers
+    /// of the scopes in `scope_chain` (from the innermost scope).
+    ///
+    /// The returned value contains the methods allowed by the modified handler, and
+    /// the values attached to it.  If the route is marked by `Route::preflight`, all
+    /// modifiers applied to it, including the scope modifiers specified by
+    /// `participates_in_preflight`, must take part in the authorization preflight.
+    fn apply<H>(
+        &self,
+        route: &str,
+        handler: H,
+        participates_in_preflight: bool,
+        tags: &[Cow<'static, str>],
+        scope_chain: &[ScopeId],
+    ) -> Result<(T::Handler, Option<AllowedMethods>, Metadata)>
+    where
+        H: Handler + Into<T::Handler>,
+    {
+        let outer: Vec<_> = scope_chain
+            .iter()
+            .filter_map(|id| self.outer.get(id))
+            .flatten()
+            .collect();
+        let mut modifiers = Vec::with_capacity(outer.len());
+        for tag in tags {
+            modifiers.extend(self.modifiers.get(tag).ok_or_else(|| {
+                Error::custom(failure::format_err!(
+                    "the route `{}` is tagged with `{}`, but no modifier is bound to the tag",
+                    route,
+                    tag
+                ))
+            })?);
+        }
+        modifiers.extend(outer);
+
+        if PreflightTarget::is_attached(handler.metadata())
+            && !(participates_in_preflight
+                && modifiers
+                    .iter()
+                    .all(|modifier| modifier.participates_in_preflight))
+        {
+            return Err(Error::custom(failure::format_err!(
+                "the route `{}` takes part in the authorization preflight, \
+                 but some of the modifiers applied to it do not",
+                route
+            )));
+        }
+
+        if modifiers.is_empty() {
+            let allowed_methods = handler.allowed_methods().cloned();
+            let metadata = handler.metadata().clone();
+            return Ok((handler.into(), allowed_methods, metadata));
+        }
+        let mut handler = TaggedHandler::new(handler);
+        for modifier in modifiers {
+            handler = (modifier.modify)(handler);
+        }
+        Ok((handler.inner, handler.allowed_methods, handler.metadata))
+    }
+}
+
 /// A type representing the contextual information in `Config::configure`.
 #[derive(Debug)]
 pub struct Scope<'a, M, T: Concurrency> {
-    recognizer: &'a mut Recognizer<Arc<Endpoint<T>>>,
+    recognizer: &'a mut Recognizer<Vec<Arc<Endpoint<T>>>>,
     scopes: &'a mut Scopes<ScopeData<T>>,
+    tags: &'a mut Tags<T>,
+    preflight_endpoint: &'a mut Option<(Uri, PreflightLimits)>,
+    trailing_slash: &'a mut TrailingSlash,
+    method_override: &'a mut Option<MethodOverride>,
+    path_normalization: &'a mut Option<PathNormalization>,
+    mount_point: &'a mut Option<MountPoint>,
+    error_observers: &'a mut ErrorObservers,
+    route_names: &'a mut RouteNames,
     modifier: &'a M,
     scope_id: ScopeId,
     _marker: PhantomData<Rc<()>>,
@@ -287,42 +614,248 @@ where
         M: ModifyHandler<H>,
         M::Handler: Into<T::Handler>,
     {
-        let uri: Option<Uri> = match path.as_ref() {
-            "*" => None,
-            path => path.parse().map(Some).map_err(Error::custom)?,
-        };
+        self.route_with_options(path, handler, &[], None, None)
+    }
 
-        if let Some(uri) = uri {
-            let uri = self.scopes[self.scope_id]
-                .data
-                .prefix
-                .join(&uri)
-                .map_err(Error::custom)?;
+    /// Registers the default handler of the current scope, which must not be replaced
+    /// by the other ones.
+    ///
+    /// This is used by the configurations serving all paths in the scope by themselves
+    /// (e.g. `Staticfiles`), so that the conflicts with the other default handlers in
+    /// the same scope are reported when the application is built.
+    pub(crate) fn exclusive_default_handler<H>(
+        &mut self,
+        owner: &'static str,
+        handler: H,
+    ) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<T::Handler>,
+    {
+        if self.scopes[self.scope_id].data.default_handler.is_some() {
+            return Err(Error::custom(failure::format_err!(
+                "the default handler registered by `{}` conflicts with the one `{}*`",
+                owner,
+                self.scopes[self.scope_id].data.prefix.as_str()
+            )));
+        }
+        self.route("*", handler)?;
+        self.scopes[self.scope_id].data.exclusive_fallback = Some(owner);
+        Ok(())
+    }
+
+    /// Returns the current scope and its ancestors, from the innermost one.
+    fn scope_chain(&self) -> Vec<ScopeId> {
+        let scope = &self.scopes[self.scope_id];
+        Some(scope.id())
+            .into_iter()
+            .chain(scope.ancestors().iter().rev().cloned())
+            .collect()
+    }
+
+    /// Returns the prefix of the current scope.
+    pub(crate) fn prefix(&self) -> &Uri {
+        &self.scopes[self.scope_id].data.prefix
+    }
+
+    pub(crate) fn route_with_options<H>(
+        &mut self,
+        path: impl AsRef<str>,
+        handler: H,
+        tags: &[Cow<'static, str>],
+        name: Option<Cow<'static, str>>,
+        location: Option<Location>,
+    ) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<T::Handler>,
+    {
+        if path.as_ref() != "*" {
+            let (uris, pattern) = self.expand_path(path.as_ref())?;
+            let longest = uris.last().expect("should not be empty");
 
+            // the name refers to the path with all optional parameters.
+            if let Some(ref name) = name {
+                self.route_names
+                    .insert(name.clone(), longest.clone())
+                    .map_err(Error::custom)?;
+            }
+
+            let (handler, allowed_methods, metadata) = self.tags.apply(
+                pattern
+                    .as_ref()
+                    .map_or(longest.as_str(), |pattern| &**pattern),
+                self.modifier.modify(handler),
+                <M as ModifyHandler<H>>::participates_in_preflight(self.modifier),
+                tags,
+                &self.scope_chain(),
+            )?;
+            let handler = Arc::new(handler);
             let scope = &self.scopes[self.scope_id];
-            self.recognizer
-                .insert(
-                    uri.as_str(),
-                    Arc::new(Endpoint {
-                        scope: scope.id(),
-                        ancestors: scope
-                            .ancestors()
-                            .into_iter()
-                            .cloned()
-                            .chain(Some(scope.id()))
-                            .collect(),
-                        uri: uri.clone(),
-                        handler: self.modifier.modify(handler).into(),
-                    }),
-                )
-                .map_err(Error::custom)?;
+            let ancestors: Vec<_> = scope
+                .ancestors()
+                .iter()
+                .cloned()
+                .chain(Some(scope.id()))
+                .collect();
+            for (i, uri) in uris.iter().enumerate() {
+                self.insert_endpoint(Endpoint {
+                    scope: self.scope_id,
+                    ancestors: ancestors.clone(),
+                    uri: uri.clone(),
+                    pattern: pattern.clone(),
+                    name: if i == uris.len() - 1 {
+                        name.clone()
+                    } else {
+                        None
+                    },
+                    location,
+                    allowed_methods: allowed_methods.clone(),
+                    metadata: metadata.clone(),
+                    handler: handler.clone(),
+                    fallback: None,
+                })?;
+            }
         } else {
-            self.scopes[self.scope_id].data.default_handler =
-                Some(self.modifier.modify(handler).into());
+            if let Some(name) = name {
+                return Err(Error::custom(failure::format_err!(
+                    "the default handler cannot be named (name: `{}`)",
+                    name
+                )));
+            }
+            let route = format!("{}*", self.scopes[self.scope_id].data.prefix.as_str());
+            if let Some(owner) = self.scopes[self.scope_id].data.exclusive_fallback {
+                return Err(Error::custom(failure::format_err!(
+                    "the default handler `{}` conflicts with the one registered by `{}`",
+                    route,
+                    owner
+                )));
+            }
+            let (handler, ..) = self.tags.apply(
+                &route,
+                self.modifier.modify(handler),
+                <M as ModifyHandler<H>>::participates_in_preflight(self.modifier),
+                tags,
+                &self.scope_chain(),
+            )?;
+            let data = &mut self.scopes[self.scope_id].data;
+            if data.default_handler.replace(Arc::new(handler)).is_some() {
+                data.overridden_fallbacks += 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Sets the handler called instead of the route with the specified path, when the
+    /// route does not allow the method of request.
+    ///
+    /// The route must be registered in the current scope beforehand.
+    pub(crate) fn route_fallback<H>(
+        &mut self,
+        path: impl AsRef<str>,
+        fallback: H,
+        tags: &[Cow<'static, str>],
+    ) -> Result<()>
+    where
+        H: Handler,
+        M: ModifyHandler<H>,
+        M::Handler: Into<T::Handler>,
+    {
+        if path.as_ref() == "*" {
+            return Err(Error::custom(failure::format_err!(
+                "the default handler cannot have a fallback"
+            )));
+        }
+        let (uris, pattern) = self.expand_path(path.as_ref())?;
+        let longest = uris.last().expect("should not be empty");
+        let (fallback, ..) = self.tags.apply(
+            pattern
+                .as_ref()
+                .map_or(longest.as_str(), |pattern| &**pattern),
+            self.modifier.modify(fallback),
+            <M as ModifyHandler<H>>::participates_in_preflight(self.modifier),
+            tags,
+            &self.scope_chain(),
+        )?;
+        let fallback = Arc::new(fallback);
+        for uri in &uris {
+            let endpoint = self
+                .recognizer
+                .get_by_path_mut(uri.as_str())
+                .and_then(|endpoints| endpoints.last_mut())
+                .and_then(Arc::get_mut)
+                .ok_or_else(|| {
+                    Error::custom(failure::format_err!(
+                        "the route `{}` has not been registered",
+                        uri.as_str()
+                    ))
+                })?;
+            endpoint.fallback = Some(fallback.clone());
         }
         Ok(())
     }
 
+    /// Inserts the endpoint into the recognizer.
+    ///
+    /// The routes with the same path are allowed if they belong to the different hosts
+    /// or methods, which is validated after all scopes are configured.
+    fn insert_endpoint(&mut self, endpoint: Endpoint<T>) -> Result<()> {
+        let endpoint = Arc::new(endpoint);
+        if let Some(endpoints) = self.recognizer.get_by_path_mut(endpoint.uri.as_str()) {
+            endpoints.push(endpoint);
+            return Ok(());
+        }
+        let uri = endpoint.uri.clone();
+        self.recognizer
+            .insert(uri.as_str(), vec![endpoint.clone()])
+            .map_err(|cause| {
+                let description = endpoint.describe(self.scopes);
+                Error::custom(
+                    match cause
+                        .downcast_ref::<Conflict>()
+                        .and_then(|conflict| self.recognizer.get(conflict.index))
+                        .and_then(|endpoints| endpoints.first())
+                    {
+                        Some(other) => failure::format_err!(
+                            "the route {} conflicts with the route {}: {}",
+                            description,
+                            other.describe(self.scopes),
+                            cause
+                        ),
+                        None => failure::format_err!(
+                            "failed to register the route {}: {}",
+                            description,
+                            cause
+                        ),
+                    },
+                )
+            })
+    }
+
+    /// Expands the path with the optional parameters into the URIs under the prefix
+    /// of current scope, from the shortest one.
+    ///
+    /// The original path joined with the prefix is also returned if it is expanded.
+    fn expand_path(&self, path: &str) -> Result<(Vec<Uri>, Option<Arc<str>>)> {
+        let prefix = &self.scopes[self.scope_id].data.prefix;
+        let uris = crate::uri::expand_optional_params(path)
+            .and_then(|paths| {
+                paths
+                    .iter()
+                    .map(|path| prefix.join(path.parse::<Uri>()?))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+            })
+            .map_err(Error::custom)?;
+        let pattern = if uris.len() > 1 {
+            Some(join_pattern(prefix, path))
+        } else {
+            None
+        };
+        Ok((uris, pattern))
+    }
+
     /// Creates a sub-scope with the provided prefix onto the current scope.
     pub fn mount(&mut self, prefix: impl AsRef<str>, config: impl Config<M, T>) -> Result<()> {
         let prefix: Uri = prefix.as_ref().parse().map_err(Error::custom)?;
@@ -334,6 +867,11 @@ where
                 ScopeData {
                     prefix: parent.prefix.join(&prefix).map_err(Error::custom)?,
                     default_handler: None,
+                    overridden_fallbacks: 0,
+                    exclusive_fallback: None,
+                    states: StateMap::default(),
+                    configs: ScopeConfigs::default(),
+                    host: None,
                 }
             })
             .map_err(Error::custom)?;
@@ -342,6 +880,14 @@ where
             .configure(&mut Scope {
                 recognizer: &mut *self.recognizer,
                 scopes: &mut *self.scopes,
+                tags: &mut *self.tags,
+                preflight_endpoint: &mut *self.preflight_endpoint,
+                trailing_slash: &mut *self.trailing_slash,
+                method_override: &mut *self.method_override,
+                path_normalization: &mut *self.path_normalization,
+                mount_point: &mut *self.mount_point,
+                error_observers: &mut *self.error_observers,
+                route_names: &mut *self.route_names,
                 scope_id,
                 modifier: &*self.modifier,
                 _marker: PhantomData,
@@ -351,7 +897,374 @@ where
         Ok(())
     }
 
+    /// Mounts the routes of a pre-built `App` onto the current scope with the provided prefix.
+    ///
+    /// The routes, fallbacks and states of `app` are rebased under the prefix, with
+    /// its modifiers already applied.  The modifiers of the current scope are *not*
+    /// applied to them, since the handlers of `app` have been built.
+    ///
+    /// The states and configuration values registered in `app` take precedence over the
+    /// ones of the same type in the enclosing scopes, and are never visible from the
+    /// routes outside of the prefix.  The fallbacks of `app` apply only under the prefix,
+    /// and the policies of trailing slash and method override and the error observers
+    /// of the enclosing application are used instead of the ones of `app`.  The prefix
+    /// cannot contain any parameter.
+    pub fn mount_app(&mut self, prefix: impl AsRef<str>, app: AppBase<T>) -> Result<()> {
+        let prefix: Uri = prefix.as_ref().parse().map_err(Error::custom)?;
+        let prefix = self.scopes[self.scope_id]
+            .data
+            .prefix
+            .join(&prefix)
+            .map_err(Error::custom)?;
+        if prefix.capture_names().is_some() {
+            return Err(Error::custom(failure::format_err!(
+                "the prefix of mounted application cannot contain any parameter: `{}`",
+                prefix.as_str()
+            )));
+        }
+        let inner = &*app.inner;
+
+        // copy the scopes of `app`, in the order that the parents come before their children.
+        let mut scope_ids: Vec<(ScopeId, ScopeId)> = vec![];
+        for id in inner.scopes.ids() {
+            let scope = &inner.scopes[id];
+            let parent = match scope.ancestors().last() {
+                Some(parent) => {
+                    scope_ids
+                        .iter()
+                        .find(|&&(inner_id, _)| inner_id == *parent)
+                        .expect("the parent should be copied beforehand")
+                        .1
+                }
+                None => self.scope_id,
+            };
+            // the host propagated from the parent is set again when building the application.
+            let host = match scope.ancestors().last() {
+                Some(&parent) if inner.scopes[parent].data.host.is_some() => None,
+                _ => scope.data.host.clone(),
+            };
+            let scope_id = self
+                .scopes
+                .add_node(
+                    parent,
+                    ScopeData {
+                        prefix: prefix.join(&scope.data.prefix).map_err(Error::custom)?,
+                        default_handler: scope.data.default_handler.clone(),
+                        overridden_fallbacks: 0,
+                        exclusive_fallback: scope.data.exclusive_fallback,
+                        states: scope.data.states.clone(),
+                        configs: scope.data.configs.clone(),
+                        host,
+                    },
+                )
+                .map_err(Error::custom)?;
+            scope_ids.push((id, scope_id));
+        }
+
+        for endpoint in inner.endpoints() {
+            let uri = prefix.join(&endpoint.uri).map_err(Error::custom)?;
+            if let Some(ref name) = endpoint.name {
+                self.route_names
+                    .insert(name.clone(), uri.clone())
+                    .map_err(Error::custom)?;
+            }
+            let scope = &self.scopes[scope_ids
+                .iter()
+                .find(|&&(inner_id, _)| inner_id == endpoint.scope)
+                .expect("the scope should be copied")
+                .1];
+            let endpoint = Endpoint {
+                scope: scope.id(),
+                ancestors: scope
+                    .ancestors()
+                    .iter()
+                    .cloned()
+                    .chain(Some(scope.id()))
+                    .collect(),
+                uri,
+                pattern: endpoint
+                    .pattern
+                    .as_ref()
+                    .map(|pattern| join_pattern(&prefix, pattern)),
+                name: endpoint.name.clone(),
+                location: endpoint.location,
+                allowed_methods: endpoint.allowed_methods.clone(),
+                metadata: endpoint.metadata.clone(),
+                handler: endpoint.handler.clone(),
+                fallback: endpoint.fallback.clone(),
+            };
+            self.insert_endpoint(endpoint)?;
+        }
+
+        if let Some((ref preflight_endpoint, ref limits)) = inner.preflight_endpoint {
+            if self.preflight_endpoint.is_some() {
+                return Err(Error::custom(failure::format_err!(
+                    "the preflight endpoint has already been registered"
+                )));
+            }
+            *self.preflight_endpoint = Some((
+                prefix.join(preflight_endpoint).map_err(Error::custom)?,
+                limits.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Registers a shared value onto the current scope.
+    ///
+    /// The registered value can be accessed from the handlers in this scope and its
+    /// descendants by using `extractor::state`.  If a value of the same type is
+    /// registered in a sub-scope, it shadows the value of the ancestor scope.
+    pub fn state<S>(&mut self, state: S) -> Result<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        self.scopes[self.scope_id].data.states.insert(state);
+        Ok(())
+    }
+
+    /// Registers a typed configuration value onto the current scope.
+    ///
+    /// The value applies to the routes in this scope and its descendants, and a value
+    /// of the same type registered in a sub-scope overrides it.  The effective values
+    /// are resolved when the application is built, and can be accessed from the handlers
+    /// by using `extractor::scope_config`.
+    pub fn config<V>(&mut self, value: V) -> Result<()>
+    where
+        V: Send + Sync + 'static,
+    {
+        self.scopes[self.scope_id].data.configs.insert(value);
+        Ok(())
+    }
+
+    /// Binds a `ModifyHandler` to the specified tag.
+    ///
+    /// The modifier is applied to all routes tagged with `tag` by `Route::tag`,
+    /// regardless of the scope where they are registered.  The tag modifiers wrap the
+    /// outside of the scope modifiers, in the order in which the tags are added to
+    /// the route.
+    ///
+    /// The tag modifiers must be bound at the root scope, before the routes using
+    /// the tag are registered.
+    pub fn with_tagged<M2>(&mut self, tag: impl Into<Cow<'static, str>>, modifier: M2) -> Result<()>
+    where
+        M2: ModifyHandler<TaggedHandler<T>> + 'static,
+        M2::Handler: Into<T::Handler>,
+    {
+        let tag = tag.into();
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the modifier for the tag `{}` must be bound at the root scope",
+                tag
+            )));
+        }
+        self.tags
+            .modifiers
+            .entry(tag)
+            .or_default()
+            .push(TagModifier::new(modifier));
+        Ok(())
+    }
+
+    /// Registers a `ModifyHandler` that wraps the outside of all modifiers applied to
+    /// the routes in the current scope.
+    ///
+    /// Unlike `Scope::modify`, the modifier wraps the modifiers applied in the nested
+    /// scopes and the tag modifiers as well, so that it observes the requests rejected
+    /// by them (e.g. logging outside of CORS outside of authentication).  The outer
+    /// modifiers of an enclosing scope wrap the ones of the nested scopes, and the
+    /// modifier registered later wraps the earlier ones in the same scope.
+    ///
+    /// The outer modifier applies to the routes and fallbacks registered after it,
+    /// and the output of route is converted into `Response<ResponseBody>` before it
+    /// is passed to the modifier, in the same way as the tag modifiers.
+    pub fn with_outer<M2>(&mut self, modifier: M2) -> Result<()>
+    where
+        M2: ModifyHandler<TaggedHandler<T>> + 'static,
+        M2::Handler: Into<T::Handler>,
+    {
+        self.tags
+            .outer
+            .entry(self.scope_id)
+            .or_default()
+            .push(TagModifier::new(modifier));
+        Ok(())
+    }
+
+    /// Declares that the route named `name` is referenced by the handlers, e.g.
+    /// through the links written in the templates.
+    ///
+    /// The creation of `App` fails if no route is registered with the name by
+    /// `Route::name`, so that the typos are detected at startup rather than when
+    /// the handler is called.
+    pub fn require_route(&mut self, name: impl Into<Cow<'static, str>>) -> Result<()> {
+        self.route_names.require(name.into());
+        Ok(())
+    }
+
+    /// Returns a reference to the route named `name`, which is used by the handlers
+    /// through `output::redirect::to_route`.
+    ///
+    /// As with `require_route`, the creation of `App` fails if no route is registered
+    /// with the name by `Route::name`.
+    pub fn route_ref(&mut self, name: impl Into<Cow<'static, str>>) -> RouteRef {
+        let name = name.into();
+        self.route_names.require(name.clone());
+        RouteRef::new(name)
+    }
+
+    /// Registers the built-in endpoint that evaluates a batch of requests by the
+    /// authorization preflight (see `App::authorize`).
+    ///
+    /// The endpoint accepts a `POST` request whose body is a JSON array of objects
+    /// with the fields `method` and `path`, and responds with a JSON array of the
+    /// results in the same order.  The requests to the same path with the other
+    /// methods are routed as usual.  Each sub-request shares the headers and extensions
+    /// of the request to the endpoint, and its path is rewritten by `PathNormalization`
+    /// and `MountPoint` before routing.  The modifiers are not applied to this endpoint.
+    ///
+    /// The requests whose body or number of sub-requests exceeds `limits` are rejected
+    /// with `413 Payload Too Large`.
+    pub fn preflight_endpoint(
+        &mut self,
+        path: impl AsRef<str>,
+        limits: PreflightLimits,
+    ) -> Result<()> {
+        let uri: Uri = path.as_ref().parse().map_err(Error::custom)?;
+        let uri = self.scopes[self.scope_id]
+            .data
+            .prefix
+            .join(&uri)
+            .map_err(Error::custom)?;
+        if self.preflight_endpoint.is_some() {
+            return Err(Error::custom(failure::format_err!(
+                "the preflight endpoint has already been registered"
+            )));
+        }
+        *self.preflight_endpoint = Some((uri, limits));
+        Ok(())
+    }
+
+    /// Constrains the routes and the fallback in the current scope and its descendants
+    /// to the requests to the specified host.
+    ///
+    /// The host of request is taken from the authority of request URI (`:authority`
+    /// in HTTP/2), or the header field `Host`, without the port.  The pattern is either
+    /// an exact host name such as `api.example.com`, or a wildcard subdomain such as
+    /// `*.example.com` whose matched part can be extracted by `extractor::subdomain`.
+    ///
+    /// A request whose host does not match continues to the routes in the other scopes,
+    /// and hence the same path can be registered for the different hosts.  The scopes
+    /// constrained to the hosts cannot be nested.
+    pub fn host(&mut self, pattern: impl AsRef<str>) -> Result<()> {
+        let pattern = HostPattern::parse(pattern.as_ref()).map_err(Error::custom)?;
+        let data = &mut self.scopes[self.scope_id].data;
+        if let Some(ref host) = data.host {
+            return Err(Error::custom(failure::format_err!(
+                "the scope has already been constrained to the host `{}`",
+                host
+            )));
+        }
+        data.host = Some(pattern);
+        Ok(())
+    }
+
+    /// Sets the policy of routing the requests with or without the trailing slash.
+    ///
+    /// The policy applies to the entire application, and hence must be set at the
+    /// root scope.  See the documentation of `TrailingSlash` for details.
+    pub fn trailing_slash(&mut self, policy: TrailingSlash) -> Result<()> {
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the policy of trailing slash must be set at the root scope"
+            )));
+        }
+        *self.trailing_slash = policy;
+        Ok(())
+    }
+
+    /// Enables the override of request method before routing.
+    ///
+    /// The policy applies to the entire application, and hence must be set at the
+    /// root scope.  See the documentation of `MethodOverride` for details.
+    pub fn method_override(&mut self, policy: MethodOverride) -> Result<()> {
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the method override must be enabled at the root scope"
+            )));
+        }
+        *self.method_override = Some(policy);
+        Ok(())
+    }
+
+    /// Enables the normalization of request path before routing.
+    ///
+    /// The policy applies to the entire application, and hence must be set at the
+    /// root scope.  See the documentation of `PathNormalization` for details.
+    pub fn path_normalization(&mut self, policy: PathNormalization) -> Result<()> {
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the path normalization must be enabled at the root scope"
+            )));
+        }
+        *self.path_normalization = Some(policy);
+        Ok(())
+    }
+
+    /// Sets the mount point of the application deployed under a path prefix.
+    ///
+    /// The policy applies to the entire application, and hence must be set at the
+    /// root scope.  See the documentation of `MountPoint` for details.
+    pub fn mount_point(&mut self, policy: MountPoint) -> Result<()> {
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the mount point must be set at the root scope"
+            )));
+        }
+        if self.mount_point.is_some() {
+            return Err(Error::custom(failure::format_err!(
+                "the mount point has already been set"
+            )));
+        }
+        *self.mount_point = Some(super::mount::normalize(policy).map_err(Error::custom)?);
+        Ok(())
+    }
+
+    /// Registers a function called with every error occurring in the application.
+    ///
+    /// The function observes the errors returned from all handlers and their `Responder`s,
+    /// as well as the ones occurring before reaching a handler (e.g. `404 Not Found` from
+    /// routing).  The errors are converted into the response before calling the function,
+    /// in order to inspect the status code.  The errors handled by the modifiers (i.e.
+    /// converted into a successful output) are not observed.
+    ///
+    /// The errors from the stream of `StreamBody`, which occur after the response head
+    /// has been sent, are also passed to the function (see `ErrorEvent::committed`).
+    ///
+    /// The observers apply to the entire application, and hence must be registered at
+    /// the root scope.  They are called in the order of registration.
+    pub fn observe_errors<F>(&mut self, f: F) -> Result<()>
+    where
+        F: Fn(&ErrorEvent<'_>) + Send + Sync + 'static,
+    {
+        if self.scope_id != ScopeId::root() {
+            return Err(Error::custom(failure::format_err!(
+                "the error observers must be registered at the root scope"
+            )));
+        }
+        self.error_observers.push(f);
+        Ok(())
+    }
+
     /// Applies the specified configuration with a `ModifyHandler` on the current scope.
+    ///
+    /// The modifier wraps the outside of the modifiers applied in the enclosing scopes,
+    /// and is wrapped by the ones applied in the nested scopes.  That is, the modifier
+    /// closest to the route is the outermost one and sees the request first (e.g.
+    /// `route.modify(a).modify(b)` calls `a` first, and `chain![a, b]` calls `b` first).
+    /// The tag modifiers and the outer modifiers registered by `Scope::with_outer`
+    /// wrap the outside of all scope modifiers.
     pub fn modify<M2>(
         &mut self,
         modifier: M2,
@@ -361,6 +1274,14 @@ where
             .configure(&mut Scope {
                 recognizer: &mut *self.recognizer,
                 scopes: &mut *self.scopes,
+                tags: &mut *self.tags,
+                preflight_endpoint: &mut *self.preflight_endpoint,
+                trailing_slash: &mut *self.trailing_slash,
+                method_override: &mut *self.method_override,
+                path_normalization: &mut *self.path_normalization,
+                mount_point: &mut *self.mount_point,
+                error_observers: &mut *self.error_observers,
+                route_names: &mut *self.route_names,
                 scope_id: self.scope_id,
                 modifier: &Chain::new(self.modifier, modifier),
                 _marker: PhantomData,
@@ -0,0 +1,83 @@
+//! Adapters for embedding `App` into the servers other than `tsukuyomi-server`.
+
+use {
+    super::{config::Concurrency, AppService},
+    crate::{output::ResponseBody, util::Never},
+    futures01::{future::FutureResult, Poll},
+    http::{Request, Response},
+    hyper::body::{Body, Payload},
+    std::error::Error as StdError,
+    tsukuyomi_service::Service,
+};
+
+/// A factory of `HyperService`s, which implements `hyper::service::MakeService`.
+///
+/// The value is created by `App::into_make_service`, and is passed to
+/// `hyper::Server::serve` directly.
+#[derive(Debug)]
+pub struct MakeHyperService<C: Concurrency> {
+    pub(super) service: AppService<C>,
+}
+
+impl<C, Ctx> hyper::service::MakeService<Ctx> for MakeHyperService<C>
+where
+    C: Concurrency,
+{
+    type ReqBody = Body;
+    type ResBody = ResponseBody;
+    type Error = Never;
+    type Service = HyperService<AppService<C>>;
+    type Future = FutureResult<Self::Service, Never>;
+    type MakeError = Never;
+
+    fn make_service(&mut self, _: Ctx) -> Self::Future {
+        futures01::future::ok(HyperService::new(self.service.clone()))
+    }
+}
+
+/// An adapter which converts a `tower_service::Service` into `hyper::service::Service`.
+///
+/// This is useful for serving `AppService` wrapped with some Tower middlewares by hyper.
+#[derive(Debug, Clone)]
+pub struct HyperService<S> {
+    inner: S,
+}
+
+impl<S> HyperService<S> {
+    /// Wraps the specified `Service`.
+    pub fn new(inner: S) -> Self {
+        Self { inner }
+    }
+
+    /// Returns the reference to the inner `Service`.
+    pub fn get_ref(&self) -> &S {
+        &self.inner
+    }
+
+    /// Consumes itself and returns the inner `Service`.
+    pub fn into_inner(self) -> S {
+        self.inner
+    }
+}
+
+impl<S, Bd> hyper::service::Service for HyperService<S>
+where
+    S: Service<Request<Body>, Response = Response<Bd>>,
+    S::Error: Into<Box<dyn StdError + Send + Sync + 'static>>,
+    Bd: Payload,
+{
+    type ReqBody = Body;
+    type ResBody = Bd;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    #[inline]
+    fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+        self.inner.poll_ready()
+    }
+
+    #[inline]
+    fn call(&mut self, request: Request<Body>) -> Self::Future {
+        self.inner.call(request)
+    }
+}
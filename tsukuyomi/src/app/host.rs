@@ -0,0 +1,183 @@
+//! Matching the scopes constrained to the specific hosts.
+
+use {
+    crate::input::localmap::{local_key, LocalData},
+    failure::Error,
+    http::{header::HOST, Request},
+    std::fmt,
+};
+
+/// The pattern of host name registered by `Scope::host`.
+///
+/// The pattern is either an exact host name (e.g. `api.example.com`) or a wildcard
+/// subdomain (e.g. `*.example.com`), compared with the request case-insensitively.
+#[derive(Clone, PartialEq)]
+pub(crate) struct HostPattern {
+    domain: String,
+    wildcard: bool,
+}
+
+impl fmt::Debug for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl fmt::Display for HostPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.wildcard {
+            f.write_str("*.")?;
+        }
+        f.write_str(&self.domain)
+    }
+}
+
+impl HostPattern {
+    pub(crate) fn parse(pattern: &str) -> Result<Self, Error> {
+        let (wildcard, domain) = if pattern.starts_with("*.") {
+            (true, &pattern[2..])
+        } else {
+            (false, pattern)
+        };
+        if domain.is_empty() {
+            failure::bail!("the host name must not be empty");
+        }
+        for label in domain.split('.') {
+            if label.is_empty() {
+                failure::bail!("the host name `{}` contains an empty label", pattern);
+            }
+            if !label
+                .bytes()
+                .all(|b| b.is_ascii_alphanumeric() || b == b'-')
+            {
+                failure::bail!(
+                    "the host name `{}` contains an invalid character (the wildcard `*` is allowed only as the first label)",
+                    pattern
+                );
+            }
+        }
+        Ok(Self {
+            domain: domain.to_ascii_lowercase(),
+            wildcard,
+        })
+    }
+
+    /// Returns whether the host name matches this pattern.
+    pub(crate) fn matches(&self, host: &str) -> bool {
+        if self.wildcard {
+            self.subdomain(host).is_some()
+        } else {
+            host.eq_ignore_ascii_case(&self.domain)
+        }
+    }
+
+    /// Returns the part of host name matched by the wildcard, if any.
+    pub(crate) fn subdomain<'h>(&self, host: &'h str) -> Option<&'h str> {
+        if !self.wildcard || host.len() <= self.domain.len() + 1 {
+            return None;
+        }
+        let (subdomain, domain) = host.split_at(host.len() - self.domain.len() - 1);
+        if domain.starts_with('.') && domain[1..].eq_ignore_ascii_case(&self.domain) {
+            Some(subdomain)
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the host name of the request, without the port.
+///
+/// The authority in the request URI (e.g. the pseudo header `:authority` of HTTP/2)
+/// takes precedence over the header field `Host`.
+pub(crate) fn request_host(request: &Request<()>) -> Option<&str> {
+    let authority = match request.uri().authority_part() {
+        Some(authority) => authority.as_str(),
+        None => request.headers().get(HOST)?.to_str().ok()?,
+    };
+    let authority = authority.rsplit('@').next()?;
+    let host = if authority.starts_with('[') {
+        &authority[..=authority.find(']')?]
+    } else {
+        authority.split(':').next()?
+    };
+    let host = host.trim_end_matches('.');
+    if host.is_empty() {
+        None
+    } else {
+        Some(host)
+    }
+}
+
+/// The part of host name matched by the wildcard subdomain of the scope.
+#[derive(Debug, Clone)]
+pub(crate) struct Subdomain(pub(crate) String);
+
+impl LocalData for Subdomain {
+    local_key! {
+        /// The local key to access the subdomain captured by the matched scope.
+        const KEY: Self;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exact_host() {
+        let pattern = HostPattern::parse("API.example.com").unwrap();
+        assert!(pattern.matches("api.example.com"));
+        assert!(pattern.matches("Api.Example.Com"));
+        assert!(!pattern.matches("www.example.com"));
+        assert!(!pattern.matches("x.api.example.com"));
+        assert_eq!(pattern.subdomain("api.example.com"), None);
+    }
+
+    #[test]
+    fn wildcard_host() {
+        let pattern = HostPattern::parse("*.example.com").unwrap();
+        assert!(pattern.matches("alice.example.com"));
+        assert_eq!(pattern.subdomain("alice.example.com"), Some("alice"));
+        assert_eq!(pattern.subdomain("a.b.Example.com"), Some("a.b"));
+        assert!(!pattern.matches("example.com"));
+        assert!(!pattern.matches(".example.com"));
+        assert!(!pattern.matches("aliceexample.com"));
+    }
+
+    #[test]
+    fn invalid_patterns() {
+        assert!(HostPattern::parse("").is_err());
+        assert!(HostPattern::parse("*.").is_err());
+        assert!(HostPattern::parse("api..example.com").is_err());
+        assert!(HostPattern::parse("api.*.com").is_err());
+        assert!(HostPattern::parse("example.com:8080").is_err());
+    }
+
+    #[test]
+    fn host_of_request() {
+        let host = |request: Request<()>| request_host(&request).map(ToOwned::to_owned);
+        assert_eq!(
+            host(
+                Request::get("/")
+                    .header(HOST, "example.com:8080")
+                    .body(())
+                    .unwrap()
+            ),
+            Some("example.com".into())
+        );
+        assert_eq!(
+            host(
+                Request::get("http://api.example.com/")
+                    .header(HOST, "www.example.com")
+                    .body(())
+                    .unwrap()
+            ),
+            Some("api.example.com".into())
+        );
+        assert_eq!(
+            host(Request::get("/").header(HOST, "[::1]:80").body(()).unwrap()),
+            Some("[::1]".into())
+        );
+        assert_eq!(host(Request::get("/").body(()).unwrap()), None);
+    }
+}
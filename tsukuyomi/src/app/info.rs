@@ -0,0 +1,177 @@
+//! Introspection of the registered routes.
+
+use {
+    super::{config::Concurrency, scope::ScopeId, AppInner},
+    crate::handler::AllowedMethods,
+    std::fmt,
+};
+
+/// The information about a route registered in the application, returned by `App::routes`.
+#[derive(Debug, Clone)]
+pub struct RouteInfo {
+    path: String,
+    allowed_methods: Option<AllowedMethods>,
+    scopes: Vec<String>,
+    host: Option<String>,
+    name: Option<String>,
+    params: Vec<RouteParam>,
+    fallback: bool,
+    method_fallback: bool,
+}
+
+impl RouteInfo {
+    /// Returns the full path pattern of the route (e.g. `/api/posts/:id`).
+    ///
+    /// The fallbacks registered by `path!("*")` are represented with the prefix of
+    /// the scope followed by `*`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    /// Returns the methods allowed by the route, if declared.
+    ///
+    /// `None` means that the route receives the requests with any method.
+    pub fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.allowed_methods.as_ref()
+    }
+
+    /// Returns the prefixes of the scopes containing the route, from the root to the
+    /// innermost one.
+    pub fn scopes(&self) -> &[String] {
+        &self.scopes
+    }
+
+    /// Returns the host pattern which the scope of route is constrained to, if any.
+    pub fn host(&self) -> Option<&str> {
+        self.host.as_ref().map(|host| &**host)
+    }
+
+    /// Returns the name of the route set by `Route::name`, if any.
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_ref().map(|name| &**name)
+    }
+
+    /// Returns the parameters in the path, in the order of appearance.
+    pub fn params(&self) -> &[RouteParam] {
+        &self.params
+    }
+
+    /// Returns whether the route is the fallback of a scope registered by `path!("*")`.
+    pub fn is_fallback(&self) -> bool {
+        self.fallback
+    }
+
+    /// Returns whether the route has the fallback for the disallowed methods, set by
+    /// `Route::fallback`.
+    pub fn has_method_fallback(&self) -> bool {
+        self.method_fallback
+    }
+}
+
+impl fmt::Display for RouteInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.allowed_methods {
+            Some(ref methods) => {
+                let methods = methods.to_header_value();
+                f.write_str(methods.to_str().expect("should be a visible ASCII string"))?;
+            }
+            None => f.write_str("*")?,
+        }
+        write!(f, " {}", self.path)?;
+        if let Some(ref host) = self.host {
+            write!(f, " (host: {})", host)?;
+        }
+        if let Some(ref name) = self.name {
+            write!(f, " (name: {})", name)?;
+        }
+        if self.method_fallback {
+            f.write_str(" (with fallback)")?;
+        }
+        Ok(())
+    }
+}
+
+/// A parameter in the path of route.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteParam {
+    /// A parameter matching a segment, declared as `:name`.
+    Segment(String),
+
+    /// A parameter matching the remaining path, declared as `*name`.
+    Wildcard(String),
+}
+
+impl RouteParam {
+    /// Returns the name of this parameter.
+    pub fn name(&self) -> &str {
+        match self {
+            RouteParam::Segment(name) | RouteParam::Wildcard(name) => name,
+        }
+    }
+
+    /// Returns whether this parameter is a wildcard.
+    pub fn is_wildcard(&self) -> bool {
+        match self {
+            RouteParam::Wildcard(..) => true,
+            RouteParam::Segment(..) => false,
+        }
+    }
+}
+
+/// Collects the information of registered routes, in the order of routes and then fallbacks.
+///
+/// The routes shadowed by the preflight endpoint are excluded.
+pub(super) fn collect_routes<C: Concurrency>(inner: &AppInner<C>) -> Vec<RouteInfo> {
+    let scope_chain = |id: ScopeId| {
+        let scope = inner.scope(id);
+        scope
+            .ancestors()
+            .iter()
+            .chain(Some(&scope.id()))
+            .map(|&id| inner.scope(id).data.prefix.as_str().to_owned())
+            .collect::<Vec<_>>()
+    };
+    let host = |id: ScopeId| inner.scope(id).data.host.as_ref().map(ToString::to_string);
+
+    let routes = inner
+        .endpoints()
+        .filter(|endpoint| !inner.is_shadowed_by_preflight_endpoint(endpoint))
+        .map(|endpoint| RouteInfo {
+            path: endpoint.uri.as_str().to_owned(),
+            allowed_methods: endpoint.allowed_methods.clone(),
+            scopes: scope_chain(endpoint.scope),
+            host: host(endpoint.scope),
+            name: endpoint.name.as_ref().map(|name| name.to_string()),
+            params: params(endpoint.uri.as_str()),
+            fallback: false,
+            method_fallback: endpoint.fallback.is_some(),
+        });
+
+    let fallbacks = inner
+        .scopes
+        .ids()
+        .into_iter()
+        .filter(|&id| inner.scope(id).data.default_handler.is_some())
+        .map(|id| RouteInfo {
+            path: format!("{}*", inner.scope(id).data.prefix.as_str()),
+            allowed_methods: None,
+            scopes: scope_chain(id),
+            host: host(id),
+            name: None,
+            params: vec![],
+            fallback: true,
+            method_fallback: false,
+        });
+
+    routes.chain(fallbacks).collect()
+}
+
+fn params(path: &str) -> Vec<RouteParam> {
+    path.split('/')
+        .filter_map(|segment| match segment.as_bytes().first() {
+            Some(b':') => Some(RouteParam::Segment(segment[1..].to_owned())),
+            Some(b'*') => Some(RouteParam::Wildcard(segment[1..].to_owned())),
+            _ => None,
+        })
+        .collect()
+}
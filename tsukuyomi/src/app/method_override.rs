@@ -0,0 +1,176 @@
+//! Overriding the method of `POST` requests before routing.
+
+use {
+    crate::{
+        config::MethodOverride,
+        error::Error,
+        input::{
+            body::RequestBody,
+            localmap::{local_key, LocalData, LocalMap},
+        },
+    },
+    bytes::BytesMut,
+    futures01::{Async, Poll, Stream},
+    http::{header::CONTENT_TYPE, Method, Request},
+    hyper::body::{Body, Payload},
+    url::form_urlencoded,
+};
+
+/// The name of header field that specifies the effective method.
+const OVERRIDE_HEADER: &str = "x-http-method-override";
+
+/// The name of form field that specifies the effective method.
+const OVERRIDE_FIELD: &[u8] = b"_method=";
+
+/// The maximum length of the first form field, inspected for `_method`.
+const MAX_FIELD_LEN: usize = 64;
+
+/// The method of request before overridden by `MethodOverride`.
+#[derive(Debug, Clone)]
+pub(crate) struct OriginalMethod(pub(crate) Method);
+
+impl LocalData for OriginalMethod {
+    local_key! {
+        /// The local key to access the original method of request.
+        const KEY: Self;
+    }
+}
+
+/// Overrides the method of request by the header field, or starts peeking the form
+/// if the method may be specified by the form field.
+pub(super) fn start(
+    policy: &MethodOverride,
+    request: &mut Request<()>,
+    locals: &mut LocalMap,
+) -> Result<Option<PeekForm>, Error> {
+    if request.method() != Method::POST {
+        return Ok(None);
+    }
+
+    if policy.header {
+        if let Some(value) = request.headers().get(OVERRIDE_HEADER) {
+            let value = value.as_bytes().to_owned();
+            override_method(policy, request, locals, &value)?;
+            return Ok(None);
+        }
+    }
+
+    let is_urlencoded = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .map_or(false, |mime| {
+            mime.trim()
+                .eq_ignore_ascii_case("application/x-www-form-urlencoded")
+        });
+    if !policy.form_field || !is_urlencoded {
+        return Ok(None);
+    }
+
+    let body = RequestBody::take_from(locals).ok_or_else(|| {
+        crate::error::internal_server_error("the request body has already been stolen")
+    })?;
+    Ok(Some(PeekForm {
+        body: Some(body),
+        buf: BytesMut::new(),
+    }))
+}
+
+/// An asynchronous task that peeks the first field of the form to find `_method`.
+///
+/// Only the chunks up to the end of the first field are received before routing,
+/// and the remaining part of the body is left to the handler.
+#[allow(missing_debug_implementations)]
+pub(super) struct PeekForm {
+    body: Option<RequestBody>,
+    buf: BytesMut,
+}
+
+impl PeekForm {
+    /// Polls the form, and then overrides the method of request by its first field
+    /// if it is `_method`.
+    ///
+    /// The received chunks are stored again as the request body, followed by the
+    /// rest of the original one.
+    pub(super) fn poll(
+        &mut self,
+        policy: &MethodOverride,
+        request: &mut Request<()>,
+        locals: &mut LocalMap,
+    ) -> Poll<(), Error> {
+        let mut end_of_stream = false;
+        let method = loop {
+            if let Some(method) = self.peek(end_of_stream) {
+                break method;
+            }
+            let body = self.body.as_mut().expect("the body should be available");
+            match futures01::try_ready!(body.poll_data()) {
+                Some(chunk) => self.buf.extend_from_slice(&chunk),
+                None => end_of_stream = true,
+            }
+        };
+
+        let head = self.buf.take().freeze();
+        let body = self.body.take().expect("the body should be available");
+        let body = if end_of_stream {
+            RequestBody::from(head)
+        } else if head.is_empty() {
+            body
+        } else {
+            let head = futures01::stream::once(Ok(hyper::Chunk::from(head)));
+            RequestBody::from(Body::wrap_stream(head.chain(body)))
+        };
+        body.insert_into(locals);
+
+        if let Some(method) = method {
+            override_method(policy, request, locals, method.as_bytes())?;
+        }
+        Ok(Async::Ready(()))
+    }
+
+    /// Determines the method specified by the first field of the received form,
+    /// or returns `None` if more chunks are required.
+    ///
+    /// The form is not inspected further if its first field is not `_method` or
+    /// exceeds `MAX_FIELD_LEN`.
+    #[allow(clippy::option_option)]
+    fn peek(&self, end_of_stream: bool) -> Option<Option<String>> {
+        let len = std::cmp::min(self.buf.len(), OVERRIDE_FIELD.len());
+        if self.buf[..len] != OVERRIDE_FIELD[..len] {
+            return Some(None);
+        }
+        let field = match self.buf.iter().position(|&b| b == b'&') {
+            Some(pos) => &self.buf[..pos],
+            None if end_of_stream => &self.buf[..],
+            None if self.buf.len() > MAX_FIELD_LEN => return Some(None),
+            None => return None,
+        };
+        if field.len() > MAX_FIELD_LEN || len < OVERRIDE_FIELD.len() {
+            return Some(None);
+        }
+        Some(
+            form_urlencoded::parse(field)
+                .next()
+                .map(|(_, value)| value.into_owned()),
+        )
+    }
+}
+
+fn override_method(
+    policy: &MethodOverride,
+    request: &mut Request<()>,
+    locals: &mut LocalMap,
+    value: &[u8],
+) -> Result<(), Error> {
+    let method = Method::from_bytes(&value.to_ascii_uppercase())
+        .map_err(|_| crate::error::bad_request("invalid method to override"))?;
+    if !policy.allowed_methods.contains(&method) {
+        return Err(crate::error::bad_request(format!(
+            "the method cannot be overridden with `{}`",
+            method
+        )));
+    }
+    OriginalMethod(std::mem::replace(request.method_mut(), method)).insert_into(locals);
+    Ok(())
+}
@@ -0,0 +1,242 @@
+//! Stripping the mount point of application from the request path before routing.
+
+use {
+    super::rewrite::replace_path,
+    crate::{config::MountPoint, uri::Uri},
+    failure::Error,
+    http::{request::Parts, StatusCode},
+    std::borrow::Cow,
+};
+
+/// The name of header field that specifies the prefix stripped by the reverse proxy.
+const FORWARDED_PREFIX_HEADER: &str = "x-forwarded-prefix";
+
+/// Validates the prefix of `MountPoint`, and normalizes it without the trailing slash.
+///
+/// The root prefix `/` is normalized into the empty string.
+pub(super) fn normalize(mut policy: MountPoint) -> Result<MountPoint, Error> {
+    let prefix = Uri::parse(&policy.prefix)?;
+    if prefix.capture_names().is_some() {
+        failure::bail!(
+            "the mount point cannot contain any parameter: `{}`",
+            prefix.as_str()
+        );
+    }
+    if prefix.as_str().contains(&['?', '#'][..]) {
+        failure::bail!(
+            "the mount point cannot contain the query or fragment: `{}`",
+            prefix.as_str()
+        );
+    }
+    policy.prefix = Cow::Owned(prefix.as_str().trim_end_matches('/').to_owned());
+    Ok(policy)
+}
+
+/// The prefix of the URIs generated by the application.
+///
+/// The value is stored in the extension map of request so that the responders can
+/// refer to it.
+#[derive(Debug, Clone)]
+pub(crate) struct Mounted {
+    pub(crate) prefix: String,
+}
+
+impl Mounted {
+    /// Returns the specified path with the prefix added.
+    pub(crate) fn to_external(&self, path: &str) -> String {
+        format!("{}{}", self.prefix, path)
+    }
+}
+
+/// Strips the mount point from the request path, and records the original path.
+///
+/// The request path outside of the mount point is rejected with `404 Not Found`,
+/// except for the asterisk-form (i.e. `OPTIONS *`).
+pub(super) fn strip(policy: &MountPoint, parts: &mut Parts) -> Result<(), crate::Error> {
+    let raw_path = parts.uri.path().to_owned();
+    if !raw_path.starts_with('/') {
+        return Ok(());
+    }
+    let path = match raw_path.get(policy.prefix.len()..) {
+        Some(rest) if raw_path.starts_with(&*policy.prefix) => match rest {
+            "" => "/",
+            rest if rest.starts_with('/') => rest,
+            _ => return Err(StatusCode::NOT_FOUND.into()),
+        },
+        _ => return Err(StatusCode::NOT_FOUND.into()),
+    };
+
+    let mut prefix = match forwarded_prefix(policy, parts) {
+        Some(forwarded) => forwarded.to_owned(),
+        None => String::new(),
+    };
+    prefix += &policy.prefix;
+
+    if path.len() != raw_path.len() {
+        replace_path(parts, path);
+    }
+
+    parts.extensions.insert(Mounted { prefix });
+    Ok(())
+}
+
+/// Returns the value of `X-Forwarded-Prefix` without the trailing slash, if trusted.
+fn forwarded_prefix<'a>(policy: &MountPoint, parts: &'a Parts) -> Option<&'a str> {
+    if !policy.forwarded_prefix {
+        return None;
+    }
+    let value = parts.headers.get(FORWARDED_PREFIX_HEADER)?.to_str().ok()?;
+    let value = value.split(',').next()?.trim().trim_end_matches('/');
+    if value.is_empty() {
+        return None;
+    }
+    // avoid the location interpreted as a network-path reference (e.g. `//example.com`).
+    if !value.starts_with('/') || value.starts_with("//") {
+        return None;
+    }
+    if !is_valid_path(value) {
+        return None;
+    }
+    Some(value)
+}
+
+/// Returns whether the value consists only of the characters allowed in the path
+/// of URI, without the dot segments.
+///
+/// The backslashes are rejected since some user agents treat them as slashes (e.g.
+/// `/\example.com`), as well as the control characters.
+fn is_valid_path(value: &str) -> bool {
+    let bytes = value.as_bytes();
+    for (i, &b) in bytes.iter().enumerate() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => {}
+            b'-' | b'.' | b'_' | b'~' | b'/' | b':' | b'@' => {}
+            b'!' | b'$' | b'&' | b'\'' | b'(' | b')' | b'*' | b'+' | b',' | b';' | b'=' => {}
+            b'%' => {
+                let is_hex = |j: usize| bytes.get(j).map_or(false, u8::is_ascii_hexdigit);
+                if !is_hex(i + 1) || !is_hex(i + 2) {
+                    return false;
+                }
+            }
+            _ => return false,
+        }
+    }
+    value
+        .split('/')
+        .all(|segment| segment != "." && segment != "..")
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::app::rewrite::RawPath,
+        http::{Method, Request},
+    };
+
+    fn parts(mut request: http::request::Builder) -> Parts {
+        request.body(()).unwrap().into_parts().0
+    }
+
+    fn mount(policy: MountPoint) -> MountPoint {
+        normalize(policy).unwrap()
+    }
+
+    #[test]
+    fn normalize_prefix() {
+        assert_eq!(mount(MountPoint::new("/service-a/")).prefix, "/service-a");
+        assert_eq!(mount(MountPoint::new("/")).prefix, "");
+        assert!(normalize(MountPoint::new("service-a")).is_err());
+        assert!(normalize(MountPoint::new("/:tenant")).is_err());
+        assert!(normalize(MountPoint::new("/a//b")).is_err());
+    }
+
+    #[test]
+    fn strip_prefix() {
+        let policy = mount(MountPoint::new("/service-a"));
+
+        let mut p = parts(Request::get("/service-a/posts?page=2"));
+        assert!(strip(&policy, &mut p).is_ok());
+        assert_eq!(p.uri, "/posts?page=2");
+        assert_eq!(p.extensions.get::<RawPath>().unwrap().0, "/service-a/posts");
+        let mounted = p.extensions.get::<Mounted>().unwrap();
+        assert_eq!(mounted.to_external("/login"), "/service-a/login");
+
+        let mut p = parts(Request::get("http://example.com/service-a"));
+        assert!(strip(&policy, &mut p).is_ok());
+        assert_eq!(p.uri, "http://example.com/");
+
+        let mut p = parts({
+            let mut request = Request::builder();
+            request.method(Method::OPTIONS).uri("*");
+            request
+        });
+        assert!(strip(&policy, &mut p).is_ok());
+        assert_eq!(p.uri, "*");
+
+        assert!(strip(&policy, &mut parts(Request::get("/service-ab"))).is_err());
+        assert!(strip(&policy, &mut parts(Request::get("/posts"))).is_err());
+    }
+
+    #[test]
+    fn forwarded_prefix_header() {
+        let policy = mount(MountPoint::new("/").forwarded_prefix(true));
+        let mut p = parts({
+            let mut request = Request::get("/posts");
+            request.header(FORWARDED_PREFIX_HEADER, "/service-a/");
+            request
+        });
+        assert!(strip(&policy, &mut p).is_ok());
+        assert_eq!(p.uri, "/posts");
+        assert_eq!(
+            p.extensions.get::<Mounted>().unwrap().to_external("/login"),
+            "/service-a/login"
+        );
+
+        let mut p = parts({
+            let mut request = Request::get("/posts");
+            request.header(FORWARDED_PREFIX_HEADER, "//evil.example.com");
+            request
+        });
+        assert!(strip(&policy, &mut p).is_ok());
+        assert_eq!(
+            p.extensions.get::<Mounted>().unwrap().to_external("/login"),
+            "/login"
+        );
+
+        for value in &[
+            "/\\evil.example.com",
+            "/a\\b",
+            "/a\tb",
+            "/a b",
+            "/a%2",
+            "/a/../b",
+            "/a?b",
+            "/a#b",
+        ] {
+            let mut p = parts({
+                let mut request = Request::get("/posts");
+                request.header(FORWARDED_PREFIX_HEADER, *value);
+                request
+            });
+            assert!(strip(&policy, &mut p).is_ok());
+            assert_eq!(
+                p.extensions.get::<Mounted>().unwrap().to_external("/login"),
+                "/login",
+                "{}",
+                value
+            );
+        }
+
+        let mut p = parts({
+            let mut request = Request::get("/posts");
+            request.header(FORWARDED_PREFIX_HEADER, "/service%20a/v1.2/~user");
+            request
+        });
+        assert!(strip(&policy, &mut p).is_ok());
+        assert_eq!(
+            p.extensions.get::<Mounted>().unwrap().to_external("/login"),
+            "/service%20a/v1.2/~user/login"
+        );
+    }
+}
@@ -0,0 +1,143 @@
+//! Normalizing the request path before routing.
+
+use {super::rewrite::replace_path, crate::config::PathNormalization, http::request::Parts};
+
+/// Normalizes the request path according to the policy, and records the original path.
+///
+/// The path going up beyond the root is rejected with `400 Bad Request`.
+pub(super) fn normalize(policy: &PathNormalization, parts: &mut Parts) -> Result<(), crate::Error> {
+    let path = parts.uri.path();
+    if !path.starts_with('/') {
+        return Ok(());
+    }
+    let normalized = normalize_path(policy, path)
+        .ok_or_else(|| crate::error::bad_request("the request path goes up beyond the root"))?;
+    if normalized != path {
+        replace_path(parts, &normalized);
+    }
+    Ok(())
+}
+
+/// Returns the normalized form of path, or `None` if the path escapes the root.
+fn normalize_path(policy: &PathNormalization, path: &str) -> Option<String> {
+    let mut segments: Vec<&str> = vec![];
+    let mut iter = path[1..].split('/').peekable();
+    while let Some(segment) = iter.next() {
+        let is_last = iter.peek().is_none();
+        if policy.merge_slashes && segment.is_empty() && !is_last {
+            continue;
+        }
+        if policy.dot_segments {
+            if let Some(dots) = dot_segment(segment) {
+                if dots == 2 {
+                    segments.pop()?;
+                }
+                // the dot segment at the end is replaced with the trailing slash.
+                if is_last {
+                    segments.push("");
+                }
+                continue;
+            }
+        }
+        segments.push(segment);
+    }
+    Some(format!("/{}", segments.join("/")))
+}
+
+/// Returns the number of dots if the segment is `.` or `..`, including the forms
+/// whose dots are percent-encoded.
+fn dot_segment(segment: &str) -> Option<usize> {
+    let mut rest = segment.as_bytes();
+    let mut dots = 0;
+    while !rest.is_empty() {
+        if rest[0] == b'.' {
+            rest = &rest[1..];
+        } else if rest.len() >= 3 && rest[..2] == *b"%2" && rest[2].eq_ignore_ascii_case(&b'e') {
+            rest = &rest[3..];
+        } else {
+            return None;
+        }
+        dots += 1;
+    }
+    match dots {
+        1 | 2 => Some(dots),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn normalized(path: &str) -> Option<String> {
+        normalize_path(&PathNormalization::new(), path)
+    }
+
+    fn some(path: &str) -> Option<String> {
+        Some(path.to_owned())
+    }
+
+    #[test]
+    fn duplicate_slashes() {
+        assert_eq!(normalized("/a//b"), some("/a/b"));
+        assert_eq!(normalized("//a///b//"), some("/a/b/"));
+        assert_eq!(normalized("//"), some("/"));
+        assert_eq!(normalized("/"), some("/"));
+        assert_eq!(
+            normalize_path(&PathNormalization::new().merge_slashes(false), "/a//b"),
+            some("/a//b")
+        );
+    }
+
+    #[test]
+    fn dot_segments() {
+        assert_eq!(normalized("/a/./b"), some("/a/b"));
+        assert_eq!(normalized("/a/b/../c"), some("/a/c"));
+        assert_eq!(normalized("/a/b/."), some("/a/b/"));
+        assert_eq!(normalized("/a/b/.."), some("/a/"));
+        assert_eq!(normalized("/a/../..b"), some("/..b"));
+        assert_eq!(normalized("/."), some("/"));
+        assert_eq!(normalized("/a/.../b"), some("/a/.../b"));
+        assert_eq!(
+            normalize_path(&PathNormalization::new().dot_segments(false), "/../a"),
+            some("/../a")
+        );
+    }
+
+    #[test]
+    fn percent_encoded_dot_segments() {
+        assert_eq!(normalized("/a/%2e/b"), some("/a/b"));
+        assert_eq!(normalized("/a/b/%2e%2e/c"), some("/a/c"));
+        assert_eq!(normalized("/a/b/.%2E/c"), some("/a/c"));
+        assert_eq!(normalized("/a/b/%2E./c"), some("/a/c"));
+    }
+
+    #[test]
+    fn escaping_the_root() {
+        for path in &[
+            "/..",
+            "/../etc/passwd",
+            "/a/../../etc/passwd",
+            "/static/%2e%2e/%2e%2e/etc/passwd",
+            "/static/.%2e/%2E./etc/passwd",
+            "//..//..//etc/passwd",
+        ] {
+            assert_eq!(normalized(path), None, "{}", path);
+        }
+    }
+
+    #[test]
+    fn not_dot_segments() {
+        // the encoded slashes, double encodings and path parameters are left as they are.
+        for path in &[
+            "/static/..%2fsecret",
+            "/static/%2e%2e%2fsecret",
+            "/static/%252e%252e/secret",
+            "/admin/..;/secret",
+            "/static/..\\secret",
+            "/static/%2e%2e%5csecret",
+        ] {
+            assert_eq!(normalized(path), some(path), "{}", path);
+        }
+    }
+}
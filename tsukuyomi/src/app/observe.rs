@@ -0,0 +1,103 @@
+use {
+    crate::{
+        error::{Error, ErrorEvent},
+        output::ResponseBody,
+    },
+    http::{Request, Response},
+    std::{error::Error as StdError, fmt, sync::Arc},
+};
+
+type Observer = dyn Fn(&ErrorEvent<'_>) + Send + Sync + 'static;
+
+/// The functions registered by `Scope::observe_errors`.
+#[derive(Clone, Default)]
+pub(super) struct ErrorObservers(Vec<Arc<Observer>>);
+
+impl fmt::Debug for ErrorObservers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ErrorObservers")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
+
+impl ErrorObservers {
+    pub(super) fn push<F>(&mut self, f: F)
+    where
+        F: Fn(&ErrorEvent<'_>) + Send + Sync + 'static,
+    {
+        self.0.push(Arc::new(f));
+    }
+
+    fn notify(&self, event: &ErrorEvent<'_>) {
+        for f in &self.0 {
+            f(event);
+        }
+    }
+
+    /// Converts the error into the response, and passes it to the observers along
+    /// with the status code of the converted response.
+    pub(super) fn observe(&self, err: Error, request: &Request<()>) -> Response<ResponseBody> {
+        if self.0.is_empty() {
+            return err.into_response(request);
+        }
+
+        let mut chain = vec![err.to_string()];
+        if let Some(err) = err.downcast_ref::<failure::Error>() {
+            chain.extend(err.iter_causes().map(ToString::to_string));
+        }
+        let response = err.into_response(request);
+
+        self.notify(&ErrorEvent {
+            request,
+            status: response.status(),
+            chain: &chain,
+            committed: false,
+        });
+
+        response
+    }
+
+    /// Registers the hook that passes the errors from the streaming body to the
+    /// observers, which occur after the response head has been sent.
+    pub(super) fn observe_stream(
+        &self,
+        request: &Request<()>,
+        response: &mut Response<ResponseBody>,
+    ) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let status = response.status();
+        response.body_mut().on_stream_error(|| {
+            let observers = self.clone();
+            let request = clone_head(request);
+            move |err: &dyn StdError| {
+                let mut chain = vec![err.to_string()];
+                let mut source = err.source();
+                while let Some(err) = source {
+                    chain.push(err.to_string());
+                    source = err.source();
+                }
+                observers.notify(&ErrorEvent {
+                    request: &request,
+                    status,
+                    chain: &chain,
+                    committed: true,
+                });
+            }
+        });
+    }
+}
+
+/// Copies the head of request, since the errors from the response body are
+/// reported after the request has gone.
+fn clone_head(request: &Request<()>) -> Request<()> {
+    let mut head = Request::new(());
+    *head.method_mut() = request.method().clone();
+    *head.uri_mut() = request.uri().clone();
+    *head.version_mut() = request.version();
+    *head.headers_mut() = request.headers().clone();
+    head
+}
@@ -0,0 +1,590 @@
+//! The authorization preflight, which evaluates the requests without executing the endpoints.
+
+use {
+    super::{
+        config::Concurrency,
+        host::request_host,
+        recognizer::Captures,
+        rewrite::RawPath,
+        service::{insert_route_data, insert_scope_data, rewrite_path},
+        AppInner, Endpoint,
+    },
+    crate::{
+        config::{PreflightLimits, TrailingSlash},
+        error::Error,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, Metadata},
+        input::{
+            body::RequestBody,
+            localmap::{local_key, LocalData, LocalMap},
+            param::Params,
+            Cookies, Input,
+        },
+        output::ResponseBody,
+        util::Never,
+    },
+    bytes::BytesMut,
+    cookie::CookieJar,
+    futures01::Future,
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Extensions, Method, Request, Response, StatusCode, Uri,
+    },
+    hyper::body::Payload,
+    serde::{Deserialize, Serialize},
+    std::{borrow::Cow, fmt, marker::PhantomData, mem, sync::Arc},
+};
+
+/// The result of the authorization preflight.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Outcome {
+    /// The request would reach the endpoint.
+    Allowed,
+
+    /// The request would be rejected before reaching the endpoint.
+    Denied {
+        /// The layer which rejected the request.
+        layer: Layer,
+        /// The status code of the response that the layer would return.
+        status: StatusCode,
+    },
+
+    /// The request was not evaluated, because the matched route does not take part
+    /// in the preflight (see `Route::preflight`).
+    NotEvaluated,
+}
+
+impl Outcome {
+    /// Returns whether the request would reach the endpoint.
+    pub fn is_allowed(&self) -> bool {
+        match self {
+            Outcome::Allowed => true,
+            Outcome::Denied { .. } | Outcome::NotEvaluated => false,
+        }
+    }
+}
+
+/// The layer which rejected the request during the preflight.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Layer {
+    /// The router, i.e. no route matches the path or the route does not accept the method.
+    Routing,
+
+    /// The modifier with the specified label.
+    ///
+    /// The label is given to the modifier by the application, e.g. by
+    /// `Participate::label`, so the internal structure of the application is not
+    /// exposed to the clients of the preflight endpoint.
+    Modifier(Cow<'static, str>),
+
+    /// A modifier which could not be identified, e.g. because a handler between it
+    /// and the route does not forward `Handler::metadata`.
+    Unknown,
+}
+
+impl fmt::Display for Layer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Layer::Routing => f.write_str("routing"),
+            Layer::Modifier(name) => f.write_str(name),
+            Layer::Unknown => f.write_str("unknown"),
+        }
+    }
+}
+
+/// The marker attached to the routes by `Route::preflight`.
+#[derive(Debug)]
+pub(crate) struct PreflightTarget;
+
+impl PreflightTarget {
+    pub(crate) fn is_attached(metadata: &Metadata) -> bool {
+        metadata.contains::<Self>()
+    }
+}
+
+/// The request-local data stored while the request is evaluated by the preflight.
+#[derive(Debug)]
+pub struct Preflight {
+    reached: bool,
+    layer: Option<Cow<'static, str>>,
+}
+
+impl LocalData for Preflight {
+    local_key! {
+        /// The local key to manage the state of preflight.
+        const KEY: Self;
+    }
+}
+
+impl Preflight {
+    fn new() -> Self {
+        Self {
+            reached: false,
+            layer: None,
+        }
+    }
+
+    /// Returns an error if the current request is being evaluated by the preflight.
+    ///
+    /// The request is regarded as having reached the endpoint, and the returned error
+    /// is discarded by the preflight.
+    pub(crate) fn stop(locals: &mut LocalMap) -> crate::Result<()> {
+        match Self::get_mut(locals) {
+            Some(preflight) => {
+                preflight.reached = true;
+                Err(StatusCode::NO_CONTENT.into())
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn record(locals: &mut LocalMap, layer: Cow<'static, str>) {
+        if let Some(preflight) = Self::get_mut(locals) {
+            if !preflight.reached && preflight.layer.is_none() {
+                preflight.layer = Some(layer);
+            }
+        }
+    }
+}
+
+/// A `Handler` that reports the label of modifier to the preflight when the request
+/// does not reach the endpoint.
+///
+/// The label is only recorded for the routes marked by `Route::preflight`, so that
+/// the other routes do not pay for looking up the state of preflight.
+#[allow(missing_debug_implementations)]
+pub struct Recorded<H> {
+    inner: H,
+    label: Option<Cow<'static, str>>,
+}
+
+impl<H> Recorded<H>
+where
+    H: Handler,
+{
+    pub(crate) fn new(inner: H, label: Cow<'static, str>) -> Self {
+        let label = if PreflightTarget::is_attached(inner.metadata()) {
+            Some(label)
+        } else {
+            None
+        };
+        Self { inner, label }
+    }
+}
+
+impl<H> Handler for Recorded<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = H::Error;
+    type Handle = HandleRecorded<H::Handle>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleRecorded {
+            inner: self.inner.handle(),
+            label: self.label.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleRecorded<Fut> {
+    inner: Fut,
+    label: Option<Cow<'static, str>>,
+}
+
+impl<Fut> TryFuture for HandleRecorded<Fut>
+where
+    Fut: TryFuture,
+{
+    type Ok = Fut::Ok;
+    type Error = Fut::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let polled = self.inner.poll_ready(input);
+        if let Some(ref label) = self.label {
+            match polled {
+                Ok(Async::NotReady) => {}
+                _ => Preflight::record(input.locals, label.clone()),
+            }
+        }
+        polled
+    }
+}
+
+/// The state of evaluation of a request by the preflight.
+pub(super) struct Evaluation<C: Concurrency> {
+    endpoint: Option<Arc<Endpoint<C>>>,
+    captures: Option<Captures>,
+    cookie_jar: Option<CookieJar>,
+    response_headers: Option<HeaderMap>,
+    locals: LocalMap,
+    handle: Option<C::Handle>,
+}
+
+impl<C: Concurrency> Evaluation<C> {
+    pub(super) fn new() -> Self {
+        let mut locals = LocalMap::default();
+        Preflight::new().insert_into(&mut locals);
+        Self {
+            endpoint: None,
+            captures: None,
+            cookie_jar: None,
+            response_headers: None,
+            locals,
+            handle: None,
+        }
+    }
+
+    pub(super) fn poll(&mut self, inner: &AppInner<C>, request: &Request<()>) -> Async<Outcome> {
+        if self.handle.is_none() {
+            let host = request_host(request);
+            let path = request.uri().path();
+            let method = request.method();
+            let endpoint = match inner.find_endpoint(host, method, path, &mut self.captures) {
+                Ok(endpoint) => endpoint.clone(),
+                Err(..) => {
+                    // the requests redirected by `TrailingSlash::Redirect` never reach the endpoint.
+                    self.captures = None;
+                    match inner.find_alternate_endpoint(host, method, path, &mut self.captures) {
+                        Some((endpoint, _)) if inner.trailing_slash == TrailingSlash::Merge => {
+                            endpoint.clone()
+                        }
+                        _ => return Async::Ready(denied(Layer::Routing, StatusCode::NOT_FOUND)),
+                    }
+                }
+            };
+            if !endpoint.allows(method) && endpoint.fallback.is_none() {
+                return Async::Ready(denied(Layer::Routing, StatusCode::METHOD_NOT_ALLOWED));
+            }
+            if !PreflightTarget::is_attached(&endpoint.metadata) {
+                return Async::Ready(Outcome::NotEvaluated);
+            }
+            insert_scope_data(&mut self.locals, &inner.scope(endpoint.scope).data, host);
+            insert_route_data(&mut self.locals, &endpoint);
+            self.handle = Some(C::handle(endpoint.handler_for(method)));
+            self.endpoint = Some(endpoint);
+        }
+
+        let polled = {
+            let Self {
+                ref endpoint,
+                ref captures,
+                ref mut cookie_jar,
+                ref mut locals,
+                ref mut response_headers,
+                ref mut handle,
+            } = *self;
+            let params = endpoint.as_ref().map(|endpoint| Params {
+                path: request.uri().path(),
+                names: endpoint.uri.capture_names(),
+                captures: captures.as_ref(),
+            });
+            C::poll_ready(
+                handle.as_mut().expect("the handle should be initialized"),
+                &mut Input {
+                    request,
+                    params: &params,
+                    cookies: &mut Cookies::new(cookie_jar, request),
+                    locals,
+                    response_headers,
+                    _marker: PhantomData,
+                },
+            )
+        };
+
+        let status = match polled {
+            Ok(Async::NotReady) => return Async::NotReady,
+            Ok(Async::Ready(response)) => response.status(),
+            Err(err) => err.into_response(request).status(),
+        };
+        let preflight = Preflight::get(&self.locals).expect("the preflight state should be stored");
+        if preflight.reached {
+            return Async::Ready(Outcome::Allowed);
+        }
+        let layer = preflight
+            .layer
+            .clone()
+            .map_or(Layer::Unknown, Layer::Modifier);
+        Async::Ready(denied(layer, status))
+    }
+}
+
+fn denied(layer: Layer, status: StatusCode) -> Outcome {
+    Outcome::Denied { layer, status }
+}
+
+/// A future that evaluates a request by the preflight, created by `App::authorize`.
+#[must_use = "futures do nothing unless polled"]
+pub struct Authorize<C: Concurrency> {
+    pub(super) inner: Arc<AppInner<C>>,
+    pub(super) request: Request<()>,
+    pub(super) evaluation: Evaluation<C>,
+}
+
+impl<C: Concurrency> fmt::Debug for Authorize<C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Authorize")
+            .field("request", &self.request)
+            .finish()
+    }
+}
+
+impl<C: Concurrency> Future for Authorize<C> {
+    type Item = Outcome;
+    type Error = Never;
+
+    fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
+        Ok(self.evaluation.poll(&self.inner, &self.request))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct BatchItem {
+    method: String,
+    path: String,
+}
+
+#[derive(Debug, Serialize)]
+struct BatchResult {
+    method: String,
+    path: String,
+    allowed: bool,
+    evaluated: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    layer: Option<String>,
+}
+
+/// The state of the built-in endpoint which evaluates a batch of requests.
+///
+/// Each item is evaluated as a separate request which copies the version and
+/// the headers of the request to the endpoint, and its path is rewritten in the
+/// same way as the requests to the application.  The extensions of the request
+/// cannot be copied, so they are lent to the item being evaluated and returned
+/// to the request once the batch is completed.
+pub(super) struct Batch<C: Concurrency> {
+    limits: PreflightLimits,
+    body: Option<RequestBody>,
+    buf: BytesMut,
+    items: Vec<(Method, Uri, BatchItem)>,
+    current: Option<(BatchItem, Request<()>, Evaluation<C>)>,
+    extensions: Option<Extensions>,
+    raw_path: Option<RawPath>,
+    results: Vec<BatchResult>,
+}
+
+impl<C: Concurrency> Batch<C> {
+    pub(super) fn start(
+        request: &Request<()>,
+        locals: &mut LocalMap,
+        limits: &PreflightLimits,
+    ) -> crate::Result<Self> {
+        let content_length = request
+            .headers()
+            .get(header::CONTENT_LENGTH)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse::<u64>().ok());
+        if content_length.map_or(false, |len| len > limits.max_body_size as u64) {
+            return Err(payload_too_large());
+        }
+        let body = RequestBody::take_from(locals)
+            .ok_or_else(|| crate::error::internal_server_error("the request body has gone"))?;
+        Ok(Self {
+            limits: limits.clone(),
+            body: Some(body),
+            buf: BytesMut::new(),
+            items: vec![],
+            current: None,
+            extensions: None,
+            raw_path: None,
+            results: vec![],
+        })
+    }
+
+    fn parse(&mut self) -> crate::Result<()> {
+        let items: Vec<BatchItem> =
+            serde_json::from_slice(&self.buf[..]).map_err(crate::error::bad_request)?;
+        if items.len() > self.limits.max_items {
+            return Err(crate::error::custom(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                format!(
+                    "the batch contains more than {} requests",
+                    self.limits.max_items
+                ),
+            ));
+        }
+        for item in items.into_iter().rev() {
+            let method = item
+                .method
+                .parse::<Method>()
+                .map_err(crate::error::bad_request)?;
+            let uri = item
+                .path
+                .parse::<Uri>()
+                .map_err(crate::error::bad_request)?;
+            self.items.push((method, uri, item));
+        }
+        Ok(())
+    }
+
+    pub(super) fn poll(
+        &mut self,
+        inner: &AppInner<C>,
+        request: &mut Request<()>,
+    ) -> Poll<Response<ResponseBody>, Error> {
+        if let Some(ref mut body) = self.body {
+            while let Some(chunk) =
+                futures01::try_ready!(body.poll_data().map_err(crate::error::bad_request))
+            {
+                if self.buf.len() + chunk.len() > self.limits.max_body_size {
+                    return Err(payload_too_large());
+                }
+                self.buf.extend_from_slice(&chunk);
+            }
+        }
+        if self.body.take().is_some() {
+            self.parse()?;
+            let mut extensions = mem::replace(request.extensions_mut(), Extensions::new());
+            self.raw_path = extensions.remove::<RawPath>();
+            self.extensions = Some(extensions);
+        }
+
+        loop {
+            if self.current.is_none() {
+                match self.items.pop() {
+                    Some((method, uri, item)) => {
+                        let extensions = self
+                            .extensions
+                            .take()
+                            .expect("the extensions should be returned");
+                        let mut sub_request = sub_request(request, method, uri, extensions);
+
+                        // the path of sub-request is rewritten in the same way as the
+                        // requests to the application, before routing.
+                        if let Err(err) = rewrite_sub_request(inner, &mut sub_request) {
+                            let status = err.into_response(&sub_request).status();
+                            self.finish(item, sub_request, denied(Layer::Routing, status));
+                            continue;
+                        }
+                        self.current = Some((item, sub_request, Evaluation::new()));
+                    }
+                    None => break,
+                }
+            }
+
+            let outcome = {
+                let (_, ref sub_request, ref mut evaluation) = self
+                    .current
+                    .as_mut()
+                    .expect("the evaluation should be in progress");
+                match evaluation.poll(inner, sub_request) {
+                    Async::Ready(outcome) => outcome,
+                    Async::NotReady => return Ok(Async::NotReady),
+                }
+            };
+            let (item, sub_request, _) = self
+                .current
+                .take()
+                .expect("the evaluation should be in progress");
+            self.finish(item, sub_request, outcome);
+        }
+
+        if let Some(mut extensions) = self.extensions.take() {
+            if let Some(raw_path) = self.raw_path.take() {
+                extensions.insert(raw_path);
+            }
+            *request.extensions_mut() = extensions;
+        }
+
+        let body =
+            serde_json::to_vec(&self.results).map_err(crate::error::internal_server_error)?;
+        Ok(Async::Ready(
+            Response::builder()
+                .header(
+                    header::CONTENT_TYPE,
+                    HeaderValue::from_static("application/json"),
+                )
+                .body(body.into())
+                .expect("should be a valid response"),
+        ))
+    }
+
+    /// Records the outcome of the item, and takes back the extensions lent to it.
+    fn finish(&mut self, item: BatchItem, mut sub_request: Request<()>, outcome: Outcome) {
+        let mut extensions = mem::replace(sub_request.extensions_mut(), Extensions::new());
+        extensions.remove::<RawPath>();
+        self.extensions = Some(extensions);
+
+        self.results.push(match outcome {
+            Outcome::Allowed => BatchResult {
+                method: item.method,
+                path: item.path,
+                allowed: true,
+                evaluated: true,
+                status: None,
+                layer: None,
+            },
+            Outcome::Denied { layer, status } => BatchResult {
+                method: item.method,
+                path: item.path,
+                allowed: false,
+                evaluated: true,
+                status: Some(status.as_u16()),
+                layer: Some(layer.to_string()),
+            },
+            Outcome::NotEvaluated => BatchResult {
+                method: item.method,
+                path: item.path,
+                allowed: false,
+                evaluated: false,
+                status: None,
+                layer: None,
+            },
+        });
+    }
+}
+
+/// Rewrites the path of the sub-request by `PathNormalization` and `MountPoint`.
+fn rewrite_sub_request<C: Concurrency>(
+    inner: &AppInner<C>,
+    sub_request: &mut Request<()>,
+) -> crate::Result<()> {
+    let (mut parts, ()) = mem::replace(sub_request, Request::new(())).into_parts();
+    let rewritten = rewrite_path(inner, &mut parts);
+    *sub_request = Request::from_parts(parts, ());
+    rewritten
+}
+
+/// Creates the request evaluated for an item of the batch.
+fn sub_request(
+    request: &Request<()>,
+    method: Method,
+    uri: Uri,
+    extensions: Extensions,
+) -> Request<()> {
+    let mut sub_request = Request::new(());
+    *sub_request.method_mut() = method;
+    *sub_request.uri_mut() = uri;
+    *sub_request.version_mut() = request.version();
+    *sub_request.headers_mut() = request.headers().clone();
+    *sub_request.extensions_mut() = extensions;
+    sub_request
+}
+
+fn payload_too_large() -> Error {
+    crate::error::custom(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "the body of the batch is too large",
+    )
+}
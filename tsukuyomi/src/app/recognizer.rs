@@ -25,6 +25,15 @@ impl Captures {
     }
 }
 
+/// The error that the inserted path conflicts with a path registered before.
+#[derive(Debug, failure::Fail)]
+#[fail(display = "{}", reason)]
+pub struct Conflict {
+    /// The index of conflicting path registered before.
+    pub index: usize,
+    reason: &'static str,
+}
+
 #[derive(Clone, Debug, PartialEq)]
 pub struct Candidates(IndexSet<usize>);
 
@@ -44,6 +53,7 @@ pub struct Recognizer<T> {
     inner: IndexMap<String, T>,
     tree: Tree,
     asterisk: Option<usize>,
+    reject_empty_params: bool,
 }
 
 impl<T> Default for Recognizer<T> {
@@ -52,11 +62,20 @@ impl<T> Default for Recognizer<T> {
             inner: IndexMap::default(),
             tree: Tree::default(),
             asterisk: None,
+            reject_empty_params: false,
         }
     }
 }
 
 impl<T> Recognizer<T> {
+    /// Sets whether the parameters reject the empty segment (e.g. `/posts/` for
+    /// the path `/posts/:id`).
+    ///
+    /// By default, a parameter matches the empty segment.
+    pub fn reject_empty_params(&mut self, enabled: bool) {
+        self.reject_empty_params = enabled;
+    }
+
     pub fn insert(&mut self, path: &str, data: T) -> Result<(), Error> {
         if !path.is_ascii() {
             failure::bail!("The path must be a sequence of ASCII characters");
@@ -68,6 +87,7 @@ impl<T> Recognizer<T> {
             }
             self.asterisk = Some(self.inner.len());
         } else {
+            validate_wildcards(path.as_ref())?;
             InsertContext {
                 path: path.as_ref(),
                 index: self.inner.len(),
@@ -95,15 +115,36 @@ impl<T> Recognizer<T> {
             RecognizeContext {
                 path: path.as_ref(),
                 captures,
+                reject_empty_params: self.reject_empty_params,
             } //
             .visit_tree(&self.tree)?
         };
         Ok(self.get(index).expect("should be success"))
     }
 
+    /// Returns the value registered with the exactly same path.
+    pub fn get_by_path(&self, path: &str) -> Option<&T> {
+        self.inner.get(path)
+    }
+
+    /// Returns the value registered with the exactly same path.
+    pub fn get_by_path_mut(&mut self, path: &str) -> Option<&mut T> {
+        self.inner.get_mut(path)
+    }
+
     pub fn get(&self, index: usize) -> Option<&T> {
         Some(self.inner.get_index(index)?.1)
     }
+
+    /// Returns an iterator over the registered values, in the order of insertion.
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = &'a T> + 'a {
+        self.inner.values()
+    }
+
+    /// Returns a mutable iterator over the registered values, in the order of insertion.
+    pub fn iter_mut<'a>(&'a mut self) -> impl Iterator<Item = &'a mut T> + 'a {
+        self.inner.values_mut()
+    }
 }
 
 #[derive(Clone, PartialEq)]
@@ -111,6 +152,9 @@ enum NodeKind {
     Static(Vec<u8>),
     Param,
     CatchAll,
+    /// The static segments following a catch-all parameter (e.g. `/info/refs` of
+    /// `/repos/*path/info/refs`), placed only as a child of `CatchAll`.
+    Suffix(Vec<u8>),
 }
 
 #[cfg_attr(tarpaulin, skip)]
@@ -123,6 +167,10 @@ impl fmt::Debug for NodeKind {
                 .finish(),
             NodeKind::Param => f.debug_tuple("Param").finish(),
             NodeKind::CatchAll => f.debug_tuple("CatchAll").finish(),
+            NodeKind::Suffix(ref s) => f
+                .debug_tuple("Suffix")
+                .field(&String::from_utf8_lossy(s))
+                .finish(),
         }
     }
 }
@@ -140,6 +188,14 @@ struct Tree {
     root: Option<Node>,
 }
 
+const CONFLICT_SAME_PATHS: &str = "both routes match the same paths";
+const CONFLICT_STATIC_AND_PARAM: &str =
+    "a static segment cannot be placed at the same position as a parameter";
+const CONFLICT_CATCH_ALL: &str =
+    "a catch-all parameter cannot be placed at the same position as other segments";
+const CONFLICT_SUFFIX: &str =
+    "the segments following a catch-all parameter cannot end with those of another route";
+
 #[derive(Debug)]
 struct InsertContext<'a> {
     path: &'a [u8],
@@ -188,14 +244,24 @@ impl<'a> InsertContext<'a> {
                     return Ok(());
                 }
 
-                Some(b'*') if n.children.is_empty() => failure::bail!("'catch-all' conflict"),
+                Some(b'*') if n.children.is_empty() => {
+                    return Err(self.conflict(&n.candidates, CONFLICT_CATCH_ALL))
+                }
 
                 Some(b':') | Some(b'*') => {
-                    if n.children.iter().any(|ch| match ch.kind {
+                    if let Some(ch) = n.children.iter().find(|ch| match ch.kind {
                         NodeKind::Static(..) => true,
                         _ => false,
                     }) {
-                        failure::bail!("A static node has already inserted at wildcard position.");
+                        return Err(self.conflict(&ch.candidates, CONFLICT_STATIC_AND_PARAM));
+                    }
+
+                    let kind = match self.path[offset] {
+                        b'*' => NodeKind::CatchAll,
+                        _ => NodeKind::Param,
+                    };
+                    if n.children[0].kind != kind {
+                        return Err(self.conflict(&n.children[0].candidates, CONFLICT_CATCH_ALL));
                     }
 
                     n.candidates.insert(self.index);
@@ -205,6 +271,9 @@ impl<'a> InsertContext<'a> {
                         break 'walk;
                     }
                     offset = end;
+                    if n.kind == NodeKind::CatchAll {
+                        return self.insert_suffix(n, offset);
+                    }
                 }
 
                 Some(&c) => {
@@ -218,8 +287,8 @@ impl<'a> InsertContext<'a> {
                                     break;
                                 }
                             }
-                            NodeKind::Param | NodeKind::CatchAll => {
-                                failure::bail!("A wildcard node has already inserted.")
+                            NodeKind::Param | NodeKind::CatchAll | NodeKind::Suffix(..) => {
+                                return Err(self.conflict(&ch.candidates, CONFLICT_STATIC_AND_PARAM))
                             }
                         }
                     }
@@ -242,8 +311,8 @@ impl<'a> InsertContext<'a> {
             }
         }
 
-        if n.children.iter().any(|ch| ch.kind == NodeKind::CatchAll) {
-            failure::bail!("catch-all conflict");
+        if let Some(ch) = n.children.iter().find(|ch| ch.kind == NodeKind::CatchAll) {
+            return Err(self.conflict(&ch.candidates, CONFLICT_CATCH_ALL));
         }
 
         self.set_leaf(n)?;
@@ -269,8 +338,11 @@ impl<'a> InsertContext<'a> {
             // Insert a normal node
             if pos < path.len() {
                 let index = find_wildcard_begin(path, pos);
-                n.children
-                    .push(self.new_node(NodeKind::Static(path[pos..index].into())));
+                let segments = path[pos..index].into();
+                n.children.push(self.new_node(match n.kind {
+                    NodeKind::CatchAll => NodeKind::Suffix(segments),
+                    _ => NodeKind::Static(segments),
+                }));
                 n = { n }.children.iter_mut().last().unwrap();
                 pos = index;
             }
@@ -280,14 +352,50 @@ impl<'a> InsertContext<'a> {
         Ok(())
     }
 
+    /// Inserts the static segments following the catch-all parameter `n`.
+    ///
+    /// Since the catch-all parameter matches greedily, the suffixes that end with
+    /// another one are rejected as ambiguous (e.g. `/refs` and `/info/refs`).
+    fn insert_suffix(&self, n: &mut Node, offset: usize) -> Result<(), Error> {
+        let suffix = &self.path[offset..];
+        for ch in &n.children {
+            if let NodeKind::Suffix(ref s) = ch.kind {
+                if s[..] == *suffix {
+                    return Err(self.conflict(&ch.candidates, CONFLICT_SAME_PATHS));
+                }
+                if s.ends_with(suffix) || suffix.ends_with(s) {
+                    return Err(self.conflict(&ch.candidates, CONFLICT_SUFFIX));
+                }
+            }
+        }
+        let mut ch = self.new_node(NodeKind::Suffix(suffix.into()));
+        ch.leaf = Some(self.index);
+        n.children.push(ch);
+        n.candidates.insert(self.index);
+        Ok(())
+    }
+
     fn set_leaf(&self, n: &mut Node) -> Result<(), Error> {
-        if n.leaf.is_some() {
-            failure::bail!("normal path conflict");
+        if let Some(index) = n.leaf {
+            return Err(Conflict {
+                index,
+                reason: CONFLICT_SAME_PATHS,
+            }
+            .into());
         }
         n.leaf = Some(self.index);
         Ok(())
     }
 
+    /// Creates the error that the path conflicts with one of the candidates.
+    fn conflict(&self, candidates: &Candidates, reason: &'static str) -> Error {
+        let index = candidates
+            .iter()
+            .find(|&index| index != self.index)
+            .expect("the candidates should contain a path registered before");
+        Conflict { index, reason }.into()
+    }
+
     fn new_node(&self, kind: NodeKind) -> Node {
         Node {
             kind,
@@ -324,6 +432,7 @@ pub enum RecognizeError<'a> {
 struct RecognizeContext<'a> {
     path: &'a [u8],
     captures: &'a mut Option<Captures>,
+    reject_empty_params: bool,
 }
 
 impl<'a> RecognizeContext<'a> {
@@ -349,6 +458,9 @@ impl<'a> RecognizeContext<'a> {
                         .iter()
                         .position(|&b| b == b'/')
                         .unwrap_or(self.path.len() - offset);
+                    if span == 0 && self.reject_empty_params {
+                        return Err(RecognizeError::PartiallyMatched(&n.candidates));
+                    }
                     self.captures
                         .get_or_insert_with(Default::default)
                         .params
@@ -362,12 +474,27 @@ impl<'a> RecognizeContext<'a> {
                     }
                 }
                 NodeKind::CatchAll => {
+                    // the routes with the trailing segments take precedence over the one
+                    // ending with the catch-all parameter, which never matches the empty
+                    // string in the middle of path.
+                    let rest = &self.path[offset..];
+                    let (end, leaf) = n
+                        .children
+                        .iter()
+                        .find_map(|ch| match ch.kind {
+                            NodeKind::Suffix(ref s)
+                                if rest.len() > s.len() && rest.ends_with(s) =>
+                            {
+                                Some((self.path.len() - s.len(), ch.leaf))
+                            }
+                            _ => None,
+                        })
+                        .unwrap_or((self.path.len(), n.leaf));
                     self.captures.get_or_insert_with(Default::default).wildcard =
-                        Some((offset, self.path.len()));
-                    return n
-                        .leaf //
-                        .ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates));
+                        Some((offset, end));
+                    return leaf.ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates));
                 }
+                NodeKind::Suffix(..) => unreachable!("the suffix is matched by its parent"),
             }
 
             n = n
@@ -376,6 +503,7 @@ impl<'a> RecognizeContext<'a> {
                 .find(|ch| match ch.kind {
                     NodeKind::Static(ref s) => self.path.get(offset).map_or(false, |&c| s[0] == c),
                     NodeKind::Param | NodeKind::CatchAll => true,
+                    NodeKind::Suffix(..) => false,
                 }) //
                 .ok_or_else(|| RecognizeError::PartiallyMatched(&n.candidates))?;
         }
@@ -409,6 +537,16 @@ fn find_wildcard_begin(path: &[u8], offset: usize) -> usize {
         .map_or_else(|| path.len(), |i| i + offset)
 }
 
+/// Checks the positions of wildcards in the path before modifying the tree.
+fn validate_wildcards(path: &[u8]) -> Result<(), Error> {
+    let mut offset = find_wildcard_begin(path, 0);
+    while offset < path.len() {
+        let end = find_wildcard_end(path, offset)?;
+        offset = find_wildcard_begin(path, end);
+    }
+    Ok(())
+}
+
 fn find_wildcard_end(path: &[u8], offset: usize) -> Result<usize, Error> {
     debug_assert!(path[offset] == b':' || path[offset] == b'*');
     if offset > 0 && path[offset - 1] != b'/' {
@@ -426,8 +564,8 @@ fn find_wildcard_end(path: &[u8], offset: usize) -> Result<usize, Error> {
     if end == 1 {
         failure::bail!("empty wildcard name");
     }
-    if path[offset] == b'*' && offset + end < path.len() {
-        failure::bail!("a 'catch-all' param must be located at the end of path");
+    if path[offset] == b'*' && find_wildcard_begin(path, offset + end) < path.len() {
+        failure::bail!("a 'catch-all' param can be followed only by static segments");
     }
     Ok(offset + end)
 }
@@ -554,6 +692,83 @@ mod tests {
         );
     }
 
+    #[test]
+    fn case12_param_empty_segment() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert("/posts", 0).unwrap();
+        recognizer.insert("/posts/:id", 1).unwrap();
+
+        assert_eq!(recognizer.recognize("/posts", &mut None), Ok(&0));
+        assert_eq!(recognizer.recognize("/posts/", &mut None), Ok(&1));
+
+        recognizer.reject_empty_params(true);
+        assert_eq!(recognizer.recognize("/posts", &mut None), Ok(&0));
+        assert_eq!(
+            recognizer.recognize("/posts/", &mut None),
+            Err(RecognizeError::PartiallyMatched(&Candidates(indexset![1])))
+        );
+    }
+
+    #[test]
+    fn case13_wildcard_in_middle() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert("/repos/*path/info/refs", 0).unwrap();
+        recognizer
+            .insert("/repos/*path/git-upload-pack", 1)
+            .unwrap();
+        recognizer.insert("/repos/*path", 2).unwrap();
+
+        let mut captures = None;
+        assert_eq!(
+            recognizer.recognize("/repos/a/b.git/info/refs", &mut captures),
+            Ok(&0)
+        );
+        assert_eq!(
+            captures,
+            Some(Captures {
+                params: vec![],
+                wildcard: Some((7, 14)),
+            })
+        );
+
+        // the catch-all parameter matches greedily.
+        let mut captures = None;
+        assert_eq!(
+            recognizer.recognize("/repos/info/refs/info/refs", &mut captures),
+            Ok(&0)
+        );
+        assert_eq!(captures.unwrap().wildcard, Some((7, 16)));
+
+        let mut captures = None;
+        assert_eq!(
+            recognizer.recognize("/repos/a/git-upload-pack", &mut captures),
+            Ok(&1)
+        );
+        assert_eq!(captures.unwrap().wildcard, Some((7, 8)));
+
+        // the paths without the trailing segments fall back to the trailing catch-all.
+        let mut captures = None;
+        assert_eq!(recognizer.recognize("/repos/a/info", &mut captures), Ok(&2));
+        assert_eq!(captures.unwrap().wildcard, Some((7, 13)));
+        assert_eq!(recognizer.recognize("/repos/info/refs", &mut None), Ok(&2));
+    }
+
+    #[test]
+    fn case14_wildcard_in_middle_without_trailing() {
+        let mut recognizer = Recognizer::default();
+        recognizer.insert("/repos/*path/info/refs", 0).unwrap();
+
+        assert_eq!(
+            recognizer.recognize("/repos/a/info", &mut None),
+            Err(RecognizeError::PartiallyMatched(&Candidates(indexset![0])))
+        );
+        // the catch-all parameter in the middle never matches the empty string.
+        assert_eq!(
+            recognizer.recognize("/repos//info/refs", &mut None),
+            Err(RecognizeError::PartiallyMatched(&Candidates(indexset![0])))
+        );
+    }
+
     #[test]
     fn case8_partially_matched() {
         let mut recognizer = Recognizer::default();
@@ -612,7 +827,7 @@ mod tests {
 #[cfg(test)]
 mod tests_tree {
     use {
-        super::{Candidates, Node, NodeKind, Recognizer},
+        super::{Candidates, Conflict, Node, NodeKind, Recognizer},
         indexmap::indexset,
     };
 
@@ -784,6 +999,39 @@ mod tests_tree {
         }
     );
 
+    t!(
+        catch_all_case3,
+        [
+            "/repos/*path/info/refs",
+            "/repos/*path",
+            "/repos/*path/HEAD"
+        ],
+        Node {
+            kind: NodeKind::Static("/repos/".into()),
+            leaf: None,
+            candidates: Candidates(indexset![0, 1, 2]),
+            children: vec![Node {
+                kind: NodeKind::CatchAll, // "*path"
+                leaf: Some(1),
+                candidates: Candidates(indexset![0, 1, 2]),
+                children: vec![
+                    Node {
+                        kind: NodeKind::Suffix("/info/refs".into()),
+                        leaf: Some(0),
+                        candidates: Candidates(indexset![0]),
+                        children: vec![],
+                    },
+                    Node {
+                        kind: NodeKind::Suffix("/HEAD".into()),
+                        leaf: Some(2),
+                        candidates: Candidates(indexset![2]),
+                        children: vec![],
+                    },
+                ],
+            }],
+        }
+    );
+
     #[test]
     fn failcase1_conflict_static_and_param() {
         let mut recognizer = Recognizer::default();
@@ -839,4 +1087,52 @@ mod tests_tree {
         assert!(recognizer.insert("/path/to", ()).is_ok());
         assert!(recognizer.insert("/path/to", ()).is_err());
     }
+
+    #[test]
+    fn failcase9_conflict_param_with_different_kind_3() {
+        let mut recognizer = Recognizer::default();
+        assert!(recognizer.insert("/:id/edit", ()).is_ok());
+        assert!(recognizer.insert("/*path", ()).is_err());
+        assert!(recognizer.insert("/*path/edit", ()).is_err());
+    }
+
+    #[test]
+    fn failcase10_conflict_ambiguous_suffix() {
+        let mut recognizer = Recognizer::default();
+        assert!(recognizer.insert("/repos/*path/info/refs", ()).is_ok());
+        assert!(recognizer.insert("/repos/*path/refs", ()).is_err());
+        assert!(recognizer.insert("/repos/*path/x/info/refs", ()).is_err());
+        assert!(recognizer.insert("/repos/*path/info/refs", ()).is_err());
+        assert!(recognizer.insert("/repos/*path/info/refs/", ()).is_ok());
+    }
+
+    #[test]
+    fn failcase11_param_after_catch_all() {
+        let mut recognizer = Recognizer::default();
+        assert!(recognizer.insert("/repos/*path/:name", ()).is_err());
+        assert!(recognizer.insert("/repos/*path/info/*rest", ()).is_err());
+    }
+
+    #[test]
+    fn conflicting_index() {
+        let conflict = |paths: &[&str]| {
+            let mut recognizer = Recognizer::default();
+            let (last, paths) = paths.split_last().unwrap();
+            for path in paths {
+                recognizer.insert(path, ()).unwrap();
+            }
+            let err = recognizer.insert(last, ()).unwrap_err();
+            err.downcast_ref::<Conflict>()
+                .map(|conflict| conflict.index)
+        };
+        assert_eq!(conflict(&["/posts", "/users/:id", "/users/:name"]), Some(1));
+        assert_eq!(conflict(&["/users/:id", "/posts", "/users/new"]), Some(0));
+        assert_eq!(conflict(&["/users/new", "/users/:id"]), Some(0));
+        assert_eq!(conflict(&["/files/", "/about", "/files/*path"]), Some(0));
+        assert_eq!(conflict(&["/files/*path", "/files/"]), Some(0));
+        assert_eq!(
+            conflict(&["/r/*path/a/b", "/r/*path/c", "/r/*path/b"]),
+            Some(0)
+        );
+    }
 }
@@ -0,0 +1,32 @@
+//! Rewriting the request path before routing.
+
+use http::{
+    request::Parts,
+    uri::{PathAndQuery, Uri},
+};
+
+/// The path of request before rewritten by `PathNormalization` or `MountPoint`.
+///
+/// The value is stored in the extension map of request only if the path is rewritten.
+#[derive(Debug, Clone)]
+pub(crate) struct RawPath(pub(crate) String);
+
+/// Replaces the path of request URI, with the query and the original path preserved.
+pub(super) fn replace_path(parts: &mut Parts, path: &str) {
+    if parts.extensions.get::<RawPath>().is_none() {
+        let raw_path = RawPath(parts.uri.path().to_owned());
+        parts.extensions.insert(raw_path);
+    }
+
+    let path_and_query = match parts.uri.query() {
+        Some(query) => format!("{}?{}", path, query),
+        None => path.to_owned(),
+    };
+    let mut uri_parts = std::mem::replace(&mut parts.uri, Uri::default()).into_parts();
+    uri_parts.path_and_query = Some(
+        path_and_query
+            .parse::<PathAndQuery>()
+            .expect("the rewritten path should be valid"),
+    );
+    parts.uri = Uri::from_parts(uri_parts).expect("the rewritten URI should be valid");
+}
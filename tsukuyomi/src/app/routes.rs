@@ -0,0 +1,136 @@
+use {
+    crate::uri::Uri,
+    failure::Error,
+    serde::Serialize,
+    serde_json::{Map, Value},
+    std::{borrow::Cow, collections::HashMap, fmt, sync::Arc},
+    url::{
+        form_urlencoded,
+        percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET, PATH_SEGMENT_ENCODE_SET},
+    },
+};
+
+/// The table of named routes, registered by `Route::name`.
+///
+/// The table is inserted into the extension map of each request so that the
+/// responders (e.g. `output::redirect::to_route`) can build the URIs from it.
+#[derive(Clone, Default)]
+pub(crate) struct RouteNames {
+    routes: Arc<HashMap<Cow<'static, str>, Uri>>,
+    required: Vec<Cow<'static, str>>,
+}
+
+impl fmt::Debug for RouteNames {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RouteNames")
+            .field("routes", &self.routes)
+            .finish()
+    }
+}
+
+impl RouteNames {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    pub(super) fn insert(&mut self, name: Cow<'static, str>, uri: Uri) -> Result<(), Error> {
+        if self.routes.contains_key(&name) {
+            failure::bail!("the route name `{}` has already been used", name);
+        }
+        Arc::make_mut(&mut self.routes).insert(name, uri);
+        Ok(())
+    }
+
+    pub(super) fn require(&mut self, name: Cow<'static, str>) {
+        self.required.push(name);
+    }
+
+    /// Checks that all of the names required by `Scope::require_route` are registered.
+    pub(super) fn validate(&mut self) -> Result<(), Error> {
+        let routes = &self.routes;
+        let missing: Vec<_> = self
+            .required
+            .drain(..)
+            .filter(|name| !routes.contains_key(name))
+            .map(|name| format!("`{}`", name))
+            .collect();
+        if !missing.is_empty() {
+            failure::bail!(
+                "the required routes are not registered: {}",
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    /// Builds the URI of the named route, with the parameters filled by `params`.
+    ///
+    /// `params` must be serialized into either a map, whose fields are filled by
+    /// their names, or a sequence, whose elements are filled in order.  The fields
+    /// of map which do not correspond to any parameter are appended as the query
+    /// string.  `()` can be used for the routes without parameters.
+    pub(crate) fn url_for<P>(&self, name: &str, params: &P) -> Result<String, Error>
+    where
+        P: ?Sized + Serialize,
+    {
+        let uri = self
+            .routes
+            .get(name)
+            .ok_or_else(|| failure::format_err!("no route named `{}`", name))?;
+
+        let (mut named, mut positional) = match serde_json::to_value(params)? {
+            Value::Null => (Map::new(), vec![]),
+            Value::Object(map) => (map, vec![]),
+            Value::Array(values) => (Map::new(), values),
+            _ => failure::bail!("the route parameters must be a map or a sequence"),
+        };
+        positional.reverse();
+
+        let mut path = String::new();
+        for segment in uri.as_str().split('/').skip(1) {
+            path.push('/');
+            let (kind, name) = match segment.as_bytes().first() {
+                Some(b':') | Some(b'*') => segment.split_at(1),
+                _ => {
+                    path += segment;
+                    continue;
+                }
+            };
+            let value = named
+                .remove(name)
+                .or_else(|| positional.pop())
+                .ok_or_else(|| failure::format_err!("missing the route parameter `{}`", name))?;
+            let value = param_to_string(name, value)?;
+            if kind == "*" {
+                path.extend(utf8_percent_encode(&value, DEFAULT_ENCODE_SET));
+            } else {
+                path.extend(utf8_percent_encode(&value, PATH_SEGMENT_ENCODE_SET));
+            }
+        }
+
+        if !positional.is_empty() {
+            failure::bail!("too many route parameters for `{}`", uri);
+        }
+        if named.is_empty() {
+            return Ok(path);
+        }
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        for (key, value) in named {
+            if value.is_null() {
+                continue;
+            }
+            let value = param_to_string(&key, value)?;
+            query.append_pair(&key, &value);
+        }
+        Ok(crate::output::uri::join(&path, &query.finish()))
+    }
+}
+
+fn param_to_string(name: &str, value: Value) -> Result<String, Error> {
+    match value {
+        Value::String(s) => Ok(s),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => failure::bail!("the route parameter `{}` must be a scalar value", name),
+    }
+}
@@ -3,12 +3,12 @@ use {
     std::ops::{Index, IndexMut},
 };
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub(super) struct ScopeId {
     inner: ScopeIdInner,
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 enum ScopeIdInner {
     Root,
     Index(usize),
@@ -58,6 +58,14 @@ impl<T> Scopes<T> {
 
         Ok(id)
     }
+
+    /// Returns the list of scope IDs, in the order that the parents come before their children.
+    pub(super) fn ids(&self) -> Vec<ScopeId> {
+        Some(self.root.id)
+            .into_iter()
+            .chain(self.nodes.iter().map(|node| node.id))
+            .collect()
+    }
 }
 
 impl<T> Index<ScopeId> for Scopes<T> {
@@ -1,6 +1,16 @@
 use {
-    super::{config::Concurrency, recognizer::Captures, AppInner, Endpoint},
+    super::{
+        config::Concurrency,
+        host::{request_host, Subdomain},
+        method_override::{self, PeekForm},
+        mount::{self, Mounted},
+        normalize,
+        preflight::Batch,
+        recognizer::Captures,
+        AppInner, Endpoint, RouteMetadata, ScopeData,
+    },
     crate::{
+        config::TrailingSlash,
         input::{
             body::RequestBody,
             localmap::{LocalData, LocalMap},
@@ -14,7 +24,7 @@ use {
     futures01::{Async, Future, Poll},
     http::{
         header::{self, HeaderMap, HeaderValue},
-        Request, Response,
+        Method, Request, Response, StatusCode,
     },
     hyper::body::Payload,
     std::{fmt, marker::PhantomData, sync::Arc},
@@ -37,6 +47,14 @@ pub struct AppService<C: Concurrency> {
     pub(super) inner: Arc<AppInner<C>>,
 }
 
+impl<C: Concurrency> Clone for AppService<C> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
 impl<C, Bd> Service<Request<Bd>> for AppService<C>
 where
     C: Concurrency,
@@ -53,7 +71,11 @@ where
 
     #[inline]
     fn call(&mut self, request: Request<Bd>) -> Self::Future {
-        let (parts, body) = request.into_parts();
+        let (mut parts, body) = request.into_parts();
+        if !self.inner.route_names.is_empty() {
+            parts.extensions.insert(self.inner.route_names.clone());
+        }
+        let rewritten = rewrite_path(&self.inner, &mut parts);
 
         let mut locals = LocalMap::default();
         RequestBody::from(body).insert_into(&mut locals);
@@ -66,7 +88,10 @@ where
             locals,
             endpoint: None,
             captures: None,
-            state: AppFutureState::Init,
+            state: match rewritten {
+                Ok(()) => AppFutureState::Init,
+                Err(err) => AppFutureState::Rejected(Some(err)),
+            },
         }
     }
 }
@@ -87,7 +112,11 @@ pub struct AppFuture<C: Concurrency> {
 
 enum AppFutureState<C: Concurrency> {
     Init,
+    Rejected(Option<crate::Error>),
+    PeekingForm(Box<PeekForm>),
+    Routing,
     InFlight(C::Handle),
+    Preflight(Box<Batch<C>>),
     Done,
 }
 
@@ -95,12 +124,22 @@ impl<C: Concurrency> fmt::Debug for AppFutureState<C> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AppFutureState::Init => f.debug_struct("Init").finish(),
+            AppFutureState::Rejected(..) => f.debug_struct("Rejected").finish(),
+            AppFutureState::PeekingForm(..) => f.debug_struct("PeekingForm").finish(),
+            AppFutureState::Routing => f.debug_struct("Routing").finish(),
             AppFutureState::InFlight(..) => f.debug_struct("InFlight").finish(),
+            AppFutureState::Preflight(..) => f.debug_struct("Preflight").finish(),
             AppFutureState::Done => f.debug_struct("Done").finish(),
         }
     }
 }
 
+/// The result of routing the request.
+enum Recognized<C: Concurrency> {
+    Handle(C::Handle),
+    Redirect(String),
+}
+
 macro_rules! input {
     ($self:expr) => {
         &mut Input {
@@ -125,22 +164,93 @@ macro_rules! input {
 }
 
 impl<C: Concurrency> AppFuture<C> {
-    fn process_recognize(&mut self) -> Result<C::Handle, crate::Error> {
+    fn process_recognize(&mut self) -> Result<Recognized<C>, crate::Error> {
         self.endpoint = None;
         self.captures = None;
 
-        match self
+        let host = request_host(&self.request);
+        let method = self.request.method();
+        let path = self.request.uri().path();
+        let endpoint = match self
             .inner
-            .find_endpoint(self.request.uri().path(), &mut self.captures)
+            .find_endpoint(host, method, path, &mut self.captures)
         {
-            Ok(endpoint) => {
-                self.endpoint = Some(endpoint.clone());
-                Ok(C::handle(&endpoint.handler))
+            Ok(endpoint) => endpoint,
+            Err(scope) => {
+                self.captures = None;
+                match self
+                    .inner
+                    .find_alternate_endpoint(host, method, path, &mut self.captures)
+                {
+                    Some((endpoint, _)) if self.inner.trailing_slash == TrailingSlash::Merge => {
+                        endpoint
+                    }
+                    Some((_, alternate)) => return Ok(Recognized::Redirect(alternate)),
+                    None => {
+                        return match self.inner.find_fallback(scope.id(), host, path) {
+                            Some((scope, fallback)) => {
+                                insert_scope_data(&mut self.locals, &scope.data, host);
+                                Ok(Recognized::Handle(C::handle(fallback)))
+                            }
+                            None => Err(http::StatusCode::NOT_FOUND.into()),
+                        };
+                    }
+                }
             }
-            Err(scope) => match self.inner.find_default_handler(scope.id()) {
-                Some(fallback) => Ok(C::handle(fallback)),
-                None => Err(http::StatusCode::NOT_FOUND.into()),
-            },
+        };
+
+        self.endpoint = Some(endpoint.clone());
+        insert_scope_data(
+            &mut self.locals,
+            &self.inner.scope(endpoint.scope).data,
+            host,
+        );
+        insert_route_data(&mut self.locals, endpoint);
+        Ok(Recognized::Handle(C::handle(endpoint.handler_for(method))))
+    }
+
+    /// Creates the response redirecting to the canonical form of the path.
+    fn redirect_to(&self, mut location: String) -> Response<ResponseBody> {
+        if let Some(mounted) = self.request.extensions().get::<Mounted>() {
+            location = mounted.to_external(&location);
+        }
+        if let Some(query) = self.request.uri().query() {
+            location.push('?');
+            location.push_str(query);
+        }
+        let status = match *self.request.method() {
+            Method::GET | Method::HEAD => StatusCode::MOVED_PERMANENTLY,
+            _ => StatusCode::PERMANENT_REDIRECT,
+        };
+        Response::builder()
+            .status(status)
+            .header(header::LOCATION, location)
+            .body(ResponseBody::empty())
+            .expect("should be a valid response")
+    }
+
+    fn process_preflight(&mut self) -> Result<Box<Batch<C>>, crate::Error> {
+        let (_, ref limits) = *self
+            .inner
+            .preflight_endpoint
+            .as_ref()
+            .expect("the preflight endpoint should be registered");
+        Batch::start(&self.request, &mut self.locals, limits).map(Box::new)
+    }
+
+    /// Appends `Allow` to the response of `405 Method Not Allowed`, with the methods
+    /// allowed by all endpoints registered with the resource of matched endpoint.
+    fn insert_allow_header(&self, output: &mut Response<ResponseBody>) {
+        if let Some(allowed_methods) = self
+            .endpoint
+            .as_ref()
+            .and_then(|endpoint| self.inner.resource_allowed_methods(endpoint))
+        {
+            output
+                .headers_mut()
+                .entry(header::ALLOW)
+                .expect("never fails")
+                .or_insert_with(|| allowed_methods.to_header_value());
         }
     }
 
@@ -162,8 +272,38 @@ impl<C: Concurrency> AppFuture<C> {
             }
         }
 
+        // append the default header fields registered within the scope.
+        crate::output::headers::apply_default_headers(&mut self.locals, output);
+
+        // the trailers are available only on HTTP/2.
+        output
+            .body_mut()
+            .allow_trailers(self.request.version() == http::Version::HTTP_2);
+
+        // let the server know the truncation of the streaming body, e.g. for the access log.
+        if let Some(truncation) = output.body().truncation() {
+            output.extensions_mut().insert(truncation);
+        }
+
+        // these responses never have a body, even if the responder supplied one.
+        // `1xx` and `204 No Content` must not have Content-Length (RFC 7230, section 3.3.2).
+        let status = output.status();
+        if status.is_informational() || status == StatusCode::NO_CONTENT {
+            output.body_mut().discard_without_length();
+            output.headers_mut().remove(header::CONTENT_LENGTH);
+        } else if status == StatusCode::NOT_MODIFIED {
+            output.body_mut().discard();
+        }
+
         // append the value of Content-Length to the response header if missing.
-        if let Some(len) = output.body().content_length() {
+        //
+        // The one of `304 Not Modified` is left as it is since it describes the
+        // representation to be sent, not the empty body.
+        if let Some(len) = output
+            .body()
+            .content_length()
+            .filter(|_| status != StatusCode::NOT_MODIFIED)
+        {
             output
                 .headers_mut()
                 .entry(header::CONTENT_LENGTH)
@@ -174,6 +314,51 @@ impl<C: Concurrency> AppFuture<C> {
                     unsafe { HeaderValue::from_shared_unchecked(len.to_string().into()) }
                 });
         }
+
+        // the response to HEAD has no body, but keeps Content-Length of the one to GET.
+        if self.request.method() == Method::HEAD {
+            output.body_mut().discard();
+        }
+    }
+}
+
+/// Normalizes the request path and strips the mount point from it, before routing.
+pub(super) fn rewrite_path<C: Concurrency>(
+    inner: &AppInner<C>,
+    parts: &mut http::request::Parts,
+) -> Result<(), crate::Error> {
+    if let Some(ref policy) = inner.path_normalization {
+        normalize::normalize(policy, parts)?;
+    }
+    if let Some(ref policy) = inner.mount_point {
+        mount::strip(policy, parts)?;
+    }
+    Ok(())
+}
+
+pub(super) fn insert_route_data<C: Concurrency>(locals: &mut LocalMap, endpoint: &Endpoint<C>) {
+    if !endpoint.metadata.is_empty() || endpoint.metadata.has_configs() {
+        RouteMetadata(endpoint.metadata.clone()).insert_into(locals);
+    }
+}
+
+pub(super) fn insert_scope_data<C: Concurrency>(
+    locals: &mut LocalMap,
+    data: &ScopeData<C>,
+    host: Option<&str>,
+) {
+    if let Some(subdomain) = data
+        .host
+        .as_ref()
+        .and_then(|pattern| pattern.subdomain(host?))
+    {
+        Subdomain(subdomain.to_owned()).insert_into(locals);
+    }
+    if !data.states.is_empty() {
+        data.states.clone().insert_into(locals);
+    }
+    if !data.configs.is_empty() {
+        data.configs.clone().insert_into(locals);
     }
 }
 
@@ -184,13 +369,53 @@ impl<C: Concurrency> Future for AppFuture<C> {
     fn poll(&mut self) -> Poll<Self::Item, Self::Error> {
         let polled = loop {
             self.state = match self.state {
-                AppFutureState::Init => match self.process_recognize() {
-                    Ok(in_flight) => AppFutureState::InFlight(in_flight),
+                AppFutureState::Init
+                    if self.inner.is_preflight_endpoint(
+                        self.request.method(),
+                        self.request.uri().path(),
+                    ) =>
+                {
+                    match self.process_preflight() {
+                        Ok(batch) => AppFutureState::Preflight(batch),
+                        Err(err) => break Err(err),
+                    }
+                }
+                AppFutureState::Rejected(ref mut err) => {
+                    break Err(err.take().expect("the error has already been taken"));
+                }
+                AppFutureState::Init => match self.inner.method_override {
+                    Some(ref policy) => {
+                        match method_override::start(policy, &mut self.request, &mut self.locals) {
+                            Ok(Some(peek_form)) => AppFutureState::PeekingForm(Box::new(peek_form)),
+                            Ok(None) => AppFutureState::Routing,
+                            Err(err) => break Err(err),
+                        }
+                    }
+                    None => AppFutureState::Routing,
+                },
+                AppFutureState::PeekingForm(ref mut peek_form) => {
+                    let policy = self
+                        .inner
+                        .method_override
+                        .as_ref()
+                        .expect("the method override should be enabled");
+                    match peek_form.poll(policy, &mut self.request, &mut self.locals) {
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Ok(Async::Ready(())) => AppFutureState::Routing,
+                        Err(err) => break Err(err),
+                    }
+                }
+                AppFutureState::Routing => match self.process_recognize() {
+                    Ok(Recognized::Handle(in_flight)) => AppFutureState::InFlight(in_flight),
+                    Ok(Recognized::Redirect(location)) => break Ok(self.redirect_to(location)),
                     Err(err) => break Err(err),
                 },
                 AppFutureState::InFlight(ref mut in_flight) => {
                     break ready!(C::poll_ready(in_flight, input!(self)));
                 }
+                AppFutureState::Preflight(ref mut batch) => {
+                    break ready!(batch.poll(&self.inner, &mut self.request));
+                }
                 AppFutureState::Done => panic!("the future has already polled."),
             };
         };
@@ -198,9 +423,18 @@ impl<C: Concurrency> Future for AppFuture<C> {
 
         let mut output = match polled {
             Ok(output) => output,
-            Err(err) => err.into_response(&self.request),
+            Err(err) => {
+                let mut output = self.inner.error_observers.observe(err, &self.request);
+                if output.status() == StatusCode::METHOD_NOT_ALLOWED {
+                    self.insert_allow_header(&mut output);
+                }
+                output
+            }
         };
 
+        self.inner
+            .error_observers
+            .observe_stream(&self.request, &mut output);
         self.process_before_reply(&mut output);
 
         Ok(Async::Ready(output))
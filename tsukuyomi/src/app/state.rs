@@ -0,0 +1,137 @@
+use {
+    crate::handler::Metadata,
+    crate::input::localmap::{local_key, LocalData},
+    std::{
+        any::{Any, TypeId},
+        collections::HashMap,
+        fmt,
+        sync::Arc,
+    },
+};
+
+/// A type map that holds the application-wide values registered in a scope.
+///
+/// The values are shared by `Arc`, and thus cloning this map is cheap.
+#[derive(Clone, Default)]
+pub(crate) struct StateMap {
+    inner: Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl fmt::Debug for StateMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StateMap")
+            .field("len", &self.inner.len())
+            .finish()
+    }
+}
+
+impl LocalData for StateMap {
+    local_key! {
+        /// The local key to access the state map associated with the matched scope.
+        const KEY: Self;
+    }
+}
+
+impl StateMap {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    pub(super) fn insert<T>(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.inner).insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Adds the values registered in the parent scope, without overwriting the own values.
+    pub(super) fn inherit(&mut self, parent: &Self) {
+        if parent.is_empty() {
+            return;
+        }
+        if self.is_empty() {
+            *self = parent.clone();
+            return;
+        }
+        let inner = Arc::make_mut(&mut self.inner);
+        for (id, value) in parent.inner.iter() {
+            inner.entry(*id).or_insert_with(|| value.clone());
+        }
+    }
+
+    pub(crate) fn find<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.inner
+            .get(&TypeId::of::<T>())
+            .cloned()
+            .and_then(|value| value.downcast().ok())
+    }
+
+    pub(crate) fn find_ref<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.inner
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+}
+
+/// A type map that holds the typed configuration values registered in a scope.
+///
+/// Unlike `StateMap`, the values are resolved at build time into the effective
+/// configuration of each scope, with the values registered in the inner scopes
+/// overriding those of the outer scopes.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct ScopeConfigs(StateMap);
+
+impl LocalData for ScopeConfigs {
+    local_key! {
+        /// The local key to access the configuration values associated with the matched scope.
+        const KEY: Self;
+    }
+}
+
+impl ScopeConfigs {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(super) fn insert<T>(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        self.0.insert(value);
+    }
+
+    pub(super) fn inherit(&mut self, parent: &Self) {
+        self.0.inherit(&parent.0);
+    }
+
+    pub(crate) fn find<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.0.find()
+    }
+
+    pub(crate) fn find_ref<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.0.find_ref()
+    }
+}
+
+/// The values attached to the matched route by `Route::extension`.
+#[derive(Debug, Clone)]
+pub(crate) struct RouteMetadata(pub(crate) Metadata);
+
+impl LocalData for RouteMetadata {
+    local_key! {
+        /// The local key to access the values attached to the matched route.
+        const KEY: Self;
+    }
+}
@@ -1,13 +1,17 @@
 use {
-    super::{config::Result, App, LocalApp},
+    super::{config::Result, App, LocalApp, Reason},
     crate::config::prelude::*,
+    http::Method,
     matches::assert_matches,
 };
 
 #[test]
 fn new_empty() -> Result<()> {
     let app = App::create(())?;
-    assert_matches!(app.inner.find_endpoint("/", &mut None), Err(..));
+    assert_matches!(
+        app.inner.find_endpoint(None, &Method::GET, "/", &mut None),
+        Err(..)
+    );
     Ok(())
 }
 
@@ -19,14 +23,18 @@ fn route_single_method() -> Result<()> {
     )?;
 
     assert_matches!(
-        app.inner.find_endpoint("/", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/", &mut None),
         Ok(endpoint) if endpoint.uri == "/"
     );
 
-    assert_matches!(app.inner.find_endpoint("/path/to", &mut None), Err(..));
+    assert_matches!(
+        app.inner
+            .find_endpoint(None, &Method::GET, "/path/to", &mut None),
+        Err(..)
+    );
 
     assert_matches!(
-        app.inner.find_endpoint("/", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/", &mut None),
         Ok(endpoint) if endpoint.uri == "/"
     );
 
@@ -45,19 +53,19 @@ fn scope_simple() -> Result<()> {
     ])?;
 
     assert_matches!(
-        app.inner.find_endpoint("/a", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/a", &mut None),
         Ok(endpoint) if endpoint.uri == "/a"
     );
     assert_matches!(
-        app.inner.find_endpoint("/b", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/b", &mut None),
         Ok(endpoint) if endpoint.uri == "/b"
     );
     assert_matches!(
-        app.inner.find_endpoint("/foo", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/foo", &mut None),
         Ok(endpoint) if endpoint.uri == "/foo"
     );
     assert_matches!(
-        app.inner.find_endpoint("/c/d", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/c/d", &mut None),
         Ok(endpoint) if endpoint.uri == "/c/d"
     );
 
@@ -85,31 +93,35 @@ fn scope_nested() -> Result<()> {
     ])?;
 
     assert_matches!(
-        app.inner.find_endpoint("/foo", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/foo", &mut None),
         Ok(endpoint) if endpoint.uri == "/foo"
     );
     assert_matches!(
-        app.inner.find_endpoint("/bar", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/bar", &mut None),
         Ok(endpoint) if endpoint.uri == "/bar"
     );
     assert_matches!(
-        app.inner.find_endpoint("/foo", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/foo", &mut None),
         Ok(endpoint) if endpoint.uri == "/foo"
     );
     assert_matches!(
-        app.inner.find_endpoint("/baz", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/baz", &mut None),
         Ok(endpoint) if endpoint.uri == "/baz"
     );
     assert_matches!(
-        app.inner.find_endpoint("/baz/foobar", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/baz/foobar", &mut None),
         Ok(endpoint) if endpoint.uri == "/baz/foobar"
     );
     assert_matches!(
-        app.inner.find_endpoint("/hoge", &mut None),
+        app.inner.find_endpoint(None, &Method::GET, "/hoge", &mut None),
         Ok(endpoint) if endpoint.uri == "/hoge"
     );
 
-    assert_matches!(app.inner.find_endpoint("/baz/", &mut None), Err(..));
+    assert_matches!(
+        app.inner
+            .find_endpoint(None, &Method::GET, "/baz/", &mut None),
+        Err(..)
+    );
 
     Ok(())
 }
@@ -118,12 +130,38 @@ fn scope_nested() -> Result<()> {
 fn failcase_duplicate_uri() -> Result<()> {
     let app = App::create(chain![
         path!("/path").to(endpoint::get().call(|| "")),
-        path!("/path").to(endpoint::allow_only("POST, PUT")?.call(|| "")),
+        path!("/path").to(endpoint::allow_only("GET, PUT")?.call(|| "")),
     ]);
     assert!(app.is_err());
     Ok(())
 }
 
+#[test]
+fn split_methods_at_the_same_uri() -> Result<()> {
+    let app = App::create(chain![
+        path!("/path").to(endpoint::get().call(|| "")),
+        path!("/path").to(endpoint::allow_only("POST, PUT")?.call(|| "")),
+    ])?;
+
+    let endpoint = app
+        .inner
+        .find_endpoint(None, &Method::PUT, "/path", &mut None)
+        .map_err(|_| "no route")
+        .unwrap();
+    assert_eq!(
+        endpoint
+            .allowed_methods
+            .as_ref()
+            .map(|m| m.contains(&Method::PUT)),
+        Some(true)
+    );
+
+    let allowed_methods = app.inner.resource_allowed_methods(endpoint).unwrap();
+    assert_eq!(allowed_methods.to_header_value(), "GET, POST, PUT");
+
+    Ok(())
+}
+
 #[test]
 fn failcase_different_scope_at_the_same_uri() -> Result<()> {
     let app = App::create(chain![
@@ -152,3 +190,125 @@ fn current_thread() -> Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn unreachable_overridden_fallback() -> Result<()> {
+    let app = App::create(chain![
+        path!("/api/users").to(endpoint::reply("")),
+        mount("/api").with(chain![
+            path!("/posts").to(endpoint::reply("")),
+            path!("*").to(endpoint::reply("first")),
+            path!("*").to(endpoint::reply("second")),
+        ]),
+    ])?;
+
+    let unreachable = app.unreachable_routes();
+    assert_eq!(unreachable.len(), 1);
+    assert_eq!(unreachable[0].route(), "/api*");
+    assert_matches!(
+        unreachable[0].reason(),
+        Reason::Shadowed { by } if by.starts_with("/api*")
+    );
+    Ok(())
+}
+
+#[test]
+fn reachable_fallback_in_empty_scope() -> Result<()> {
+    let app = App::create(chain![
+        path!("/").to(endpoint::reply("")),
+        mount("/static").with(path!("*").to(endpoint::reply(""))),
+    ])?;
+    assert!(app.unreachable_routes().is_empty());
+    Ok(())
+}
+
+#[test]
+fn unreachable_fallback_shadowed_by_another_scope() -> Result<()> {
+    let app = App::create(chain![
+        path!("/").to(endpoint::reply("")),
+        mount("/static").with(path!("*").to(endpoint::reply(""))),
+        mount("/static").with(path!("*").to(endpoint::reply(""))),
+        mount("/docs").with(chain![
+            path!("*").to(endpoint::reply("")),
+            mount("/").with(path!("*").to(endpoint::reply(""))),
+        ]),
+    ])?;
+
+    let unreachable = app.unreachable_routes();
+    assert_eq!(unreachable.len(), 2);
+    assert_eq!(unreachable[0].route(), "/static*");
+    assert_matches!(unreachable[0].reason(), Reason::Shadowed { .. });
+    assert_eq!(unreachable[1].route(), "/docs*");
+    assert_matches!(unreachable[1].reason(), Reason::Shadowed { .. });
+    Ok(())
+}
+
+#[test]
+fn unreachable_route_shadowed_by_preflight_endpoint() -> Result<()> {
+    let app = App::create(chain![
+        path!("/authorize").to(endpoint::post().reply("")),
+        preflight_endpoint("/authorize"),
+    ])?;
+
+    let unreachable = app.unreachable_routes();
+    assert_eq!(unreachable.len(), 1);
+    assert_eq!(unreachable[0].route(), "/authorize");
+    assert_matches!(unreachable[0].reason(), Reason::Shadowed { .. });
+    Ok(())
+}
+
+#[test]
+fn unreachable_route_without_allowed_methods() -> Result<()> {
+    let app = App::create(chain![
+        path!("/a").to(endpoint::allow_only(Vec::<http::Method>::new())?.reply("")),
+        path!("/b").to(endpoint::allow_only("GET, POST")?.reply("")),
+    ])?;
+
+    let unreachable = app.unreachable_routes();
+    assert_eq!(unreachable.len(), 1);
+    assert_eq!(unreachable[0].route(), "/a");
+    assert_eq!(*unreachable[0].reason(), Reason::NoAllowedMethods);
+    Ok(())
+}
+
+#[test]
+fn reachable_routes_are_not_flagged() -> Result<()> {
+    let app = App::create(chain![
+        path!("*").to(endpoint::reply("root fallback")),
+        path!("/users/:id").to(endpoint::call(|_: u32| "")),
+        path!("/users/:id/posts").to(endpoint::call(|_: u32| "")),
+        path!("/files/*path").to(endpoint::call(|_: String| "")),
+        mount("/api").with(chain![
+            path!("*").to(endpoint::reply("api fallback")),
+            mount("/v1").with(chain![
+                path!("/items").to(endpoint::reply("")),
+                path!("*").to(endpoint::reply("v1 fallback")),
+            ]),
+        ]),
+        preflight_endpoint("/preflight"),
+    ])?;
+
+    assert!(app.unreachable_routes().is_empty());
+    Ok(())
+}
+
+#[test]
+fn reachable_root_fallback_without_routes() -> Result<()> {
+    let app = App::create(path!("*").to(endpoint::reply("")))?;
+    assert!(app.unreachable_routes().is_empty());
+    Ok(())
+}
+
+#[test]
+fn failcase_unreachable_routes_in_strict_mode() -> Result<()> {
+    let app = App::create_strict(chain![
+        path!("/").to(endpoint::reply("")),
+        mount("/static").with(path!("*").to(endpoint::reply(""))),
+        mount("/static").with(path!("*").to(endpoint::reply(""))),
+    ]);
+    assert!(app.is_err());
+
+    let app = App::create_strict(path!("/").to(endpoint::reply("")));
+    assert!(app.is_ok());
+    Ok(())
+}
@@ -8,27 +8,32 @@ pub mod prelude {
     pub use crate::{chain, path};
 
     #[doc(no_inline)]
-    pub use super::{mount, Config, ConfigExt};
+    pub use super::{
+        host, mount, mount_app, observe_errors, preflight_endpoint, require_route, scope_config,
+        state, with_outer, with_tagged, Config, ConfigExt,
+    };
 
     pub mod endpoint {
         #[doc(no_inline)]
         pub use super::super::endpoint::{
             allow_only, any, call, call_async, connect, delete, get, head, options, patch, post,
-            put, reply, trace,
+            put, reply, trace, tupled,
         };
     }
 }
 
 #[doc(no_inline)]
-pub use crate::app::config::{Config, Error, Result, Scope};
+pub use crate::app::config::{Config, Error, Result, Scope, TaggedHandler};
 
 use {
     crate::{
-        app::config::Concurrency,
-        handler::{Handler, ModifyHandler},
+        app::{config::Concurrency, AppBase, PreflightTarget},
+        error::ErrorEvent,
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler, WithMetadata},
         util::Chain,
     },
-    std::borrow::Cow,
+    http::Method,
+    std::{borrow::Cow, fmt, time::Duration},
 };
 
 /// Creates a `Config` that creates a sub-scope with the provided prefix.
@@ -71,7 +76,672 @@ where
     }
 }
 
+/// Creates a `Config` that mounts a pre-built `App` with the provided prefix.
+///
+/// See the documentation of `Scope::mount_app` for details.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let admin = App::create(chain![
+///     state(String::from("admin")),
+///     path!("/").to(endpoint::get().reply("dashboard")),
+///     path!("*").to(endpoint::reply("admin page not found")),
+/// ])?;
+///
+/// let app = App::create(chain![
+///     path!("/").to(endpoint::get().reply("index")),
+///     mount_app("/admin", admin),
+/// ])?;
+/// # Ok::<(), tsukuyomi::app::Error>(())
+/// ```
+pub fn mount_app<P, C>(prefix: P, app: AppBase<C>) -> MountApp<P, C>
+where
+    P: AsRef<str>,
+    C: Concurrency,
+{
+    MountApp { prefix, app }
+}
+
+/// A `Config` that mounts a pre-built `App`.
+#[derive(Debug)]
+pub struct MountApp<P, C: Concurrency> {
+    prefix: P,
+    app: AppBase<C>,
+}
+
+impl<P, M, C> Config<M, C> for MountApp<P, C>
+where
+    P: AsRef<str>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.mount_app(self.prefix, self.app)
+    }
+}
+
+/// Creates a `Config` that constrains the current scope to the requests to the specified host.
+///
+/// See the documentation of `Scope::host` for details.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// let app = App::create(chain![
+///     mount("/").with(chain![
+///         host("api.example.com"),
+///         path!("/").to(endpoint::get().reply("api")),
+///     ]),
+///     mount("/").with(chain![
+///         host("*.example.com"),
+///         path!("/").to(endpoint::get()
+///             .extract(extractor::subdomain())
+///             .call(|user: String| format!("the page of {}", user))),
+///     ]),
+/// ]);
+/// # app.unwrap();
+/// ```
+pub fn host<P>(pattern: P) -> Host<P>
+where
+    P: AsRef<str>,
+{
+    Host { pattern }
+}
+
+/// A `Config` that constrains the current scope to a host.
+#[derive(Debug)]
+pub struct Host<P> {
+    pattern: P,
+}
+
+impl<P, M, C> Config<M, C> for Host<P>
+where
+    P: AsRef<str>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.host(self.pattern)
+    }
+}
+
+/// Creates a `Config` that registers a shared value onto the current scope.
+///
+/// The registered value can be extracted by using `extractor::state`.
+pub fn state<S>(state: S) -> State<S>
+where
+    S: Send + Sync + 'static,
+{
+    State { state }
+}
+
+/// A `Config` that registers a shared value onto the current scope.
+#[derive(Debug)]
+pub struct State<S> {
+    state: S,
+}
+
+impl<S, M, C> Config<M, C> for State<S>
+where
+    S: Send + Sync + 'static,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.state(self.state)
+    }
+}
+
+/// Creates a `Config` that registers a typed configuration value onto the current scope.
+///
+/// See the documentation of `Scope::config` for details.
+pub fn scope_config<V>(value: V) -> ScopeConfig<V>
+where
+    V: Send + Sync + 'static,
+{
+    ScopeConfig { value }
+}
+
+/// A `Config` that registers a typed configuration value onto the current scope.
+#[derive(Debug)]
+pub struct ScopeConfig<V> {
+    value: V,
+}
+
+impl<V, M, C> Config<M, C> for ScopeConfig<V>
+where
+    V: Send + Sync + 'static,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.config(self.value)
+    }
+}
+
+/// The scope configuration that limits the size of request body read by the body extractors.
+///
+/// If the size of body exceeds the limit, the extraction fails with
+/// `413 Payload Too Large`.  The raw body stream taken by `extractor::body::stream`
+/// is not limited.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BodyLimit(pub usize);
+
+/// The scope configuration that specifies how the text extractors (e.g. `extractor::body::plain`)
+/// handle the byte sequences that are invalid in the charset of request body.
+///
+/// If not specified, the invalid sequences are rejected as `Strict`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextDecoding {
+    /// Fails the extraction with `400 Bad Request`.
+    Strict,
+
+    /// Replaces the invalid sequences with `U+FFFD REPLACEMENT CHARACTER`.
+    Lossy,
+}
+
+/// The scope configuration that specifies how the router replies to the requests whose
+/// method is not allowed by any route registered with the path.
+///
+/// If not specified, the requests are replied with `405 Method Not Allowed` and the
+/// header field `Allow` listing the methods of all routes with the path.  The routes
+/// with a fallback set by `Route::fallback` handle the disallowed methods regardless
+/// of this configuration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DisallowedMethod {
+    /// Replies with `405 Method Not Allowed`.
+    MethodNotAllowed,
+
+    /// Handles the request as if the path matched no route, so that the existence
+    /// of route is hidden.  The request is passed to the fallback of scope, if any.
+    NotFound,
+}
+
+/// The scope configuration that specifies the duration used by `ExtractorExt::scoped_timeout`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ExtractTimeout(pub Duration);
+
+/// The policy of routing the requests whose path differs from a registered route
+/// only in the trailing slash (e.g. `/posts/` for the route `/posts`).
+///
+/// The policy applies to the entire application, and is registered at the root scope
+/// as a `Config`.  If not specified, the paths are compared strictly.
+///
+/// The slash is never added or removed when the alternate path is matched by a
+/// wildcard route, and a route registered with the exact path always takes precedence
+/// over the alternate one, as well as over the default handlers.
+///
+/// With `Redirect` or `Merge`, a parameter never matches the empty segment, so that
+/// `/posts/` is routed to `/posts` rather than `/posts/:id`.  With `Strict`, the
+/// parameter matches the empty segment as it does without the policy.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TrailingSlash {
+    /// Distinguishes the paths with and without the trailing slash.
+    Strict,
+
+    /// Redirects the client to the registered form of path, with the query string preserved.
+    ///
+    /// The redirect uses `301 Moved Permanently` for `GET` and `HEAD`, and
+    /// `308 Permanent Redirect` for the other methods so that the method and body are kept.
+    Redirect,
+
+    /// Handles the request with the route registered in the other form of path.
+    Merge,
+}
+
+impl Default for TrailingSlash {
+    fn default() -> Self {
+        TrailingSlash::Strict
+    }
+}
+
+impl<M, C> Config<M, C> for TrailingSlash
+where
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.trailing_slash(self)
+    }
+}
+
+/// The policy of overriding the method of `POST` requests before routing, for the
+/// clients such as HTML forms that can send only `GET` and `POST`.
+///
+/// The effective method is taken from the header field `X-HTTP-Method-Override`, or
+/// the field `_method` of the form encoded as `application/x-www-form-urlencoded`.
+/// The requests with the other methods are never overridden, and the methods not in
+/// the allowlist (`PUT`, `PATCH` and `DELETE` by default) are rejected with
+/// `400 Bad Request`.  The original method is available by `Input::original_method`.
+///
+/// The policy applies to the entire application, and is registered at the root scope
+/// as a `Config`.  Only the first field of the form is inspected for `_method`, as
+/// the hidden field generated by the form helpers is placed at the beginning.  The
+/// chunks received before routing are passed to the handlers with the rest of the
+/// request body, so the forms of any size are not buffered nor rejected here.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::{prelude::*, MethodOverride}, App};
+/// let app = App::create(chain![
+///     MethodOverride::new(),
+///     path!("/posts/:id")
+///         .to(endpoint::delete().call(|id: u32| format!("deleted {}", id))),
+/// ]);
+/// # app.unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MethodOverride {
+    pub(crate) header: bool,
+    pub(crate) form_field: bool,
+    pub(crate) allowed_methods: AllowedMethods,
+}
+
+impl Default for MethodOverride {
+    fn default() -> Self {
+        Self {
+            header: true,
+            form_field: true,
+            allowed_methods: vec![Method::PUT, Method::PATCH, Method::DELETE]
+                .into_iter()
+                .collect(),
+        }
+    }
+}
+
+impl MethodOverride {
+    /// Creates a `MethodOverride` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to use the header field `X-HTTP-Method-Override`.
+    ///
+    /// The header field takes precedence over the form field.
+    pub fn header(self, enabled: bool) -> Self {
+        Self {
+            header: enabled,
+            ..self
+        }
+    }
+
+    /// Sets whether to use the form field `_method`.
+    pub fn form_field(self, enabled: bool) -> Self {
+        Self {
+            form_field: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the methods that the `POST` requests can be overridden with.
+    pub fn allow(self, methods: impl IntoIterator<Item = Method>) -> Self {
+        Self {
+            allowed_methods: methods.into_iter().collect(),
+            ..self
+        }
+    }
+}
+
+impl<M, C> Config<M, C> for MethodOverride
+where
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.method_override(self)
+    }
+}
+
+/// The policy of normalizing the request path before routing, so that the equivalent
+/// paths (e.g. `/a//b` and `/a/./b` for `/a/b`) are routed in the same way.
+///
+/// The duplicate slashes are collapsed into one, and the dot segments (`.` and `..`,
+/// including the percent-encoded forms such as `%2e%2e`) are resolved as described
+/// in RFC 3986.  The paths whose `..` goes up beyond the root are rejected with
+/// `400 Bad Request`.  The other percent-encoded characters, including `%2F`, are
+/// left as they are.
+///
+/// The normalized path is used for routing and the parameters, and the path as received
+/// is available by `Input::raw_path`.  The policy applies to the entire application,
+/// and is registered at the root scope as a `Config`.  If not specified, the paths are
+/// routed as they are.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::{prelude::*, PathNormalization}, App};
+/// let app = App::create(chain![
+///     PathNormalization::new(),
+///     path!("/docs/*path").to(endpoint::get().call(|path: String| path)),
+/// ]);
+/// # app.unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct PathNormalization {
+    pub(crate) merge_slashes: bool,
+    pub(crate) dot_segments: bool,
+}
+
+impl Default for PathNormalization {
+    fn default() -> Self {
+        Self {
+            merge_slashes: true,
+            dot_segments: true,
+        }
+    }
+}
+
+impl PathNormalization {
+    /// Creates a `PathNormalization` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to collapse the duplicate slashes into one.
+    pub fn merge_slashes(self, enabled: bool) -> Self {
+        Self {
+            merge_slashes: enabled,
+            ..self
+        }
+    }
+
+    /// Sets whether to resolve the dot segments.
+    ///
+    /// If disabled, the paths going up beyond the root are not rejected either.
+    pub fn dot_segments(self, enabled: bool) -> Self {
+        Self {
+            dot_segments: enabled,
+            ..self
+        }
+    }
+}
+
+impl<M, C> Config<M, C> for PathNormalization
+where
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.path_normalization(self)
+    }
+}
+
+/// The mount point of the application deployed under a path prefix behind a reverse
+/// proxy (e.g. `/service-a` for the requests to `/service-a/...`).
+///
+/// The prefix is stripped from the request path before routing, so that the routes
+/// are registered as if the application were served at `/`, and the requests outside
+/// of the prefix are replied with `404 Not Found`.  The prefix is added again to the
+/// URIs generated by the application, i.e. the locations of `output::redirect::to_route`
+/// and the redirects by `TrailingSlash::Redirect`.  The locations passed explicitly
+/// to the other redirects are sent as they are.
+///
+/// If `forwarded_prefix` is enabled, the value of header field `X-Forwarded-Prefix`,
+/// which specifies the prefix already stripped by the proxy, is also added to the
+/// generated URIs before the mount point.  Enable it only if the header field is
+/// always set or removed by the trusted proxy.
+///
+/// The mount point is stripped after the path is normalized by `PathNormalization`,
+/// and the path of request before stripped is available by `Input::raw_path`.  The policy
+/// applies to the entire application, and is registered at the root scope as a `Config`.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::{prelude::*, MountPoint}, App};
+/// let app = App::create(chain![
+///     MountPoint::new("/service-a"),
+///     path!("/login").to(endpoint::get().reply("login form")),
+/// ]);
+/// # app.unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct MountPoint {
+    pub(crate) prefix: Cow<'static, str>,
+    pub(crate) forwarded_prefix: bool,
+}
+
+impl MountPoint {
+    /// Creates a `MountPoint` with the specified path prefix.
+    ///
+    /// The prefix `/` means that the request path is not stripped, which is useful
+    /// for using only `X-Forwarded-Prefix`.
+    pub fn new(prefix: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            prefix: prefix.into(),
+            forwarded_prefix: false,
+        }
+    }
+
+    /// Sets whether to use the header field `X-Forwarded-Prefix` for generating URIs.
+    pub fn forwarded_prefix(self, enabled: bool) -> Self {
+        Self {
+            forwarded_prefix: enabled,
+            ..self
+        }
+    }
+}
+
+impl<M, C> Config<M, C> for MountPoint
+where
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.mount_point(self)
+    }
+}
+
+/// Creates a `Config` that declares the route named `name` is referenced by the handlers.
+///
+/// See the documentation of `Scope::require_route` for details.
+pub fn require_route(name: impl Into<Cow<'static, str>>) -> RequireRoute {
+    RequireRoute { name: name.into() }
+}
+
+/// A `Config` that declares a reference to a named route.
+#[derive(Debug)]
+pub struct RequireRoute {
+    name: Cow<'static, str>,
+}
+
+impl<M, C> Config<M, C> for RequireRoute
+where
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.require_route(self.name)
+    }
+}
+
+/// Creates a `Config` that binds a `ModifyHandler` to the specified tag.
+///
+/// See the documentation of `Scope::with_tagged` for details.
+pub fn with_tagged<M>(tag: impl Into<Cow<'static, str>>, modifier: M) -> WithTagged<M> {
+    WithTagged {
+        tag: tag.into(),
+        modifier,
+    }
+}
+
+/// A `Config` that binds a `ModifyHandler` to a tag.
+#[derive(Debug)]
+pub struct WithTagged<M> {
+    tag: Cow<'static, str>,
+    modifier: M,
+}
+
+impl<M, M2, C> Config<M2, C> for WithTagged<M>
+where
+    M: ModifyHandler<TaggedHandler<C>> + 'static,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M2, C>) -> std::result::Result<(), Self::Error> {
+        scope.with_tagged(self.tag, self.modifier)
+    }
+}
+
+/// Creates a `Config` that registers a `ModifyHandler` wrapping the outside of all
+/// modifiers applied to the routes in the scope.
+///
+/// See the documentation of `Scope::with_outer` for details.
+pub fn with_outer<M>(modifier: M) -> WithOuter<M> {
+    WithOuter { modifier }
+}
+
+/// A `Config` that registers a `ModifyHandler` wrapping the outside of the scope.
+#[derive(Debug)]
+pub struct WithOuter<M> {
+    modifier: M,
+}
+
+impl<M, M2, C> Config<M2, C> for WithOuter<M>
+where
+    M: ModifyHandler<TaggedHandler<C>> + 'static,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M2, C>) -> std::result::Result<(), Self::Error> {
+        scope.with_outer(self.modifier)
+    }
+}
+
+/// Creates a `Config` that registers a function called with every error occurring
+/// in the application.
+///
+/// See the documentation of `Scope::observe_errors` for details.
+pub fn observe_errors<F>(f: F) -> ObserveErrors<F>
+where
+    F: Fn(&ErrorEvent<'_>) + Send + Sync + 'static,
+{
+    ObserveErrors { f }
+}
+
+/// A `Config` that registers a function observing the errors in the application.
+pub struct ObserveErrors<F> {
+    f: F,
+}
+
+impl<F> fmt::Debug for ObserveErrors<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ObserveErrors").finish()
+    }
+}
+
+impl<F, M, C> Config<M, C> for ObserveErrors<F>
+where
+    F: Fn(&ErrorEvent<'_>) + Send + Sync + 'static,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.observe_errors(self.f)
+    }
+}
+
+/// Creates a `Config` that registers the built-in endpoint of the authorization preflight.
+///
+/// See the documentation of `Scope::preflight_endpoint` for details.
+pub fn preflight_endpoint<P>(path: P) -> PreflightEndpoint<P>
+where
+    P: AsRef<str>,
+{
+    PreflightEndpoint {
+        path,
+        limits: PreflightLimits::default(),
+    }
+}
+
+/// A `Config` that registers the built-in endpoint of the authorization preflight.
+#[derive(Debug)]
+pub struct PreflightEndpoint<P> {
+    path: P,
+    limits: PreflightLimits,
+}
+
+impl<P> PreflightEndpoint<P>
+where
+    P: AsRef<str>,
+{
+    /// Sets the limits of the requests to the endpoint.
+    pub fn limits(self, limits: PreflightLimits) -> Self {
+        Self { limits, ..self }
+    }
+}
+
+impl<P, M, C> Config<M, C> for PreflightEndpoint<P>
+where
+    P: AsRef<str>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.preflight_endpoint(self.path, self.limits)
+    }
+}
+
+/// The limits of the requests to the built-in endpoint of the authorization preflight.
+///
+/// By default, the body is limited to 64 KiB and the batch to 100 sub-requests.
+#[derive(Debug, Clone)]
+pub struct PreflightLimits {
+    pub(crate) max_body_size: usize,
+    pub(crate) max_items: usize,
+}
+
+impl Default for PreflightLimits {
+    fn default() -> Self {
+        Self {
+            max_body_size: 64 * 1024,
+            max_items: 100,
+        }
+    }
+}
+
+impl PreflightLimits {
+    /// Creates a `PreflightLimits` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the maximum size of the request body, in bytes.
+    pub fn max_body_size(self, max_body_size: usize) -> Self {
+        Self {
+            max_body_size,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of the sub-requests in a batch.
+    pub fn max_items(self, max_items: usize) -> Self {
+        Self { max_items, ..self }
+    }
+}
+
 /// Crates a `Config` that wraps a config with a `ModifyHandler`.
+///
+/// See the documentation of `Scope::modify` for the order in which the modifiers wrap.
 pub fn modify<M, T>(modifier: M, config: T) -> Modify<M, T> {
     Modify { modifier, config }
 }
@@ -97,6 +767,9 @@ where
 
 pub trait ConfigExt: Sized {
     /// Creates a `Config` with the specified `ModifyHandler`
+    ///
+    /// The modifier given first is the outermost, i.e. `route.modify(a).modify(b)`
+    /// calls `a` before `b`.
     fn modify<M>(self, modifier: M) -> Modify<M, Self> {
         modify(modifier, self)
     }
@@ -106,9 +779,14 @@ impl<T> ConfigExt for T {}
 
 /// A `Config` that registers a route into a scope.
 #[derive(Debug)]
-pub struct Route<H> {
+pub struct Route<H, F = ()> {
     path: Cow<'static, str>,
     handler: H,
+    tags: Vec<Cow<'static, str>>,
+    name: Option<Cow<'static, str>>,
+    location: Option<self::path::Location>,
+    metadata: Metadata,
+    fallback: F,
 }
 
 impl<H> Route<H>
@@ -120,20 +798,122 @@ where
         Self {
             path: path.into(),
             handler,
+            tags: vec![],
+            name: None,
+            location: None,
+            metadata: Metadata::default(),
+            fallback: (),
         }
     }
 }
 
+impl<H, F> Route<H, F>
+where
+    H: Handler,
+{
+    /// Adds a tag to this route.
+    ///
+    /// The modifiers bound to the tag by `with_tagged` are applied to this route.
+    pub fn tag(mut self, tag: impl Into<Cow<'static, str>>) -> Self {
+        self.tags.push(tag.into());
+        self
+    }
+
+    /// Sets the name of this route.
+    ///
+    /// The URI of the named route can be built from the handlers by using
+    /// `output::redirect::to_route`.  The names must be unique within the application.
+    pub fn name(self, name: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            name: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Attaches a value to this route, replacing the one of the same type.
+    ///
+    /// The attached values are available to the modifiers through `Handler::metadata`,
+    /// and to the handlers through `Input::metadata`.  The type of value is used as the
+    /// key, so a dedicated type should be defined for each kind of value.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use tsukuyomi::{config::prelude::*, App};
+    /// /// The marker of the routes that skip the authentication.
+    /// struct Public;
+    ///
+    /// let app = App::create(chain![
+    ///     path!("/login")
+    ///         .to(endpoint::get().reply("login form"))
+    ///         .extension(Public),
+    ///     path!("/dashboard").to(endpoint::get().reply("dashboard")),
+    /// ]);
+    /// # app.unwrap();
+    /// ```
+    pub fn extension<T>(mut self, value: T) -> Self
+    where
+        T: Send + Sync + 'static,
+    {
+        self.metadata.insert(value);
+        self
+    }
+
+    /// Marks this route as evaluated by the authorization preflight (see `App::authorize`).
+    ///
+    /// The preflight passes the request through the modifiers applied to this route,
+    /// and stops right before the handler is called.  Therefore, all modifiers applied
+    /// to this route must take part in the preflight (see `guard::participate`), or
+    /// the creation of `App` fails.
+    pub fn preflight(mut self) -> Self {
+        self.metadata.insert(PreflightTarget);
+        self
+    }
+}
+
 impl<H, M, C> Config<M, C> for Route<H>
 where
     H: Handler,
-    M: ModifyHandler<H>,
+    M: ModifyHandler<WithMetadata<H>>,
     M::Handler: Into<C::Handler>,
     C: Concurrency,
 {
     type Error = Error;
 
     fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
-        scope.route(self.path, self.handler)
+        scope.route_with_options(
+            self.path,
+            WithMetadata::new(self.handler, self.metadata),
+            &self.tags,
+            self.name,
+            self.location,
+        )
+    }
+}
+
+impl<H, F, M, C> Config<M, C> for Route<H, F>
+where
+    H: Handler,
+    F: Handler,
+    M: ModifyHandler<WithMetadata<H>> + ModifyHandler<WithMetadata<F>>,
+    <M as ModifyHandler<WithMetadata<H>>>::Handler: Into<C::Handler>,
+    <M as ModifyHandler<WithMetadata<F>>>::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> std::result::Result<(), Self::Error> {
+        scope.route_with_options(
+            &*self.path,
+            WithMetadata::new(self.handler, self.metadata.clone()),
+            &self.tags,
+            self.name,
+            self.location,
+        )?;
+        scope.route_fallback(
+            self.path,
+            WithMetadata::new(self.fallback, self.metadata),
+            &self.tags,
+        )
     }
 }
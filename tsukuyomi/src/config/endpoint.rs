@@ -11,6 +11,8 @@ use {
     http::Method,
 };
 
+pub use crate::generic::Tupled;
+
 pub fn any() -> Builder {
     Builder::allow_any()
 }
@@ -46,6 +48,26 @@ define_builder_with_allowing_sigle_method! {
     trace => TRACE,
 }
 
+/// Wraps a function so that it takes the extracted values as a single tuple,
+/// instead of receiving each of them as a separate argument.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// let app = App::create(
+///     path!("/:name/:id").to(endpoint::get()
+///         .extract(extractor::header::headers())
+///         .call(endpoint::tupled(|(name, id, headers): (String, u32, http::HeaderMap)| {
+///             format!("{}#{} ({} headers)", name, id, headers.len())
+///         }))),
+/// );
+/// # app.unwrap();
+/// ```
+pub fn tupled<F>(f: F) -> Tupled<F> {
+    Tupled(f)
+}
+
 pub fn get_or_head() -> Builder {
     Builder::allow_only(vec![Method::GET, Method::HEAD]).expect("should be valid methods")
 }
@@ -7,7 +7,7 @@ use {
         handler::Handler,
         input::param::Params,
     },
-    std::{marker::PhantomData, sync::Arc},
+    std::{fmt, marker::PhantomData, sync::Arc},
 };
 
 #[doc(hidden)]
@@ -30,6 +30,45 @@ impl PathExtractor for () {
 
 /// A macro for generating the code that creates a [`Path`] from the provided tokens.
 ///
+/// The parameters at the end of path can be made optional with the suffix `?`
+/// (e.g. `/archive/:year/:month?`).  Such a path is registered as the routes with
+/// and without each of the optional parameters, and their values are extracted as
+/// `Option<T>`.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(
+///     path!("/archive/:year/:month?").to(endpoint::get().call(
+///         |year: u32, month: Option<u32>| match month {
+///             Some(month) => format!("{}-{:02}", year, month),
+///             None => format!("{}", year),
+///         },
+///     )),
+/// );
+/// # app.unwrap();
+/// ```
+///
+/// The catch-all parameter `*name` matches the remaining segments, and can be
+/// followed by static segments (e.g. `/repos/*path/info/refs`).  In that case, it
+/// matches greedily one or more characters followed by the static segments at the
+/// end of path.  The routes sharing the catch-all parameter at the same position
+/// are tried in preference to the one ending with it, and the static segments
+/// ending with those of another route (e.g. `/refs` and `/info/refs`) are rejected
+/// as ambiguous when the application is created.  The value is percent-decoded in
+/// the same way as the trailing catch-all parameters.
+///
+/// ```
+/// # use std::path::PathBuf;
+/// # use tsukuyomi::{config::prelude::*, App};
+/// let app = App::create(chain![
+///     path!("/repos/*path/info/refs")
+///         .to(endpoint::get().call(|repo: PathBuf| format!("refs of {}", repo.display()))),
+///     path!("/repos/*path")
+///         .to(endpoint::get().call(|path: PathBuf| format!("file {}", path.display()))),
+/// ]);
+/// # app.unwrap();
+/// ```
+///
 /// [`Path`]: ./app/config/route/struct.Path.html
 #[macro_export]
 macro_rules! path {
@@ -39,7 +78,7 @@ macro_rules! path {
         impl __Dummy {
             $crate::config::path::path_impl!(__path_internal, $path);
         }
-        __Dummy::call()
+        __Dummy::call().located(file!(), line!())
     }};
 }
 
@@ -54,9 +93,23 @@ pub mod internal {
     };
 }
 
+/// The location in the source code where a route is declared by `path!`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Location {
+    file: &'static str,
+    line: u32,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
 #[derive(Debug)]
 pub struct Path<E: PathExtractor = ()> {
     path: &'static str,
+    location: Option<Location>,
     _marker: PhantomData<E>,
 }
 
@@ -68,10 +121,21 @@ where
     pub fn new(path: &'static str) -> Self {
         Self {
             path,
+            location: None,
             _marker: PhantomData,
         }
     }
 
+    /// Sets the location where this path is declared, reported in the errors at
+    /// building the application.
+    #[doc(hidden)]
+    pub fn located(self, file: &'static str, line: u32) -> Self {
+        Self {
+            location: Some(Location { file, line }),
+            ..self
+        }
+    }
+
     /// Creates a `Route` with this path configuration and the specified `Endpoint`.
     pub fn to<T>(
         self,
@@ -86,15 +150,57 @@ where
     where
         T: Endpoint<E::Output>,
     {
-        let Self { path, .. } = self;
+        let Self { path, location, .. } = self;
         let endpoint = Arc::new(endpoint);
         let allowed_methods = endpoint.allowed_methods();
 
         Route {
-            path: path.into(),
-            handler: crate::handler::handler(
+            location,
+            ..Route::new(
+                path,
+                crate::handler::handler(
+                    move || self::handle::RouteHandle::new(endpoint.clone()),
+                    allowed_methods,
+                ),
+            )
+        }
+    }
+}
+
+impl<H> Route<H>
+where
+    H: Handler,
+{
+    /// Sets the `Endpoint` called when the route does not allow the method of request.
+    ///
+    /// By default, such requests are replied with `405 Method Not Allowed`.  The fallback
+    /// takes effect only if the route declares its allowed methods, and the modifiers
+    /// and tags of the route are applied to it as well.
+    pub fn fallback<T>(
+        self,
+        endpoint: T,
+    ) -> Route<
+        H,
+        impl Handler<
+            Output = T::Output,
+            Error = Error,
+            Handle = self::handle::RouteHandle<(), T>, // private
+        >,
+    >
+    where
+        T: Endpoint<()>,
+    {
+        let endpoint = Arc::new(endpoint);
+        Route {
+            path: self.path,
+            handler: self.handler,
+            tags: self.tags,
+            name: self.name,
+            location: self.location,
+            metadata: self.metadata,
+            fallback: crate::handler::handler(
                 move || self::handle::RouteHandle::new(endpoint.clone()),
-                allowed_methods,
+                None,
             ),
         }
     }
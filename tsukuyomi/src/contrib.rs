@@ -0,0 +1,8 @@
+//! Ready-made components built on top of the framework.
+
+#[cfg(feature = "json-schema")]
+pub mod json_schema;
+pub mod recent_errors;
+pub mod upload;
+#[cfg(feature = "webhook")]
+pub mod webhook;
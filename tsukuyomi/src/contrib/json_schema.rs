@@ -0,0 +1,529 @@
+//! Validation of the JSON request/response bodies against JSON Schema.
+//!
+//! This module is available only if the feature `json-schema` is enabled.
+//!
+//! The schemas are attached to a route (or any other `Config`) by
+//! `ValidateSchemaExt::validate_request` and `validate_response`.  They are
+//! compiled when the application is created, and the invalid schemas are reported
+//! as the error of `App::create`.
+//!
+//! * The request body with a JSON media type (`application/json` or `*/*+json`) is
+//!   parsed and validated before the endpoint is called.  The requests with `GET`, `HEAD`
+//!   and `OPTIONS` and the bodies of other media types are passed to the endpoint as they
+//!   are.  The violations are rejected with `400 Bad Request`, whose JSON body lists the
+//!   JSON pointers of the invalid values.  The body is put back after validation, so the
+//!   endpoint can extract it again (e.g. by `extractor::body::json`).
+//! * The successful (`2xx`) responses with a JSON body are validated after the endpoint
+//!   returns them.  The behavior on violation is specified by `ResponseMode`; by default,
+//!   the violations are replaced with `500 Internal Server Error` in the debug builds,
+//!   and the responses are not validated in the release builds.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, extractor, App};
+//! use tsukuyomi::contrib::json_schema::{ResponseMode, Schema, ValidateSchemaExt};
+//!
+//! let app = App::create(
+//!     path!("/users")
+//!         .to(endpoint::post()
+//!             .extract(extractor::body::json())
+//!             .call(|user: serde_json::Value| tsukuyomi::output::json(user)))
+//!         .validate_request(serde_json::json!({
+//!             "type": "object",
+//!             "properties": {
+//!                 "name": { "type": "string" },
+//!                 "age": { "type": "integer", "minimum": 0 },
+//!             },
+//!             "required": ["name"],
+//!         }))
+//!         .validate_response(Schema::file("schemas/user.json"))
+//!         .response_mode(ResponseMode::LogOnly),
+//! );
+//! # drop(app);
+//! ```
+
+use {
+    crate::{
+        app::config::{Concurrency, Config, Error as ConfigError, Scope},
+        error::{Error, HttpError},
+        extractor::body::ReadBody,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+        input::{body::RequestBody, localmap::LocalData, Input},
+        output::{IntoResponse, ResponseBody},
+        responder::Responder,
+        util::Chain,
+    },
+    bytes::{Bytes, BytesMut},
+    http::{header::CONTENT_TYPE, HeaderMap, Method, Request, Response, StatusCode},
+    hyper::body::Payload,
+    jsonschema::JSONSchema,
+    serde::Serialize,
+    serde_json::Value,
+    std::{fmt, path::PathBuf, sync::Arc},
+};
+
+/// A JSON Schema, given as an inline value or a file.
+#[derive(Debug, Clone)]
+pub struct Schema(SchemaSource);
+
+#[derive(Debug, Clone)]
+enum SchemaSource {
+    Value(Value),
+    File(PathBuf),
+}
+
+impl Schema {
+    /// Creates a `Schema` from a JSON value.
+    pub fn value(schema: Value) -> Self {
+        Schema(SchemaSource::Value(schema))
+    }
+
+    /// Creates a `Schema` loaded from the specified file when the application is created.
+    pub fn file(path: impl Into<PathBuf>) -> Self {
+        Schema(SchemaSource::File(path.into()))
+    }
+
+    fn compile(&self) -> Result<Arc<JSONSchema>, failure::Error> {
+        let value = match self.0 {
+            SchemaSource::Value(ref value) => value.clone(),
+            SchemaSource::File(ref path) => {
+                let content = std::fs::read(path).map_err(|err| {
+                    failure::format_err!("failed to read the schema {}: {}", path.display(), err)
+                })?;
+                serde_json::from_slice(&content).map_err(|err| {
+                    failure::format_err!("failed to parse the schema {}: {}", path.display(), err)
+                })?
+            }
+        };
+        let schema = JSONSchema::compile(&value)
+            .map_err(|err| failure::format_err!("invalid JSON Schema: {}", err))?;
+        Ok(Arc::new(schema))
+    }
+}
+
+impl From<Value> for Schema {
+    fn from(schema: Value) -> Self {
+        Self::value(schema)
+    }
+}
+
+/// The behavior when a response violates the schema.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ResponseMode {
+    /// Logs the violation as an error, and replaces the response with
+    /// `500 Internal Server Error`.
+    Enforce,
+
+    /// Logs the violation as a warning, and sends the response as it is.
+    LogOnly,
+
+    /// Does not validate the responses.
+    Disabled,
+}
+
+impl Default for ResponseMode {
+    fn default() -> Self {
+        if cfg!(debug_assertions) {
+            ResponseMode::Enforce
+        } else {
+            ResponseMode::Disabled
+        }
+    }
+}
+
+/// An extension trait for attaching the schemas to a `Config`.
+pub trait ValidateSchemaExt: Sized {
+    /// Validates the request bodies of the routes in this configuration against `schema`.
+    fn validate_request(self, schema: impl Into<Schema>) -> SchemaValidation<Self> {
+        SchemaValidation::new(self).validate_request(schema)
+    }
+
+    /// Validates the response bodies of the routes in this configuration against `schema`.
+    fn validate_response(self, schema: impl Into<Schema>) -> SchemaValidation<Self> {
+        SchemaValidation::new(self).validate_response(schema)
+    }
+}
+
+impl<T> ValidateSchemaExt for T {}
+
+/// A `Config` that applies the schema validation to the inner configuration.
+///
+/// The value of this type is created by the methods of `ValidateSchemaExt`.
+#[derive(Debug)]
+pub struct SchemaValidation<T> {
+    config: T,
+    request: Option<Schema>,
+    response: Option<Schema>,
+    response_mode: ResponseMode,
+}
+
+impl<T> SchemaValidation<T> {
+    fn new(config: T) -> Self {
+        Self {
+            config,
+            request: None,
+            response: None,
+            response_mode: ResponseMode::default(),
+        }
+    }
+
+    /// Sets the schema of the request bodies.
+    pub fn validate_request(self, schema: impl Into<Schema>) -> Self {
+        Self {
+            request: Some(schema.into()),
+            ..self
+        }
+    }
+
+    /// Sets the schema of the response bodies.
+    pub fn validate_response(self, schema: impl Into<Schema>) -> Self {
+        Self {
+            response: Some(schema.into()),
+            ..self
+        }
+    }
+
+    /// Sets the behavior when a response violates the schema.
+    ///
+    /// The default value is `Enforce` in the debug builds, and `Disabled` otherwise.
+    pub fn response_mode(self, response_mode: ResponseMode) -> Self {
+        Self {
+            response_mode,
+            ..self
+        }
+    }
+}
+
+impl<T, M, C> Config<M, C> for SchemaValidation<T>
+where
+    for<'a> T: Config<Chain<&'a M, SchemaValidator>, C>,
+    C: Concurrency,
+{
+    type Error = ConfigError;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> Result<(), Self::Error> {
+        let compile = |schema: Option<Schema>| -> Result<_, ConfigError> {
+            match schema {
+                Some(schema) => schema.compile().map(Some).map_err(ConfigError::custom),
+                None => Ok(None),
+            }
+        };
+        let validator = SchemaValidator {
+            request: compile(self.request)?,
+            response: match self.response_mode {
+                ResponseMode::Disabled => None,
+                _ => compile(self.response)?,
+            },
+            response_mode: self.response_mode,
+        };
+        scope.modify(validator, self.config)
+    }
+}
+
+/// A `ModifyHandler` that validates the request/response bodies.
+///
+/// The value of this type is created by `SchemaValidation` when the application is created.
+#[derive(Clone)]
+pub struct SchemaValidator {
+    request: Option<Arc<JSONSchema>>,
+    response: Option<Arc<JSONSchema>>,
+    response_mode: ResponseMode,
+}
+
+impl fmt::Debug for SchemaValidator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SchemaValidator")
+            .field("request", &self.request.is_some())
+            .field("response", &self.response.is_some())
+            .field("response_mode", &self.response_mode)
+            .finish()
+    }
+}
+
+/// A violation of the schema, reported by `SchemaViolations`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Violation {
+    /// The JSON pointer to the invalid value.
+    pub pointer: String,
+    /// The description of the violation.
+    pub message: String,
+}
+
+/// The error type returned when the request body violates the schema.
+///
+/// This error is rendered as `400 Bad Request` with the JSON body which lists the violations.
+#[derive(Debug)]
+pub struct SchemaViolations {
+    violations: Vec<Violation>,
+}
+
+impl SchemaViolations {
+    /// Returns the list of violations.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+}
+
+impl fmt::Display for SchemaViolations {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("the body violates the schema: ")?;
+        for (i, violation) in self.violations.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", violation.pointer, violation.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl HttpError for SchemaViolations {
+    type Body = ResponseBody;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        #[derive(Serialize)]
+        struct Body<'a> {
+            errors: &'a [Violation],
+        }
+
+        let body = serde_json::to_vec(&Body {
+            errors: &self.violations,
+        })
+        .expect("should be serializable");
+        Response::builder()
+            .status(StatusCode::BAD_REQUEST)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .expect("should be a valid response")
+    }
+}
+
+fn validate(schema: &JSONSchema, data: &[u8]) -> Result<(), SchemaViolations> {
+    let instance: Value = serde_json::from_slice(data).map_err(|err| SchemaViolations {
+        violations: vec![Violation {
+            pointer: String::new(),
+            message: format!("invalid JSON: {}", err),
+        }],
+    })?;
+    schema
+        .validate(&instance)
+        .map_err(|errors| SchemaViolations {
+            violations: errors
+                .map(|error| Violation {
+                    pointer: error.instance_path.to_string(),
+                    message: error.to_string(),
+                })
+                .collect(),
+        })
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|s| s.parse::<mime::Mime>().ok())
+        .map(|mime| {
+            mime.type_() == mime::APPLICATION
+                && (mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON))
+        })
+        .unwrap_or(false)
+}
+
+impl<H> ModifyHandler<H> for SchemaValidator
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Response<ResponseBody>;
+    type Handler = SchemaValidatorHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        SchemaValidatorHandler {
+            inner,
+            validator: self.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct SchemaValidatorHandler<H> {
+    inner: H,
+    validator: SchemaValidator,
+}
+
+impl<H> Handler for SchemaValidatorHandler<H>
+where
+    H: Handler,
+    H::Output: Responder,
+{
+    type Output = Response<ResponseBody>;
+    type Error = Error;
+    type Handle = HandleSchemaValidator<H::Handle>;
+
+    fn handle(&self) -> Self::Handle {
+        HandleSchemaValidator {
+            state: State::Init(self.inner.handle()),
+            validator: self.validator.clone(),
+        }
+    }
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleSchemaValidator<H>
+where
+    H: TryFuture,
+    H::Ok: Responder,
+{
+    state: State<H, <H::Ok as Responder>::Respond>,
+    validator: SchemaValidator,
+}
+
+enum State<H, R> {
+    Init(H),
+    ReadRequest(ReadBody, H),
+    Handle(H),
+    Respond(R),
+    ReadResponse(http::response::Parts, ResponseBody, BytesMut),
+    Done,
+}
+
+impl<H> TryFuture for HandleSchemaValidator<H>
+where
+    H: TryFuture,
+    H::Ok: Responder,
+{
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match std::mem::replace(&mut self.state, State::Done) {
+                State::Init(handle) => match self.validator.request {
+                    Some(..)
+                        if !is_safe_method(input.request.method())
+                            && is_json(input.request.headers()) =>
+                    {
+                        State::ReadRequest(ReadBody::start(input)?, handle)
+                    }
+                    _ => State::Handle(handle),
+                },
+                State::ReadRequest(mut read_body, handle) => {
+                    let data = match read_body.poll()? {
+                        Async::Ready(data) => data,
+                        Async::NotReady => {
+                            self.state = State::ReadRequest(read_body, handle);
+                            return Ok(Async::NotReady);
+                        }
+                    };
+                    if let Some(ref schema) = self.validator.request {
+                        validate(schema, &data)?;
+                    }
+                    RequestBody::from(data).insert_into(input.locals);
+                    State::Handle(handle)
+                }
+                State::Handle(mut handle) => match handle.poll_ready(input).map_err(Into::into)? {
+                    Async::Ready(output) => State::Respond(output.respond()),
+                    Async::NotReady => {
+                        self.state = State::Handle(handle);
+                        return Ok(Async::NotReady);
+                    }
+                },
+                State::Respond(mut respond) => {
+                    let response: Response<ResponseBody> =
+                        match respond.poll_ready(input).map_err(Into::into)? {
+                            Async::Ready(output) => output
+                                .respond_to(input)
+                                .map_err(Into::into)?
+                                .map(Into::into),
+                            Async::NotReady => {
+                                self.state = State::Respond(respond);
+                                return Ok(Async::NotReady);
+                            }
+                        };
+                    match self.validator.response {
+                        Some(..)
+                            if input.request.method() != Method::HEAD
+                                && response.status().is_success()
+                                && is_json(response.headers()) =>
+                        {
+                            let (parts, body) = response.into_parts();
+                            State::ReadResponse(parts, body, BytesMut::new())
+                        }
+                        _ => return Ok(Async::Ready(response)),
+                    }
+                }
+                State::ReadResponse(parts, mut body, mut buf) => {
+                    loop {
+                        match body
+                            .poll_data()
+                            .map_err(crate::error::internal_server_error)?
+                        {
+                            Async::Ready(Some(chunk)) => buf.extend_from_slice(&chunk),
+                            Async::Ready(None) => break,
+                            Async::NotReady => {
+                                self.state = State::ReadResponse(parts, body, buf);
+                                return Ok(Async::NotReady);
+                            }
+                        }
+                    }
+                    let data = buf.freeze();
+                    return self.check_response(input.request, parts, data);
+                }
+                State::Done => panic!("the future has already polled"),
+            };
+        }
+    }
+}
+
+impl<H> HandleSchemaValidator<H>
+where
+    H: TryFuture,
+    H::Ok: Responder,
+{
+    fn check_response(
+        &self,
+        request: &Request<()>,
+        parts: http::response::Parts,
+        data: Bytes,
+    ) -> Poll<Response<ResponseBody>, Error> {
+        let schema = self
+            .validator
+            .response
+            .as_ref()
+            .expect("the response schema should be available");
+        if let Err(violations) = validate(schema, &data) {
+            if self.validator.response_mode == ResponseMode::Enforce {
+                log::error!(
+                    "invalid response to {} {}: {}",
+                    request.method(),
+                    request.uri(),
+                    violations
+                );
+                return Err(crate::error::internal_server_error(
+                    "the response violates the schema",
+                ));
+            }
+            log::warn!(
+                "invalid response to {} {}: {}",
+                request.method(),
+                request.uri(),
+                violations
+            );
+        }
+        Ok(Async::Ready(Response::from_parts(parts, data.into())))
+    }
+}
+
+fn is_safe_method(method: &Method) -> bool {
+    *method == Method::GET || *method == Method::HEAD || *method == Method::OPTIONS
+}
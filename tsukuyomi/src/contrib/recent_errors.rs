@@ -0,0 +1,339 @@
+//! A bounded in-memory log of the recent server errors, for debugging in the
+//! environments without log aggregation.
+//!
+//! `RecentErrors` is fed by `config::observe_errors` and keeps the last N errors
+//! whose responses have `5xx` status codes.  The recorded errors can be inspected
+//! through the endpoint registered by `RecentErrors::admin`, which renders them as
+//! JSON or HTML according to `Accept`.  The endpoint exposes the internal error
+//! messages, so it must be protected by the guard passed to `admin`.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, guard::Decision, App};
+//! use tsukuyomi::contrib::recent_errors::RecentErrors;
+//!
+//! let recent = RecentErrors::new(50).redact("token");
+//! let admin_guard = tsukuyomi::guard::client_cert(|chain| {
+//!     // verify the client certificate of the operators.
+//! #   let _ = chain;
+//!     Decision::Allow
+//! });
+//!
+//! let app = App::create(chain![
+//!     observe_errors({
+//!         let recent = recent.clone();
+//!         move |event| recent.record(event)
+//!     }),
+//!     path!("/")
+//!         .to(endpoint::call(|| -> tsukuyomi::Result<&'static str> {
+//!             Err(tsukuyomi::error::internal_server_error("oops"))
+//!         })),
+//!     mount("/admin/recent-errors").with(recent.admin(admin_guard)),
+//! ]);
+//! # app.unwrap();
+//! ```
+
+use {
+    crate::{
+        app::config::{Concurrency, Config, Scope},
+        error::{Error, ErrorEvent},
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+        output::{
+            html::escape,
+            negotiate::{negotiate, Negotiate},
+        },
+    },
+    http::{header::HeaderName, Method, Uri},
+    serde::Serialize,
+    std::{
+        fmt::{self, Write as _Write},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+    },
+    url::form_urlencoded,
+};
+
+const REDACTED: &str = "[REDACTED]";
+
+/// A bounded buffer that keeps the most recent server errors.
+///
+/// The clones share the same buffer.  The writers only lock the slot that they
+/// overwrite, so concurrent recordings do not contend unless the buffer wraps
+/// around within them.
+#[derive(Clone)]
+pub struct RecentErrors {
+    buffer: Arc<Buffer>,
+    redacted: Arc<Vec<String>>,
+    request_id_header: HeaderName,
+}
+
+struct Buffer {
+    slots: Vec<Mutex<Option<ErrorRecord>>>,
+    next: AtomicUsize,
+}
+
+impl fmt::Debug for RecentErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RecentErrors")
+            .field("capacity", &self.capacity())
+            .field("redacted", &self.redacted)
+            .field("request_id_header", &self.request_id_header)
+            .finish()
+    }
+}
+
+impl RecentErrors {
+    /// Creates a `RecentErrors` that keeps up to `capacity` errors.
+    ///
+    /// # Panics
+    /// This function panics if `capacity` is zero.
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must be positive");
+        Self {
+            buffer: Arc::new(Buffer {
+                slots: (0..capacity).map(|_| Mutex::new(None)).collect(),
+                next: AtomicUsize::new(0),
+            }),
+            redacted: Arc::new(vec![]),
+            request_id_header: HeaderName::from_static("x-request-id"),
+        }
+    }
+
+    /// Adds the name of a query parameter whose value is masked in the recorded URIs.
+    pub fn redact(mut self, name: impl Into<String>) -> Self {
+        Arc::make_mut(&mut self.redacted).push(name.into());
+        self
+    }
+
+    /// Sets the name of header field used as the request ID.
+    ///
+    /// The default value is `X-Request-Id`.
+    pub fn request_id_header(self, name: HeaderName) -> Self {
+        Self {
+            request_id_header: name,
+            ..self
+        }
+    }
+
+    /// Returns the maximum number of errors kept in the buffer.
+    pub fn capacity(&self) -> usize {
+        self.buffer.slots.len()
+    }
+
+    /// Returns the number of errors recorded so far, including the evicted ones.
+    pub fn total(&self) -> usize {
+        self.buffer.next.load(Ordering::SeqCst)
+    }
+
+    /// Records an error if its response has a `5xx` status code.
+    ///
+    /// This method is intended to be called from the function passed to
+    /// `config::observe_errors`.
+    pub fn record(&self, event: &ErrorEvent<'_>) {
+        // the errors after the response head has been sent are always on the server side.
+        if !event.status().is_server_error() && !event.committed() {
+            return;
+        }
+
+        let request = event.request();
+        let seq = self.buffer.next.fetch_add(1, Ordering::SeqCst);
+        let record = ErrorRecord {
+            seq,
+            time: time::at_utc(time::get_time()).rfc3339().to_string(),
+            method: request.method().to_string(),
+            uri: self.redact_uri(request.uri()),
+            status: event.status().as_u16(),
+            request_id: request
+                .headers()
+                .get(&self.request_id_header)
+                .map(|h| String::from_utf8_lossy(h.as_bytes()).into_owned()),
+            chain: event.chain().to_vec(),
+        };
+
+        let mut slot = self.buffer.slots[seq % self.capacity()]
+            .lock()
+            .expect("the slot should not be poisoned");
+        // a slower writer must not overwrite the newer record.
+        match *slot {
+            Some(ref current) if current.seq > seq => {}
+            _ => *slot = Some(record),
+        }
+    }
+
+    /// Returns a snapshot of the recorded errors, from the oldest to the newest.
+    pub fn entries(&self) -> Vec<ErrorRecord> {
+        let mut entries: Vec<ErrorRecord> = self
+            .buffer
+            .slots
+            .iter()
+            .filter_map(|slot| {
+                slot.lock()
+                    .expect("the slot should not be poisoned")
+                    .clone()
+            })
+            .collect();
+        entries.sort_by_key(|entry| entry.seq);
+        entries
+    }
+
+    fn redact_uri(&self, uri: &Uri) -> String {
+        let query = match uri.query() {
+            Some(query) if !self.redacted.is_empty() => query,
+            _ => return uri.to_string(),
+        };
+        let query = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(
+                form_urlencoded::parse(query.as_bytes()).map(|(name, value)| {
+                    if self.redacted.iter().any(|redacted| *redacted == name) {
+                        (name, REDACTED.into())
+                    } else {
+                        (name, value)
+                    }
+                }),
+            )
+            .finish();
+        format!("{}?{}", uri.path(), query)
+    }
+
+    /// Creates a `Config` that registers the endpoint showing the recorded errors,
+    /// protected by the specified guard.
+    ///
+    /// The endpoint is registered at the root of the current scope and accepts
+    /// only `GET`.  The errors are rendered as JSON by default, or as HTML if
+    /// the client prefers `text/html`.
+    pub fn admin<G>(&self, guard: G) -> Admin<G> {
+        Admin {
+            recent: self.clone(),
+            guard,
+        }
+    }
+}
+
+/// A recorded server error.
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorRecord {
+    /// The sequence number of this error.
+    pub seq: usize,
+    /// The time when the error was recorded, in RFC 3339 format.
+    pub time: String,
+    /// The request method.
+    pub method: String,
+    /// The request URI, with the redacted query parameters masked.
+    pub uri: String,
+    /// The status code of the response.
+    pub status: u16,
+    /// The request ID, if the request has it.
+    pub request_id: Option<String>,
+    /// The messages of the error and its causes.
+    pub chain: Vec<String>,
+}
+
+/// A `Config` that registers the endpoint showing the recent errors.
+///
+/// The value of this type is created by `RecentErrors::admin`.
+#[derive(Debug)]
+pub struct Admin<G> {
+    recent: RecentErrors,
+    guard: G,
+}
+
+impl<G, M, C> Config<M, C> for Admin<G>
+where
+    G: ModifyHandler<AdminHandler>,
+    M: ModifyHandler<G::Handler>,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = crate::config::Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> crate::app::Result<()> {
+        scope.route(
+            "/",
+            self.guard.modify(AdminHandler {
+                recent: self.recent,
+                allowed_methods: Method::GET.into(),
+            }),
+        )
+    }
+}
+
+/// The `Handler` of the endpoint registered by `Admin`.
+#[derive(Debug)]
+pub struct AdminHandler {
+    recent: RecentErrors,
+    allowed_methods: AllowedMethods,
+}
+
+impl Handler for AdminHandler {
+    type Output = Negotiate<Vec<ErrorRecord>>;
+    type Error = Error;
+    type Handle = HandleAdmin; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        Some(&self.allowed_methods)
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleAdmin {
+            recent: self.recent.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleAdmin {
+    recent: RecentErrors,
+}
+
+impl TryFuture for HandleAdmin {
+    type Ok = Negotiate<Vec<ErrorRecord>>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if input.request.method() != Method::GET {
+            return Err(http::StatusCode::METHOD_NOT_ALLOWED.into());
+        }
+        let capacity = self.recent.capacity();
+        Ok(Async::Ready(negotiate(self.recent.entries()).html(
+            move |entries| Ok::<_, Error>(render_html(entries, capacity)),
+        )))
+    }
+}
+
+fn render_html(entries: &[ErrorRecord], capacity: usize) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        "<!DOCTYPE html>\n<html>\n<head><title>Recent errors</title></head>\n<body>\n\
+         <h1>Recent errors ({} of the last {})</h1>\n<table>\n\
+         <tr><th>#</th><th>time</th><th>request</th><th>status</th><th>request id</th><th>error</th></tr>\n",
+        entries.len(),
+        capacity,
+    );
+    for entry in entries.iter().rev() {
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{} {}</td><td>{}</td><td>{}</td><td>",
+            entry.seq,
+            escape(&entry.time),
+            escape(&entry.method),
+            escape(&entry.uri),
+            entry.status,
+            escape(entry.request_id.as_ref().map_or("-", |id| id.as_str())),
+        );
+        for (i, message) in entry.chain.iter().enumerate() {
+            if i > 0 {
+                html.push_str("<br>caused by: ");
+            }
+            html.push_str(&escape(message));
+        }
+        html.push_str("</td></tr>\n");
+    }
+    html.push_str("</table>\n</body>\n</html>\n");
+    html
+}
@@ -0,0 +1,670 @@
+//! A minimal protocol for resumable uploads.
+//!
+//! The protocol is a subset of [tus](https://tus.io/), with the additional support
+//! of `Content-Range` on `PUT`:
+//!
+//! * `POST <prefix>` with the header `Upload-Length` creates a new upload and
+//!   returns `201 Created` with its location in `Location`.
+//! * `HEAD <prefix>/<id>` reports the number of bytes received so far in `Upload-Offset`.
+//! * `PATCH <prefix>/<id>` with `Upload-Offset: <offset>`, or `PUT <prefix>/<id>` with
+//!   `Content-Range: bytes <start>-<end>/<length>`, appends the request body to the
+//!   upload.  The offset must be equal to the current one; otherwise the request is
+//!   rejected with `409 Conflict`.
+//!
+//! The received chunks are written into the store as soon as they arrive, so when the
+//! connection is lost in the middle of a transfer the client can ask the current offset
+//! by `HEAD` and resume the upload from there.  Once all bytes have been received,
+//! the callback passed to `Uploads::new` is called with the assembled content and then
+//! the upload is removed from the store.
+//!
+//! The concurrent writes to the same upload are rejected with `423 Locked`.  The uploads
+//! being written are tracked in the memory of the process, so this exclusion does not
+//! work across the processes sharing a store (e.g. the instances behind a load balancer).
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, App};
+//! use std::io::Read;
+//! use tsukuyomi::contrib::upload::{Completed, FsStore, Uploads};
+//!
+//! # fn main() -> Result<(), failure::Error> {
+//! let store = FsStore::new(std::env::temp_dir().join("tsukuyomi-uploads"))?;
+//!
+//! let app = App::create(
+//!     mount("/uploads").with(
+//!         Uploads::new(store, |mut upload: Completed| {
+//!             let mut content = vec![];
+//!             upload.read_to_end(&mut content)?;
+//!             println!("received {} bytes", content.len());
+//!             Ok(())
+//!         })
+//!         .max_size(16 * 1024 * 1024),
+//!     ),
+//! )?;
+//! # drop(app);
+//! # Ok(())
+//! # }
+//! ```
+
+use {
+    crate::{
+        app::config::{Concurrency, Config, Scope},
+        error::Error,
+        fs::{blocking_io, PendingIo},
+        future::TryFuture,
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::{body::RequestBody, localmap::LocalData, Input},
+        util::generate_id,
+    },
+    bytes::Bytes,
+    futures01::{Async, Poll},
+    http::{
+        header::{HeaderMap, HeaderName, CACHE_CONTROL, CONTENT_RANGE, LOCATION},
+        Method, Request, Response, StatusCode,
+    },
+    hyper::body::Payload,
+    std::{
+        collections::HashSet,
+        fmt,
+        fs::{File, OpenOptions},
+        io::{self, Read, Write},
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    },
+};
+
+const UPLOAD_LENGTH: &str = "upload-length";
+const UPLOAD_OFFSET: &str = "upload-offset";
+
+/// The default value of the maximum size of an upload (1 GiB).
+pub const DEFAULT_MAX_SIZE: u64 = 1024 * 1024 * 1024;
+
+// ==== store ====
+
+/// The progress of an upload.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UploadStatus {
+    /// The number of bytes received so far.
+    pub offset: u64,
+    /// The total number of bytes declared at the creation.
+    pub length: u64,
+}
+
+/// A trait representing the storage of uploads in progress.
+///
+/// The methods are called in the blocking section of the thread pool,
+/// so the implementors may perform blocking I/O.
+pub trait UploadStore: Send + Sync + 'static {
+    /// Creates a new empty upload with the specified total length.
+    fn create(&self, id: &str, length: u64) -> io::Result<()>;
+
+    /// Returns the progress of the upload, or `None` if it does not exist.
+    fn status(&self, id: &str) -> io::Result<Option<UploadStatus>>;
+
+    /// Appends a chunk to the upload.
+    ///
+    /// The implementors must reject the chunk if `offset` is not equal to
+    /// the number of bytes stored so far.
+    fn append(&self, id: &str, offset: u64, chunk: &[u8]) -> io::Result<()>;
+
+    /// Opens the assembled content of the upload.
+    fn open(&self, id: &str) -> io::Result<Box<dyn Read + Send>>;
+
+    /// Removes the upload from the store.
+    fn remove(&self, id: &str) -> io::Result<()>;
+}
+
+/// An implementor of `UploadStore` which stores the uploads in a directory.
+///
+/// Each upload is stored as a pair of files, `<id>.part` for the received bytes and
+/// `<id>.info` for the declared length.
+#[derive(Debug, Clone)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    /// Creates a `FsStore` with the specified directory, creating it if necessary.
+    pub fn new(root: impl Into<PathBuf>) -> io::Result<Self> {
+        let root = root.into();
+        std::fs::create_dir_all(&root)?;
+        Ok(Self { root })
+    }
+
+    fn data_path(&self, id: &str) -> PathBuf {
+        self.root.join(format!("{}.part", id))
+    }
+
+    fn info_path(&self, id: &str) -> PathBuf {
+        self.root.join(format!("{}.info", id))
+    }
+}
+
+impl UploadStore for FsStore {
+    fn create(&self, id: &str, length: u64) -> io::Result<()> {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(self.data_path(id))?;
+        std::fs::write(self.info_path(id), length.to_string())
+    }
+
+    fn status(&self, id: &str) -> io::Result<Option<UploadStatus>> {
+        let info = match std::fs::read_to_string(self.info_path(id)) {
+            Ok(info) => info,
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        let length = info
+            .trim()
+            .parse()
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let offset = std::fs::metadata(self.data_path(id))?.len();
+        Ok(Some(UploadStatus { offset, length }))
+    }
+
+    fn append(&self, id: &str, offset: u64, chunk: &[u8]) -> io::Result<()> {
+        let mut file = OpenOptions::new().append(true).open(self.data_path(id))?;
+        if file.metadata()?.len() != offset {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the chunk is not contiguous to the stored data",
+            ));
+        }
+        file.write_all(chunk)?;
+        file.flush()
+    }
+
+    fn open(&self, id: &str) -> io::Result<Box<dyn Read + Send>> {
+        Ok(Box::new(File::open(self.data_path(id))?))
+    }
+
+    fn remove(&self, id: &str) -> io::Result<()> {
+        std::fs::remove_file(self.data_path(id))?;
+        std::fs::remove_file(self.info_path(id))
+    }
+}
+
+// ==== completion ====
+
+/// The assembled content of a completed upload, passed to the callback.
+pub struct Completed {
+    id: String,
+    length: u64,
+    reader: Box<dyn Read + Send>,
+}
+
+impl fmt::Debug for Completed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Completed")
+            .field("id", &self.id)
+            .field("length", &self.length)
+            .finish()
+    }
+}
+
+impl Completed {
+    /// Returns the identifier of the upload.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns the total number of bytes of the upload.
+    pub fn length(&self) -> u64 {
+        self.length
+    }
+}
+
+impl Read for Completed {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+// ==== config ====
+
+/// A configuration type for adding the endpoints of resumable uploads to the scope.
+///
+/// The endpoints are registered at `/` and `/:id` of the current scope.
+#[derive(Debug)]
+pub struct Uploads<S, F> {
+    store: S,
+    on_complete: F,
+    max_size: u64,
+}
+
+impl<S, F> Uploads<S, F>
+where
+    S: UploadStore,
+    F: Fn(Completed) -> io::Result<()> + Send + Sync + 'static,
+{
+    /// Creates an `Uploads` with the specified store and the callback called
+    /// when an upload has been completed.
+    ///
+    /// The callback is called on a background thread rather than on the worker
+    /// threads of the runtime, so it may take a while to process the content.
+    pub fn new(store: S, on_complete: F) -> Self {
+        Self {
+            store,
+            on_complete,
+            max_size: DEFAULT_MAX_SIZE,
+        }
+    }
+
+    /// Sets the maximum number of bytes of an upload.
+    ///
+    /// The creation of an upload whose length exceeds this value is rejected
+    /// with `413 Payload Too Large`.  The default value is `DEFAULT_MAX_SIZE`.
+    pub fn max_size(self, max_size: u64) -> Self {
+        Self { max_size, ..self }
+    }
+}
+
+impl<S, F, M, C> Config<M, C> for Uploads<S, F>
+where
+    S: UploadStore,
+    F: Fn(Completed) -> io::Result<()> + Send + Sync + 'static,
+    M: ModifyHandler<UploadHandler<S, F>>,
+    M::Handler: Into<C::Handler>,
+    C: Concurrency,
+{
+    type Error = crate::config::Error;
+
+    fn configure(self, scope: &mut Scope<'_, M, C>) -> crate::app::Result<()> {
+        let inner = Arc::new(Inner {
+            store: self.store,
+            on_complete: self.on_complete,
+            max_size: self.max_size,
+            writers: Arc::new(Mutex::new(HashSet::new())),
+        });
+
+        scope.route(
+            "/",
+            UploadHandler {
+                inner: inner.clone(),
+                kind: Kind::Collection,
+                allowed_methods: Method::POST.into(),
+            },
+        )?;
+        scope.route(
+            "/:id",
+            UploadHandler {
+                inner,
+                kind: Kind::Upload,
+                allowed_methods: vec![Method::HEAD, Method::PATCH, Method::PUT]
+                    .into_iter()
+                    .collect(),
+            },
+        )?;
+
+        Ok(())
+    }
+}
+
+// ==== handler ====
+
+#[derive(Debug)]
+struct Inner<S, F> {
+    store: S,
+    on_complete: F,
+    max_size: u64,
+    writers: Arc<Mutex<HashSet<String>>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Kind {
+    Collection,
+    Upload,
+}
+
+/// The `Handler` of the endpoints registered by `Uploads`.
+#[derive(Debug)]
+pub struct UploadHandler<S, F> {
+    inner: Arc<Inner<S, F>>,
+    kind: Kind,
+    allowed_methods: AllowedMethods,
+}
+
+impl<S, F> Handler for UploadHandler<S, F>
+where
+    S: UploadStore,
+    F: Fn(Completed) -> io::Result<()> + Send + Sync + 'static,
+{
+    type Output = Response<()>;
+    type Error = Error;
+    type Handle = HandleUpload<S, F>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        Some(&self.allowed_methods)
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleUpload {
+            inner: self.inner.clone(),
+            kind: self.kind,
+            state: State::Init,
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleUpload<S, F> {
+    inner: Arc<Inner<S, F>>,
+    kind: Kind,
+    state: State,
+}
+
+enum State {
+    Init,
+    Create { id: String, length: u64 },
+    Status { id: String },
+    Write(Box<Transfer>),
+}
+
+struct Transfer {
+    id: String,
+    _guard: WriterGuard,
+    body: RequestBody,
+    phase: Phase,
+    start: u64,
+    end: Option<u64>,
+    total: Option<u64>,
+    offset: u64,
+    length: u64,
+    pending: Option<Bytes>,
+    completing: PendingIo<()>,
+}
+
+enum Phase {
+    Check,
+    Receive,
+    Complete,
+}
+
+/// The mark that the upload is being written by a request.
+///
+/// The marks are shared only within the process.
+struct WriterGuard {
+    writers: Arc<Mutex<HashSet<String>>>,
+    id: String,
+}
+
+impl WriterGuard {
+    fn acquire(writers: &Arc<Mutex<HashSet<String>>>, id: &str) -> Option<Self> {
+        if writers.lock().unwrap().insert(id.to_owned()) {
+            Some(Self {
+                writers: writers.clone(),
+                id: id.to_owned(),
+            })
+        } else {
+            None
+        }
+    }
+}
+
+impl Drop for WriterGuard {
+    fn drop(&mut self) {
+        self.writers.lock().unwrap().remove(&self.id);
+    }
+}
+
+impl<S, F> TryFuture for HandleUpload<S, F>
+where
+    S: UploadStore,
+    F: Fn(Completed) -> io::Result<()> + Send + Sync + 'static,
+{
+    type Ok = Response<()>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        loop {
+            self.state = match self.state {
+                State::Init => self.start(input)?,
+                State::Create { ref id, length } => {
+                    let store = &self.inner.store;
+                    futures01::try_ready!(blocking_io(|| store.create(id, length)));
+                    let location = format!(
+                        "{}/{}",
+                        input.request.uri().path().trim_end_matches('/'),
+                        id
+                    );
+                    return Ok(Async::Ready(
+                        Response::builder()
+                            .status(StatusCode::CREATED)
+                            .header(LOCATION, location)
+                            .body(())
+                            .expect("should be a valid response"),
+                    ));
+                }
+                State::Status { ref id } => {
+                    let store = &self.inner.store;
+                    let status = futures01::try_ready!(blocking_io(|| store.status(id)))
+                        .ok_or_else(|| crate::error::not_found("no such upload"))?;
+                    return Ok(Async::Ready(
+                        Response::builder()
+                            .header(UPLOAD_OFFSET, status.offset)
+                            .header(UPLOAD_LENGTH, status.length)
+                            .header(CACHE_CONTROL, "no-store")
+                            .body(())
+                            .expect("should be a valid response"),
+                    ));
+                }
+                State::Write(ref mut write) => {
+                    let offset = futures01::try_ready!(write.poll_write(&self.inner));
+                    return Ok(Async::Ready(
+                        Response::builder()
+                            .status(StatusCode::NO_CONTENT)
+                            .header(UPLOAD_OFFSET, offset)
+                            .body(())
+                            .expect("should be a valid response"),
+                    ));
+                }
+            };
+        }
+    }
+}
+
+impl<S, F> HandleUpload<S, F>
+where
+    S: UploadStore,
+{
+    fn start(&mut self, input: &mut Input<'_>) -> crate::error::Result<State> {
+        let request = input.request;
+
+        if let Kind::Collection = self.kind {
+            let length = parse_header::<u64>(request.headers(), UPLOAD_LENGTH)?
+                .ok_or_else(|| crate::error::bad_request("missing Upload-Length"))?;
+            if length > self.inner.max_size {
+                return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+            }
+            return Ok(State::Create {
+                id: generate_id()?,
+                length,
+            });
+        }
+
+        let id = input
+            .params
+            .as_ref()
+            .and_then(|params| params.name("id"))
+            .filter(|id| is_valid_id(id))
+            .ok_or_else(|| crate::error::not_found("no such upload"))?
+            .to_owned();
+
+        let (start, end, total) = match *request.method() {
+            Method::HEAD => return Ok(State::Status { id }),
+            Method::PATCH => {
+                let start = parse_header(request.headers(), UPLOAD_OFFSET)?
+                    .ok_or_else(|| crate::error::bad_request("missing Upload-Offset"))?;
+                (start, None, None)
+            }
+            Method::PUT => {
+                let (start, end, total) = parse_content_range(request)?;
+                (start, Some(end), Some(total))
+            }
+            _ => return Err(StatusCode::METHOD_NOT_ALLOWED.into()),
+        };
+
+        let guard = WriterGuard::acquire(&self.inner.writers, &id).ok_or_else(|| {
+            crate::error::custom(StatusCode::LOCKED, "the upload is being written")
+        })?;
+
+        let body = RequestBody::take_from(input.locals).ok_or_else(|| {
+            crate::error::internal_server_error("the request body has already been stolen")
+        })?;
+
+        Ok(State::Write(Box::new(Transfer {
+            id,
+            _guard: guard,
+            body,
+            phase: Phase::Check,
+            start,
+            end,
+            total,
+            offset: start,
+            length: 0,
+            pending: None,
+            completing: PendingIo::default(),
+        })))
+    }
+}
+
+impl Transfer {
+    /// Drives the transfer and returns the offset after receiving the request body.
+    fn poll_write<S, F>(&mut self, inner: &Arc<Inner<S, F>>) -> Poll<u64, Error>
+    where
+        S: UploadStore,
+        F: Fn(Completed) -> io::Result<()> + Send + Sync + 'static,
+    {
+        let store = &inner.store;
+        loop {
+            match self.phase {
+                Phase::Check => {
+                    let id = &self.id;
+                    let status = futures01::try_ready!(blocking_io(|| store.status(id)))
+                        .ok_or_else(|| crate::error::not_found("no such upload"))?;
+                    if self.total.is_some() && self.total != Some(status.length) {
+                        return Err(crate::error::bad_request(
+                            "the complete length in Content-Range does not match Upload-Length",
+                        ));
+                    }
+                    if self.start != status.offset {
+                        return Err(crate::error::custom(
+                            StatusCode::CONFLICT,
+                            format!("the current offset is {}", status.offset),
+                        ));
+                    }
+                    self.length = status.length;
+                    self.phase = Phase::Receive;
+                }
+
+                Phase::Receive => {
+                    if let Some(ref chunk) = self.pending {
+                        let (id, offset) = (&self.id, self.offset);
+                        futures01::try_ready!(blocking_io(|| store.append(id, offset, chunk)));
+                        self.offset += chunk.len() as u64;
+                        self.pending = None;
+                        continue;
+                    }
+
+                    match futures01::try_ready!(self.body.poll_data()) {
+                        Some(chunk) => {
+                            let next_offset = self.offset + chunk.len() as u64;
+                            if next_offset > self.length {
+                                return Err(StatusCode::PAYLOAD_TOO_LARGE.into());
+                            }
+                            if let Some(end) = self.end {
+                                if next_offset > end + 1 {
+                                    return Err(crate::error::bad_request(
+                                        "the request body exceeds the range in Content-Range",
+                                    ));
+                                }
+                            }
+                            self.pending = Some(Bytes::from(chunk));
+                        }
+                        None if self.offset == self.length => self.phase = Phase::Complete,
+                        None => return Ok(Async::Ready(self.offset)),
+                    }
+                }
+
+                Phase::Complete => {
+                    // The callback is user code that may process the whole content, so it
+                    // is run on the background threads instead of holding a blocking section.
+                    let (id, length) = (&self.id, self.length);
+                    futures01::try_ready!(self.completing.poll_background(|| {
+                        let (inner, id) = (inner.clone(), id.clone());
+                        move || {
+                            let reader = inner.store.open(&id)?;
+                            (inner.on_complete)(Completed {
+                                id: id.clone(),
+                                length,
+                                reader,
+                            })?;
+                            inner.store.remove(&id)
+                        }
+                    }));
+                    return Ok(Async::Ready(self.offset));
+                }
+            }
+        }
+    }
+}
+
+// ==== helpers ====
+
+fn parse_header<T>(headers: &HeaderMap, name: &'static str) -> crate::error::Result<Option<T>>
+where
+    T: std::str::FromStr,
+{
+    match headers.get(HeaderName::from_static(name)) {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(|value| value.trim().parse().ok())
+            .map(Some)
+            .ok_or_else(|| crate::error::bad_request(format!("invalid header: {}", name))),
+        None => Ok(None),
+    }
+}
+
+/// Parses the header value of the form `bytes <start>-<end>/<length>`.
+fn parse_content_range(request: &Request<()>) -> crate::error::Result<(u64, u64, u64)> {
+    let invalid = || crate::error::bad_request("invalid Content-Range");
+
+    let value = request
+        .headers()
+        .get(CONTENT_RANGE)
+        .ok_or_else(|| crate::error::bad_request("missing Content-Range"))?
+        .to_str()
+        .map_err(|_| invalid())?;
+
+    let range = value.trim().trim_start_matches("bytes ");
+    let mut iter = range.splitn(2, '/');
+    let (range, total) = match (iter.next(), iter.next()) {
+        (Some(range), Some(total)) => (range, total),
+        _ => return Err(invalid()),
+    };
+    let mut iter = range.splitn(2, '-');
+    let (start, end) = match (iter.next(), iter.next()) {
+        (Some(start), Some(end)) => (start, end),
+        _ => return Err(invalid()),
+    };
+
+    let start: u64 = start.parse().map_err(|_| invalid())?;
+    let end: u64 = end.parse().map_err(|_| invalid())?;
+    let total: u64 = total.parse().map_err(|_| invalid())?;
+    if start > end || end >= total {
+        return Err(invalid());
+    }
+
+    Ok((start, end, total))
+}
+
+fn is_valid_id(id: &str) -> bool {
+    id.len() == 32
+        && id.bytes().all(|b| match b {
+            b'0'..=b'9' | b'a'..=b'f' => true,
+            _ => false,
+        })
+}
@@ -0,0 +1,732 @@
+//! Delivery of outbound webhooks, with signing and retries.
+//!
+//! This module is available only if the feature `webhook` is enabled.
+//!
+//! `Dispatcher` serializes the events into JSON and posts them to the registered
+//! subscribers in the background, on the runtime that drives the server.  Each
+//! request is signed with the secret of the subscriber, in the same format as the
+//! signatures verified by the inbound webhook handlers:
+//!
+//! ```text
+//! Webhook-Signature: t=<unix time>,v1=<hex(HMAC-SHA256(secret, "<unix time>." + payload))>
+//! ```
+//!
+//! The failed deliveries (the errors, the timeouts and the non-`2xx` responses) are
+//! retried with exponential backoff, and the ones that still fail after the maximum
+//! number of attempts are passed to the dead-letter callback.
+//!
+//! The background worker is shut down by `Dispatcher::close`, or when all clones of
+//! the dispatcher are dropped, so it does not keep the runtime alive.
+//!
+//! # Example
+//!
+//! ```
+//! # use serde::Serialize;
+//! # use std::sync::Arc;
+//! # use tsukuyomi::{config::prelude::*, extractor, App};
+//! use tsukuyomi::contrib::webhook::Dispatcher;
+//!
+//! #[derive(Serialize)]
+//! struct OrderCreated {
+//!     id: u64,
+//! }
+//!
+//! let dispatcher = Dispatcher::new()
+//!     .max_attempts(5)
+//!     .on_dead_letter(|dead| eprintln!("gave up delivering {} to {}", dead.id, dead.url));
+//! dispatcher.subscribe("http://127.0.0.1:4000/hooks".parse().unwrap(), "whsec_test");
+//!
+//! let app = App::create(chain![
+//!     state(dispatcher),
+//!     path!("/orders").to(endpoint::post()
+//!         .extract(extractor::state())
+//!         .call(|dispatcher: Arc<Dispatcher>| {
+//!             dispatcher
+//!                 .dispatch(&OrderCreated { id: 42 })
+//!                 .map(|_| "created")
+//!         })),
+//! ]);
+//! # app.unwrap();
+//! ```
+
+use {
+    crate::error::Error,
+    bytes::Bytes,
+    futures01::{
+        future::{self, Either, Loop},
+        sync::{mpsc, oneshot},
+        Future, Stream,
+    },
+    hmac::{Hmac, Mac},
+    http::{
+        header::{HeaderName, HeaderValue, CONTENT_TYPE},
+        Method, Request, Uri,
+    },
+    hyper::{
+        client::{connect::Connect, ResponseFuture},
+        Body, Client,
+    },
+    serde::Serialize,
+    sha2::Sha256,
+    std::{
+        collections::VecDeque,
+        fmt,
+        sync::{
+            atomic::{AtomicBool, AtomicUsize, Ordering},
+            Arc, Mutex, RwLock, Weak,
+        },
+        time::{Duration, Instant},
+    },
+    tokio_timer::{Delay, Timeout},
+};
+
+type SendFn = dyn Fn(Request<Body>) -> ResponseFuture + Send + Sync + 'static;
+type DeadLetterFn = dyn Fn(&DeadLetter) + Send + Sync + 'static;
+
+/// A dispatcher of the outbound webhooks.
+///
+/// The clones share the subscribers, the delivery queue and the history, so the
+/// value is typically registered as a state of the application and extracted by
+/// the handlers with `extractor::state`.  The settings must be specified before
+/// the first dispatch.
+#[derive(Clone)]
+pub struct Dispatcher {
+    config: Arc<Config>,
+    shared: Arc<Shared>,
+}
+
+#[derive(Clone)]
+struct Config {
+    signature_header: HeaderName,
+    max_attempts: u32,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    timeout: Duration,
+    max_concurrency: usize,
+    queue_capacity: usize,
+    history: usize,
+    send: Arc<SendFn>,
+    dead_letter: Option<Arc<DeadLetterFn>>,
+}
+
+struct Shared {
+    subscribers: RwLock<Vec<Subscriber>>,
+    queue: Mutex<Option<Queue>>,
+    closed: AtomicBool,
+    deliveries: Mutex<VecDeque<Delivery>>,
+    next_id: AtomicUsize,
+    counters: Counters,
+}
+
+#[derive(Default)]
+struct Counters {
+    dispatched: AtomicUsize,
+    attempts: AtomicUsize,
+    retries: AtomicUsize,
+    delivered: AtomicUsize,
+    dead_lettered: AtomicUsize,
+    in_flight: AtomicUsize,
+}
+
+/// The sending half of the channel to the worker.
+///
+/// The worker is notified of the shutdown when `_close` is dropped.
+struct Queue {
+    tx: mpsc::Sender<Job>,
+    _close: oneshot::Sender<()>,
+}
+
+/// The background worker, which holds the shared state only weakly so that
+/// dropping all clones of `Dispatcher` shuts it down.
+#[derive(Clone)]
+struct Worker {
+    config: Arc<Config>,
+    shared: Weak<Shared>,
+    closed: future::Shared<oneshot::Receiver<()>>,
+}
+
+#[derive(Clone)]
+struct Subscriber {
+    url: Uri,
+    secret: Arc<Vec<u8>>,
+}
+
+struct Job {
+    id: usize,
+    url: Uri,
+    secret: Arc<Vec<u8>>,
+    payload: Bytes,
+}
+
+impl fmt::Debug for Dispatcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Dispatcher")
+            .field("signature_header", &self.config.signature_header)
+            .field("max_attempts", &self.config.max_attempts)
+            .field("initial_backoff", &self.config.initial_backoff)
+            .field("max_backoff", &self.config.max_backoff)
+            .field("timeout", &self.config.timeout)
+            .field("max_concurrency", &self.config.max_concurrency)
+            .field("queue_capacity", &self.config.queue_capacity)
+            .field("history", &self.config.history)
+            .field("metrics", &self.metrics())
+            .finish()
+    }
+}
+
+impl Default for Dispatcher {
+    fn default() -> Self {
+        let client = Client::new();
+        Self {
+            config: Arc::new(Config {
+                signature_header: HeaderName::from_static("webhook-signature"),
+                max_attempts: 8,
+                initial_backoff: Duration::from_secs(1),
+                max_backoff: Duration::from_secs(300),
+                timeout: Duration::from_secs(10),
+                max_concurrency: 16,
+                queue_capacity: 1024,
+                history: 1000,
+                send: Arc::new(move |request| client.request(request)),
+                dead_letter: None,
+            }),
+            shared: Arc::new(Shared {
+                subscribers: RwLock::new(vec![]),
+                queue: Mutex::new(None),
+                closed: AtomicBool::new(false),
+                deliveries: Mutex::new(VecDeque::new()),
+                next_id: AtomicUsize::new(0),
+                counters: Counters::default(),
+            }),
+        }
+    }
+}
+
+impl Dispatcher {
+    /// Creates a `Dispatcher` with the default settings.
+    ///
+    /// The default client supports only `http` URLs.  Use `Dispatcher::client` to
+    /// deliver the webhooks over TLS.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the HTTP client used for sending the webhooks.
+    pub fn client<C>(mut self, client: Client<C>) -> Self
+    where
+        C: Connect + Sync + 'static,
+        C::Transport: 'static,
+        C::Future: 'static,
+    {
+        Arc::make_mut(&mut self.config).send = Arc::new(move |request| client.request(request));
+        self
+    }
+
+    /// Sets the name of header field that contains the signature.
+    ///
+    /// The default value is `Webhook-Signature`.
+    pub fn signature_header(mut self, name: HeaderName) -> Self {
+        Arc::make_mut(&mut self.config).signature_header = name;
+        self
+    }
+
+    /// Sets the maximum number of attempts for each delivery, including the first one.
+    ///
+    /// The default value is `8`.
+    ///
+    /// # Panics
+    /// This method panics if `max_attempts` is zero.
+    pub fn max_attempts(mut self, max_attempts: u32) -> Self {
+        assert!(
+            max_attempts > 0,
+            "the maximum number of attempts must be positive"
+        );
+        Arc::make_mut(&mut self.config).max_attempts = max_attempts;
+        self
+    }
+
+    /// Sets the delay before the first retry and the upper limit of the delays.
+    ///
+    /// The delay is doubled for each retry.  The default values are 1 second and
+    /// 5 minutes.
+    pub fn backoff(mut self, initial: Duration, max: Duration) -> Self {
+        let config = Arc::make_mut(&mut self.config);
+        config.initial_backoff = initial;
+        config.max_backoff = max;
+        self
+    }
+
+    /// Sets the timeout of each attempt.
+    ///
+    /// The default value is 10 seconds.
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        Arc::make_mut(&mut self.config).timeout = timeout;
+        self
+    }
+
+    /// Sets the maximum number of deliveries processed concurrently.
+    ///
+    /// The deliveries waiting for their retries are also counted.
+    /// The default value is `16`.
+    ///
+    /// # Panics
+    /// This method panics if `max_concurrency` is zero.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        assert!(
+            max_concurrency > 0,
+            "the maximum concurrency must be positive"
+        );
+        Arc::make_mut(&mut self.config).max_concurrency = max_concurrency;
+        self
+    }
+
+    /// Sets the maximum number of deliveries waiting for the worker.
+    ///
+    /// The deliveries dispatched while the queue is full are passed to the
+    /// dead-letter callback without any attempts.  The default value is `1024`.
+    ///
+    /// # Panics
+    /// This method panics if `capacity` is zero.
+    pub fn queue_capacity(mut self, capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity of the queue must be positive");
+        Arc::make_mut(&mut self.config).queue_capacity = capacity;
+        self
+    }
+
+    /// Sets the maximum number of deliveries kept for `Dispatcher::deliveries`.
+    ///
+    /// The default value is `1000`.
+    pub fn history(mut self, history: usize) -> Self {
+        Arc::make_mut(&mut self.config).history = history;
+        self
+    }
+
+    /// Sets the function called with the deliveries that failed after the maximum
+    /// number of attempts.
+    pub fn on_dead_letter<F>(mut self, f: F) -> Self
+    where
+        F: Fn(&DeadLetter) + Send + Sync + 'static,
+    {
+        Arc::make_mut(&mut self.config).dead_letter = Some(Arc::new(f));
+        self
+    }
+
+    /// Registers a subscriber with the secret used for signing the payloads.
+    pub fn subscribe(&self, url: Uri, secret: impl Into<Vec<u8>>) {
+        self.shared
+            .subscribers
+            .write()
+            .expect("the subscribers should not be poisoned")
+            .push(Subscriber {
+                url,
+                secret: Arc::new(secret.into()),
+            });
+    }
+
+    /// Removes the subscribers registered with the specified URL.
+    pub fn unsubscribe(&self, url: &Uri) {
+        self.shared
+            .subscribers
+            .write()
+            .expect("the subscribers should not be poisoned")
+            .retain(|subscriber| subscriber.url != *url);
+    }
+
+    /// Serializes the event into JSON and schedules its delivery to all subscribers.
+    ///
+    /// The returned values are the IDs of the scheduled deliveries, which can be
+    /// passed to `Dispatcher::delivery`.  The deliveries are processed on the
+    /// default executor, so this method must be called within the runtime (e.g.
+    /// from the handlers).
+    ///
+    /// This method fails after the dispatcher is closed.
+    pub fn dispatch<T>(&self, event: &T) -> Result<Vec<usize>, Error>
+    where
+        T: Serialize + ?Sized,
+    {
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Err(crate::error::internal_server_error(
+                "the webhook dispatcher has been closed",
+            ));
+        }
+        let payload: Bytes = serde_json::to_vec(event)
+            .map_err(crate::error::internal_server_error)?
+            .into();
+        let subscribers = self
+            .shared
+            .subscribers
+            .read()
+            .expect("the subscribers should not be poisoned")
+            .clone();
+
+        let mut ids = Vec::with_capacity(subscribers.len());
+        for subscriber in subscribers {
+            let id = self.shared.next_id.fetch_add(1, Ordering::SeqCst);
+            self.shared
+                .counters
+                .dispatched
+                .fetch_add(1, Ordering::SeqCst);
+            self.record(Delivery {
+                id,
+                url: subscriber.url.to_string(),
+                attempts: 0,
+                state: DeliveryState::Pending,
+                last_error: None,
+            });
+            self.enqueue(Job {
+                id,
+                url: subscriber.url,
+                secret: subscriber.secret,
+                payload: payload.clone(),
+            });
+            ids.push(id);
+        }
+        Ok(ids)
+    }
+
+    /// Stops accepting the events and shuts down the background worker.
+    ///
+    /// The requests in flight are completed, and the deliveries still in the queue
+    /// or waiting for their retries are passed to the dead-letter callback.
+    pub fn close(&self) {
+        let mut queue = self
+            .shared
+            .queue
+            .lock()
+            .expect("the queue should not be poisoned");
+        self.shared.closed.store(true, Ordering::SeqCst);
+        queue.take();
+    }
+
+    /// Returns the state of the specified delivery, if it is still in the history.
+    pub fn delivery(&self, id: usize) -> Option<Delivery> {
+        self.shared
+            .deliveries
+            .lock()
+            .expect("the deliveries should not be poisoned")
+            .iter()
+            .rev()
+            .find(|delivery| delivery.id == id)
+            .cloned()
+    }
+
+    /// Returns a snapshot of the recent deliveries, from the oldest to the newest.
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        self.shared
+            .deliveries
+            .lock()
+            .expect("the deliveries should not be poisoned")
+            .iter()
+            .cloned()
+            .collect()
+    }
+
+    /// Returns a snapshot of the counters.
+    pub fn metrics(&self) -> Metrics {
+        let counters = &self.shared.counters;
+        Metrics {
+            dispatched: counters.dispatched.load(Ordering::SeqCst),
+            attempts: counters.attempts.load(Ordering::SeqCst),
+            retries: counters.retries.load(Ordering::SeqCst),
+            delivered: counters.delivered.load(Ordering::SeqCst),
+            dead_lettered: counters.dead_lettered.load(Ordering::SeqCst),
+            in_flight: counters.in_flight.load(Ordering::SeqCst),
+        }
+    }
+
+    fn record(&self, delivery: Delivery) {
+        let mut deliveries = self
+            .shared
+            .deliveries
+            .lock()
+            .expect("the deliveries should not be poisoned");
+        deliveries.push_back(delivery);
+        while deliveries.len() > self.config.history {
+            deliveries.pop_front();
+        }
+    }
+
+    fn enqueue(&self, job: Job) {
+        // the dead-letter callback is called after releasing the lock.
+        if let Some((job, reason)) = self.try_enqueue(job) {
+            dead_letter(&self.config, Some(&self.shared), &job, 0, reason.into());
+        }
+    }
+
+    /// Sends the job to the worker, or returns it back with the reason of rejection.
+    fn try_enqueue(&self, job: Job) -> Option<(Job, &'static str)> {
+        let mut queue = self
+            .shared
+            .queue
+            .lock()
+            .expect("the queue should not be poisoned");
+
+        if self.shared.closed.load(Ordering::SeqCst) {
+            return Some((job, "the dispatcher has been closed"));
+        }
+
+        let job = match *queue {
+            Some(ref mut queue) => match queue.tx.try_send(job) {
+                Ok(()) => return None,
+                Err(err) => {
+                    if err.is_full() {
+                        return Some((err.into_inner(), "the delivery queue is full"));
+                    }
+                    // the worker has gone with the previous runtime.
+                    err.into_inner()
+                }
+            },
+            None => job,
+        };
+
+        let (mut tx, rx) = mpsc::channel(self.config.queue_capacity - 1);
+        let (close, closed) = oneshot::channel();
+        let worker = Worker {
+            config: self.config.clone(),
+            shared: Arc::downgrade(&self.shared),
+            closed: closed.shared(),
+        };
+        hyper::rt::spawn(
+            rx.map(move |job| worker.deliver(job))
+                .buffer_unordered(self.config.max_concurrency)
+                .for_each(|()| Ok(())),
+        );
+        tx.try_send(job)
+            .unwrap_or_else(|_| unreachable!("the receiver should be alive"));
+        *queue = Some(Queue { tx, _close: close });
+        None
+    }
+}
+
+impl Shared {
+    fn update(&self, id: usize, f: impl FnOnce(&mut Delivery)) {
+        let mut deliveries = self
+            .deliveries
+            .lock()
+            .expect("the deliveries should not be poisoned");
+        if let Some(delivery) = deliveries.iter_mut().rev().find(|d| d.id == id) {
+            f(delivery);
+        }
+    }
+}
+
+impl Worker {
+    fn with_shared(&self, f: impl FnOnce(&Shared)) {
+        if let Some(shared) = self.shared.upgrade() {
+            f(&shared);
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.closed.peek().is_some()
+    }
+
+    fn dead_letter(&self, job: &Job, attempts: u32, error: String) {
+        let shared = self.shared.upgrade();
+        dead_letter(
+            &self.config,
+            shared.as_ref().map(|shared| &**shared),
+            job,
+            attempts,
+            error,
+        );
+    }
+
+    fn deliver(&self, job: Job) -> impl Future<Item = (), Error = ()> + Send + 'static {
+        if self.is_closed() {
+            self.dead_letter(&job, 0, "the dispatcher has been closed".into());
+            return Either::A(future::ok(()));
+        }
+
+        let worker = self.clone();
+        let job = Arc::new(job);
+        Either::B(future::loop_fn(1, move |attempt| {
+            let worker = worker.clone();
+            let job = job.clone();
+            worker.with_shared(|shared| {
+                shared.counters.attempts.fetch_add(1, Ordering::SeqCst);
+                shared.counters.in_flight.fetch_add(1, Ordering::SeqCst);
+                shared.update(job.id, |delivery| delivery.attempts = attempt);
+            });
+
+            let request = worker.build_request(&job);
+            Timeout::new((worker.config.send)(request), worker.config.timeout).then(move |result| {
+                worker.with_shared(|shared| {
+                    shared.counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+                });
+
+                let error = match result {
+                    Ok(ref response) if response.status().is_success() => {
+                        worker.with_shared(|shared| {
+                            shared.counters.delivered.fetch_add(1, Ordering::SeqCst);
+                            shared.update(job.id, |delivery| {
+                                delivery.state = DeliveryState::Delivered;
+                            });
+                        });
+                        return Either::A(future::ok(Loop::Break(())));
+                    }
+                    Ok(response) => {
+                        format!("the subscriber responded with {}", response.status())
+                    }
+                    Err(ref err) if err.is_elapsed() => "the request timed out".to_owned(),
+                    Err(err) => err.to_string(),
+                };
+
+                if attempt >= worker.config.max_attempts || worker.is_closed() {
+                    worker.dead_letter(&job, attempt, error);
+                    return Either::A(future::ok(Loop::Break(())));
+                }
+
+                worker.with_shared(|shared| {
+                    shared.counters.retries.fetch_add(1, Ordering::SeqCst);
+                    shared.update(job.id, |delivery| {
+                        delivery.state = DeliveryState::Retrying;
+                        delivery.last_error = Some(error.clone());
+                    });
+                });
+                let backoff = worker.retry_delay(attempt);
+                Either::B(
+                    Delay::new(Instant::now() + backoff)
+                        .select2(worker.closed.clone())
+                        .then(move |result| match result {
+                            Ok(Either::A(..)) | Err(Either::A(..)) => {
+                                Ok(Loop::Continue(attempt + 1))
+                            }
+                            // the dispatcher has been closed during the backoff.
+                            Ok(Either::B(..)) | Err(Either::B(..)) => {
+                                worker.dead_letter(&job, attempt, error);
+                                Ok(Loop::Break(()))
+                            }
+                        }),
+                )
+            })
+        }))
+    }
+
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let factor = 1u32.checked_shl(attempt - 1).unwrap_or(u32::max_value());
+        self.config
+            .initial_backoff
+            .checked_mul(factor)
+            .map_or(self.config.max_backoff, |backoff| {
+                std::cmp::min(backoff, self.config.max_backoff)
+            })
+    }
+
+    fn build_request(&self, job: &Job) -> Request<Body> {
+        let timestamp = time::get_time().sec;
+        let signature = format!(
+            "t={},v1={}",
+            timestamp,
+            sign(&job.secret, timestamp, &job.payload)
+        );
+
+        let mut request = Request::new(Body::from(job.payload.clone()));
+        *request.method_mut() = Method::POST;
+        *request.uri_mut() = job.url.clone();
+        request
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        request.headers_mut().insert(
+            self.config.signature_header.clone(),
+            HeaderValue::from_shared(signature.into()).expect("should be a valid header value"),
+        );
+        request
+    }
+}
+
+/// Records the failed delivery and passes it to the dead-letter callback.
+fn dead_letter(config: &Config, shared: Option<&Shared>, job: &Job, attempts: u32, error: String) {
+    if let Some(shared) = shared {
+        shared.counters.dead_lettered.fetch_add(1, Ordering::SeqCst);
+        shared.update(job.id, |delivery| {
+            delivery.state = DeliveryState::DeadLettered;
+            delivery.last_error = Some(error.clone());
+        });
+    }
+    if let Some(ref dead_letter) = config.dead_letter {
+        dead_letter(&DeadLetter {
+            id: job.id,
+            url: job.url.clone(),
+            payload: job.payload.clone(),
+            attempts,
+            last_error: error,
+        });
+    }
+}
+
+/// Computes the signature of the payload, as the lowercase hexadecimal string.
+///
+/// The signed content is the timestamp and the payload joined with `.`.
+pub fn sign(secret: &[u8], timestamp: i64, payload: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_varkey(secret).expect("HMAC accepts the keys of any size");
+    mac.input(timestamp.to_string().as_bytes());
+    mac.input(b".");
+    mac.input(payload);
+    mac.result()
+        .code()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// The state of a delivery.
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    /// The ID of this delivery.
+    pub id: usize,
+    /// The URL of the subscriber.
+    pub url: String,
+    /// The number of attempts made so far.
+    pub attempts: u32,
+    /// The current state.
+    pub state: DeliveryState,
+    /// The message of the last failure, if any.
+    pub last_error: Option<String>,
+}
+
+/// The kinds of state of a delivery.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    /// The first attempt has not been completed yet.
+    Pending,
+    /// At least one attempt has failed, and the next one is scheduled.
+    Retrying,
+    /// The subscriber has accepted the payload.
+    Delivered,
+    /// All attempts have failed.
+    DeadLettered,
+}
+
+/// A delivery that has failed after the maximum number of attempts.
+#[derive(Debug, Clone)]
+pub struct DeadLetter {
+    /// The ID of the delivery.
+    pub id: usize,
+    /// The URL of the subscriber.
+    pub url: Uri,
+    /// The serialized payload.
+    pub payload: Bytes,
+    /// The number of attempts.
+    pub attempts: u32,
+    /// The message of the last failure.
+    pub last_error: String,
+}
+
+/// A snapshot of the counters of a `Dispatcher`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Metrics {
+    /// The number of scheduled deliveries.
+    pub dispatched: usize,
+    /// The number of attempts, including the retries.
+    pub attempts: usize,
+    /// The number of retries scheduled after the failed attempts.
+    pub retries: usize,
+    /// The number of successful deliveries.
+    pub delivered: usize,
+    /// The number of deliveries passed to the dead-letter callback.
+    pub dead_lettered: usize,
+    /// The number of requests in flight.
+    pub in_flight: usize,
+}
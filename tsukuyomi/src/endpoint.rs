@@ -26,7 +26,7 @@ pub trait Endpoint<T> {
 }
 
 #[derive(Debug)]
-pub struct ApplyContext<'a, 'task: 'a> {
+pub struct ApplyContext<'a, 'task> {
     input: &'a mut Input<'task>,
 }
 
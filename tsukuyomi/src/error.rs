@@ -205,13 +205,13 @@ pub struct Error {
 
 impl fmt::Debug for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        (self.fmt_debug_fn)(&self.obj, formatter)
+        (self.fmt_debug_fn)(&*self.obj, formatter)
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
-        (self.fmt_display_fn)(&self.obj, formatter)
+        (self.fmt_display_fn)(&*self.obj, formatter)
     }
 }
 
@@ -285,3 +285,43 @@ impl Error {
         (self.into_response_fn)(self.obj, request)
     }
 }
+
+// ==== ErrorEvent ====
+
+/// The information about an error passed to the functions registered by
+/// `Scope::observe_errors`.
+#[derive(Debug)]
+pub struct ErrorEvent<'a> {
+    pub(crate) request: &'a Request<()>,
+    pub(crate) status: StatusCode,
+    pub(crate) chain: &'a [String],
+    pub(crate) committed: bool,
+}
+
+impl<'a> ErrorEvent<'a> {
+    /// Returns the request in which the error occurred.
+    pub fn request(&self) -> &Request<()> {
+        self.request
+    }
+
+    /// Returns the status code of the response converted from the error.
+    ///
+    /// If the error occurred after the response head has been sent, this is the
+    /// status code already sent to the client.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns `true` if the error occurred after the response head has been sent.
+    ///
+    /// In that case the response body has been truncated, and the request returned
+    /// by `request` has no extensions.
+    pub fn committed(&self) -> bool {
+        self.committed
+    }
+
+    /// Returns the messages of the error and its causes, starting from the error itself.
+    pub fn chain(&self) -> &[String] {
+        self.chain
+    }
+}
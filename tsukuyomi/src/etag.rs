@@ -0,0 +1,170 @@
+//! The entity tags used in the header fields for conditional requests.
+
+use std::{fmt, str::FromStr};
+
+/// An entity tag, the opaque validator of a representation (RFC 7232, section 2.3).
+///
+/// The value of this type is displayed in the format used in the header field `ETag`,
+/// e.g. `"xyzzy"` or `W/"xyzzy"`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ETag {
+    weak: bool,
+    tag: String,
+}
+
+impl ETag {
+    /// Creates a strong entity tag.
+    ///
+    /// # Panics
+    /// This function panics if the tag contains characters other than the visible
+    /// ASCII characters except `"`.
+    pub fn strong(tag: impl Into<String>) -> Self {
+        Self::new(false, tag.into())
+    }
+
+    /// Creates a weak entity tag.
+    ///
+    /// # Panics
+    /// This function panics if the tag contains characters other than the visible
+    /// ASCII characters except `"`.
+    pub fn weak(tag: impl Into<String>) -> Self {
+        Self::new(true, tag.into())
+    }
+
+    /// Computes a weak entity tag from the content of a buffered body.
+    ///
+    /// The tag consists of the length and the 64-bit FNV-1a hash of the content,
+    /// so it is stable across the processes and the server instances.
+    pub fn from_body(body: impl AsRef<[u8]>) -> Self {
+        let body = body.as_ref();
+        let hash = fnv1a(FNV_OFFSET_BASIS, body);
+        Self::weak(format!("{:x}-{:016x}", body.len(), hash))
+    }
+
+    fn new(weak: bool, tag: String) -> Self {
+        assert!(
+            tag.bytes().all(|b| b.is_ascii_graphic() && b != b'"'),
+            "the entity tag must consist of visible ASCII characters except '\"'"
+        );
+        Self { weak, tag }
+    }
+
+    /// Returns `true` if this entity tag is weak.
+    pub fn is_weak(&self) -> bool {
+        self.weak
+    }
+
+    /// Returns the opaque tag, without the quotes and the weakness indicator.
+    pub fn tag(&self) -> &str {
+        &self.tag
+    }
+
+    fn parse_inner(weak: bool, s: &str) -> Result<Self, failure::Error> {
+        if s.len() < 2 {
+            failure::bail!("");
+        }
+        if !s.starts_with('"') || !s.ends_with('"') {
+            failure::bail!("");
+        }
+
+        let tag = &s[1..s.len() - 1];
+        if !tag.bytes().all(|b| b.is_ascii_graphic() && b != b'"') {
+            failure::bail!("");
+        }
+
+        Ok(Self {
+            weak,
+            tag: tag.to_owned(),
+        })
+    }
+
+    /// Compares two entity tags with the strong comparison (RFC 7232, section 2.3.2).
+    pub fn strong_eq(&self, other: &Self) -> bool {
+        !self.weak && !other.weak && self.tag == other.tag
+    }
+
+    /// Compares two entity tags with the weak comparison (RFC 7232, section 2.3.2).
+    pub fn weak_eq(&self, other: &Self) -> bool {
+        self.tag == other.tag
+    }
+}
+
+impl FromStr for ETag {
+    type Err = failure::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.get(0..3) {
+            Some("W/\"") if s[2..].starts_with('"') => Self::parse_inner(true, &s[2..]),
+            Some(t) if t.starts_with('"') => Self::parse_inner(false, s),
+            Some(..) => failure::bail!("invalid string to parse ETag"),
+            None => failure::bail!("empty string to parse ETag"),
+        }
+    }
+}
+
+impl fmt::Display for ETag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.weak {
+            f.write_str("W/")?;
+        }
+        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+/// The initial value of the 64-bit FNV-1a hash.
+pub(crate) const FNV_OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+
+/// Updates the 64-bit FNV-1a hash with the specified bytes.
+///
+/// The hash is used for the entity tags computed from the contents, which must be
+/// stable across the processes and the server instances.
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, &b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_and_display() {
+        let etag: ETag = "\"abc\"".parse().unwrap();
+        assert_eq!(etag, ETag::strong("abc"));
+        assert_eq!(etag.to_string(), "\"abc\"");
+
+        let etag: ETag = "W/\"abc\"".parse().unwrap();
+        assert_eq!(etag, ETag::weak("abc"));
+        assert_eq!(etag.to_string(), "W/\"abc\"");
+
+        assert!("abc".parse::<ETag>().is_err());
+        assert!("\"abc".parse::<ETag>().is_err());
+        assert!("".parse::<ETag>().is_err());
+    }
+
+    #[test]
+    fn weak_comparison() {
+        assert!(ETag::strong("a").weak_eq(&ETag::strong("a")));
+        assert!(ETag::weak("a").weak_eq(&ETag::strong("a")));
+        assert!(ETag::weak("a").weak_eq(&ETag::weak("a")));
+        assert!(!ETag::weak("a").weak_eq(&ETag::weak("b")));
+    }
+
+    #[test]
+    fn from_body() {
+        let etag = ETag::from_body("hello");
+        assert!(etag.is_weak());
+        assert_eq!(etag.to_string(), "W/\"5-a430d84680aabd0b\"");
+        assert_eq!(etag, ETag::from_body(&b"hello"[..]));
+        assert_ne!(etag, ETag::from_body("hello!"));
+    }
+
+    #[test]
+    fn strong_comparison() {
+        assert!(ETag::strong("a").strong_eq(&ETag::strong("a")));
+        assert!(!ETag::strong("a").strong_eq(&ETag::strong("b")));
+        assert!(!ETag::weak("a").strong_eq(&ETag::strong("a")));
+        assert!(!ETag::strong("a").strong_eq(&ETag::weak("a")));
+    }
+}
@@ -1,19 +1,27 @@
 //! Definition of `Extractor` and its implementors.
 
+pub mod accept;
 pub mod body;
+pub mod connection;
 pub mod ext;
+pub mod guard;
 pub mod header;
 pub mod local;
 pub mod method;
+pub mod param;
+pub mod range;
+pub mod tls;
+pub mod validate;
 
-pub use self::ext::ExtractorExt;
+pub use self::{ext::ExtractorExt, method::switch as method_switch};
 
 use {
+    self::range::{Range, RangeNotSatisfiable},
     crate::{
         error::Error,
         future::TryFuture,
         generic::Tuple,
-        input::Input,
+        input::{localmap::LocalData, Input},
         util::Never, //
     },
     serde::de::DeserializeOwned,
@@ -244,6 +252,70 @@ pub fn version() -> impl Extractor<
     self::ready(|input| Ok((input.request.version(),)))
 }
 
+/// Creates an `Extractor` that returns the metadata of the connection on which the request was received.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::extractor::{self, connection::ConnectionInfo};
+/// let endpoint = |info: ConnectionInfo| {
+///     if info.version() == http::Version::HTTP_2 {
+///         "served over HTTP/2"
+///     } else {
+///         "served over HTTP/1.x"
+///     }
+/// };
+/// # let _ = (extractor::connection_info(), endpoint);
+/// ```
+pub fn connection_info() -> impl Extractor<
+    Output = (self::connection::ConnectionInfo,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (self::connection::ConnectionInfo,), Error = Never> + Send + 'static,
+> {
+    self::connection::info()
+}
+
+/// Creates an `Extractor` that returns a copy of the request head along with the entire request body.
+///
+/// The buffered body is put back into the context as with `body::buffered`, and hence the
+/// body extractors placed after this one can still decode it. Note that the extension map
+/// of the returned `Parts` is always empty, since the extensions cannot be cloned.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// # use bytes::Bytes;
+/// # #[derive(serde::Deserialize)]
+/// # struct Event {}
+/// let app = App::create(
+///     path!("/webhook").to(endpoint::post()
+///         .extract(extractor::request_parts())
+///         .extract(extractor::body::json())
+///         .call(|parts: http::request::Parts, raw: Bytes, event: Event| {
+///             // verify the signature in `parts.headers` against `raw`, and then handle `event`.
+/// #           drop((parts, raw, event));
+///             "ok"
+///         })),
+/// );
+/// # app.unwrap();
+/// ```
+pub fn request_parts() -> impl Extractor<
+    Output = (http::request::Parts, bytes::Bytes), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (http::request::Parts, bytes::Bytes), Error = Error> + Send + 'static,
+> {
+    self::ready(|input| {
+        let (mut parts, ()) = http::Request::new(()).into_parts();
+        parts.method = input.request.method().clone();
+        parts.uri = input.request.uri().clone();
+        parts.version = input.request.version();
+        parts.headers = input.request.headers().clone();
+        Ok::<_, Never>((parts,))
+    })
+    .and(self::body::buffered())
+}
+
 /// Creates an `Extractor` that parses the value of query string to `T`.
 pub fn query<T>() -> impl Extractor<
     Output = (T,), //
@@ -283,3 +355,194 @@ where
             .ok_or_else(|| crate::error::internal_server_error("missing extension"))
     })
 }
+
+/// Creates an `Extractor` that returns a clone of the request-local data of type `T`.
+///
+/// The value is typically inserted by a `ModifyHandler` in front of the endpoint
+/// (e.g. the principal authenticated by a guard), so that the endpoints can obtain it
+/// without re-parsing the request.  If the value is missing, the extraction fails with
+/// an internal server error.  Use `ExtractorExt::optional` to receive it as an `Option<T>`.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{
+/// #     extractor::{self, ExtractorExt},
+/// #     input::localmap::{local_key, LocalData},
+/// # };
+/// #[derive(Clone)]
+/// struct CurrentUser(String);
+///
+/// impl LocalData for CurrentUser {
+///     local_key! {
+///         const KEY: Self;
+///     }
+/// }
+///
+/// let current_user = extractor::local::<CurrentUser>();
+/// let maybe_current_user = extractor::local::<CurrentUser>().optional();
+/// # drop((current_user, maybe_current_user));
+/// ```
+pub fn local<T>() -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: LocalData + Clone,
+{
+    self::local::clone(&T::KEY)
+}
+
+/// Creates an `Extractor` that parses the header field `Accept` for the content negotiation.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{extractor::{self, accept::Accept}, output::{html, json}};
+/// # use either::Either;
+/// let endpoint = |accept: Accept| -> tsukuyomi::Result<_> {
+///     let supported = [mime::TEXT_HTML, mime::APPLICATION_JSON];
+///     match *accept.negotiate(&supported)? {
+///         ref m if *m == mime::TEXT_HTML => Ok(Either::Left(html("<p>Hello</p>"))),
+///         _ => Ok(Either::Right(json(vec!["Hello"]))),
+///     }
+/// };
+/// # let _ = (extractor::accept(), endpoint);
+/// ```
+pub fn accept() -> impl Extractor<
+    Output = (self::accept::Accept,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (self::accept::Accept,), Error = Never> + Send + 'static,
+> {
+    self::accept::parse()
+}
+
+/// Creates an `Extractor` that parses the header field `Range` into a list of byte ranges.
+///
+/// See the documentation of `range::parse` for details.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor::{self, range::Range}, App};
+/// const DATA: &[u8] = b"0123456789";
+///
+/// let app = App::create(
+///     path!("/data").to(endpoint::get()
+///         .extract(extractor::range(Some(DATA.len() as u64)))
+///         .call(|range: Option<Range>| -> tsukuyomi::Result<_> {
+///             let response = match range {
+///                 Some(range) => {
+///                     let ranges = range.resolve(DATA.len() as u64)?;
+///                     let r = &ranges[0];
+///                     http::Response::builder()
+///                         .status(http::StatusCode::PARTIAL_CONTENT)
+///                         .header(http::header::CONTENT_RANGE, r.to_content_range(DATA.len() as u64))
+///                         .body(&DATA[r.first() as usize..=r.last() as usize])
+///                         .unwrap()
+///                 }
+///                 None => http::Response::new(DATA),
+///             };
+///             Ok(response)
+///         })),
+/// );
+/// # app.unwrap();
+/// ```
+pub fn range(
+    total_len_hint: Option<u64>,
+) -> impl Extractor<
+    Output = (Option<Range>,), //
+    Error = RangeNotSatisfiable,
+    Extract = impl TryFuture<Ok = (Option<Range>,), Error = RangeNotSatisfiable> + Send + 'static,
+> {
+    self::range::parse(total_len_hint)
+}
+
+/// Creates an `Extractor` that returns the effective configuration value of the specified type
+/// registered in the scope by `Scope::config`.
+///
+/// The value registered in the innermost scope of the matched route is returned, or
+/// `None` if neither the scope nor its ancestors have the value of `T`.
+pub fn scope_config<T>() -> impl Extractor<
+    Output = (Option<std::sync::Arc<T>>,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Option<std::sync::Arc<T>>,), Error = Never> + Send + 'static,
+>
+where
+    T: Send + Sync + 'static,
+{
+    use crate::app::ScopeConfigs;
+    self::ready(|input| {
+        Ok((ScopeConfigs::get(input.locals).and_then(|configs| configs.find::<T>()),))
+    })
+}
+
+/// Creates an `Extractor` that returns the part of host name matched by the wildcard
+/// subdomain of the scope (e.g. `alice` for `*.example.com`), registered by `Scope::host`.
+///
+/// If the matched scope is not constrained to a wildcard subdomain, the extraction fails
+/// with an internal server error.
+pub fn subdomain() -> impl Extractor<
+    Output = (String,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (String,), Error = Error> + Send + 'static,
+> {
+    use crate::app::Subdomain;
+    self::ready(|input| {
+        input
+            .locals
+            .get(&Subdomain::KEY)
+            .map(|subdomain| (subdomain.0.clone(),))
+            .ok_or_else(|| {
+                crate::error::internal_server_error(
+                    "the scope is not constrained to a wildcard subdomain",
+                )
+            })
+    })
+}
+
+/// Creates an `Extractor` that returns the shared value of the specified type
+/// registered in the scope.
+///
+/// If the value of `T` is not registered in the scope or its ancestors,
+/// the extraction fails with an internal server error.
+pub fn state<T>() -> impl Extractor<
+    Output = (std::sync::Arc<T>,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (std::sync::Arc<T>,), Error = Error> + Send + 'static,
+>
+where
+    T: Send + Sync + 'static,
+{
+    self::state_labeled("the requested type")
+}
+
+/// Creates an `Extractor` that behaves like `state`, except that the error message
+/// when the value is not registered refers to the type by `label`.
+///
+/// ```
+/// # use tsukuyomi::extractor;
+/// struct Database;
+/// let extractor = extractor::state_labeled::<Database>(stringify!(Database));
+/// # drop(extractor);
+/// ```
+pub fn state_labeled<T>(
+    label: &'static str,
+) -> impl Extractor<
+    Output = (std::sync::Arc<T>,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (std::sync::Arc<T>,), Error = Error> + Send + 'static,
+>
+where
+    T: Send + Sync + 'static,
+{
+    self::ready(move |input| {
+        input.state::<T>().map(|state| (state,)).ok_or_else(|| {
+            crate::error::internal_server_error(format!(
+                "the state of {} is not registered in the scope",
+                label
+            ))
+        })
+    })
+}
@@ -0,0 +1,279 @@
+//! Extractors for the content negotiation based on the header field `Accept`.
+
+use {
+    super::Extractor,
+    crate::{error::Error, future::TryFuture, util::Never},
+    http::{
+        header::{HeaderMap, ACCEPT},
+        StatusCode,
+    },
+    mime::Mime,
+    std::fmt::Write,
+};
+
+/// Creates an `Extractor` that parses the header field `Accept`.
+///
+/// The malformed elements in the header value are silently ignored, and
+/// the extraction itself never fails.
+pub fn parse() -> impl Extractor<
+    Output = (Accept,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Accept,), Error = Never> + Send + 'static,
+> {
+    super::ready(|input| Ok((Accept::from_headers(input.request.headers()),)))
+}
+
+/// The list of acceptable media ranges, sorted by the descending order of quality.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Accept {
+    ranges: Vec<MediaRange>,
+    present: bool,
+}
+
+impl Accept {
+    /// Parses the values of `Accept` in the specified header map.
+    ///
+    /// If the header map does not contain `Accept`, the returned value
+    /// accepts any media type.
+    pub fn from_headers(headers: &HeaderMap) -> Self {
+        let mut ranges = vec![];
+        let mut present = false;
+        for value in headers.get_all(ACCEPT) {
+            present = true;
+            if let Ok(value) = value.to_str() {
+                ranges.extend(split_elements(value).filter_map(MediaRange::parse));
+            }
+        }
+        Self::new(ranges, present)
+    }
+
+    /// Parses a header value of `Accept`.
+    pub fn parse(value: &str) -> Self {
+        Self::new(
+            split_elements(value)
+                .filter_map(MediaRange::parse)
+                .collect(),
+            true,
+        )
+    }
+
+    fn new(mut ranges: Vec<MediaRange>, present: bool) -> Self {
+        // stable sort: the ranges with the same quality preserve the order in the header.
+        ranges.sort_by_key(|range| std::cmp::Reverse(range.quality));
+        Self { ranges, present }
+    }
+
+    /// Returns an iterator over the media ranges, in the descending order of quality.
+    pub fn iter(&self) -> std::slice::Iter<'_, MediaRange> {
+        self.ranges.iter()
+    }
+
+    /// Returns whether the list contains no valid media range.
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    /// Returns the quality assigned to the specified media type, between `0.0` and `1.0`.
+    ///
+    /// The quality is taken from the most specific media range matching `mime`,
+    /// and is `0.0` if no range matches it.
+    pub fn quality(&self, mime: &Mime) -> f32 {
+        f32::from(self.quality_raw(mime)) / 1000.0
+    }
+
+    fn quality_raw(&self, mime: &Mime) -> u16 {
+        if !self.present {
+            return 1000;
+        }
+        self.ranges
+            .iter()
+            .filter(|range| range.matches(mime))
+            .max_by_key(|range| range.specificity())
+            .map_or(0, |range| range.quality)
+    }
+
+    /// Chooses the most preferred media type from the supported ones.
+    ///
+    /// The candidate with the highest quality is returned, and the order of `supported`
+    /// is used to break the ties. If none of the candidates are acceptable, this method
+    /// returns an error with the status code `406 Not Acceptable`.
+    pub fn negotiate<'a>(&self, supported: &'a [Mime]) -> Result<&'a Mime, Error> {
+        let mut best: Option<(&Mime, u16)> = None;
+        for mime in supported {
+            let quality = self.quality_raw(mime);
+            let is_better = match best {
+                Some((_, q)) => quality > q,
+                None => quality > 0,
+            };
+            if is_better {
+                best = Some((mime, quality));
+            }
+        }
+        best.map(|(mime, _)| mime).ok_or_else(|| {
+            crate::error::custom(
+                StatusCode::NOT_ACCEPTABLE,
+                "none of the supported media types is acceptable",
+            )
+        })
+    }
+}
+
+impl<'a> IntoIterator for &'a Accept {
+    type Item = &'a MediaRange;
+    type IntoIter = std::slice::Iter<'a, MediaRange>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.ranges.iter()
+    }
+}
+
+/// A media range in `Accept`, along with its quality.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaRange {
+    mime: Mime,
+    quality: u16,
+}
+
+impl MediaRange {
+    /// Returns the media range, without the quality and the extension parameters.
+    pub fn mime(&self) -> &Mime {
+        &self.mime
+    }
+
+    /// Returns the quality of this media range, between `0.0` and `1.0`.
+    pub fn quality(&self) -> f32 {
+        f32::from(self.quality) / 1000.0
+    }
+
+    /// Returns whether the specified media type matches this range.
+    ///
+    /// The wildcards (`*/*` and `type/*`) match any type and subtype respectively, and
+    /// the parameters of this range must be present in `mime` with the same values.
+    pub fn matches(&self, mime: &Mime) -> bool {
+        if self.mime.type_() != mime::STAR {
+            if !self
+                .mime
+                .type_()
+                .as_str()
+                .eq_ignore_ascii_case(mime.type_().as_str())
+            {
+                return false;
+            }
+            if self.mime.subtype() != mime::STAR
+                && !essence(&self.mime).eq_ignore_ascii_case(essence(mime))
+            {
+                return false;
+            }
+        }
+        self.mime
+            .params()
+            .all(|(name, value)| match mime.get_param(name.as_str()) {
+                Some(v) => v.as_str().eq_ignore_ascii_case(value.as_str()),
+                None => false,
+            })
+    }
+
+    fn specificity(&self) -> usize {
+        if self.mime.type_() == mime::STAR {
+            0
+        } else if self.mime.subtype() == mime::STAR {
+            1
+        } else {
+            2 + self.mime.params().count()
+        }
+    }
+
+    fn parse(element: &str) -> Option<Self> {
+        let parsed: Mime = element.parse().ok()?;
+        if parsed.type_() == mime::STAR && parsed.subtype() != mime::STAR {
+            return None;
+        }
+
+        // The parameters after `q` are the extension parameters and are not a part of media range.
+        let mut quality = 1000;
+        let mut essence = essence(&parsed).to_owned();
+        for (name, value) in parsed.params() {
+            if name.as_str().eq_ignore_ascii_case("q") {
+                quality = parse_quality(value.as_str())?;
+                break;
+            }
+            let value = value.as_str();
+            if value.bytes().all(is_token) && !value.is_empty() {
+                write!(essence, "; {}={}", name.as_str(), value).ok()?;
+            } else {
+                write!(essence, "; {}=\"{}\"", name.as_str(), value).ok()?;
+            }
+        }
+
+        Some(Self {
+            mime: essence.parse().ok()?,
+            quality,
+        })
+    }
+}
+
+/// Returns the media type without the parameters, e.g. `text/html` of `text/html; level=1`.
+fn essence(mime: &Mime) -> &str {
+    let s: &str = mime.as_ref();
+    s.split(';').next().unwrap_or(s).trim()
+}
+
+/// Parses a `qvalue` (RFC 7231, section 5.3.1) into the range between 0 and 1000.
+pub(crate) fn parse_quality(s: &str) -> Option<u16> {
+    let (int, frac) = match s.find('.') {
+        Some(pos) => (&s[..pos], &s[pos + 1..]),
+        None => (s, ""),
+    };
+    if frac.len() > 3 || !frac.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let frac = frac
+        .bytes()
+        .chain(std::iter::repeat(b'0'))
+        .take(3)
+        .fold(0u16, |acc, b| acc * 10 + u16::from(b - b'0'));
+    match int {
+        "0" => Some(frac),
+        "1" if frac == 0 => Some(1000),
+        _ => None,
+    }
+}
+
+/// Splits the header value at the commas which are not enclosed with quotes.
+pub(crate) fn split_elements(value: &str) -> impl Iterator<Item = &str> {
+    std::iter::repeat(()).scan(Some(value), |rest, ()| loop {
+        let s = (*rest)?;
+        let mut quoted = false;
+        let end = s
+            .char_indices()
+            .find(|&(_, c)| {
+                if c == '"' {
+                    quoted = !quoted;
+                }
+                c == ',' && !quoted
+            })
+            .map(|(i, _)| i);
+        let element = match end {
+            Some(end) => {
+                *rest = Some(&s[end + 1..]);
+                &s[..end]
+            }
+            None => {
+                *rest = None;
+                s
+            }
+        };
+        let element = element.trim();
+        if !element.is_empty() {
+            return Some(element);
+        }
+    })
+}
+
+fn is_token(b: u8) -> bool {
+    match b {
+        b'!' | b'#' | b'$' | b'%' | b'&' | b'\'' | b'*' | b'+' | b'-' | b'.' | b'^' | b'_'
+        | b'`' | b'|' | b'~' => true,
+        b => b.is_ascii_alphanumeric(),
+    }
+}
@@ -1,17 +1,31 @@
 //! Extractors for parsing message body.
 
+mod charset;
+mod multipart;
+mod nested;
+
+pub use self::{
+    multipart::{Files, FormFile},
+    nested::NestedFormError,
+};
+
 use {
+    self::charset::Charset,
     super::Extractor,
     crate::{
+        app::ScopeConfigs,
+        config::{BodyLimit, TextDecoding},
         error::Error,
-        future::{Poll, TryFuture},
+        fs::{Spool, TempUploads},
+        future::{Async, Poll, TryFuture},
         input::{body::RequestBody, header::ContentType, localmap::LocalData, Input},
     },
-    bytes::Bytes,
-    futures01::{Future, Stream},
+    bytes::{Bytes, BytesMut},
+    http::StatusCode,
+    hyper::body::Payload,
     mime::Mime,
     serde::de::DeserializeOwned,
-    std::{marker::PhantomData, str},
+    std::{marker::PhantomData, sync::Arc},
 };
 
 #[derive(Debug, failure::Fail)]
@@ -25,19 +39,33 @@ enum ExtractBodyError {
     )]
     UnexpectedContentType { expected: &'static str },
 
-    #[fail(display = "the header field `Content-type` is not a valid MIME")]
-    InvalidMime,
-
-    #[fail(display = "charset in `Content-type` must be equal to `utf-8`")]
-    NotUtf8Charset,
+    #[fail(display = "the charset `{}` is not supported", charset)]
+    UnsupportedCharset { charset: String },
 
     #[fail(display = "the content of message body is invalid: {}", cause)]
     InvalidContent { cause: failure::Error },
 }
 
+impl ExtractBodyError {
+    fn into_error(self) -> Error {
+        match self {
+            ExtractBodyError::UnsupportedCharset { .. } => {
+                crate::error::custom(StatusCode::UNSUPPORTED_MEDIA_TYPE, self)
+            }
+            _ => crate::error::bad_request(self),
+        }
+    }
+}
+
 trait Decoder<T> {
     fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError>;
-    fn decode(data: &[u8]) -> Result<T, ExtractBodyError>;
+
+    /// Returns the charset of the text data.  The binary formats ignore it.
+    fn charset(_mime: Option<&Mime>) -> Result<Charset, ExtractBodyError> {
+        Ok(Charset::Utf8)
+    }
+
+    fn decode(data: &[u8], charset: Charset, mode: TextDecoding) -> Result<T, ExtractBodyError>;
 }
 
 fn decode<T, D>() -> impl Extractor<
@@ -73,7 +101,7 @@ where
     #[allow(missing_debug_implementations)]
     enum State {
         Init,
-        ReadAll(futures01::stream::Concat2<RequestBody>),
+        ReadAll(ReadBody, Charset, TextDecoding),
     }
 
     #[allow(missing_debug_implementations)]
@@ -94,16 +122,18 @@ where
                 self.state = match self.state {
                     State::Init => {
                         let mime_opt = crate::input::header::parse::<ContentType>(input)?;
-                        D::validate_mime(mime_opt).map_err(crate::error::bad_request)?;
-                        RequestBody::take_from(input.locals)
-                            .map(|body| State::ReadAll(body.concat2()))
-                            .ok_or_else(stolen_payload)?
+                        D::validate_mime(mime_opt).map_err(ExtractBodyError::into_error)?;
+                        let charset = D::charset(mime_opt).map_err(ExtractBodyError::into_error)?;
+                        let mode = ScopeConfigs::get(input.locals)
+                            .and_then(|configs| configs.find::<TextDecoding>())
+                            .map_or(TextDecoding::Strict, |mode| *mode);
+                        State::ReadAll(ReadBody::start(input)?, charset, mode)
                     }
-                    State::ReadAll(ref mut read_all) => {
+                    State::ReadAll(ref mut read_all, charset, mode) => {
                         let data = futures01::try_ready!(read_all.poll());
-                        return D::decode(&*data)
+                        return D::decode(&*data, charset, mode)
                             .map(|out| (out,).into())
-                            .map_err(crate::error::bad_request);
+                            .map_err(ExtractBodyError::into_error);
                     }
                 };
             }
@@ -116,6 +146,13 @@ where
 }
 
 /// Creates an `Extractor` that parses the entire of request body into `T` as a plain text.
+///
+/// The content is decoded according to the parameter `charset` in `Content-type`,
+/// or as UTF-8 if it is not specified.  The charsets other than UTF-8 (e.g.
+/// `ISO-8859-1` or `UTF-16`) are supported only if the feature `encoding` is enabled,
+/// and the unsupported ones are rejected with `415 Unsupported Media Type`.
+/// The handling of invalid byte sequences is controlled by the scope configuration
+/// `TextDecoding`.
 pub fn plain<T>() -> impl Extractor<
     Output = (T,),
     Error = Error,
@@ -138,21 +175,21 @@ where
                         expected: "text/plain",
                     });
                 }
-                if let Some(charset) = mime.get_param("charset") {
-                    if charset != "utf-8" {
-                        return Err(ExtractBodyError::NotUtf8Charset);
-                    }
-                }
             }
             Ok(())
         }
 
-        fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            let s = str::from_utf8(&*data) //
-                .map_err(|cause| ExtractBodyError::InvalidContent {
-                    cause: cause.into(),
-                })?;
-            serde_plain::from_str(s) //
+        fn charset(mime: Option<&Mime>) -> Result<Charset, ExtractBodyError> {
+            Charset::from_mime(mime)
+        }
+
+        fn decode(
+            data: &[u8],
+            charset: Charset,
+            mode: TextDecoding,
+        ) -> Result<T, ExtractBodyError> {
+            let s = charset.decode(data, mode)?;
+            serde_plain::from_str(&s) //
                 .map_err(|cause| ExtractBodyError::InvalidContent {
                     cause: cause.into(),
                 })
@@ -188,7 +225,7 @@ where
             Ok(())
         }
 
-        fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
+        fn decode(data: &[u8], _: Charset, _: TextDecoding) -> Result<T, ExtractBodyError> {
             serde_json::from_slice(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
                 cause: cause.into(),
             })
@@ -198,7 +235,112 @@ where
     decode::<T, JsonDecoder>()
 }
 
+/// Creates an `Extractor` that parses the entire of request body into `T` as MessagePack data.
+///
+/// The request must have `Content-type: application/msgpack` (or its legacy form
+/// `application/x-msgpack`).
+///
+/// This function is available only if the feature `msgpack` is enabled.
+#[cfg(feature = "msgpack")]
+pub fn msgpack<T>() -> impl Extractor<
+    Output = (T,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    #[allow(missing_debug_implementations)]
+    struct MsgpackDecoder(());
+
+    impl<T> Decoder<T> for MsgpackDecoder
+    where
+        T: DeserializeOwned,
+    {
+        fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError> {
+            let mime = mime.ok_or_else(|| ExtractBodyError::MissingContentType)?;
+            if mime.type_() != mime::APPLICATION
+                || (mime.subtype() != "msgpack" && mime.subtype() != "x-msgpack")
+            {
+                return Err(ExtractBodyError::UnexpectedContentType {
+                    expected: "application/msgpack",
+                });
+            }
+            Ok(())
+        }
+
+        fn decode(data: &[u8], _: Charset, _: TextDecoding) -> Result<T, ExtractBodyError> {
+            rmp_serde::from_slice(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
+                cause: cause.into(),
+            })
+        }
+    }
+
+    decode::<T, MsgpackDecoder>()
+}
+
+/// Creates an `Extractor` that parses the entire of request body into `T` as XML data.
+///
+/// The request must have `Content-type: application/xml` or `text/xml`.  The message of
+/// the error returned when the document is not well-formed contains the line and column
+/// where the XML parser has detected the error.
+///
+/// This function is available only if the feature `xml` is enabled.
+#[cfg(feature = "xml")]
+pub fn xml<T>() -> impl Extractor<
+    Output = (T,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    #[allow(missing_debug_implementations)]
+    struct XmlDecoder(());
+
+    impl<T> Decoder<T> for XmlDecoder
+    where
+        T: DeserializeOwned,
+    {
+        fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError> {
+            let mime = mime.ok_or_else(|| ExtractBodyError::MissingContentType)?;
+            if (mime.type_() != mime::APPLICATION && mime.type_() != mime::TEXT)
+                || mime.subtype() != mime::XML
+            {
+                return Err(ExtractBodyError::UnexpectedContentType {
+                    expected: "application/xml",
+                });
+            }
+            Ok(())
+        }
+
+        fn decode(data: &[u8], _: Charset, _: TextDecoding) -> Result<T, ExtractBodyError> {
+            serde_xml_rs::from_reader(data).map_err(|cause| ExtractBodyError::InvalidContent {
+                cause: match cause {
+                    serde_xml_rs::Error::Syntax { source } => {
+                        // The deserializer reads ahead of the value being decoded, so
+                        // only the position tracked by the XML parser is reliable.
+                        let pos = xml::common::Position::position(&source);
+                        failure::format_err!(
+                            "{} (line {}, column {})",
+                            source.msg(),
+                            pos.row + 1,
+                            pos.column + 1
+                        )
+                    }
+                    cause => failure::format_err!("{}", cause),
+                },
+            })
+        }
+    }
+
+    decode::<T, XmlDecoder>()
+}
+
 /// Creates an `Extractor` that parses the entire of request body into `T` as url-encoded data.
+///
+/// The percent-decoded keys and values are decoded according to the parameter `charset`
+/// in `Content-type`, in the same way as `plain`.
 pub fn urlencoded<T>() -> impl Extractor<
     Output = (T,),
     Error = Error,
@@ -216,7 +358,7 @@ where
     {
         fn validate_mime(mime: Option<&Mime>) -> Result<(), ExtractBodyError> {
             let mime = mime.ok_or_else(|| ExtractBodyError::MissingContentType)?;
-            if *mime != mime::APPLICATION_WWW_FORM_URLENCODED {
+            if mime.type_() != mime::APPLICATION || mime.subtype() != mime::WWW_FORM_URLENCODED {
                 return Err(ExtractBodyError::UnexpectedContentType {
                     expected: "application/x-www-form-urlencoded",
                 });
@@ -224,16 +366,296 @@ where
             Ok(())
         }
 
-        fn decode(data: &[u8]) -> Result<T, ExtractBodyError> {
-            serde_urlencoded::from_bytes(&*data).map_err(|cause| ExtractBodyError::InvalidContent {
-                cause: cause.into(),
-            })
+        fn charset(mime: Option<&Mime>) -> Result<Charset, ExtractBodyError> {
+            Charset::from_mime(mime)
+        }
+
+        fn decode(
+            data: &[u8],
+            charset: Charset,
+            mode: TextDecoding,
+        ) -> Result<T, ExtractBodyError> {
+            let invalid_content =
+                |cause: serde_urlencoded::de::Error| ExtractBodyError::InvalidContent {
+                    cause: cause.into(),
+                };
+            if charset.is_utf8() && mode == TextDecoding::Lossy {
+                return serde_urlencoded::from_bytes(&*data).map_err(invalid_content);
+            }
+
+            // Decode the percent-encoded octets in the specified charset, and then
+            // re-encode them in UTF-8 so that serde_urlencoded can handle them.
+            let mut encoded = url::form_urlencoded::Serializer::new(String::new());
+            for pair in data.split(|&b| b == b'&').filter(|pair| !pair.is_empty()) {
+                let mut kv = pair.splitn(2, |&b| b == b'=');
+                let key = percent_decode(kv.next().unwrap_or(&[]));
+                let value = percent_decode(kv.next().unwrap_or(&[]));
+                encoded.append_pair(&charset.decode(&key, mode)?, &charset.decode(&value, mode)?);
+            }
+            serde_urlencoded::from_str(&encoded.finish()).map_err(invalid_content)
         }
     }
 
     decode::<T, UrlencodedDecoder>()
 }
 
+fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let replaced: Vec<u8> = input
+        .iter()
+        .map(|&b| if b == b'+' { b' ' } else { b })
+        .collect();
+    url::percent_encoding::percent_decode(&replaced).collect()
+}
+
+/// The default maximum depth of nested keys used by `urlencoded_nested`.
+pub const DEFAULT_MAX_DEPTH: usize = 5;
+
+/// Creates an `Extractor` that parses the entire of request body into `T` as url-encoded data,
+/// with the support for the nested keys such as `a[b][c]=1`.
+///
+/// The bracketed keys are parsed into the nested maps, and the empty brackets (`a[]=1`)
+/// or the numeric indices (`a[0][b]=1`) into the sequences.  The keys nested deeper than
+/// `DEFAULT_MAX_DEPTH` are rejected; use `urlencoded_nested_with_depth` to change the limit.
+///
+/// The extraction fails with `400 Bad Request` and the error `NestedFormError`,
+/// which reports the key in the form data where the parsing failed.
+pub fn urlencoded_nested<T>() -> impl Extractor<
+    Output = (T,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    self::urlencoded_nested_with_depth(DEFAULT_MAX_DEPTH)
+}
+
+/// Creates an `Extractor` that parses the url-encoded data with the nested keys into `T`,
+/// with the specified maximum depth of the keys.
+///
+/// See the documentation of `urlencoded_nested` for details.
+pub fn urlencoded_nested_with_depth<T>(
+    max_depth: usize,
+) -> impl Extractor<
+    Output = (T,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    super::extract(move || {
+        let mut read_all: Option<ReadBody> = None;
+        crate::future::poll_fn(move |input| loop {
+            if let Some(ref mut read_all) = read_all {
+                let data = futures01::try_ready!(read_all.poll());
+                return nested::from_bytes(&data, max_depth)
+                    .map(|form| (form,).into())
+                    .map_err(crate::error::bad_request);
+            }
+            let mime = crate::input::header::parse::<ContentType>(input)?
+                .ok_or_else(|| crate::error::bad_request(ExtractBodyError::MissingContentType))?;
+            if *mime != mime::APPLICATION_WWW_FORM_URLENCODED {
+                return Err(crate::error::bad_request(
+                    ExtractBodyError::UnexpectedContentType {
+                        expected: "application/x-www-form-urlencoded",
+                    },
+                ));
+            }
+            read_all = Some(ReadBody::start(input)?);
+        })
+    })
+}
+
+/// Creates an `Extractor` that parses the HTML form data into `T`.
+///
+/// The format of the form data is chosen from the header field `Content-type`,
+/// which must be either `application/x-www-form-urlencoded` or `multipart/form-data`.
+/// Otherwise, the extraction fails with `415 Unsupported Media Type`.
+///
+/// The files uploaded with a multipart form are discarded.
+/// Use `form_with_files` in order to access them.
+pub fn form<T>() -> impl Extractor<
+    Output = (T,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    use super::ExtractorExt;
+    self::form_with_files::<T>().map(|form: T, _files: Files| form)
+}
+
+/// Creates an `Extractor` that parses the HTML form data into `T`, along with the uploaded files.
+///
+/// The text fields are deserialized into `T` in the same way as `form`, and the
+/// files are returned as the second element. If the form data is url-encoded,
+/// the returned `Files` is always empty.
+pub fn form_with_files<T>() -> impl Extractor<
+    Output = (T, Files),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T, Files), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    fn decode_form<T>(kind: &FormKind, data: Bytes) -> Result<(T, Files), ExtractBodyError>
+    where
+        T: DeserializeOwned,
+    {
+        let invalid_content = |cause: failure::Error| ExtractBodyError::InvalidContent { cause };
+        match kind {
+            FormKind::Urlencoded => serde_urlencoded::from_bytes(&data)
+                .map(|form| (form, Files::default()))
+                .map_err(|cause| invalid_content(cause.into())),
+            FormKind::Multipart { boundary } => {
+                let (fields, files) = multipart::parse(&data, boundary).map_err(invalid_content)?;
+                decode_fields(fields).map(|form| (form, files))
+            }
+        }
+    }
+
+    super::extract(|| {
+        let mut in_flight: Option<(FormKind, ReadBody)> = None;
+        crate::future::poll_fn(move |input| loop {
+            if let Some((ref kind, ref mut read_all)) = in_flight {
+                let data = futures01::try_ready!(read_all.poll());
+                return decode_form(kind, data)
+                    .map(Into::into)
+                    .map_err(crate::error::bad_request);
+            }
+            let kind = form_kind(crate::input::header::parse::<ContentType>(input)?)?;
+            in_flight = Some((kind, ReadBody::start(input)?));
+        })
+    })
+}
+
+#[allow(missing_debug_implementations)]
+enum FormKind {
+    Urlencoded,
+    Multipart { boundary: String },
+}
+
+fn form_kind(mime: Option<&Mime>) -> Result<FormKind, Error> {
+    const EXPECTED: &str = "application/x-www-form-urlencoded or multipart/form-data";
+    let unsupported = |err| crate::error::custom(StatusCode::UNSUPPORTED_MEDIA_TYPE, err);
+
+    let mime = mime.ok_or_else(|| unsupported(ExtractBodyError::MissingContentType))?;
+    match (mime.type_(), mime.subtype()) {
+        (mime::APPLICATION, mime::WWW_FORM_URLENCODED) => Ok(FormKind::Urlencoded),
+        (mime::MULTIPART, mime::FORM_DATA) => {
+            let boundary = mime.get_param(mime::BOUNDARY).ok_or_else(|| {
+                crate::error::bad_request("missing the boundary of multipart form data")
+            })?;
+            Ok(FormKind::Multipart {
+                boundary: boundary.as_str().to_owned(),
+            })
+        }
+        _ => Err(unsupported(ExtractBodyError::UnexpectedContentType {
+            expected: EXPECTED,
+        })),
+    }
+}
+
+/// Deserializes the text fields of a multipart form into `T`.
+fn decode_fields<T>(fields: Vec<(String, String)>) -> Result<T, ExtractBodyError>
+where
+    T: DeserializeOwned,
+{
+    let encoded = url::form_urlencoded::Serializer::new(String::new())
+        .extend_pairs(fields)
+        .finish();
+    serde_urlencoded::from_str(&encoded).map_err(|cause| ExtractBodyError::InvalidContent {
+        cause: cause.into(),
+    })
+}
+
+/// Creates an `Extractor` that parses the HTML form data into `T`, while writing the
+/// uploaded files into the spool directory.
+///
+/// Unlike `form_with_files`, the file parts of a multipart form are not buffered
+/// in memory, and are returned as `TempUpload`s which remove the files when dropped.
+/// The limits configured with `Spool` are applied to each file, and `BodyLimit`
+/// in the scope configuration only to the total size of the text fields.
+/// If the form data is url-encoded, the returned `TempUploads` is always empty.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, fs::{Spool, TempUploads}, App};
+/// # #[derive(Debug, serde::Deserialize)]
+/// # struct Profile { user: String }
+/// let app = App::create(
+///     path!("/profile").to(endpoint::post()
+///         .extract(extractor::body::form_with_uploads(Spool::new("/var/spool/app")))
+///         .call(|profile: Profile, mut uploads: TempUploads| -> tsukuyomi::Result<_> {
+///             if let Some(avatar) = uploads.take("avatar") {
+///                 avatar.persist(format!("/srv/avatars/{}", profile.user))?;
+///             }
+///             Ok("updated")
+///         })),
+/// );
+/// # app.unwrap();
+/// ```
+pub fn form_with_uploads<T>(
+    spool: Spool,
+) -> impl Extractor<
+    Output = (T, TempUploads),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T, TempUploads), Error = Error> + Send + 'static,
+>
+where
+    T: DeserializeOwned + 'static,
+{
+    #[allow(missing_debug_implementations)]
+    enum State {
+        Init,
+        Urlencoded(ReadBody),
+        Multipart(Box<multipart::SpoolForm>),
+    }
+
+    let spool = Arc::new(spool);
+    super::extract(move || {
+        let spool = spool.clone();
+        let mut state = State::Init;
+        crate::future::poll_fn(move |input| loop {
+            state = match state {
+                State::Init => {
+                    match form_kind(crate::input::header::parse::<ContentType>(input)?)? {
+                        FormKind::Urlencoded => State::Urlencoded(ReadBody::start(input)?),
+                        FormKind::Multipart { boundary } => {
+                            let body =
+                                RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+                            let limit = ScopeConfigs::get(input.locals)
+                                .and_then(|configs| configs.find::<BodyLimit>())
+                                .map(|limit| limit.0);
+                            State::Multipart(Box::new(multipart::SpoolForm::new(
+                                body,
+                                &boundary,
+                                spool.clone(),
+                                limit,
+                            )))
+                        }
+                    }
+                }
+                State::Urlencoded(ref mut read_all) => {
+                    let data = futures01::try_ready!(read_all.poll());
+                    return serde_urlencoded::from_bytes(&data)
+                        .map(|form| (form, TempUploads::default()).into())
+                        .map_err(crate::error::bad_request);
+                }
+                State::Multipart(ref mut spool_form) => {
+                    let (fields, uploads) = futures01::try_ready!(spool_form.poll());
+                    return decode_fields(fields)
+                        .map(|form| (form, uploads).into())
+                        .map_err(crate::error::bad_request);
+                }
+            };
+        })
+    })
+}
+
 /// Creates an extractor that reads the entire of request body as a single byte sequence.
 pub fn read_all() -> impl Extractor<
     Output = (Bytes,),
@@ -241,19 +663,36 @@ pub fn read_all() -> impl Extractor<
     Extract = impl TryFuture<Ok = (Bytes,), Error = Error> + Send + 'static,
 > {
     super::extract(|| {
-        let mut read_all: Option<futures01::stream::Concat2<RequestBody>> = None;
+        let mut read_all: Option<ReadBody> = None;
         crate::future::poll_fn(move |input| loop {
             if let Some(ref mut read_all) = read_all {
-                return read_all
-                    .poll()
-                    .map(|x| x.map(|chunk| (chunk.into_bytes(),)))
-                    .map_err(Into::into);
+                return read_all.poll().map(|x| x.map(|data| (data,)));
             }
-            read_all = Some(
-                RequestBody::take_from(input.locals)
-                    .ok_or_else(stolen_payload)?
-                    .concat2(),
-            );
+            read_all = Some(ReadBody::start(input)?);
+        })
+    })
+}
+
+/// Creates an extractor that reads the entire of request body as a single byte sequence,
+/// and puts a copy of it back into the context.
+///
+/// Unlike `read_all`, the subsequent body extractors (e.g. `json`) can read the same bytes
+/// again. This is useful when the raw bytes are needed in addition to the decoded value,
+/// such as verifying the signature of payload.
+pub fn buffered() -> impl Extractor<
+    Output = (Bytes,),
+    Error = Error,
+    Extract = impl TryFuture<Ok = (Bytes,), Error = Error> + Send + 'static,
+> {
+    super::extract(|| {
+        let mut read_all: Option<ReadBody> = None;
+        crate::future::poll_fn(move |input| loop {
+            if let Some(ref mut read_all) = read_all {
+                let data = futures01::try_ready!(read_all.poll());
+                RequestBody::from(data.clone()).insert_into(input.locals);
+                return Ok((data,).into());
+            }
+            read_all = Some(ReadBody::start(input)?);
         })
     })
 }
@@ -273,6 +712,55 @@ pub fn stream() -> impl Extractor<
     })
 }
 
+/// An asynchronous task that reads the entire of request body, up to the size
+/// specified by `BodyLimit` in the scope configuration.
+pub(crate) struct ReadBody {
+    body: RequestBody,
+    buf: BytesMut,
+    limit: Option<usize>,
+}
+
+impl ReadBody {
+    pub(crate) fn start(input: &mut Input<'_>) -> Result<Self, Error> {
+        let body = RequestBody::take_from(input.locals).ok_or_else(stolen_payload)?;
+        let limit = ScopeConfigs::get(input.locals)
+            .and_then(|configs| configs.find::<BodyLimit>())
+            .map(|limit| limit.0);
+        if let (Some(limit), Some(len)) = (limit, body.content_length()) {
+            if len > limit as u64 {
+                return Err(payload_too_large(limit));
+            }
+        }
+        Ok(Self {
+            body,
+            buf: BytesMut::new(),
+            limit,
+        })
+    }
+
+    pub(crate) fn poll(&mut self) -> futures01::Poll<Bytes, Error> {
+        while let Some(chunk) = futures01::try_ready!(self.body.poll_data()) {
+            if let Some(limit) = self.limit {
+                if self.buf.len() + chunk.len() > limit {
+                    return Err(payload_too_large(limit));
+                }
+            }
+            self.buf.extend_from_slice(&chunk);
+        }
+        Ok(Async::Ready(self.buf.take().freeze()))
+    }
+}
+
+fn payload_too_large(limit: usize) -> Error {
+    crate::error::custom(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        format!(
+            "the size of request body exceeds the limit ({} bytes)",
+            limit
+        ),
+    )
+}
+
 fn stolen_payload() -> crate::error::Error {
     crate::error::internal_server_error("The instance of raw RequestBody has already stolen.")
 }
@@ -0,0 +1,93 @@
+//! Decoding of the text data in the charset specified by `Content-type`.
+
+use {
+    super::ExtractBodyError,
+    crate::config::TextDecoding,
+    mime::Mime,
+    std::{borrow::Cow, str},
+};
+
+/// The charset of request body.
+///
+/// The charsets other than UTF-8 (and its subset US-ASCII) are supported only if
+/// the feature `encoding` is enabled.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Charset {
+    Utf8,
+    #[cfg(feature = "encoding")]
+    Encoding(&'static encoding_rs::Encoding),
+}
+
+impl Charset {
+    /// Determines the charset from the parameter `charset` in the MIME type.
+    ///
+    /// If the parameter is missing, the content is assumed to be encoded in UTF-8.
+    pub(super) fn from_mime(mime: Option<&Mime>) -> Result<Self, ExtractBodyError> {
+        match mime.and_then(|mime| mime.get_param(mime::CHARSET)) {
+            Some(label) => Self::from_label(label.as_str()),
+            None => Ok(Charset::Utf8),
+        }
+    }
+
+    fn from_label(label: &str) -> Result<Self, ExtractBodyError> {
+        if label.eq_ignore_ascii_case("utf-8")
+            || label.eq_ignore_ascii_case("utf8")
+            || label.eq_ignore_ascii_case("us-ascii")
+        {
+            return Ok(Charset::Utf8);
+        }
+
+        #[cfg(feature = "encoding")]
+        {
+            if let Some(encoding) = encoding_rs::Encoding::for_label(label.as_bytes()) {
+                if encoding == encoding_rs::UTF_8 {
+                    return Ok(Charset::Utf8);
+                }
+                return Ok(Charset::Encoding(encoding));
+            }
+        }
+
+        Err(ExtractBodyError::UnsupportedCharset {
+            charset: label.to_owned(),
+        })
+    }
+
+    /// Decodes the byte sequence into a string.
+    pub(super) fn decode<'a>(
+        self,
+        data: &'a [u8],
+        mode: TextDecoding,
+    ) -> Result<Cow<'a, str>, ExtractBodyError> {
+        match self {
+            Charset::Utf8 => match mode {
+                TextDecoding::Strict => str::from_utf8(data).map(Cow::Borrowed).map_err(|cause| {
+                    ExtractBodyError::InvalidContent {
+                        cause: cause.into(),
+                    }
+                }),
+                TextDecoding::Lossy => Ok(String::from_utf8_lossy(data)),
+            },
+            #[cfg(feature = "encoding")]
+            Charset::Encoding(encoding) => {
+                let (decoded, _, had_errors) = encoding.decode(data);
+                if had_errors && mode == TextDecoding::Strict {
+                    return Err(ExtractBodyError::InvalidContent {
+                        cause: failure::format_err!(
+                            "the content contains a byte sequence invalid in {}",
+                            encoding.name()
+                        ),
+                    });
+                }
+                Ok(decoded)
+            }
+        }
+    }
+
+    pub(super) fn is_utf8(self) -> bool {
+        match self {
+            Charset::Utf8 => true,
+            #[cfg(feature = "encoding")]
+            Charset::Encoding(..) => false,
+        }
+    }
+}
@@ -0,0 +1,573 @@
+//! A minimal parser for `multipart/form-data` (RFC 7578).
+
+use {
+    crate::{
+        error::Error,
+        fs::{PendingIo, Spool, TempUpload, TempUploads},
+        input::body::RequestBody,
+    },
+    bytes::{Bytes, BytesMut},
+    failure::format_err,
+    futures01::{Async, Poll},
+    http::StatusCode,
+    hyper::body::Payload,
+    mime::Mime,
+    std::{cmp, slice, str, sync::Arc, vec},
+};
+
+/// A collection of the files uploaded with a multipart form.
+#[derive(Debug, Clone, Default)]
+pub struct Files {
+    files: Vec<FormFile>,
+}
+
+impl Files {
+    /// Returns the first file associated with the specified field name, if exists.
+    pub fn get(&self, name: &str) -> Option<&FormFile> {
+        self.files.iter().find(|file| file.name == name)
+    }
+
+    /// Returns an iterator over all files associated with the specified field name.
+    pub fn get_all<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a FormFile> + 'a {
+        self.files.iter().filter(move |file| file.name == name)
+    }
+
+    /// Returns an iterator over all uploaded files.
+    pub fn iter(&self) -> slice::Iter<'_, FormFile> {
+        self.files.iter()
+    }
+
+    /// Returns the number of uploaded files.
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Returns whether no file has been uploaded.
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+impl IntoIterator for Files {
+    type Item = FormFile;
+    type IntoIter = vec::IntoIter<FormFile>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Files {
+    type Item = &'a FormFile;
+    type IntoIter = slice::Iter<'a, FormFile>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.files.iter()
+    }
+}
+
+/// A file uploaded with a multipart form.
+#[derive(Debug, Clone)]
+pub struct FormFile {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<Mime>,
+    data: Bytes,
+}
+
+impl FormFile {
+    /// Returns the name of form field.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the file name sent by the client, if exists.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_ref().map(|s| s.as_str())
+    }
+
+    /// Returns the value of `Content-type` of this part, if exists.
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// Returns the content of file.
+    pub fn data(&self) -> &Bytes {
+        &self.data
+    }
+
+    /// Consumes itself and returns the content of file.
+    pub fn into_bytes(self) -> Bytes {
+        self.data
+    }
+}
+
+/// Splits the multipart form data into the text fields and uploaded files.
+pub(super) fn parse(
+    data: &Bytes,
+    boundary: &str,
+) -> Result<(Vec<(String, String)>, Files), failure::Error> {
+    let delimiter = format!("\r\n--{}", boundary).into_bytes();
+
+    let mut fields = vec![];
+    let mut files = vec![];
+
+    // The preamble is placed before the first boundary line, and hence the delimiter
+    // is looked up without the leading CRLF.
+    let mut pos = find(data, &delimiter[2..], 0)
+        .map(|i| i + delimiter.len() - 2)
+        .ok_or_else(|| format_err!("missing the boundary"))?;
+    loop {
+        let rest = &data[pos..];
+        if rest.starts_with(b"--") {
+            break;
+        }
+        if !rest.starts_with(b"\r\n") {
+            return Err(format_err!("malformed boundary line"));
+        }
+
+        let header_start = pos + 2;
+        let header_end = find(data, b"\r\n\r\n", header_start)
+            .ok_or_else(|| format_err!("missing the end of part headers"))?;
+        let content_start = header_end + 4;
+        let content_end = find(data, &delimiter, content_start)
+            .ok_or_else(|| format_err!("missing the closing boundary"))?;
+
+        let PartHeaders {
+            name,
+            filename,
+            content_type,
+        } = parse_part_headers(&data[header_start..header_end])?;
+
+        let content = data.slice(content_start, content_end);
+        match filename {
+            Some(filename) => files.push(FormFile {
+                name,
+                filename: Some(filename),
+                content_type,
+                data: content,
+            }),
+            None => fields.push((name, str::from_utf8(&content)?.to_owned())),
+        }
+
+        pos = content_end + delimiter.len();
+    }
+
+    Ok((fields, Files { files }))
+}
+
+/// The header fields of a part.
+#[derive(Debug)]
+pub(super) struct PartHeaders {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<Mime>,
+}
+
+fn parse_part_headers(data: &[u8]) -> Result<PartHeaders, failure::Error> {
+    let mut disposition = None;
+    let mut content_type = None;
+    for line in str::from_utf8(data)?.split("\r\n") {
+        let colon = line
+            .find(':')
+            .ok_or_else(|| format_err!("malformed part header"))?;
+        let (name, value) = (line[..colon].trim(), line[colon + 1..].trim());
+        if name.eq_ignore_ascii_case("content-disposition") {
+            disposition = Some(parse_disposition(value)?);
+        } else if name.eq_ignore_ascii_case("content-type") {
+            content_type = Some(value.parse::<Mime>()?);
+        }
+    }
+    let (name, filename) =
+        disposition.ok_or_else(|| format_err!("missing the header `Content-Disposition`"))?;
+    Ok(PartHeaders {
+        name,
+        filename,
+        content_type,
+    })
+}
+
+/// Parses the value of `Content-Disposition` and returns the field name and file name.
+fn parse_disposition(value: &str) -> Result<(String, Option<String>), failure::Error> {
+    let mut params = split_params(value);
+    match params.next() {
+        Some(ref kind) if kind.eq_ignore_ascii_case("form-data") => {}
+        _ => return Err(format_err!("the disposition type must be `form-data`")),
+    }
+
+    let mut name = None;
+    let mut filename = None;
+    for param in params {
+        let eq = match param.find('=') {
+            Some(eq) => eq,
+            None => continue,
+        };
+        let (key, value) = (param[..eq].trim(), unquote(param[eq + 1..].trim()));
+        if key.eq_ignore_ascii_case("name") {
+            name = Some(value);
+        } else if key.eq_ignore_ascii_case("filename") {
+            filename = Some(value);
+        }
+    }
+
+    let name = name.ok_or_else(|| format_err!("missing the field name"))?;
+    Ok((name, filename))
+}
+
+/// Splits the header value at the semicolons which are not enclosed with quotes.
+fn split_params(value: &str) -> impl Iterator<Item = String> + '_ {
+    std::iter::repeat(()).scan(value.chars().peekable(), |chars, ()| {
+        chars.peek()?;
+        let mut param = String::new();
+        let mut quoted = false;
+        while let Some(c) = chars.next() {
+            match c {
+                ';' if !quoted => break,
+                '"' => quoted = !quoted,
+                '\\' if quoted => {
+                    param.push(c);
+                    if let Some(c) = chars.next() {
+                        param.push(c);
+                    }
+                    continue;
+                }
+                _ => {}
+            }
+            param.push(c);
+        }
+        Some(param.trim().to_owned())
+    })
+}
+
+fn unquote(value: &str) -> String {
+    if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+        let mut unquoted = String::with_capacity(value.len() - 2);
+        let mut chars = value[1..value.len() - 1].chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => unquoted.extend(chars.next()),
+                c => unquoted.push(c),
+            }
+        }
+        unquoted
+    } else {
+        value.to_owned()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8], start: usize) -> Option<usize> {
+    haystack
+        .get(start..)?
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| start + i)
+}
+
+// ==== streaming ====
+
+/// The maximum size of the header section of a part.
+const MAX_HEADER_SIZE: usize = 8 * 1024;
+
+/// An event emitted by `Parser`.
+#[derive(Debug)]
+pub(super) enum Event {
+    /// The beginning of a part.
+    Part(PartHeaders),
+    /// A chunk of the content of the current part.
+    Data(Bytes),
+    /// The end of the current part.
+    End,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ParserState {
+    Preamble,
+    Boundary,
+    Headers,
+    Content,
+    Epilogue,
+}
+
+/// An incremental parser of the multipart form data, fed with the chunks of request body.
+///
+/// The content of parts is emitted as soon as it is known not to be a part of the
+/// delimiter, so only the header sections and a tail shorter than the delimiter are
+/// buffered.
+#[derive(Debug)]
+pub(super) struct Parser {
+    delimiter: Vec<u8>,
+    buf: BytesMut,
+    state: ParserState,
+}
+
+impl Parser {
+    pub(super) fn new(boundary: &str) -> Self {
+        // The first boundary line is not preceded by CRLF unless there is a preamble,
+        // so it is supplied here in order to look up the delimiter in the same way.
+        let mut buf = BytesMut::new();
+        buf.extend_from_slice(b"\r\n");
+        Self {
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            buf,
+            state: ParserState::Preamble,
+        }
+    }
+
+    pub(super) fn feed(&mut self, chunk: &[u8]) {
+        if self.state != ParserState::Epilogue {
+            self.buf.extend_from_slice(chunk);
+        }
+    }
+
+    /// Returns whether the closing boundary has been received.
+    pub(super) fn is_complete(&self) -> bool {
+        self.state == ParserState::Epilogue
+    }
+
+    /// Returns the next event, or `None` if more data is required.
+    pub(super) fn next_event(&mut self) -> Result<Option<Event>, failure::Error> {
+        loop {
+            match self.state {
+                ParserState::Preamble => match find(&self.buf, &self.delimiter, 0) {
+                    Some(i) => {
+                        self.buf.advance(i + self.delimiter.len());
+                        self.state = ParserState::Boundary;
+                    }
+                    None => {
+                        let keep = cmp::min(self.buf.len(), self.delimiter.len() - 1);
+                        let len = self.buf.len();
+                        self.buf.advance(len - keep);
+                        return Ok(None);
+                    }
+                },
+                ParserState::Boundary => {
+                    if self.buf.len() < 2 {
+                        return Ok(None);
+                    }
+                    if self.buf.starts_with(b"--") {
+                        self.buf.clear();
+                        self.state = ParserState::Epilogue;
+                        return Ok(None);
+                    }
+                    if !self.buf.starts_with(b"\r\n") {
+                        return Err(format_err!("malformed boundary line"));
+                    }
+                    self.buf.advance(2);
+                    self.state = ParserState::Headers;
+                }
+                ParserState::Headers => match find(&self.buf, b"\r\n\r\n", 0) {
+                    Some(i) => {
+                        let headers = parse_part_headers(&self.buf[..i])?;
+                        self.buf.advance(i + 4);
+                        self.state = ParserState::Content;
+                        return Ok(Some(Event::Part(headers)));
+                    }
+                    None if self.buf.len() > MAX_HEADER_SIZE => {
+                        return Err(format_err!("the part headers are too large"));
+                    }
+                    None => return Ok(None),
+                },
+                ParserState::Content => match find(&self.buf, &self.delimiter, 0) {
+                    Some(0) => {
+                        self.buf.advance(self.delimiter.len());
+                        self.state = ParserState::Boundary;
+                        return Ok(Some(Event::End));
+                    }
+                    Some(i) => return Ok(Some(Event::Data(self.buf.split_to(i).freeze()))),
+                    None => {
+                        // the tail may be the beginning of the delimiter.
+                        let keep = self.delimiter.len() - 1;
+                        if self.buf.len() <= keep {
+                            return Ok(None);
+                        }
+                        let len = self.buf.len() - keep;
+                        return Ok(Some(Event::Data(self.buf.split_to(len).freeze())));
+                    }
+                },
+                ParserState::Epilogue => return Ok(None),
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Part {
+    Field(String, Vec<u8>),
+    File(TempUpload),
+}
+
+fn take_upload(part: &mut Option<Part>) -> TempUpload {
+    match part.take() {
+        Some(Part::File(upload)) => upload,
+        _ => unreachable!("the file part should be in progress"),
+    }
+}
+
+#[derive(Debug)]
+enum Pending {
+    Create(PartHeaders),
+    Write(Bytes),
+    Complete,
+}
+
+/// A future that receives the multipart form data, and writes the uploaded files
+/// into the spool directory.
+///
+/// The text fields are held in memory, up to the specified limit.
+#[derive(Debug)]
+pub(super) struct SpoolForm {
+    body: RequestBody,
+    parser: Parser,
+    spool: Arc<Spool>,
+    fields_limit: Option<usize>,
+    fields: Vec<(String, String)>,
+    fields_len: usize,
+    uploads: TempUploads,
+    part: Option<Part>,
+    pending: Option<Pending>,
+    io_pending: PendingIo<TempUpload>,
+    eof: bool,
+}
+
+impl SpoolForm {
+    pub(super) fn new(
+        body: RequestBody,
+        boundary: &str,
+        spool: Arc<Spool>,
+        fields_limit: Option<usize>,
+    ) -> Self {
+        Self {
+            body,
+            parser: Parser::new(boundary),
+            spool,
+            fields_limit,
+            fields: vec![],
+            fields_len: 0,
+            uploads: TempUploads::default(),
+            part: None,
+            pending: None,
+            io_pending: PendingIo::default(),
+            eof: false,
+        }
+    }
+
+    pub(super) fn poll(&mut self) -> Poll<(Vec<(String, String)>, TempUploads), Error> {
+        let io = self.spool.io_strategy();
+        loop {
+            match self.pending {
+                Some(Pending::Create(ref headers)) => {
+                    let spool = &self.spool;
+                    let upload = futures01::try_ready!(io
+                        .poll_io(&mut self.io_pending, || {
+                            let spool = spool.clone();
+                            let name = headers.name.clone();
+                            let filename = headers.filename.clone();
+                            let content_type = headers.content_type.clone();
+                            move || spool.create(name, filename, content_type)
+                        })
+                        .map_err(crate::error::internal_server_error));
+                    self.part = Some(Part::File(upload));
+                    self.pending = None;
+                    continue;
+                }
+                Some(Pending::Write(ref chunk)) => {
+                    // The upload is moved into the operation, and returned after the
+                    // chunk is written.
+                    let part = &mut self.part;
+                    let upload = futures01::try_ready!(io
+                        .poll_io(&mut self.io_pending, || {
+                            let mut upload = take_upload(part);
+                            let chunk = chunk.clone();
+                            move || upload.write(&chunk).map(|()| upload)
+                        })
+                        .map_err(crate::error::internal_server_error));
+                    self.part = Some(Part::File(upload));
+                    self.pending = None;
+                    continue;
+                }
+                Some(Pending::Complete) => {
+                    let part = &mut self.part;
+                    let upload = futures01::try_ready!(io
+                        .poll_io(&mut self.io_pending, || {
+                            let mut upload = take_upload(part);
+                            move || upload.complete().map(|()| upload)
+                        })
+                        .map_err(crate::error::internal_server_error));
+                    self.uploads.push(upload);
+                    self.pending = None;
+                    continue;
+                }
+                None => {}
+            }
+
+            match self
+                .parser
+                .next_event()
+                .map_err(crate::error::bad_request)?
+            {
+                Some(Event::Part(headers)) => {
+                    if headers.filename.is_none() {
+                        self.part = Some(Part::Field(headers.name, vec![]));
+                        continue;
+                    }
+                    if self.uploads.len() >= self.spool.max_files_limit() {
+                        return Err(crate::error::custom(
+                            StatusCode::PAYLOAD_TOO_LARGE,
+                            "too many files in the form",
+                        ));
+                    }
+                    self.pending = Some(Pending::Create(headers));
+                }
+                Some(Event::Data(data)) => match self.part {
+                    Some(Part::Field(_, ref mut content)) => {
+                        self.fields_len += data.len();
+                        if let Some(limit) = self.fields_limit {
+                            if self.fields_len > limit {
+                                return Err(super::payload_too_large(limit));
+                            }
+                        }
+                        content.extend_from_slice(&data);
+                    }
+                    Some(Part::File(ref upload)) => {
+                        let max_file_size = self.spool.max_file_size_limit();
+                        if upload.len() + data.len() as u64 > max_file_size {
+                            return Err(crate::error::custom(
+                                StatusCode::PAYLOAD_TOO_LARGE,
+                                format!(
+                                    "the size of file exceeds the limit ({} bytes)",
+                                    max_file_size
+                                ),
+                            ));
+                        }
+                        self.pending = Some(Pending::Write(data));
+                    }
+                    None => unreachable!("the data is emitted only within a part"),
+                },
+                Some(Event::End) => match self.part.take() {
+                    Some(Part::Field(name, content)) => {
+                        let value =
+                            String::from_utf8(content).map_err(crate::error::bad_request)?;
+                        self.fields.push((name, value));
+                    }
+                    Some(Part::File(upload)) => {
+                        self.part = Some(Part::File(upload));
+                        self.pending = Some(Pending::Complete);
+                    }
+                    None => unreachable!("the end is emitted only within a part"),
+                },
+                None if self.eof => {
+                    if !self.parser.is_complete() {
+                        return Err(crate::error::bad_request("missing the closing boundary"));
+                    }
+                    let fields = std::mem::replace(&mut self.fields, vec![]);
+                    let uploads = std::mem::replace(&mut self.uploads, TempUploads::default());
+                    return Ok(Async::Ready((fields, uploads)));
+                }
+                None => match futures01::try_ready!(self.body.poll_data()) {
+                    Some(chunk) => self.parser.feed(&chunk),
+                    None => self.eof = true,
+                },
+            }
+        }
+    }
+}
@@ -0,0 +1,468 @@
+//! A parser for url-encoded data with the nested keys (e.g. `a[b][0]=c`).
+
+use {
+    serde::de::{
+        self, DeserializeOwned, DeserializeSeed, Deserializer, IntoDeserializer, MapAccess,
+        SeqAccess, Visitor,
+    },
+    std::{fmt, vec},
+};
+
+/// The error type which will be returned when parsing a url-encoded data with the nested keys.
+#[derive(Debug)]
+pub enum NestedFormError {
+    /// The key is nested deeper than the allowed depth.
+    TooDeep { key: String, max_depth: usize },
+
+    /// The field could not be parsed or deserialized.
+    InvalidField {
+        key: Option<String>,
+        message: String,
+    },
+}
+
+impl NestedFormError {
+    /// Returns the key in the form data where this error occurred, if available.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            NestedFormError::TooDeep { key, .. } => Some(key),
+            NestedFormError::InvalidField { key, .. } => key.as_ref().map(|s| s.as_str()),
+        }
+    }
+
+    fn invalid(key: &str, message: impl fmt::Display) -> Self {
+        NestedFormError::InvalidField {
+            key: Some(key.to_owned()),
+            message: message.to_string(),
+        }
+    }
+
+    /// Attaches the key to this error if not set.
+    fn at(self, key: &str) -> Self {
+        match self {
+            NestedFormError::InvalidField { key: None, message } => NestedFormError::InvalidField {
+                key: if key.is_empty() {
+                    None
+                } else {
+                    Some(key.to_owned())
+                },
+                message,
+            },
+            err => err,
+        }
+    }
+}
+
+impl fmt::Display for NestedFormError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NestedFormError::TooDeep { key, max_depth } => write!(
+                f,
+                "the key `{}` exceeds the maximum nesting depth ({})",
+                key, max_depth
+            ),
+            NestedFormError::InvalidField {
+                key: Some(key),
+                message,
+            } => write!(f, "invalid form field `{}`: {}", key, message),
+            NestedFormError::InvalidField { key: None, message } => {
+                write!(f, "invalid form data: {}", message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for NestedFormError {}
+
+impl de::Error for NestedFormError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        NestedFormError::InvalidField {
+            key: None,
+            message: msg.to_string(),
+        }
+    }
+}
+
+/// Deserializes an instance of `T` from the url-encoded data with the nested keys.
+pub(super) fn from_bytes<T>(data: &[u8], max_depth: usize) -> Result<T, NestedFormError>
+where
+    T: DeserializeOwned,
+{
+    let mut root = vec![];
+    for (key, value) in url::form_urlencoded::parse(data) {
+        let (name, segments) = parse_key(&key, max_depth)?;
+        insert_into_map(&mut root, name, &segments, value.into_owned(), &key)?;
+    }
+    T::deserialize(NodeDeserializer {
+        node: Node::Map(root),
+        key: String::new(),
+    })
+}
+
+#[derive(Debug)]
+enum Node {
+    Leaf(String),
+    Map(Vec<(String, Node)>),
+    Seq(Vec<Node>),
+}
+
+#[derive(Debug)]
+enum Segment<'a> {
+    Key(&'a str),
+    Push,
+}
+
+/// Splits a key such as `a[b][]` into the name and the bracketed segments.
+fn parse_key(key: &str, max_depth: usize) -> Result<(&str, Vec<Segment<'_>>), NestedFormError> {
+    let (name, mut rest) = match key.find('[') {
+        Some(pos) => (&key[..pos], &key[pos..]),
+        None => (key, ""),
+    };
+    if name.is_empty() {
+        return Err(NestedFormError::invalid(key, "the field name is empty"));
+    }
+
+    let mut segments = vec![];
+    while !rest.is_empty() {
+        let end = match (rest.starts_with('['), rest.find(']')) {
+            (true, Some(end)) => end,
+            _ => return Err(NestedFormError::invalid(key, "malformed brackets")),
+        };
+        segments.push(match &rest[1..end] {
+            "" => Segment::Push,
+            segment => Segment::Key(segment),
+        });
+        if segments.len() > max_depth {
+            return Err(NestedFormError::TooDeep {
+                key: key.to_owned(),
+                max_depth,
+            });
+        }
+        rest = &rest[end + 1..];
+    }
+
+    Ok((name, segments))
+}
+
+fn new_node(segment: &Segment<'_>) -> Node {
+    match segment {
+        Segment::Key(..) => Node::Map(vec![]),
+        Segment::Push => Node::Seq(vec![]),
+    }
+}
+
+fn insert_into_map(
+    entries: &mut Vec<(String, Node)>,
+    name: &str,
+    rest: &[Segment<'_>],
+    value: String,
+    key: &str,
+) -> Result<(), NestedFormError> {
+    let pos = entries.iter().position(|(n, _)| n == name);
+
+    if rest.is_empty() {
+        // the repeated keys are collected into a sequence.
+        let node = match pos {
+            Some(pos) => &mut entries[pos].1,
+            None => {
+                entries.push((name.to_owned(), Node::Leaf(value)));
+                return Ok(());
+            }
+        };
+        return match node {
+            Node::Leaf(..) => {
+                let first = std::mem::replace(node, Node::Seq(vec![]));
+                *node = Node::Seq(vec![first, Node::Leaf(value)]);
+                Ok(())
+            }
+            Node::Seq(ref mut items) => {
+                items.push(Node::Leaf(value));
+                Ok(())
+            }
+            Node::Map(..) => Err(NestedFormError::invalid(
+                key,
+                "conflicts with another field",
+            )),
+        };
+    }
+
+    let pos = match pos {
+        Some(pos) => pos,
+        None => {
+            entries.push((name.to_owned(), new_node(&rest[0])));
+            entries.len() - 1
+        }
+    };
+    insert_into_node(&mut entries[pos].1, rest, value, key)
+}
+
+fn insert_into_node(
+    node: &mut Node,
+    segments: &[Segment<'_>],
+    value: String,
+    key: &str,
+) -> Result<(), NestedFormError> {
+    let conflict = || NestedFormError::invalid(key, "conflicts with another field");
+    match (node, &segments[0]) {
+        (Node::Map(ref mut entries), Segment::Key(name)) => {
+            insert_into_map(entries, name, &segments[1..], value, key)
+        }
+        (Node::Seq(ref mut items), Segment::Push) => {
+            let child = match segments.get(1) {
+                Some(next) => {
+                    let mut child = new_node(next);
+                    insert_into_node(&mut child, &segments[1..], value, key)?;
+                    child
+                }
+                None => Node::Leaf(value),
+            };
+            items.push(child);
+            Ok(())
+        }
+        _ => Err(conflict()),
+    }
+}
+
+/// A `Deserializer` for a node in the form data, along with its key.
+struct NodeDeserializer {
+    node: Node,
+    key: String,
+}
+
+impl NodeDeserializer {
+    /// Returns the elements of this node as a sequence.
+    ///
+    /// The map whose keys are all indices (e.g. `a[0]=x&a[1]=y`) is regarded as
+    /// a sequence ordered by the indices, and a single value as a sequence with
+    /// one element.
+    fn into_elements(self) -> Result<Vec<(String, Node)>, NestedFormError> {
+        let NodeDeserializer { node, key } = self;
+        match node {
+            Node::Seq(items) => Ok(items
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| (child_key(&key, &i.to_string()), item))
+                .collect()),
+            Node::Map(entries) => {
+                let mut indexed = Vec::with_capacity(entries.len());
+                for (name, node) in entries {
+                    let child = child_key(&key, &name);
+                    let index: usize = name
+                        .parse()
+                        .map_err(|_| NestedFormError::invalid(&child, "expected an index"))?;
+                    indexed.push((index, child, node));
+                }
+                indexed.sort_by_key(|&(index, ..)| index);
+                Ok(indexed
+                    .into_iter()
+                    .map(|(_, child, node)| (child, node))
+                    .collect())
+            }
+            leaf @ Node::Leaf(..) => Ok(vec![(key, leaf)]),
+        }
+    }
+}
+
+fn child_key(parent: &str, name: &str) -> String {
+    if parent.is_empty() {
+        name.to_owned()
+    } else {
+        format!("{}[{}]", parent, name)
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {$(
+        fn $method<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+        where
+            V: Visitor<'de>,
+        {
+            match self.node {
+                Node::Leaf(ref value) => {
+                    let parsed = value.parse().map_err(de::Error::custom)?;
+                    visitor.$visit(parsed)
+                }
+                _ => self.deserialize_any(visitor),
+            }
+        }
+    )*};
+}
+
+impl<'de> Deserializer<'de> for NodeDeserializer {
+    type Error = NestedFormError;
+
+    fn deserialize_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.node {
+            Node::Leaf(value) => visitor.visit_string(value),
+            Node::Map(entries) => visitor.visit_map(NodeMapAccess {
+                parent: self.key,
+                entries: entries.into_iter(),
+                value: None,
+            }),
+            node @ Node::Seq(..) => NodeDeserializer {
+                node,
+                key: self.key,
+            }
+            .deserialize_seq(visitor),
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_option<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_seq(NodeSeqAccess {
+            elements: self.into_elements()?.into_iter(),
+        })
+    }
+
+    fn deserialize_tuple<V>(self, _len: usize, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_enum<V>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        match self.node {
+            Node::Leaf(value) => visitor.visit_enum(value.into_deserializer()),
+            _ => Err(de::Error::custom("expected a unit variant")),
+        }
+    }
+
+    fn deserialize_ignored_any<V>(self, visitor: V) -> Result<V::Value, Self::Error>
+    where
+        V: Visitor<'de>,
+    {
+        visitor.visit_unit()
+    }
+
+    serde::forward_to_deserialize_any! {
+        str string bytes byte_buf unit_struct map struct identifier
+    }
+}
+
+struct NodeMapAccess {
+    parent: String,
+    entries: vec::IntoIter<(String, Node)>,
+    value: Option<(String, Node)>,
+}
+
+impl<'de> MapAccess<'de> for NodeMapAccess {
+    type Error = NestedFormError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, Self::Error>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((name, node)) => {
+                self.value = Some((child_key(&self.parent, &name), node));
+                seed.deserialize(name.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, Self::Error>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        let (key, node) = self
+            .value
+            .take()
+            .expect("next_value_seed is called before next_key_seed");
+        seed.deserialize(NodeDeserializer {
+            node,
+            key: key.clone(),
+        })
+        .map_err(|err| err.at(&key))
+    }
+}
+
+struct NodeSeqAccess {
+    elements: vec::IntoIter<(String, Node)>,
+}
+
+impl<'de> SeqAccess<'de> for NodeSeqAccess {
+    type Error = NestedFormError;
+
+    fn next_element_seed<T>(&mut self, seed: T) -> Result<Option<T::Value>, Self::Error>
+    where
+        T: DeserializeSeed<'de>,
+    {
+        match self.elements.next() {
+            Some((key, node)) => seed
+                .deserialize(NodeDeserializer {
+                    node,
+                    key: key.clone(),
+                })
+                .map(Some)
+                .map_err(|err| err.at(&key)),
+            None => Ok(None),
+        }
+    }
+}
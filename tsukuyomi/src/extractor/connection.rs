@@ -0,0 +1,63 @@
+//! Extractors for accessing the metadata of the underlying connection.
+
+use {
+    super::{tls::TlsInfo, Extractor},
+    crate::{future::TryFuture, util::Never},
+    http::Version,
+};
+
+/// The metadata of the connection on which the current request was received.
+///
+/// The TLS-related values are only available if the TLS metadata has been
+/// captured when establishing the connection (see the documentation of
+/// `extractor::tls` for details).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConnectionInfo {
+    version: Version,
+    tls: Option<TlsInfo>,
+}
+
+impl ConnectionInfo {
+    /// Returns the HTTP version used in the current request.
+    ///
+    /// The value reflects the protocol negotiated on the connection, e.g.
+    /// `HTTP/2.0` for the connections negotiated via ALPN or started with
+    /// the HTTP/2 connection preface (h2c with prior knowledge).
+    pub fn version(&self) -> Version {
+        self.version
+    }
+
+    /// Returns whether the connection is secured by TLS.
+    pub fn is_tls(&self) -> bool {
+        self.tls.is_some()
+    }
+
+    /// Returns the protocol negotiated via ALPN, e.g. `"h2"`, if available.
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.tls.as_ref().and_then(TlsInfo::alpn_protocol)
+    }
+
+    /// Returns the server name sent by the client via SNI, if available.
+    pub fn server_name(&self) -> Option<&str> {
+        self.tls.as_ref().and_then(TlsInfo::server_name)
+    }
+
+    /// Returns the TLS metadata of the connection, if available.
+    pub fn tls_info(&self) -> Option<&TlsInfo> {
+        self.tls.as_ref()
+    }
+}
+
+/// Creates an `Extractor` that returns the metadata of the current connection.
+pub fn info() -> impl Extractor<
+    Output = (ConnectionInfo,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (ConnectionInfo,), Error = Never> + Send + 'static,
+> {
+    super::ready(|input| {
+        Ok((ConnectionInfo {
+            version: input.request.version(),
+            tls: input.request.extensions().get::<TlsInfo>().cloned(),
+        },))
+    })
+}
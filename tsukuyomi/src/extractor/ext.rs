@@ -1,20 +1,23 @@
 //! A set of extensions for `Extractor`s.
 
 use {
-    super::Extractor,
+    super::{validate::Validated, Extractor},
     crate::{
         error::Error,
         generic::{Combine, Func},
         util::Chain, //
     },
+    std::time::Duration,
 };
 
 pub use self::{
+    cached::{Cached, CachedFuture},
     fallible::Fallible, //
     map::Map,
     map_err::MapErr,
     optional::Optional,
     or::Or,
+    timeout::Timeout,
 };
 
 /// A set of extension methods for composing/formatting `Extractor`s.
@@ -72,6 +75,69 @@ pub trait ExtractorExt: Extractor + Sized {
     {
         MapErr { extractor: self, f }
     }
+
+    /// Validates the extracted value by using its implementation of `Validate`.
+    fn validate<T>(self) -> Validated<Self>
+    where
+        Self: Extractor<Output = (T,)>,
+        T: super::validate::Validate,
+    {
+        Validated {
+            extractor: self,
+            renderer: None,
+        }
+    }
+
+    /// Caches the extracted value within the lifecycle of the current request.
+    ///
+    /// The first successful output of this extractor is stored in the request-local
+    /// cache keyed by the returned instance, and the subsequent extractions by the
+    /// same instance (e.g. shared with `Arc` between a modifier and an endpoint)
+    /// in the same request return its clone instead of extracting again.
+    /// The errors are not cached.
+    fn cached(self) -> Cached<Self>
+    where
+        Self::Output: Clone + Send + 'static,
+    {
+        Cached::new(self)
+    }
+
+    /// Fails the extraction with `408 Request Timeout` if it does not complete
+    /// within the specified duration.
+    ///
+    /// The timer is registered when the extraction is polled for the first time,
+    /// so the duration measures the time spent on the extraction itself rather than
+    /// the time the request waited before reaching this extractor.
+    /// The error can be customized by using `Timeout::on_timeout`.
+    ///
+    /// Note that the timeout only covers the extraction. If the inner extractor
+    /// reads the entire of request body (e.g. `body::json`), the time to receive
+    /// the body is included, but if it just takes the body stream (`body::stream`),
+    /// the subsequent consumption of the stream is not limited by this timeout.
+    ///
+    /// The timer is provided by `tokio-timer`, and hence the extraction must be
+    /// executed on the runtime with the timer enabled.
+    fn timeout(self, duration: Duration) -> Timeout<Self> {
+        Timeout {
+            extractor: self,
+            duration: Some(duration),
+            on_timeout: None,
+        }
+    }
+
+    /// Fails the extraction with `408 Request Timeout` if it does not complete
+    /// within the duration specified by `config::ExtractTimeout` in the scope.
+    ///
+    /// The duration is looked up from the scope of the matched route when the
+    /// extraction starts, and the extraction is not limited if the scope does not
+    /// have the configuration.  Otherwise, this adaptor behaves the same as `timeout`.
+    fn scoped_timeout(self) -> Timeout<Self> {
+        Timeout {
+            extractor: self,
+            duration: None,
+            on_timeout: None,
+        }
+    }
 }
 
 impl<E: Extractor> ExtractorExt for E {}
@@ -436,3 +502,220 @@ mod map_err {
         }
     }
 }
+
+mod cached {
+    use {
+        crate::{
+            extractor::Extractor,
+            future::{Poll, TryFuture},
+            input::{
+                localmap::{local_key, LocalData},
+                Input,
+            },
+        },
+        std::{
+            any::Any,
+            collections::HashMap,
+            sync::atomic::{AtomicUsize, Ordering},
+        },
+    };
+
+    #[derive(Debug)]
+    pub struct Cached<E> {
+        extractor: E,
+        id: usize,
+    }
+
+    impl<E> Cached<E> {
+        pub(super) fn new(extractor: E) -> Self {
+            // the identifier distinguishes the instances, rather than the types, since
+            // the extractors of the same type may extract the different values.
+            static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+            Self {
+                extractor,
+                id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            }
+        }
+    }
+
+    impl<E> Extractor for Cached<E>
+    where
+        E: Extractor,
+        E::Output: Clone + Send + 'static,
+    {
+        type Output = E::Output;
+        type Error = E::Error;
+        type Extract = CachedFuture<E::Extract>;
+
+        fn extract(&self) -> Self::Extract {
+            CachedFuture {
+                future: self.extractor.extract(),
+                id: self.id,
+            }
+        }
+    }
+
+    /// The request-local storage of the cached outputs, keyed by the identifier of extractors.
+    #[derive(Default)]
+    struct ExtractCache(HashMap<usize, Box<dyn Any + Send>>);
+
+    impl LocalData for ExtractCache {
+        local_key! {
+            const KEY: Self;
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct CachedFuture<Fut> {
+        future: Fut,
+        id: usize,
+    }
+
+    impl<Fut> TryFuture for CachedFuture<Fut>
+    where
+        Fut: TryFuture,
+        Fut::Ok: Clone + Send + 'static,
+    {
+        type Ok = Fut::Ok;
+        type Error = Fut::Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if let Some(cached) = ExtractCache::get(input.locals)
+                .and_then(|cache| cache.0.get(&self.id))
+                .and_then(|cached| cached.downcast_ref::<Fut::Ok>())
+            {
+                return Ok(cached.clone().into());
+            }
+
+            let output = futures01::try_ready!(self.future.poll_ready(input));
+            ExtractCache::entry(input.locals)
+                .or_insert_with(ExtractCache::default)
+                .0
+                .insert(self.id, Box::new(output.clone()));
+            Ok(output.into())
+        }
+    }
+}
+
+mod timeout {
+    use {
+        crate::{
+            app::ScopeConfigs,
+            config::ExtractTimeout,
+            error::Error,
+            extractor::Extractor,
+            future::{Async, Poll, TryFuture},
+            input::{localmap::LocalData, Input},
+        },
+        futures01::Future,
+        http::StatusCode,
+        std::{
+            fmt,
+            sync::Arc,
+            time::{Duration, Instant},
+        },
+        tokio_timer::Delay,
+    };
+
+    type OnTimeout = Arc<dyn Fn(Duration) -> Error + Send + Sync + 'static>;
+
+    /// An `Extractor` which limits the time spent on the inner extractor.
+    ///
+    /// The value of this type is created by `ExtractorExt::timeout` or `ExtractorExt::scoped_timeout`.
+    pub struct Timeout<E> {
+        pub(super) extractor: E,
+        pub(super) duration: Option<Duration>,
+        pub(super) on_timeout: Option<OnTimeout>,
+    }
+
+    impl<E: fmt::Debug> fmt::Debug for Timeout<E> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Timeout")
+                .field("extractor", &self.extractor)
+                .field("duration", &self.duration)
+                .finish()
+        }
+    }
+
+    impl<E> Timeout<E> {
+        /// Overrides the function to create the error returned when the extraction times out.
+        pub fn on_timeout<F, U>(self, f: F) -> Self
+        where
+            F: Fn(Duration) -> U + Send + Sync + 'static,
+            U: Into<Error>,
+        {
+            Self {
+                on_timeout: Some(Arc::new(move |duration| f(duration).into())),
+                ..self
+            }
+        }
+    }
+
+    impl<E> Extractor for Timeout<E>
+    where
+        E: Extractor,
+    {
+        type Output = E::Output;
+        type Error = Error;
+        type Extract = TimeoutFuture<E::Extract>;
+
+        fn extract(&self) -> Self::Extract {
+            TimeoutFuture {
+                future: self.extractor.extract(),
+                duration: self.duration,
+                delay: None,
+                on_timeout: self.on_timeout.clone(),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct TimeoutFuture<Fut> {
+        future: Fut,
+        duration: Option<Duration>,
+        delay: Option<(Delay, Duration)>,
+        on_timeout: Option<OnTimeout>,
+    }
+
+    impl<Fut> TryFuture for TimeoutFuture<Fut>
+    where
+        Fut: TryFuture,
+    {
+        type Ok = Fut::Ok;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if self.delay.is_none() {
+                let duration = self.duration.or_else(|| {
+                    ScopeConfigs::get(input.locals)
+                        .and_then(|configs| configs.find::<ExtractTimeout>())
+                        .map(|timeout| timeout.0)
+                });
+                match duration {
+                    Some(duration) => {
+                        self.delay = Some((Delay::new(Instant::now() + duration), duration));
+                    }
+                    None => return self.future.poll_ready(input).map_err(Into::into),
+                }
+            }
+
+            if let Async::Ready(output) = self.future.poll_ready(input).map_err(Into::into)? {
+                return Ok(Async::Ready(output));
+            }
+
+            let (ref mut delay, duration) =
+                *self.delay.as_mut().expect("the timer should be registered");
+            match delay.poll() {
+                Ok(Async::Ready(())) => Err(match self.on_timeout {
+                    Some(ref on_timeout) => on_timeout(duration),
+                    None => crate::error::custom(
+                        StatusCode::REQUEST_TIMEOUT,
+                        format!("the extraction did not complete within {:?}", duration),
+                    ),
+                }),
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(err) => Err(crate::error::internal_server_error(err)),
+            }
+        }
+    }
+}
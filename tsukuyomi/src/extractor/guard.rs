@@ -0,0 +1,103 @@
+//! Extractors that validate the framing of request body without reading it.
+//!
+//! The extractors in this module inspect only the header fields and extract `()`,
+//! so they can be placed at the beginning of an extractor chain in order to reject
+//! the unwanted requests before any body polling happens.
+
+use {
+    super::Extractor,
+    crate::{error::Error, future::TryFuture},
+    http::{
+        header::{HeaderMap, CONTENT_LENGTH, TRANSFER_ENCODING},
+        StatusCode,
+    },
+};
+
+/// Creates an `Extractor` that rejects the requests with a message body.
+///
+/// The request is rejected with `413 Payload Too Large` if it has a non-zero
+/// `Content-Length` or a `Transfer-Encoding`, and with `400 Bad Request` if the
+/// value of `Content-Length` is malformed.
+pub fn no_body() -> impl Extractor<
+    Output = (), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (), Error = Error> + Send + 'static,
+> {
+    super::ready(|input| {
+        let headers = input.request.headers();
+        if headers.contains_key(TRANSFER_ENCODING) {
+            return Err(payload_too_large("the request body is not allowed"));
+        }
+        match content_length(headers)? {
+            Some(len) if len > 0 => Err(payload_too_large("the request body is not allowed")),
+            _ => Ok(()),
+        }
+    })
+}
+
+/// Creates an `Extractor` that requires the request to have a `Content-Length`
+/// not greater than `max`.
+///
+/// The request is rejected with `411 Length Required` if `Content-Length` is missing
+/// (including the case where the body is sent with `Transfer-Encoding`),
+/// `413 Payload Too Large` if it exceeds `max`, and `400 Bad Request` if its value
+/// is malformed.
+pub fn content_length_max(
+    max: u64,
+) -> impl Extractor<
+    Output = (), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (), Error = Error> + Send + 'static,
+> {
+    super::ready(move |input| {
+        let headers = input.request.headers();
+        if headers.contains_key(TRANSFER_ENCODING) {
+            return Err(length_required());
+        }
+        match content_length(headers)? {
+            Some(len) if len > max => Err(payload_too_large(format!(
+                "the length of request body exceeds the limit ({} bytes)",
+                max
+            ))),
+            Some(..) => Ok(()),
+            None => Err(length_required()),
+        }
+    })
+}
+
+/// Parses the value of `Content-Length`.
+///
+/// Multiple field values are accepted only if all of them are identical.
+fn content_length(headers: &HeaderMap) -> Result<Option<u64>, Error> {
+    let mut len = None;
+    for value in headers.get_all(CONTENT_LENGTH) {
+        let parsed = value
+            .to_str()
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .ok_or_else(|| crate::error::bad_request("malformed header field: content-length"))?;
+        match len {
+            Some(len) if len != parsed => {
+                return Err(crate::error::bad_request(
+                    "conflicting header fields: content-length",
+                ));
+            }
+            _ => len = Some(parsed),
+        }
+    }
+    Ok(len)
+}
+
+fn length_required() -> Error {
+    crate::error::custom(
+        StatusCode::LENGTH_REQUIRED,
+        "the request must have a content-length",
+    )
+}
+
+fn payload_too_large<D>(msg: D) -> Error
+where
+    D: std::fmt::Debug + std::fmt::Display + Send + 'static,
+{
+    crate::error::custom(StatusCode::PAYLOAD_TOO_LARGE, msg)
+}
@@ -4,6 +4,7 @@ use {
     super::Extractor,
     crate::future::TryFuture,
     http::{Method, StatusCode},
+    std::sync::Arc,
 };
 
 /// Creates an `Extractor` that checks if the request method is equal to `method`.
@@ -62,3 +63,233 @@ pub fn get_or_head() -> impl Extractor<
         }
     })
 }
+
+/// Creates a builder of `Extractor` that switches the extraction by the request method.
+///
+/// The extractor chooses the inner extractor mapped to the request method when it is
+/// polled, and drives only the chosen one.  If no extractor is mapped to the request
+/// method, it returns `Err(StatusCode::METHOD_NOT_ALLOWED)`.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// # use http::Method;
+/// #[derive(Debug, serde::Deserialize)]
+/// struct Params {
+///     id: u32,
+/// }
+///
+/// let extractor = extractor::method_switch()
+///     .on(Method::GET, extractor::query::<Params>())
+///     .on(Method::POST, extractor::body::json::<Params>());
+///
+/// let app = App::create(
+///     path!("/items").to(endpoint::allow_only("GET, POST")?
+///         .extract(extractor)
+///         .call(|params: Params| format!("id = {}", params.id))),
+/// )?;
+/// # drop(app);
+/// # Ok::<_, tsukuyomi::config::Error>(())
+/// ```
+pub fn switch() -> self::switch::MethodSwitch<()> {
+    self::switch::MethodSwitch {
+        cases: Arc::new(()),
+    }
+}
+
+pub mod switch {
+    //! Components for `method::switch()`.
+
+    use {
+        crate::{
+            error::Error,
+            extractor::Extractor,
+            future::{Poll, TryFuture},
+            generic::Tuple,
+            input::Input,
+            util::Never,
+        },
+        http::{Method, StatusCode},
+        std::{fmt, marker::PhantomData, sync::Arc},
+    };
+
+    /// A trait representing a set of extractors mapped to the request methods.
+    pub trait Cases {
+        type Output: Tuple;
+        type Extract: TryFuture<Ok = Self::Output, Error = Error>;
+
+        /// Creates the future of extractor mapped to the specified method, if exists.
+        fn select(&self, method: &Method) -> Option<Self::Extract>;
+    }
+
+    /// An `Extractor` that switches the extraction by the request method.
+    pub struct MethodSwitch<C> {
+        pub(super) cases: Arc<C>,
+    }
+
+    impl<C> fmt::Debug for MethodSwitch<C> {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("MethodSwitch").finish()
+        }
+    }
+
+    impl MethodSwitch<()> {
+        /// Maps the specified extractor to the request method.
+        pub fn on<E>(self, method: Method, extractor: E) -> MethodSwitch<Case<E, NoCase<E::Output>>>
+        where
+            E: Extractor,
+        {
+            MethodSwitch {
+                cases: Arc::new(Case {
+                    method,
+                    extractor,
+                    next: NoCase(PhantomData),
+                }),
+            }
+        }
+    }
+
+    impl<C> MethodSwitch<C>
+    where
+        C: Cases,
+    {
+        /// Maps the specified extractor to the request method.
+        ///
+        /// If the same method is mapped multiple times, the first one takes precedence.
+        pub fn on<E>(self, method: Method, extractor: E) -> MethodSwitch<Case<E, C>>
+        where
+            E: Extractor<Output = C::Output>,
+        {
+            let next = Arc::try_unwrap(self.cases)
+                .ok()
+                .expect("the cases should not be shared during building");
+            MethodSwitch {
+                cases: Arc::new(Case {
+                    method,
+                    extractor,
+                    next,
+                }),
+            }
+        }
+    }
+
+    impl<C> Extractor for MethodSwitch<C>
+    where
+        C: Cases,
+    {
+        type Output = C::Output;
+        type Error = Error;
+        type Extract = MethodSwitchFuture<C>;
+
+        fn extract(&self) -> Self::Extract {
+            MethodSwitchFuture {
+                cases: self.cases.clone(),
+                chosen: None,
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct MethodSwitchFuture<C: Cases> {
+        cases: Arc<C>,
+        chosen: Option<C::Extract>,
+    }
+
+    impl<C> TryFuture for MethodSwitchFuture<C>
+    where
+        C: Cases,
+    {
+        type Ok = C::Output;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if self.chosen.is_none() {
+                self.chosen = Some(
+                    self.cases
+                        .select(input.request.method())
+                        .ok_or(StatusCode::METHOD_NOT_ALLOWED)?,
+                );
+            }
+            self.chosen
+                .as_mut()
+                .expect("the future should be chosen")
+                .poll_ready(input)
+        }
+    }
+
+    /// The terminal of `Cases`, which matches no methods.
+    #[allow(missing_debug_implementations)]
+    pub struct NoCase<T>(PhantomData<fn() -> T>);
+
+    impl<T: Tuple> Cases for NoCase<T> {
+        type Output = T;
+        type Extract = NoCaseFuture<T>;
+
+        fn select(&self, _: &Method) -> Option<Self::Extract> {
+            None
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct NoCaseFuture<T>(Never, PhantomData<fn() -> T>);
+
+    impl<T: Tuple> TryFuture for NoCaseFuture<T> {
+        type Ok = T;
+        type Error = Error;
+
+        fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            match self.0 {}
+        }
+    }
+
+    /// A case of `MethodSwitch` that maps an extractor to a method.
+    #[allow(missing_debug_implementations)]
+    pub struct Case<E, C> {
+        method: Method,
+        extractor: E,
+        next: C,
+    }
+
+    impl<E, C> Cases for Case<E, C>
+    where
+        E: Extractor,
+        C: Cases<Output = E::Output>,
+    {
+        type Output = E::Output;
+        type Extract = CaseFuture<E::Extract, C::Extract>;
+
+        fn select(&self, method: &Method) -> Option<Self::Extract> {
+            // the cases are stored in the reverse order of registration.
+            self.next.select(method).map(CaseFuture::Next).or_else(|| {
+                if *method == self.method {
+                    Some(CaseFuture::This(self.extractor.extract()))
+                } else {
+                    None
+                }
+            })
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub enum CaseFuture<L, R> {
+        This(L),
+        Next(R),
+    }
+
+    impl<L, R> TryFuture for CaseFuture<L, R>
+    where
+        L: TryFuture,
+        R: TryFuture<Ok = L::Ok, Error = Error>,
+    {
+        type Ok = L::Ok;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            match self {
+                CaseFuture::This(ref mut future) => future.poll_ready(input).map_err(Into::into),
+                CaseFuture::Next(ref mut future) => future.poll_ready(input),
+            }
+        }
+    }
+}
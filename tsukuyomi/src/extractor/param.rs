@@ -0,0 +1,73 @@
+//! Extractors for accessing the path parameters.
+//!
+//! The values are converted by using `FromPercentEncoded`, in the same way as
+//! the parameters declared in `path!`.
+
+use {
+    super::Extractor,
+    crate::{
+        error::Error,
+        future::TryFuture,
+        input::{
+            param::{FromPercentEncoded, Params, PercentEncoded},
+            Input,
+        },
+    },
+};
+
+fn parse<T>(
+    input: &Input<'_>,
+    f: impl for<'a> FnOnce(&'a Params<'_>) -> Option<&'a str>,
+) -> Result<(T,), Error>
+where
+    T: FromPercentEncoded,
+{
+    let params = input
+        .params
+        .as_ref()
+        .ok_or_else(|| crate::error::internal_server_error("missing Params"))?;
+    let raw = f(params).ok_or_else(|| crate::error::internal_server_error("missing parameter"))?;
+    T::from_percent_encoded(unsafe { PercentEncoded::new_unchecked(raw) })
+        .map(|value| (value,))
+        .map_err(Into::into)
+}
+
+/// Creates an `Extractor` that parses the `pos`-th parameter.
+pub fn pos<T>(
+    pos: usize,
+) -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: FromPercentEncoded + 'static,
+{
+    super::ready(move |input| parse(input, |params| params.get(pos)))
+}
+
+/// Creates an `Extractor` that parses the parameter with the specified name.
+pub fn named<T>(
+    name: &'static str,
+) -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: FromPercentEncoded + 'static,
+{
+    super::ready(move |input| parse(input, |params| params.name(name)))
+}
+
+/// Creates an `Extractor` that parses the catch-all parameter.
+pub fn wildcard<T>() -> impl Extractor<
+    Output = (T,), //
+    Error = Error,
+    Extract = impl TryFuture<Ok = (T,), Error = Error> + Send + 'static,
+>
+where
+    T: FromPercentEncoded + 'static,
+{
+    super::ready(move |input| parse(input, |params| params.catch_all()))
+}
@@ -0,0 +1,417 @@
+//! Extractors for the partial requests based on the header field `Range`.
+
+use {
+    super::Extractor,
+    crate::{error::HttpError, future::TryFuture},
+    http::{
+        header::{HeaderValue, CONTENT_RANGE, RANGE},
+        Request, Response, StatusCode,
+    },
+    std::fmt,
+};
+
+/// Creates an `Extractor` that parses the header field `Range`.
+///
+/// The extracted value is `None` if the request does not have `Range`, or its value is
+/// malformed or uses a range unit other than `bytes` (such values are ignored as
+/// permitted by RFC 7233).  If the length of representation is known in advance,
+/// the extraction fails with `RangeNotSatisfiable` when none of the ranges overlaps
+/// the representation.
+pub fn parse(
+    total_len_hint: Option<u64>,
+) -> impl Extractor<
+    Output = (Option<Range>,), //
+    Error = RangeNotSatisfiable,
+    Extract = impl TryFuture<Ok = (Option<Range>,), Error = RangeNotSatisfiable> + Send + 'static,
+> {
+    super::ready(move |input| {
+        let range = match Range::from_request(input.request) {
+            Some(range) => range,
+            None => return Ok((None,)),
+        };
+        if let Some(total_len) = total_len_hint {
+            range.resolve(total_len)?;
+        }
+        Ok((Some(range),))
+    })
+}
+
+/// Creates an `Extractor` that parses the header field `Range`, rejecting the requests
+/// with multiple ranges.
+///
+/// The behavior is the same as `parse`, except that the extraction fails with
+/// `RangeNotSatisfiable` if the value contains multiple ranges.  If the length of
+/// representation is known, the ranges are counted after coalescing the overlapping ones.
+pub fn single(
+    total_len_hint: Option<u64>,
+) -> impl Extractor<
+    Output = (Option<Range>,), //
+    Error = RangeNotSatisfiable,
+    Extract = impl TryFuture<Ok = (Option<Range>,), Error = RangeNotSatisfiable> + Send + 'static,
+> {
+    super::ready(move |input| {
+        let range = match Range::from_request(input.request) {
+            Some(range) => range,
+            None => return Ok((None,)),
+        };
+        let num_ranges = match total_len_hint {
+            Some(total_len) => range.resolve(total_len)?.len(),
+            None => range.specs().len(),
+        };
+        if num_ranges > 1 {
+            return Err(RangeNotSatisfiable {
+                complete_length: total_len_hint,
+                reason: "multiple ranges are not supported",
+            });
+        }
+        Ok((Some(range),))
+    })
+}
+
+/// An element of the header field `Range`, not yet resolved against the length of
+/// representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeSpec {
+    /// The range with both ends, e.g. `bytes=0-499`.
+    Bounded {
+        /// The position of the first byte.
+        first: u64,
+        /// The position of the last byte (inclusive).
+        last: u64,
+    },
+
+    /// The open-ended range, e.g. `bytes=100-`.
+    From {
+        /// The position of the first byte.
+        first: u64,
+    },
+
+    /// The range of the last bytes, e.g. `bytes=-500`.
+    Suffix {
+        /// The number of the last bytes.
+        length: u64,
+    },
+}
+
+impl RangeSpec {
+    fn parse(s: &str) -> Option<Self> {
+        let mut iter = s.splitn(2, '-');
+        let (first, last) = (iter.next()?.trim(), iter.next()?.trim());
+        let parse_pos = |s: &str| {
+            if s.bytes().all(|b| b.is_ascii_digit()) {
+                s.parse::<u64>().ok()
+            } else {
+                None
+            }
+        };
+        match (first, last) {
+            ("", "") => None,
+            ("", length) => Some(RangeSpec::Suffix {
+                length: parse_pos(length)?,
+            }),
+            (first, "") => Some(RangeSpec::From {
+                first: parse_pos(first)?,
+            }),
+            (first, last) => {
+                let (first, last) = (parse_pos(first)?, parse_pos(last)?);
+                if last < first {
+                    return None;
+                }
+                Some(RangeSpec::Bounded { first, last })
+            }
+        }
+    }
+
+    /// Resolves this range against the complete length of representation.
+    ///
+    /// The returned value is `None` if the range is not satisfiable.
+    pub fn resolve(self, complete_length: u64) -> Option<ByteRange> {
+        let (first, last) = match self {
+            RangeSpec::Bounded { first, last } => (first, Some(last)),
+            RangeSpec::From { first } => (first, None),
+            RangeSpec::Suffix { length: 0 } => return None,
+            RangeSpec::Suffix { length } => (complete_length.saturating_sub(length), None),
+        };
+        if first >= complete_length {
+            return None;
+        }
+        let end = complete_length - 1;
+        Some(ByteRange {
+            first,
+            last: last.map_or(end, |last| std::cmp::min(last, end)),
+        })
+    }
+}
+
+/// A satisfiable range of bytes within the representation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    first: u64,
+    last: u64,
+}
+
+impl ByteRange {
+    /// Returns the position of the first byte.
+    pub fn first(&self) -> u64 {
+        self.first
+    }
+
+    /// Returns the position of the last byte (inclusive).
+    pub fn last(&self) -> u64 {
+        self.last
+    }
+
+    /// Returns the number of bytes in this range.
+    pub fn len(&self) -> u64 {
+        self.last - self.first + 1
+    }
+
+    /// Returns whether this range is empty, which is always `false`.
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    /// Creates the value of `Content-Range` for this range.
+    pub fn to_content_range(&self, complete_length: u64) -> HeaderValue {
+        HeaderValue::from_shared(
+            format!("bytes {}-{}/{}", self.first, self.last, complete_length).into(),
+        )
+        .expect("should be a valid header value")
+    }
+}
+
+/// The parsed value of the header field `Range`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    specs: Vec<RangeSpec>,
+}
+
+impl Range {
+    /// Parses the value of `Range` in the specified request.
+    pub fn from_request<T>(request: &Request<T>) -> Option<Self> {
+        Self::parse(request.headers().get(RANGE)?.to_str().ok()?)
+    }
+
+    /// Parses a header value of `Range`.
+    ///
+    /// The returned value is `None` if the value is malformed, or the range unit
+    /// is not `bytes`.
+    pub fn parse(value: &str) -> Option<Self> {
+        let mut iter = value.trim().splitn(2, '=');
+        let (unit, ranges) = (iter.next()?.trim(), iter.next()?);
+        if !unit.eq_ignore_ascii_case("bytes") {
+            return None;
+        }
+
+        let mut specs = vec![];
+        for element in ranges.split(',').map(str::trim) {
+            if element.is_empty() {
+                continue;
+            }
+            specs.push(RangeSpec::parse(element)?);
+        }
+        if specs.is_empty() {
+            return None;
+        }
+
+        Some(Self { specs })
+    }
+
+    /// Returns the list of range specifiers, in the order they appear in the header value.
+    pub fn specs(&self) -> &[RangeSpec] {
+        &self.specs[..]
+    }
+
+    /// Resolves the ranges against the complete length of representation.
+    ///
+    /// The unsatisfiable ranges are dropped, and the overlapping or adjacent ones are
+    /// coalesced.  The returned ranges are sorted in ascending order.  If none of the
+    /// ranges is satisfiable, an error is returned.
+    pub fn resolve(&self, complete_length: u64) -> Result<Vec<ByteRange>, RangeNotSatisfiable> {
+        let mut ranges: Vec<ByteRange> = self
+            .specs
+            .iter()
+            .filter_map(|spec| spec.resolve(complete_length))
+            .collect();
+        ranges.sort_by_key(|range| range.first);
+
+        let mut coalesced: Vec<ByteRange> = Vec::with_capacity(ranges.len());
+        for range in ranges {
+            if let Some(prev) = coalesced.last_mut() {
+                if range.first <= prev.last.saturating_add(1) {
+                    prev.last = std::cmp::max(prev.last, range.last);
+                    continue;
+                }
+            }
+            coalesced.push(range);
+        }
+
+        if coalesced.is_empty() {
+            return Err(RangeNotSatisfiable {
+                complete_length: Some(complete_length),
+                reason: "no satisfiable range",
+            });
+        }
+        Ok(coalesced)
+    }
+}
+
+/// The error that represents the status code `416 Range Not Satisfiable`.
+///
+/// If the complete length of representation is known, the response contains
+/// the header field `Content-Range` with the length (e.g. `bytes */1000`).
+#[derive(Debug)]
+pub struct RangeNotSatisfiable {
+    complete_length: Option<u64>,
+    reason: &'static str,
+}
+
+impl RangeNotSatisfiable {
+    /// Returns the complete length of representation, if known.
+    pub fn complete_length(&self) -> Option<u64> {
+        self.complete_length
+    }
+}
+
+impl fmt::Display for RangeNotSatisfiable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "range not satisfiable: {}", self.reason)
+    }
+}
+
+impl HttpError for RangeNotSatisfiable {
+    type Body = String;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let mut response = Response::builder();
+        response.status(StatusCode::RANGE_NOT_SATISFIABLE);
+        if let Some(complete_length) = self.complete_length {
+            response.header(CONTENT_RANGE, format!("bytes */{}", complete_length));
+        }
+        response
+            .body(self.to_string())
+            .expect("should be a valid response")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(value: &str, complete_length: u64) -> Option<Vec<(u64, u64)>> {
+        let range = Range::parse(value).expect("should be a valid value");
+        range.resolve(complete_length).ok().map(|ranges| {
+            ranges
+                .into_iter()
+                .map(|range| (range.first(), range.last()))
+                .collect()
+        })
+    }
+
+    #[test]
+    fn parse_specs() {
+        assert_eq!(
+            Range::parse("bytes=0-499").unwrap().specs(),
+            &[RangeSpec::Bounded {
+                first: 0,
+                last: 499
+            }]
+        );
+        assert_eq!(
+            Range::parse("bytes=100-").unwrap().specs(),
+            &[RangeSpec::From { first: 100 }]
+        );
+        assert_eq!(
+            Range::parse("bytes=-500").unwrap().specs(),
+            &[RangeSpec::Suffix { length: 500 }]
+        );
+        assert_eq!(
+            Range::parse("Bytes = 0-0 , -1,, 5-").unwrap().specs(),
+            &[
+                RangeSpec::Bounded { first: 0, last: 0 },
+                RangeSpec::Suffix { length: 1 },
+                RangeSpec::From { first: 5 },
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_invalid() {
+        assert_eq!(Range::parse(""), None);
+        assert_eq!(Range::parse("bytes"), None);
+        assert_eq!(Range::parse("bytes="), None);
+        assert_eq!(Range::parse("bytes=,"), None);
+        assert_eq!(Range::parse("bytes=-"), None);
+        assert_eq!(Range::parse("bytes=500-499"), None);
+        assert_eq!(Range::parse("bytes=a-b"), None);
+        assert_eq!(Range::parse("bytes=+1-2"), None);
+        assert_eq!(Range::parse("bytes=0-1,x"), None);
+        assert_eq!(Range::parse("bytes=18446744073709551616-"), None);
+        assert_eq!(Range::parse("items=0-1"), None);
+    }
+
+    #[test]
+    fn resolve_single() {
+        assert_eq!(resolve("bytes=0-499", 1000), Some(vec![(0, 499)]));
+        assert_eq!(resolve("bytes=500-", 1000), Some(vec![(500, 999)]));
+        assert_eq!(resolve("bytes=-200", 1000), Some(vec![(800, 999)]));
+        assert_eq!(resolve("bytes=0-0", 1), Some(vec![(0, 0)]));
+    }
+
+    #[test]
+    fn resolve_clamped() {
+        assert_eq!(resolve("bytes=900-1999", 1000), Some(vec![(900, 999)]));
+        assert_eq!(resolve("bytes=-2000", 1000), Some(vec![(0, 999)]));
+    }
+
+    #[test]
+    fn resolve_unsatisfiable() {
+        assert_eq!(resolve("bytes=1000-", 1000), None);
+        assert_eq!(resolve("bytes=1000-1999", 1000), None);
+        assert_eq!(resolve("bytes=-0", 1000), None);
+        assert_eq!(resolve("bytes=0-", 0), None);
+        assert_eq!(resolve("bytes=-1", 0), None);
+
+        let err = Range::parse("bytes=2000-")
+            .unwrap()
+            .resolve(1000)
+            .unwrap_err();
+        assert_eq!(err.complete_length(), Some(1000));
+    }
+
+    #[test]
+    fn resolve_drops_unsatisfiable_ranges() {
+        assert_eq!(resolve("bytes=2000-,0-9", 1000), Some(vec![(0, 9)]));
+    }
+
+    #[test]
+    fn resolve_coalesces_ranges() {
+        // overlapping
+        assert_eq!(resolve("bytes=0-499,400-599", 1000), Some(vec![(0, 599)]));
+        // adjacent
+        assert_eq!(resolve("bytes=0-9,10-19", 1000), Some(vec![(0, 19)]));
+        // contained
+        assert_eq!(resolve("bytes=0-999,10-19", 1000), Some(vec![(0, 999)]));
+        // unordered
+        assert_eq!(
+            resolve("bytes=500-599,-100,0-9", 1000),
+            Some(vec![(0, 9), (500, 599), (900, 999)])
+        );
+        // suffix overlapping with the open-ended range
+        assert_eq!(resolve("bytes=-100,850-", 1000), Some(vec![(850, 999)]));
+        // disjoint
+        assert_eq!(
+            resolve("bytes=0-9,20-29", 1000),
+            Some(vec![(0, 9), (20, 29)])
+        );
+    }
+
+    #[test]
+    fn byte_range_content_range() {
+        let range = RangeSpec::Bounded { first: 0, last: 9 }
+            .resolve(100)
+            .unwrap();
+        assert_eq!(range.len(), 10);
+        assert_eq!(range.to_content_range(100), "bytes 0-9/100");
+    }
+}
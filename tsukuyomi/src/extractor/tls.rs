@@ -0,0 +1,221 @@
+//! Extractors for accessing the TLS metadata of the underlying connection.
+//!
+//! The metadata and the certificate chain presented by the client are captured once
+//! per connection when the TLS handshake completes, and stored in the extension map
+//! of each request issued on the connection.
+//! Currently the capturing is provided for the connections accepted by `tokio-rustls`
+//! (requires the feature `use-rustls`).
+
+#[cfg(feature = "use-rustls")]
+pub use self::rustls::{with_tls_info, WithTlsInfo};
+
+use {
+    super::Extractor,
+    crate::{future::TryFuture, guard::PeerCertificates, util::Never},
+};
+
+/// The TLS metadata negotiated on the connection.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsInfo {
+    protocol_version: String,
+    cipher_suite: String,
+    server_name: Option<String>,
+    alpn_protocol: Option<String>,
+}
+
+impl TlsInfo {
+    /// Creates a new `TlsInfo` from the specified components.
+    pub fn new(
+        protocol_version: impl Into<String>,
+        cipher_suite: impl Into<String>,
+        server_name: Option<String>,
+    ) -> Self {
+        Self {
+            protocol_version: protocol_version.into(),
+            cipher_suite: cipher_suite.into(),
+            server_name,
+            alpn_protocol: None,
+        }
+    }
+
+    /// Sets the protocol negotiated via ALPN.
+    pub fn with_alpn_protocol(self, protocol: impl Into<String>) -> Self {
+        Self {
+            alpn_protocol: Some(protocol.into()),
+            ..self
+        }
+    }
+
+    /// Returns the negotiated protocol version, e.g. `"TLSv1.2"`.
+    pub fn protocol_version(&self) -> &str {
+        &self.protocol_version
+    }
+
+    /// Returns the name of negotiated cipher suite, e.g. `"TLS13_AES_128_GCM_SHA256"`.
+    pub fn cipher_suite(&self) -> &str {
+        &self.cipher_suite
+    }
+
+    /// Returns the server name sent by the client via SNI, if available.
+    pub fn server_name(&self) -> Option<&str> {
+        self.server_name.as_ref().map(|s| s.as_str())
+    }
+
+    /// Returns the protocol negotiated via ALPN, e.g. `"h2"`, if available.
+    pub fn alpn_protocol(&self) -> Option<&str> {
+        self.alpn_protocol.as_ref().map(AsRef::as_ref)
+    }
+}
+
+/// Creates an `Extractor` that returns the TLS metadata of the current connection.
+///
+/// The extracted value is `None` if the request was received on a plaintext
+/// listener or the metadata was not captured.
+pub fn info() -> impl Extractor<
+    Output = (Option<TlsInfo>,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Option<TlsInfo>,), Error = Never> + Send + 'static,
+> {
+    super::ready(|input| Ok((input.request.extensions().get::<TlsInfo>().cloned(),)))
+}
+
+/// Creates an `Extractor` that returns the certificate chain presented by the client
+/// on the current connection (i.e. mutual TLS).
+///
+/// The extracted value is `None` if the request was received on a plaintext listener,
+/// the client did not present any certificate, or the chain was not captured.
+/// The DER-encoded certificates can be inspected by the helper functions in
+/// `guard::x509` (requires the feature `x509`).
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, extractor, guard::PeerCertificates, App};
+/// # use http::StatusCode;
+/// # fn main() -> tsukuyomi::app::Result<()> {
+/// let app = App::create(
+///     path!("/").to(endpoint::get()
+///         .extract(extractor::tls::peer_certificates())
+///         .call(|certs: Option<PeerCertificates>| match certs {
+///             Some(ref certs) if certs.chain()[0] == &b"trusted"[..] => Ok("welcome"),
+///             _ => Err(tsukuyomi::Error::from(StatusCode::FORBIDDEN)),
+///         }))
+/// )?;
+/// # drop(app);
+/// # Ok(())
+/// # }
+/// ```
+pub fn peer_certificates() -> impl Extractor<
+    Output = (Option<PeerCertificates>,), //
+    Error = Never,
+    Extract = impl TryFuture<Ok = (Option<PeerCertificates>,), Error = Never> + Send + 'static,
+> {
+    super::ready(|input| {
+        Ok((input
+            .request
+            .extensions()
+            .get::<PeerCertificates>()
+            .filter(|certs| !certs.chain().is_empty())
+            .cloned(),))
+    })
+}
+
+#[cfg(feature = "use-rustls")]
+mod rustls {
+    use {
+        super::TlsInfo,
+        crate::guard::PeerCertificates,
+        futures01::Poll,
+        http::Request,
+        tokio_rustls::{
+            rustls::{ProtocolVersion, ServerSession, Session},
+            TlsStream,
+        },
+        tsukuyomi_service::{modify_service_ref, ModifyService, Service},
+    };
+
+    /// Creates a `ModifyService` that captures the TLS metadata and the certificate
+    /// chain of client from the session established by `tokio-rustls`, and inserts
+    /// them into the extension map of each `Request` before calling the internal service.
+    ///
+    /// The certificate chain is available only if the server requests the client
+    /// authentication (e.g. `AllowAnyAuthenticatedClient`).
+    pub fn with_tls_info<IO, S, Bd>() -> impl for<'a> ModifyService<
+        &'a TlsStream<IO, ServerSession>, //
+        Request<Bd>,
+        S,
+        Response = S::Response,
+        Error = S::Error,
+        Service = WithTlsInfo<S>,
+        ModifyError = crate::util::Never,
+        Future = futures01::future::FutureResult<WithTlsInfo<S>, crate::util::Never>,
+    >
+    where
+        S: Service<Request<Bd>>,
+    {
+        modify_service_ref(|service: S, stream: &TlsStream<IO, ServerSession>| {
+            let (_, session) = stream.get_ref();
+            Ok(WithTlsInfo {
+                service,
+                info: capture(session),
+                peer_certificates: session
+                    .get_peer_certificates()
+                    .filter(|certs| !certs.is_empty())
+                    .map(|certs| {
+                        PeerCertificates::new(certs.into_iter().map(|cert| cert.0).collect())
+                    }),
+            })
+        })
+    }
+
+    fn capture(session: &ServerSession) -> Option<TlsInfo> {
+        let protocol_version = match session.get_protocol_version()? {
+            ProtocolVersion::SSLv2 => "SSLv2".into(),
+            ProtocolVersion::SSLv3 => "SSLv3".into(),
+            ProtocolVersion::TLSv1_0 => "TLSv1.0".into(),
+            ProtocolVersion::TLSv1_1 => "TLSv1.1".into(),
+            ProtocolVersion::TLSv1_2 => "TLSv1.2".into(),
+            ProtocolVersion::TLSv1_3 => "TLSv1.3".into(),
+            ProtocolVersion::Unknown(v) => format!("Unknown(0x{:04x})", v),
+        };
+        let cipher_suite = format!("{:?}", session.get_negotiated_ciphersuite()?.suite);
+        let server_name = session.get_sni_hostname().map(ToOwned::to_owned);
+        let info = TlsInfo::new(protocol_version, cipher_suite, server_name);
+        Some(match session.get_alpn_protocol() {
+            Some(protocol) => info.with_alpn_protocol(protocol),
+            None => info,
+        })
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct WithTlsInfo<S> {
+        service: S,
+        info: Option<TlsInfo>,
+        peer_certificates: Option<PeerCertificates>,
+    }
+
+    impl<S, Bd> Service<Request<Bd>> for WithTlsInfo<S>
+    where
+        S: Service<Request<Bd>>,
+    {
+        type Response = S::Response;
+        type Error = S::Error;
+        type Future = S::Future;
+
+        #[inline]
+        fn poll_ready(&mut self) -> Poll<(), Self::Error> {
+            self.service.poll_ready()
+        }
+
+        #[inline]
+        fn call(&mut self, mut request: Request<Bd>) -> Self::Future {
+            if let Some(ref info) = self.info {
+                request.extensions_mut().insert(info.clone());
+            }
+            if let Some(ref certs) = self.peer_certificates {
+                request.extensions_mut().insert(certs.clone());
+            }
+            self.service.call(request)
+        }
+    }
+}
@@ -0,0 +1,306 @@
+//! Validation of the extracted values.
+//!
+//! # Example
+//!
+//! ```
+//! # use serde::Deserialize;
+//! use tsukuyomi::extractor::{
+//!     validate::{Validate, ValidationErrors},
+//!     ExtractorExt,
+//! };
+//!
+//! #[derive(Debug, Deserialize)]
+//! struct SignUp {
+//!     password: String,
+//!     confirm_password: String,
+//! }
+//!
+//! impl Validate for SignUp {
+//!     fn validate(&self) -> Result<(), ValidationErrors> {
+//!         let mut errors = ValidationErrors::new();
+//!         if self.password != self.confirm_password {
+//!             errors.add("confirm_password", "not matched to password");
+//!         }
+//!         errors.into_result()
+//!     }
+//! }
+//!
+//! let extractor = tsukuyomi::extractor::body::json::<SignUp>().validate();
+//! # drop(extractor);
+//! ```
+
+use {
+    crate::{
+        error::{Error, HttpError},
+        extractor::Extractor,
+        future::{Async, Poll, TryFuture},
+        input::Input,
+        output::ResponseBody,
+    },
+    http::{header, Request, Response, StatusCode},
+    serde::Serialize,
+    std::{borrow::Cow, fmt, sync::Arc},
+};
+
+/// A trait representing the values to be validated after extraction.
+pub trait Validate {
+    /// Validates the value and returns the list of errors if it is invalid.
+    fn validate(&self) -> Result<(), ValidationErrors>;
+}
+
+/// A field-level error reported by `Validate`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FieldError {
+    /// The name of field.
+    pub field: Cow<'static, str>,
+    /// The description of the error.
+    pub message: Cow<'static, str>,
+}
+
+/// A collection of `FieldError`s.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    /// Creates an empty `ValidationErrors`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends an error associated with the specified field.
+    pub fn add(
+        &mut self,
+        field: impl Into<Cow<'static, str>>,
+        message: impl Into<Cow<'static, str>>,
+    ) -> &mut Self {
+        self.errors.push(FieldError {
+            field: field.into(),
+            message: message.into(),
+        });
+        self
+    }
+
+    /// Returns whether no errors have been added.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// Returns an iterator over the added errors.
+    pub fn iter(&self) -> impl Iterator<Item = &FieldError> + '_ {
+        self.errors.iter()
+    }
+
+    /// Converts itself into a `Result`, which will be an `Err` if some errors have been added.
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.errors.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, error) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str(", ")?;
+            }
+            write!(f, "{}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+type Renderer =
+    Arc<dyn Fn(ValidationErrors, &Request<()>) -> Response<ResponseBody> + Send + Sync + 'static>;
+
+/// A function to render the validation errors, shared by the routes in a scope.
+///
+/// The renderer is registered as a state of the scope by `config::state`, and is
+/// used by the `Validated`s in the scope and its descendants unless they override
+/// it by `Validated::render_with`.
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// use tsukuyomi::extractor::validate::ValidationRenderer;
+///
+/// let app = App::create(chain![
+///     state(ValidationRenderer::new(|errors, _| {
+///         http::Response::builder()
+///             .status(400)
+///             .body(errors.to_string())
+///             .unwrap()
+///     })),
+///     // ...
+/// ]);
+/// # app.unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ValidationRenderer(Renderer);
+
+impl fmt::Debug for ValidationRenderer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValidationRenderer").finish()
+    }
+}
+
+impl ValidationRenderer {
+    /// Creates a `ValidationRenderer` from the function to render the validation errors.
+    pub fn new<F, Bd>(f: F) -> Self
+    where
+        F: Fn(ValidationErrors, &Request<()>) -> Response<Bd> + Send + Sync + 'static,
+        Bd: Into<ResponseBody>,
+    {
+        ValidationRenderer(Arc::new(move |errors, request| {
+            f(errors, request).map(Into::into)
+        }))
+    }
+}
+
+/// The error type returned from `Validated` when the extracted value is invalid.
+///
+/// By default, this error is rendered as a response with the status code
+/// `422 Unprocessable Entity` and the JSON body which lists the field errors.
+/// The rendering can be overridden by `Validated::render_with`, or for the entire
+/// scope by registering a `ValidationRenderer` as its state.
+pub struct ValidationError {
+    errors: ValidationErrors,
+    renderer: Option<Renderer>,
+}
+
+impl ValidationError {
+    /// Creates a `ValidationError` from the list of field errors.
+    pub fn new(errors: ValidationErrors) -> Self {
+        Self {
+            errors,
+            renderer: None,
+        }
+    }
+
+    /// Returns the list of field errors.
+    pub fn errors(&self) -> &ValidationErrors {
+        &self.errors
+    }
+}
+
+impl fmt::Debug for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ValidationError")
+            .field("errors", &self.errors)
+            .finish()
+    }
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "validation failed: {}", self.errors)
+    }
+}
+
+impl HttpError for ValidationError {
+    type Body = ResponseBody;
+
+    fn into_response(self, request: &Request<()>) -> Response<Self::Body> {
+        if let Some(renderer) = self.renderer {
+            return renderer(self.errors, request);
+        }
+
+        #[derive(Serialize)]
+        struct Body<'a> {
+            errors: &'a [FieldError],
+        }
+
+        let body = serde_json::to_vec(&Body {
+            errors: &self.errors.errors,
+        })
+        .expect("should be serializable");
+        Response::builder()
+            .status(StatusCode::UNPROCESSABLE_ENTITY)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(body.into())
+            .expect("should be a valid response")
+    }
+}
+
+/// An `Extractor` which validates the output of the inner extractor.
+///
+/// The value of this type is created by `ExtractorExt::validate`.
+pub struct Validated<E> {
+    pub(super) extractor: E,
+    pub(super) renderer: Option<Renderer>,
+}
+
+impl<E: fmt::Debug> fmt::Debug for Validated<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Validated")
+            .field("extractor", &self.extractor)
+            .finish()
+    }
+}
+
+impl<E> Validated<E> {
+    /// Overrides the function to render the validation errors into an HTTP response.
+    ///
+    /// The function takes precedence over the `ValidationRenderer` registered in the scope.
+    pub fn render_with<F, Bd>(self, f: F) -> Self
+    where
+        F: Fn(ValidationErrors, &Request<()>) -> Response<Bd> + Send + Sync + 'static,
+        Bd: Into<ResponseBody>,
+    {
+        Self {
+            renderer: Some(ValidationRenderer::new(f).0),
+            ..self
+        }
+    }
+}
+
+impl<E, T> Extractor for Validated<E>
+where
+    E: Extractor<Output = (T,)>,
+    T: Validate,
+{
+    type Output = (T,);
+    type Error = Error;
+    type Extract = ValidatedFuture<E::Extract>;
+
+    fn extract(&self) -> Self::Extract {
+        ValidatedFuture {
+            future: self.extractor.extract(),
+            renderer: self.renderer.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct ValidatedFuture<Fut> {
+    future: Fut,
+    renderer: Option<Renderer>,
+}
+
+impl<Fut, T> TryFuture for ValidatedFuture<Fut>
+where
+    Fut: TryFuture<Ok = (T,)>,
+    T: Validate,
+{
+    type Ok = (T,);
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let (value,) = futures01::try_ready!(self.future.poll_ready(input).map_err(Into::into));
+        match value.validate() {
+            Ok(()) => Ok(Async::Ready((value,))),
+            Err(errors) => Err(ValidationError {
+                errors,
+                renderer: self.renderer.take().or_else(|| {
+                    input
+                        .state::<ValidationRenderer>()
+                        .map(|renderer| renderer.0.clone())
+                }),
+            }
+            .into()),
+        }
+    }
+}
@@ -1,111 +1,793 @@
 //! The basic components for serving static files.
+//!
+//! # Resolution of request paths
+//!
+//! The handler registered by `Staticfiles` maps the request paths to the files
+//! through `resolve_path` at request time.  The mount prefix is matched by the
+//! router, and the remaining part is canonicalized by the rules described in
+//! `resolve_path`.  As a result, `/static/app.css`, `/static//app.css`,
+//! `/static/./app.css` and `/static/%2e/app.css` are served from the same file,
+//! while any request that would escape the root directory is rejected with
+//! `404 Not Found`.  The same rules apply in the eager mode (`Staticfiles::eager`),
+//! where the top-level entries are also registered as the routes.
+//!
+//! The resolved path is then checked on the file system by `SafePath`, so that
+//! the symbolic links are handled according to the `SymlinkPolicy` configured
+//! with `Staticfiles::symlinks`.  By default, the files reachable through the
+//! symbolic links pointing outside of the root directory are not served.  If the
+//! additional roots are given by `Staticfiles::with_fallback`, the path is looked up
+//! in each root in order and checked by the `SafePath` of that root.
+//!
+//! # Methods
+//!
+//! The handlers of `Staticfiles` answer `GET`, `HEAD` and `OPTIONS`.  The response
+//! to `HEAD` has the same header fields as the one to `GET` and the file is not
+//! read, and `OPTIONS` is answered with `204 No Content` and the `Allow` header.
+//! The other methods are rejected with `405 Method Not Allowed`.
+
+#[cfg(feature = "archive")]
+mod archive;
+mod cache;
+#[cfg(feature = "embed")]
+mod embedded;
+mod upload;
+
+#[cfg(feature = "archive")]
+pub use self::archive::{ArchiveDir, ArchiveFormat, OpenArchiveDir};
+pub use self::cache::{CacheConfig, CacheMetrics, CachedFile, FileCache};
+#[cfg(feature = "embed")]
+pub use self::embedded::{Embedded, ServeEmbedded};
+pub use self::upload::{
+    Fsync, PersistError, Spool, TempUpload, TempUploads, DEFAULT_MAX_FILES, DEFAULT_MAX_FILE_SIZE,
+};
 
 use {
+    self::cache::Lookup,
     crate::{
-        error::Error,
+        error::{Error, HttpError},
+        etag::{fnv1a, ETag, FNV_OFFSET_BASIS},
         future::TryFuture,
-        handler::ModifyHandler,
+        handler::{AllowedMethods, ModifyHandler},
         input::Input,
-        output::{IntoResponse, ResponseBody},
+        output::{conditional, html, resumable, IntoResponse, ResponseBody},
         responder::Responder,
     },
     bytes::{BufMut, Bytes, BytesMut},
     filetime::FileTime,
-    futures01::{Async, Poll, Stream},
+    futures01::{sync::oneshot, Async, Future, Poll, Stream},
     http::{
-        header::{self, HeaderMap},
-        Request, Response, StatusCode,
+        header::{self, HeaderMap, HeaderValue},
+        Method, Request, Response, StatusCode,
     },
+    lazy_static::lazy_static,
     log::trace,
     mime::Mime,
     std::{
-        borrow::Cow,
-        cmp, fmt,
+        cmp,
+        collections::{btree_map, BTreeMap, HashMap, HashSet, VecDeque},
+        ffi::OsString,
+        fmt,
         fs::{File, Metadata},
-        io::{self, Read as _Read},
+        io::{self, Read as _Read, Seek as _Seek, SeekFrom},
         mem,
-        ops::Deref,
-        path::{Path, PathBuf},
-        str::FromStr,
-        sync::Arc,
-        time::Duration,
+        ops::{self, Deref},
+        path::{Component, Path, PathBuf},
+        sync::{Arc, Mutex, MutexGuard},
+        time::{Duration, SystemTime},
     },
-    time::Timespec,
-    tokio_threadpool::blocking as poll_blocking,
+    tokio_threadpool::{blocking as poll_blocking, ThreadPool},
+    url::percent_encoding::{percent_decode, utf8_percent_encode, PATH_SEGMENT_ENCODE_SET},
 };
 
+// ==== path resolution ====
+
+/// Resolves the percent-encoded request path relative to `root` into a file path.
+///
+/// The path is canonicalized by the following rules:
+///
+/// 1. The path is split into segments by `/` *before* percent-decoding, and each
+///    segment is decoded exactly once as UTF-8.  The segments that are not valid
+///    UTF-8 are rejected.
+/// 2. The empty segments (such as those produced by `//`) and the segments equal
+///    to `.` after decoding are skipped.
+/// 3. The segments equal to `..` after decoding are rejected.  Parent references
+///    are never resolved lexically, so that a path cannot climb back into the root
+///    after leaving it.
+/// 4. The decoded segments containing `/`, `\`, `:` or NUL are rejected, since
+///    they would be interpreted as multiple components or as a drive prefix by the
+///    platform.  In particular, `%2e%2e%2f` is rejected rather than treated as `../`.
+/// 5. The remaining segments are appended to `root`, and the result is checked to
+///    start with `root` again.
+///
+/// The rejected paths are reported as `404 Not Found`.  Note that the symbolic
+/// links within `root` are not resolved by this function; use `SafePath` to check
+/// the resolved path on the file system.
+pub fn resolve_path(root: &Path, path: &str) -> Result<PathBuf, Error> {
+    let mut resolved = root.to_path_buf();
+    for segment in path.split('/') {
+        let segment = percent_decode(segment.as_bytes())
+            .decode_utf8()
+            .map_err(crate::error::not_found)?;
+        push_segment(&mut resolved, &segment)?;
+    }
+
+    if !resolved.starts_with(root) {
+        return Err(crate::error::not_found(
+            "the path may point outside of the root directory",
+        ));
+    }
+
+    Ok(resolved)
+}
+
+/// Converts the *decoded* path into a relative file path, by the same rules
+/// as `resolve_path` except that the path is split by `/` after decoding.
+///
+/// This is used by the implementation of `FromPercentEncoded` for `PathBuf`.
+pub(crate) fn sanitize_path(path: &str) -> Result<PathBuf, Error> {
+    if path.starts_with('/') {
+        return Err(crate::error::not_found(
+            "the path may point outside of the base directory",
+        ));
+    }
+    let mut sanitized = PathBuf::new();
+    for segment in path.split('/') {
+        push_segment(&mut sanitized, segment)?;
+    }
+    Ok(sanitized)
+}
+
+/// Returns the relative path of the file as the path of URL, e.g. `/assets/app.js`.
+fn relative_url_path(relative: &Path) -> String {
+    let mut url_path = String::new();
+    for component in relative.components() {
+        if let Component::Normal(segment) = component {
+            url_path.push('/');
+            url_path.push_str(&segment.to_string_lossy());
+        }
+    }
+    if url_path.is_empty() {
+        url_path.push('/');
+    }
+    url_path
+}
+
+fn push_segment(path: &mut PathBuf, segment: &str) -> Result<(), Error> {
+    match segment {
+        "" | "." => Ok(()),
+        ".." => Err(crate::error::not_found(
+            "the path may point outside of the root directory",
+        )),
+        s if s.contains(&['/', '\\', ':', '\0'][..]) => Err(crate::error::not_found(
+            "the path segment contains a reserved character",
+        )),
+        s => {
+            path.push(s);
+            Ok(())
+        }
+    }
+}
+
+/// The policy for the symbolic links found while resolving the paths with `SafePath`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SymlinkPolicy {
+    /// Follows all symbolic links, even if they point outside of the root directory.
+    Follow,
+    /// Rejects the paths containing any symbolic link below the root directory.
+    Deny,
+    /// Follows the symbolic links only if the resolved path is still under the
+    /// root directory.
+    ///
+    /// This is the default policy.
+    DenyExternal,
+}
+
+impl Default for SymlinkPolicy {
+    fn default() -> Self {
+        SymlinkPolicy::DenyExternal
+    }
+}
+
+/// A resolver of the untrusted paths into the files under a root directory.
+///
+/// In addition to the lexical rules of `resolve_path`, the resolved path is
+/// canonicalized on the file system and is checked against the configured
+/// `SymlinkPolicy`.  The paths that do not exist, or are rejected by the rules,
+/// are reported as `404 Not Found`.
+///
+/// Note that the methods of this type access the file system synchronously.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, fs::{NamedFile, SafePath}, App};
+/// # use std::path::PathBuf;
+/// # let root = std::env::temp_dir();
+/// let root = SafePath::new(root).expect("the root directory should exist");
+/// let app = App::create(
+///     path!("/static/*path").to(endpoint::get().call_async(move |path: PathBuf| {
+///         root.join(path).map(NamedFile::open)
+///     })),
+/// );
+/// # app.unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct SafePath {
+    root: PathBuf,
+    symlinks: SymlinkPolicy,
+}
+
+impl SafePath {
+    /// Creates a new `SafePath` with the specified root directory.
+    ///
+    /// The root directory is canonicalized at this point.
+    pub fn new(root: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self {
+            root: root.as_ref().canonicalize()?,
+            symlinks: SymlinkPolicy::default(),
+        })
+    }
+
+    /// Sets the policy for the symbolic links.
+    pub fn symlinks(self, policy: SymlinkPolicy) -> Self {
+        Self {
+            symlinks: policy,
+            ..self
+        }
+    }
+
+    /// Returns the canonicalized path of the root directory.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Resolves the percent-encoded request path into a file path under the root directory.
+    pub fn resolve(&self, path: &str) -> Result<PathBuf, Error> {
+        let path = resolve_path(&self.root, path)?;
+        self.verify_or_not_found(&path)
+    }
+
+    /// Resolves the decoded relative path into a file path under the root directory.
+    ///
+    /// The path must consist of only the normal components.
+    pub fn join(&self, path: impl AsRef<Path>) -> Result<PathBuf, Error> {
+        let mut joined = self.root.clone();
+        for component in path.as_ref().components() {
+            match component {
+                Component::Normal(segment) => {
+                    let segment = segment
+                        .to_str()
+                        .ok_or_else(|| crate::error::not_found("the path segment must be UTF-8"))?;
+                    push_segment(&mut joined, segment)?;
+                }
+                Component::CurDir => {}
+                _ => {
+                    return Err(crate::error::not_found(
+                        "the path may point outside of the root directory",
+                    ))
+                }
+            }
+        }
+        self.verify_or_not_found(&joined)
+    }
+
+    fn verify_or_not_found(&self, path: &Path) -> Result<PathBuf, Error> {
+        match self.verify(path) {
+            Ok(Some(path)) => Ok(path),
+            Ok(None) => Err(crate::error::not_found(
+                "the path is rejected by the symlink policy",
+            )),
+            Err(err) => Err(FileError(err).into()),
+        }
+    }
+
+    /// Checks the lexically resolved path under the root directory on the file system.
+    ///
+    /// Returns `None` if the path is rejected by the symlink policy, or the error
+    /// with `NotFound` if the path does not exist.
+    fn verify(&self, path: &Path) -> io::Result<Option<PathBuf>> {
+        match self.symlinks {
+            SymlinkPolicy::Follow => path.canonicalize().map(Some),
+            SymlinkPolicy::DenyExternal => {
+                let canonical = path.canonicalize()?;
+                if canonical.starts_with(&self.root) {
+                    Ok(Some(canonical))
+                } else {
+                    Ok(None)
+                }
+            }
+            SymlinkPolicy::Deny => {
+                let relative = match path.strip_prefix(&self.root) {
+                    Ok(relative) => relative,
+                    Err(..) => return Ok(None),
+                };
+                let mut current = self.root.clone();
+                for component in relative.components() {
+                    current.push(component);
+                    if std::fs::symlink_metadata(&current)?
+                        .file_type()
+                        .is_symlink()
+                    {
+                        return Ok(None);
+                    }
+                }
+                Ok(Some(current))
+            }
+        }
+    }
+}
+
+// ==== errors ====
+
+/// An error that occurred while accessing the file system.
+///
+/// The status code of the response is determined from the kind of the I/O error:
+/// `404 Not Found` for `NotFound`, `403 Forbidden` for `PermissionDenied` and
+/// `500 Internal Server Error` otherwise.  The body of the response contains only
+/// the reason phrase, while the underlying I/O error is kept for logging.
+#[derive(Debug)]
+pub struct FileError(io::Error);
+
+impl FileError {
+    /// Returns the status code of the response.
+    pub fn status(&self) -> StatusCode {
+        match self.0.kind() {
+            io::ErrorKind::NotFound => StatusCode::NOT_FOUND,
+            io::ErrorKind::PermissionDenied => StatusCode::FORBIDDEN,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Returns a reference to the underlying I/O error.
+    pub fn get_ref(&self) -> &io::Error {
+        &self.0
+    }
+
+    /// Consumes itself and returns the underlying I/O error.
+    pub fn into_inner(self) -> io::Error {
+        self.0
+    }
+}
+
+impl From<io::Error> for FileError {
+    fn from(err: io::Error) -> Self {
+        FileError(err)
+    }
+}
+
+impl fmt::Display for FileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to access the file: {}", self.0)
+    }
+}
+
+impl HttpError for FileError {
+    type Body = &'static str;
+
+    fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+        let status = self.status();
+        let mut response = Response::new(status.canonical_reason().unwrap_or(""));
+        *response.status_mut() = status;
+        response
+    }
+}
+
 // ==== headers ====
 
-fn parse_http_date(s: &str) -> Result<Timespec, time::ParseError> {
-    time::strptime(s, "%a, %d %b %Y %T %Z")
-        .or_else(|_| time::strptime(s, "%A, %d-%b-%y %T %Z"))
-        .or_else(|_| time::strptime(s, "%c"))
-        .map(|tm| tm.to_timespec())
+/// Returns the key identifying the version of the file, from its inode (on Unix),
+/// size and modification time.
+fn metadata_key(metadata: &Metadata) -> String {
+    let last_modified = FileTime::from_last_modification_time(&metadata);
+    let key = format!(
+        "{:x}-{:x}.{:x}",
+        metadata.len(),
+        last_modified.seconds(),
+        last_modified.nanoseconds()
+    );
+    match inode(metadata) {
+        Some(ino) => format!("{:x}-{}", ino, key),
+        None => key,
+    }
+}
+
+#[cfg(unix)]
+fn inode(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.ino())
+}
+
+#[cfg(not(unix))]
+fn inode(_: &Metadata) -> Option<u64> {
+    None
+}
+
+/// The strategy for computing the entity tags of the files served by `NamedFile`.
+#[derive(Debug, Clone)]
+pub enum ETagStrategy {
+    /// A weak entity tag computed from the inode (on Unix), size and modification
+    /// time of the file.
+    ///
+    /// This is the default strategy.
+    Metadata,
+
+    /// Same as `Metadata`, but the entity tag is sent as a strong validator.
+    ///
+    /// This strategy is suitable only if the files are replaced atomically, since
+    /// the content may change without updating the metadata within the resolution
+    /// of the modification time.
+    StrongMetadata,
+
+    /// A strong entity tag computed from the hash of the content.
+    ///
+    /// The computed values are cached in memory by the path of file, and recomputed
+    /// only when the metadata of the file changes.  See `ContentHashCache` for the
+    /// bound of the cache.
+    ContentHash(ContentHashCache),
+}
+
+impl Default for ETagStrategy {
+    fn default() -> Self {
+        ETagStrategy::Metadata
+    }
+}
+
+impl ETagStrategy {
+    /// Creates a `ContentHash` with an empty cache of the default capacity.
+    pub fn content_hash() -> Self {
+        ETagStrategy::ContentHash(ContentHashCache::default())
+    }
+
+    fn compute(&self, path: &Path, metadata: &Metadata) -> io::Result<ETag> {
+        match self {
+            ETagStrategy::Metadata => Ok(ETag::weak(metadata_key(metadata))),
+            ETagStrategy::StrongMetadata => Ok(ETag::strong(metadata_key(metadata))),
+            ETagStrategy::ContentHash(cache) => cache.get_or_compute(path, metadata),
+        }
+    }
+}
+
+/// The in-memory cache of the entity tags computed from the content of files.
+///
+/// The cache holds at most `capacity` entries, and the oldest entry is evicted
+/// when a new file is added to the full cache.  The clones of this value share
+/// the same cache.
+#[derive(Debug, Clone)]
+pub struct ContentHashCache {
+    inner: Arc<Mutex<ContentHashCacheInner>>,
 }
 
 #[derive(Debug)]
-struct ETag {
-    weak: bool,
-    tag: String,
+struct ContentHashCacheInner {
+    entries: HashMap<PathBuf, (String, ETag)>,
+    order: VecDeque<PathBuf>,
+    capacity: usize,
 }
 
-impl ETag {
-    fn from_metadata(metadata: &Metadata) -> Self {
-        let last_modified = FileTime::from_last_modification_time(&metadata);
+impl Default for ContentHashCache {
+    fn default() -> Self {
+        Self::with_capacity(1024)
+    }
+}
+
+impl ContentHashCache {
+    /// Creates an empty cache holding at most `capacity` entries.
+    ///
+    /// # Panics
+    /// This function panics if `capacity` is zero.
+    pub fn with_capacity(capacity: usize) -> Self {
+        assert!(capacity > 0, "the capacity must be positive");
         Self {
-            weak: true,
-            tag: format!(
-                "{:x}-{:x}.{:x}",
-                metadata.len(),
-                last_modified.seconds(),
-                last_modified.nanoseconds()
-            ),
+            inner: Arc::new(Mutex::new(ContentHashCacheInner {
+                entries: HashMap::new(),
+                order: VecDeque::new(),
+                capacity,
+            })),
+        }
+    }
+
+    /// Returns the number of the cached entries.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// Returns `true` if no entry is cached.
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    fn get_or_compute(&self, path: &Path, metadata: &Metadata) -> io::Result<ETag> {
+        let key = metadata_key(metadata);
+        if let Some((ref cached_key, ref etag)) = self.lock().entries.get(path) {
+            if *cached_key == key {
+                return Ok(etag.clone());
+            }
+        }
+
+        // The content is hashed outside of the lock, so that the other files can be
+        // served in the meantime.
+        let mut file = File::open(path)?;
+        let mut hash = FNV_OFFSET_BASIS;
+        let mut len = 0u64;
+        let mut buf = [0u8; 8192];
+        loop {
+            let n = file.read(&mut buf)?;
+            if n == 0 {
+                break;
+            }
+            hash = fnv1a(hash, &buf[..n]);
+            len += n as u64;
         }
+        let etag = content_hash_etag(len, hash);
+
+        self.lock().insert(path.to_path_buf(), (key, etag.clone()));
+        Ok(etag)
+    }
+
+    fn lock(&self) -> MutexGuard<'_, ContentHashCacheInner> {
+        self.inner
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
+}
 
-    fn parse_inner(weak: bool, s: &str) -> Result<Self, failure::Error> {
-        if s.len() < 2 {
-            failure::bail!("");
+impl ContentHashCacheInner {
+    fn insert(&mut self, path: PathBuf, entry: (String, ETag)) {
+        if self.entries.insert(path.clone(), entry).is_some() {
+            return;
         }
-        if !s.starts_with('"') || !s.ends_with('"') {
-            failure::bail!("");
+        self.order.push_back(path);
+        while self.entries.len() > self.capacity {
+            match self.order.pop_front() {
+                Some(oldest) => {
+                    self.entries.remove(&oldest);
+                }
+                None => break,
+            }
         }
+    }
+}
+
+fn content_hash_etag(len: u64, hash: u64) -> ETag {
+    ETag::strong(format!("{:x}-{:016x}", len, hash))
+}
 
-        let tag = &s[1..s.len() - 1];
-        if !tag.is_ascii() {
-            failure::bail!("");
+/// Determines the value of `Cache-Control` for the served file.
+///
+/// Returns `None` if the value is supplied by the modifiers wrapping the handler,
+/// so that the explicit one is not overridden.
+/// The rules of `CachePolicy` are evaluated with `policy_path`, or the path of request
+/// if it is not specified.
+fn cache_control(
+    config: &OpenConfig,
+    policy_path: Option<&str>,
+    path: &Path,
+    input: &Input<'_>,
+) -> Option<HeaderValue> {
+    let supplied = input
+        .response_headers
+        .as_ref()
+        .map_or(false, |headers| headers.contains_key(header::CACHE_CONTROL))
+        || crate::output::headers::has_default_header(input.locals, &header::CACHE_CONTROL);
+    if supplied {
+        return None;
+    }
+
+    let policy_path = policy_path.unwrap_or_else(|| input.request.uri().path());
+    if let Some(value) = config
+        .cache_policy
+        .as_ref()
+        .and_then(|policy| policy.evaluate(policy_path, path))
+    {
+        return Some(value.clone());
+    }
+
+    Some(match config.max_age {
+        Some(ref max_age) => {
+            HeaderValue::from_shared(format!("public, max-age={}", max_age.as_secs()).into())
+                .expect("should be a valid header value")
         }
+        None => HeaderValue::from_static("public"),
+    })
+}
 
-        Ok(Self {
-            weak,
-            tag: tag.to_owned(),
-        })
+fn validator_headers(
+    config: &OpenConfig,
+    cache_control: Option<HeaderValue>,
+    etag: &ETag,
+    last_modified: Option<SystemTime>,
+) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    if let Some(cache_control) = cache_control {
+        headers.insert(header::CACHE_CONTROL, cache_control);
+    }
+    headers.insert(
+        header::ETAG,
+        HeaderValue::from_shared(etag.to_string().into()).expect("should be a valid header value"),
+    );
+    if let Some(last_modified) = last_modified {
+        headers.insert(
+            header::LAST_MODIFIED,
+            HeaderValue::from_shared(conditional::http_date(last_modified).into())
+                .expect("should be a valid header value"),
+        );
+    }
+    if config.precompressed.is_some() {
+        headers.insert(header::VARY, HeaderValue::from_static("accept-encoding"));
+    }
+    headers
+}
+
+/// Creates the response of a file whose content is held in memory.
+///
+/// `headers` contains the validators of the file, and is also used in the
+/// response of `304 Not Modified`.
+fn in_memory_response(
+    request: &Request<()>,
+    content: &Bytes,
+    content_type: &Mime,
+    etag: &ETag,
+    last_modified: Option<SystemTime>,
+    headers: HeaderMap,
+) -> Result<Response<ResponseBody>, Error> {
+    if conditional::is_not_modified(request, Some(etag), last_modified) {
+        return Ok(conditional::not_modified(&headers));
     }
 
-    fn eq(&self, other: &Self) -> bool {
-        self.tag == other.tag && (self.weak || !other.weak)
+    let total_len = content.len() as u64;
+    let requested = resumable::requested_range(request, etag, last_modified, total_len)?;
+    let (status, content) = match requested {
+        Some(ref range) => (
+            StatusCode::PARTIAL_CONTENT,
+            content.slice(range.first() as usize, range.last() as usize + 1),
+        ),
+        None => (StatusCode::OK, content.clone()),
+    };
+
+    let mut response = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, content_type.as_ref())
+        .header(header::ACCEPT_RANGES, "bytes")
+        .header(header::CONTENT_LENGTH, content.len())
+        .body(ResponseBody::from(content))
+        .unwrap();
+    response.headers_mut().extend(headers);
+    if let Some(range) = requested {
+        response
+            .headers_mut()
+            .insert(header::CONTENT_RANGE, range.to_content_range(total_len));
     }
+
+    Ok(response)
 }
 
-impl FromStr for ETag {
-    type Err = failure::Error;
+// ==== precompressed files ====
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.get(0..3) {
-            Some("W/\"") if s[2..].starts_with('"') => Self::parse_inner(true, &s[2..]),
-            Some(t) if t.starts_with('"') => Self::parse_inner(false, s),
-            Some(..) => failure::bail!("invalid string to parse ETag"),
-            None => failure::bail!("empty string to parse ETag"),
+/// The content codings of the precompressed files.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Brotli, stored with the extension `.br`.
+    Brotli,
+    /// Gzip, stored with the extension `.gz`.
+    Gzip,
+}
+
+impl Encoding {
+    fn token(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+        }
+    }
+
+    fn extension(self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gz",
         }
     }
+
+    fn sidecar_path(self, path: &Path) -> PathBuf {
+        let mut sidecar = path.as_os_str().to_owned();
+        sidecar.push(".");
+        sidecar.push(self.extension());
+        sidecar.into()
+    }
 }
 
-impl fmt::Display for ETag {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.weak {
-            f.write_str("W/")?;
+/// The configuration for serving the precompressed variants of the files.
+///
+/// When `Accept-Encoding` in the request permits, the file with the extension of
+/// the encoding (e.g. `app.js.br` for `app.js`) is served instead of the original
+/// file, with `Content-Encoding` and the content type of the original file.
+/// The responses are sent with `Vary: Accept-Encoding`.
+///
+/// The original file must exist even if the precompressed variants are provided.
+#[derive(Debug, Clone)]
+pub struct Precompressed {
+    /// The encodings to be looked up, in the order of preference.
+    ///
+    /// The default value is `[Brotli, Gzip]`.
+    pub encodings: Vec<Encoding>,
+
+    /// Whether to ignore the precompressed files older than the original file.
+    ///
+    /// The default value is `true`.
+    pub check_modified: bool,
+}
+
+impl Default for Precompressed {
+    fn default() -> Self {
+        Self {
+            encodings: vec![Encoding::Brotli, Encoding::Gzip],
+            check_modified: true,
         }
-        write!(f, "\"{}\"", self.tag)
+    }
+}
+
+impl Precompressed {
+    /// Returns the encodings acceptable for the request, in the order of preference.
+    fn acceptable_encodings(&self, request: &Request<()>) -> Vec<Encoding> {
+        use crate::extractor::accept::{parse_quality, split_elements};
+
+        let mut qualities: Vec<(String, u16)> = vec![];
+        for value in request.headers().get_all(header::ACCEPT_ENCODING) {
+            let value = match value.to_str() {
+                Ok(value) => value,
+                Err(..) => continue,
+            };
+            for element in split_elements(value) {
+                let mut params = element.split(';');
+                let coding = params.next().unwrap_or("").trim().to_ascii_lowercase();
+                let quality = params
+                    .map(str::trim)
+                    .find(|param| param.starts_with("q=") || param.starts_with("Q="))
+                    .map_or(Some(1000), |param| parse_quality(param[2..].trim()));
+                if let Some(quality) = quality {
+                    qualities.push((coding, quality));
+                }
+            }
+        }
+
+        let quality_of = |name: &str| {
+            qualities
+                .iter()
+                .find(|(coding, _)| coding == name)
+                .map(|&(_, quality)| quality)
+        };
+        self.encodings
+            .iter()
+            .cloned()
+            .filter(|encoding| {
+                let quality = match encoding {
+                    Encoding::Gzip => quality_of("gzip").or_else(|| quality_of("x-gzip")),
+                    encoding => quality_of(encoding.token()),
+                };
+                quality.or_else(|| quality_of("*")).unwrap_or(0) > 0
+            })
+            .collect()
+    }
+
+    /// Finds the precompressed variant of the file to be served.
+    fn find(
+        &self,
+        path: &Path,
+        metadata: &Metadata,
+        encodings: &[Encoding],
+    ) -> io::Result<Option<(Encoding, Metadata)>> {
+        for &encoding in encodings {
+            let sidecar = match std::fs::metadata(encoding.sidecar_path(path)) {
+                Ok(ref sidecar) if !sidecar.is_file() => continue,
+                Ok(sidecar) => sidecar,
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            if self.check_modified && sidecar.modified()? < metadata.modified()? {
+                trace!("ignore the stale precompressed file: {:?}", encoding);
+                continue;
+            }
+            return Ok(Some((encoding, sidecar)));
+        }
+        Ok(None)
     }
 }
 
@@ -119,11 +801,191 @@ pub struct OpenConfig {
     /// If `None`, it will be guessed based on the block size on the filesystem.
     pub chunk_size: Option<usize>,
 
+    /// The strategy for performing the file system operations.
+    pub io: IoStrategy,
+
     /// The maximal amount of time to refresh the resource.
     ///
     /// If this field is set, the generated HTTP response will include a "Cache-Control" header
     /// that includes the parameter max-age.
     pub max_age: Option<Duration>,
+
+    /// The rules for determining the value of "Cache-Control" for each file.
+    ///
+    /// If the policy yields no value for the file, the one derived from `max_age`
+    /// is used.
+    pub cache_policy: Option<CachePolicy>,
+
+    /// The strategy for computing the entity tags.
+    pub etag: ETagStrategy,
+
+    /// The configuration for serving the precompressed variants of the files.
+    ///
+    /// If `None`, the precompressed files are not looked up.
+    pub precompressed: Option<Precompressed>,
+}
+
+/// The strategy for performing the file system operations in `NamedFile` and
+/// `Staticfiles`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IoStrategy {
+    /// The operations are performed in the blocking section of the thread pool, so
+    /// that the other tasks are moved to another worker in the meantime.
+    ///
+    /// This is the default strategy.  It requires the handlers to run on the
+    /// thread pool of the default runtime, and fails on the current-thread runtime.
+    Blocking,
+
+    /// The operations are moved to the background threads managed by this crate, and
+    /// the task is notified when each of them completes.
+    ///
+    /// This strategy does not use the blocking pool, so that the files can be served
+    /// on the current-thread runtime without blocking the thread driving the
+    /// connections.  The chunks of file are read one by one with the size of
+    /// `OpenConfig::chunk_size`, and an error while reading aborts the transfer.
+    Background,
+}
+
+impl Default for IoStrategy {
+    fn default() -> Self {
+        IoStrategy::Blocking
+    }
+}
+
+/// A set of rules that determines the value of `Cache-Control` for each file.
+///
+/// The rules are evaluated in the order of registration when responding, and the
+/// first matched one is used.  If no rule matches, the value of the policy itself
+/// is used.  A `Cache-Control` supplied by the modifiers wrapping the handler (e.g.
+/// `SetHeaders`) always takes precedence over the policy.
+///
+/// # Example
+///
+/// ```
+/// # use std::time::Duration;
+/// # use tsukuyomi::fs::CachePolicy;
+/// let policy = CachePolicy::public(Duration::from_secs(3600))
+///     .prefix("/assets/", CachePolicy::immutable())
+///     .extension("html", CachePolicy::no_cache());
+/// # drop(policy);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CachePolicy {
+    value: Option<HeaderValue>,
+    rules: Vec<(Matcher, CachePolicy)>,
+}
+
+type MatchFn = dyn Fn(&str, &Path) -> bool + Send + Sync + 'static;
+
+#[derive(Clone)]
+enum Matcher {
+    Extension(String),
+    Prefix(String),
+    Custom(Arc<MatchFn>),
+}
+
+impl fmt::Debug for Matcher {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Matcher::Extension(ext) => f.debug_tuple("Extension").field(ext).finish(),
+            Matcher::Prefix(prefix) => f.debug_tuple("Prefix").field(prefix).finish(),
+            Matcher::Custom(..) => f.debug_tuple("Custom").finish(),
+        }
+    }
+}
+
+impl Matcher {
+    fn matches(&self, request_path: &str, path: &Path) -> bool {
+        match self {
+            Matcher::Extension(ext) => path
+                .extension()
+                .map_or(false, |e| e.to_string_lossy().eq_ignore_ascii_case(ext)),
+            Matcher::Prefix(prefix) => request_path.starts_with(&**prefix),
+            Matcher::Custom(f) => f(request_path, path),
+        }
+    }
+}
+
+impl CachePolicy {
+    /// Creates a policy that uses the specified value of `Cache-Control`.
+    pub fn new(value: HeaderValue) -> Self {
+        Self {
+            value: Some(value),
+            rules: vec![],
+        }
+    }
+
+    /// Creates a policy for the files that never change under the same URL, such
+    /// as the assets with the content hash in their names.
+    ///
+    /// The value is `public, max-age=31536000, immutable`.
+    pub fn immutable() -> Self {
+        Self::new(HeaderValue::from_static(
+            "public, max-age=31536000, immutable",
+        ))
+    }
+
+    /// Creates a policy that requires the revalidation on each use (`no-cache`).
+    pub fn no_cache() -> Self {
+        Self::new(HeaderValue::from_static("no-cache"))
+    }
+
+    /// Creates a policy that forbids storing the response (`no-store`).
+    pub fn no_store() -> Self {
+        Self::new(HeaderValue::from_static("no-store"))
+    }
+
+    /// Creates a policy that allows caching the response for `max_age`.
+    ///
+    /// The value is `public, max-age=<seconds>`.
+    pub fn public(max_age: Duration) -> Self {
+        Self::new(
+            HeaderValue::from_shared(format!("public, max-age={}", max_age.as_secs()).into())
+                .expect("should be a valid header value"),
+        )
+    }
+
+    /// Adds a rule applied to the files with the specified extension.
+    ///
+    /// The extension is compared case-insensitively, without the leading dot.
+    pub fn extension(self, ext: impl Into<String>, policy: CachePolicy) -> Self {
+        let ext = ext.into();
+        let ext = ext.trim_start_matches('.').to_owned();
+        self.rule(Matcher::Extension(ext), policy)
+    }
+
+    /// Adds a rule applied to the files whose path starts with the specified prefix.
+    ///
+    /// The prefix is compared with the path of the file relative to the root
+    /// directory of `Staticfiles` or `Embedded` (e.g. `/assets/app.js`), regardless
+    /// of the scope where they are mounted.  For `NamedFile` used directly, it is
+    /// compared with the path of the request.
+    pub fn prefix(self, prefix: impl Into<String>, policy: CachePolicy) -> Self {
+        self.rule(Matcher::Prefix(prefix.into()), policy)
+    }
+
+    /// Adds a rule applied when the specified function returns `true`.
+    ///
+    /// The function receives the path compared by `prefix` and the path of the file.
+    pub fn matches<F>(self, f: F, policy: CachePolicy) -> Self
+    where
+        F: Fn(&str, &Path) -> bool + Send + Sync + 'static,
+    {
+        self.rule(Matcher::Custom(Arc::new(f)), policy)
+    }
+
+    fn rule(mut self, matcher: Matcher, policy: CachePolicy) -> Self {
+        self.rules.push((matcher, policy));
+        self
+    }
+
+    fn evaluate(&self, request_path: &str, path: &Path) -> Option<&HeaderValue> {
+        self.rules
+            .iter()
+            .find(|(matcher, _)| matcher.matches(request_path, path))
+            .and_then(|(_, policy)| policy.evaluate(request_path, path))
+            .or(self.value.as_ref())
+    }
 }
 
 // ==== NamedFile ====
@@ -133,6 +995,7 @@ pub struct OpenConfig {
 pub struct NamedFile<P> {
     path: P,
     config: Option<OpenConfig>,
+    policy_path: Option<String>,
 }
 
 impl<P> NamedFile<P>
@@ -141,7 +1004,11 @@ where
 {
     /// Open a specified file with the default configuration.
     pub fn open(path: P) -> Self {
-        Self { path, config: None }
+        Self {
+            path,
+            config: None,
+            policy_path: None,
+        }
     }
 
     /// Open a specified file with the provided configuration.
@@ -149,6 +1016,29 @@ where
         Self {
             path,
             config: Some(config),
+            policy_path: None,
+        }
+    }
+
+    /// Open a specified file, performing the file system operations on the background
+    /// threads (`IoStrategy::Background`).
+    ///
+    /// Unlike `open`, the file can be served on the current-thread runtime.
+    pub fn open_async(path: P) -> Self {
+        Self::open_with_config(
+            path,
+            OpenConfig {
+                io: IoStrategy::Background,
+                ..Default::default()
+            },
+        )
+    }
+
+    /// Sets the path compared by the rules of `CachePolicy`, instead of the path of request.
+    fn policy_path(self, policy_path: Option<String>) -> Self {
+        Self {
+            policy_path,
+            ..self
         }
     }
 }
@@ -166,6 +1056,10 @@ where
         OpenNamedFile {
             path: self.path,
             config: self.config,
+            policy_path: self.policy_path,
+            validated: None,
+            pending_validate: PendingIo::default(),
+            pending_open: PendingIo::default(),
         }
     }
 }
@@ -175,6 +1069,18 @@ where
 pub struct OpenNamedFile<P> {
     path: P,
     config: Option<OpenConfig>,
+    policy_path: Option<String>,
+    validated: Option<Validated>,
+    pending_validate: PendingIo<Option<Validated>>,
+    pending_open: PendingIo<File>,
+}
+
+/// The metadata of the file to be served, retrieved before opening it.
+#[derive(Debug)]
+struct Validated {
+    meta: Metadata,
+    etag: ETag,
+    encoding: Option<Encoding>,
 }
 
 impl<P> TryFuture for OpenNamedFile<P>
@@ -185,25 +1091,90 @@ where
     type Error = crate::Error;
 
     fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-        let (file, meta) = futures01::try_ready!(blocking_io(|| {
-            let file = File::open(&self.path)?;
-            let meta = file.metadata()?;
-            Ok((file, meta))
-        }));
+        let path = self.path.as_ref();
+        let config = self.config.get_or_insert_with(Default::default);
 
-        let config = self.config.take().unwrap_or_default();
+        if self.validated.is_none() {
+            let encodings = match config.precompressed {
+                Some(ref precompressed) => precompressed.acceptable_encodings(input.request),
+                None => vec![],
+            };
+            let validated =
+                futures01::try_ready!(config.io.poll_fs(&mut self.pending_validate, || {
+                    let path = path.to_path_buf();
+                    let precompressed = config.precompressed.clone();
+                    let etag = config.etag.clone();
+                    move || {
+                        let meta = std::fs::metadata(&path)?;
+                        if meta.is_dir() {
+                            return Ok(None);
+                        }
+                        let (encoding, meta) = match precompressed {
+                            Some(ref precompressed) => {
+                                match precompressed.find(&path, &meta, &encodings)? {
+                                    Some((encoding, sidecar)) => (Some(encoding), sidecar),
+                                    None => (None, meta),
+                                }
+                            }
+                            None => (None, meta),
+                        };
+                        let etag = match encoding {
+                            Some(encoding) => etag.compute(&encoding.sidecar_path(&path), &meta)?,
+                            None => etag.compute(&path, &meta)?,
+                        };
+                        Ok(Some(Validated {
+                            meta,
+                            etag,
+                            encoding,
+                        }))
+                    }
+                }))
+                .ok_or_else(|| crate::error::not_found("the path points to a directory"))?;
+            self.validated = Some(validated);
+        }
+        let validated = self.validated.as_ref().expect("should be validated");
+        let last_modified = validated.meta.modified().ok();
+
+        // The conditional requests are answered without opening the file.
+        let cache_control =
+            cache_control(config, self.policy_path.as_ref().map(|p| &**p), path, input);
+        if conditional::is_not_modified(input.request, Some(&validated.etag), last_modified) {
+            let headers = validator_headers(config, cache_control, &validated.etag, last_modified);
+            return Ok(Async::Ready(conditional::not_modified(&headers)));
+        }
 
-        let last_modified = FileTime::from_last_modification_time(&meta);
-        let etag = ETag::from_metadata(&meta);
+        // The response to HEAD only needs the metadata, so the file is not opened.
+        let file = if input.request.method() == Method::HEAD {
+            None
+        } else {
+            Some(futures01::try_ready!(config.io.poll_fs(
+                &mut self.pending_open,
+                || {
+                    let path = match validated.encoding {
+                        Some(encoding) => encoding.sidecar_path(path),
+                        None => path.to_path_buf(),
+                    };
+                    move || File::open(path)
+                }
+            )))
+        };
 
-        let content_type = mime_guess::guess_mime_type(&self.path);
+        let Validated {
+            meta,
+            etag,
+            encoding,
+        } = self.validated.take().expect("should be validated");
+        let config = self.config.take().unwrap_or_default();
+        let content_type = mime_guess::from_path(path).first_or_octet_stream();
 
         let response = NamedFileResponse {
             file,
             meta,
             content_type,
+            encoding,
             last_modified,
             etag,
+            cache_control,
             config,
         }
         .into_response(input.request)?;
@@ -214,74 +1185,16 @@ where
 
 #[derive(Debug)]
 struct NamedFileResponse {
-    file: File,
+    file: Option<File>,
     meta: Metadata,
     content_type: Mime,
+    encoding: Option<Encoding>,
     etag: ETag,
-    last_modified: FileTime,
+    last_modified: Option<SystemTime>,
+    cache_control: Option<HeaderValue>,
     config: OpenConfig,
 }
 
-impl NamedFileResponse {
-    #[allow(clippy::cast_sign_loss)]
-    fn is_modified(&self, headers: &HeaderMap) -> Result<bool, Error> {
-        if let Some(h) = headers.get(header::IF_NONE_MATCH) {
-            trace!("NamedFile::is_modified(): validate If-None-Match");
-
-            let etag: ETag = h
-                .to_str()
-                .map_err(crate::error::bad_request)?
-                .parse()
-                .map_err(crate::error::bad_request)?;
-            let modified = !etag.eq(&self.etag);
-
-            trace!(
-                "--> self.etag={:?}, etag={:?}, modified={}",
-                self.etag,
-                etag,
-                modified
-            );
-            return Ok(modified);
-        }
-
-        if let Some(h) = headers.get(header::IF_MODIFIED_SINCE) {
-            trace!("NamedFile::is_modified(): validate If-Modified-Since");
-
-            let if_modified_since = {
-                let timespec = parse_http_date(h.to_str().map_err(crate::error::bad_request)?)
-                    .map_err(crate::error::bad_request)?;
-                FileTime::from_unix_time(timespec.sec, timespec.nsec as u32)
-            };
-            let modified = self.last_modified > if_modified_since;
-
-            trace!(
-                "--> if_modified_sicne={:?}, modified={}",
-                if_modified_since,
-                modified
-            );
-            return Ok(modified);
-        }
-
-        Ok(true)
-    }
-
-    fn cache_control(&self) -> Cow<'static, str> {
-        match self.config.max_age {
-            Some(ref max_age) => format!("public, max-age={}", max_age.as_secs()).into(),
-            None => "public".into(),
-        }
-    }
-
-    #[allow(clippy::cast_possible_wrap)]
-    fn last_modified(&self) -> Result<String, time::ParseError> {
-        let tm = time::at(Timespec::new(
-            self.last_modified.seconds(),
-            self.last_modified.nanoseconds() as i32,
-        ));
-        time::strftime("%c", &tm)
-    }
-}
-
 impl IntoResponse for NamedFileResponse {
     type Body = ResponseBody;
     type Error = Error;
@@ -289,28 +1202,52 @@ impl IntoResponse for NamedFileResponse {
     fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
         trace!("NamedFile::respond_to");
 
-        if !self.is_modified(request.headers())? {
-            return Ok(Response::builder()
-                .status(StatusCode::NOT_MODIFIED)
-                .body(ResponseBody::empty())
-                .unwrap());
+        let total_len = self.meta.len();
+        let requested =
+            resumable::requested_range(request, &self.etag, self.last_modified, total_len)?;
+        let (status, range) = match requested {
+            Some(ref range) => (StatusCode::PARTIAL_CONTENT, range.first()..range.last() + 1),
+            None => (StatusCode::OK, 0..total_len),
+        };
+
+        let headers = validator_headers(
+            &self.config,
+            self.cache_control,
+            &self.etag,
+            self.last_modified,
+        );
+        let body = match self.file {
+            Some(file) => ResponseBody::wrap_stream(ReadStream::new(
+                file,
+                self.meta,
+                self.config.chunk_size,
+                self.config.io,
+                range.clone(),
+            )),
+            None => ResponseBody::empty(),
+        };
+
+        let mut response = Response::builder()
+            .status(status)
+            .header(header::CONTENT_TYPE, self.content_type.as_ref())
+            .header(header::ACCEPT_RANGES, "bytes")
+            .header(header::CONTENT_LENGTH, range.end - range.start)
+            .body(body)
+            .unwrap();
+        response.headers_mut().extend(headers);
+        if let Some(encoding) = self.encoding {
+            response.headers_mut().insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.token()),
+            );
+        }
+        if let Some(range) = requested {
+            response
+                .headers_mut()
+                .insert(header::CONTENT_RANGE, range.to_content_range(total_len));
         }
 
-        // FIXME: optimize
-
-        let cache_control = self.cache_control();
-        let last_modified = self
-            .last_modified()
-            .map_err(crate::error::internal_server_error)?;
-        let stream = ReadStream::new(self.file, self.meta, self.config.chunk_size);
-
-        Ok(Response::builder()
-            .header(header::CONTENT_TYPE, self.content_type.as_ref())
-            .header(header::CACHE_CONTROL, &*cache_control)
-            .header(header::LAST_MODIFIED, &*last_modified)
-            .header(header::ETAG, &*self.etag.to_string())
-            .body(ResponseBody::wrap_stream(stream))
-            .unwrap())
+        Ok(response)
     }
 }
 
@@ -321,16 +1258,41 @@ struct ReadStream(State);
 
 #[derive(Debug)]
 enum State {
-    Reading { file: File, buf_size: usize },
+    Reading {
+        /// The file is moved out while it is being read by the background threads.
+        file: Option<File>,
+        buf_size: usize,
+        io: IoStrategy,
+        seek_to: Option<u64>,
+        remaining: u64,
+        pending: PendingIo<(File, BytesMut)>,
+    },
     Eof,
     Gone,
 }
 
 impl ReadStream {
-    fn new(file: File, meta: Metadata, buf_size: Option<usize>) -> Self {
+    fn new(
+        file: File,
+        meta: Metadata,
+        buf_size: Option<usize>,
+        io: IoStrategy,
+        range: ops::Range<u64>,
+    ) -> Self {
         let buf_size = finalize_block_size(buf_size, &meta);
         drop(meta);
-        ReadStream(State::Reading { file, buf_size })
+        ReadStream(State::Reading {
+            file: Some(file),
+            buf_size,
+            io,
+            seek_to: if range.start > 0 {
+                Some(range.start)
+            } else {
+                None
+            },
+            remaining: range.end - range.start,
+            pending: PendingIo::default(),
+        })
     }
 }
 
@@ -344,26 +1306,57 @@ impl Stream for ReadStream {
                 State::Reading {
                     ref mut file,
                     buf_size,
-                    ..
-                } => {
+                    io,
+                    ref mut seek_to,
+                    ref mut remaining,
+                    ref mut pending,
+                } if *remaining > 0 => {
                     trace!("ReadStream::poll(): polling on the mode State::Reading");
 
-                    let buf = futures01::try_ready!(blocking_io(|| {
-                        let mut buf = BytesMut::with_capacity(buf_size);
-                        if !buf.has_remaining_mut() {
-                            buf.reserve(buf_size);
+                    // the seek is also performed on the blocking section, along with the reads.
+                    #[allow(clippy::cast_possible_truncation)]
+                    let len = cmp::min(buf_size as u64, *remaining) as usize;
+                    let polled = io.poll_io(pending, || {
+                        let mut file = file.take().expect("the file is being read");
+                        let seek_to = seek_to.take();
+                        move || {
+                            if let Some(pos) = seek_to {
+                                file.seek(SeekFrom::Start(pos))?;
+                            }
+                            let mut buf = BytesMut::with_capacity(len);
+                            unsafe {
+                                let n = file.read(&mut buf.bytes_mut()[..len])?;
+                                buf.advance_mut(n);
+                            }
+                            Ok((file, buf))
                         }
-                        unsafe {
-                            let n = file.read(buf.bytes_mut())?;
-                            buf.advance_mut(n);
+                    });
+                    let buf = match polled {
+                        Ok(Async::Ready((read, buf))) => {
+                            *file = Some(read);
+                            buf
                         }
-                        Ok(buf)
-                    }));
-
-                    if !buf.is_empty() {
-                        return Ok(Async::Ready(Some(buf.freeze())));
+                        Ok(Async::NotReady) => return Ok(Async::NotReady),
+                        Err(err) => {
+                            self.0 = State::Eof;
+                            return Err(err);
+                        }
+                    };
+
+                    // The file has been truncated after the response header was sent.
+                    // The error aborts the transfer, rather than completing the body
+                    // shorter than the advertised `Content-Length`.
+                    if buf.is_empty() {
+                        self.0 = State::Eof;
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "the file was truncated while reading",
+                        ));
                     }
+                    *remaining -= buf.len() as u64;
+                    return Ok(Async::Ready(Some(buf.freeze())));
                 }
+                State::Reading { .. } => {}
                 State::Eof => {
                     trace!("ReadStream::poll(): polling on the mode State::Reading");
                     return Ok(Async::Ready(None));
@@ -382,7 +1375,7 @@ impl Stream for ReadStream {
 #[allow(dead_code)]
 const DEFAULT_BUF_SIZE: u64 = 8192;
 
-fn blocking_io<T>(f: impl FnOnce() -> io::Result<T>) -> Poll<T, io::Error> {
+pub(crate) fn blocking_io<T>(f: impl FnOnce() -> io::Result<T>) -> Poll<T, io::Error> {
     match poll_blocking(f) {
         Ok(Async::Ready(ready)) => ready.map(Async::Ready),
         Ok(Async::NotReady) => Ok(Async::NotReady),
@@ -390,13 +1383,93 @@ fn blocking_io<T>(f: impl FnOnce() -> io::Result<T>) -> Poll<T, io::Error> {
     }
 }
 
+impl IoStrategy {
+    /// Polls an operation on the file system with this strategy.
+    ///
+    /// The function `f` is called only once the operation starts, and returns the
+    /// operation owning all of its state, so that it can be moved to the background
+    /// threads.  The operation in progress is held by `pending` until it completes.
+    pub(crate) fn poll_io<T, F>(
+        self,
+        pending: &mut PendingIo<T>,
+        f: impl FnOnce() -> F,
+    ) -> Poll<T, io::Error>
+    where
+        F: FnOnce() -> io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        match self {
+            IoStrategy::Blocking => blocking_io(|| f()()),
+            IoStrategy::Background => pending.poll_background(f),
+        }
+    }
+
+    /// Same as `poll_io`, but the error is converted into `FileError`, to hide
+    /// its details from the client.
+    fn poll_fs<T, F>(self, pending: &mut PendingIo<T>, f: impl FnOnce() -> F) -> Poll<T, FileError>
+    where
+        F: FnOnce() -> io::Result<T> + Send + 'static,
+        T: Send + 'static,
+    {
+        self.poll_io(pending, f).map_err(FileError)
+    }
+}
+
+lazy_static! {
+    static ref BACKGROUND_POOL: ThreadPool = tokio_threadpool::Builder::new()
+        .name_prefix("tsukuyomi-fs-")
+        .build();
+}
+
+/// An operation on the file system in progress on the background threads.
+#[derive(Debug)]
+pub(crate) struct PendingIo<T>(Option<oneshot::Receiver<io::Result<T>>>);
+
+impl<T> Default for PendingIo<T> {
+    fn default() -> Self {
+        PendingIo(None)
+    }
+}
+
+impl<T> PendingIo<T>
+where
+    T: Send + 'static,
+{
+    pub(crate) fn poll_background<F>(&mut self, f: impl FnOnce() -> F) -> Poll<T, io::Error>
+    where
+        F: FnOnce() -> io::Result<T> + Send + 'static,
+    {
+        if self.0.is_none() {
+            let op = f();
+            let (tx, rx) = oneshot::channel();
+            BACKGROUND_POOL.spawn(futures01::future::lazy(move || {
+                let _ = tx.send(op());
+                Ok(())
+            }));
+            self.0 = Some(rx);
+        }
+
+        let result = match self.0.as_mut().expect("should be started").poll() {
+            Ok(Async::Ready(result)) => result,
+            Ok(Async::NotReady) => return Ok(Async::NotReady),
+            Err(oneshot::Canceled) => Err(io::Error::new(
+                io::ErrorKind::Other,
+                "the background operation has been canceled",
+            )),
+        };
+        self.0 = None;
+        result.map(Async::Ready)
+    }
+}
+
 // FIXME: replace usize to u64
 #[allow(clippy::cast_possible_truncation)]
 fn finalize_block_size(buf_size: Option<usize>, meta: &Metadata) -> usize {
-    match buf_size {
+    let buf_size = match buf_size {
         Some(n) => cmp::min(meta.len(), n as u64) as usize,
         None => cmp::min(meta.len(), block_size(&meta)) as usize,
-    }
+    };
+    cmp::max(buf_size, 1)
 }
 
 #[cfg(unix)]
@@ -443,64 +1516,502 @@ pub struct ServeFile {
 struct ServeFileInner {
     path: ArcPath,
     config: Option<OpenConfig>,
-    extract_path: bool,
+    request_path: RequestPath,
+    options: Arc<ResolveOptions>,
+}
+
+/// The part of the request path resolved relative to the path of `ServeFile`.
+#[derive(Debug)]
+enum RequestPath {
+    /// The path of `ServeFile` is served as it is.
+    Fixed,
+    /// The value of catch-all parameter.
+    CatchAll,
+    /// The remaining part after the prefix of the scope.
+    StripPrefix(String),
+}
+
+impl RequestPath {
+    fn extract<'a>(&self, input: &'a Input<'_>) -> Result<&'a str, Error> {
+        match self {
+            RequestPath::Fixed => Ok(""),
+            RequestPath::CatchAll => input
+                .params
+                .as_ref()
+                .and_then(|params| params.catch_all())
+                .ok_or_else(|| crate::error::internal_server_error("missing params")),
+            RequestPath::StripPrefix(prefix) => {
+                let path = input.request.uri().path();
+                if !path.starts_with(&**prefix) {
+                    return Err(crate::error::not_found("the path is outside of the scope"));
+                }
+                let rest = &path[prefix.len()..];
+                if prefix.ends_with('/') || rest.is_empty() || rest.starts_with('/') {
+                    Ok(rest)
+                } else {
+                    Err(crate::error::not_found("the path is outside of the scope"))
+                }
+            }
+        }
+    }
+}
+
+/// The target to be served, determined from the file type of the resolved path.
+#[derive(Debug)]
+enum Resolved {
+    File(ArcPath),
+    Fallback(ArcPath),
+    Cached(CachedFile),
+    Listing(html::Document),
+    NotFound,
+}
+
+/// The options for resolving the request paths into the targets.
+#[derive(Debug)]
+struct ResolveOptions {
+    /// The root directories, in the order of precedence.
+    roots: Vec<SafePath>,
+    index_file: Option<String>,
+    listing: bool,
+    fallback_file: Option<ArcPath>,
+    cache: Option<FileCache>,
+    not_found: Option<NotFound>,
+    /// The names of top-level entries served in the eager mode.
+    entries: Option<HashSet<OsString>>,
+}
+
+/// The function that creates the error for the request paths not resolved to any file.
+#[derive(Clone)]
+struct NotFound(Arc<dyn Fn(&str) -> Error + Send + Sync + 'static>);
+
+impl fmt::Debug for NotFound {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("NotFound").finish()
+    }
+}
+
+impl ResolveOptions {
+    fn serves_directory(&self) -> bool {
+        self.index_file.is_some() || self.listing
+    }
+
+    /// Returns the path of the file relative to the root containing it, with the
+    /// leading slash, which is compared by the rules of `CachePolicy`.
+    fn policy_path(&self, path: &Path) -> Option<String> {
+        let relative = self
+            .roots
+            .iter()
+            .find_map(|safe_path| path.strip_prefix(safe_path.root()).ok())?;
+        Some(relative_url_path(relative))
+    }
+
+    /// Replaces the error for the request path not resolved to any file with
+    /// the configured one.
+    fn not_found(&self, request: &Request<()>, err: Error) -> Error {
+        match self.not_found {
+            Some(NotFound(ref f)) => f(request.uri().path()),
+            None => err,
+        }
+    }
+
+    /// Resolves the path, lexically resolved within one of the roots, by looking up
+    /// the root directories in order.
+    ///
+    /// The first found file is served, and each root is checked by its own
+    /// `SafePath`.  The directories found in multiple roots are merged, so that
+    /// the index file is looked up in all of them and the listing shows the
+    /// union of their entries.
+    fn resolve(&self, path: &Path, request_path: &str) -> io::Result<Resolved> {
+        let relative = match self
+            .roots
+            .iter()
+            .find_map(|safe_path| path.strip_prefix(safe_path.root()).ok())
+        {
+            Some(relative) => relative,
+            None => return Ok(Resolved::NotFound),
+        };
+
+        // the entries added after startup are not served in the eager mode.
+        if let Some(ref entries) = self.entries {
+            match relative.components().next() {
+                Some(Component::Normal(name)) if !entries.contains(name) => {
+                    return Ok(match self.fallback_file {
+                        Some(ref fallback) => Resolved::Fallback(fallback.clone()),
+                        None => Resolved::NotFound,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        let mut rejected = false;
+        let mut found_dir = false;
+        let mut listed_dirs = vec![];
+        for safe_path in &self.roots {
+            let path = match safe_path.verify(&safe_path.root().join(relative)) {
+                Ok(Some(path)) => path,
+                Ok(None) => {
+                    rejected = true;
+                    continue;
+                }
+                Err(ref err) if err.kind() == io::ErrorKind::NotFound => continue,
+                Err(err) => return Err(err),
+            };
+            if !std::fs::metadata(&path)?.is_dir() {
+                // the files are shadowed by the directories in the preceding roots.
+                if found_dir {
+                    continue;
+                }
+                return Ok(Resolved::File(path.into()));
+            }
+            found_dir = true;
+
+            if let Some(ref index_file) = self.index_file {
+                match safe_path.verify(&path.join(index_file)) {
+                    Ok(Some(ref index)) if index.is_file() => {
+                        return Ok(Resolved::File(index.clone().into()));
+                    }
+                    Ok(..) => {}
+                    Err(ref err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err),
+                }
+            }
+
+            // the listing is never rendered for the directories outside of the root,
+            // even if they are reachable through a symbolic link.
+            if self.listing && path.canonicalize()?.starts_with(safe_path.root()) {
+                listed_dirs.push(path);
+            }
+        }
+
+        if !listed_dirs.is_empty() {
+            return render_listing(&listed_dirs, request_path).map(Resolved::Listing);
+        }
+
+        Ok(match self.fallback_file {
+            Some(ref fallback) if !rejected || found_dir => Resolved::Fallback(fallback.clone()),
+            _ => Resolved::NotFound,
+        })
+    }
+
+    /// Resolves the path in the same way as `resolve`, and loads the resolved file
+    /// into the cache.  The fallback file is never cached, since the request path
+    /// may be created later.
+    fn resolve_cached(
+        &self,
+        path: &Path,
+        request_path: &str,
+        cache: &FileCache,
+        lookup: Lookup,
+        config: &OpenConfig,
+    ) -> io::Result<Resolved> {
+        if let Lookup::Check(check) = lookup {
+            if let Some(cached) = cache.check(path, check)? {
+                return Ok(Resolved::Cached(cached));
+            }
+        }
+        match self.resolve(path, request_path)? {
+            Resolved::File(file) => Ok(match cache.load(path, &file, config)? {
+                Some(cached) => Resolved::Cached(cached),
+                None => Resolved::File(file),
+            }),
+            resolved => Ok(resolved),
+        }
+    }
+}
+
+/// Renders a minimal HTML listing of the entries in the directories.
+///
+/// The entries with the same name are taken from the preceding directory.
+fn render_listing(dirs: &[PathBuf], request_path: &str) -> io::Result<html::Document> {
+    let mut entries = BTreeMap::new();
+    for dir in dirs {
+        for entry in std::fs::read_dir(dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let btree_map::Entry::Vacant(vacant) = entries.entry(name) {
+                vacant.insert(entry.metadata()?);
+            }
+        }
+    }
+
+    let base = request_path.trim_end_matches('/');
+    let mut rows = html::Markup::new();
+    for (mut name, metadata) in entries {
+        let mut href = format!(
+            "{}/{}",
+            base,
+            utf8_percent_encode(&name, PATH_SEGMENT_ENCODE_SET)
+        );
+        if metadata.is_dir() {
+            name.push('/');
+            href.push('/');
+        }
+        let size = if metadata.is_dir() {
+            "-".to_owned()
+        } else {
+            metadata.len().to_string()
+        };
+        let modified = metadata
+            .modified()
+            .map(conditional::http_date)
+            .unwrap_or_default();
+        rows = rows
+            .raw("<tr><td><a href=\"")
+            .text(&href)
+            .raw("\">")
+            .text(&name)
+            .raw("</a></td>")
+            .element("td", html::text(&size))
+            .element("td", html::text(&modified))
+            .raw("</tr>");
+    }
+
+    let title = format!("Index of {}", request_path);
+    Ok(html::document().title(title.clone()).body(
+        html::Markup::new()
+            .element("h1", html::text(&title))
+            .raw("<table><tr><th>Name</th><th>Size</th><th>Last modified</th></tr>")
+            .append(rows)
+            .raw("</table>"),
+    ))
+}
+
+/// Returns the methods allowed by the handler of `Staticfiles`.
+///
+/// The responses to `HEAD` have the same header fields as the ones to `GET`, and
+/// `OPTIONS` is answered with the allowed methods without accessing the file system.
+fn allowed_methods() -> &'static AllowedMethods {
+    lazy_static! {
+        static ref VALUE: AllowedMethods = vec![Method::GET, Method::HEAD, Method::OPTIONS]
+            .into_iter()
+            .collect();
+    }
+    &VALUE
+}
+
+/// Checks the method of the request against `allowed_methods`.
+///
+/// Returns the response to `OPTIONS`, or an error for the methods not allowed.
+fn check_method(request: &Request<()>) -> Result<Option<Response<()>>, Error> {
+    match *request.method() {
+        Method::GET | Method::HEAD => Ok(None),
+        Method::OPTIONS => Ok(Some(
+            Response::builder()
+                .status(StatusCode::NO_CONTENT)
+                .header(header::ALLOW, allowed_methods().to_header_value())
+                .body(())
+                .expect("should be a valid response"),
+        )),
+        _ => {
+            // The `Allow` header is inserted here since the default handler
+            // of the scope is not associated with any endpoint.
+            Err(crate::error::error_response(
+                Response::builder()
+                    .status(StatusCode::METHOD_NOT_ALLOWED)
+                    .header(header::ALLOW, allowed_methods().to_header_value())
+                    .body("")
+                    .expect("should be a valid response"),
+            ))
+        }
+    }
 }
 
 mod impl_handler_for_serve_file {
     use {
-        super::{ArcPath, NamedFile, ServeFile},
+        super::{
+            allowed_methods, check_method, ArcPath, CachedFile, Lookup, NamedFile, PendingIo,
+            Resolved, ServeFile, ServeFileInner,
+        },
         crate::{
             error::Error,
             future::TryFuture,
             handler::{AllowedMethods, Handler},
             input::Input,
+            output::html::Document,
+            util::{Either, Either3},
         },
         futures01::{Async, Poll},
+        http::{Method, Response},
+        std::sync::Arc,
     };
 
+    // `NamedFile` is not an `IntoResponse` but an asynchronous `Responder`, so it is
+    // put in `Either` rather than `Either4`.
+    type Output = Either<NamedFile<ArcPath>, Either3<Document, CachedFile, Response<()>>>;
+
     impl Handler for ServeFile {
-        type Output = NamedFile<ArcPath>;
+        type Output = Output;
         type Error = Error;
-        type Handle = Self;
+        type Handle = ResolveFile;
 
         fn allowed_methods(&self) -> Option<&AllowedMethods> {
-            Some(&AllowedMethods::get())
+            Some(allowed_methods())
         }
 
         fn handle(&self) -> Self::Handle {
-            self.clone()
+            ResolveFile {
+                inner: self.inner.clone(),
+                pending: PendingIo::default(),
+            }
         }
     }
 
-    impl TryFuture for ServeFile {
-        type Ok = NamedFile<ArcPath>;
+    #[doc(hidden)]
+    #[derive(Debug)]
+    pub struct ResolveFile {
+        inner: Arc<ServeFileInner>,
+        pending: PendingIo<Resolved>,
+    }
+
+    impl TryFuture for ResolveFile {
+        type Ok = Output;
         type Error = Error;
 
         fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
-            let path = if self.inner.extract_path {
-                let path = input
-                    .params
-                    .as_ref()
-                    .and_then(|params| params.catch_all())
-                    .ok_or_else(|| crate::error::internal_server_error("missing params"))?;
-                self.inner.path.join(path).into()
-            } else {
-                self.inner.path.clone()
+            // the other methods are answered only after the path resolves to an existing
+            // file, so that the requests to the missing paths are reported as not found.
+            let is_get = match *input.request.method() {
+                Method::GET | Method::HEAD => true,
+                _ => false,
+            };
+
+            let options = &self.inner.options;
+            let path = self
+                .inner
+                .request_path
+                .extract(input)
+                .and_then(|path| super::resolve_path(&self.inner.path, path))
+                .map_err(|err| options.not_found(input.request, err))?;
+
+            let request_path = input.request.uri().path();
+            let default_config;
+            let config = match self.inner.config {
+                Some(ref config) => config,
+                None => {
+                    default_config = Default::default();
+                    &default_config
+                }
+            };
+
+            // the cache is bypassed when the precompressed variant may be served.
+            let cache = options.cache.as_ref().filter(|_| {
+                is_get
+                    && config.precompressed.as_ref().map_or(true, |precompressed| {
+                        precompressed.acceptable_encodings(input.request).is_empty()
+                    })
+            });
+
+            let resolved = match cache {
+                Some(cache) => match cache.lookup(&path) {
+                    Lookup::Hit(cached) => {
+                        let cache_control = super::cache_control(
+                            config,
+                            options.policy_path(cached.path()).as_ref().map(|p| &**p),
+                            cached.path(),
+                            input,
+                        );
+                        return Ok(Async::Ready(Either::Right(Either3::B(
+                            cached.cache_control(cache_control),
+                        ))));
+                    }
+                    lookup => futures01::try_ready!(config.io.poll_fs(&mut self.pending, || {
+                        let options = options.clone();
+                        let cache = cache.clone();
+                        let config = config.clone();
+                        let request_path = request_path.to_owned();
+                        move || {
+                            options.resolve_cached(&path, &request_path, &cache, lookup, &config)
+                        }
+                    })),
+                },
+                None => futures01::try_ready!(config.io.poll_fs(&mut self.pending, || {
+                    let options = options.clone();
+                    let request_path = request_path.to_owned();
+                    move || options.resolve(&path, &request_path)
+                })),
+            };
+            let resolved = match resolved {
+                Resolved::File(..) | Resolved::Listing(..) => {
+                    if let Some(response) = check_method(input.request)? {
+                        return Ok(Async::Ready(Either::Right(Either3::C(response))));
+                    }
+                    resolved
+                }
+                _ if !is_get => {
+                    return Err(options.not_found(
+                        input.request,
+                        crate::error::not_found("no such file or directory"),
+                    ));
+                }
+                resolved => resolved,
+            };
+            let path = match resolved {
+                Resolved::File(path) | Resolved::Fallback(path) => path,
+                Resolved::Cached(cached) => {
+                    let cache_control = super::cache_control(
+                        config,
+                        options.policy_path(cached.path()).as_ref().map(|p| &**p),
+                        cached.path(),
+                        input,
+                    );
+                    return Ok(Async::Ready(Either::Right(Either3::B(
+                        cached.cache_control(cache_control),
+                    ))));
+                }
+                Resolved::Listing(listing) => {
+                    return Ok(Async::Ready(Either::Right(Either3::A(listing))))
+                }
+                Resolved::NotFound => {
+                    return Err(options.not_found(
+                        input.request,
+                        crate::error::not_found("no such file or directory"),
+                    ));
+                }
             };
 
-            Ok(Async::Ready(match self.inner.config {
-                Some(ref config) => NamedFile::open_with_config(path, config.clone()),
-                None => NamedFile::open(path),
-            }))
+            let policy_path = options.policy_path(&path);
+            Ok(Async::Ready(Either::Left(
+                match self.inner.config {
+                    Some(ref config) => NamedFile::open_with_config(path, config.clone()),
+                    None => NamedFile::open(path),
+                }
+                .policy_path(policy_path),
+            )))
         }
     }
 }
 
 /// A configuration type for adding entries in the directory to the route.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, fs::Staticfiles, App};
+/// # let root = std::env::temp_dir().join("tsukuyomi-doctest-staticfiles");
+/// # std::fs::create_dir_all(&root).unwrap();
+/// let app = App::create(
+///     mount("/public").with(
+///         Staticfiles::new(root)
+///             .index_file("index.html")
+///             .listing(true),
+///     ),
+/// );
+/// # app.unwrap();
+/// ```
 #[derive(Debug)]
 pub struct Staticfiles<P> {
     root_dir: P,
+    fallback_roots: Vec<PathBuf>,
     config: Option<OpenConfig>,
+    index_file: Option<String>,
+    listing: bool,
+    fallback_file: Option<String>,
+    symlinks: SymlinkPolicy,
+    cache: Option<FileCache>,
+    not_found: Option<NotFound>,
+    not_found_file: Option<String>,
+    eager: bool,
 }
 
 impl<P> Staticfiles<P>
@@ -511,10 +2022,32 @@ where
     pub fn new(root_dir: P) -> Self {
         Self {
             root_dir,
+            fallback_roots: vec![],
             config: None,
+            index_file: None,
+            listing: false,
+            fallback_file: None,
+            symlinks: SymlinkPolicy::default(),
+            cache: None,
+            not_found: None,
+            not_found_file: None,
+            eager: false,
         }
     }
 
+    /// Adds a directory looked up when the file is missing in the preceding ones.
+    ///
+    /// The root directories are looked up in order at request time, and the
+    /// request is rejected with `404 Not Found` only if all of them miss.  This is
+    /// intended for overlaying a theme directory over the base assets, for example.
+    /// Each root is checked by its own `SafePath` with the same symlink policy, and
+    /// the directories with the same path are merged.  Note that the cached files
+    /// are not invalidated when a file shadowing them is added to a preceding root.
+    pub fn with_fallback(mut self, root_dir: impl AsRef<Path>) -> Self {
+        self.fallback_roots.push(root_dir.as_ref().to_path_buf());
+        self
+    }
+
     /// Sets the value of `OpenConfig` used in handlers.
     pub fn open_config(self, config: OpenConfig) -> Self {
         Self {
@@ -522,6 +2055,144 @@ where
             ..self
         }
     }
+
+    /// Sets the rules for determining the value of `Cache-Control` for the served files.
+    ///
+    /// This replaces `cache_policy` of the `OpenConfig` set by `open_config`.
+    pub fn cache_policy(self, policy: CachePolicy) -> Self {
+        Self {
+            config: Some(OpenConfig {
+                cache_policy: Some(policy),
+                ..self.config.unwrap_or_default()
+            }),
+            ..self
+        }
+    }
+
+    /// Sets the name of file served when the request path resolves to a directory.
+    ///
+    /// The root directory and the sub-directories are also registered as routes
+    /// without the trailing slash (e.g. `/docs`).
+    pub fn index_file(self, name: impl Into<String>) -> Self {
+        Self {
+            index_file: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets whether to render an HTML listing of the entries when the request path
+    /// resolves to a directory without the index file.
+    ///
+    /// The listing shows the name, size and the last modification date of each entry.
+    /// It is never rendered for the directories outside of the root directory.
+    pub fn listing(self, enabled: bool) -> Self {
+        Self {
+            listing: enabled,
+            ..self
+        }
+    }
+
+    /// Sets the path of file, relative to the root directory, served for the request
+    /// paths that do not resolve to any file.
+    ///
+    /// This is intended for the single-page applications that use the history API
+    /// for routing.  The file is served for the unmatched paths within the current
+    /// scope, as well as for the missing files.  The paths rejected by `resolve_path`
+    /// are still reported as `404 Not Found`.
+    pub fn fallback_file(self, name: impl Into<String>) -> Self {
+        Self {
+            fallback_file: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets the policy for the symbolic links within the root directory.
+    ///
+    /// The default policy is `SymlinkPolicy::DenyExternal`.
+    pub fn symlinks(self, policy: SymlinkPolicy) -> Self {
+        Self {
+            symlinks: policy,
+            ..self
+        }
+    }
+
+    /// Sets the in-memory cache of the file contents.
+    ///
+    /// The same cache can be shared among multiple `Staticfiles`, and its
+    /// counters can be retrieved with `FileCache::metrics`.  The cache is not used
+    /// for the directory listings, the fallback file and the precompressed variants.
+    pub fn cache(self, cache: FileCache) -> Self {
+        Self {
+            cache: Some(cache),
+            ..self
+        }
+    }
+
+    /// Sets the path of file, relative to the root directory, served with the status
+    /// `404 Not Found` for the request paths that do not resolve to any file.
+    ///
+    /// The file is read when the application is built.  The response is returned
+    /// as an error, so the modifiers and the error handling of the scope still apply.
+    pub fn not_found_file(self, name: impl Into<String>) -> Self {
+        Self {
+            not_found: None,
+            not_found_file: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets the function that creates the error for the request paths that do not
+    /// resolve to any file.
+    ///
+    /// The function receives the path of the request.
+    pub fn not_found<F>(self, f: F) -> Self
+    where
+        F: Fn(&str) -> Error + Send + Sync + 'static,
+    {
+        Self {
+            not_found: Some(NotFound(Arc::new(f))),
+            not_found_file: None,
+            ..self
+        }
+    }
+
+    /// Sets whether to register the routes for the entries in the root directory
+    /// when the application is built.
+    ///
+    /// By default, the request paths are resolved by a single handler registered as
+    /// the default handler of the current scope, so the files added after startup
+    /// are also served, and registering another default handler in the same scope
+    /// is rejected when the application is built.  The eager mode additionally
+    /// registers a route for each entry at the top of the root directory, which is
+    /// suitable for immutable deployments.  In this mode, the entries added after
+    /// startup are not served.  The request paths are resolved by `resolve_path` in
+    /// both modes, so the same paths are served regardless of this setting.
+    pub fn eager(self, enabled: bool) -> Self {
+        Self {
+            eager: enabled,
+            ..self
+        }
+    }
+}
+
+/// Reads the page served for the request paths not resolved to any file.
+fn not_found_page(path: &Path) -> Result<NotFound, failure::Error> {
+    let content_type = mime_guess::from_path(path).first_or_octet_stream();
+    let body = std::fs::read_to_string(path)?;
+    Ok(not_found_response(content_type, body))
+}
+
+/// Creates the error responding with the specified page as `404 Not Found`.
+fn not_found_response(content_type: Mime, body: String) -> NotFound {
+    NotFound(Arc::new(move |_| {
+        crate::error::error_response(
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .header(header::CONTENT_TYPE, content_type.as_ref())
+                .body(body.clone())
+                .expect("should be a valid response"),
+        )
+    }))
 }
 
 impl<P, M, C> crate::config::Config<M, C> for Staticfiles<P>
@@ -534,54 +2205,209 @@ where
     type Error = crate::config::Error;
 
     fn configure(self, scope: &mut crate::app::config::Scope<'_, M, C>) -> crate::app::Result<()> {
-        let Self { root_dir, config } = self;
-
-        for entry in std::fs::read_dir(root_dir).map_err(crate::config::Error::custom)? {
-            let entry = entry.map_err(crate::config::Error::custom)?;
-
-            let name = entry.file_name();
-            let name = name
-                .to_str() //
-                .ok_or_else(|| {
-                    crate::config::Error::custom(failure::format_err!("the filename must be UTF-8"))
-                })?;
-
-            let path = entry
-                .path()
-                .canonicalize()
-                .map(|path| ArcPath(Arc::new(path)))
-                .map_err(crate::config::Error::custom)?;
-
-            let file_type = entry.file_type().map_err(crate::config::Error::custom)?;
-            if file_type.is_file() {
+        let Self {
+            root_dir,
+            fallback_roots,
+            config,
+            index_file,
+            listing,
+            fallback_file,
+            symlinks,
+            cache,
+            not_found,
+            not_found_file,
+            eager,
+        } = self;
+
+        let roots = Some(root_dir.as_ref())
+            .into_iter()
+            .chain(fallback_roots.iter().map(PathBuf::as_path))
+            .map(|root_dir| {
+                SafePath::new(root_dir)
+                    .map(|safe_path| safe_path.symlinks(symlinks))
+                    .map_err(crate::config::Error::custom)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let root = roots[0].root().to_path_buf();
+
+        // The fallback file and the not-found page are taken from the first root
+        // containing them.
+        let find_file = |name: &str| -> Result<PathBuf, failure::Error> {
+            let mut found = None;
+            for safe_path in &roots {
+                let path = resolve_path(safe_path.root(), name)
+                    .map_err(|err| failure::format_err!("{}", err))?;
+                if found.is_none() && path.is_file() {
+                    found = Some(path);
+                }
+            }
+            Ok(found.unwrap_or_else(|| root.join(name)))
+        };
+        let fallback_file = match fallback_file {
+            Some(name) => Some(ArcPath::from(find_file(&name).map_err(|err| {
+                crate::config::Error::custom(failure::format_err!("invalid fallback file: {}", err))
+            })?)),
+            None => None,
+        };
+        let not_found = match not_found_file {
+            Some(name) => Some(
+                find_file(&name)
+                    .and_then(|path| not_found_page(&path))
+                    .map_err(|err| {
+                        crate::config::Error::custom(failure::format_err!(
+                            "invalid not-found file: {}",
+                            err
+                        ))
+                    })?,
+            ),
+            None => not_found,
+        };
+        // In the eager mode, the entries at the top of the root directories are
+        // enumerated over all roots, and each of them is registered with the path
+        // under the primary root so that the roots are looked up in order at request
+        // time.  The names are resolved by `resolve_path`, in the same way as the
+        // request paths.
+        let entries = if eager {
+            let mut entries = vec![];
+            let mut names = HashSet::new();
+            for safe_path in &roots {
+                for entry in
+                    std::fs::read_dir(safe_path.root()).map_err(crate::config::Error::custom)?
+                {
+                    let entry = entry.map_err(crate::config::Error::custom)?;
+
+                    let name = entry.file_name();
+                    let name = name
+                        .to_str() //
+                        .ok_or_else(|| {
+                            crate::config::Error::custom(failure::format_err!(
+                                "the filename must be UTF-8"
+                            ))
+                        })?;
+                    // the entries whose names are not resolved to themselves (e.g. `a:b`
+                    // or `%2e`) are never served, as in the lazy mode.
+                    let path = match resolve_path(&root, name) {
+                        Ok(path) => path,
+                        Err(..) => continue,
+                    };
+                    if path != root.join(name) {
+                        continue;
+                    }
+                    if !names.insert(name.to_owned()) {
+                        continue;
+                    }
+
+                    // the symbolic links are followed here, and are checked against the
+                    // policy when the request is handled.
+                    let file_type = std::fs::metadata(entry.path())
+                        .map_err(crate::config::Error::custom)?
+                        .file_type();
+                    if !file_type.is_file() && !file_type.is_dir() {
+                        return Err(crate::config::Error::custom(failure::format_err!(
+                            "unexpected file type"
+                        )));
+                    }
+                    entries.push((name.to_owned(), path, file_type.is_dir()));
+                }
+            }
+            Some(entries)
+        } else {
+            None
+        };
+
+        let options = Arc::new(ResolveOptions {
+            roots,
+            index_file,
+            listing,
+            fallback_file,
+            cache,
+            not_found,
+            entries: entries.as_ref().map(|entries| {
+                entries
+                    .iter()
+                    .filter_map(|(_, path, _)| path.file_name().map(ToOwned::to_owned))
+                    .collect()
+            }),
+        });
+        let serve_file = |path: ArcPath, request_path: RequestPath| ServeFile {
+            inner: Arc::new(ServeFileInner {
+                path,
+                config: config.clone(),
+                request_path,
+                options: options.clone(),
+            }),
+        };
+
+        // A single handler resolves the paths at request time.  It is registered as
+        // the default handler of the scope, so that it does not conflict with the
+        // other routes, together with the route to the prefix itself (e.g. `/public`)
+        // unless the prefix ends with a slash.  In the eager mode, the handler only
+        // serves the entries enumerated above.
+        let prefix = scope.prefix().as_str().to_owned();
+        if !prefix.ends_with('/') {
+            let handler = serve_file(
+                root.clone().into(),
+                RequestPath::StripPrefix(prefix.clone()),
+            );
+            scope.route("/", handler)?;
+        }
+        scope.exclusive_default_handler(
+            "Staticfiles",
+            serve_file(root.clone().into(), RequestPath::StripPrefix(prefix)),
+        )?;
+
+        // The routes to the entries are matched by the router ahead of the default
+        // handler, without resolving the paths at request time.
+        for (name, path, is_dir) in entries.unwrap_or_default() {
+            let path = ArcPath::from(path);
+            if !is_dir {
+                scope.route(format!("/{}", name), serve_file(path, RequestPath::Fixed))?;
+                continue;
+            }
+            if options.serves_directory() {
                 scope.route(
                     format!("/{}", name),
-                    ServeFile {
-                        inner: Arc::new(ServeFileInner {
-                            path,
-                            config: config.clone(),
-                            extract_path: false,
-                        }),
-                    },
-                )?;
-            } else if file_type.is_dir() {
-                scope.route(
-                    format!("/{}/*path", name),
-                    ServeFile {
-                        inner: Arc::new(ServeFileInner {
-                            path,
-                            config: config.clone(),
-                            extract_path: true,
-                        }),
-                    },
+                    serve_file(path.clone(), RequestPath::Fixed),
                 )?;
-            } else {
-                return Err(crate::config::Error::custom(failure::format_err!(
-                    "unexpected file type"
-                )));
             }
+            scope.route(
+                format!("/{}/*path", name),
+                serve_file(path, RequestPath::CatchAll),
+            )?;
         }
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_stream_aborts_on_truncated_file() -> io::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("tsukuyomi-fs-truncated-{}", std::process::id()));
+        std::fs::write(&path, vec![0u8; 64])?;
+        let file = File::open(&path)?;
+        let meta = file.metadata()?;
+        std::fs::write(&path, vec![0u8; 40])?;
+
+        let stream = ReadStream::new(file, meta, Some(16), IoStrategy::Background, 0..64);
+        let mut stream = futures01::executor::spawn(stream);
+        let mut received = 0;
+        let err = loop {
+            match stream.wait_stream() {
+                Some(Ok(chunk)) => received += chunk.len(),
+                Some(Err(err)) => break err,
+                None => panic!("the stream should be aborted"),
+            }
+        };
+        assert!(stream.wait_stream().is_none());
+        assert_eq!(received, 40);
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        std::fs::remove_file(&path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,801 @@
+//! Streaming the directories as archives.
+
+use {
+    super::{IoStrategy, PendingIo},
+    crate::{
+        error::Error,
+        future::TryFuture,
+        input::Input,
+        output::{attachment::content_disposition, ResponseBody},
+        responder::Responder,
+    },
+    bytes::Bytes,
+    futures01::{Async, Poll, Stream},
+    globset::{Glob, GlobSet, GlobSetBuilder},
+    http::{
+        header::{self, HeaderValue},
+        Response,
+    },
+    std::{
+        cmp,
+        fs::{File, Metadata},
+        io::{self, Read as _Read},
+        path::{Component, Path, PathBuf},
+    },
+    walkdir::WalkDir,
+};
+
+const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// The format of the archives created by `ArchiveDir`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveFormat {
+    /// The tar archive, sent as `application/x-tar`.
+    ///
+    /// The paths longer than 100 bytes are stored with the GNU extension.
+    Tar,
+
+    /// The zip archive without compression, sent as `application/zip`.
+    ///
+    /// The sizes and checksums of the entries are written after their contents, so
+    /// the archive is not seekable while created.  ZIP64 is not supported, so the
+    /// transfer is aborted if the archive exceeds 4 GiB or 65535 entries.
+    ///
+    /// This variant is available only if the feature `archive-zip` is enabled.
+    #[cfg(feature = "archive-zip")]
+    Zip,
+}
+
+impl ArchiveFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "tar",
+            #[cfg(feature = "archive-zip")]
+            ArchiveFormat::Zip => "zip",
+        }
+    }
+
+    fn content_type(self) -> &'static str {
+        match self {
+            ArchiveFormat::Tar => "application/x-tar",
+            #[cfg(feature = "archive-zip")]
+            ArchiveFormat::Zip => "application/zip",
+        }
+    }
+}
+
+/// An instance of `Responder` for downloading a directory as an archive.
+///
+/// The entries are walked and read while the response body is sent, so the archive
+/// is never materialized on the memory or the disk.  Only a chunk of the body is
+/// held at a time, in addition to the listing of the directory being walked (and
+/// the central directory of zip).  If an error occurs in the middle of the walk
+/// (e.g. a file has been removed or truncated), the transfer is aborted rather than
+/// completing the body with a broken archive.
+///
+/// The paths in the archive are relative to the directory.  The symbolic links are
+/// neither followed nor archived, and the entries other than the regular files and
+/// the directories are skipped.
+///
+/// The filter patterns are the glob patterns matched against the relative paths
+/// separated by `/`, in which `*` also matches `/`.  The excluded directories are
+/// not descended.  If any include pattern is given, only the files matching one
+/// of them are archived, and the directories are created implicitly by their files.
+///
+/// The response has `Content-Disposition: attachment` with the name of the directory
+/// and the extension of the format, unless specified by `filename`.  The directory
+/// missing is reported as `404 Not Found`.
+///
+/// This type is available only if the feature `archive` is enabled.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, fs::ArchiveDir, App};
+/// let app = App::create(
+///     path!("/admin/logs.tar").to(endpoint::get().call(|| {
+///         ArchiveDir::new("/var/log/myapp")
+///             .include("*.log")
+///             .exclude("archived/*")
+///             .filename("logs.tar")
+///     })),
+/// );
+/// # app.unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct ArchiveDir {
+    root: PathBuf,
+    format: ArchiveFormat,
+    filename: Option<String>,
+    include: Vec<String>,
+    exclude: Vec<String>,
+    chunk_size: Option<usize>,
+    io: IoStrategy,
+}
+
+impl ArchiveDir {
+    /// Creates a new `ArchiveDir` which archives the specified directory as tar.
+    pub fn new(root: impl AsRef<Path>) -> Self {
+        Self {
+            root: root.as_ref().to_path_buf(),
+            format: ArchiveFormat::Tar,
+            filename: None,
+            include: vec![],
+            exclude: vec![],
+            chunk_size: None,
+            io: IoStrategy::default(),
+        }
+    }
+
+    /// Sets the format of the archive.
+    pub fn format(self, format: ArchiveFormat) -> Self {
+        Self { format, ..self }
+    }
+
+    /// Sets the file name sent in `Content-Disposition`.
+    pub fn filename(self, filename: impl Into<String>) -> Self {
+        Self {
+            filename: Some(filename.into()),
+            ..self
+        }
+    }
+
+    /// Adds a pattern of the files to be archived.
+    pub fn include(mut self, pattern: impl Into<String>) -> Self {
+        self.include.push(pattern.into());
+        self
+    }
+
+    /// Adds a pattern of the files and directories not to be archived.
+    ///
+    /// The exclude patterns take precedence over the include ones.
+    pub fn exclude(mut self, pattern: impl Into<String>) -> Self {
+        self.exclude.push(pattern.into());
+        self
+    }
+
+    /// Sets the size of chunks of the response body.
+    ///
+    /// The default value is 64 KiB.
+    pub fn chunk_size(self, chunk_size: usize) -> Self {
+        Self {
+            chunk_size: Some(chunk_size),
+            ..self
+        }
+    }
+
+    /// Sets the strategy for performing the file system operations.
+    pub fn io(self, io: IoStrategy) -> Self {
+        Self { io, ..self }
+    }
+
+    fn default_filename(&self) -> String {
+        let name = self
+            .root
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or("archive");
+        format!("{}.{}", name, self.format.extension())
+    }
+}
+
+impl Responder for ArchiveDir {
+    type Response = Response<ResponseBody>;
+    type Error = Error;
+    type Respond = OpenArchiveDir;
+
+    #[inline]
+    fn respond(self) -> Self::Respond {
+        OpenArchiveDir {
+            archive: Some(self),
+            pending: PendingIo::default(),
+        }
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct OpenArchiveDir {
+    archive: Option<ArchiveDir>,
+    pending: PendingIo<bool>,
+}
+
+impl TryFuture for OpenArchiveDir {
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, _: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        {
+            let archive = self
+                .archive
+                .as_ref()
+                .expect("the future has already been polled");
+            let is_dir = futures01::try_ready!(archive.io.poll_fs(&mut self.pending, || {
+                let root = archive.root.clone();
+                move || std::fs::metadata(root).map(|meta| meta.is_dir())
+            }));
+            if !is_dir {
+                return Err(crate::error::not_found("the path is not a directory"));
+            }
+        }
+
+        let archive = self.archive.take().expect("should be available");
+        let filters = Filters::new(&archive.include, &archive.exclude)
+            .map_err(crate::error::internal_server_error)?;
+        let filename = archive
+            .filename
+            .clone()
+            .unwrap_or_else(|| archive.default_filename());
+        let disposition = HeaderValue::from_shared(content_disposition(&filename).into())
+            .expect("should be a valid header value");
+
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, archive.format.content_type())
+            .header(header::CONTENT_DISPOSITION, disposition)
+            .body(ResponseBody::wrap_stream(ArchiveStream::new(
+                archive, filters,
+            )))
+            .expect("should be a valid response");
+
+        Ok(Async::Ready(response))
+    }
+}
+
+// ==== Filters ====
+
+#[derive(Debug)]
+struct Filters {
+    include: Option<GlobSet>,
+    exclude: GlobSet,
+}
+
+impl Filters {
+    fn new(include: &[String], exclude: &[String]) -> Result<Self, globset::Error> {
+        fn build(patterns: &[String]) -> Result<GlobSet, globset::Error> {
+            let mut builder = GlobSetBuilder::new();
+            for pattern in patterns {
+                builder.add(Glob::new(pattern)?);
+            }
+            builder.build()
+        }
+
+        Ok(Self {
+            include: if include.is_empty() {
+                None
+            } else {
+                Some(build(include)?)
+            },
+            exclude: build(exclude)?,
+        })
+    }
+
+    fn is_excluded(&self, name: &str) -> bool {
+        self.exclude.is_match(name)
+    }
+
+    fn is_included(&self, name: &str) -> bool {
+        !self.is_excluded(name)
+            && self
+                .include
+                .as_ref()
+                .map_or(true, |include| include.is_match(name))
+    }
+
+    fn includes_directories(&self) -> bool {
+        self.include.is_none()
+    }
+}
+
+// ==== ArchiveStream ====
+
+#[derive(Debug)]
+struct ArchiveStream {
+    /// The walker is moved out while filling a chunk on the background threads.
+    walker: Option<Box<Walker>>,
+    io: IoStrategy,
+    pending: PendingIo<(Box<Walker>, Vec<u8>)>,
+    done: bool,
+}
+
+#[derive(Debug)]
+struct Walker {
+    root: PathBuf,
+    walker: walkdir::IntoIter,
+    filters: Filters,
+    encoder: Encoder,
+    current: Option<CurrentFile>,
+    chunk_size: usize,
+    state: StreamState,
+}
+
+#[derive(Debug)]
+struct CurrentFile {
+    file: File,
+    len: u64,
+    remaining: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum StreamState {
+    Walking,
+    Finished,
+}
+
+impl ArchiveStream {
+    fn new(archive: ArchiveDir, filters: Filters) -> Self {
+        let walker = WalkDir::new(&archive.root)
+            .min_depth(1)
+            .sort_by(|a, b| a.file_name().cmp(b.file_name()))
+            .into_iter();
+        Self {
+            walker: Some(Box::new(Walker {
+                root: archive.root,
+                walker,
+                filters,
+                encoder: Encoder::new(archive.format),
+                current: None,
+                chunk_size: cmp::max(archive.chunk_size.unwrap_or(DEFAULT_CHUNK_SIZE), 1),
+                state: StreamState::Walking,
+            })),
+            io: archive.io,
+            pending: PendingIo::default(),
+            done: false,
+        }
+    }
+}
+
+impl Walker {
+    /// Fills a chunk of the archive, by walking the entries until the chunk is full.
+    ///
+    /// This function accesses the file system synchronously.
+    fn fill_chunk(&mut self) -> io::Result<Vec<u8>> {
+        let mut buf = Vec::with_capacity(self.chunk_size);
+        while buf.len() < self.chunk_size && self.state == StreamState::Walking {
+            if let Some(ref mut current) = self.current {
+                if current.remaining > 0 {
+                    #[allow(clippy::cast_possible_truncation)]
+                    let len =
+                        cmp::min((self.chunk_size - buf.len()) as u64, current.remaining) as usize;
+                    let start = buf.len();
+                    buf.resize(start + len, 0);
+                    let n = current.file.read(&mut buf[start..])?;
+                    buf.truncate(start + n);
+                    if n == 0 {
+                        return Err(io::Error::new(
+                            io::ErrorKind::UnexpectedEof,
+                            "the file was truncated while archiving",
+                        ));
+                    }
+                    self.encoder.update(&buf[start..]);
+                    current.remaining -= n as u64;
+                    continue;
+                }
+                self.encoder.end_entry(current.len, &mut buf)?;
+            }
+            self.current = None;
+
+            match self.walker.next() {
+                Some(entry) => {
+                    let entry = entry?;
+                    self.begin_entry(&entry, &mut buf)?;
+                }
+                None => {
+                    self.encoder.finish(&mut buf)?;
+                    self.state = StreamState::Finished;
+                }
+            }
+        }
+        Ok(buf)
+    }
+
+    fn begin_entry(&mut self, entry: &walkdir::DirEntry, buf: &mut Vec<u8>) -> io::Result<()> {
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            let name = relative_name(&self.root, entry.path())?;
+            if self.filters.is_excluded(&name) {
+                self.walker.skip_current_dir();
+            } else if self.filters.includes_directories() {
+                self.encoder.begin_entry(&name, &entry.metadata()?, buf)?;
+                self.encoder.end_entry(0, buf)?;
+            }
+            return Ok(());
+        }
+        if !file_type.is_file() {
+            return Ok(());
+        }
+
+        let name = relative_name(&self.root, entry.path())?;
+        if !self.filters.is_included(&name) {
+            return Ok(());
+        }
+        let file = File::open(entry.path())?;
+        let meta = file.metadata()?;
+        self.encoder.begin_entry(&name, &meta, buf)?;
+        self.current = Some(CurrentFile {
+            file,
+            len: meta.len(),
+            remaining: meta.len(),
+        });
+        Ok(())
+    }
+}
+
+/// Returns the path of the entry relative to the root, separated by `/`.
+fn relative_name(root: &Path, path: &Path) -> io::Result<String> {
+    let relative = path
+        .strip_prefix(root)
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+    let mut name = String::new();
+    for component in relative.components() {
+        let segment = match component {
+            Component::Normal(segment) => segment.to_str().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "the filename must be UTF-8")
+            })?,
+            _ => continue,
+        };
+        if !name.is_empty() {
+            name.push('/');
+        }
+        name.push_str(segment);
+    }
+    Ok(name)
+}
+
+impl Stream for ArchiveStream {
+    type Item = Bytes;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        let finished = self
+            .walker
+            .as_ref()
+            .map_or(false, |walker| walker.state != StreamState::Walking);
+        if self.done || finished {
+            self.done = true;
+            return Ok(Async::Ready(None));
+        }
+
+        let walker = &mut self.walker;
+        let polled = self.io.poll_io(&mut self.pending, || {
+            let mut walker = walker.take().expect("the stream has already finished");
+            move || {
+                let chunk = walker.fill_chunk()?;
+                Ok((walker, chunk))
+            }
+        });
+        match polled {
+            Ok(Async::Ready((_, ref chunk))) if chunk.is_empty() => {
+                self.done = true;
+                Ok(Async::Ready(None))
+            }
+            Ok(Async::Ready((walker, chunk))) => {
+                self.walker = Some(walker);
+                Ok(Async::Ready(Some(chunk.into())))
+            }
+            Ok(Async::NotReady) => Ok(Async::NotReady),
+            Err(err) => {
+                // The error aborts the transfer, rather than completing the body with
+                // a broken archive.
+                self.done = true;
+                Err(err)
+            }
+        }
+    }
+}
+
+// ==== Encoder ====
+
+#[derive(Debug)]
+enum Encoder {
+    Tar,
+    #[cfg(feature = "archive-zip")]
+    Zip(self::zip::ZipEncoder),
+}
+
+impl Encoder {
+    fn new(format: ArchiveFormat) -> Self {
+        match format {
+            ArchiveFormat::Tar => Encoder::Tar,
+            #[cfg(feature = "archive-zip")]
+            ArchiveFormat::Zip => Encoder::Zip(Default::default()),
+        }
+    }
+
+    fn begin_entry(&mut self, name: &str, meta: &Metadata, buf: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Encoder::Tar => tar_header(name, meta, buf),
+            #[cfg(feature = "archive-zip")]
+            Encoder::Zip(zip) => zip.begin_entry(name, meta, buf),
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn update(&mut self, data: &[u8]) {
+        match self {
+            Encoder::Tar => {}
+            #[cfg(feature = "archive-zip")]
+            Encoder::Zip(zip) => zip.update(data),
+        }
+    }
+
+    fn end_entry(&mut self, len: u64, buf: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Encoder::Tar => {
+                pad_block(len, buf);
+                Ok(())
+            }
+            #[cfg(feature = "archive-zip")]
+            Encoder::Zip(zip) => zip.end_entry(buf),
+        }
+    }
+
+    fn finish(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+        match self {
+            Encoder::Tar => {
+                // the end of archive is marked by two zero blocks.
+                buf.extend_from_slice(&[0; 1024]);
+                Ok(())
+            }
+            #[cfg(feature = "archive-zip")]
+            Encoder::Zip(zip) => zip.finish(buf),
+        }
+    }
+}
+
+const TAR_BLOCK_SIZE: u64 = 512;
+
+fn pad_block(len: u64, buf: &mut Vec<u8>) {
+    let remainder = len % TAR_BLOCK_SIZE;
+    if remainder > 0 {
+        #[allow(clippy::cast_possible_truncation)]
+        buf.resize(buf.len() + (TAR_BLOCK_SIZE - remainder) as usize, 0);
+    }
+}
+
+fn tar_header(name: &str, meta: &Metadata, buf: &mut Vec<u8>) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_metadata(meta);
+
+    if let Err(err) = header.set_path(name) {
+        let old_name = &mut header.as_old_mut().name;
+        if name.len() < old_name.len() {
+            return Err(err);
+        }
+
+        // The long path is stored in the preceding entry, with the GNU extension.
+        let mut long_name = tar::Header::new_gnu();
+        let link = b"././@LongLink";
+        long_name.as_old_mut().name[..link.len()].copy_from_slice(link);
+        long_name.set_mode(0o644);
+        long_name.set_uid(0);
+        long_name.set_gid(0);
+        long_name.set_mtime(0);
+        long_name.set_size(name.len() as u64 + 1);
+        long_name.set_entry_type(tar::EntryType::GNULongName);
+        long_name.set_cksum();
+        buf.extend_from_slice(long_name.as_bytes());
+        buf.extend_from_slice(name.as_bytes());
+        buf.push(0);
+        pad_block(name.len() as u64 + 1, buf);
+
+        let len = old_name.len();
+        old_name.copy_from_slice(&name.as_bytes()[..len]);
+    }
+
+    header.set_cksum();
+    buf.extend_from_slice(header.as_bytes());
+    Ok(())
+}
+
+#[cfg(feature = "archive-zip")]
+mod zip {
+    use {
+        bytes::BufMut,
+        std::{
+            fs::Metadata,
+            io,
+            time::{SystemTime, UNIX_EPOCH},
+        },
+    };
+
+    const LOCAL_FILE_HEADER: u32 = 0x0403_4b50;
+    const DATA_DESCRIPTOR: u32 = 0x0807_4b50;
+    const CENTRAL_DIRECTORY_HEADER: u32 = 0x0201_4b50;
+    const END_OF_CENTRAL_DIRECTORY: u32 = 0x0605_4b50;
+
+    /// The version 2.0, which is required for the directories and the data descriptors.
+    const VERSION: u16 = 20;
+    /// The host system of the external attributes (UNIX), along with the version.
+    const VERSION_MADE_BY: u16 = (3 << 8) | VERSION;
+    /// The sizes and CRC are given in the data descriptor, and the names are UTF-8.
+    const FLAGS: u16 = (1 << 3) | (1 << 11);
+
+    /// The state of a zip archive being created, without compression.
+    #[derive(Debug, Default)]
+    pub(super) struct ZipEncoder {
+        offset: u64,
+        entries: Vec<ZipEntry>,
+        hasher: crc32fast::Hasher,
+    }
+
+    #[derive(Debug)]
+    struct ZipEntry {
+        name: String,
+        time: u16,
+        date: u16,
+        offset: u32,
+        crc: u32,
+        size: u32,
+        external_attributes: u32,
+    }
+
+    fn too_large() -> io::Error {
+        io::Error::new(
+            io::ErrorKind::Other,
+            "the archive exceeds the limits of zip without ZIP64",
+        )
+    }
+
+    fn to_u32(n: u64) -> io::Result<u32> {
+        if n > u64::from(std::u32::MAX) {
+            return Err(too_large());
+        }
+        Ok(n as u32)
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn to_u16(n: usize) -> io::Result<u16> {
+        if n > usize::from(std::u16::MAX) {
+            return Err(too_large());
+        }
+        Ok(n as u16)
+    }
+
+    impl ZipEncoder {
+        pub(super) fn begin_entry(
+            &mut self,
+            name: &str,
+            meta: &Metadata,
+            buf: &mut Vec<u8>,
+        ) -> io::Result<()> {
+            let name = if meta.is_dir() {
+                format!("{}/", name)
+            } else {
+                name.to_owned()
+            };
+            let (time, date) = dos_datetime(meta.modified().unwrap_or(UNIX_EPOCH));
+            to_u32(meta.len())?;
+            to_u16(self.entries.len() + 1)?;
+
+            let start = buf.len();
+            buf.put_u32_le(LOCAL_FILE_HEADER);
+            buf.put_u16_le(VERSION);
+            buf.put_u16_le(FLAGS);
+            buf.put_u16_le(0); // stored
+            buf.put_u16_le(time);
+            buf.put_u16_le(date);
+            buf.put_u32_le(0); // CRC, given in the data descriptor
+            buf.put_u32_le(0); // compressed size
+            buf.put_u32_le(0); // uncompressed size
+            buf.put_u16_le(to_u16(name.len())?);
+            buf.put_u16_le(0); // extra field length
+            buf.extend_from_slice(name.as_bytes());
+
+            self.entries.push(ZipEntry {
+                time,
+                date,
+                offset: to_u32(self.offset)?,
+                crc: 0,
+                size: 0,
+                external_attributes: external_attributes(meta),
+                name,
+            });
+            self.offset += (buf.len() - start) as u64;
+            self.hasher = crc32fast::Hasher::new();
+            Ok(())
+        }
+
+        pub(super) fn update(&mut self, data: &[u8]) {
+            self.hasher.update(data);
+            self.offset += data.len() as u64;
+        }
+
+        pub(super) fn end_entry(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+            let hasher = std::mem::replace(&mut self.hasher, crc32fast::Hasher::new());
+            let entry = self
+                .entries
+                .last_mut()
+                .expect("the entry should be started");
+            entry.crc = hasher.finalize();
+            entry.size = to_u32(self.offset)? - entry.offset - local_header_len(&entry.name);
+
+            buf.put_u32_le(DATA_DESCRIPTOR);
+            buf.put_u32_le(entry.crc);
+            buf.put_u32_le(entry.size);
+            buf.put_u32_le(entry.size);
+            self.offset += 16;
+            Ok(())
+        }
+
+        pub(super) fn finish(&mut self, buf: &mut Vec<u8>) -> io::Result<()> {
+            let start = buf.len();
+            for entry in &self.entries {
+                buf.put_u32_le(CENTRAL_DIRECTORY_HEADER);
+                buf.put_u16_le(VERSION_MADE_BY);
+                buf.put_u16_le(VERSION);
+                buf.put_u16_le(FLAGS);
+                buf.put_u16_le(0); // stored
+                buf.put_u16_le(entry.time);
+                buf.put_u16_le(entry.date);
+                buf.put_u32_le(entry.crc);
+                buf.put_u32_le(entry.size);
+                buf.put_u32_le(entry.size);
+                buf.put_u16_le(to_u16(entry.name.len())?);
+                buf.put_u16_le(0); // extra field length
+                buf.put_u16_le(0); // comment length
+                buf.put_u16_le(0); // disk number
+                buf.put_u16_le(0); // internal attributes
+                buf.put_u32_le(entry.external_attributes);
+                buf.put_u32_le(entry.offset);
+                buf.extend_from_slice(entry.name.as_bytes());
+            }
+            let size = (buf.len() - start) as u64;
+
+            let entries = to_u16(self.entries.len())?;
+            buf.put_u32_le(END_OF_CENTRAL_DIRECTORY);
+            buf.put_u16_le(0); // disk number
+            buf.put_u16_le(0); // disk with the central directory
+            buf.put_u16_le(entries);
+            buf.put_u16_le(entries);
+            buf.put_u32_le(to_u32(size)?);
+            buf.put_u32_le(to_u32(self.offset)?);
+            buf.put_u16_le(0); // comment length
+
+            self.offset += size + 22;
+            Ok(())
+        }
+    }
+
+    #[allow(clippy::cast_possible_truncation)]
+    fn local_header_len(name: &str) -> u32 {
+        30 + name.len() as u32
+    }
+
+    #[cfg(unix)]
+    fn external_attributes(meta: &Metadata) -> u32 {
+        use std::os::unix::fs::PermissionsExt;
+        let dos = if meta.is_dir() { 0x10 } else { 0 };
+        (meta.permissions().mode() << 16) | dos
+    }
+
+    #[cfg(not(unix))]
+    fn external_attributes(meta: &Metadata) -> u32 {
+        if meta.is_dir() {
+            0x10
+        } else {
+            0
+        }
+    }
+
+    /// Converts the time into the MS-DOS format, in UTC.
+    ///
+    /// The times out of 1980-2107 are clamped, since the format cannot represent them.
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn dos_datetime(time: SystemTime) -> (u16, u16) {
+        let secs = time
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_secs())
+            .unwrap_or(0);
+        let tm = time::at_utc(time::Timespec::new(secs as i64, 0));
+        if tm.tm_year < 80 {
+            return (0, (1 << 5) | 1);
+        }
+        if tm.tm_year > 207 {
+            return ((23 << 11) | (59 << 5) | 29, (127 << 9) | (12 << 5) | 31);
+        }
+        let time = ((tm.tm_hour as u16) << 11) | ((tm.tm_min as u16) << 5) | (tm.tm_sec as u16 / 2);
+        let date =
+            (((tm.tm_year - 80) as u16) << 9) | (((tm.tm_mon + 1) as u16) << 5) | tm.tm_mday as u16;
+        (time, date)
+    }
+}
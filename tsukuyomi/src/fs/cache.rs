@@ -0,0 +1,366 @@
+//! The in-memory cache of the small static files.
+
+use {
+    super::{in_memory_response, metadata_key, validator_headers, OpenConfig},
+    crate::{
+        error::Error,
+        etag::ETag,
+        output::{IntoResponse, ResponseBody},
+    },
+    bytes::Bytes,
+    http::{
+        header::{self, HeaderMap, HeaderValue},
+        Request, Response,
+    },
+    mime::Mime,
+    serde::Serialize,
+    std::{
+        collections::{hash_map::DefaultHasher, HashMap},
+        fs::File,
+        hash::{Hash, Hasher},
+        io::{self, Read as _Read},
+        path::{Path, PathBuf},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, RwLock, RwLockReadGuard, RwLockWriteGuard,
+        },
+        time::{Duration, Instant, SystemTime},
+    },
+};
+
+const NUM_SHARDS: usize = 16;
+
+/// The configuration of `FileCache`.
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// The maximum size of a file to be cached, in bytes.
+    ///
+    /// The default value is 64 KiB.
+    pub max_file_size: u64,
+
+    /// The maximum total size of the cached files, in bytes.
+    ///
+    /// When the total size exceeds this value, the least recently used files
+    /// are evicted.  The default value is 8 MiB.
+    pub max_total_size: u64,
+
+    /// The duration for which the cached files are served without checking
+    /// the file system.
+    ///
+    /// If `None`, the modification time of the file is checked on each hit
+    /// and the cached content is discarded when the file has been modified.
+    /// This is the default behavior.
+    pub ttl: Option<Duration>,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            max_file_size: 64 * 1024,
+            max_total_size: 8 * 1024 * 1024,
+            ttl: None,
+        }
+    }
+}
+
+/// An in-memory cache of the file contents, used with `Staticfiles::cache`.
+///
+/// The cache is partitioned into the shards guarded by the read-write locks,
+/// so the hits never wait for a global lock.  The clones of this value share
+/// the same cache.
+#[derive(Debug, Clone)]
+pub struct FileCache {
+    inner: Arc<Inner>,
+}
+
+#[derive(Debug)]
+struct Inner {
+    config: CacheConfig,
+    shards: Vec<RwLock<HashMap<PathBuf, Arc<Entry>>>>,
+    clock: AtomicUsize,
+    size: AtomicUsize,
+    hits: AtomicUsize,
+    misses: AtomicUsize,
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    version: String,
+    content: Bytes,
+    content_type: Mime,
+    etag: ETag,
+    last_modified: Option<SystemTime>,
+    headers: HeaderMap,
+    cached_at: Instant,
+    last_used: AtomicUsize,
+}
+
+/// The result of looking up the cache without accessing the file system.
+pub(super) enum Lookup {
+    Hit(CachedFile),
+    Check(CacheCheck),
+    Miss,
+}
+
+/// A cached entry that needs to be checked against the file system before served.
+pub(super) struct CacheCheck(Arc<Entry>);
+
+impl FileCache {
+    /// Creates an empty `FileCache` with the specified configuration.
+    pub fn new(config: CacheConfig) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                config,
+                shards: (0..NUM_SHARDS).map(|_| RwLock::default()).collect(),
+                clock: AtomicUsize::new(0),
+                size: AtomicUsize::new(0),
+                hits: AtomicUsize::new(0),
+                misses: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    /// Returns a snapshot of the counters.
+    pub fn metrics(&self) -> CacheMetrics {
+        CacheMetrics {
+            hits: self.inner.hits.load(Ordering::SeqCst),
+            misses: self.inner.misses.load(Ordering::SeqCst),
+            entries: (0..NUM_SHARDS).map(|i| self.read(i).len()).sum(),
+            size: self.inner.size.load(Ordering::SeqCst),
+        }
+    }
+
+    /// Looks up the entry with the lexically resolved path of the request.
+    pub(super) fn lookup(&self, key: &Path) -> Lookup {
+        let shard = shard_index(key);
+        let entry = match self.read(shard).get(key) {
+            Some(entry) => entry.clone(),
+            None => return Lookup::Miss,
+        };
+        entry.last_used.store(
+            self.inner.clock.fetch_add(1, Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+
+        match self.inner.config.ttl {
+            Some(ttl) if entry.cached_at.elapsed() < ttl => {
+                self.inner.hits.fetch_add(1, Ordering::SeqCst);
+                Lookup::Hit(CachedFile::new(entry))
+            }
+            Some(..) => {
+                self.remove(key, &entry);
+                Lookup::Miss
+            }
+            None => Lookup::Check(CacheCheck(entry)),
+        }
+    }
+
+    /// Checks whether the file of the cached entry has not been modified.
+    ///
+    /// This function accesses the file system synchronously.
+    pub(super) fn check(&self, key: &Path, check: CacheCheck) -> io::Result<Option<CachedFile>> {
+        let CacheCheck(entry) = check;
+        match std::fs::metadata(&entry.path) {
+            Ok(ref metadata) if metadata_key(metadata) == entry.version => {
+                self.inner.hits.fetch_add(1, Ordering::SeqCst);
+                Ok(Some(CachedFile::new(entry)))
+            }
+            Ok(..) => {
+                self.remove(key, &entry);
+                Ok(None)
+            }
+            Err(ref err) if err.kind() == io::ErrorKind::NotFound => {
+                self.remove(key, &entry);
+                Ok(None)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Reads the resolved file into the cache, if it is small enough.
+    ///
+    /// This function accesses the file system synchronously.
+    pub(super) fn load(
+        &self,
+        key: &Path,
+        path: &Path,
+        config: &OpenConfig,
+    ) -> io::Result<Option<CachedFile>> {
+        self.inner.misses.fetch_add(1, Ordering::SeqCst);
+
+        let metadata = std::fs::metadata(path)?;
+        if !metadata.is_file() || metadata.len() > self.inner.config.max_file_size {
+            return Ok(None);
+        }
+
+        let mut content = Vec::with_capacity(metadata.len() as usize);
+        File::open(path)?.read_to_end(&mut content)?;
+        if content.len() as u64 != metadata.len() {
+            // the file has been modified while reading.
+            return Ok(None);
+        }
+
+        let etag = config.etag.compute(path, &metadata)?;
+        let last_modified = metadata.modified().ok();
+        let entry = Arc::new(Entry {
+            path: path.to_path_buf(),
+            version: metadata_key(&metadata),
+            content: content.into(),
+            content_type: mime_guess::from_path(path).first_or_octet_stream(),
+            headers: validator_headers(config, None, &etag, last_modified),
+            etag,
+            last_modified,
+            cached_at: Instant::now(),
+            last_used: AtomicUsize::new(self.inner.clock.fetch_add(1, Ordering::SeqCst)),
+        });
+        self.insert(key, entry.clone());
+
+        Ok(Some(CachedFile::new(entry)))
+    }
+
+    fn insert(&self, key: &Path, entry: Arc<Entry>) {
+        let len = entry.content.len();
+        if len as u64 > self.inner.config.max_total_size {
+            return;
+        }
+
+        if let Some(old) = self
+            .write(shard_index(key))
+            .insert(key.to_path_buf(), entry)
+        {
+            self.inner
+                .size
+                .fetch_sub(old.content.len(), Ordering::SeqCst);
+        }
+        let mut size = self.inner.size.fetch_add(len, Ordering::SeqCst) + len;
+        while size as u64 > self.inner.config.max_total_size {
+            match self.evict_one() {
+                Some(evicted) => size -= evicted,
+                None => break,
+            }
+        }
+    }
+
+    /// Evicts the least recently used entry, and returns its size.
+    ///
+    /// The shards are scanned one by one, so this function is called only
+    /// when a new entry is inserted.
+    fn evict_one(&self) -> Option<usize> {
+        let mut oldest: Option<(usize, PathBuf, usize)> = None;
+        for shard in 0..NUM_SHARDS {
+            for (key, entry) in self.read(shard).iter() {
+                let last_used = entry.last_used.load(Ordering::SeqCst);
+                if oldest
+                    .as_ref()
+                    .map_or(true, |&(_, _, used)| last_used < used)
+                {
+                    oldest = Some((shard, key.clone(), last_used));
+                }
+            }
+        }
+
+        let (shard, key, _) = oldest?;
+        let evicted = self.write(shard).remove(&key)?;
+        self.inner
+            .size
+            .fetch_sub(evicted.content.len(), Ordering::SeqCst);
+        Some(evicted.content.len())
+    }
+
+    fn remove(&self, key: &Path, entry: &Arc<Entry>) {
+        let mut shard = self.write(shard_index(key));
+        // the entry may have been replaced by another request in the meantime.
+        if shard
+            .get(key)
+            .map_or(false, |current| Arc::ptr_eq(current, entry))
+        {
+            shard.remove(key);
+            self.inner
+                .size
+                .fetch_sub(entry.content.len(), Ordering::SeqCst);
+        }
+    }
+
+    fn read(&self, shard: usize) -> RwLockReadGuard<'_, HashMap<PathBuf, Arc<Entry>>> {
+        self.inner.shards[shard]
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self, shard: usize) -> RwLockWriteGuard<'_, HashMap<PathBuf, Arc<Entry>>> {
+        self.inner.shards[shard]
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn shard_index(key: &Path) -> usize {
+    let mut hasher = DefaultHasher::new();
+    key.hash(&mut hasher);
+    (hasher.finish() % NUM_SHARDS as u64) as usize
+}
+
+/// A snapshot of the counters of a `FileCache`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct CacheMetrics {
+    /// The number of requests served from the cache.
+    pub hits: usize,
+    /// The number of requests that read the file from the file system.
+    pub misses: usize,
+    /// The number of cached files.
+    pub entries: usize,
+    /// The total size of the cached files, in bytes.
+    pub size: usize,
+}
+
+/// The response of a file served from `FileCache`.
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct CachedFile {
+    entry: Arc<Entry>,
+    cache_control: Option<HeaderValue>,
+}
+
+impl CachedFile {
+    fn new(entry: Arc<Entry>) -> Self {
+        Self {
+            entry,
+            cache_control: None,
+        }
+    }
+
+    /// Returns the path of the cached file.
+    pub(super) fn path(&self) -> &Path {
+        &self.entry.path
+    }
+
+    /// Sets the value of `Cache-Control`, which is determined for each request.
+    pub(super) fn cache_control(self, value: Option<HeaderValue>) -> Self {
+        Self {
+            cache_control: value,
+            ..self
+        }
+    }
+}
+
+impl IntoResponse for CachedFile {
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let entry = &*self.entry;
+        let mut headers = entry.headers.clone();
+        if let Some(cache_control) = self.cache_control {
+            headers.insert(header::CACHE_CONTROL, cache_control);
+        }
+        in_memory_response(
+            request,
+            &entry.content,
+            &entry.content_type,
+            &entry.etag,
+            entry.last_modified,
+            headers,
+        )
+    }
+}
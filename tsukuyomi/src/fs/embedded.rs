@@ -0,0 +1,399 @@
+//! Serving the assets compiled into the executable.
+
+use {
+    super::{
+        allowed_methods, cache_control, check_method, content_hash_etag, in_memory_response,
+        not_found_response, relative_url_path, resolve_path, validator_headers, CachePolicy,
+        Encoding, NotFound, OpenConfig, RequestPath,
+    },
+    crate::{
+        error::Error,
+        etag::{fnv1a, ETag, FNV_OFFSET_BASIS},
+        future::TryFuture,
+        handler::{AllowedMethods, Handler, ModifyHandler},
+        input::Input,
+        output::ResponseBody,
+    },
+    bytes::Bytes,
+    futures01::{Async, Poll},
+    http::{
+        header::{self, HeaderValue},
+        Response, StatusCode,
+    },
+    include_dir::{Dir, DirEntry},
+    mime::Mime,
+    std::{
+        collections::HashMap,
+        fmt,
+        path::{Path, PathBuf},
+        sync::Arc,
+    },
+};
+
+/// A configuration type for serving the files embedded by `include_dir!`.
+///
+/// The entries of the directory are registered in a table when the application is
+/// built, and the request paths are resolved by a single handler registered as the
+/// default handler of the current scope, in the same way as `Staticfiles`.  The
+/// entity tags are computed from the contents at the same time.
+///
+/// The fields of `OpenConfig` other than `chunk_size` and `etag` are respected.  If
+/// `precompressed` is set, the files compressed when building the executable (e.g.
+/// `app.css.gz` for `app.css`) are served to the clients accepting the encoding.
+///
+/// This type is available only if the feature `embed` is enabled.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, fs::Embedded, App};
+/// use include_dir::{include_dir, Dir};
+///
+/// static ASSETS: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/tests/fixtures/embedded");
+///
+/// let app = App::create(
+///     mount("/assets").with(Embedded::new(&ASSETS).index_file("index.html")),
+/// );
+/// # app.unwrap();
+/// ```
+pub struct Embedded {
+    dir: &'static Dir<'static>,
+    config: Option<OpenConfig>,
+    index_file: Option<String>,
+    not_found: Option<NotFound>,
+    not_found_file: Option<String>,
+}
+
+impl fmt::Debug for Embedded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Embedded")
+            .field("dir", &self.dir.path())
+            .field("config", &self.config)
+            .field("index_file", &self.index_file)
+            .field("not_found", &self.not_found)
+            .field("not_found_file", &self.not_found_file)
+            .finish()
+    }
+}
+
+impl Embedded {
+    /// Creates a new `Embedded` with the specified directory.
+    pub fn new(dir: &'static Dir<'static>) -> Self {
+        Self {
+            dir,
+            config: None,
+            index_file: None,
+            not_found: None,
+            not_found_file: None,
+        }
+    }
+
+    /// Sets the value of `OpenConfig` used in handlers.
+    pub fn open_config(self, config: OpenConfig) -> Self {
+        Self {
+            config: Some(config),
+            ..self
+        }
+    }
+
+    /// Sets the rules for determining the value of `Cache-Control` for the served files.
+    ///
+    /// This replaces `cache_policy` of the `OpenConfig` set by `open_config`.
+    pub fn cache_policy(self, policy: CachePolicy) -> Self {
+        Self {
+            config: Some(OpenConfig {
+                cache_policy: Some(policy),
+                ..self.config.unwrap_or_default()
+            }),
+            ..self
+        }
+    }
+
+    /// Sets the name of file served when the request path resolves to a directory.
+    pub fn index_file(self, name: impl Into<String>) -> Self {
+        Self {
+            index_file: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets the path of the embedded file served with the status `404 Not Found`
+    /// for the request paths that do not resolve to any file.
+    ///
+    /// The file must be UTF-8.  The response is returned as an error, so the
+    /// modifiers and the error handling of the scope still apply.
+    pub fn not_found_file(self, name: impl Into<String>) -> Self {
+        Self {
+            not_found: None,
+            not_found_file: Some(name.into()),
+            ..self
+        }
+    }
+
+    /// Sets the function that creates the error for the request paths that do not
+    /// resolve to any file.
+    ///
+    /// The function receives the path of the request.
+    pub fn not_found<F>(self, f: F) -> Self
+    where
+        F: Fn(&str) -> Error + Send + Sync + 'static,
+    {
+        Self {
+            not_found: Some(NotFound(Arc::new(f))),
+            not_found_file: None,
+            ..self
+        }
+    }
+}
+
+impl<M, C> crate::config::Config<M, C> for Embedded
+where
+    M: ModifyHandler<ServeEmbedded>,
+    M::Handler: Into<C::Handler>,
+    C: crate::app::config::Concurrency,
+{
+    type Error = crate::config::Error;
+
+    fn configure(self, scope: &mut crate::app::config::Scope<'_, M, C>) -> crate::app::Result<()> {
+        let not_found = match self.not_found_file {
+            Some(ref name) => Some(embedded_not_found_page(self.dir, name).map_err(|err| {
+                crate::config::Error::custom(failure::format_err!(
+                    "invalid not-found file: {}",
+                    err
+                ))
+            })?),
+            None => self.not_found,
+        };
+        let assets = Arc::new(Assets::new(
+            self.dir,
+            self.index_file,
+            self.config.unwrap_or_default(),
+            not_found,
+        ));
+        let serve = |request_path: RequestPath| ServeEmbedded {
+            inner: Arc::new(ServeEmbeddedInner {
+                assets: assets.clone(),
+                request_path,
+            }),
+        };
+
+        let prefix = scope.prefix().as_str().to_owned();
+        if !prefix.ends_with('/') {
+            scope.route("/", serve(RequestPath::StripPrefix(prefix.clone())))?;
+        }
+        scope.exclusive_default_handler("Embedded", serve(RequestPath::StripPrefix(prefix)))?;
+        Ok(())
+    }
+}
+
+fn embedded_not_found_page(
+    dir: &'static Dir<'static>,
+    name: &str,
+) -> Result<NotFound, failure::Error> {
+    let file = dir
+        .get_file(name.trim_start_matches('/'))
+        .ok_or_else(|| failure::format_err!("no such file is embedded: {}", name))?;
+    let body = std::str::from_utf8(file.contents())?.to_owned();
+    let content_type = mime_guess::from_path(file.path()).first_or_octet_stream();
+    Ok(not_found_response(content_type, body))
+}
+
+/// The table of the embedded files, keyed by the relative paths.
+#[derive(Debug)]
+struct Assets {
+    entries: HashMap<PathBuf, Arc<Asset>>,
+    config: OpenConfig,
+    not_found: Option<NotFound>,
+}
+
+#[derive(Debug)]
+struct Asset {
+    path: &'static Path,
+    policy_path: String,
+    content: Bytes,
+    content_type: Mime,
+    etag: ETag,
+    variants: Vec<(Encoding, Bytes, ETag)>,
+}
+
+impl Asset {
+    fn new(path: &'static Path, content: &'static [u8]) -> Self {
+        Self {
+            path,
+            policy_path: relative_url_path(path),
+            content: Bytes::from_static(content),
+            content_type: mime_guess::from_path(path).first_or_octet_stream(),
+            etag: content_hash_etag(content.len() as u64, fnv1a(FNV_OFFSET_BASIS, content)),
+            variants: vec![],
+        }
+    }
+}
+
+impl Assets {
+    fn new(
+        dir: &'static Dir<'static>,
+        index_file: Option<String>,
+        config: OpenConfig,
+        not_found: Option<NotFound>,
+    ) -> Self {
+        let mut files = vec![];
+        collect_files(dir, &mut files);
+
+        let mut entries: HashMap<PathBuf, Asset> = files
+            .into_iter()
+            .map(|(path, content)| (path.to_path_buf(), Asset::new(path, content)))
+            .collect();
+
+        if let Some(ref precompressed) = config.precompressed {
+            let sidecars: Vec<_> = entries
+                .keys()
+                .flat_map(|path| {
+                    precompressed
+                        .encodings
+                        .iter()
+                        .map(move |&encoding| (path.clone(), encoding, encoding.sidecar_path(path)))
+                })
+                .collect();
+            for (path, encoding, sidecar) in sidecars {
+                let variant = match entries.get(&sidecar) {
+                    Some(sidecar) => (encoding, sidecar.content.clone(), sidecar.etag.clone()),
+                    None => continue,
+                };
+                if let Some(asset) = entries.get_mut(&path) {
+                    asset.variants.push(variant);
+                }
+            }
+        }
+
+        let mut entries: HashMap<PathBuf, Arc<Asset>> = entries
+            .into_iter()
+            .map(|(path, asset)| (path, Arc::new(asset)))
+            .collect();
+
+        if let Some(ref index_file) = index_file {
+            let mut dirs = vec![dir.path().to_path_buf()];
+            collect_dirs(dir, &mut dirs);
+            for dir in dirs {
+                if let Some(index) = entries.get(&dir.join(index_file)).cloned() {
+                    entries.insert(dir, index);
+                }
+            }
+        }
+
+        Self {
+            entries,
+            config,
+            not_found,
+        }
+    }
+
+    /// Replaces the error for the request path not resolved to any file with
+    /// the configured one.
+    fn not_found(&self, input: &Input<'_>, err: Error) -> Error {
+        match self.not_found {
+            Some(NotFound(ref f)) => f(input.request.uri().path()),
+            None => err,
+        }
+    }
+}
+
+fn collect_files(dir: &'static Dir<'static>, files: &mut Vec<(&'static Path, &'static [u8])>) {
+    for entry in dir.entries() {
+        match entry {
+            DirEntry::Dir(dir) => collect_files(dir, files),
+            DirEntry::File(file) => files.push((file.path(), file.contents())),
+        }
+    }
+}
+
+fn collect_dirs(dir: &'static Dir<'static>, dirs: &mut Vec<PathBuf>) {
+    for dir in dir.dirs() {
+        dirs.push(dir.path().to_path_buf());
+        collect_dirs(dir, dirs);
+    }
+}
+
+#[doc(hidden)]
+#[derive(Debug, Clone)]
+pub struct ServeEmbedded {
+    inner: Arc<ServeEmbeddedInner>,
+}
+
+#[derive(Debug)]
+struct ServeEmbeddedInner {
+    assets: Arc<Assets>,
+    request_path: RequestPath,
+}
+
+impl Handler for ServeEmbedded {
+    type Output = Response<ResponseBody>;
+    type Error = Error;
+    type Handle = Self;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        Some(allowed_methods())
+    }
+
+    fn handle(&self) -> Self::Handle {
+        self.clone()
+    }
+}
+
+impl TryFuture for ServeEmbedded {
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if let Some(response) = check_method(input.request)? {
+            return Ok(Async::Ready(response.map(|()| ResponseBody::empty())));
+        }
+
+        let assets = &self.inner.assets;
+        let path = self.inner.request_path.extract(input)?;
+        let path = resolve_path(Path::new(""), path).map_err(|err| assets.not_found(input, err))?;
+        let asset = match assets.entries.get(&path) {
+            Some(asset) => asset,
+            None => {
+                return Err(
+                    assets.not_found(input, crate::error::not_found("no such file or directory"))
+                )
+            }
+        };
+
+        let config = &assets.config;
+        let (encoding, content, etag) = match config.precompressed {
+            Some(ref precompressed) => precompressed
+                .acceptable_encodings(input.request)
+                .into_iter()
+                .filter_map(|encoding| {
+                    asset
+                        .variants
+                        .iter()
+                        .find(|&&(variant, _, _)| variant == encoding)
+                })
+                .next()
+                .map(|(encoding, content, etag)| (Some(*encoding), content, etag))
+                .unwrap_or((None, &asset.content, &asset.etag)),
+            None => (None, &asset.content, &asset.etag),
+        };
+
+        let cache_control = cache_control(config, Some(&asset.policy_path), asset.path, input);
+        let headers = validator_headers(config, cache_control, etag, None);
+        let mut response = in_memory_response(
+            input.request,
+            content,
+            &asset.content_type,
+            etag,
+            None,
+            headers,
+        )?;
+        if let Some(encoding) = encoding.filter(|_| response.status() != StatusCode::NOT_MODIFIED) {
+            response.headers_mut().insert(
+                header::CONTENT_ENCODING,
+                HeaderValue::from_static(encoding.token()),
+            );
+        }
+
+        Ok(Async::Ready(response))
+    }
+}
@@ -0,0 +1,373 @@
+//! Spooling the files uploaded with multipart forms to the disk.
+
+use {
+    super::IoStrategy,
+    crate::{error::HttpError, util::generate_id},
+    http::{Request, Response},
+    mime::Mime,
+    std::{
+        error::Error as StdError,
+        fmt,
+        fs::{File, OpenOptions},
+        io::{self, Write},
+        path::{Path, PathBuf},
+        slice, vec,
+    },
+};
+
+/// The default value of the maximum size of an uploaded file (16 MiB).
+pub const DEFAULT_MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// The default value of the maximum number of files in a form.
+pub const DEFAULT_MAX_FILES: usize = 16;
+
+/// The policy of flushing the uploaded files to the storage device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fsync {
+    /// The files are never synced explicitly.
+    ///
+    /// This is the default policy.
+    Never,
+
+    /// The content is synced before the file is moved by `TempUpload::persist`,
+    /// and the destination directory is synced after that.
+    OnPersist,
+
+    /// In addition to `OnPersist`, each file is synced as soon as it has been
+    /// received, before the handler is called.
+    Always,
+}
+
+impl Default for Fsync {
+    fn default() -> Self {
+        Fsync::Never
+    }
+}
+
+/// The configuration for the files uploaded with `extractor::body::form_with_uploads`.
+///
+/// The file parts of the form are written into the spool directory while the request
+/// body is received, rather than being buffered in memory.  The directory is created
+/// when the first file is written, if missing.
+#[derive(Debug, Clone)]
+pub struct Spool {
+    dir: PathBuf,
+    max_file_size: u64,
+    max_files: usize,
+    fsync: Fsync,
+    io: IoStrategy,
+}
+
+impl Spool {
+    /// Creates a new `Spool` with the specified directory.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_file_size: DEFAULT_MAX_FILE_SIZE,
+            max_files: DEFAULT_MAX_FILES,
+            fsync: Fsync::default(),
+            io: IoStrategy::default(),
+        }
+    }
+
+    /// Sets the maximum number of bytes of an uploaded file.
+    ///
+    /// The request sending a larger file is rejected with `413 Payload Too Large`.
+    /// The default value is `DEFAULT_MAX_FILE_SIZE`.
+    pub fn max_file_size(self, max_file_size: u64) -> Self {
+        Self {
+            max_file_size,
+            ..self
+        }
+    }
+
+    /// Sets the maximum number of files in a form.
+    ///
+    /// The request sending more files is rejected with `413 Payload Too Large`.
+    /// The default value is `DEFAULT_MAX_FILES`.
+    pub fn max_files(self, max_files: usize) -> Self {
+        Self { max_files, ..self }
+    }
+
+    /// Sets the policy of flushing the files to the storage device.
+    pub fn fsync(self, fsync: Fsync) -> Self {
+        Self { fsync, ..self }
+    }
+
+    /// Sets the strategy for performing the file system operations while receiving
+    /// the request body.
+    pub fn io(self, io: IoStrategy) -> Self {
+        Self { io, ..self }
+    }
+
+    pub(crate) fn max_file_size_limit(&self) -> u64 {
+        self.max_file_size
+    }
+
+    pub(crate) fn max_files_limit(&self) -> usize {
+        self.max_files
+    }
+
+    pub(crate) fn io_strategy(&self) -> IoStrategy {
+        self.io
+    }
+
+    /// Creates an empty file in the spool directory.
+    ///
+    /// This function accesses the file system synchronously.
+    pub(crate) fn create(
+        &self,
+        name: String,
+        filename: Option<String>,
+        content_type: Option<Mime>,
+    ) -> io::Result<TempUpload> {
+        std::fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("upload-{}.tmp", generate_id()?));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(TempUpload {
+            name,
+            filename,
+            content_type,
+            path,
+            file: Some(file),
+            len: 0,
+            fsync: self.fsync,
+            persisted: false,
+        })
+    }
+}
+
+/// A file uploaded with a multipart form, stored in the spool directory.
+///
+/// The file is removed when this value is dropped, unless `persist` has been called.
+/// Since the value is owned by the request while the file is being received, the
+/// partially written file is also removed when the request is aborted (e.g. the
+/// connection has been lost in the middle of the body).
+///
+/// The methods accessing the file system block the current thread.
+#[derive(Debug)]
+pub struct TempUpload {
+    name: String,
+    filename: Option<String>,
+    content_type: Option<Mime>,
+    path: PathBuf,
+    file: Option<File>,
+    len: u64,
+    fsync: Fsync,
+    persisted: bool,
+}
+
+impl TempUpload {
+    /// Returns the name of form field.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the file name sent by the client, if exists.
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_ref().map(|filename| &**filename)
+    }
+
+    /// Returns the value of `Content-type` of this part, if exists.
+    pub fn content_type(&self) -> Option<&Mime> {
+        self.content_type.as_ref()
+    }
+
+    /// Returns the number of bytes of the file.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns whether the file is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the path of the temporary file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Opens the temporary file for reading.
+    pub fn open(&self) -> io::Result<File> {
+        File::open(&self.path)
+    }
+
+    /// Moves the file to the specified path, so that it is not removed.
+    ///
+    /// If the file cannot be renamed (e.g. the destination is on another file
+    /// system), the content is copied instead.  On failure, the upload is returned
+    /// back with the error so that it can be retried or inspected.
+    pub fn persist(mut self, path: impl AsRef<Path>) -> Result<(), PersistError> {
+        match self.persist_inner(path.as_ref()) {
+            Ok(()) => Ok(()),
+            Err(error) => Err(PersistError {
+                error,
+                upload: Box::new(self),
+            }),
+        }
+    }
+
+    fn persist_inner(&mut self, path: &Path) -> io::Result<()> {
+        self.file = None;
+        let sync = self.fsync != Fsync::Never;
+
+        if sync {
+            File::open(&self.path)?.sync_all()?;
+        }
+        match std::fs::rename(&self.path, path) {
+            Ok(()) => {
+                // the upload refers to the moved file from now on.
+                self.path = path.to_owned();
+                self.persisted = true;
+            }
+            Err(..) => {
+                std::fs::copy(&self.path, path)?;
+                if sync {
+                    File::open(path)?.sync_all()?;
+                }
+            }
+        }
+        if sync {
+            sync_parent(path)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&mut self, chunk: &[u8]) -> io::Result<()> {
+        let file = self
+            .file
+            .as_mut()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "the upload has been completed"))?;
+        file.write_all(chunk)?;
+        self.len += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Closes the file after all content has been written.
+    pub(crate) fn complete(&mut self) -> io::Result<()> {
+        if let Some(file) = self.file.take() {
+            if self.fsync == Fsync::Always {
+                file.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for TempUpload {
+    fn drop(&mut self) {
+        self.file = None;
+        if !self.persisted {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// The error returned from `TempUpload::persist`, with the upload that could not
+/// be persisted.
+#[derive(Debug)]
+pub struct PersistError {
+    /// The underlying I/O error.
+    pub error: io::Error,
+    /// The upload which is still kept at `TempUpload::path`.
+    pub upload: Box<TempUpload>,
+}
+
+impl fmt::Display for PersistError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to persist the uploaded file: {}", self.error)
+    }
+}
+
+impl StdError for PersistError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PersistError> for io::Error {
+    fn from(err: PersistError) -> Self {
+        err.error
+    }
+}
+
+impl HttpError for PersistError {
+    type Body = String;
+
+    fn into_response(self, request: &Request<()>) -> Response<Self::Body> {
+        self.error.into_response(request)
+    }
+}
+
+#[cfg(unix)]
+fn sync_parent(path: &Path) -> io::Result<()> {
+    match path.parent().filter(|parent| *parent != Path::new("")) {
+        Some(parent) => File::open(parent)?.sync_all(),
+        None => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// A collection of the files uploaded with a multipart form.
+#[derive(Debug, Default)]
+pub struct TempUploads {
+    uploads: Vec<TempUpload>,
+}
+
+impl TempUploads {
+    pub(crate) fn push(&mut self, upload: TempUpload) {
+        self.uploads.push(upload);
+    }
+
+    /// Returns the first file associated with the specified field name, if exists.
+    pub fn get(&self, name: &str) -> Option<&TempUpload> {
+        self.uploads.iter().find(|upload| upload.name == name)
+    }
+
+    /// Removes the first file associated with the specified field name and returns it.
+    pub fn take(&mut self, name: &str) -> Option<TempUpload> {
+        let pos = self.uploads.iter().position(|upload| upload.name == name)?;
+        Some(self.uploads.remove(pos))
+    }
+
+    /// Returns an iterator over all uploaded files.
+    pub fn iter(&self) -> slice::Iter<'_, TempUpload> {
+        self.uploads.iter()
+    }
+
+    /// Returns the number of uploaded files.
+    pub fn len(&self) -> usize {
+        self.uploads.len()
+    }
+
+    /// Returns whether no file has been uploaded.
+    pub fn is_empty(&self) -> bool {
+        self.uploads.is_empty()
+    }
+}
+
+impl IntoIterator for TempUploads {
+    type Item = TempUpload;
+    type IntoIter = vec::IntoIter<TempUpload>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.uploads.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a TempUploads {
+    type Item = &'a TempUpload;
+    type IntoIter = slice::Iter<'a, TempUpload>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.uploads.iter()
+    }
+}
@@ -1,3 +1,9 @@
+//! Type-level utilities for handling the extracted values as tuples.
+//!
+//! The tuples are supported up to 16 elements.  The endpoints which extract more
+//! values should group some of them into a struct, by combining the extractors with
+//! `ExtractorExt::and` and converting their outputs with `ExtractorExt::map`.
+
 use std::sync::Arc;
 
 // ==== Tuple/HList ====
@@ -124,6 +130,50 @@ impl_hlist! {
     T15, T14, T13, T12, T11, T10, T9, T8, T7, T6, T5, T4, T3, T2, T1, T0,
 }
 
+/// A placeholder for the extracted values exceeding the maximum arity of tuples.
+///
+/// The combination of extractors producing 17 values results in this type instead
+/// of failing at the deeply nested `HList` bounds, so that the error is reported
+/// at the call of the endpoint function as the unsatisfied bound of `Func`.
+///
+/// ```compile_fail,E0277
+/// # use tsukuyomi::{config::prelude::*, extractor, App};
+/// let app = App::create(
+///     path!("/:a/:b/:c/:d/:e/:f/:g/:h/:i/:j/:k/:l/:m/:n/:o/:p").to(endpoint::get()
+///         .extract(extractor::value(0u32))
+///         .call(|| "too many values")),
+/// );
+/// ```
+#[doc(hidden)]
+#[derive(Debug)]
+pub struct TooManyValues<L>(L);
+
+macro_rules! impl_too_many_values {
+    ($($T:ident),*) => {
+        impl<$($T),*> Tuple for TooManyValues<HCons!($($T),*)> {
+            type HList = HCons!($($T),*);
+
+            #[inline]
+            fn into_hlist(self) -> Self::HList {
+                self.0
+            }
+        }
+
+        impl<$($T),*> HList for HCons!($($T),*) {
+            type Tuple = TooManyValues<Self>;
+
+            #[inline]
+            fn into_tuple(self) -> Self::Tuple {
+                TooManyValues(self)
+            }
+        }
+    };
+}
+
+impl_too_many_values! {
+    T16, T15, T14, T13, T12, T11, T10, T9, T8, T7, T6, T5, T4, T3, T2, T1, T0
+}
+
 // ==== Combine =====
 
 pub trait Combine<T: Tuple>: Tuple {
@@ -195,6 +245,24 @@ where
     }
 }
 
+/// A wrapper of function which takes the extracted values as a single tuple.
+///
+/// This value is created by `config::endpoint::tupled`.
+#[derive(Debug, Clone, Copy)]
+pub struct Tupled<F>(pub(crate) F);
+
+impl<F, Args: Tuple, R> Func<Args> for Tupled<F>
+where
+    F: Fn(Args) -> R,
+{
+    type Out = R;
+
+    #[inline]
+    fn call(&self, args: Args) -> Self::Out {
+        (self.0)(args)
+    }
+}
+
 impl<F, R> Func<()> for F
 where
     F: Fn() -> R,
@@ -0,0 +1,298 @@
+//! Guards for authorizing the incoming requests before reaching the handlers.
+
+pub use self::{
+    client_cert::{ClientCert, ClientCertError, Decision},
+    participate::Participate,
+};
+
+/// The certificate chain presented by the peer of the connection.
+///
+/// The value is inserted by the TLS acceptor of `tsukuyomi-server`, or by
+/// `extractor::tls::with_tls_info` when the application is embedded.
+pub use tsukuyomi_service::PeerCertificates;
+
+/// Creates a `ModifyHandler` that makes the specified modifier participate in the
+/// authorization preflight.
+///
+/// Only the modifiers wrapped by this function can be applied to the routes marked
+/// by `Route::preflight`.  The wrapped modifier is executed while the request is
+/// evaluated by `App::authorize`, and its rejection is reported as the outcome of
+/// the preflight.  The rejection is reported with the label `"modifier"`, unless
+/// another one is given by `Participate::label`.
+pub fn participate<M>(modifier: M) -> Participate<M> {
+    Participate {
+        modifier,
+        label: "modifier".into(),
+    }
+}
+
+mod participate {
+    use {
+        crate::{
+            app::Recorded,
+            handler::{Handler, ModifyHandler},
+        },
+        std::borrow::Cow,
+    };
+
+    /// A `ModifyHandler` that participates in the authorization preflight.
+    #[derive(Debug, Clone)]
+    pub struct Participate<M> {
+        pub(super) modifier: M,
+        pub(super) label: Cow<'static, str>,
+    }
+
+    impl<M> Participate<M> {
+        /// Sets the label which identifies the modifier in the outcome of the preflight.
+        ///
+        /// The label is exposed to the clients of the preflight endpoint.
+        pub fn label(self, label: impl Into<Cow<'static, str>>) -> Self {
+            Self {
+                label: label.into(),
+                ..self
+            }
+        }
+    }
+
+    impl<M, H> ModifyHandler<H> for Participate<M>
+    where
+        M: ModifyHandler<H>,
+        H: Handler,
+    {
+        type Output = M::Output;
+        type Handler = Recorded<M::Handler>;
+
+        #[inline]
+        fn modify(&self, inner: H) -> Self::Handler {
+            Recorded::new(self.modifier.modify(inner), self.label.clone())
+        }
+
+        #[inline]
+        fn participates_in_preflight(&self) -> bool {
+            true
+        }
+    }
+}
+
+/// Creates a `ModifyHandler` that authorizes the requests by using the certificate
+/// chain presented by the client.
+///
+/// The certificate chain is obtained from the value of [`PeerCertificates`] stored
+/// in the extension map of the request.  If the client does not present any
+/// certificate or the provided function rejects it, the request is rejected
+/// with `403 Forbidden`.
+///
+/// The function is called for every request, even for the ones sent over the same
+/// keep-alive connection, so the time-dependent checks such as `x509::within_validity`
+/// are always up to date.
+///
+/// [`PeerCertificates`]: ./struct.PeerCertificates.html
+pub fn client_cert<F>(f: F) -> ClientCert<F>
+where
+    F: Fn(&[Vec<u8>]) -> Decision + Send + Sync + 'static,
+{
+    ClientCert::new(f)
+}
+
+mod client_cert {
+    use {
+        crate::{
+            app::Recorded,
+            error::{Error, HttpError},
+            future::{Poll, TryFuture},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+            input::Input,
+        },
+        failure::Fail,
+        http::{Request, Response, StatusCode},
+        std::{borrow::Cow, sync::Arc},
+        tsukuyomi_service::PeerCertificates,
+    };
+
+    /// The result of authorization by the certificate chain.
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum Decision {
+        /// The request is authorized.
+        Allow,
+        /// The request is rejected with the specified reason.
+        Deny(Cow<'static, str>),
+    }
+
+    impl Decision {
+        /// Creates a `Decision::Deny` with the specified reason.
+        pub fn deny(reason: impl Into<Cow<'static, str>>) -> Self {
+            Decision::Deny(reason.into())
+        }
+
+        /// Returns whether the request is authorized.
+        pub fn is_allowed(&self) -> bool {
+            match self {
+                Decision::Allow => true,
+                Decision::Deny(..) => false,
+            }
+        }
+
+        /// Continues the authorization with `f` if the request has been authorized so far.
+        pub fn and_then(self, f: impl FnOnce() -> Decision) -> Self {
+            match self {
+                Decision::Allow => f(),
+                deny => deny,
+            }
+        }
+    }
+
+    /// The error type which will be returned when the guard rejects the request.
+    #[derive(Debug, Fail)]
+    pub enum ClientCertError {
+        #[fail(display = "the client certificate is not presented")]
+        Missing,
+
+        #[fail(display = "the client certificate is rejected: {}", reason)]
+        Rejected { reason: Cow<'static, str> },
+    }
+
+    impl HttpError for ClientCertError {
+        type Body = String;
+
+        fn into_response(self, _: &Request<()>) -> Response<Self::Body> {
+            Response::builder()
+                .status(StatusCode::FORBIDDEN)
+                .body(self.to_string())
+                .expect("should be a valid response")
+        }
+    }
+
+    /// A `ModifyHandler` that authorizes the requests by the client certificate.
+    #[derive(Debug)]
+    pub struct ClientCert<F> {
+        inner: Arc<Guard<F>>,
+        label: Cow<'static, str>,
+    }
+
+    #[derive(Debug)]
+    struct Guard<F> {
+        f: F,
+    }
+
+    impl<F> ClientCert<F>
+    where
+        F: Fn(&[Vec<u8>]) -> Decision,
+    {
+        pub(super) fn new(f: F) -> Self {
+            Self {
+                inner: Arc::new(Guard { f }),
+                label: "client_cert".into(),
+            }
+        }
+
+        /// Sets the label which identifies this guard in the outcome of the preflight.
+        ///
+        /// The default label is `"client_cert"`.
+        pub fn label(self, label: impl Into<Cow<'static, str>>) -> Self {
+            Self {
+                label: label.into(),
+                ..self
+            }
+        }
+    }
+
+    impl<F> Guard<F>
+    where
+        F: Fn(&[Vec<u8>]) -> Decision,
+    {
+        fn authorize(&self, request: &Request<()>) -> Result<(), ClientCertError> {
+            let certs = request
+                .extensions()
+                .get::<PeerCertificates>()
+                .filter(|certs| !certs.chain().is_empty())
+                .ok_or(ClientCertError::Missing)?;
+
+            match (self.f)(certs.chain()) {
+                Decision::Allow => Ok(()),
+                Decision::Deny(reason) => Err(ClientCertError::Rejected { reason }),
+            }
+        }
+    }
+
+    impl<F, H> ModifyHandler<H> for ClientCert<F>
+    where
+        F: Fn(&[Vec<u8>]) -> Decision,
+        H: Handler,
+    {
+        type Output = H::Output;
+        type Handler = Recorded<ClientCertHandler<H, F>>; // private;
+
+        fn modify(&self, inner: H) -> Self::Handler {
+            Recorded::new(
+                ClientCertHandler {
+                    inner,
+                    guard: self.inner.clone(),
+                },
+                self.label.clone(),
+            )
+        }
+
+        fn participates_in_preflight(&self) -> bool {
+            true
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct ClientCertHandler<H, F> {
+        inner: H,
+        guard: Arc<Guard<F>>,
+    }
+
+    impl<H, F> Handler for ClientCertHandler<H, F>
+    where
+        H: Handler,
+        F: Fn(&[Vec<u8>]) -> Decision,
+    {
+        type Output = H::Output;
+        type Error = Error;
+        type Handle = HandleClientCert<H::Handle, F>;
+
+        fn allowed_methods(&self) -> Option<&AllowedMethods> {
+            self.inner.allowed_methods()
+        }
+
+        fn metadata(&self) -> &Metadata {
+            self.inner.metadata()
+        }
+
+        fn handle(&self) -> Self::Handle {
+            HandleClientCert {
+                inner: self.inner.handle(),
+                guard: self.guard.clone(),
+                authorized: false,
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct HandleClientCert<H, F> {
+        inner: H,
+        guard: Arc<Guard<F>>,
+        authorized: bool,
+    }
+
+    impl<H, F> TryFuture for HandleClientCert<H, F>
+    where
+        H: TryFuture,
+        F: Fn(&[Vec<u8>]) -> Decision,
+    {
+        type Ok = H::Ok;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if !self.authorized {
+                self.guard.authorize(input.request)?;
+                self.authorized = true;
+            }
+            self.inner.poll_ready(input).map_err(Into::into)
+        }
+    }
+}
+
+#[cfg(feature = "x509")]
+pub mod x509;
@@ -0,0 +1,219 @@
+//! Helper functions for inspecting the certificate chain, used with `client_cert`
+//! or `extractor::tls::peer_certificates`.
+//!
+//! The certificates are decoded by `x509-parser`.  Note that the signatures are
+//! verified by the TLS layer, not here.
+//!
+//! This module is available only if the feature `x509` is enabled.
+
+use {
+    super::Decision,
+    x509_parser::{
+        certificate::X509Certificate,
+        extensions::GeneralName,
+        objects::oid_registry,
+        oid_registry::{
+            OID_DOMAIN_COMPONENT, OID_PKCS9_EMAIL_ADDRESS, OID_X509_COMMON_NAME,
+            OID_X509_COUNTRY_NAME, OID_X509_LOCALITY_NAME, OID_X509_ORGANIZATIONAL_UNIT,
+            OID_X509_ORGANIZATION_NAME, OID_X509_STATE_OR_PROVINCE_NAME,
+        },
+        x509::X509Name,
+    },
+};
+
+fn parse(der: &[u8]) -> Option<X509Certificate<'_>> {
+    x509_parser::parse_x509_certificate(der)
+        .ok()
+        .map(|(_, cert)| cert)
+}
+
+fn with_leaf(chain: &[Vec<u8>], f: impl FnOnce(&X509Certificate<'_>) -> Decision) -> Decision {
+    let leaf = match chain.first() {
+        Some(leaf) => leaf,
+        None => return Decision::deny("empty certificate chain"),
+    };
+    match parse(leaf) {
+        Some(cert) => f(&cert),
+        None => Decision::deny("malformed certificate"),
+    }
+}
+
+fn dns_names_of<'a>(cert: &'a X509Certificate<'_>) -> impl Iterator<Item = &'a str> + 'a {
+    cert.subject_alternative_name()
+        .ok()
+        .and_then(|ext| ext)
+        .into_iter()
+        .flat_map(|ext| ext.value.general_names.iter())
+        .filter_map(|name| match name {
+            GeneralName::DNSName(name) => Some(*name),
+            _ => None,
+        })
+}
+
+/// Checks if the common name or a DNS name in the subject alternative names
+/// of the end-entity certificate is contained in `names`.
+pub fn subject_name_in<S>(chain: &[Vec<u8>], names: &[S]) -> Decision
+where
+    S: AsRef<str>,
+{
+    with_leaf(chain, |cert| {
+        let contains = |name: &str| names.iter().any(|n| n.as_ref() == name);
+        let common_names = cert
+            .subject()
+            .iter_common_name()
+            .filter_map(|cn| cn.as_str().ok());
+        if common_names.chain(dns_names_of(cert)).any(contains) {
+            Decision::Allow
+        } else {
+            Decision::deny("the subject name is not allowed")
+        }
+    })
+}
+
+/// Checks if the issuer of the end-entity certificate matches the specified
+/// distinguished name (e.g. `"CN=Example CA, O=Example"`).
+///
+/// The relative distinguished names are listed in the order of the certificate,
+/// in the same format as `subject`, and the multi-valued ones are joined by `+`.
+/// The attribute types are either the abbreviations (`CN`, `C`, `L`, `ST`, `O`,
+/// `OU`, `DC` and `Email`) or dotted-decimal OIDs.
+///
+/// The names are compared attribute by attribute rather than as strings: the
+/// attribute types are compared by OID, and the values are compared ignoring case
+/// and the redundant white spaces.
+pub fn issuer_is(chain: &[Vec<u8>], issuer: &str) -> Decision {
+    let expected = match parse_name(issuer) {
+        Some(expected) => expected,
+        None => return Decision::deny("invalid distinguished name of the issuer"),
+    };
+    with_leaf(chain, |cert| match decompose(cert.issuer()) {
+        Some(ref actual) if *actual == expected => Decision::Allow,
+        _ => Decision::deny("the certificate is issued by an unexpected authority"),
+    })
+}
+
+/// Returns the distinguished name of the subject of the end-entity certificate
+/// (e.g. `"CN=client.example, O=Example"`), or `None` if the chain is empty or
+/// malformed.
+pub fn subject(chain: &[Vec<u8>]) -> Option<String> {
+    let cert = parse(chain.first()?)?;
+    cert.subject().to_string_with_registry(oid_registry()).ok()
+}
+
+/// Returns the DNS names in the subject alternative names of the end-entity
+/// certificate.
+///
+/// The returned list is empty if the chain is empty or malformed.
+pub fn dns_names(chain: &[Vec<u8>]) -> Vec<String> {
+    chain
+        .first()
+        .and_then(|leaf| parse(leaf))
+        .map(|cert| dns_names_of(&cert).map(ToOwned::to_owned).collect())
+        .unwrap_or_default()
+}
+
+/// Checks if all certificates in the chain are within their validity periods.
+pub fn within_validity(chain: &[Vec<u8>]) -> Decision {
+    if chain.is_empty() {
+        return Decision::deny("empty certificate chain");
+    }
+    for der in chain {
+        match parse(der) {
+            Some(ref cert) if cert.validity().is_valid() => {}
+            Some(..) => return Decision::deny("the certificate is out of its validity period"),
+            None => return Decision::deny("malformed certificate"),
+        }
+    }
+    Decision::Allow
+}
+
+// ==== structured comparison of the distinguished names ====
+
+/// A distinguished name decomposed into the relative distinguished names, each of
+/// which is a sorted list of the pairs of the dotted-decimal OID and the normalized value.
+type Rdns = Vec<Vec<(String, String)>>;
+
+fn decompose(name: &X509Name<'_>) -> Option<Rdns> {
+    name.iter()
+        .map(|rdn| {
+            let mut attrs = rdn
+                .iter()
+                .map(|attr| {
+                    let value = attr.as_str().ok()?;
+                    Some((attr.attr_type().to_id_string(), normalize(value)))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            attrs.sort();
+            Some(attrs)
+        })
+        .collect()
+}
+
+fn parse_name(name: &str) -> Option<Rdns> {
+    split_unescaped(name, ',')
+        .into_iter()
+        .map(|rdn| {
+            let mut attrs = split_unescaped(&rdn, '+')
+                .into_iter()
+                .map(|attr| {
+                    let pos = attr.find('=')?;
+                    let oid = attribute_oid(attr[..pos].trim())?;
+                    Some((oid, normalize(&attr[pos + 1..])))
+                })
+                .collect::<Option<Vec<_>>>()?;
+            attrs.sort();
+            Some(attrs)
+        })
+        .collect()
+}
+
+/// Splits `s` by `sep`, except the ones escaped by backslashes.
+///
+/// The escaping backslashes are removed from the returned strings.
+fn split_unescaped(s: &str, sep: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                if let Some(c) = chars.next() {
+                    if c != sep {
+                        parts.last_mut().unwrap().push('\\');
+                    }
+                    parts.last_mut().unwrap().push(c);
+                }
+            }
+            c if c == sep => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+fn attribute_oid(name: &str) -> Option<String> {
+    if !name.is_empty() && name.bytes().all(|b| b.is_ascii_digit() || b == b'.') {
+        return Some(name.to_owned());
+    }
+    let oid = match &*name.to_ascii_uppercase() {
+        "CN" => OID_X509_COMMON_NAME,
+        "C" => OID_X509_COUNTRY_NAME,
+        "L" => OID_X509_LOCALITY_NAME,
+        "ST" => OID_X509_STATE_OR_PROVINCE_NAME,
+        "O" => OID_X509_ORGANIZATION_NAME,
+        "OU" => OID_X509_ORGANIZATIONAL_UNIT,
+        "DC" => OID_DOMAIN_COMPONENT,
+        "EMAIL" | "EMAILADDRESS" => OID_PKCS9_EMAIL_ADDRESS,
+        _ => return None,
+    };
+    Some(oid.to_id_string())
+}
+
+/// Normalizes an attribute value for the case-insensitive comparison, in the manner
+/// of `caseIgnoreMatch` in RFC 4518 (without the Unicode normalization).
+fn normalize(value: &str) -> String {
+    value
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
@@ -2,14 +2,22 @@
 
 use {
     crate::{
+        app::{Preflight, PreflightTarget, ScopeConfigs},
         error::Error,
-        future::TryFuture,
+        future::{Poll, TryFuture},
+        input::Input,
         util::{Chain, Never, TryFrom}, //
     },
     http::{header::HeaderValue, HttpTryFrom, Method},
     indexmap::{indexset, IndexSet},
     lazy_static::lazy_static,
-    std::{iter::FromIterator, sync::Arc},
+    std::{
+        any::{Any, TypeId},
+        collections::HashMap,
+        fmt,
+        iter::FromIterator,
+        sync::Arc,
+    },
 };
 
 /// A set of request methods that a route accepts.
@@ -131,6 +139,104 @@ impl<'a> IntoIterator for &'a AllowedMethods {
     }
 }
 
+/// A type map that holds the values attached to a route by `Route::extension`.
+///
+/// The values are keyed by their types, and thus the modifiers should define a
+/// dedicated type (e.g. `struct Public;`) for each kind of value in order to avoid
+/// colliding with others.  The values are shared by `Arc`, and cloning the map is cheap.
+///
+/// The metadata of the matched route also carries the effective configuration values
+/// of its scope, which are accessible by `Metadata::config`.
+#[derive(Clone, Default)]
+pub struct Metadata {
+    inner: Option<Arc<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>>,
+    configs: Option<ScopeConfigs>,
+}
+
+impl fmt::Debug for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Metadata")
+            .field("len", &self.len())
+            .field("configs", &self.configs)
+            .finish()
+    }
+}
+
+impl Metadata {
+    /// Returns a reference to the empty `Metadata`.
+    pub fn empty() -> &'static Metadata {
+        static EMPTY: Metadata = Metadata {
+            inner: None,
+            configs: None,
+        };
+        &EMPTY
+    }
+
+    /// Returns the number of attached values.
+    pub fn len(&self) -> usize {
+        self.inner.as_ref().map_or(0, |inner| inner.len())
+    }
+
+    /// Returns `true` if no values are attached.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Attaches a value, replacing the existing value of the same type.
+    pub fn insert<T>(&mut self, value: T)
+    where
+        T: Send + Sync + 'static,
+    {
+        Arc::make_mut(self.inner.get_or_insert_with(Default::default))
+            .insert(TypeId::of::<T>(), Arc::new(value));
+    }
+
+    /// Returns a reference to the value of the specified type, if attached.
+    pub fn get<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.inner
+            .as_ref()?
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+    }
+
+    /// Returns `true` if a value of the specified type is attached.
+    pub fn contains<T>(&self) -> bool
+    where
+        T: Send + Sync + 'static,
+    {
+        self.get::<T>().is_some()
+    }
+
+    /// Returns a reference to the effective value of the specified type registered by
+    /// `Scope::config` in the scope of the route or its ancestors.
+    ///
+    /// The configuration values are resolved when the application is built, and hence
+    /// they are not available from the handlers being configured (e.g. in
+    /// `ModifyHandler::modify`), but only from the metadata of the matched route
+    /// obtained by `Input::metadata`.
+    pub fn config<T>(&self) -> Option<&T>
+    where
+        T: Send + Sync + 'static,
+    {
+        self.configs.as_ref()?.find_ref()
+    }
+
+    pub(crate) fn has_configs(&self) -> bool {
+        self.configs.is_some()
+    }
+
+    pub(crate) fn set_configs(&mut self, configs: &ScopeConfigs) {
+        self.configs = if configs.is_empty() {
+            None
+        } else {
+            Some(configs.clone())
+        };
+    }
+}
+
 /// A trait representing the handler associated with the specified endpoint.
 pub trait Handler {
     type Output;
@@ -142,6 +248,15 @@ pub trait Handler {
     /// If it returns a `None`, it means that the handler accepts *all* methods.
     fn allowed_methods(&self) -> Option<&AllowedMethods>;
 
+    /// Returns the values attached to the route by `Route::extension`.
+    ///
+    /// The handlers wrapping another handler (e.g. those created by the modifiers)
+    /// should forward this method to the inner handler.
+    #[inline]
+    fn metadata(&self) -> &Metadata {
+        Metadata::empty()
+    }
+
     /// Creates a `Handle` which handles the incoming request.
     fn handle(&self) -> Self::Handle;
 }
@@ -159,6 +274,11 @@ where
         (**self).allowed_methods()
     }
 
+    #[inline]
+    fn metadata(&self) -> &Metadata {
+        (**self).metadata()
+    }
+
     #[inline]
     fn handle(&self) -> Self::Handle {
         (**self).handle()
@@ -178,12 +298,92 @@ where
         (**self).allowed_methods()
     }
 
+    #[inline]
+    fn metadata(&self) -> &Metadata {
+        (**self).metadata()
+    }
+
     #[inline]
     fn handle(&self) -> Self::Handle {
         (**self).handle()
     }
 }
 
+/// A `Handler` that attaches the values set by `Route::extension` to the inner handler.
+///
+/// The inner handler is not called while the request is evaluated by the authorization
+/// preflight, if the route is marked by `Route::preflight`.
+#[derive(Debug)]
+pub struct WithMetadata<H> {
+    inner: H,
+    metadata: Metadata,
+    preflight: bool,
+}
+
+impl<H> WithMetadata<H>
+where
+    H: Handler,
+{
+    pub(crate) fn new(inner: H, metadata: Metadata) -> Self {
+        Self {
+            inner,
+            preflight: PreflightTarget::is_attached(&metadata),
+            metadata,
+        }
+    }
+}
+
+impl<H> Handler for WithMetadata<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = Error;
+    type Handle = HandleWithMetadata<H::Handle>;
+
+    #[inline]
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    #[inline]
+    fn metadata(&self) -> &Metadata {
+        &self.metadata
+    }
+
+    #[inline]
+    fn handle(&self) -> Self::Handle {
+        HandleWithMetadata {
+            inner: self.inner.handle(),
+            preflight: self.preflight,
+        }
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct HandleWithMetadata<Fut> {
+    inner: Fut,
+    preflight: bool,
+}
+
+impl<Fut> TryFuture for HandleWithMetadata<Fut>
+where
+    Fut: TryFuture,
+{
+    type Ok = Fut::Ok;
+    type Error = Error;
+
+    #[inline]
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if self.preflight {
+            Preflight::stop(input.locals)?;
+            self.preflight = false;
+        }
+        self.inner.poll_ready(input).map_err(Into::into)
+    }
+}
+
 pub fn handler<T>(
     handle_fn: impl Fn() -> T,
     allowed_methods: Option<AllowedMethods>,
@@ -233,6 +433,17 @@ pub trait ModifyHandler<H: Handler> {
     type Handler: Handler<Output = Self::Output>;
 
     fn modify(&self, input: H) -> Self::Handler;
+
+    /// Returns whether the handlers modified by this value may be executed by the
+    /// authorization preflight performed by `App::authorize`.
+    ///
+    /// The routes marked by `Route::preflight` can only be modified by the modifiers
+    /// that take part in the preflight, so that the middleware that reads the request
+    /// body or causes side effects is never executed while a request is evaluated.
+    #[inline]
+    fn participates_in_preflight(&self) -> bool {
+        false
+    }
 }
 
 #[doc(hidden)]
@@ -284,6 +495,11 @@ where
     fn modify(&self, input: H) -> Self::Handler {
         (**self).modify(input)
     }
+
+    #[inline]
+    fn participates_in_preflight(&self) -> bool {
+        (**self).participates_in_preflight()
+    }
 }
 
 impl<M, H> ModifyHandler<H> for std::rc::Rc<M>
@@ -298,6 +514,11 @@ where
     fn modify(&self, input: H) -> Self::Handler {
         (**self).modify(input)
     }
+
+    #[inline]
+    fn participates_in_preflight(&self) -> bool {
+        (**self).participates_in_preflight()
+    }
 }
 
 impl<M, H> ModifyHandler<H> for std::sync::Arc<M>
@@ -312,6 +533,11 @@ where
     fn modify(&self, input: H) -> Self::Handler {
         (**self).modify(input)
     }
+
+    #[inline]
+    fn participates_in_preflight(&self) -> bool {
+        (**self).participates_in_preflight()
+    }
 }
 
 impl<H> ModifyHandler<H> for ()
@@ -325,6 +551,11 @@ where
     fn modify(&self, input: H) -> Self::Handler {
         input
     }
+
+    #[inline]
+    fn participates_in_preflight(&self) -> bool {
+        true
+    }
 }
 
 impl<I, O, H> ModifyHandler<H> for Chain<I, O>
@@ -340,4 +571,9 @@ where
     fn modify(&self, input: H) -> Self::Handler {
         self.right.modify(self.left.modify(input))
     }
+
+    #[inline]
+    fn participates_in_preflight(&self) -> bool {
+        self.left.participates_in_preflight() && self.right.participates_in_preflight()
+    }
 }
@@ -6,10 +6,14 @@ pub mod localmap;
 pub mod param;
 
 use {
-    self::{localmap::LocalMap, param::Params},
+    self::{
+        localmap::{LocalData, LocalMap},
+        param::Params,
+    },
+    crate::handler::Metadata,
     cookie::{Cookie, CookieJar},
-    http::{header::HeaderMap, Request},
-    std::{marker::PhantomData, rc::Rc},
+    http::{header::HeaderMap, Method, Request},
+    std::{marker::PhantomData, rc::Rc, sync::Arc},
 };
 
 /// A proxy object for accessing the incoming HTTP request data.
@@ -33,6 +37,63 @@ pub struct Input<'task> {
     pub(crate) _marker: PhantomData<Rc<()>>,
 }
 
+impl<'task> Input<'task> {
+    /// Returns the method of request before overridden by `config::MethodOverride`.
+    ///
+    /// If the method is not overridden, it is the same as `request.method()`.
+    pub fn original_method(&self) -> &Method {
+        match crate::app::OriginalMethod::get(self.locals) {
+            Some(original) => &original.0,
+            None => self.request.method(),
+        }
+    }
+
+    /// Returns the path of request as received, before normalized by
+    /// `config::PathNormalization` and stripped by `config::MountPoint`.
+    ///
+    /// If the path is not rewritten, it is the same as `request.uri().path()`,
+    /// which is the effective path used for routing.
+    pub fn raw_path(&self) -> &str {
+        match self.request.extensions().get::<crate::app::RawPath>() {
+            Some(raw_path) => &raw_path.0,
+            None => self.request.uri().path(),
+        }
+    }
+
+    /// Returns the prefix added to the URIs generated by the application.
+    ///
+    /// The prefix consists of the value of `X-Forwarded-Prefix`, if trusted, and the
+    /// mount point set by `config::MountPoint`.  It is empty if the mount point is not set.
+    pub fn mount_prefix(&self) -> &str {
+        match self.request.extensions().get::<crate::app::Mounted>() {
+            Some(mounted) => &mounted.prefix,
+            None => "",
+        }
+    }
+
+    /// Returns the values attached to the matched route by `Route::extension`, along
+    /// with the configuration values of its scope.
+    ///
+    /// It is empty if no route is matched, e.g. in the fallback of scope.
+    pub fn metadata(&self) -> &Metadata {
+        match crate::app::RouteMetadata::get(self.locals) {
+            Some(metadata) => &metadata.0,
+            None => Metadata::empty(),
+        }
+    }
+
+    /// Returns the shared value of the specified type registered in the matched scope.
+    ///
+    /// The value registered in the nearest scope from the matched one is returned, and
+    /// the values registered in the sibling scopes are never visible.
+    pub fn state<T>(&self) -> Option<Arc<T>>
+    where
+        T: Send + Sync + 'static,
+    {
+        crate::app::StateMap::get(self.locals)?.find::<T>()
+    }
+}
+
 /// A proxy object for accessing Cookie values.
 #[derive(Debug)]
 pub struct Cookies<'task> {
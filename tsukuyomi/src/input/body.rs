@@ -47,6 +47,12 @@ impl From<Body> for RequestBody {
     }
 }
 
+impl From<Bytes> for RequestBody {
+    fn from(data: Bytes) -> Self {
+        RequestBody(Body::from(data))
+    }
+}
+
 impl Payload for RequestBody {
     type Data = hyper::Chunk;
     type Error = hyper::Error;
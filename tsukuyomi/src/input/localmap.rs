@@ -61,6 +61,11 @@ impl<T: Send + 'static> LocalKey<T> {
 }
 
 /// A trait representing a data to be stored in `LocalMap`s.
+///
+/// The key is declared with the `local_key!` macro, so that the values of different
+/// types never collide.  The stored value lives for the duration of a single request
+/// and can be accessed through `Input::locals` from the modifiers, the extractors
+/// (see also `extractor::local`) and the `Responder`s.
 pub trait LocalData: Sized + Send + 'static {
     /// The value of `LocalKey` associated with this type.
     const KEY: LocalKey<Self>;
@@ -2,6 +2,7 @@ use {
     crate::{app::Captures, uri::CaptureNames},
     std::borrow::Cow,
     std::ops::Index,
+    std::path::PathBuf,
     std::str::Utf8Error,
     url::percent_encoding::percent_decode,
 };
@@ -59,6 +60,7 @@ impl<'input, 'a> Index<&'a str> for Params<'input> {
     }
 }
 
+/// A percent-encoded string slice extracted from the request path.
 #[derive(Debug)]
 #[repr(C)]
 pub struct PercentEncoded(str);
@@ -68,6 +70,11 @@ impl PercentEncoded {
         &*(s as *const str as *const Self)
     }
 
+    /// Returns the raw string without decoding.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
     pub fn decode_utf8(&self) -> Result<Cow<'_, str>, Utf8Error> {
         percent_decode(self.0.as_bytes()).decode_utf8()
     }
@@ -77,6 +84,11 @@ impl PercentEncoded {
     }
 }
 
+/// A trait for converting the path parameters into values.
+///
+/// This trait is used by `path!` and the extractors in `extractor::param`.
+/// If the conversion fails, the route is treated as unmatched and the request
+/// is rejected with `404 Not Found` by the default implementations.
 pub trait FromPercentEncoded: Sized {
     type Error: Into<crate::Error>;
 
@@ -91,9 +103,9 @@ macro_rules! impl_from_percent_encoded {
             #[inline]
             fn from_percent_encoded(s: &PercentEncoded) -> Result<Self, Self::Error> {
                 s.decode_utf8()
-                    .map_err(crate::error::bad_request)?
+                    .map_err(crate::error::not_found)?
                     .parse()
-                    .map_err(crate::error::bad_request)
+                    .map_err(crate::error::not_found)
             }
         }
     )*};
@@ -113,13 +125,26 @@ impl_from_percent_encoded!(
     uuid::Uuid
 );
 
-impl FromPercentEncoded for std::path::PathBuf {
+/// Rejects the paths that may point outside of the base directory,
+/// e.g. absolute paths or those containing `..`.
+///
+/// The decoded path is sanitized by the same rules as `fs::resolve_path`, and
+/// can be passed to `fs::SafePath::join`.
+impl FromPercentEncoded for PathBuf {
     type Error = crate::Error;
 
+    fn from_percent_encoded(s: &PercentEncoded) -> Result<Self, Self::Error> {
+        let path = s.decode_utf8().map_err(crate::error::not_found)?;
+        crate::fs::sanitize_path(&path)
+    }
+}
+
+/// Returns the raw bytes without decoding.
+impl FromPercentEncoded for bytes::Bytes {
+    type Error = crate::util::Never;
+
     #[inline]
     fn from_percent_encoded(s: &PercentEncoded) -> Result<Self, Self::Error> {
-        s.decode_utf8()
-            .map(|s| Self::from(s.into_owned()))
-            .map_err(crate::error::bad_request)
+        Ok(Self::from(s.as_str()))
     }
 }
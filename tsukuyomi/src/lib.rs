@@ -14,21 +14,25 @@
 #[macro_use]
 pub mod util;
 
+mod etag;
 mod generic;
 mod uri;
 
 pub mod app;
 pub mod config;
+pub mod contrib;
 pub mod endpoint;
 pub mod error;
 pub mod extractor;
 pub mod fs;
 pub mod future;
+pub mod guard;
 pub mod handler;
 pub mod input;
 pub mod modifiers;
 pub mod output;
 pub mod responder;
+pub mod test;
 
 #[doc(inline)]
 pub use crate::{
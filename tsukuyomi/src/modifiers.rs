@@ -1,6 +1,11 @@
 //! A set of built-in `ModifyHandler`s.
 
-pub use self::{default_options::DefaultOptions, map_output::MapOutput};
+pub use self::{
+    default_options::DefaultOptions,
+    map_output::MapOutput,
+    sampling::{Diagnostics, Report, SampleRate, Sampling},
+    transform_body::TransformBody,
+};
 
 /// Creates a `ModifyHandler` that overwrites the handling when receiving `OPTIONS`.
 pub fn default_options() -> DefaultOptions {
@@ -11,7 +16,7 @@ mod default_options {
     use {
         crate::{
             future::{Poll, TryFuture},
-            handler::{AllowedMethods, Handler, ModifyHandler},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
             input::Input,
         },
         either::Either,
@@ -64,6 +69,10 @@ mod default_options {
         fn allowed_methods(&self) -> Option<&AllowedMethods> {
             self.allowed_methods.as_ref()
         }
+
+        fn metadata(&self) -> &Metadata {
+            self.inner.metadata()
+        }
     }
 
     #[allow(missing_debug_implementations)]
@@ -111,7 +120,7 @@ pub fn map_output<F>(f: F) -> MapOutput<F> {
 mod map_output {
     use crate::{
         future::{Poll, TryFuture},
-        handler::{AllowedMethods, Handler, ModifyHandler},
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
         input::Input,
     };
 
@@ -161,6 +170,10 @@ mod map_output {
         fn allowed_methods(&self) -> Option<&AllowedMethods> {
             self.handler.allowed_methods()
         }
+
+        fn metadata(&self) -> &Metadata {
+            self.handler.metadata()
+        }
     }
 
     #[allow(missing_debug_implementations)]
@@ -186,3 +199,651 @@ mod map_output {
         }
     }
 }
+
+/// Creates a `ModifyHandler` that rewrites the bodies of the responses with the specified
+/// media type, by using a [`ChunkTransformer`] created by `f` for each response.
+///
+/// The parameters of the media type (e.g. `charset`) are ignored when comparing with the
+/// value of `Content-Type`.  The responses marked as non-transformable
+/// (see `output::is_transformable`) or encoded with `Content-Encoding` are passed through.
+/// Since the length of the rewritten body is not known in advance, the header field
+/// `Content-Length` is removed from the transformed responses.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, modifiers, output::{html, transform}, App};
+/// let app = App::create(
+///     path!("/")
+///         .to(endpoint::call(|| html("<body><p>Hello</p></body>")))
+///         .modify(modifiers::transform_body(mime::TEXT_HTML, || {
+///             transform::replace("<body>", "<body><div class=\"banner\">Preview</div>")
+///         })),
+/// );
+/// # app.unwrap();
+/// ```
+///
+/// [`ChunkTransformer`]: ../output/transform/trait.ChunkTransformer.html
+pub fn transform_body<F, T>(media_type: mime::Mime, f: F) -> TransformBody<F>
+where
+    F: Fn() -> T + Clone,
+    T: crate::output::transform::ChunkTransformer,
+{
+    TransformBody { media_type, f }
+}
+
+mod transform_body {
+    use {
+        crate::{
+            error::Error,
+            future::{Async, Poll, TryFuture},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+            input::Input,
+            output::{is_transformable, transform::ChunkTransformer, IntoResponse, ResponseBody},
+            responder::Responder,
+        },
+        http::{
+            header::{CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE},
+            Response,
+        },
+    };
+
+    #[derive(Debug, Clone)]
+    pub struct TransformBody<F> {
+        pub(super) media_type: mime::Mime,
+        pub(super) f: F,
+    }
+
+    impl<H, F, T> ModifyHandler<H> for TransformBody<F>
+    where
+        H: Handler,
+        H::Output: Responder,
+        F: Fn() -> T + Clone,
+        T: ChunkTransformer,
+    {
+        type Output = Response<ResponseBody>;
+        type Handler = TransformBodyHandler<H, F>; // private
+
+        fn modify(&self, inner: H) -> Self::Handler {
+            TransformBodyHandler {
+                inner,
+                media_type: self.media_type.clone(),
+                f: self.f.clone(),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct TransformBodyHandler<H, F> {
+        inner: H,
+        media_type: mime::Mime,
+        f: F,
+    }
+
+    impl<H, F, T> Handler for TransformBodyHandler<H, F>
+    where
+        H: Handler,
+        H::Output: Responder,
+        F: Fn() -> T + Clone,
+        T: ChunkTransformer,
+    {
+        type Output = Response<ResponseBody>;
+        type Error = Error;
+        type Handle = HandleTransformBody<H::Handle, F>;
+
+        fn handle(&self) -> Self::Handle {
+            HandleTransformBody {
+                state: State::First(self.inner.handle()),
+                media_type: self.media_type.clone(),
+                f: self.f.clone(),
+            }
+        }
+
+        fn allowed_methods(&self) -> Option<&AllowedMethods> {
+            self.inner.allowed_methods()
+        }
+
+        fn metadata(&self) -> &Metadata {
+            self.inner.metadata()
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct HandleTransformBody<H, F>
+    where
+        H: TryFuture,
+        H::Ok: Responder,
+    {
+        state: State<H, <H::Ok as Responder>::Respond>,
+        media_type: mime::Mime,
+        f: F,
+    }
+
+    enum State<A, B> {
+        First(A),
+        Second(B),
+    }
+
+    impl<H, F, T> TryFuture for HandleTransformBody<H, F>
+    where
+        H: TryFuture,
+        H::Ok: Responder,
+        F: Fn() -> T,
+        T: ChunkTransformer,
+    {
+        type Ok = Response<ResponseBody>;
+        type Error = Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let response = loop {
+                self.state = match self.state {
+                    State::First(ref mut handle) => {
+                        let output =
+                            futures01::try_ready!(handle.poll_ready(input).map_err(Into::into));
+                        State::Second(output.respond())
+                    }
+                    State::Second(ref mut respond) => {
+                        break futures01::try_ready!(respond.poll_ready(input).map_err(Into::into))
+                            .respond_to(input)
+                            .map_err(Into::into)?
+                            .map(Into::into);
+                    }
+                };
+            };
+
+            if !self.matches(&response) {
+                return Ok(Async::Ready(response));
+            }
+
+            let (mut parts, body) = response.into_parts();
+            parts.headers.remove(CONTENT_LENGTH);
+            let body = body.map_chunks((self.f)());
+            Ok(Async::Ready(Response::from_parts(parts, body)))
+        }
+    }
+
+    impl<H, F> HandleTransformBody<H, F>
+    where
+        H: TryFuture,
+        H::Ok: Responder,
+    {
+        fn matches(&self, response: &Response<ResponseBody>) -> bool {
+            if !is_transformable(response) || response.headers().contains_key(CONTENT_ENCODING) {
+                return false;
+            }
+            let content_type = match response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|h| h.to_str().ok())
+                .and_then(|s| s.parse::<mime::Mime>().ok())
+            {
+                Some(content_type) => content_type,
+                None => return false,
+            };
+            content_type.type_() == self.media_type.type_()
+                && content_type.subtype() == self.media_type.subtype()
+        }
+    }
+}
+
+/// Creates a `ModifyHandler` that captures verbose diagnostics for a sampled
+/// fraction of the incoming requests.
+///
+/// The sampling decision is made from the 64-bit FNV-1a hash of the request ID (the
+/// header field `X-Request-Id` by default), so that retries of the same request are
+/// co-sampled even if they are routed to the different server instances.
+/// When a request is sampled, a value of [`Diagnostics`] is stored in the request-local
+/// data so that downstream components can enable their own verbose paths.
+///
+/// [`Diagnostics`]: ./struct.Diagnostics.html
+pub fn sampling(rate: SampleRate) -> Sampling {
+    Sampling {
+        rate,
+        request_id_header: http::header::HeaderName::from_static("x-request-id"),
+        body_limit: self::sampling::DEFAULT_BODY_LIMIT,
+        reporter: None,
+    }
+}
+
+mod sampling {
+    use {
+        crate::{
+            etag::{fnv1a, FNV_OFFSET_BASIS},
+            future::{Async, Poll, TryFuture},
+            handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+            input::{
+                body::RequestBody,
+                localmap::{local_key, LocalData},
+                Input,
+            },
+        },
+        bytes::{Bytes, BytesMut},
+        futures01::Stream,
+        http::{header::HeaderName, Method, Uri},
+        std::{
+            fmt,
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc, Mutex, MutexGuard, PoisonError,
+            },
+            time::{Duration, Instant},
+        },
+    };
+
+    type Reporter = Arc<dyn Fn(&Report) + Send + Sync + 'static>;
+
+    pub(super) const DEFAULT_BODY_LIMIT: usize = 64 * 1024;
+
+    const ONE_MILLION: usize = 1_000_000;
+
+    /// A sampling rate which can be adjusted at runtime.
+    ///
+    /// The value is shared among the clones, so that the rate can be changed from
+    /// another place (e.g. an admin endpoint) after the application has been built.
+    #[derive(Debug, Clone)]
+    pub struct SampleRate {
+        per_million: Arc<AtomicUsize>,
+        counter: Arc<AtomicUsize>,
+    }
+
+    impl SampleRate {
+        fn new(per_million: usize) -> Self {
+            Self {
+                per_million: Arc::new(AtomicUsize::new(per_million)),
+                counter: Arc::new(AtomicUsize::new(0)),
+            }
+        }
+
+        /// Creates a `SampleRate` that never samples the requests.
+        pub fn never() -> Self {
+            Self::new(0)
+        }
+
+        /// Creates a `SampleRate` that samples all of the requests.
+        pub fn always() -> Self {
+            Self::new(ONE_MILLION)
+        }
+
+        /// Creates a `SampleRate` that samples one in `n` requests.
+        ///
+        /// If `n` is zero, the requests are never sampled.
+        pub fn one_in(n: usize) -> Self {
+            let rate = Self::never();
+            rate.set_one_in(n);
+            rate
+        }
+
+        /// Changes the rate to one in `n` requests.
+        ///
+        /// If `n` is zero, the requests are never sampled.
+        pub fn set_one_in(&self, n: usize) {
+            self.set_per_million(ONE_MILLION.checked_div(n).unwrap_or(0));
+        }
+
+        /// Changes the rate in units of one per million.
+        pub fn set_per_million(&self, per_million: usize) {
+            self.per_million
+                .store(std::cmp::min(per_million, ONE_MILLION), Ordering::SeqCst);
+        }
+
+        /// Returns the current rate in units of one per million.
+        pub fn per_million(&self) -> usize {
+            self.per_million.load(Ordering::SeqCst)
+        }
+
+        fn sample(&self, request_id: Option<&[u8]>) -> bool {
+            let per_million = self.per_million();
+            if per_million == 0 {
+                return false;
+            }
+            if per_million >= ONE_MILLION {
+                return true;
+            }
+
+            // The hash function must not vary between the processes, since the
+            // retries of a request may be handled by another server instance.
+            let hash = match request_id {
+                Some(id) => fnv1a(FNV_OFFSET_BASIS, id),
+                None => {
+                    let count = self.counter.fetch_add(1, Ordering::SeqCst) as u64;
+                    let mut bytes = [0u8; 8];
+                    for (i, b) in bytes.iter_mut().enumerate() {
+                        *b = (count >> (i * 8)) as u8;
+                    }
+                    fnv1a(FNV_OFFSET_BASIS, &bytes)
+                }
+            };
+            (hash % ONE_MILLION as u64) < per_million as u64
+        }
+    }
+
+    /// A `ModifyHandler` that captures the verbose diagnostics of sampled requests.
+    #[derive(Clone)]
+    pub struct Sampling {
+        pub(super) rate: SampleRate,
+        pub(super) request_id_header: HeaderName,
+        pub(super) body_limit: usize,
+        pub(super) reporter: Option<Reporter>,
+    }
+
+    impl fmt::Debug for Sampling {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            f.debug_struct("Sampling")
+                .field("rate", &self.rate)
+                .field("request_id_header", &self.request_id_header)
+                .field("body_limit", &self.body_limit)
+                .finish()
+        }
+    }
+
+    impl Sampling {
+        /// Sets the name of header field used as the request ID.
+        pub fn request_id_header(self, name: HeaderName) -> Self {
+            Self {
+                request_id_header: name,
+                ..self
+            }
+        }
+
+        /// Sets the maximum number of bytes of the request body to be captured.
+        ///
+        /// The default value is 64 KiB.
+        pub fn capture_body(self, limit: usize) -> Self {
+            Self {
+                body_limit: limit,
+                ..self
+            }
+        }
+
+        /// Registers a function that receives the report of each sampled request.
+        ///
+        /// Regardless of this setting, the reports are always emitted to the logger.
+        pub fn report<F>(self, f: F) -> Self
+        where
+            F: Fn(&Report) + Send + Sync + 'static,
+        {
+            Self {
+                reporter: Some(Arc::new(f)),
+                ..self
+            }
+        }
+    }
+
+    impl<H> ModifyHandler<H> for Sampling
+    where
+        H: Handler,
+    {
+        type Output = H::Output;
+        type Handler = SamplingHandler<H>; // private
+
+        fn modify(&self, inner: H) -> Self::Handler {
+            SamplingHandler {
+                inner,
+                config: Arc::new(self.clone()),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct SamplingHandler<H> {
+        inner: H,
+        config: Arc<Sampling>,
+    }
+
+    impl<H> Handler for SamplingHandler<H>
+    where
+        H: Handler,
+    {
+        type Output = H::Output;
+        type Error = H::Error;
+        type Handle = HandleSampling<H::Handle>;
+
+        fn allowed_methods(&self) -> Option<&AllowedMethods> {
+            self.inner.allowed_methods()
+        }
+
+        fn metadata(&self) -> &Metadata {
+            self.inner.metadata()
+        }
+
+        fn handle(&self) -> Self::Handle {
+            HandleSampling {
+                inner: self.inner.handle(),
+                config: self.config.clone(),
+                state: State::Init,
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct HandleSampling<H> {
+        inner: H,
+        config: Arc<Sampling>,
+        state: State,
+    }
+
+    enum State {
+        Init,
+        Skipped,
+        Sampled(Recording),
+    }
+
+    struct Recording {
+        method: Method,
+        uri: Uri,
+        diagnostics: Diagnostics,
+        polls: usize,
+    }
+
+    impl<H> HandleSampling<H> {
+        fn start(&mut self, input: &mut Input<'_>) {
+            let request_id = input
+                .request
+                .headers()
+                .get(&self.config.request_id_header)
+                .map(|h| h.as_bytes());
+            if !self.config.rate.sample(request_id) {
+                self.state = State::Skipped;
+                return;
+            }
+
+            let diagnostics = Diagnostics {
+                request_id: request_id.map(|id| String::from_utf8_lossy(id).into_owned()),
+                started: Instant::now(),
+                body: Arc::new(Mutex::new(CapturedBody {
+                    buf: BytesMut::new(),
+                    limit: self.config.body_limit,
+                    truncated: false,
+                })),
+            };
+            log::debug!(
+                "sampled the request for verbose diagnostics: {} {} (request-id = {:?})",
+                input.request.method(),
+                input.request.uri(),
+                diagnostics.request_id,
+            );
+
+            if self.config.body_limit > 0 {
+                if let Some(body) = RequestBody::take_from(input.locals) {
+                    let capture = CaptureBody {
+                        body,
+                        captured: diagnostics.body.clone(),
+                    };
+                    RequestBody::from(hyper::Body::wrap_stream(capture)).insert_into(input.locals);
+                }
+            }
+            diagnostics.clone().insert_into(input.locals);
+
+            self.state = State::Sampled(Recording {
+                method: input.request.method().clone(),
+                uri: input.request.uri().clone(),
+                diagnostics,
+                polls: 0,
+            });
+        }
+
+        fn finish(&self, recording: &Recording, succeeded: bool) {
+            let body = lock(&recording.diagnostics.body);
+            let report = Report {
+                method: recording.method.clone(),
+                uri: recording.uri.clone(),
+                request_id: recording.diagnostics.request_id.clone(),
+                elapsed: recording.diagnostics.started.elapsed(),
+                polls: recording.polls,
+                body: Bytes::from(&body.buf[..]),
+                body_truncated: body.truncated,
+                succeeded,
+            };
+            log::info!("{}", report);
+            if let Some(ref reporter) = self.config.reporter {
+                reporter(&report);
+            }
+        }
+    }
+
+    impl<H> TryFuture for HandleSampling<H>
+    where
+        H: TryFuture,
+    {
+        type Ok = H::Ok;
+        type Error = H::Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            if let State::Init = self.state {
+                self.start(input);
+            }
+
+            let polled = self.inner.poll_ready(input);
+            if let State::Sampled(ref mut recording) = self.state {
+                recording.polls += 1;
+            }
+
+            let succeeded = match polled {
+                Ok(Async::NotReady) => return Ok(Async::NotReady),
+                Ok(Async::Ready(..)) => true,
+                Err(..) => false,
+            };
+            if let State::Sampled(ref recording) = self.state {
+                self.finish(recording, succeeded);
+            }
+            polled
+        }
+    }
+
+    #[derive(Debug)]
+    struct CapturedBody {
+        buf: BytesMut,
+        limit: usize,
+        truncated: bool,
+    }
+
+    impl CapturedBody {
+        fn push(&mut self, chunk: &[u8]) {
+            let remaining = self.limit.saturating_sub(self.buf.len());
+            if chunk.len() > remaining {
+                self.truncated = true;
+            }
+            self.buf
+                .extend_from_slice(&chunk[..std::cmp::min(chunk.len(), remaining)]);
+        }
+    }
+
+    /// Acquires the lock of captured body, ignoring the poisoning.
+    ///
+    /// The captured bytes are only used for the diagnostics, so a panic during the
+    /// capturing should not propagate to the other requests.
+    fn lock(body: &Mutex<CapturedBody>) -> MutexGuard<'_, CapturedBody> {
+        body.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    struct CaptureBody {
+        body: RequestBody,
+        captured: Arc<Mutex<CapturedBody>>,
+    }
+
+    impl Stream for CaptureBody {
+        type Item = hyper::Chunk;
+        type Error = hyper::Error;
+
+        fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+            let chunk = futures01::try_ready!(self.body.poll());
+            if let Some(ref chunk) = chunk {
+                lock(&self.captured).push(chunk);
+            }
+            Ok(Async::Ready(chunk))
+        }
+    }
+
+    /// The request-local data stored when the request is sampled.
+    ///
+    /// The downstream components can check whether the current request is sampled
+    /// by using `Diagnostics::get(input.locals)`.
+    #[derive(Debug, Clone)]
+    pub struct Diagnostics {
+        request_id: Option<String>,
+        started: Instant,
+        body: Arc<Mutex<CapturedBody>>,
+    }
+
+    impl LocalData for Diagnostics {
+        local_key! {
+            /// The local key to manage the diagnostics of sampled request.
+            const KEY: Self;
+        }
+    }
+
+    impl Diagnostics {
+        /// Returns the request ID used for the sampling decision, if exists.
+        pub fn request_id(&self) -> Option<&str> {
+            self.request_id.as_ref().map(|s| s.as_str())
+        }
+
+        /// Returns the instant when the sampled request has started to be handled.
+        pub fn started(&self) -> Instant {
+            self.started
+        }
+
+        /// Returns the request body captured so far.
+        pub fn captured_body(&self) -> Bytes {
+            Bytes::from(&lock(&self.body).buf[..])
+        }
+    }
+
+    /// A summary of the diagnostics collected from a sampled request.
+    #[derive(Debug, Clone)]
+    pub struct Report {
+        /// The request method.
+        pub method: Method,
+        /// The request URI.
+        pub uri: Uri,
+        /// The request ID used for the sampling decision.
+        pub request_id: Option<String>,
+        /// The elapsed time from the start of handling to its completion.
+        pub elapsed: Duration,
+        /// The number of times the inner handler has been polled.
+        pub polls: usize,
+        /// The captured request body, up to the configured limit.
+        pub body: Bytes,
+        /// Whether the request body exceeded the limit of capturing.
+        pub body_truncated: bool,
+        /// Whether the inner handler has completed successfully.
+        pub succeeded: bool,
+    }
+
+    impl fmt::Display for Report {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(
+                f,
+                "sampled request: {} {} (request-id = {:?}, elapsed = {:?}, polls = {}, body = {} bytes{}, succeeded = {})",
+                self.method,
+                self.uri,
+                self.request_id,
+                self.elapsed,
+                self.polls,
+                self.body.len(),
+                if self.body_truncated { ", truncated" } else { "" },
+                self.succeeded,
+            )
+        }
+    }
+}
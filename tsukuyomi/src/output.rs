@@ -1,16 +1,48 @@
 //! Components for constructing HTTP responses.
 
+pub mod attachment;
+pub mod conditional;
+#[cfg(feature = "csv")]
+pub mod csv;
+pub mod headers;
+pub mod html;
+mod json;
+pub mod negotiate;
 pub mod redirect;
+pub mod resumable;
+pub mod sse;
+mod stream;
+pub mod transform;
+pub mod uri;
 
-pub use tsukuyomi_macros::IntoResponse;
+pub use {
+    self::{
+        attachment::attachment,
+        conditional::{with_etag, with_last_modified},
+        json::{Json, JsonConfig},
+        negotiate::negotiate,
+        resumable::resumable,
+        stream::StreamBody,
+    },
+    crate::etag::ETag,
+    tsukuyomi_macros::IntoResponse,
+};
 
 use {
-    crate::{error::Error, input::body::RequestBody, util::Never},
+    crate::{
+        error::{Error, ErrorResponse},
+        input::{body::RequestBody, Input},
+        util::Never,
+    },
     bytes::{Buf, Bytes, IntoBuf},
     futures01::{Poll, Stream},
-    http::{header::HeaderMap, Request, Response, StatusCode},
+    http::{
+        header::{self, HeaderMap},
+        Request, Response, StatusCode,
+    },
     hyper::body::{Body, Payload},
     serde::Serialize,
+    std::fmt,
 };
 
 // the private API for custom derive.
@@ -19,6 +51,7 @@ pub mod internal {
     pub use {
         crate::{
             error::Error,
+            input::Input,
             output::{preset::Preset, IntoResponse, ResponseBody},
         },
         http::{Request, Response},
@@ -26,8 +59,20 @@ pub mod internal {
 }
 
 /// A type representing the message body in an HTTP response.
-#[derive(Debug, Default)]
-pub struct ResponseBody(Body);
+#[derive(Debug)]
+pub struct ResponseBody(Inner);
+
+#[derive(Debug)]
+enum Inner {
+    Body(Body),
+    Stream(self::stream::StreamPayload),
+}
+
+impl Default for ResponseBody {
+    fn default() -> Self {
+        ResponseBody(Inner::Body(Body::default()))
+    }
+}
 
 impl ResponseBody {
     /// Creates an empty `ResponseBody`.
@@ -43,21 +88,113 @@ impl ResponseBody {
         S::Error: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
         S::Item: IntoBuf,
     {
-        ResponseBody(Body::wrap_stream(
+        ResponseBody(Inner::Body(Body::wrap_stream(
             stream.map(|chunk| chunk.into_buf().collect::<Bytes>()),
-        ))
+        )))
+    }
+
+    /// Rewrites the chunks of this body incrementally by using the specified transformer.
+    ///
+    /// The returned body is always streamed without the known length, and the trailers
+    /// of the original body are discarded.
+    pub fn map_chunks<T>(self, transformer: T) -> Self
+    where
+        T: self::transform::ChunkTransformer,
+    {
+        Self::wrap_stream(MapChunks {
+            body: self,
+            transformer,
+            finished: false,
+        })
+    }
+
+    /// Notifies whether the protocol of the current connection can send the trailers.
+    pub(crate) fn allow_trailers(&mut self, allowed: bool) {
+        if let Inner::Stream(ref mut stream) = self.0 {
+            stream.allow_trailers(allowed);
+        }
+    }
+
+    /// Returns the flag of truncation shared with the server, if this body is a `StreamBody`.
+    pub(crate) fn truncation(&self) -> Option<tsukuyomi_service::Truncation> {
+        match self.0 {
+            Inner::Stream(ref stream) => Some(stream.truncation()),
+            Inner::Body(..) => None,
+        }
+    }
+
+    /// Registers the function called with the error occurring after the response head
+    /// has been sent, if this body is a `StreamBody`.
+    ///
+    /// The function `f` is called only if the hook is registered.
+    pub(crate) fn on_stream_error<F>(&mut self, f: impl FnOnce() -> F)
+    where
+        F: Fn(&dyn std::error::Error) + Send + 'static,
+    {
+        if let Inner::Stream(ref mut stream) = self.0 {
+            stream.on_error(f());
+        }
+    }
+
+    /// Discards the content of this body.
+    ///
+    /// The body whose length is unknown is replaced with an empty stream rather than
+    /// an empty body, so that the server does not send `Content-Length: 0` for it.
+    pub(crate) fn discard(&mut self) {
+        match self.content_length() {
+            Some(..) => *self = Self::empty(),
+            None => self.discard_without_length(),
+        }
+    }
+
+    /// Discards the content of this body, and makes its length unknown.
+    ///
+    /// This is used for the responses which must not have `Content-Length`.
+    pub(crate) fn discard_without_length(&mut self) {
+        *self = Self::wrap_stream(futures01::stream::empty::<Bytes, Never>());
+    }
+}
+
+/// A `Stream` that applies a `ChunkTransformer` to the data of `ResponseBody`.
+struct MapChunks<T> {
+    body: ResponseBody,
+    transformer: T,
+    finished: bool,
+}
+
+impl<T> Stream for MapChunks<T>
+where
+    T: self::transform::ChunkTransformer,
+{
+    type Item = Bytes;
+    type Error = hyper::Error;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        while !self.finished {
+            let chunk = match futures01::try_ready!(self.body.poll_data()) {
+                Some(chunk) => self.transformer.transform(chunk.into()),
+                None => {
+                    self.finished = true;
+                    self.transformer.finish()
+                }
+            };
+            if !chunk.is_empty() {
+                return Ok(Some(chunk).into());
+            }
+        }
+        Ok(None.into())
     }
 }
 
 impl From<()> for ResponseBody {
     fn from(_: ()) -> Self {
-        ResponseBody(Body::empty())
+        ResponseBody(Inner::Body(Body::empty()))
     }
 }
 
 impl From<RequestBody> for ResponseBody {
     fn from(body: RequestBody) -> Self {
-        ResponseBody(body.into_inner())
+        ResponseBody(Inner::Body(body.into_inner()))
     }
 }
 
@@ -65,7 +202,7 @@ macro_rules! impl_response_body {
     ($($t:ty,)*) => {$(
         impl From<$t> for ResponseBody {
             fn from(body: $t) -> Self {
-                ResponseBody(Body::from(body))
+                ResponseBody(Inner::Body(Body::from(body)))
             }
         }
     )*};
@@ -82,6 +219,17 @@ impl_response_body! {
     hyper::Body,
 }
 
+impl<S> From<StreamBody<S>> for ResponseBody
+where
+    S: Stream + Send + 'static,
+    S::Item: IntoBuf,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+{
+    fn from(body: StreamBody<S>) -> Self {
+        ResponseBody(Inner::Stream(body.into_payload()))
+    }
+}
+
 impl Payload for ResponseBody {
     type Data = <Body as Payload>::Data;
     type Error = <Body as Payload>::Error;
@@ -89,25 +237,37 @@ impl Payload for ResponseBody {
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
-        self.0.poll_data()
+        match self.0 {
+            Inner::Body(ref mut body) => body.poll_data(),
+            Inner::Stream(ref mut stream) => stream.poll_data(),
+        }
     }
 
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
-        self.0.poll_trailers()
+        match self.0 {
+            Inner::Body(ref mut body) => body.poll_trailers(),
+            Inner::Stream(ref mut stream) => stream.poll_trailers(),
+        }
     }
 
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn is_end_stream(&self) -> bool {
-        self.0.is_end_stream()
+        match self.0 {
+            Inner::Body(ref body) => body.is_end_stream(),
+            Inner::Stream(ref stream) => stream.is_end_stream(),
+        }
     }
 
     #[inline]
     #[cfg_attr(tarpaulin, skip)]
     fn content_length(&self) -> Option<u64> {
-        self.0.content_length()
+        match self.0 {
+            Inner::Body(ref body) => body.content_length(),
+            Inner::Stream(ref stream) => stream.content_length(),
+        }
     }
 }
 
@@ -117,6 +277,18 @@ pub trait IntoResponse {
     type Error: Into<Error>;
 
     fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error>;
+
+    /// Converts itself into an HTTP response, with the access to the request-local data.
+    ///
+    /// This method is called by the framework instead of `into_response`.  The default
+    /// implementation is the same as `into_response`, and the wrappers of other values
+    /// should forward it to the inner value.
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error>
+    where
+        Self: Sized,
+    {
+        self.into_response(input.request)
+    }
 }
 
 impl IntoResponse for () {
@@ -143,26 +315,142 @@ where
             .map(|response| response.map(Into::into))
             .map_err(Into::into)
     }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        let x = self.ok_or_else(|| crate::error::not_found("None"))?;
+        x.respond_to(input)
+            .map(|response| response.map(Into::into))
+            .map_err(Into::into)
+    }
 }
 
+/// Creates the response from either side of the value.
+///
+/// The error side may be an arbitrary `IntoResponse` such as `(StatusCode, T)` or
+/// `Json<T>`, as well as the error values.  The values of `HttpError` and `Error` are
+/// not converted into the response here, and the handling of errors (e.g.
+/// `config::observe_errors`) is applied to them as if they were returned from the
+/// handler.  See `IntoErrorResponse` for the types allowed as the error side.
 impl<T, E> IntoResponse for Result<T, E>
 where
     T: IntoResponse,
-    E: Into<Error>,
+    E: IntoErrorResponse,
 {
     type Body = ResponseBody;
     type Error = Error;
 
     fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
-        self.map_err(Into::into)?
-            .into_response(request)
+        match self {
+            Ok(ok) => ok
+                .into_response(request)
+                .map(|response| response.map(Into::into))
+                .map_err(Into::into),
+            Err(err) => err.into_error_response(request),
+        }
+    }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        match self {
+            Ok(ok) => ok
+                .respond_to(input)
+                .map(|response| response.map(Into::into))
+                .map_err(Into::into),
+            Err(err) => err.respond_to_error(input),
+        }
+    }
+}
+
+/// A trait representing the values used as the error side of `Result` in the responders.
+///
+/// This trait is implemented for all `IntoResponse`s, and for the public `HttpError`s
+/// provided by this crate (e.g. `StatusCode` and `io::Error`), which fail with themselves.
+/// The other `HttpError`s can be used as the error side by implementing this trait with
+/// `Error::new`, or by converting them into `Error` in advance.
+pub trait IntoErrorResponse {
+    /// Creates the response, or fails with the error value.
+    fn into_error_response(self, request: &Request<()>) -> Result<Response<ResponseBody>, Error>;
+
+    /// Creates the response with the access to the request-local data.
+    ///
+    /// The default implementation is the same as `into_error_response`.
+    fn respond_to_error(self, input: &mut Input<'_>) -> Result<Response<ResponseBody>, Error>
+    where
+        Self: Sized,
+    {
+        self.into_error_response(input.request)
+    }
+}
+
+impl<B> IntoErrorResponse for B
+where
+    B: IntoResponse,
+{
+    fn into_error_response(self, request: &Request<()>) -> Result<Response<ResponseBody>, Error> {
+        self.into_response(request)
             .map(|response| response.map(Into::into))
             .map_err(Into::into)
     }
+
+    fn respond_to_error(self, input: &mut Input<'_>) -> Result<Response<ResponseBody>, Error> {
+        self.respond_to(input)
+            .map(|response| response.map(Into::into))
+            .map_err(Into::into)
+    }
+}
+
+macro_rules! impl_into_error_response_for_http_errors {
+    ($($t:ty),*) => {$(
+        impl IntoErrorResponse for $t {
+            #[inline]
+            fn into_error_response(self, _: &Request<()>) -> Result<Response<ResponseBody>, Error> {
+                Err(Error::new(self))
+            }
+        }
+    )*};
+}
+
+impl_into_error_response_for_http_errors!(
+    StatusCode,
+    std::io::Error,
+    failure::Error,
+    hyper::Error,
+    Never,
+    crate::fs::FileError,
+    crate::fs::PersistError,
+    crate::extractor::range::RangeNotSatisfiable,
+    crate::extractor::validate::ValidationError
+);
+
+#[cfg(feature = "json-schema")]
+impl_into_error_response_for_http_errors!(crate::contrib::json_schema::SchemaViolations);
+
+impl<D> IntoErrorResponse for ErrorResponse<D>
+where
+    D: fmt::Debug + fmt::Display + Send + 'static,
+{
+    #[inline]
+    fn into_error_response(self, _: &Request<()>) -> Result<Response<ResponseBody>, Error> {
+        Err(Error::new(self))
+    }
+}
+
+/// Fails with the error value, in order to pass it to the error handling of the framework.
+impl IntoResponse for Error {
+    type Body = ();
+    type Error = Self;
+
+    #[inline]
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        Err(self)
+    }
 }
 
 mod impl_into_response_for_either {
-    use {super::*, either::Either};
+    use {
+        super::*,
+        crate::util::{Either3, Either4},
+        either::Either,
+    };
 
     impl<L, R> IntoResponse for Either<L, R>
     where
@@ -184,6 +472,74 @@ mod impl_into_response_for_either {
                     .map_err(Into::into),
             }
         }
+
+        fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+            match self {
+                Either::Left(l) => l
+                    .respond_to(input)
+                    .map(|response| response.map(Into::into))
+                    .map_err(Into::into),
+                Either::Right(r) => r
+                    .respond_to(input)
+                    .map(|response| response.map(Into::into))
+                    .map_err(Into::into),
+            }
+        }
+    }
+
+    /// The responses with three or more outcomes are handled as the nested `Either`s.
+    impl<A, B, C> IntoResponse for Either3<A, B, C>
+    where
+        A: IntoResponse,
+        B: IntoResponse,
+        C: IntoResponse,
+    {
+        type Body = ResponseBody;
+        type Error = Error;
+
+        fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+            nest3(self).into_response(request)
+        }
+
+        fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+            nest3(self).respond_to(input)
+        }
+    }
+
+    impl<A, B, C, D> IntoResponse for Either4<A, B, C, D>
+    where
+        A: IntoResponse,
+        B: IntoResponse,
+        C: IntoResponse,
+        D: IntoResponse,
+    {
+        type Body = ResponseBody;
+        type Error = Error;
+
+        fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+            nest4(self).into_response(request)
+        }
+
+        fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+            nest4(self).respond_to(input)
+        }
+    }
+
+    fn nest3<A, B, C>(x: Either3<A, B, C>) -> Either<A, Either<B, C>> {
+        match x {
+            Either3::A(a) => Either::Left(a),
+            Either3::B(b) => Either::Right(Either::Left(b)),
+            Either3::C(c) => Either::Right(Either::Right(c)),
+        }
+    }
+
+    fn nest4<A, B, C, D>(x: Either4<A, B, C, D>) -> Either<A, Either3<B, C, D>> {
+        match x {
+            Either4::A(a) => Either::Left(a),
+            Either4::B(b) => Either::Right(Either3::A(b)),
+            Either4::C(c) => Either::Right(Either3::B(c)),
+            Either4::D(d) => Either::Right(Either3::C(d)),
+        }
     }
 }
 
@@ -200,6 +556,88 @@ where
     }
 }
 
+/// Replaces the status code of the inner response.
+impl<T> IntoResponse for (StatusCode, T)
+where
+    T: IntoResponse,
+{
+    type Body = T::Body;
+    type Error = T::Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (status, inner) = self;
+        let mut response = inner.into_response(request)?;
+        *response.status_mut() = status;
+        Ok(response)
+    }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        let (status, inner) = self;
+        let mut response = inner.respond_to(input)?;
+        *response.status_mut() = status;
+        Ok(response)
+    }
+}
+
+/// Replaces the status code and merges the header fields into the inner response.
+///
+/// The header fields in the map replace all values with the same name in the inner
+/// response, so that `Content-Type` in the map takes precedence over the one set by
+/// the inner response (e.g. the presets).  The only exception is `Set-Cookie`, whose
+/// values are appended to the inner ones since each of them sets a separate cookie.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, App};
+/// # use http::{header::{HeaderMap, LOCATION}, StatusCode};
+/// let app = App::create(
+///     path!("/posts") //
+///         .to(endpoint::post().call(|| {
+///             let mut headers = HeaderMap::new();
+///             headers.insert(LOCATION, "/posts/42".parse().unwrap());
+///             (StatusCode::CREATED, headers, "created")
+///         })),
+/// );
+/// # app.unwrap();
+/// ```
+impl<T> IntoResponse for (StatusCode, HeaderMap, T)
+where
+    T: IntoResponse,
+{
+    type Body = T::Body;
+    type Error = T::Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let (status, headers, inner) = self;
+        let response = inner.into_response(request)?;
+        Ok(merge_headers(response, status, headers))
+    }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        let (status, headers, inner) = self;
+        let response = inner.respond_to(input)?;
+        Ok(merge_headers(response, status, headers))
+    }
+}
+
+fn merge_headers<T>(
+    mut response: Response<T>,
+    status: StatusCode,
+    mut headers: HeaderMap,
+) -> Response<T> {
+    *response.status_mut() = status;
+    for (name, values) in headers.drain() {
+        if name != header::SET_COOKIE {
+            response.headers_mut().remove(&name);
+        }
+        for value in values {
+            response.headers_mut().append(name.clone(), value);
+        }
+    }
+    response
+}
+
 impl IntoResponse for &'static str {
     type Body = Self;
     type Error = Never;
@@ -284,6 +722,12 @@ where
 }
 
 /// Creates an HTML responder with the specified response body.
+///
+/// The response is sent with `Content-type: text/html; charset=utf-8`.  Use `with_charset`
+/// if the body is encoded in another charset.
+///
+/// The body is sent as it is.  Use the helpers in the module `html` to embed untrusted
+/// values into the body.
 #[allow(deprecated)]
 #[inline]
 pub fn html<T>(body: T) -> impl IntoResponse<Body = T, Error = Never>
@@ -293,6 +737,116 @@ where
     self::into_response(move |request| self::into_response::html(body, request))
 }
 
+/// Creates a responder that replaces the parameter `charset` in the header field
+/// `Content-type` of the response created by `responder`.
+///
+/// This function only changes the header field, and the body must be encoded in
+/// the specified charset by the caller.  The response without `Content-type` is
+/// sent as it is.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::{config::prelude::*, output::{html, with_charset}, App};
+/// let app = App::create(
+///     path!("/").to(endpoint::get().call(|| {
+///         // "<p>caf\u{e9}</p>" encoded in ISO-8859-1
+///         with_charset(html(&b"<p>caf\xe9</p>"[..]), "iso-8859-1")
+///     })),
+/// );
+/// # app.unwrap();
+/// ```
+pub fn with_charset<T>(
+    responder: T,
+    charset: &'static str,
+) -> impl IntoResponse<Body = T::Body, Error = Error>
+where
+    T: IntoResponse,
+{
+    self::into_response(move |request| {
+        let mut response = responder.into_response(request).map_err(Into::into)?;
+        let content_type = match response.headers().get(http::header::CONTENT_TYPE) {
+            Some(value) => value
+                .to_str()
+                .map_err(crate::error::internal_server_error)?
+                .split(';')
+                .map(str::trim)
+                .filter(|param| {
+                    !param
+                        .get(..8)
+                        .map(|name| name.eq_ignore_ascii_case("charset="))
+                        .unwrap_or(false)
+                })
+                .chain(Some(&*format!("charset={}", charset)))
+                .collect::<Vec<_>>()
+                .join("; "),
+            None => return Ok(response),
+        };
+        let content_type = http::header::HeaderValue::from_shared(content_type.into())
+            .map_err(crate::error::internal_server_error)?;
+        response
+            .headers_mut()
+            .insert(http::header::CONTENT_TYPE, content_type);
+        Ok(response)
+    })
+}
+
+/// A marker stored in the extension map of `Response`, indicating that the response
+/// must not be modified by the transforming layers (e.g. compression).
+///
+/// This value is inserted by [`no_transform`](./fn.no_transform.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoTransform(());
+
+/// Creates a responder that marks the response created by `responder` as non-transformable.
+///
+/// The directive `no-transform` is appended to the header field `Cache-Control` so that
+/// intermediaries do not modify the response, and a value of `NoTransform` is inserted into
+/// its extension map so that the transforming layers within the application can skip the
+/// response without parsing `Cache-Control`.
+pub fn no_transform<T>(responder: T) -> impl IntoResponse<Body = T::Body, Error = T::Error>
+where
+    T: IntoResponse,
+{
+    self::into_response(move |request| {
+        let mut response = responder.into_response(request)?;
+        if !has_no_transform_directive(response.headers()) {
+            let value = match response.headers().get(http::header::CACHE_CONTROL) {
+                Some(value) if !value.as_bytes().is_empty() => {
+                    let mut value = value.as_bytes().to_owned();
+                    value.extend_from_slice(b", no-transform");
+                    http::header::HeaderValue::from_shared(value.into())
+                        .expect("should be a valid header value")
+                }
+                _ => http::header::HeaderValue::from_static("no-transform"),
+            };
+            response
+                .headers_mut()
+                .insert(http::header::CACHE_CONTROL, value);
+        }
+        response.extensions_mut().insert(NoTransform(()));
+        Ok(response)
+    })
+}
+
+/// Returns whether the specified response is allowed to be modified by the transforming layers.
+///
+/// The response is regarded as non-transformable if it is marked by `no_transform`, or the
+/// header field `Cache-Control` contains the directive `no-transform`.
+pub fn is_transformable<T>(response: &Response<T>) -> bool {
+    response.extensions().get::<NoTransform>().is_none()
+        && !has_no_transform_directive(response.headers())
+}
+
+fn has_no_transform_directive(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(http::header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .any(|directive| directive.trim().eq_ignore_ascii_case("no-transform"))
+}
+
 /// Create an instance of `Response<T>` with the provided body and content type.
 fn make_response<T>(body: T, content_type: &'static str) -> Response<T> {
     let mut response = Response::new(body);
@@ -305,10 +859,11 @@ fn make_response<T>(body: T, content_type: &'static str) -> Response<T> {
 
 pub mod preset {
     use {
-        super::ResponseBody,
-        crate::{error::Error, util::Never},
+        super::{IntoResponse, ResponseBody},
+        crate::{error::Error, input::Input, util::Never},
         http::{Request, Response},
         serde::Serialize,
+        std::marker::PhantomData,
     };
 
     /// A trait representing the *preset* for deriving the implementation of `IntoResponse`.
@@ -317,6 +872,36 @@ pub mod preset {
         type Error: Into<Error>;
 
         fn into_response(t: T, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error>;
+
+        /// Converts the value into a response with the human-readable formatting.
+        ///
+        /// This method is called by the derived implementation when the flag
+        /// `#[response(pretty)]` is specified.  The default implementation is the same
+        /// as `into_response`.
+        fn into_response_pretty(
+            t: T,
+            request: &Request<()>,
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            Self::into_response(t, request)
+        }
+
+        /// Converts the value into a response, with the access to the request-local data.
+        ///
+        /// This method is called by the derived implementation of `IntoResponse::respond_to`.
+        /// The default implementation is the same as `into_response`.
+        fn respond_to(t: T, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+            Self::into_response(t, input.request)
+        }
+
+        /// The variant of `respond_to` called when the flag `#[response(pretty)]` is specified.
+        ///
+        /// The default implementation is the same as `into_response_pretty`.
+        fn respond_to_pretty(
+            t: T,
+            input: &mut Input<'_>,
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            Self::into_response_pretty(t, input.request)
+        }
     }
 
     #[allow(missing_debug_implementations)]
@@ -329,10 +914,18 @@ pub mod preset {
         type Body = Vec<u8>;
         type Error = Error;
 
-        fn into_response(data: T, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
-            serde_json::to_vec(&data)
-                .map(|body| super::make_response(body, "application/json"))
-                .map_err(crate::error::internal_server_error)
+        fn into_response(
+            data: T,
+            request: &Request<()>,
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            super::Json::new(data).into_response(request)
+        }
+
+        fn into_response_pretty(
+            data: T,
+            request: &Request<()>,
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            super::Json::new(data).pretty().into_response(request)
         }
     }
 
@@ -346,13 +939,154 @@ pub mod preset {
         type Body = Vec<u8>;
         type Error = Error;
 
+        fn into_response(
+            data: T,
+            request: &Request<()>,
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            super::Json::new(data).pretty().into_response(request)
+        }
+    }
+
+    /// A preset that serializes the value into MessagePack, with the field names.
+    ///
+    /// This preset is available only if the feature `msgpack` is enabled.
+    #[cfg(feature = "msgpack")]
+    #[allow(missing_debug_implementations)]
+    pub struct Msgpack(());
+
+    #[cfg(feature = "msgpack")]
+    impl<T> Preset<T> for Msgpack
+    where
+        T: Serialize,
+    {
+        type Body = Vec<u8>;
+        type Error = Error;
+
         fn into_response(data: T, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
-            serde_json::to_vec_pretty(&data)
-                .map(|body| super::make_response(body, "application/json"))
-                .map_err(crate::error::internal_server_error)
+            let body = rmp_serde::to_vec_named(&data) //
+                .map_err(crate::error::internal_server_error)?;
+            let content_length = body.len();
+            let mut response = super::make_response(body, "application/msgpack");
+            response
+                .headers_mut()
+                .insert(http::header::CONTENT_LENGTH, content_length.into());
+            Ok(response)
         }
     }
 
+    /// A preset that serializes the value into XML.
+    ///
+    /// The failure of serialization is reported as `500 Internal Server Error`.
+    ///
+    /// This preset is available only if the feature `xml` is enabled.
+    #[cfg(feature = "xml")]
+    #[allow(missing_debug_implementations)]
+    pub struct Xml(());
+
+    #[cfg(feature = "xml")]
+    impl<T> Preset<T> for Xml
+    where
+        T: Serialize,
+    {
+        type Body = String;
+        type Error = Error;
+
+        fn into_response(data: T, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+            let body = serde_xml_rs::to_string(&data) //
+                .map_err(crate::error::internal_server_error)?;
+            let content_length = body.len();
+            let mut response = super::make_response(body, "application/xml");
+            response
+                .headers_mut()
+                .insert(http::header::CONTENT_LENGTH, content_length.into());
+            Ok(response)
+        }
+    }
+
+    /// A trait for customizing the shape of the envelope used by `Envelope`.
+    pub trait Wrap {
+        /// Wraps the serialized payload into an envelope.
+        ///
+        /// The `Input` gives access to the request-local data, e.g. the values
+        /// inserted by the extractors or modifiers.
+        fn wrap(value: serde_json::Value, input: &Input<'_>) -> serde_json::Value;
+    }
+
+    /// The default shape of the envelope, `{"data": <payload>, "meta": {}}`.
+    #[allow(missing_debug_implementations)]
+    pub struct DataMeta(());
+
+    impl Wrap for DataMeta {
+        fn wrap(value: serde_json::Value, _: &Input<'_>) -> serde_json::Value {
+            serde_json::json!({
+                "data": value,
+                "meta": {},
+            })
+        }
+    }
+
+    /// A preset that wraps the value in an envelope before passing it to
+    /// the inner preset `P`.
+    ///
+    /// The value is converted into a `serde_json::Value` and wrapped by `W`.
+    /// The failure of the conversion is reported as `500 Internal Server Error`.
+    ///
+    /// Since `W` takes the `Input`, this preset works only through `IntoResponse::respond_to`,
+    /// and `into_response` always fails with `500 Internal Server Error`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use serde::Serialize;
+    /// # use tsukuyomi::output::IntoResponse;
+    /// #[derive(Serialize, IntoResponse)]
+    /// #[response(preset = "tsukuyomi::output::preset::Envelope<tsukuyomi::output::preset::Json>")]
+    /// struct User {
+    ///     name: String,
+    /// }
+    /// ```
+    #[allow(missing_debug_implementations)]
+    pub struct Envelope<P, W = DataMeta>(PhantomData<fn() -> (P, W)>);
+
+    impl<T, P, W> Preset<T> for Envelope<P, W>
+    where
+        T: Serialize,
+        P: Preset<serde_json::Value>,
+        W: Wrap,
+    {
+        type Body = P::Body;
+        type Error = Error;
+
+        fn into_response(_: T, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+            Err(crate::error::internal_server_error(
+                "the envelope requires the Input, passed by `IntoResponse::respond_to`",
+            ))
+        }
+
+        fn respond_to(data: T, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+            let value = wrap::<T, W>(data, input)?;
+            P::respond_to(value, input).map_err(Into::into)
+        }
+
+        fn respond_to_pretty(
+            data: T,
+            input: &mut Input<'_>,
+        ) -> Result<Response<Self::Body>, Self::Error> {
+            let value = wrap::<T, W>(data, input)?;
+            P::respond_to_pretty(value, input).map_err(Into::into)
+        }
+    }
+
+    fn wrap<T, W>(data: T, input: &Input<'_>) -> Result<serde_json::Value, Error>
+    where
+        T: Serialize,
+        W: Wrap,
+    {
+        let value = serde_json::to_value(data) //
+            .map_err(crate::error::internal_server_error)?;
+        Ok(W::wrap(value, input))
+    }
+
     #[allow(missing_debug_implementations)]
     pub struct Html(());
 
@@ -364,7 +1098,7 @@ pub mod preset {
         type Error = Never;
 
         fn into_response(body: T, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
-            Ok(super::make_response(body, "text/html"))
+            Ok(super::make_response(body, "text/html; charset=utf-8"))
         }
     }
 
@@ -422,7 +1156,7 @@ pub mod into_response {
     where
         T: Into<ResponseBody>,
     {
-        Ok(super::make_response(body, "text/html"))
+        Ok(super::make_response(body, "text/html; charset=utf-8"))
     }
 
     #[inline]
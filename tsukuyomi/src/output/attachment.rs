@@ -0,0 +1,241 @@
+//! Responders for downloading the content as a file.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, fs::NamedFile, output::attachment, App};
+//! let app = App::create(chain![
+//!     path!("/report").to(endpoint::get().call(|| {
+//!         attachment("id,name\n1,alice\n", "月次レポート.csv")
+//!     })),
+//!     path!("/archive").to(endpoint::get().call(|| {
+//!         attachment(NamedFile::open("/var/data/archive.tar.gz"), "archive.tar.gz")
+//!     })),
+//! ]);
+//! # app.unwrap();
+//! ```
+
+use {
+    super::{conditional::Conditional, resumable::Resumable, IntoResponse, ResponseBody},
+    crate::{
+        error::Error,
+        fs::NamedFile,
+        future::{Poll, TryFuture},
+        input::Input,
+        responder::Responder,
+    },
+    http::{
+        header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE},
+        Request, Response, StatusCode,
+    },
+    std::{borrow::Cow, fmt::Write as _Write, path::Path},
+};
+
+/// Creates a `Responder` that makes the client download `body` as a file named `filename`.
+///
+/// The response has the header field `Content-Disposition: attachment`.  The file name is
+/// sent as a quoted string in which the characters other than the printable ASCII, `"`,
+/// `\`, and `/` are replaced with `_`, followed by the parameter `filename*` in the form
+/// of RFC 5987 if the replacement has changed the name.
+///
+/// `Content-Type` is guessed from the extension of `filename`.  If the extension is
+/// unknown, the content type of the inner response is used, or `application/octet-stream`
+/// if it is missing.
+///
+/// The conditional and range requests are processed by the inner responder (e.g.
+/// `fs::NamedFile` or `output::resumable`), and the responses other than `304 Not Modified`
+/// receive the headers above.
+pub fn attachment<T>(body: T, filename: impl Into<Cow<'static, str>>) -> Attachment<T::Responder>
+where
+    T: AttachmentBody,
+{
+    Attachment {
+        responder: body.into_responder(),
+        filename: filename.into(),
+    }
+}
+
+/// A trait representing the values that can be sent by `attachment`.
+pub trait AttachmentBody {
+    /// The type of `Responder` that creates the response from this value.
+    type Responder: Responder;
+
+    /// Converts this value into a `Responder`.
+    fn into_responder(self) -> Self::Responder;
+}
+
+impl<T> AttachmentBody for T
+where
+    T: Into<ResponseBody>,
+{
+    type Responder = Response<T>;
+
+    #[inline]
+    fn into_responder(self) -> Self::Responder {
+        Response::new(self)
+    }
+}
+
+impl<P> AttachmentBody for NamedFile<P>
+where
+    P: AsRef<Path> + Send + 'static,
+{
+    type Responder = Self;
+
+    #[inline]
+    fn into_responder(self) -> Self::Responder {
+        self
+    }
+}
+
+impl<F> AttachmentBody for Resumable<F>
+where
+    Resumable<F>: IntoResponse,
+{
+    type Responder = Self;
+
+    #[inline]
+    fn into_responder(self) -> Self::Responder {
+        self
+    }
+}
+
+impl<R> AttachmentBody for Conditional<R>
+where
+    R: Responder,
+{
+    type Responder = Self;
+
+    #[inline]
+    fn into_responder(self) -> Self::Responder {
+        self
+    }
+}
+
+/// The `Responder` created by `attachment`.
+#[derive(Debug)]
+pub struct Attachment<R> {
+    responder: R,
+    filename: Cow<'static, str>,
+}
+
+impl<R> Responder for Attachment<R>
+where
+    R: Responder,
+{
+    type Response = AttachmentResponse<R::Response>;
+    type Error = R::Error;
+    type Respond = AttachmentRespond<R::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        AttachmentRespond {
+            respond: self.responder.respond(),
+            filename: Some(self.filename),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct AttachmentRespond<R> {
+    respond: R,
+    filename: Option<Cow<'static, str>>,
+}
+
+impl<R> TryFuture for AttachmentRespond<R>
+where
+    R: TryFuture,
+    R::Ok: IntoResponse,
+{
+    type Ok = AttachmentResponse<R::Ok>;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let response = futures01::try_ready!(self.respond.poll_ready(input));
+        let filename = self
+            .filename
+            .take()
+            .expect("the future has already been polled");
+        Ok(AttachmentResponse { response, filename }.into())
+    }
+}
+
+/// The `IntoResponse` created by `Attachment`.
+#[derive(Debug)]
+pub struct AttachmentResponse<T> {
+    response: T,
+    filename: Cow<'static, str>,
+}
+
+impl<T> IntoResponse for AttachmentResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = T::Body;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self.response.into_response(request).map_err(Into::into)?;
+        Ok(attach(response, &self.filename))
+    }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self.response.respond_to(input).map_err(Into::into)?;
+        Ok(attach(response, &self.filename))
+    }
+}
+
+fn attach<T>(mut response: Response<T>, filename: &str) -> Response<T> {
+    if response.status() == StatusCode::NOT_MODIFIED {
+        return response;
+    }
+
+    let disposition = HeaderValue::from_shared(content_disposition(filename).into())
+        .expect("should be a valid header value");
+    response
+        .headers_mut()
+        .insert(CONTENT_DISPOSITION, disposition);
+
+    let content_type = mime_guess::from_path(filename).first();
+    match content_type {
+        Some(mime) => {
+            let content_type = HeaderValue::from_shared(mime.as_ref().to_owned().into())
+                .expect("should be a valid header value");
+            response.headers_mut().insert(CONTENT_TYPE, content_type);
+        }
+        None => {
+            response
+                .headers_mut()
+                .entry(CONTENT_TYPE)
+                .expect("should be a valid header name")
+                .or_insert_with(|| HeaderValue::from_static("application/octet-stream"));
+        }
+    }
+
+    response
+}
+
+/// Creates the value of `Content-Disposition` for the specified file name.
+pub(crate) fn content_disposition(filename: &str) -> String {
+    let fallback: String = filename
+        .chars()
+        .map(|ch| match ch {
+            '"' | '\\' | '/' => '_',
+            ' '..='~' => ch,
+            _ => '_',
+        })
+        .collect();
+
+    let mut value = format!("attachment; filename=\"{}\"", fallback);
+    if fallback != filename {
+        value.push_str("; filename*=UTF-8''");
+        for &b in filename.as_bytes() {
+            match b {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' => value.push(b as char),
+                b'!' | b'#' | b'$' | b'&' | b'+' | b'-' | b'.' | b'^' | b'_' | b'`' | b'|'
+                | b'~' => value.push(b as char),
+                _ => write!(value, "%{:02X}", b).expect("infallible"),
+            }
+        }
+    }
+    value
+}
@@ -0,0 +1,347 @@
+//! Components for responding to the conditional requests (RFC 7232).
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, output::{html, with_etag, ETag}, App};
+//! let app = App::create(
+//!     path!("/").to(endpoint::get().call(|| {
+//!         let page = "<h1>Hello</h1>".to_owned();
+//!         let etag = ETag::from_body(&page);
+//!         with_etag(html(page), etag)
+//!     })),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    super::{IntoResponse, ResponseBody},
+    crate::{
+        error::Error,
+        etag::ETag,
+        future::{Poll, TryFuture},
+        input::Input,
+        responder::Responder,
+    },
+    http::{
+        header::{
+            HeaderMap, HeaderValue, CACHE_CONTROL, CONTENT_LOCATION, DATE, ETAG, EXPIRES,
+            IF_MODIFIED_SINCE, IF_NONE_MATCH, IF_RANGE, LAST_MODIFIED, VARY,
+        },
+        Method, Request, Response, StatusCode,
+    },
+    std::time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+/// Creates a `Responder` that attaches the specified entity tag to the response,
+/// and replies `304 Not Modified` if it matches `If-None-Match`.
+pub fn with_etag<R>(responder: R, etag: ETag) -> Conditional<R>
+where
+    R: Responder,
+{
+    Conditional {
+        responder,
+        etag: Some(etag),
+        last_modified: None,
+    }
+}
+
+/// Creates a `Responder` that attaches the specified modification date to the response,
+/// and replies `304 Not Modified` if it is not later than `If-Modified-Since`.
+pub fn with_last_modified<R>(responder: R, last_modified: SystemTime) -> Conditional<R>
+where
+    R: Responder,
+{
+    Conditional {
+        responder,
+        etag: None,
+        last_modified: Some(last_modified),
+    }
+}
+
+/// A `Responder` that validates the conditional request with the specified validators.
+///
+/// The value of this type is created by `with_etag` or `with_last_modified`.
+/// The preconditions are evaluated as follows:
+///
+/// * Only the responses with `200 OK` get the validators, as the header fields `ETag`
+///   and `Last-Modified`.  Other responses are sent as they are.
+/// * Only the requests with `GET` or `HEAD` are evaluated.
+/// * If the request has `If-None-Match`, the response is replaced with `304 Not Modified`
+///   when any of the listed entity tags matches by the weak comparison, and
+///   `If-Modified-Since` is ignored.
+/// * Otherwise, if the request has `If-Modified-Since` and the modification date is
+///   specified, the response is replaced with `304 Not Modified` when the date
+///   is not later than the requested one.  The invalid dates are ignored.
+///
+/// The `304 Not Modified` response has no body, and keeps only the header fields
+/// `Cache-Control`, `Content-Location`, `Date`, `Expires` and `Vary` of the
+/// original response along with the validators.
+#[derive(Debug)]
+pub struct Conditional<R> {
+    responder: R,
+    etag: Option<ETag>,
+    last_modified: Option<SystemTime>,
+}
+
+impl<R> Conditional<R>
+where
+    R: Responder,
+{
+    /// Sets the entity tag of the representation.
+    pub fn etag(self, etag: ETag) -> Self {
+        Self {
+            etag: Some(etag),
+            ..self
+        }
+    }
+
+    /// Sets the modification date of the representation.
+    pub fn last_modified(self, last_modified: SystemTime) -> Self {
+        Self {
+            last_modified: Some(last_modified),
+            ..self
+        }
+    }
+}
+
+impl<R> Responder for Conditional<R>
+where
+    R: Responder,
+{
+    type Response = ConditionalResponse<R::Response>;
+    type Error = R::Error;
+    type Respond = ConditionalRespond<R::Respond>; // private
+
+    fn respond(self) -> Self::Respond {
+        ConditionalRespond {
+            respond: self.responder.respond(),
+            validators: Some((self.etag, self.last_modified)),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct ConditionalRespond<R> {
+    respond: R,
+    validators: Option<(Option<ETag>, Option<SystemTime>)>,
+}
+
+impl<R> TryFuture for ConditionalRespond<R>
+where
+    R: TryFuture,
+    R::Ok: IntoResponse,
+{
+    type Ok = ConditionalResponse<R::Ok>;
+    type Error = R::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let response = futures01::try_ready!(self.respond.poll_ready(input));
+        let (etag, last_modified) = self
+            .validators
+            .take()
+            .expect("the future has already been polled");
+        Ok(ConditionalResponse {
+            response,
+            etag,
+            last_modified,
+        }
+        .into())
+    }
+}
+
+/// The `IntoResponse` created by `Conditional`.
+#[derive(Debug)]
+pub struct ConditionalResponse<T> {
+    response: T,
+    etag: Option<ETag>,
+    last_modified: Option<SystemTime>,
+}
+
+impl<T> IntoResponse for ConditionalResponse<T>
+where
+    T: IntoResponse,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self.response.into_response(request).map_err(Into::into)?;
+        Ok(evaluate(
+            response.map(Into::into),
+            request,
+            self.etag.as_ref(),
+            self.last_modified,
+        ))
+    }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+        let response = self.response.respond_to(input).map_err(Into::into)?;
+        Ok(evaluate(
+            response.map(Into::into),
+            input.request,
+            self.etag.as_ref(),
+            self.last_modified,
+        ))
+    }
+}
+
+fn evaluate(
+    response: Response<ResponseBody>,
+    request: &Request<()>,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> Response<ResponseBody> {
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+    let mut response = if is_not_modified(request, etag, last_modified) {
+        not_modified(response.headers())
+    } else {
+        response
+    };
+
+    if let Some(etag) = etag {
+        response.headers_mut().insert(
+            ETAG,
+            HeaderValue::from_shared(etag.to_string().into())
+                .expect("should be a valid header value"),
+        );
+    }
+    if let Some(last_modified) = last_modified {
+        response.headers_mut().insert(
+            LAST_MODIFIED,
+            HeaderValue::from_shared(http_date(last_modified).into())
+                .expect("should be a valid header value"),
+        );
+    }
+    response
+}
+
+/// Evaluates `If-None-Match` and `If-Modified-Since` in the request, and returns
+/// `true` if the representation identified by the validators is not modified.
+pub(crate) fn is_not_modified(
+    request: &Request<()>,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    if request.method() != Method::GET && request.method() != Method::HEAD {
+        return false;
+    }
+
+    let headers = request.headers();
+    if headers.contains_key(IF_NONE_MATCH) {
+        let etag = match etag {
+            Some(etag) => etag,
+            None => return false,
+        };
+        return headers
+            .get_all(IF_NONE_MATCH)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|s| s.split(','))
+            .map(str::trim)
+            .any(|s| {
+                s == "*"
+                    || match s.parse::<ETag>() {
+                        Ok(requested) => requested.weak_eq(etag),
+                        Err(..) => false,
+                    }
+            });
+    }
+
+    match (headers.get(IF_MODIFIED_SINCE), last_modified) {
+        (Some(h), Some(last_modified)) => {
+            match h.to_str().ok().and_then(parse_http_date) {
+                // HTTP-date has the resolution of one second.
+                Some(if_modified_since) => truncate_secs(last_modified) <= if_modified_since,
+                None => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Evaluates `If-Range` in the request, and returns `true` if `Range` should be applied.
+///
+/// The entity tag in `If-Range` matches only by the strong comparison, and the date
+/// matches only if it is exactly equal to the last modification date.  If the
+/// request does not have `If-Range`, this function always returns `true`.
+pub(crate) fn is_if_range_satisfied(
+    request: &Request<()>,
+    etag: Option<&ETag>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    let if_range = match request.headers().get(IF_RANGE) {
+        Some(h) => match h.to_str() {
+            Ok(s) => s.trim(),
+            Err(..) => return false,
+        },
+        None => return true,
+    };
+
+    if if_range.starts_with('"') || if_range.starts_with("W/") {
+        match (if_range.parse::<ETag>(), etag) {
+            (Ok(requested), Some(etag)) => requested.strong_eq(etag),
+            _ => false,
+        }
+    } else {
+        match (parse_http_date(if_range), last_modified) {
+            (Some(date), Some(last_modified)) => truncate_secs(last_modified) == date,
+            _ => false,
+        }
+    }
+}
+
+/// Creates a `304 Not Modified` response that keeps the cache-related header fields
+/// of the original response (RFC 7232, section 4.1).
+pub(crate) fn not_modified(headers: &HeaderMap) -> Response<ResponseBody> {
+    let mut response = Response::new(ResponseBody::empty());
+    *response.status_mut() = StatusCode::NOT_MODIFIED;
+    for name in &[
+        CACHE_CONTROL,
+        CONTENT_LOCATION,
+        DATE,
+        ETAG,
+        EXPIRES,
+        LAST_MODIFIED,
+        VARY,
+    ] {
+        for value in headers.get_all(name) {
+            response.headers_mut().append(name.clone(), value.clone());
+        }
+    }
+    response
+}
+
+/// Formats the time in the preferred format of HTTP-date (IMF-fixdate).
+pub(crate) fn http_date(time: SystemTime) -> String {
+    let secs = match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => duration.as_secs() as i64,
+        Err(err) => -(err.duration().as_secs() as i64),
+    };
+    time::at_utc(time::Timespec::new(secs, 0))
+        .rfc822()
+        .to_string()
+}
+
+/// Parses HTTP-date, in any of the formats listed in RFC 7231, section 7.1.1.1.
+pub(crate) fn parse_http_date(s: &str) -> Option<SystemTime> {
+    let tm = time::strptime(s, "%a, %d %b %Y %T %Z")
+        .or_else(|_| time::strptime(s, "%A, %d-%b-%y %T %Z"))
+        .or_else(|_| time::strptime(s, "%c"))
+        .ok()?;
+    let secs = tm.to_timespec().sec;
+    if secs >= 0 {
+        Some(UNIX_EPOCH + Duration::from_secs(secs as u64))
+    } else {
+        Some(UNIX_EPOCH - Duration::from_secs(secs.wrapping_neg() as u64))
+    }
+}
+
+fn truncate_secs(time: SystemTime) -> SystemTime {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(duration) => UNIX_EPOCH + Duration::from_secs(duration.as_secs()),
+        Err(..) => time,
+    }
+}
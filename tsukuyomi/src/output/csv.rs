@@ -0,0 +1,274 @@
+//! Components for streaming the records as CSV.
+//!
+//! This module is available only if the feature `csv` is enabled.
+//!
+//! # Example
+//!
+//! ```
+//! # use serde::Serialize;
+//! # use tsukuyomi::{config::prelude::*, output::csv::Csv, App};
+//! #[derive(Serialize)]
+//! struct Row {
+//!     id: u64,
+//!     name: String,
+//! }
+//!
+//! let app = App::create(
+//!     path!("/export.csv").to(endpoint::get().call(|| {
+//!         let rows = (0..1_000_000).map(|id| Row {
+//!             id,
+//!             name: format!("user-{}", id),
+//!         });
+//!         Csv::from_records(rows)
+//!             .batch_size(1024)
+//!             .attachment("users.csv")
+//!     })),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    super::{IntoResponse, ResponseBody},
+    crate::{error::Error, util::Never},
+    futures01::{stream::IterOk, Async, Poll, Stream},
+    http::{
+        header::{HeaderValue, CONTENT_DISPOSITION, CONTENT_TYPE},
+        Request, Response,
+    },
+    serde::Serialize,
+    std::{error::Error as StdError, fmt, fmt::Write as _Write, str},
+};
+
+type BoxedError = Box<dyn StdError + Send + Sync + 'static>;
+
+const DEFAULT_BATCH_SIZE: usize = 256;
+
+/// An `IntoResponse` that writes the records from a `Stream` as a streaming
+/// `text/csv` response.
+///
+/// The header row is derived from the field names of the first record, as long
+/// as the records are structs or maps.  The records are written in batches, and
+/// each batch is sent as a chunk so that the whole output is never buffered.
+///
+/// Since the response head has already been sent when the stream fails or a record
+/// cannot be written, such errors abort the transmission rather than being turned
+/// into an error response.  The records that would produce invalid UTF-8 (e.g. the
+/// fields serialized as raw bytes) are rejected in the same way.
+pub struct Csv<S> {
+    records: S,
+    delimiter: u8,
+    has_headers: bool,
+    batch_size: usize,
+    filename: Option<String>,
+}
+
+impl<S> fmt::Debug for Csv<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Csv")
+            .field("delimiter", &self.delimiter)
+            .field("has_headers", &self.has_headers)
+            .field("batch_size", &self.batch_size)
+            .field("filename", &self.filename)
+            .finish()
+    }
+}
+
+impl<I> Csv<IterOk<I, Never>>
+where
+    I: Iterator,
+{
+    /// Creates a `Csv` from an iterator of the records.
+    pub fn from_records<T>(records: T) -> Self
+    where
+        T: IntoIterator<IntoIter = I, Item = I::Item>,
+    {
+        Self::new(futures01::stream::iter_ok(records))
+    }
+}
+
+impl<S> Csv<S> {
+    /// Creates a `Csv` from a `Stream` of the records.
+    pub fn new(records: S) -> Self {
+        Self {
+            records,
+            delimiter: b',',
+            has_headers: true,
+            batch_size: DEFAULT_BATCH_SIZE,
+            filename: None,
+        }
+    }
+
+    /// Sets the field delimiter.
+    ///
+    /// The default value is `b','`.
+    pub fn delimiter(self, delimiter: u8) -> Self {
+        Self { delimiter, ..self }
+    }
+
+    /// Sets whether to write the header row.
+    ///
+    /// The default value is `true`.
+    pub fn has_headers(self, has_headers: bool) -> Self {
+        Self {
+            has_headers,
+            ..self
+        }
+    }
+
+    /// Sets the number of records written into a chunk.
+    ///
+    /// The pending records are also sent when the stream is not ready, so that the
+    /// client does not wait for a batch to be filled by a slow producer.
+    /// The default value is `256`.
+    ///
+    /// # Panics
+    /// This method panics if `batch_size` is zero.
+    pub fn batch_size(self, batch_size: usize) -> Self {
+        assert!(batch_size > 0, "the batch size must be positive");
+        Self { batch_size, ..self }
+    }
+
+    /// Adds the header field `Content-Disposition` that prompts the client to save
+    /// the response as a file with the specified name.
+    pub fn attachment(self, filename: impl Into<String>) -> Self {
+        Self {
+            filename: Some(filename.into()),
+            ..self
+        }
+    }
+}
+
+impl<S> IntoResponse for Csv<S>
+where
+    S: Stream + Send + 'static,
+    S::Item: Serialize,
+    S::Error: Into<BoxedError>,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let content_disposition = self
+            .filename
+            .as_ref()
+            .map(|filename| content_disposition(filename));
+
+        let mut response = Response::new(ResponseBody::wrap_stream(CsvStream {
+            records: self.records,
+            delimiter: self.delimiter,
+            headers_pending: self.has_headers,
+            batch_size: self.batch_size,
+            writer: None,
+            buffered: 0,
+            done: false,
+        }));
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/csv; charset=utf-8"),
+        );
+        if let Some(value) = content_disposition {
+            response.headers_mut().insert(CONTENT_DISPOSITION, value);
+        }
+        Ok(response)
+    }
+}
+
+fn content_disposition(filename: &str) -> HeaderValue {
+    // The ASCII fallback replaces the characters that cannot be in a quoted-string.
+    let fallback: String = filename
+        .chars()
+        .map(|c| match c {
+            ' '..='~' if c != '"' && c != '\\' => c,
+            _ => '_',
+        })
+        .collect();
+    let mut value = format!("attachment; filename=\"{}\"", fallback);
+    if fallback != filename {
+        // The extended parameter defined in RFC 6266, encoded by the rule of RFC 5987.
+        value.push_str("; filename*=UTF-8''");
+        for &b in filename.as_bytes() {
+            match b {
+                b'0'..=b'9' | b'A'..=b'Z' | b'a'..=b'z' | b'-' | b'.' | b'_' | b'~' => {
+                    value.push(b as char)
+                }
+                b => {
+                    let _ = write!(value, "%{:02X}", b);
+                }
+            }
+        }
+    }
+    HeaderValue::from_shared(value.into()).expect("should be a valid header value")
+}
+
+struct CsvStream<S> {
+    records: S,
+    delimiter: u8,
+    headers_pending: bool,
+    batch_size: usize,
+    writer: Option<::csv::Writer<Vec<u8>>>,
+    buffered: usize,
+    done: bool,
+}
+
+impl<S> CsvStream<S> {
+    fn write<T>(&mut self, record: &T) -> Result<(), BoxedError>
+    where
+        T: Serialize,
+    {
+        let delimiter = self.delimiter;
+        let has_headers = self.headers_pending;
+        let writer = self.writer.get_or_insert_with(|| {
+            ::csv::WriterBuilder::new()
+                .delimiter(delimiter)
+                .has_headers(has_headers)
+                .from_writer(vec![])
+        });
+        writer.serialize(record)?;
+        self.headers_pending = false;
+        self.buffered += 1;
+        Ok(())
+    }
+
+    fn take_chunk(&mut self) -> Result<Option<Vec<u8>>, BoxedError> {
+        let writer = match self.writer.take() {
+            Some(writer) => writer,
+            None => return Ok(None),
+        };
+        self.buffered = 0;
+        let chunk = writer.into_inner().map_err(|err| err.to_string())?;
+        // Each chunk consists of the complete records, so it can be validated alone.
+        if str::from_utf8(&chunk).is_err() {
+            return Err("the CSV record contains invalid UTF-8".into());
+        }
+        Ok(Some(chunk))
+    }
+}
+
+impl<S> Stream for CsvStream<S>
+where
+    S: Stream,
+    S::Item: Serialize,
+    S::Error: Into<BoxedError>,
+{
+    type Item = Vec<u8>;
+    type Error = BoxedError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        while !self.done {
+            match self.records.poll().map_err(Into::into)? {
+                Async::Ready(Some(record)) => {
+                    self.write(&record)?;
+                    if self.buffered >= self.batch_size {
+                        return self.take_chunk().map(Async::Ready);
+                    }
+                }
+                Async::Ready(None) => self.done = true,
+                Async::NotReady if self.buffered > 0 => {
+                    return self.take_chunk().map(Async::Ready);
+                }
+                Async::NotReady => return Ok(Async::NotReady),
+            }
+        }
+        self.take_chunk().map(Async::Ready)
+    }
+}
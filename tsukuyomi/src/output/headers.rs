@@ -0,0 +1,225 @@
+//! Components for supplying the default header fields to the responses.
+//!
+//! # Example
+//!
+//! ```
+//! # use std::time::Duration;
+//! # use tsukuyomi::{config::prelude::*, output::headers::{FrameOptions, SetHeaders}, App};
+//! # use http::header::{HeaderValue, SERVER};
+//! let security_headers = SetHeaders::new()
+//!     .hsts(Duration::from_secs(31_536_000))
+//!     .nosniff()
+//!     .frame_options(FrameOptions::Deny)
+//!     .insert(SERVER, HeaderValue::from_static("tsukuyomi"));
+//!
+//! let app = App::create(
+//!     mount("/admin")
+//!         .with(path!("/").to(endpoint::get().reply("admin")))
+//!         .modify(security_headers),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    crate::{
+        future::{Poll, TryFuture},
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+        input::{
+            localmap::{local_key, LocalData, LocalMap},
+            Input,
+        },
+    },
+    http::{
+        header::{
+            HeaderMap, HeaderName, HeaderValue, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS,
+            X_FRAME_OPTIONS,
+        },
+        Response,
+    },
+    std::{sync::Arc, time::Duration},
+};
+
+/// The value of `X-Frame-Options`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameOptions {
+    /// The page cannot be displayed in a frame.
+    Deny,
+    /// The page can be displayed in a frame only on the same origin.
+    SameOrigin,
+}
+
+impl FrameOptions {
+    fn to_header_value(self) -> HeaderValue {
+        match self {
+            FrameOptions::Deny => HeaderValue::from_static("DENY"),
+            FrameOptions::SameOrigin => HeaderValue::from_static("SAMEORIGIN"),
+        }
+    }
+}
+
+/// A `ModifyHandler` that supplies the default header fields to the responses in a scope.
+///
+/// The default values of a field are added only if the response does not have
+/// the field at all, so the values set by the handlers (or by the responders such as
+/// `output::attachment`) always take precedence.  The defaults are also applied to
+/// the error responses produced within the scope, including the ones created from
+/// the errors returned by the handlers.
+///
+/// When the modifiers are nested, the default values configured by the inner scope
+/// replace the ones with the same name configured by the outer scope.
+#[derive(Debug, Clone, Default)]
+pub struct SetHeaders {
+    headers: Arc<HeaderMap>,
+}
+
+impl SetHeaders {
+    /// Creates an empty `SetHeaders`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the default value of the specified header field.
+    ///
+    /// The values of the same name configured previously are discarded.
+    pub fn insert(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        Arc::make_mut(&mut self.headers).insert(name, value);
+        self
+    }
+
+    /// Adds a default value of the specified header field.
+    ///
+    /// Unlike `insert`, the values of the same name configured previously are kept
+    /// and all of them are added to the response.
+    pub fn append(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        Arc::make_mut(&mut self.headers).append(name, value);
+        self
+    }
+
+    /// Sets `Strict-Transport-Security` with the specified `max-age`.
+    pub fn hsts(self, max_age: Duration) -> Self {
+        let value = format!("max-age={}", max_age.as_secs());
+        self.insert(
+            STRICT_TRANSPORT_SECURITY,
+            HeaderValue::from_shared(value.into()).expect("should be a valid header value"),
+        )
+    }
+
+    /// Sets `X-Content-Type-Options: nosniff`.
+    pub fn nosniff(self) -> Self {
+        self.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"))
+    }
+
+    /// Sets `X-Frame-Options` with the specified value.
+    pub fn frame_options(self, options: FrameOptions) -> Self {
+        self.insert(X_FRAME_OPTIONS, options.to_header_value())
+    }
+}
+
+impl<H> ModifyHandler<H> for SetHeaders
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Handler = SetHeadersHandler<H>; // private
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        SetHeadersHandler {
+            inner,
+            headers: self.headers.clone(),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct SetHeadersHandler<H> {
+    inner: H,
+    headers: Arc<HeaderMap>,
+}
+
+impl<H> Handler for SetHeadersHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = H::Error;
+    type Handle = HandleSetHeaders<H::Handle>; // private
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        HandleSetHeaders {
+            inner: self.inner.handle(),
+            headers: Some(self.headers.clone()),
+        }
+    }
+}
+
+#[allow(missing_debug_implementations)]
+pub struct HandleSetHeaders<H> {
+    inner: H,
+    headers: Option<Arc<HeaderMap>>,
+}
+
+impl<H> TryFuture for HandleSetHeaders<H>
+where
+    H: TryFuture,
+{
+    type Ok = H::Ok;
+    type Error = H::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        // The defaults are registered before polling the inner handle, so that they are
+        // applied even if it fails.  The registered values are merged into the response
+        // by the application just before replying.
+        //
+        // The modifiers of the inner scopes wrap the ones of the outer scopes, so the
+        // names registered earlier are kept.
+        if let Some(headers) = self.headers.take() {
+            let defaults = DefaultHeaders::entry(input.locals).or_insert_with(Default::default);
+            for name in headers.keys() {
+                if defaults.0.contains_key(name) {
+                    continue;
+                }
+                for value in headers.get_all(name) {
+                    defaults.0.append(name.clone(), value.clone());
+                }
+            }
+        }
+        self.inner.poll_ready(input)
+    }
+}
+
+/// The default header fields registered by `SetHeaders` during the request.
+#[derive(Debug, Default)]
+struct DefaultHeaders(HeaderMap);
+
+impl LocalData for DefaultHeaders {
+    local_key! {
+        const KEY: Self;
+    }
+}
+
+/// Returns whether the default value of the header field is registered in `locals`.
+pub(crate) fn has_default_header(locals: &LocalMap, name: &HeaderName) -> bool {
+    DefaultHeaders::get(locals).map_or(false, |defaults| defaults.0.contains_key(name))
+}
+
+/// Adds the default header fields registered in `locals` to the response.
+pub(crate) fn apply_default_headers<T>(locals: &mut LocalMap, response: &mut Response<T>) {
+    if let Some(DefaultHeaders(mut defaults)) = DefaultHeaders::take_from(locals) {
+        for (name, values) in defaults.drain() {
+            if response.headers().contains_key(&name) {
+                continue;
+            }
+            for value in values {
+                response.headers_mut().append(name.clone(), value);
+            }
+        }
+    }
+}
@@ -0,0 +1,229 @@
+//! Components for building HTML responses without a template engine.
+//!
+//! The text passed to the helpers in this module is escaped unless it is explicitly
+//! marked as trusted markup with `raw`, so that user-supplied values can be embedded
+//! into the page safely.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, output::html, App};
+//! let app = App::create(
+//!     path!("/hello/:name").to(endpoint::get().call(|name: String| {
+//!         html::document()
+//!             .title("Greeting")
+//!             .body(html::raw("<p>Hello, ").text(&name).raw("!</p>"))
+//!     })),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    super::IntoResponse,
+    crate::util::Never,
+    http::{header, Request, Response},
+    std::{borrow::Cow, fmt},
+};
+
+/// Escapes the characters in `text` that have special meanings in HTML.
+///
+/// The result can be used both as a text node and as a quoted attribute value.
+pub fn escape(text: &str) -> Cow<'_, str> {
+    let mut escaped = String::new();
+    let mut last = 0;
+    for (i, ch) in text.char_indices() {
+        let replacement = match ch {
+            '&' => "&amp;",
+            '<' => "&lt;",
+            '>' => "&gt;",
+            '"' => "&quot;",
+            '\'' => "&#x27;",
+            _ => continue,
+        };
+        escaped.push_str(&text[last..i]);
+        escaped.push_str(replacement);
+        last = i + 1;
+    }
+
+    if last == 0 {
+        Cow::Borrowed(text)
+    } else {
+        escaped.push_str(&text[last..]);
+        Cow::Owned(escaped)
+    }
+}
+
+/// Creates a `Markup` from the escaped `text`.
+pub fn text(text: &str) -> Markup {
+    Markup::new().text(text)
+}
+
+/// Creates a `Markup` from the trusted markup, without escaping.
+pub fn raw(markup: &str) -> Markup {
+    Markup::new().raw(markup)
+}
+
+/// Creates an empty `Document`.
+pub fn document() -> Document {
+    Document::default()
+}
+
+/// A fragment of HTML.
+///
+/// The value is built by appending the escaped texts and the trusted markups, and
+/// can be returned from the handlers directly (with `Content-type: text/html`) or
+/// embedded into a `Document`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Markup {
+    buf: String,
+}
+
+impl Markup {
+    /// Creates an empty `Markup`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the specified text, with escaping.
+    pub fn text(mut self, text: &str) -> Self {
+        self.buf.push_str(&escape(text));
+        self
+    }
+
+    /// Appends the specified trusted markup, without escaping.
+    pub fn raw(mut self, markup: &str) -> Self {
+        self.buf.push_str(markup);
+        self
+    }
+
+    /// Appends another fragment.
+    pub fn append(mut self, other: Markup) -> Self {
+        self.buf.push_str(&other.buf);
+        self
+    }
+
+    /// Appends an element with the specified tag name and content.
+    ///
+    /// The tag name is treated as trusted and is not validated.
+    pub fn element(mut self, tag: &str, content: Markup) -> Self {
+        self.buf.push('<');
+        self.buf.push_str(tag);
+        self.buf.push('>');
+        self.buf.push_str(&content.buf);
+        self.buf.push_str("</");
+        self.buf.push_str(tag);
+        self.buf.push('>');
+        self
+    }
+
+    /// Returns the string slice of the built markup.
+    pub fn as_str(&self) -> &str {
+        &self.buf
+    }
+
+    /// Consumes itself and returns the built markup.
+    pub fn into_string(self) -> String {
+        self.buf
+    }
+}
+
+impl fmt::Display for Markup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.buf)
+    }
+}
+
+impl IntoResponse for Markup {
+    type Body = String;
+    type Error = Never;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        Ok(make_html_response(self.buf))
+    }
+}
+
+/// A builder of a complete HTML document.
+///
+/// The response is sent with `Content-type: text/html; charset=utf-8` and
+/// `Content-Length`.
+#[derive(Debug, Clone, Default)]
+pub struct Document {
+    lang: Option<String>,
+    title: Option<String>,
+    head: Markup,
+    body: Markup,
+}
+
+impl Document {
+    /// Sets the value of attribute `lang` of the root element.
+    pub fn lang(self, lang: impl Into<String>) -> Self {
+        Self {
+            lang: Some(lang.into()),
+            ..self
+        }
+    }
+
+    /// Sets the title of the document.
+    ///
+    /// The value is escaped when rendered.
+    pub fn title(self, title: impl Into<String>) -> Self {
+        Self {
+            title: Some(title.into()),
+            ..self
+        }
+    }
+
+    /// Appends a fragment to the content of `<head>`.
+    pub fn head(mut self, fragment: Markup) -> Self {
+        self.head = self.head.append(fragment);
+        self
+    }
+
+    /// Appends a fragment to the content of `<body>`.
+    pub fn body(mut self, fragment: Markup) -> Self {
+        self.body = self.body.append(fragment);
+        self
+    }
+
+    /// Renders the document into a string.
+    pub fn render(&self) -> String {
+        let mut buf = String::from("<!DOCTYPE html>\n");
+        match self.lang {
+            Some(ref lang) => {
+                buf.push_str("<html lang=\"");
+                buf.push_str(&escape(lang));
+                buf.push_str("\">");
+            }
+            None => buf.push_str("<html>"),
+        }
+        buf.push_str("<head><meta charset=\"utf-8\">");
+        if let Some(ref title) = self.title {
+            buf.push_str("<title>");
+            buf.push_str(&escape(title));
+            buf.push_str("</title>");
+        }
+        buf.push_str(self.head.as_str());
+        buf.push_str("</head><body>");
+        buf.push_str(self.body.as_str());
+        buf.push_str("</body></html>");
+        buf
+    }
+}
+
+impl IntoResponse for Document {
+    type Body = String;
+    type Error = Never;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        Ok(make_html_response(self.render()))
+    }
+}
+
+fn make_html_response(body: String) -> Response<String> {
+    let content_length = body.len();
+    let mut response = super::make_response(body, "text/html; charset=utf-8");
+    response
+        .headers_mut()
+        .insert(header::CONTENT_LENGTH, content_length.into());
+    response
+}
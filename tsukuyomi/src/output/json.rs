@@ -0,0 +1,183 @@
+use {
+    super::IntoResponse,
+    crate::error::Error,
+    http::{
+        header::{HeaderValue, CONTENT_LENGTH, CONTENT_TYPE},
+        Request, Response,
+    },
+    mime::Mime,
+    serde::Serialize,
+    std::{fmt, sync::Arc},
+};
+
+type PrettyFn = dyn Fn(&Request<()>) -> bool + Send + Sync + 'static;
+
+#[derive(Clone)]
+enum Pretty {
+    Fixed(bool),
+    When(Arc<PrettyFn>),
+}
+
+impl fmt::Debug for Pretty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pretty::Fixed(pretty) => f.debug_tuple("Fixed").field(pretty).finish(),
+            Pretty::When(..) => f.debug_tuple("When").finish(),
+        }
+    }
+}
+
+impl Pretty {
+    fn is_pretty(&self, request: &Request<()>) -> bool {
+        match self {
+            Pretty::Fixed(pretty) => *pretty,
+            Pretty::When(f) => f(request),
+        }
+    }
+}
+
+/// A set of settings for serializing the values into JSON responses.
+///
+/// The value of this type is typically shared among the handlers as a state or
+/// a scope configuration, and applied to the responses by `Json::config`.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::output::{Json, JsonConfig};
+/// // pretty-print the responses when the query contains `pretty=1`.
+/// let config = JsonConfig::new().pretty_when(|request| match request.uri().query() {
+///     Some(query) => query.split('&').any(|pair| pair == "pretty=1"),
+///     None => false,
+/// });
+/// let response = Json::new(vec![1, 2, 3]).config(&config);
+/// # drop(response);
+/// ```
+#[derive(Debug, Clone)]
+pub struct JsonConfig {
+    pretty: Pretty,
+    content_type: Option<Mime>,
+}
+
+impl Default for JsonConfig {
+    fn default() -> Self {
+        Self {
+            pretty: Pretty::Fixed(false),
+            content_type: None,
+        }
+    }
+}
+
+impl JsonConfig {
+    /// Creates a `JsonConfig` with the default settings.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets whether to pretty-print the responses.
+    pub fn pretty(self, pretty: bool) -> Self {
+        Self {
+            pretty: Pretty::Fixed(pretty),
+            ..self
+        }
+    }
+
+    /// Pretty-prints the responses only if the specified function returns `true`
+    /// for the request.
+    pub fn pretty_when<F>(self, f: F) -> Self
+    where
+        F: Fn(&Request<()>) -> bool + Send + Sync + 'static,
+    {
+        Self {
+            pretty: Pretty::When(Arc::new(f)),
+            ..self
+        }
+    }
+
+    /// Sets the media type of the responses, such as `application/vnd.api+json`.
+    ///
+    /// The default value is `application/json`.
+    pub fn content_type(self, content_type: Mime) -> Self {
+        Self {
+            content_type: Some(content_type),
+            ..self
+        }
+    }
+}
+
+/// An `IntoResponse` that serializes the value into a JSON response.
+///
+/// The response has the header fields `Content-Type` and `Content-Length`.
+#[derive(Debug)]
+pub struct Json<T> {
+    data: T,
+    config: JsonConfig,
+}
+
+impl<T> Json<T>
+where
+    T: Serialize,
+{
+    /// Creates a `Json` with the default settings.
+    pub fn new(data: T) -> Self {
+        Self {
+            data,
+            config: JsonConfig::default(),
+        }
+    }
+
+    /// Enables pretty-printing of the output.
+    pub fn pretty(self) -> Self {
+        Self {
+            config: self.config.pretty(true),
+            ..self
+        }
+    }
+
+    /// Sets the media type of the response, such as `application/vnd.api+json`.
+    pub fn content_type(self, content_type: Mime) -> Self {
+        Self {
+            config: self.config.content_type(content_type),
+            ..self
+        }
+    }
+
+    /// Replaces the settings with the specified ones.
+    pub fn config(self, config: &JsonConfig) -> Self {
+        Self {
+            config: config.clone(),
+            ..self
+        }
+    }
+}
+
+impl<T> IntoResponse for Json<T>
+where
+    T: Serialize,
+{
+    type Body = Vec<u8>;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let body = if self.config.pretty.is_pretty(request) {
+            serde_json::to_vec_pretty(&self.data)
+        } else {
+            serde_json::to_vec(&self.data)
+        }
+        .map_err(crate::error::internal_server_error)?;
+
+        let content_type = match self.config.content_type {
+            Some(ref content_type) => HeaderValue::from_str(content_type.as_ref())
+                .expect("the media type should be a valid header value"),
+            None => HeaderValue::from_static("application/json"),
+        };
+        let content_length = HeaderValue::from(body.len());
+
+        let mut response = Response::new(body);
+        response.headers_mut().insert(CONTENT_TYPE, content_type);
+        response
+            .headers_mut()
+            .insert(CONTENT_LENGTH, content_length);
+        Ok(response)
+    }
+}
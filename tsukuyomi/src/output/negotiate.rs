@@ -0,0 +1,274 @@
+//! Components for switching the representation of responses by `Accept`.
+//!
+//! # Example
+//!
+//! ```
+//! # use serde::Serialize;
+//! # use tsukuyomi::{config::prelude::*, output::negotiate, App};
+//! #[derive(Serialize)]
+//! struct User {
+//!     name: String,
+//! }
+//!
+//! let app = App::create(
+//!     path!("/user").to(endpoint::get().call(|| {
+//!         negotiate(User { name: "alice".into() })
+//!             .html(|user| Ok::<_, tsukuyomi::Error>(format!("<p>{}</p>", user.name)))
+//!             .plain(|user| Ok::<_, tsukuyomi::Error>(user.name.clone()))
+//!     })),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    super::ResponseBody,
+    crate::{
+        app::ScopeConfigs,
+        error::Error,
+        extractor::accept::Accept,
+        future::{Poll, TryFuture},
+        input::{localmap::LocalData, Input},
+        responder::Responder,
+    },
+    http::{
+        header::{HeaderValue, CONTENT_TYPE, VARY},
+        Response,
+    },
+    mime::Mime,
+    serde::Serialize,
+    std::{fmt, sync::Arc},
+};
+
+type RenderFn<T> = Box<dyn FnMut(&T) -> Result<ResponseBody, Error> + Send + 'static>;
+type RenderValueFn = dyn Fn(&serde_json::Value) -> Result<ResponseBody, Error> + Send + Sync;
+
+/// Creates a `Responder` that chooses the representation of `value` by `Accept`.
+///
+/// The candidates of representations are examined in the following order, and the
+/// first one with the highest quality in `Accept` is selected:
+///
+/// 1. `application/json`, serialized by `serde_json`,
+/// 2. the renderers specified by `Negotiate::render` and its shortcuts, in the
+///    order of registration,
+/// 3. the renderers in `Renderers` registered in the scope with `config::scope_config`.
+///
+/// If none of them is acceptable, the response is `406 Not Acceptable`.
+/// The header field `Vary: Accept` is appended to the successful responses.
+pub fn negotiate<T>(value: T) -> Negotiate<T>
+where
+    T: Serialize + Send + 'static,
+{
+    Negotiate {
+        value,
+        renderers: vec![],
+    }
+}
+
+/// A `Responder` that switches the representation of a value by `Accept`.
+///
+/// The value of this type is created by `negotiate`.
+pub struct Negotiate<T> {
+    value: T,
+    renderers: Vec<(Mime, RenderFn<T>)>,
+}
+
+impl<T> fmt::Debug for Negotiate<T>
+where
+    T: fmt::Debug,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Negotiate")
+            .field("value", &self.value)
+            .field(
+                "renderers",
+                &self.renderers.iter().map(|(m, _)| m).collect::<Vec<_>>(),
+            )
+            .finish()
+    }
+}
+
+impl<T> Negotiate<T>
+where
+    T: Serialize + Send + 'static,
+{
+    /// Adds a renderer for the specified media type.
+    pub fn render<F, B, E>(mut self, media_type: Mime, f: F) -> Self
+    where
+        F: FnOnce(&T) -> Result<B, E> + Send + 'static,
+        B: Into<ResponseBody>,
+        E: Into<Error>,
+    {
+        let mut f = Some(f);
+        self.renderers.push((
+            media_type,
+            Box::new(move |value| {
+                let f = f
+                    .take()
+                    .expect("the renderer should be called at most once");
+                f(value).map(Into::into).map_err(Into::into)
+            }),
+        ));
+        self
+    }
+
+    /// Adds a renderer for `text/html; charset=utf-8`.
+    pub fn html<F, B, E>(self, f: F) -> Self
+    where
+        F: FnOnce(&T) -> Result<B, E> + Send + 'static,
+        B: Into<ResponseBody>,
+        E: Into<Error>,
+    {
+        self.render(mime::TEXT_HTML_UTF_8, f)
+    }
+
+    /// Adds a renderer for `text/plain; charset=utf-8`.
+    pub fn plain<F, B, E>(self, f: F) -> Self
+    where
+        F: FnOnce(&T) -> Result<B, E> + Send + 'static,
+        B: Into<ResponseBody>,
+        E: Into<Error>,
+    {
+        self.render(mime::TEXT_PLAIN_UTF_8, f)
+    }
+
+    fn respond_to(self, input: &mut Input<'_>) -> Result<Response<ResponseBody>, Error> {
+        let scope_renderers = ScopeConfigs::get(input.locals) //
+            .and_then(|configs| configs.find::<Renderers>());
+
+        let mut candidates = vec![mime::APPLICATION_JSON];
+        candidates.extend(self.renderers.iter().map(|(mime, _)| mime.clone()));
+        if let Some(ref renderers) = scope_renderers {
+            candidates.extend(renderers.renderers.iter().map(|(mime, _)| mime.clone()));
+        }
+
+        let accept = Accept::from_headers(input.request.headers());
+        let selected = accept.negotiate(&candidates)?;
+        let index = candidates
+            .iter()
+            .position(|mime| std::ptr::eq(mime, selected))
+            .expect("the selected media type should be one of the candidates");
+
+        let Self {
+            value,
+            mut renderers,
+        } = self;
+        let body = if index == 0 {
+            serde_json::to_vec(&value)
+                .map(Into::into)
+                .map_err(crate::error::internal_server_error)?
+        } else if index <= renderers.len() {
+            let (_, mut render) = renderers.swap_remove(index - 1);
+            render(&value)?
+        } else {
+            let (_, ref render) = scope_renderers
+                .as_ref()
+                .expect("the scope renderers should be available")
+                .renderers[index - 1 - renderers.len()];
+            let value =
+                serde_json::to_value(&value).map_err(crate::error::internal_server_error)?;
+            render(&value)?
+        };
+
+        let mut response = Response::new(body);
+        response.headers_mut().insert(
+            CONTENT_TYPE,
+            HeaderValue::from_str(selected.as_ref()).expect("the media type should be valid"),
+        );
+        response
+            .headers_mut()
+            .append(VARY, HeaderValue::from_static("accept"));
+        Ok(response)
+    }
+}
+
+impl<T> Responder for Negotiate<T>
+where
+    T: Serialize + Send + 'static,
+{
+    type Response = Response<ResponseBody>;
+    type Error = Error;
+    type Respond = NegotiateRespond<T>;
+
+    fn respond(self) -> Self::Respond {
+        NegotiateRespond(Some(self))
+    }
+}
+
+#[doc(hidden)]
+#[allow(missing_debug_implementations)]
+pub struct NegotiateRespond<T>(Option<Negotiate<T>>);
+
+impl<T> TryFuture for NegotiateRespond<T>
+where
+    T: Serialize + Send + 'static,
+{
+    type Ok = Response<ResponseBody>;
+    type Error = Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let negotiate = self.0.take().expect("the future has already been polled");
+        negotiate.respond_to(input).map(Into::into)
+    }
+}
+
+/// A set of renderers for additional media types used by `negotiate`, registered
+/// as a scope configuration.
+///
+/// The renderers receive the value converted into `serde_json::Value`.  As with the
+/// other scope configurations, the set registered in the innermost scope is used.
+///
+/// # Example
+///
+/// ```
+/// # use serde::Serialize;
+/// # use tsukuyomi::{config::prelude::*, output::{negotiate, negotiate::Renderers}, App};
+/// #[derive(Serialize)]
+/// struct User {
+///     name: String,
+/// }
+///
+/// let app = App::create(
+///     mount("/api")
+///         .with(scope_config(Renderers::new().register(
+///             "application/x-yaml".parse().unwrap(),
+///             |value| Ok::<_, tsukuyomi::Error>(format!("name: {}\n", value["name"])),
+///         )))
+///         .with(path!("/user").to(endpoint::get().call(|| {
+///             negotiate(User { name: "alice".into() })
+///         }))),
+/// );
+/// # app.unwrap();
+/// ```
+#[derive(Default)]
+pub struct Renderers {
+    renderers: Vec<(Mime, Arc<RenderValueFn>)>,
+}
+
+impl fmt::Debug for Renderers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.renderers.iter().map(|(mime, _)| mime))
+            .finish()
+    }
+}
+
+impl Renderers {
+    /// Creates an empty set of renderers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a renderer for the specified media type.
+    pub fn register<F, B, E>(mut self, media_type: Mime, f: F) -> Self
+    where
+        F: Fn(&serde_json::Value) -> Result<B, E> + Send + Sync + 'static,
+        B: Into<ResponseBody>,
+        E: Into<Error>,
+    {
+        self.renderers.push((
+            media_type,
+            Arc::new(move |value| f(value).map(Into::into).map_err(Into::into)),
+        ));
+        self
+    }
+}
@@ -1,9 +1,54 @@
+//! Responders for redirecting the client to another location.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::{prelude::*, Scope}, output::redirect, App};
+//! let app = App::create(|scope: &mut Scope<'_, _, _>| {
+//!     let login = scope.route_ref("login");
+//!     chain![
+//!         path!("/login")
+//!             .to(endpoint::get().reply("login form"))
+//!             .name("login"),
+//!         path!("/admin").to(endpoint::get().call(move || redirect::to_route(&login, ()))),
+//!         path!("/old").to(endpoint::get().call(|| redirect::permanent("/new"))),
+//!     ]
+//!     .configure(scope)
+//! });
+//! # app.unwrap();
+//! ```
+
 use {
     super::*,
-    http::{Response, StatusCode},
+    crate::app::{Mounted, RouteNames},
+    http::{
+        header::{HOST, LOCATION, REFERER},
+        Response, StatusCode,
+    },
     std::borrow::Cow,
 };
 
+/// A reference to the route registered with a name by `Route::name`.
+///
+/// The value is obtained by `Scope::route_ref`, and the creation of `App` fails
+/// if the route with the name is not registered.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RouteRef {
+    name: Cow<'static, str>,
+}
+
+impl RouteRef {
+    pub(crate) fn new(name: Cow<'static, str>) -> Self {
+        Self { name }
+    }
+
+    /// Returns the name of route.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+/// An `IntoResponse` that redirects the client to the specified location.
 #[derive(Debug, Clone)]
 pub struct Redirect {
     status: StatusCode,
@@ -11,6 +56,7 @@ pub struct Redirect {
 }
 
 impl Redirect {
+    /// Creates a `Redirect` with the specified status code and location.
     pub fn new<T>(status: StatusCode, location: T) -> Self
     where
         T: Into<Cow<'static, str>>,
@@ -20,6 +66,16 @@ impl Redirect {
             location: location.into(),
         }
     }
+
+    /// Returns the status code of this redirect.
+    pub fn status(&self) -> StatusCode {
+        self.status
+    }
+
+    /// Returns the location of this redirect.
+    pub fn location(&self) -> &str {
+        &self.location
+    }
 }
 
 impl IntoResponse for Redirect {
@@ -30,14 +86,15 @@ impl IntoResponse for Redirect {
     fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
         Ok(Response::builder()
             .status(self.status)
-            .header("location", &*self.location)
+            .header(LOCATION, &*self.location)
             .body(())
             .expect("should be a valid response"))
     }
 }
 
 macro_rules! define_funcs {
-        ($( $name:ident => $STATUS:ident, )*) => {$(
+        ($( $(#[$m:meta])* $name:ident => $STATUS:ident, )*) => {$(
+            $(#[$m])*
             #[inline]
             pub fn $name<T>(location: T) -> Redirect
             where
@@ -49,10 +106,147 @@ macro_rules! define_funcs {
     }
 
 define_funcs! {
+    /// Creates a `Redirect` with `301 Moved Permanently`.
     moved_permanently => MOVED_PERMANENTLY,
+    /// Creates a `Redirect` with `302 Found`.
     found => FOUND,
+    /// Creates a `Redirect` with `303 See Other`.
     see_other => SEE_OTHER,
+    /// Creates a `Redirect` with `307 Temporary Redirect`.
+    temporary => TEMPORARY_REDIRECT,
+    /// Creates a `Redirect` with `308 Permanent Redirect`.
+    permanent => PERMANENT_REDIRECT,
+    #[doc(hidden)]
     temporary_redirect => TEMPORARY_REDIRECT,
+    #[doc(hidden)]
     permanent_redirect => PERMANENT_REDIRECT,
+    /// Creates a `Redirect` with `301 Moved Permanently`.
+    ///
+    /// This function is equivalent to `moved_permanently`.
     to => MOVED_PERMANENTLY,
 }
+
+/// Creates an `IntoResponse` that redirects the client back to the previous page
+/// indicated by `Referer`, or to `fallback` if it is not available.
+///
+/// The value of `Referer` is used only if it refers to the same host as the request,
+/// in order to avoid redirecting to an arbitrary site.  The response has the status
+/// code `303 See Other`.
+pub fn back<T>(fallback: T) -> Back
+where
+    T: Into<Cow<'static, str>>,
+{
+    Back {
+        fallback: fallback.into(),
+    }
+}
+
+/// The `IntoResponse` created by `back`.
+#[derive(Debug, Clone)]
+pub struct Back {
+    fallback: Cow<'static, str>,
+}
+
+impl IntoResponse for Back {
+    type Body = ();
+    type Error = Never;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let location = match referer_path(request) {
+            Some(path) => Cow::Owned(path),
+            None => self.fallback,
+        };
+        see_other(location).into_response(request)
+    }
+}
+
+fn referer_path(request: &Request<()>) -> Option<String> {
+    let referer: http::Uri = request
+        .headers()
+        .get(REFERER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    if let Some(authority) = referer.authority_part() {
+        let host = request
+            .uri()
+            .authority_part()
+            .map(|authority| authority.as_str())
+            .or_else(|| request.headers().get(HOST)?.to_str().ok())?;
+        if !authority.as_str().eq_ignore_ascii_case(host) {
+            return None;
+        }
+    }
+    let path_and_query = referer.path_and_query()?;
+    if !path_and_query.path().starts_with('/') {
+        return None;
+    }
+    Some(path_and_query.as_str().to_owned())
+}
+
+/// Creates an `IntoResponse` that redirects the client to the route referred by `route`.
+///
+/// The parameters in the path of route are filled with `params`, which must be
+/// serialized into a map (e.g. a struct) whose fields are matched by name, or a
+/// sequence (e.g. a tuple) whose elements are used in order.  The unused fields
+/// of map are appended as the query string, and `()` is used for the routes
+/// without parameters.  The response has the status code `302 Found` by default.
+///
+/// The location includes the prefix of mount point set by `config::MountPoint`.
+///
+/// The existence of the route is checked when the application is created, since
+/// `route` is obtained by `Scope::route_ref`.  If the parameters are invalid, or
+/// `route` is used in another application, the response becomes
+/// `500 Internal Server Error`.
+pub fn to_route<P>(route: &RouteRef, params: P) -> RouteRedirect<P>
+where
+    P: Serialize,
+{
+    RouteRedirect {
+        status: StatusCode::FOUND,
+        name: route.name.clone(),
+        params,
+    }
+}
+
+/// The `IntoResponse` created by `to_route`.
+#[derive(Debug, Clone)]
+pub struct RouteRedirect<P> {
+    status: StatusCode,
+    name: Cow<'static, str>,
+    params: P,
+}
+
+impl<P> RouteRedirect<P>
+where
+    P: Serialize,
+{
+    /// Sets the status code of the response.
+    pub fn status(self, status: StatusCode) -> Self {
+        Self { status, ..self }
+    }
+}
+
+impl<P> IntoResponse for RouteRedirect<P>
+where
+    P: Serialize,
+{
+    type Body = ();
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let mut location = request
+            .extensions()
+            .get::<RouteNames>()
+            .ok_or_else(|| failure::format_err!("no route named `{}`", self.name))
+            .and_then(|names| names.url_for(&self.name, &self.params))
+            .map_err(crate::error::internal_server_error)?;
+        if let Some(mounted) = request.extensions().get::<Mounted>() {
+            location = mounted.to_external(&location);
+        }
+        Redirect::new(self.status, location)
+            .into_response(request)
+            .map_err(Into::into)
+    }
+}
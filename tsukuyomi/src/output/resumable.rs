@@ -0,0 +1,152 @@
+//! Components for serving the dynamically generated content that supports
+//! resumable downloads.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, output::resumable, App};
+//! const DATA: &[u8] = b"The quick brown fox jumps over the lazy dog";
+//!
+//! let app = App::create(
+//!     path!("/export").to(endpoint::get().call(|| {
+//!         resumable("v1", DATA.len() as u64, |range| {
+//!             let chunk = &DATA[range.start as usize..range.end as usize];
+//!             futures01::stream::once::<_, std::io::Error>(Ok(chunk))
+//!         })
+//!     })),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    super::{conditional, IntoResponse, ResponseBody},
+    crate::{
+        error::Error,
+        etag::ETag,
+        extractor::range::{ByteRange, Range},
+    },
+    bytes::IntoBuf,
+    futures01::Stream,
+    http::{
+        header::{HeaderValue, ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG},
+        Method, Request, Response, StatusCode,
+    },
+    std::{error::Error as StdError, fmt, ops, time::SystemTime},
+};
+
+type BoxedError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// Creates an `IntoResponse` that serves a representation identified by the
+/// specified entity tag, generating only the part requested by `Range`.
+///
+/// The entity tag is sent as a strong validator, and it must change whenever the
+/// content changes.  The closure receives the range of bytes to be sent, and must
+/// return a `Stream` that produces exactly those bytes.
+///
+/// The requests are handled as follows:
+///
+/// * If the request is not `GET`, or it does not have a valid `Range`, the whole
+///   representation is sent with `200 OK`.
+/// * If `If-Range` does not match the entity tag by the strong comparison, `Range`
+///   is ignored and the whole representation is sent with `200 OK`.  The dates in
+///   `If-Range` never match, since the representation has no modification date.
+/// * If none of the ranges is satisfiable, the response is `416 Range Not Satisfiable`.
+/// * If the ranges are coalesced into a single range, it is sent with `206 Partial Content`.
+///   Otherwise, `Range` is ignored since `multipart/byteranges` is not supported.
+///
+/// # Panics
+/// This function panics if the entity tag contains characters other than
+/// the visible ASCII characters except `"`.
+pub fn resumable<F, S>(etag: impl Into<String>, total_len: u64, f: F) -> Resumable<F>
+where
+    F: FnOnce(ops::Range<u64>) -> S,
+    S: Stream + Send + 'static,
+    S::Item: IntoBuf,
+    S::Error: Into<BoxedError>,
+{
+    Resumable {
+        etag: ETag::strong(etag),
+        total_len,
+        f,
+    }
+}
+
+/// An `IntoResponse` that serves the requested range of dynamically generated content.
+///
+/// The value of this type is created by `resumable`.
+pub struct Resumable<F> {
+    etag: ETag,
+    total_len: u64,
+    f: F,
+}
+
+impl<F> fmt::Debug for Resumable<F> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resumable")
+            .field("etag", &self.etag)
+            .field("total_len", &self.total_len)
+            .finish()
+    }
+}
+
+impl<F, S> IntoResponse for Resumable<F>
+where
+    F: FnOnce(ops::Range<u64>) -> S,
+    S: Stream + Send + 'static,
+    S::Item: IntoBuf,
+    S::Error: Into<BoxedError>,
+{
+    type Body = ResponseBody;
+    type Error = Error;
+
+    fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let requested = requested_range(request, &self.etag, None, self.total_len)?;
+        let (status, range) = match requested {
+            Some(ref range) => (StatusCode::PARTIAL_CONTENT, range.first()..range.last() + 1),
+            None => (StatusCode::OK, 0..self.total_len),
+        };
+
+        let mut response = Response::new(ResponseBody::wrap_stream((self.f)(range.clone())));
+        *response.status_mut() = status;
+        let headers = response.headers_mut();
+        headers.insert(ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+        headers.insert(
+            ETAG,
+            HeaderValue::from_shared(self.etag.to_string().into())
+                .expect("should be a valid header value"),
+        );
+        headers.insert(CONTENT_LENGTH, (range.end - range.start).into());
+        if let Some(range) = requested {
+            headers.insert(CONTENT_RANGE, range.to_content_range(self.total_len));
+        }
+        Ok(response)
+    }
+}
+
+/// Returns the single range of bytes requested by `Range`, if it should be applied.
+///
+/// `Range` is ignored if the request is not `GET`, `If-Range` does not match the
+/// validators, or multiple ranges remain after coalescing.
+pub(crate) fn requested_range(
+    request: &Request<()>,
+    etag: &ETag,
+    last_modified: Option<SystemTime>,
+    total_len: u64,
+) -> Result<Option<ByteRange>, Error> {
+    if request.method() != Method::GET {
+        return Ok(None);
+    }
+    let range = match Range::from_request(request) {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    if !conditional::is_if_range_satisfied(request, Some(etag), last_modified) {
+        return Ok(None);
+    }
+
+    let ranges = range.resolve(total_len)?;
+    if ranges.len() > 1 {
+        return Ok(None);
+    }
+    Ok(Some(ranges[0]))
+}
@@ -0,0 +1,266 @@
+//! Components for sending Server-Sent Events.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, output::sse::{Event, Sse}, App};
+//! # use std::time::Duration;
+//! let app = App::create(
+//!     path!("/events").to(endpoint::get().call(|| {
+//!         let events = futures01::stream::iter_ok::<_, std::io::Error>(vec![
+//!             Event::new().event("greeting").data("Hello"),
+//!             Event::new().id("2").data("multi\nline"),
+//!         ]);
+//!         Sse::new(events).keep_alive(Duration::from_secs(15))
+//!     })),
+//! );
+//! # app.unwrap();
+//! ```
+
+use {
+    super::{IntoResponse, ResponseBody},
+    crate::util::Never,
+    bytes::{BufMut, Bytes, BytesMut},
+    futures01::{Async, Future, Poll, Stream},
+    http::{
+        header::{HeaderValue, CACHE_CONTROL, CONTENT_TYPE},
+        Request, Response,
+    },
+    std::{
+        error::Error as StdError,
+        fmt,
+        time::{Duration, Instant},
+    },
+    tokio_timer::Delay,
+};
+
+type BoxedError = Box<dyn StdError + Send + Sync + 'static>;
+
+/// A message sent to the client as an event stream.
+///
+/// The line breaks in `data` and `comment` are encoded as multiple fields, and those
+/// in `event` and `id` are removed since they cannot be represented.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Event {
+    comment: Option<String>,
+    event: Option<String>,
+    id: Option<String>,
+    retry: Option<Duration>,
+    data: Option<String>,
+}
+
+impl Event {
+    /// Creates an empty `Event`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the payload of this event.
+    pub fn data(self, data: impl Into<String>) -> Self {
+        Self {
+            data: Some(data.into()),
+            ..self
+        }
+    }
+
+    /// Sets the type of this event.
+    pub fn event(self, event: impl Into<String>) -> Self {
+        Self {
+            event: Some(event.into()),
+            ..self
+        }
+    }
+
+    /// Sets the ID of this event.
+    pub fn id(self, id: impl Into<String>) -> Self {
+        Self {
+            id: Some(id.into()),
+            ..self
+        }
+    }
+
+    /// Sets the reconnection time used by the client.
+    pub fn retry(self, retry: Duration) -> Self {
+        Self {
+            retry: Some(retry),
+            ..self
+        }
+    }
+
+    /// Sets the comment of this event, which is ignored by the client.
+    pub fn comment(self, comment: impl Into<String>) -> Self {
+        Self {
+            comment: Some(comment.into()),
+            ..self
+        }
+    }
+
+    /// Encodes this event into the wire format, terminated by a blank line.
+    pub fn to_bytes(&self) -> Bytes {
+        let mut buf = BytesMut::new();
+        if let Some(ref comment) = self.comment {
+            for line in lines(comment) {
+                put_field(&mut buf, "", line);
+            }
+        }
+        if let Some(ref event) = self.event {
+            put_field(&mut buf, "event", &strip_line_breaks(event));
+        }
+        if let Some(ref id) = self.id {
+            put_field(&mut buf, "id", &strip_line_breaks(id));
+        }
+        if let Some(retry) = self.retry {
+            let millis = retry.as_secs() * 1000 + u64::from(retry.subsec_millis());
+            put_field(&mut buf, "retry", &millis.to_string());
+        }
+        if let Some(ref data) = self.data {
+            for line in lines(data) {
+                put_field(&mut buf, "data", line);
+            }
+        }
+        put_slice(&mut buf, b"\n");
+        buf.freeze()
+    }
+}
+
+/// Splits the string at the line breaks recognized by the event stream format,
+/// that is `\r\n`, `\r` and `\n`.
+fn lines(s: &str) -> impl Iterator<Item = &str> {
+    std::iter::repeat(()).scan(Some(s), |rest, ()| {
+        let s = (*rest)?;
+        match s.find(|c| c == '\r' || c == '\n') {
+            Some(pos) => {
+                let len = if s[pos..].starts_with("\r\n") { 2 } else { 1 };
+                *rest = Some(&s[pos + len..]);
+                Some(&s[..pos])
+            }
+            None => {
+                *rest = None;
+                Some(s)
+            }
+        }
+    })
+}
+
+fn strip_line_breaks(s: &str) -> String {
+    s.chars().filter(|&c| c != '\r' && c != '\n').collect()
+}
+
+fn put_field(buf: &mut BytesMut, name: &str, value: &str) {
+    put_slice(buf, name.as_bytes());
+    put_slice(buf, b":");
+    if !value.is_empty() {
+        put_slice(buf, b" ");
+        put_slice(buf, value.as_bytes());
+    }
+    put_slice(buf, b"\n");
+}
+
+fn put_slice(buf: &mut BytesMut, data: &[u8]) {
+    buf.reserve(data.len());
+    buf.put_slice(data);
+}
+
+/// A responder that sends the events produced by a `Stream` as `text/event-stream`.
+///
+/// Each event is sent as a separate chunk so that it is delivered to the client
+/// without waiting for the subsequent events.
+pub struct Sse<S> {
+    stream: S,
+    keep_alive: Option<Duration>,
+}
+
+impl<S> fmt::Debug for Sse<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sse")
+            .field("keep_alive", &self.keep_alive)
+            .finish()
+    }
+}
+
+impl<S> Sse<S>
+where
+    S: Stream<Item = Event> + Send + 'static,
+    S::Error: Into<BoxedError>,
+{
+    /// Creates an `Sse` from the specified `Stream` of events.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            keep_alive: None,
+        }
+    }
+
+    /// Sends an empty comment if no event has been sent for the specified interval.
+    ///
+    /// The keep-alive comments prevent the intermediaries from closing the idle connection.
+    pub fn keep_alive(self, interval: Duration) -> Self {
+        Self {
+            keep_alive: Some(interval),
+            ..self
+        }
+    }
+}
+
+impl<S> IntoResponse for Sse<S>
+where
+    S: Stream<Item = Event> + Send + 'static,
+    S::Error: Into<BoxedError>,
+{
+    type Body = ResponseBody;
+    type Error = Never;
+
+    fn into_response(self, _: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+        let body = ResponseBody::wrap_stream(SseStream {
+            stream: self.stream,
+            keep_alive: self.keep_alive.map(|interval| (None, interval)),
+        });
+        let mut response = Response::new(body);
+        response
+            .headers_mut()
+            .insert(CONTENT_TYPE, HeaderValue::from_static("text/event-stream"));
+        response
+            .headers_mut()
+            .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        Ok(response)
+    }
+}
+
+struct SseStream<S> {
+    stream: S,
+    keep_alive: Option<(Option<Delay>, Duration)>,
+}
+
+impl<S> Stream for SseStream<S>
+where
+    S: Stream<Item = Event>,
+    S::Error: Into<BoxedError>,
+{
+    type Item = Bytes;
+    type Error = BoxedError;
+
+    fn poll(&mut self) -> Poll<Option<Self::Item>, Self::Error> {
+        match self.stream.poll().map_err(Into::into)? {
+            Async::Ready(Some(event)) => {
+                if let Some((Some(ref mut delay), interval)) = self.keep_alive {
+                    delay.reset(Instant::now() + interval);
+                }
+                return Ok(Async::Ready(Some(event.to_bytes())));
+            }
+            Async::Ready(None) => return Ok(Async::Ready(None)),
+            Async::NotReady => {}
+        }
+
+        if let Some((ref mut delay, interval)) = self.keep_alive {
+            let delay = delay.get_or_insert_with(|| Delay::new(Instant::now() + interval));
+            if let Async::Ready(()) = delay.poll()? {
+                delay.reset(Instant::now() + interval);
+                // register the reset timer to the current task.
+                let _ = delay.poll()?;
+                return Ok(Async::Ready(Some(Bytes::from_static(b":\n\n"))));
+            }
+        }
+
+        Ok(Async::NotReady)
+    }
+}
@@ -0,0 +1,212 @@
+use {
+    bytes::{Buf, Bytes, IntoBuf},
+    futures01::{Async, Poll, Stream},
+    http::header::{HeaderMap, HeaderName, HeaderValue},
+    hyper::{body::Payload, Body, Chunk},
+    std::{error::Error as StdError, fmt},
+    tsukuyomi_service::Truncation,
+};
+
+type BoxedError = Box<dyn StdError + Send + Sync + 'static>;
+type BoxedStream = Box<dyn Stream<Item = Bytes, Error = BoxedError> + Send + 'static>;
+type ErrorHook = Box<dyn Fn(&dyn StdError) + Send + 'static>;
+
+/// A streaming message body with the explicit policy for errors occurring
+/// after the response head has been sent.
+///
+/// Once the status code and the header have been sent to the client, the error from
+/// the stream can no longer be converted into an error response.  By default, the
+/// transmission is aborted in the way that the client can detect the truncation:
+///
+/// * for chunked HTTP/1.1 responses, the connection is closed without sending
+///   the terminating chunk.
+/// * for HTTP/2 responses, the stream is reset by `RST_STREAM`.
+///
+/// In any case, the error is reported to the logger at the error level and to the
+/// functions of `config::observe_errors`, and the response is marked as truncated
+/// for the server (see `tsukuyomi_service::Truncation`).
+pub struct StreamBody<S> {
+    stream: S,
+    error_trailer: Option<HeaderName>,
+}
+
+impl<S> fmt::Debug for StreamBody<S> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamBody")
+            .field("error_trailer", &self.error_trailer)
+            .finish()
+    }
+}
+
+impl<S> StreamBody<S>
+where
+    S: Stream + Send + 'static,
+    S::Item: IntoBuf,
+    S::Error: Into<BoxedError>,
+{
+    /// Creates a `StreamBody` from the specified `Stream`.
+    pub fn new(stream: S) -> Self {
+        Self {
+            stream,
+            error_trailer: None,
+        }
+    }
+
+    /// Specifies the name of trailer field that describes the error of stream.
+    ///
+    /// If the protocol supports the trailers (i.e. HTTP/2), the transmission is
+    /// completed with the specified trailer field instead of aborting the stream.
+    /// Otherwise, this setting is ignored.
+    pub fn with_error_trailer(self, name: HeaderName) -> Self {
+        Self {
+            error_trailer: Some(name),
+            ..self
+        }
+    }
+
+    pub(super) fn into_payload(self) -> StreamPayload {
+        StreamPayload {
+            state: State::Init(Box::new(
+                self.stream
+                    .map(|chunk| chunk.into_buf().collect::<Bytes>())
+                    .map_err(Into::into),
+            )),
+            error_trailer: self.error_trailer,
+            trailers_allowed: false,
+            trailers: None,
+            on_error: OnError::default(),
+        }
+    }
+}
+
+pub(super) struct StreamPayload {
+    state: State,
+    error_trailer: Option<HeaderName>,
+    trailers_allowed: bool,
+    trailers: Option<HeaderMap>,
+    on_error: OnError,
+}
+
+/// The reporting of the errors occurring after the response head has been sent.
+#[derive(Default)]
+struct OnError {
+    hook: Option<ErrorHook>,
+    truncation: Truncation,
+}
+
+impl OnError {
+    fn report(&self, message: &str, err: &BoxedError) {
+        log::error!("{}: {}", message, err);
+        self.truncation.set();
+        if let Some(ref hook) = self.hook {
+            hook(&**err);
+        }
+    }
+}
+
+enum State {
+    Init(BoxedStream),
+    Aborting(Body),
+    Trailing(BoxedStream),
+    Done,
+}
+
+impl fmt::Debug for StreamPayload {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("StreamPayload")
+            .field("error_trailer", &self.error_trailer)
+            .field("trailers_allowed", &self.trailers_allowed)
+            .finish()
+    }
+}
+
+impl StreamPayload {
+    pub(super) fn allow_trailers(&mut self, allowed: bool) {
+        self.trailers_allowed = allowed;
+    }
+
+    pub(super) fn truncation(&self) -> Truncation {
+        self.on_error.truncation.clone()
+    }
+
+    pub(super) fn on_error(&mut self, hook: impl Fn(&dyn StdError) + Send + 'static) {
+        self.on_error.hook = Some(match self.on_error.hook.take() {
+            Some(prev) => Box::new(move |err: &dyn StdError| {
+                prev(err);
+                hook(err);
+            }),
+            None => Box::new(hook),
+        });
+    }
+
+    fn start(&mut self) {
+        let stream = match std::mem::replace(&mut self.state, State::Done) {
+            State::Init(stream) => stream,
+            state => {
+                self.state = state;
+                return;
+            }
+        };
+        self.state = match self.error_trailer {
+            Some(..) if self.trailers_allowed => State::Trailing(stream),
+            _ => {
+                let on_error = std::mem::replace(&mut self.on_error, OnError::default());
+                State::Aborting(Body::wrap_stream(stream.map_err(move |err| {
+                    on_error.report("the response body has been aborted", &err);
+                    err
+                })))
+            }
+        };
+    }
+}
+
+impl Payload for StreamPayload {
+    type Data = Chunk;
+    type Error = hyper::Error;
+
+    fn poll_data(&mut self) -> Poll<Option<Self::Data>, Self::Error> {
+        self.start();
+        match self.state {
+            State::Aborting(ref mut body) => body.poll_data(),
+            State::Trailing(ref mut stream) => match stream.poll() {
+                Ok(Async::Ready(Some(chunk))) => Ok(Async::Ready(Some(chunk.into()))),
+                Ok(Async::Ready(None)) => {
+                    self.state = State::Done;
+                    Ok(Async::Ready(None))
+                }
+                Ok(Async::NotReady) => Ok(Async::NotReady),
+                Err(err) => {
+                    self.on_error
+                        .report("the response body has been terminated by the error", &err);
+                    let name = self
+                        .error_trailer
+                        .clone()
+                        .expect("the trailer name should be set");
+                    let value = HeaderValue::from_str(&err.to_string())
+                        .unwrap_or_else(|_| HeaderValue::from_static("stream error"));
+                    let mut trailers = HeaderMap::new();
+                    trailers.insert(name, value);
+                    self.trailers = Some(trailers);
+                    self.state = State::Done;
+                    Ok(Async::Ready(None))
+                }
+            },
+            State::Init(..) | State::Done => Ok(Async::Ready(None)),
+        }
+    }
+
+    fn poll_trailers(&mut self) -> Poll<Option<HeaderMap>, Self::Error> {
+        match self.state {
+            State::Aborting(ref mut body) => body.poll_trailers(),
+            _ => Ok(Async::Ready(self.trailers.take())),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        match self.state {
+            State::Aborting(ref body) => body.is_end_stream(),
+            State::Done => self.trailers.is_none(),
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,79 @@
+//! Components for rewriting the response bodies incrementally.
+
+use bytes::{Bytes, BytesMut};
+
+/// A trait representing the stateful rewriting of the chunks of a response body.
+///
+/// The transformer may hold back a part of the received chunk (e.g. the beginning of
+/// a pattern that may continue in the next chunk), and emit it with the subsequent chunks.
+pub trait ChunkTransformer: Send + 'static {
+    /// Rewrites a chunk received from the underlying body.
+    ///
+    /// The returned value is sent to the client immediately, unless it is empty.
+    fn transform(&mut self, chunk: Bytes) -> Bytes;
+
+    /// Returns the remaining data held back by this transformer.
+    ///
+    /// This method is called once after the underlying body reaches the end.
+    fn finish(&mut self) -> Bytes;
+}
+
+/// Creates a `ChunkTransformer` that replaces all occurrences of `pattern` with `replacement`.
+///
+/// The occurrences split across the chunk boundaries are also replaced.
+///
+/// # Panics
+/// This function will panic if `pattern` is empty.
+pub fn replace(pattern: impl Into<Bytes>, replacement: impl Into<Bytes>) -> Replace {
+    let pattern = pattern.into();
+    assert!(!pattern.is_empty(), "the pattern must not be empty");
+    Replace {
+        pattern,
+        replacement: replacement.into(),
+        pending: BytesMut::new(),
+    }
+}
+
+/// A `ChunkTransformer` that replaces the occurrences of a fixed pattern.
+#[derive(Debug)]
+pub struct Replace {
+    pattern: Bytes,
+    replacement: Bytes,
+    pending: BytesMut,
+}
+
+impl ChunkTransformer for Replace {
+    fn transform(&mut self, chunk: Bytes) -> Bytes {
+        let mut data = std::mem::replace(&mut self.pending, BytesMut::new());
+        data.extend_from_slice(&chunk);
+
+        let mut output = BytesMut::with_capacity(data.len());
+        let mut pos = 0;
+        while let Some(i) = find(&data[pos..], &self.pattern) {
+            output.extend_from_slice(&data[pos..pos + i]);
+            output.extend_from_slice(&self.replacement);
+            pos += i + self.pattern.len();
+        }
+
+        // hold back the tail which may be the beginning of the pattern.
+        let rest = &data[pos..];
+        let carry = (1..std::cmp::min(rest.len() + 1, self.pattern.len()))
+            .rev()
+            .find(|&n| rest[rest.len() - n..] == self.pattern[..n])
+            .unwrap_or(0);
+        output.extend_from_slice(&rest[..rest.len() - carry]);
+        self.pending.extend_from_slice(&rest[rest.len() - carry..]);
+
+        output.freeze()
+    }
+
+    fn finish(&mut self) -> Bytes {
+        std::mem::replace(&mut self.pending, BytesMut::new()).freeze()
+    }
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,318 @@
+//! Helper functions for building the URIs used in `Location` and `Link` header fields.
+//!
+//! The query strings are serialized with the same url-encoding rules as
+//! `extractor::query`, and hence the values encoded by these functions are decoded
+//! into the identical values by the extractor.
+
+use {
+    crate::error::Error,
+    http::Request,
+    serde::Serialize,
+    url::{
+        form_urlencoded,
+        percent_encoding::{utf8_percent_encode, DEFAULT_ENCODE_SET},
+    },
+};
+
+/// Creates a URI string from a path and a value to be serialized into the query string.
+///
+/// The characters not allowed in a path, including `?` and `#`, are percent-encoded.
+/// The returned string consists only of visible ASCII characters and hence it can
+/// be converted into `HeaderValue` without failure.
+///
+/// The rules of serialization follow `serde_urlencoded`, as in `extractor::query`:
+/// the top-level value must be a struct, map or sequence of pairs whose values are
+/// scalars, and `None` fields are omitted.  If the serialized query string is empty,
+/// the path is returned without `?`.
+///
+/// # Errors
+/// This function returns an error if the value cannot be serialized
+/// as a query string (e.g. it contains a nested struct).
+///
+/// # Example
+///
+/// ```
+/// # use serde::Serialize;
+/// # use tsukuyomi::output::uri::with_query;
+/// #[derive(Serialize)]
+/// struct Search {
+///     q: String,
+///     page: u32,
+/// }
+///
+/// let uri = with_query("/search", &Search {
+///     q: "rust & web".into(),
+///     page: 2,
+/// }).unwrap();
+/// assert_eq!(uri, "/search?q=rust+%26+web&page=2");
+/// ```
+pub fn with_query<T>(path: &str, query: &T) -> Result<String, Error>
+where
+    T: ?Sized + Serialize,
+{
+    let query = serde_urlencoded::to_string(query).map_err(crate::error::internal_server_error)?;
+    Ok(join(path, &query))
+}
+
+/// Creates a URI string from the path and query of the current request, with the
+/// query parameters modified by the provided function.
+///
+/// The path of the request is kept as it is, and the query parameters which are
+/// not touched by the function are re-serialized in their original order.
+///
+/// # Example
+///
+/// ```
+/// # use tsukuyomi::output::uri::current_uri_with;
+/// let request = http::Request::get("/posts?tag=rust&page=2").body(()).unwrap();
+/// assert_eq!(
+///     current_uri_with(&request, |q| q.set("page", 3)),
+///     "/posts?tag=rust&page=3"
+/// );
+/// ```
+pub fn current_uri_with<F>(request: &Request<()>, f: F) -> String
+where
+    F: FnOnce(&mut QueryPairs),
+{
+    let mut pairs = QueryPairs::parse(request.uri().query().unwrap_or(""));
+    f(&mut pairs);
+    let query = pairs.to_string();
+    if query.is_empty() {
+        request.uri().path().to_owned()
+    } else {
+        format!("{}?{}", request.uri().path(), query)
+    }
+}
+
+/// An ordered list of the decoded query parameters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryPairs {
+    pairs: Vec<(String, String)>,
+}
+
+impl QueryPairs {
+    /// Parses a url-encoded query string.
+    pub fn parse(query: &str) -> Self {
+        Self {
+            pairs: form_urlencoded::parse(query.as_bytes())
+                .into_owned()
+                .collect(),
+        }
+    }
+
+    /// Returns the first value associated with the specified key.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Sets the value of the specified key.
+    ///
+    /// The first occurrence of the key is replaced in place and the others are removed.
+    /// If the key does not exist, the pair is appended at the end.
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        let value = value.to_string();
+        match self.pairs.iter().position(|(k, _)| k == key) {
+            Some(pos) => {
+                self.pairs[pos].1 = value;
+                let mut i = 0;
+                self.pairs.retain(|(k, _)| {
+                    i += 1;
+                    i - 1 == pos || k != key
+                });
+            }
+            None => self.pairs.push((key.to_owned(), value)),
+        }
+    }
+
+    /// Appends a pair at the end, keeping the existing values of the key.
+    pub fn append(&mut self, key: &str, value: impl ToString) {
+        self.pairs.push((key.to_owned(), value.to_string()));
+    }
+
+    /// Removes all values associated with the specified key.
+    pub fn remove(&mut self, key: &str) {
+        self.pairs.retain(|(k, _)| k != key);
+    }
+
+    /// Returns an iterator over the pairs.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.pairs.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+}
+
+impl std::fmt::Display for QueryPairs {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(
+            &form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(&self.pairs)
+                .finish(),
+        )
+    }
+}
+
+pub(crate) fn join(path: &str, query: &str) -> String {
+    let path = utf8_percent_encode(path, DEFAULT_ENCODE_SET).to_string();
+    if query.is_empty() {
+        path
+    } else {
+        format!("{}?{}", path, query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        serde::{Deserialize, Serialize},
+        std::collections::BTreeMap,
+    };
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Search {
+        q: String,
+        page: u32,
+        exact: bool,
+        lang: Option<String>,
+    }
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Paged {
+        #[serde(flatten)]
+        filter: BTreeMap<String, String>,
+        cursor: Option<String>,
+    }
+
+    const SAMPLES: &[&str] = &[
+        "",
+        "a",
+        " ",
+        "+",
+        "%",
+        "%20",
+        "&",
+        "=",
+        "?",
+        "#",
+        "/",
+        "a b&c=d",
+        "日本語",
+        "\u{1F600}",
+        "\"<>'",
+        "\\",
+        "a+b%2Bc",
+    ];
+
+    fn parse_query<T: serde::de::DeserializeOwned>(uri: &str) -> T {
+        let query = uri.find('?').map_or("", |i| &uri[i + 1..]);
+        serde_urlencoded::from_str(query).expect("should be decodable")
+    }
+
+    #[test]
+    fn struct_round_trip() {
+        for q in SAMPLES {
+            for lang in SAMPLES
+                .iter()
+                .map(|s| Some(s.to_string()))
+                .chain(Some(None))
+            {
+                let value = Search {
+                    q: q.to_string(),
+                    page: 42,
+                    exact: q.len() % 2 == 0,
+                    lang,
+                };
+                let uri = with_query("/search", &value).unwrap();
+                assert!(uri.starts_with("/search?"), "{}", uri);
+                assert!(uri.chars().all(|c| c.is_ascii_graphic()), "{}", uri);
+                assert_eq!(parse_query::<Search>(&uri), value, "{}", uri);
+            }
+        }
+    }
+
+    #[test]
+    fn flattened_round_trip() {
+        for key in SAMPLES.iter().filter(|s| **s != "cursor") {
+            for val in SAMPLES {
+                let mut filter = BTreeMap::new();
+                filter.insert(key.to_string(), val.to_string());
+                let value = Paged {
+                    filter,
+                    cursor: Some(val.to_string()),
+                };
+                let uri = with_query("/items", &value).unwrap();
+                assert_eq!(parse_query::<Paged>(&uri), value, "{}", uri);
+            }
+        }
+    }
+
+    #[test]
+    fn nested_struct_is_rejected() {
+        #[derive(Serialize)]
+        struct Nested {
+            inner: Search,
+        }
+        let value = Nested {
+            inner: Search {
+                q: "a".into(),
+                page: 1,
+                exact: false,
+                lang: None,
+            },
+        };
+        assert!(with_query("/", &value).is_err());
+    }
+
+    #[test]
+    fn path_is_encoded() {
+        let uri = with_query("/a b/c?d#e", &[("x", "y")]).unwrap();
+        assert_eq!(uri, "/a%20b/c%3Fd%23e?x=y");
+
+        let uri = with_query("/empty", &Vec::<(String, String)>::new()).unwrap();
+        assert_eq!(uri, "/empty");
+    }
+
+    #[test]
+    fn query_pairs_round_trip() {
+        for key in SAMPLES.iter().filter(|s| !s.is_empty()) {
+            for val in SAMPLES {
+                let mut pairs = QueryPairs::default();
+                pairs.append(key, val);
+                pairs.append("k", "v");
+                assert_eq!(QueryPairs::parse(&pairs.to_string()), pairs);
+            }
+        }
+    }
+
+    #[test]
+    fn current_uri_modified() {
+        let request = Request::get("/list?page=1&tag=a&page=2&q=a%20b")
+            .body(())
+            .unwrap();
+
+        assert_eq!(
+            current_uri_with(&request, |q| q.set("page", 3)),
+            "/list?page=3&tag=a&q=a+b"
+        );
+        assert_eq!(
+            current_uri_with(&request, |q| q.set("sort", "new")),
+            "/list?page=1&tag=a&page=2&q=a+b&sort=new"
+        );
+        assert_eq!(
+            current_uri_with(&request, |q| {
+                q.remove("page");
+                q.remove("tag");
+                q.remove("q");
+            }),
+            "/list"
+        );
+
+        let request = Request::get("/list").body(()).unwrap();
+        assert_eq!(
+            current_uri_with(&request, |q| q.append("page", 2)),
+            "/list?page=2"
+        );
+    }
+}
@@ -1,8 +1,14 @@
 //! Definition of `Responder`.
 
-use crate::{error::Error, future::TryFuture, input::Input, output::IntoResponse, util::Never};
+use {
+    crate::{error::Error, future::TryFuture, input::Input, output::IntoResponse, util::Never},
+    std::borrow::Cow,
+};
 
-pub use self::oneshot::Oneshot;
+pub use self::{
+    oneshot::Oneshot,
+    with_cookie::{SetCookie, WithCookie},
+};
 
 /// A trait that abstracts replies to clients.
 pub trait Responder {
@@ -193,3 +199,151 @@ mod oneshot {
         }
     }
 }
+
+/// A set of extension methods for `Responder`s.
+pub trait ResponderExt: Responder + Sized {
+    /// Creates a `Responder` that appends a `Set-Cookie` header created from
+    /// `cookie` to the response of this responder.
+    ///
+    /// The header is appended rather than inserted, so that each call yields its
+    /// own `Set-Cookie` and the cookies registered to `input.cookies` (e.g. by
+    /// `tsukuyomi-session`) are still sent.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use tsukuyomi::{config::prelude::*, responder::ResponderExt, App};
+    /// use cookie::{Cookie, SameSite};
+    ///
+    /// let app = App::create(
+    ///     path!("/").to(endpoint::get().call(|| {
+    ///         "Hello"
+    ///             .with_cookie(
+    ///                 Cookie::build("theme", "dark")
+    ///                     .path("/")
+    ///                     .http_only(true)
+    ///                     .same_site(SameSite::Lax)
+    ///                     .finish(),
+    ///             )
+    ///             .remove_cookie("visited")
+    ///     })),
+    /// );
+    /// # app.unwrap();
+    /// ```
+    fn with_cookie(self, cookie: cookie::Cookie<'static>) -> WithCookie<Self> {
+        WithCookie {
+            responder: self,
+            cookie,
+        }
+    }
+
+    /// Creates a `Responder` that appends a `Set-Cookie` header which removes
+    /// the cookie with the specified name from the client.
+    ///
+    /// The removal cookie has the attribute `Path=/`.  In order to remove a cookie
+    /// set with the other `Path` or `Domain`, pass an expired cookie with the same
+    /// attributes to `with_cookie` instead.
+    fn remove_cookie(self, name: impl Into<Cow<'static, str>>) -> WithCookie<Self> {
+        let mut cookie = cookie::Cookie::build(name, "").path("/").finish();
+        cookie.set_max_age(time::Duration::seconds(0));
+        cookie.set_expires(time::now() - time::Duration::days(365));
+        self.with_cookie(cookie)
+    }
+}
+
+impl<R> ResponderExt for R where R: Responder {}
+
+mod with_cookie {
+    use {
+        super::{Error, Input, IntoResponse, Responder},
+        crate::future::{try_ready, Poll, TryFuture},
+        cookie::Cookie,
+        http::{
+            header::{HeaderValue, SET_COOKIE},
+            Request, Response,
+        },
+    };
+
+    /// A `Responder` created by `ResponderExt::with_cookie`.
+    #[derive(Debug, Clone)]
+    pub struct WithCookie<R> {
+        pub(super) responder: R,
+        pub(super) cookie: Cookie<'static>,
+    }
+
+    impl<R> Responder for WithCookie<R>
+    where
+        R: Responder,
+    {
+        type Response = SetCookie<R::Response>;
+        type Error = R::Error;
+        type Respond = WithCookieRespond<R::Respond>;
+
+        #[inline]
+        fn respond(self) -> Self::Respond {
+            WithCookieRespond {
+                respond: self.responder.respond(),
+                cookie: Some(self.cookie),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    pub struct WithCookieRespond<Fut> {
+        respond: Fut,
+        cookie: Option<Cookie<'static>>,
+    }
+
+    impl<Fut> TryFuture for WithCookieRespond<Fut>
+    where
+        Fut: TryFuture,
+        Fut::Ok: IntoResponse,
+    {
+        type Ok = SetCookie<Fut::Ok>;
+        type Error = Fut::Error;
+
+        fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+            let response = try_ready!(self.respond.poll_ready(input));
+            let cookie = self
+                .cookie
+                .take()
+                .expect("the future has already been polled.");
+            Ok(SetCookie { response, cookie }.into())
+        }
+    }
+
+    /// The response created by `WithCookie`.
+    #[derive(Debug)]
+    pub struct SetCookie<T> {
+        response: T,
+        cookie: Cookie<'static>,
+    }
+
+    impl<T> IntoResponse for SetCookie<T>
+    where
+        T: IntoResponse,
+    {
+        type Body = T::Body;
+        type Error = Error;
+
+        fn into_response(self, request: &Request<()>) -> Result<Response<Self::Body>, Self::Error> {
+            let response = self.response.into_response(request).map_err(Into::into)?;
+            append_cookie(response, &self.cookie)
+        }
+
+        fn respond_to(self, input: &mut Input<'_>) -> Result<Response<Self::Body>, Self::Error> {
+            let response = self.response.respond_to(input).map_err(Into::into)?;
+            append_cookie(response, &self.cookie)
+        }
+    }
+
+    fn append_cookie<T>(
+        mut response: Response<T>,
+        cookie: &Cookie<'static>,
+    ) -> Result<Response<T>, Error> {
+        let value = HeaderValue::from_shared(cookie.encoded().to_string().into())
+            .map_err(crate::error::internal_server_error)?;
+        response.headers_mut().append(SET_COOKIE, value);
+        Ok(response)
+    }
+}
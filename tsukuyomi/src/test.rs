@@ -0,0 +1,3 @@
+//! Utilities for testing the applications.
+
+pub mod conformance;
@@ -0,0 +1,449 @@
+//! A conformance suite that checks the interactions of the method handling and CORS.
+//!
+//! The automatic `OPTIONS` handling, `405 Method Not Allowed`, and CORS are implemented
+//! by separate components (e.g. `modifiers::default_options` and `tsukuyomi-cors`), and
+//! the regressions tend to appear where they are combined.  `Conformance` sends a matrix
+//! of requests to every route registered in an `App` and checks the following invariants:
+//!
+//! * **Allow accuracy** - The responses of `405 Method Not Allowed` have `Allow`, and
+//!   `Allow` in any response lists exactly the methods allowed by the route.
+//! * **Vary presence** - The responses with `Access-Control-Allow-Origin` other than `*`
+//!   have `Vary` containing `Origin`.
+//! * **No CORS headers without Origin** - The responses to the requests without `Origin`
+//!   have no `Access-Control-*` headers.
+//! * **Preflight bypasses authentication** - The CORS preflight requests to the routes
+//!   handling `OPTIONS` are not rejected with `401 Unauthorized` or `403 Forbidden`.
+//!
+//! For each route, the methods not allowed by the route are sent in order to check
+//! that they are rejected, and only the safe methods (`GET` and `HEAD`) allowed by the
+//! route reach the endpoints.  The parameters in the route paths are filled with `1`.
+//!
+//! # Example
+//!
+//! ```
+//! # use tsukuyomi::{config::prelude::*, modifiers, test::conformance::Conformance, App};
+//! let app = App::create(
+//!     path!("/posts")
+//!         .to(endpoint::allow_only("GET, POST")?.call(|| "posts"))
+//!         .modify(modifiers::default_options()),
+//! )?;
+//!
+//! Conformance::new()
+//!     .origin("https://example.com")
+//!     .run(&app)
+//!     .assert();
+//! # Ok::<(), failure::Error>(())
+//! ```
+
+use {
+    crate::{app::App, handler::AllowedMethods, output::ResponseBody},
+    futures01::Future,
+    http::{
+        header::{
+            HeaderMap, HeaderValue, ACCESS_CONTROL_ALLOW_ORIGIN, ACCESS_CONTROL_REQUEST_METHOD,
+            ALLOW, ORIGIN, VARY,
+        },
+        Method, Request, Response, StatusCode,
+    },
+    std::{collections::HashSet, fmt},
+    tokio_threadpool::ThreadPool,
+    tsukuyomi_service::{MakeService, Service},
+};
+
+/// The methods sent to each route.
+const METHODS: &[Method] = &[
+    Method::GET,
+    Method::HEAD,
+    Method::POST,
+    Method::PUT,
+    Method::DELETE,
+    Method::PATCH,
+];
+
+/// The invariants checked by `Conformance`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Invariant {
+    /// `405 Method Not Allowed` has `Allow`, and `Allow` lists exactly the allowed methods.
+    AllowAccuracy,
+
+    /// `Access-Control-Allow-Origin` other than `*` is accompanied with `Vary: Origin`.
+    VaryOrigin,
+
+    /// No `Access-Control-*` headers are sent for the requests without `Origin`.
+    NoCorsWithoutOrigin,
+
+    /// The CORS preflight requests are not rejected by the authentication.
+    PreflightBypassesAuth,
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Invariant::AllowAccuracy => "Allow accuracy",
+            Invariant::VaryOrigin => "Vary presence",
+            Invariant::NoCorsWithoutOrigin => "no CORS headers without Origin",
+            Invariant::PreflightBypassesAuth => "preflight bypasses authentication",
+        })
+    }
+}
+
+/// A violation of the invariants found by `Conformance`.
+#[derive(Debug, Clone)]
+pub struct Violation {
+    invariant: Invariant,
+    method: Method,
+    path: String,
+    description: String,
+}
+
+impl Violation {
+    /// Returns the violated invariant.
+    pub fn invariant(&self) -> Invariant {
+        self.invariant
+    }
+
+    /// Returns the method of the request that caused the violation.
+    pub fn method(&self) -> &Method {
+        &self.method
+    }
+
+    /// Returns the path of the request that caused the violation.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} ({})",
+            self.method, self.path, self.description, self.invariant
+        )
+    }
+}
+
+/// The result of `Conformance::run`.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    requests: usize,
+    violations: Vec<Violation>,
+}
+
+impl Report {
+    /// Returns the number of requests sent to the application.
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Returns the found violations.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Returns `true` if no violation is found.
+    pub fn is_ok(&self) -> bool {
+        self.violations.is_empty()
+    }
+
+    /// Panics with the list of violations if any violation is found.
+    pub fn assert(&self) {
+        if !self.is_ok() {
+            let violations: Vec<_> = self.violations.iter().map(ToString::to_string).collect();
+            panic!(
+                "{} conformance violation(s) found:\n  {}",
+                violations.len(),
+                violations.join("\n  ")
+            );
+        }
+    }
+}
+
+/// The runner of the conformance suite.
+#[derive(Debug, Clone)]
+pub struct Conformance {
+    origin: HeaderValue,
+    skipped: HashSet<String>,
+}
+
+impl Default for Conformance {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conformance {
+    /// Creates a `Conformance` with the default configuration.
+    pub fn new() -> Self {
+        Self {
+            origin: HeaderValue::from_static("http://conformance.test"),
+            skipped: HashSet::new(),
+        }
+    }
+
+    /// Sets the value of `Origin` used in the CORS requests.
+    ///
+    /// The origin should be allowed by the CORS configuration of the application,
+    /// since the preflight requests rejected with `403 Forbidden` are reported as
+    /// violations.  The default value is `http://conformance.test`.
+    ///
+    /// # Panics
+    /// This method panics if `origin` is not a valid header value.
+    pub fn origin(self, origin: &'static str) -> Self {
+        Self {
+            origin: HeaderValue::from_static(origin),
+            ..self
+        }
+    }
+
+    /// Excludes the route registered with the specified path (e.g. `/posts/:id`) from the suite.
+    pub fn skip(mut self, path: impl Into<String>) -> Self {
+        self.skipped.insert(path.into());
+        self
+    }
+
+    /// Sends the requests to the routes of `app` and checks the responses.
+    ///
+    /// The endpoints are executed on a dedicated thread pool, so that the blocking
+    /// operations (e.g. `fs::NamedFile`) are available.
+    pub fn run(&self, app: &App) -> Report {
+        let pool = ThreadPool::new();
+        let mut runner = Runner {
+            app,
+            pool: &pool,
+            report: Report::default(),
+        };
+
+        for info in app.routes() {
+            let (route, allowed_methods) = (info.path(), info.allowed_methods());
+            // The asterisk-form (`OPTIONS *`) is not associated with any resource.
+            if info.is_fallback() || !route.starts_with('/') || self.skipped.contains(route) {
+                continue;
+            }
+            let path = fill_params(route);
+            let origin = &self.origin;
+
+            for method in METHODS {
+                let allowed = allows(allowed_methods, method);
+                if allowed && *method != Method::GET && *method != Method::HEAD {
+                    continue;
+                }
+                for origin in &[None, Some(origin)] {
+                    let response = runner.send(method.clone(), &path, *origin, None);
+                    runner.check(&response, method, &path, allowed_methods, origin.is_some());
+                    // The requests may be rejected by the guards or the CORS policy
+                    // before the method is checked, but must not succeed.
+                    if !allowed && response.status().is_success() {
+                        runner.violation(
+                            Invariant::AllowAccuracy,
+                            method,
+                            &path,
+                            format!("the method is not allowed, but got {}", response.status()),
+                        );
+                    }
+                }
+            }
+
+            let response = runner.send(Method::OPTIONS, &path, None, None);
+            runner.check(&response, &Method::OPTIONS, &path, allowed_methods, false);
+
+            if !allows(allowed_methods, &Method::OPTIONS) {
+                continue;
+            }
+            let request_methods: Vec<Method> = match allowed_methods {
+                Some(methods) => methods
+                    .iter()
+                    .filter(|&m| *m != Method::OPTIONS && *m != Method::HEAD)
+                    .cloned()
+                    .collect(),
+                None => vec![Method::GET],
+            };
+            for request_method in request_methods {
+                let response =
+                    runner.send(Method::OPTIONS, &path, Some(origin), Some(&request_method));
+                runner.check(&response, &Method::OPTIONS, &path, allowed_methods, true);
+                match response.status() {
+                    StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => runner.violation(
+                        Invariant::PreflightBypassesAuth,
+                        &Method::OPTIONS,
+                        &path,
+                        format!(
+                            "the preflight request for {} is rejected with {}",
+                            request_method,
+                            response.status()
+                        ),
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        let report = runner.report;
+        let _ = pool.shutdown_now().wait();
+        report
+    }
+}
+
+struct Runner<'a> {
+    app: &'a App,
+    pool: &'a ThreadPool,
+    report: Report,
+}
+
+impl<'a> Runner<'a> {
+    fn send(
+        &mut self,
+        method: Method,
+        path: &str,
+        origin: Option<&HeaderValue>,
+        request_method: Option<&Method>,
+    ) -> Response<ResponseBody> {
+        let mut request = Request::new(hyper::Body::empty());
+        *request.method_mut() = method;
+        *request.uri_mut() = path.parse().expect("should be a valid path");
+        if let Some(origin) = origin {
+            request.headers_mut().insert(ORIGIN, origin.clone());
+        }
+        if let Some(request_method) = request_method {
+            request.headers_mut().insert(
+                ACCESS_CONTROL_REQUEST_METHOD,
+                HeaderValue::from_str(request_method.as_str()).expect("should be a valid value"),
+            );
+        }
+
+        self.report.requests += 1;
+        let mut service =
+            match MakeService::<(), Request<hyper::Body>>::make_service(self.app, ()).wait() {
+                Ok(service) => service,
+                Err(never) => match never {},
+            };
+        match self.pool.spawn_handle(service.call(request)).wait() {
+            Ok(response) => response,
+            Err(never) => match never {},
+        }
+    }
+
+    fn check(
+        &mut self,
+        response: &Response<ResponseBody>,
+        method: &Method,
+        path: &str,
+        allowed_methods: Option<&AllowedMethods>,
+        with_origin: bool,
+    ) {
+        let headers = response.headers();
+
+        if let Some(allowed_methods) = allowed_methods {
+            match headers.get(ALLOW) {
+                Some(allow) => {
+                    let expected: HashSet<&str> =
+                        allowed_methods.iter().map(Method::as_str).collect();
+                    let actual: HashSet<&str> = allow
+                        .to_str()
+                        .unwrap_or("")
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|m| !m.is_empty())
+                        .collect();
+                    if actual != expected {
+                        self.violation(
+                            Invariant::AllowAccuracy,
+                            method,
+                            path,
+                            format!(
+                                "Allow is {:?}, but the route allows {:?}",
+                                allow,
+                                allowed_methods.to_header_value()
+                            ),
+                        );
+                    }
+                }
+                None if response.status() == StatusCode::METHOD_NOT_ALLOWED => self.violation(
+                    Invariant::AllowAccuracy,
+                    method,
+                    path,
+                    "405 Method Not Allowed is sent without Allow".into(),
+                ),
+                None => {}
+            }
+        }
+
+        if with_origin {
+            match headers.get(ACCESS_CONTROL_ALLOW_ORIGIN) {
+                Some(allow_origin) if allow_origin != "*" && !varies_by_origin(headers) => self
+                    .violation(
+                        Invariant::VaryOrigin,
+                        method,
+                        path,
+                        format!(
+                            "Access-Control-Allow-Origin is {:?}, but Vary does not contain Origin",
+                            allow_origin
+                        ),
+                    ),
+                _ => {}
+            }
+        } else {
+            let cors_headers: Vec<_> = headers
+                .keys()
+                .filter(|name| name.as_str().starts_with("access-control-"))
+                .map(|name| name.as_str())
+                .collect();
+            if !cors_headers.is_empty() {
+                self.violation(
+                    Invariant::NoCorsWithoutOrigin,
+                    method,
+                    path,
+                    format!(
+                        "the request has no Origin, but got {}",
+                        cors_headers.join(", ")
+                    ),
+                );
+            }
+        }
+    }
+
+    fn violation(
+        &mut self,
+        invariant: Invariant,
+        method: &Method,
+        path: &str,
+        description: String,
+    ) {
+        self.report.violations.push(Violation {
+            invariant,
+            method: method.clone(),
+            path: path.into(),
+            description,
+        });
+    }
+}
+
+/// Returns whether the route accepts the method, regarding the unknown set as accepting all methods.
+fn allows(allowed_methods: Option<&AllowedMethods>, method: &Method) -> bool {
+    match allowed_methods {
+        Some(methods) => methods.contains(method),
+        None => true,
+    }
+}
+
+/// Replaces the parameters in the route path with a dummy value.
+fn fill_params(route: &str) -> String {
+    route
+        .split('/')
+        .map(|segment| match segment.as_bytes().first() {
+            Some(b':') | Some(b'*') => "1",
+            _ => segment,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+fn varies_by_origin(headers: &HeaderMap) -> bool {
+    headers
+        .get_all(VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .any(|name| name == "*" || name.eq_ignore_ascii_case("origin"))
+}
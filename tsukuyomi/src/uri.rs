@@ -134,9 +134,6 @@ impl Uri {
 
         let mut names: Option<CaptureNames> = None;
         for segment in s[1..].split('/') {
-            if names.as_ref().map_or(false, |names| names.has_wildcard) {
-                failure::bail!("The wildcard parameter has already set.");
-            }
             if segment.is_empty() {
                 failure::bail!("empty segment");
             }
@@ -196,7 +193,7 @@ impl Uri {
                 UriKind::Root => Ok(Self::segments(segment, names)),
                 UriKind::Segments(ref other_segment, ref other_names) => {
                     segment += if segment.ends_with('/') {
-                        other_segment.trim_left_matches('/')
+                        other_segment.trim_start_matches('/')
                     } else {
                         other_segment
                     };
@@ -216,6 +213,48 @@ impl Uri {
     }
 }
 
+/// Expands the path ending with the optional parameters (e.g. `/archive/:year/:month?`)
+/// into the paths with each number of them, from the shortest one.
+///
+/// The path without any optional parameter is returned as it is.
+pub(crate) fn expand_optional_params(path: &str) -> Result<Vec<String>, Error> {
+    let segments: Vec<&str> = path.split('/').collect();
+    let n = match segments.iter().position(|segment| segment.ends_with('?')) {
+        Some(n) if n > 0 => n,
+        _ => return Ok(vec![path.to_owned()]),
+    };
+
+    let mut expanded = match segments[..n].join("/") {
+        ref head if head.is_empty() => vec!["/".to_owned()],
+        head => vec![head],
+    };
+    for segment in &segments[n..] {
+        let optional = if segment.ends_with('?') {
+            Some(&segment[..segment.len() - 1])
+        } else {
+            None
+        };
+        let param = match optional {
+            Some(param) if param.starts_with(':') => param,
+            Some(..) => failure::bail!(
+                "the segment `{}` cannot be optional (only `:name?` is allowed)",
+                segment
+            ),
+            None if segment.is_empty() => {
+                failure::bail!("the optional parameters cannot be followed by a trailing slash")
+            }
+            None => failure::bail!(
+                "the segment `{}` follows an optional parameter, but is not optional",
+                segment
+            ),
+        };
+        let last = expanded.last().expect("should not be empty");
+        let next = format!("{}/{}", last.trim_end_matches('/'), param);
+        expanded.push(next);
+    }
+    Ok(expanded)
+}
+
 #[derive(Clone, Debug, Default, PartialEq)]
 pub struct CaptureNames {
     params: IndexSet<String>,
@@ -225,7 +264,7 @@ pub struct CaptureNames {
 impl CaptureNames {
     fn push(&mut self, segment: &str) -> Result<(), Error> {
         if self.has_wildcard {
-            failure::bail!("The wildcard parameter has already set");
+            failure::bail!("the wildcard parameter can be followed only by static segments");
         }
 
         let (kind, name) = segment.split_at(1);
@@ -297,6 +336,16 @@ mod tests {
             "/path/to/lib/".parse(),
             Uri::static_("/path/to/lib/")
         );
+        parse_uri_has_wildcard_in_middle(
+            "/repos/*path/info/refs".parse(),
+            Uri::captured(
+                "/repos/*path/info/refs",
+                CaptureNames {
+                    params: indexset!["path".into()],
+                    has_wildcard: true,
+                }
+            )
+        );
         parse_uri_has_wildcard_params(
             "/api/v1/:param/*path".parse(),
             Uri::captured(
@@ -344,7 +393,36 @@ mod tests {
 
     #[test]
     fn parse_uri_failcase_after_wildcard_name() {
-        assert!("/path/to/*a/id".parse::<Uri>().is_err());
+        assert!("/path/to/*a/:id".parse::<Uri>().is_err());
+        assert!("/path/to/*a/*b".parse::<Uri>().is_err());
+    }
+
+    #[test]
+    fn expand_optional_params_cases() {
+        assert_eq!(
+            expand_optional_params("/archive/:year").ok(),
+            Some(vec!["/archive/:year".to_owned()])
+        );
+        assert_eq!(
+            expand_optional_params("/archive/:year/:month?/:day?").ok(),
+            Some(vec![
+                "/archive/:year".to_owned(),
+                "/archive/:year/:month".to_owned(),
+                "/archive/:year/:month/:day".to_owned(),
+            ])
+        );
+        assert_eq!(
+            expand_optional_params("/:page?").ok(),
+            Some(vec!["/".to_owned(), "/:page".to_owned()])
+        );
+    }
+
+    #[test]
+    fn expand_optional_params_failcase() {
+        assert!(expand_optional_params("/archive/:year?/latest").is_err());
+        assert!(expand_optional_params("/archive/:year?/").is_err());
+        assert!(expand_optional_params("/archive/*path?").is_err());
+        assert!(expand_optional_params("/archive/latest?").is_err());
     }
 
     t! [
@@ -1,6 +1,13 @@
 //! Miscellaneous components used within the framework.
 
-use std::{error::Error as StdError, fmt};
+use {
+    rand::{rngs::OsRng, RngCore},
+    std::{
+        error::Error as StdError,
+        fmt::{self, Write as _Write},
+        io,
+    },
+};
 
 /// A helper type which emulates the standard `never_type` (`!`).
 #[allow(clippy::empty_enum)]
@@ -84,3 +91,38 @@ pub enum Either<L, R> {
     Left(L),
     Right(R),
 }
+
+/// A variant of `Either` which holds one of the three values.
+///
+/// Unlike `Either`, this type implements `IntoResponse` rather than `Responder`, so that
+/// it can be used as a part of other responses (e.g. `Result` or `(StatusCode, T)`).  The
+/// asynchronous responders should be put in the nested `Either`s instead.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Either3<A, B, C> {
+    A(A),
+    B(B),
+    C(C),
+}
+
+/// A variant of `Either` which holds one of the four values.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Either4<A, B, C, D> {
+    A(A),
+    B(B),
+    C(C),
+    D(D),
+}
+
+/// Generates a random identifier of 32 hexadecimal digits, from 128 bits provided by the OS.
+pub(crate) fn generate_id() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    OsRng::new()
+        .and_then(|mut rng| rng.try_fill_bytes(&mut bytes))
+        .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+
+    let mut id = String::with_capacity(32);
+    for b in &bytes {
+        let _ = write!(id, "{:02x}", b);
+    }
+    Ok(id)
+}
@@ -1,8 +1,12 @@
 use {
     http::{header, Request, StatusCode},
     tsukuyomi::{
-        config::prelude::*, //
+        config::{
+            prelude::*, DisallowedMethod, MethodOverride, MountPoint, PathNormalization, Scope,
+            TrailingSlash,
+        },
         extractor,
+        output::redirect,
         App,
     },
     tsukuyomi_server::test::ResponseExt,
@@ -138,7 +142,7 @@ fn default_options() -> tsukuyomi_server::Result<()> {
     let response = server.perform(Request::options("/path"))?;
     assert_eq!(response.status(), 204);
     assert_eq!(response.header(header::ALLOW)?, "GET, POST, OPTIONS");
-    assert_eq!(response.header(header::CONTENT_LENGTH)?, "0");
+    assert!(!response.headers().contains_key(header::CONTENT_LENGTH));
 
     Ok(())
 }
@@ -210,3 +214,1635 @@ fn scoped_fallback() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn nested_fallbacks() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("*").to(endpoint::reply("html 404")),
+        mount("/api").with(chain![
+            path!("*").to(endpoint::reply("json 404")),
+            mount("/v1").with(chain![
+                path!("/users").to(endpoint::get().reply("users")),
+                mount("/admin").with(chain![
+                    path!("*").to(endpoint::reply("admin 404")),
+                    path!("/stats").to(endpoint::get().reply("stats")),
+                ]),
+            ]),
+        ]),
+    ])?;
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/about")?;
+    assert_eq!(response.body().to_utf8()?, "html 404");
+
+    let response = server.perform("/apis")?;
+    assert_eq!(response.body().to_utf8()?, "html 404");
+
+    let response = server.perform("/api")?;
+    assert_eq!(response.body().to_utf8()?, "json 404");
+
+    let response = server.perform("/api/v2/users")?;
+    assert_eq!(response.body().to_utf8()?, "json 404");
+
+    // the scope `/api/v1` has no fallback, and the one of its parent is used.
+    let response = server.perform("/api/v1/groups")?;
+    assert_eq!(response.body().to_utf8()?, "json 404");
+
+    let response = server.perform("/api/v1/users")?;
+    assert_eq!(response.body().to_utf8()?, "users");
+
+    let response = server.perform("/api/v1/admin")?;
+    assert_eq!(response.body().to_utf8()?, "admin 404");
+
+    let response = server.perform("/api/v1/admin/logs/today")?;
+    assert_eq!(response.body().to_utf8()?, "admin 404");
+
+    let response = server.perform("/api/v1/admin/stats")?;
+    assert_eq!(response.body().to_utf8()?, "stats");
+
+    Ok(())
+}
+
+#[test]
+fn route_fallback() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/posts") //
+            .to(endpoint::get().reply("posts"))
+            .fallback(endpoint::call(|| "posts fallback")),
+        path!("/posts/:id") //
+            .to(endpoint::get().call(|id: u32| format!("post {}", id)))
+            .fallback(endpoint::call(|| "post fallback")),
+        path!("/about") //
+            .to(endpoint::get().reply("about")),
+    ])?;
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/posts")?;
+    assert_eq!(response.body().to_utf8()?, "posts");
+
+    let response = server.perform(Request::delete("/posts"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "posts fallback");
+
+    let response = server.perform(Request::put("/posts/1"))?;
+    assert_eq!(response.body().to_utf8()?, "post fallback");
+
+    let response = server.perform(Request::post("/about"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header(header::ALLOW)?, "GET");
+
+    Ok(())
+}
+
+#[test]
+fn route_fallback_on_default_handler() {
+    let app = App::create(
+        path!("*")
+            .to(endpoint::reply(""))
+            .fallback(endpoint::reply("")),
+    );
+    assert!(app.is_err());
+}
+
+#[test]
+fn route_table() -> tsukuyomi::app::Result<()> {
+    use tsukuyomi::app::RouteParam;
+
+    let app = App::create(chain![
+        path!("/").to(endpoint::get().reply("index")).name("index"),
+        mount("/api").with(chain![
+            host("api.example.com"),
+            path!("*").to(endpoint::reply("api 404")),
+            mount("/v1").with(
+                path!("/posts/:id")
+                    .to(endpoint::get().call(|id: u32| format!("post {}", id)))
+                    .fallback(endpoint::call(|| "fallback")),
+            ),
+        ]),
+        path!("/static/*path").to(endpoint::allow_only("GET, HEAD")?.call(|path: String| path)),
+    ])?;
+
+    let routes = app.routes();
+    assert_eq!(routes.len(), 4);
+
+    assert_eq!(routes[0].path(), "/");
+    assert_eq!(routes[0].name(), Some("index"));
+    assert_eq!(routes[0].scopes(), &["/".to_owned()]);
+    assert!(routes[0].params().is_empty());
+    assert_eq!(routes[0].to_string(), "GET / (name: index)");
+
+    assert_eq!(routes[1].path(), "/api/v1/posts/:id");
+    assert_eq!(routes[1].scopes(), &["/", "/api", "/api/v1"]);
+    assert_eq!(routes[1].host(), Some("api.example.com"));
+    assert_eq!(routes[1].params(), &[RouteParam::Segment("id".into())]);
+    assert!(routes[1].has_method_fallback());
+    assert!(!routes[1].is_fallback());
+
+    assert_eq!(routes[2].path(), "/static/*path");
+    assert_eq!(routes[2].params(), &[RouteParam::Wildcard("path".into())]);
+    assert!(routes[2].params()[0].is_wildcard());
+    assert_eq!(routes[2].to_string(), "GET, HEAD /static/*path");
+
+    assert_eq!(routes[3].path(), "/api*");
+    assert!(routes[3].is_fallback());
+    assert!(routes[3].allowed_methods().is_none());
+    assert_eq!(routes[3].scopes(), &["/", "/api"]);
+    assert_eq!(routes[3].to_string(), "* /api* (host: api.example.com)");
+
+    Ok(())
+}
+
+#[test]
+fn shared_state() -> tsukuyomi_server::Result<()> {
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct Name(&'static str);
+
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::state())
+                .call(|name: Arc<Name>| name.0)),
+        mount("/sub").with(chain![
+            path!("/") //
+                .to(endpoint::get()
+                    .extract(extractor::state())
+                    .call(|name: Arc<Name>| name.0)),
+            state(Name("sub")),
+        ]),
+        path!("/missing") //
+            .to(endpoint::get()
+                .extract(extractor::state_labeled("u32"))
+                .call(|n: Arc<u32>| n.to_string())),
+        state(Name("root")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "root");
+
+    let response = server.perform("/sub")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "sub");
+
+    let response = server.perform("/missing")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert!(response.body().to_utf8()?.contains("u32"));
+
+    Ok(())
+}
+
+#[test]
+fn scoped_state() -> tsukuyomi_server::Result<()> {
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct DatabaseUrl(&'static str);
+
+    let database_url = || {
+        extractor::ready(|input| {
+            input
+                .state::<DatabaseUrl>()
+                .map(|url| (url.0,))
+                .ok_or_else(|| tsukuyomi::error::internal_server_error("missing"))
+        })
+    };
+
+    let app = App::create(chain![
+        mount("/users").with(chain![
+            state(DatabaseUrl("postgres://users")),
+            path!("/").to(endpoint::get()
+                .extract(extractor::state())
+                .call(|url: Arc<DatabaseUrl>| url.0)),
+            mount("/archive").with(chain![
+                state(DatabaseUrl("postgres://archive")),
+                path!("/").to(endpoint::get().extract(database_url()).call(|url| url)),
+            ]),
+            mount("/admin")
+                .with(path!("/").to(endpoint::get().extract(database_url()).call(|url| url)),),
+        ]),
+        mount("/posts").with(chain![
+            state(DatabaseUrl("postgres://posts")),
+            path!("/").to(endpoint::get().extract(database_url()).call(|url| url)),
+        ]),
+        mount("/tags").with(
+            path!("/").to(endpoint::get()
+                .extract(extractor::state())
+                .call(|url: Arc<DatabaseUrl>| url.0)),
+        ),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/users")?;
+    assert_eq!(response.body().to_utf8()?, "postgres://users");
+    let response = server.perform("/users/archive")?;
+    assert_eq!(response.body().to_utf8()?, "postgres://archive");
+    let response = server.perform("/users/admin")?;
+    assert_eq!(response.body().to_utf8()?, "postgres://users");
+    let response = server.perform("/posts")?;
+    assert_eq!(response.body().to_utf8()?, "postgres://posts");
+
+    // the states of sibling scopes are isolated.
+    let response = server.perform("/tags")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    Ok(())
+}
+
+#[test]
+fn mounted_app() -> tsukuyomi_server::Result<()> {
+    use std::sync::Arc;
+
+    #[derive(Debug)]
+    struct Name(&'static str);
+
+    let admin = App::create(chain![
+        state(Name("admin")),
+        path!("/")
+            .to(endpoint::get()
+                .extract(extractor::state())
+                .call(|name: Arc<Name>| name.0))
+            .name("admin"),
+        mount("/users")
+            .with(path!("/:id").to(endpoint::get().call(|id: u32| format!("user {}", id))),),
+        path!("/config").to(endpoint::get()
+            .extract(extractor::state())
+            .call(|n: Arc<u32>| n.to_string())),
+        path!("*").to(endpoint::reply("admin 404")),
+    ])?;
+
+    let app = App::create(chain![
+        state(Name("main")),
+        state(42u32),
+        path!("/").to(endpoint::get()
+            .extract(extractor::state())
+            .call(|name: Arc<Name>| name.0)),
+        path!("*").to(endpoint::reply("main 404")),
+        mount_app("/admin", admin.clone()),
+    ])?;
+    let routes = app.routes();
+    assert!(routes
+        .iter()
+        .any(|route| route.path() == "/admin/users/:id" && route.scopes().len() == 3));
+    assert!(routes
+        .iter()
+        .any(|route| route.path() == "/admin" && route.name() == Some("admin")));
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "main");
+
+    let response = server.perform("/admin")?;
+    assert_eq!(response.body().to_utf8()?, "admin");
+
+    let response = server.perform("/admin/users/7")?;
+    assert_eq!(response.body().to_utf8()?, "user 7");
+
+    // the states not registered in the mounted app are inherited from the enclosing scopes.
+    let response = server.perform("/admin/config")?;
+    assert_eq!(response.body().to_utf8()?, "42");
+
+    let response = server.perform("/admin/unknown")?;
+    assert_eq!(response.body().to_utf8()?, "admin 404");
+
+    let response = server.perform("/administrator")?;
+    assert_eq!(response.body().to_utf8()?, "main 404");
+
+    // the mounted app can still be used on its own.
+    let mut server = tsukuyomi_server::test::server(admin)?;
+    let response = server.perform("/config")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    Ok(())
+}
+
+#[test]
+fn mounted_app_conflicts() -> tsukuyomi::app::Result<()> {
+    let admin = || App::create(path!("/").to(endpoint::get().reply("admin")).name("index"));
+
+    let app = App::create(chain![
+        path!("/admin").to(endpoint::get().reply("admin")),
+        mount_app("/admin", admin()?),
+    ]);
+    assert!(app.is_err());
+
+    let app = App::create(chain![
+        path!("/").to(endpoint::get().reply("index")).name("index"),
+        mount_app("/admin", admin()?),
+    ]);
+    assert!(app.is_err());
+
+    let app = App::create(mount("/:tenant").with(mount_app("/admin", admin()?)));
+    assert!(app.is_err());
+
+    Ok(())
+}
+
+#[test]
+fn scope_config_override() -> tsukuyomi_server::Result<()> {
+    use {std::sync::Arc, tsukuyomi::config::BodyLimit};
+
+    #[derive(Debug)]
+    struct ErrorDetail(bool);
+
+    let describe = || {
+        endpoint::get()
+            .extract(extractor::scope_config())
+            .extract(extractor::scope_config())
+            .extract(extractor::ready(|input| {
+                // the same values are exposed through the metadata of the route.
+                let metadata = input.metadata();
+                Ok::<_, tsukuyomi::Error>((
+                    metadata.config::<BodyLimit>().map(|limit| limit.0),
+                    metadata.config::<ErrorDetail>().map(|detail| detail.0),
+                ))
+            }))
+            .call(
+                |limit: Option<Arc<BodyLimit>>,
+                 detail: Option<Arc<ErrorDetail>>,
+                 limit_in_metadata: Option<usize>,
+                 detail_in_metadata: Option<bool>| {
+                    let limit = limit.map(|limit| limit.0);
+                    let detail = detail.map(|detail| detail.0);
+                    assert_eq!((limit, detail), (limit_in_metadata, detail_in_metadata));
+                    format!("{:?},{:?}", limit, detail)
+                },
+            )
+    };
+
+    let app = App::create(chain![
+        path!("/").to(describe()),
+        mount("/a").with(chain![
+            path!("/").to(describe()),
+            mount("/b").with(chain![
+                path!("/").to(describe()),
+                scope_config(BodyLimit(10)),
+                scope_config(ErrorDetail(false)),
+            ]),
+            mount("/c").with(path!("/").to(describe())),
+            scope_config(BodyLimit(100)),
+        ]),
+        scope_config(BodyLimit(1000)),
+        scope_config(ErrorDetail(true)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "Some(1000),Some(true)");
+
+    let response = server.perform("/a")?;
+    assert_eq!(response.body().to_utf8()?, "Some(100),Some(true)");
+
+    let response = server.perform("/a/b")?;
+    assert_eq!(response.body().to_utf8()?, "Some(10),Some(false)");
+
+    let response = server.perform("/a/c")?;
+    assert_eq!(response.body().to_utf8()?, "Some(100),Some(true)");
+
+    let app = App::create(path!("/").to(describe()))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "None,None");
+
+    Ok(())
+}
+
+#[test]
+fn scope_config_body_limit() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::config::BodyLimit;
+
+    let echo = || {
+        endpoint::post()
+            .extract(extractor::body::read_all())
+            .call(|data: bytes::Bytes| data.len().to_string())
+    };
+
+    let app = App::create(chain![
+        path!("/").to(echo()),
+        mount("/small").with(chain![path!("/").to(echo()), scope_config(BodyLimit(4)),]),
+        scope_config(BodyLimit(8)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/").body("12345678"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "8");
+
+    let response = server.perform(Request::post("/").body("123456789"))?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let response = server.perform(Request::post("/small").body("1234"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = server.perform(Request::post("/small").body("12345"))?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    Ok(())
+}
+
+#[test]
+fn stream_body_error_after_commit() -> tsukuyomi_server::Result<()> {
+    use {
+        http::{header::HeaderName, Response, Version},
+        std::sync::{Arc, Mutex},
+        tsukuyomi::output::StreamBody,
+    };
+
+    let observed = Arc::new(Mutex::new(vec![]));
+    let app = App::create(chain![
+        observe_errors({
+            let observed = observed.clone();
+            move |event| {
+                observed.lock().unwrap().push((
+                    event.status(),
+                    event.committed(),
+                    event.request().version(),
+                    event.chain().to_vec(),
+                ));
+            }
+        }),
+        path!("/") //
+            .to(endpoint::call(|| {
+                let stream = futures01::stream::iter_result(vec![
+                    Ok("chunk1"),
+                    Err(std::io::Error::new(
+                        std::io::ErrorKind::Other,
+                        "cursor error",
+                    )),
+                    Ok("chunk2"),
+                ]);
+                Response::new(
+                    StreamBody::new(stream)
+                        .with_error_trailer(HeaderName::from_static("x-stream-error")),
+                )
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // HTTP/1.1: the transmission is aborted.
+    assert!(server.perform("/").is_err());
+
+    // HTTP/2: the transmission is completed with the error trailer.
+    let response = server.perform(Request::get("/").version(Version::HTTP_2))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "chunk1");
+    let trailers = response.body().trailers().expect("missing trailers");
+    assert_eq!(trailers["x-stream-error"], "cursor error");
+
+    // both errors are reported to the observer, with the status already sent.
+    let observed = observed.lock().unwrap();
+    assert_eq!(observed.len(), 2);
+    for (i, &(status, committed, version, ref chain)) in observed.iter().enumerate() {
+        assert_eq!(status, StatusCode::OK);
+        assert!(committed);
+        assert_eq!(
+            version,
+            if i == 0 {
+                Version::HTTP_11
+            } else {
+                Version::HTTP_2
+            }
+        );
+        assert_eq!(chain[0], "cursor error");
+    }
+
+    Ok(())
+}
+
+#[test]
+fn observe_errors_in_entire_app() -> tsukuyomi_server::Result<()> {
+    use std::sync::{Arc, Mutex};
+
+    let observed = Arc::new(Mutex::new(vec![]));
+    let app = App::create(chain![
+        observe_errors({
+            let observed = observed.clone();
+            move |event| {
+                observed.lock().unwrap().push((
+                    event.request().uri().path().to_owned(),
+                    event.status(),
+                    event.chain().to_vec(),
+                ));
+            }
+        }),
+        mount("/api").with(
+            path!("/fail") //
+                .to(endpoint::call(|| -> tsukuyomi::Result<&'static str> {
+                    Err(tsukuyomi::error::internal_server_error("oops"))
+                })),
+        ),
+        path!("/ok").to(endpoint::get().reply("ok")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/api/fail")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    let response = server.perform("/missing")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = server.perform(Request::post("/ok"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    let response = server.perform("/ok")?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let observed = observed.lock().unwrap();
+    assert_eq!(observed.len(), 3);
+    assert_eq!(observed[0].0, "/api/fail");
+    assert_eq!(observed[0].1, StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(observed[0].2, vec!["oops"]);
+    assert_eq!(observed[1].0, "/missing");
+    assert_eq!(observed[1].1, StatusCode::NOT_FOUND);
+    assert_eq!(observed[2].0, "/ok");
+    assert_eq!(observed[2].1, StatusCode::METHOD_NOT_ALLOWED);
+
+    // the observers must be registered at the root scope.
+    assert!(App::create(mount("/api").with(observe_errors(|_| {}))).is_err());
+
+    Ok(())
+}
+
+#[test]
+fn raw_chunk_extensions_and_pipelining() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/echo") //
+            .to(endpoint::post()
+                .extract(tsukuyomi::extractor::body::plain())
+                .call(|body: String| body)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let output = server.perform_raw(
+        &b"POST /echo HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           Transfer-Encoding: chunked\r\n\
+           \r\n\
+           3;name=value\r\nfox\r\n\
+           5;flag\r\n jump\r\n\
+           0\r\n\
+           \r\n\
+           POST /echo HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           Content-Length: 3\r\n\
+           \r\n\
+           dog"[..],
+    )?;
+    assert!(output.connection_error().is_none());
+    assert_eq!(output.responses().len(), 2);
+    assert_eq!(output.responses()[0].body().to_utf8()?, "fox jump");
+    assert_eq!(output.responses()[1].body().to_utf8()?, "dog");
+
+    Ok(())
+}
+
+#[test]
+fn raw_expect_continue() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/echo") //
+            .to(endpoint::post()
+                .extract(tsukuyomi::extractor::body::plain())
+                .call(|body: String| body)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let output = server.perform_raw(
+        &b"POST /echo HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           Content-Length: 3\r\n\
+           Expect: 100-continue\r\n\
+           \r\n\
+           fox"[..],
+    )?;
+    assert!(output
+        .to_utf8()?
+        .starts_with("HTTP/1.1 100 Continue\r\n\r\n"));
+    assert_eq!(output.responses().len(), 2);
+    assert_eq!(output.responses()[0].status(), 100);
+    let response = output.response().expect("missing response");
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "fox");
+
+    Ok(())
+}
+
+#[test]
+fn raw_folded_header_is_rejected() -> tsukuyomi_server::Result<()> {
+    let app = App::create(path!("/").to(endpoint::call(|| "hello")))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let output = server.perform_raw(
+        &b"GET / HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           X-Folded: first\r\n  second\r\n\
+           \r\n"[..],
+    )?;
+    assert!(output.connection_error().is_some());
+    assert_eq!(output.responses().len(), 1);
+    assert_eq!(output.responses()[0].status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn raw_content_length_of_buffered_bodies() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/string") //
+            .to(endpoint::get().call(|| String::from("hello"))),
+        path!("/bytes") //
+            .to(endpoint::get().call(|| http::Response::new(b"bytes".to_vec()))),
+        path!("/json") //
+            .to(endpoint::get().call(|| tsukuyomi::output::json(vec![1, 2, 3]))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for &(path, body) in &[
+        ("/string", "hello"),
+        ("/bytes", "bytes"),
+        ("/json", "[1,2,3]"),
+    ] {
+        let output =
+            server.perform_raw(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path))?;
+        let raw = output.to_utf8()?;
+        assert!(
+            raw.contains(&format!("content-length: {}\r\n", body.len())),
+            "{}",
+            raw
+        );
+        assert!(!raw.contains("transfer-encoding"), "{}", raw);
+        assert!(raw.ends_with(&format!("\r\n\r\n{}", body)), "{}", raw);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn raw_head_request_has_no_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::allow_only("GET, HEAD")?.call(|| "hello")),
+        path!("/stream") //
+            .to(endpoint::allow_only("GET, HEAD")?.call(|| {
+                http::Response::new(tsukuyomi::output::ResponseBody::wrap_stream(
+                    futures01::stream::iter_ok::<_, std::io::Error>(vec!["chunk1", "chunk2"]),
+                ))
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the response to HEAD keeps the length of the body sent to GET.
+    let response = server.perform(Request::head("/"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(header::CONTENT_LENGTH)?, "5");
+    assert!(response.body().to_bytes().is_empty());
+
+    let output = server.perform_raw(
+        &b"HEAD / HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           \r\n\
+           GET / HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           \r\n"[..],
+    )?;
+    let raw = output.to_utf8()?;
+    let head_end = raw.find("\r\n\r\n").expect("missing the end of header") + 4;
+    assert!(raw[..head_end].contains("content-length: 5\r\n"), "{}", raw);
+    assert!(
+        raw[head_end..].starts_with("HTTP/1.1 200 OK\r\n"),
+        "{}",
+        raw
+    );
+    assert!(raw.ends_with("\r\n\r\nhello"), "{}", raw);
+
+    // the body with unknown length does not claim `Content-Length: 0`.
+    let output = server.perform_raw(&b"HEAD /stream HTTP/1.1\r\nHost: localhost\r\n\r\n"[..])?;
+    let raw = output.to_utf8()?;
+    assert!(raw.starts_with("HTTP/1.1 200 OK\r\n"), "{}", raw);
+    assert!(!raw.contains("content-length"), "{}", raw);
+    assert!(raw.ends_with("\r\n\r\n"), "{}", raw);
+
+    Ok(())
+}
+
+#[test]
+fn raw_bodyless_statuses_discard_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/no-content") //
+            .to(endpoint::get().call(|| {
+                http::Response::builder()
+                    .status(StatusCode::NO_CONTENT)
+                    .body("ignored")
+                    .unwrap()
+            })),
+        path!("/not-modified") //
+            .to(endpoint::get().call(|| {
+                http::Response::builder()
+                    .status(StatusCode::NOT_MODIFIED)
+                    .body("ignored")
+                    .unwrap()
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/no-content")?;
+    assert_eq!(response.status(), 204);
+    assert!(!response.headers().contains_key(header::CONTENT_LENGTH));
+    assert!(response.body().to_bytes().is_empty());
+
+    for &(path, status_line) in &[
+        ("/no-content", "HTTP/1.1 204 No Content\r\n"),
+        ("/not-modified", "HTTP/1.1 304 Not Modified\r\n"),
+    ] {
+        let output =
+            server.perform_raw(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path))?;
+        let raw = output.to_utf8()?;
+        assert!(raw.starts_with(status_line), "{}", raw);
+        assert!(!raw.contains("ignored"), "{}", raw);
+        assert!(!raw.to_lowercase().contains("content-length"), "{}", raw);
+        assert!(raw.ends_with("\r\n\r\n"), "{}", raw);
+    }
+
+    Ok(())
+}
+
+fn trailing_slash_app(policy: TrailingSlash) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        policy,
+        path!("/posts") //
+            .to(endpoint::allow_only("GET, POST")?.call(|| "posts")),
+        path!("/posts/:id") //
+            .to(endpoint::get().call(|id: u32| format!("post {}", id))),
+        path!("/about/").to(endpoint::get().reply("about")),
+        path!("/static/*path") //
+            .to(endpoint::get().call(|path: String| format!("static {}", path))),
+        mount("/docs").with(chain![
+            path!("/intro").to(endpoint::get().reply("intro")),
+            path!("*").to(endpoint::call(|| "docs fallback")),
+        ]),
+    ])
+}
+
+#[test]
+fn trailing_slash_strict() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(trailing_slash_app(TrailingSlash::Strict)?)?;
+
+    let response = server.perform("/posts/")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server.perform(Request::post("/posts/"))?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server.perform("/posts/1/")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server.perform("/about")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server.perform("/docs/intro/")?;
+    assert_eq!(response.body().to_utf8()?, "docs fallback");
+
+    Ok(())
+}
+
+#[test]
+fn param_matches_empty_segment() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/posts/:id") //
+            .to(endpoint::get().call(|id: String| format!("post {:?}", id))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/posts/")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "post \"\"");
+
+    Ok(())
+}
+
+#[test]
+fn trailing_slash_redirect() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(trailing_slash_app(TrailingSlash::Redirect)?)?;
+
+    let response = server.perform("/posts/?page=2&sort=desc")?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(
+        response.header(header::LOCATION)?,
+        "/posts?page=2&sort=desc"
+    );
+
+    let response = server.perform(Request::post("/posts/"))?;
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.header(header::LOCATION)?, "/posts");
+
+    let response = server.perform("/posts/1/")?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.header(header::LOCATION)?, "/posts/1");
+
+    let response = server.perform("/about?lang=ja")?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.header(header::LOCATION)?, "/about/?lang=ja");
+
+    let response = server.perform(Request::post("/about"))?;
+    assert_eq!(response.status(), StatusCode::PERMANENT_REDIRECT);
+    assert_eq!(response.header(header::LOCATION)?, "/about/");
+
+    // the registered forms are served as they are.
+    let response = server.perform("/posts")?;
+    assert_eq!(response.body().to_utf8()?, "posts");
+    let response = server.perform("/posts/1")?;
+    assert_eq!(response.body().to_utf8()?, "post 1");
+    let response = server.perform("/about/")?;
+    assert_eq!(response.body().to_utf8()?, "about");
+
+    // the slash within the wildcard segment is left as it is.
+    let response = server.perform("/static/css/")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "static css/");
+
+    // the explicit route takes precedence over the fallback.
+    let response = server.perform("/docs/intro/")?;
+    assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+    assert_eq!(response.header(header::LOCATION)?, "/docs/intro");
+    let response = server.perform("/docs/intro/more/")?;
+    assert_eq!(response.body().to_utf8()?, "docs fallback");
+
+    Ok(())
+}
+
+#[test]
+fn trailing_slash_merge() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(trailing_slash_app(TrailingSlash::Merge)?)?;
+
+    let response = server.perform("/posts/?page=2")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "posts");
+
+    let response = server.perform(Request::post("/posts/"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "posts");
+
+    let response = server.perform("/posts/1/")?;
+    assert_eq!(response.body().to_utf8()?, "post 1");
+
+    let response = server.perform(Request::post("/posts/1/"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let response = server.perform("/about")?;
+    assert_eq!(response.body().to_utf8()?, "about");
+
+    let response = server.perform("/static/css/")?;
+    assert_eq!(response.body().to_utf8()?, "static css/");
+
+    let response = server.perform("/docs/intro/")?;
+    assert_eq!(response.body().to_utf8()?, "intro");
+
+    Ok(())
+}
+
+#[test]
+fn trailing_slash_outside_root() {
+    let app = App::create(mount("/api").with(TrailingSlash::Merge));
+    assert!(app.is_err());
+}
+
+fn method_override_app(policy: MethodOverride) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        policy,
+        path!("/posts/:id").to(endpoint::allow_only("GET, PUT, DELETE")?
+            .extract(extractor::ready(|input| {
+                Ok::<_, tsukuyomi::error::Error>((
+                    input.request.method().clone(),
+                    input.original_method().clone(),
+                ))
+            }))
+            .extract(extractor::body::read_all())
+            .call(
+                |id: u32, method: http::Method, original: http::Method, body: bytes::Bytes| {
+                    let body = String::from_utf8_lossy(&body);
+                    format!("{} {} (original: {}, body: {})", method, id, original, body)
+                }
+            )),
+    ])
+}
+
+#[test]
+fn method_override_header() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(method_override_app(MethodOverride::new())?)?;
+
+    let response = server.perform(
+        Request::post("/posts/1")
+            .header("x-http-method-override", "put")
+            .body("title=foo"),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "PUT 1 (original: POST, body: title=foo)"
+    );
+
+    // the methods outside of the allowlist are rejected.
+    let response = server.perform(
+        Request::post("/posts/1")
+            .header("x-http-method-override", "GET")
+            .body(""),
+    )?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    // only POST is overridden.
+    let response = server.perform(
+        Request::get("/posts/1")
+            .header("x-http-method-override", "DELETE")
+            .body(""),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "GET 1 (original: GET, body: )");
+
+    let response = server.perform(
+        Request::post("/posts/1")
+            .header("x-http-method-override", "DELETE")
+            .body(""),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "DELETE 1 (original: POST, body: )"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn method_override_form_field() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(method_override_app(
+        MethodOverride::new().allow(vec![http::Method::DELETE]),
+    )?)?;
+
+    let form = |body: &'static str| {
+        Request::post("/posts/2")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body)
+    };
+
+    let response = server.perform(form("_method=delete&confirm=yes"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "DELETE 2 (original: POST, body: _method=delete&confirm=yes)"
+    );
+
+    let response = server.perform(form("_method=PUT"))?;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    let response = server.perform(form("confirm=yes"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // the form is not inspected when the body is not a form.
+    let response = server.perform(
+        Request::post("/posts/2")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .body("_method=DELETE"),
+    )?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    let mut server = tsukuyomi_server::test::server(method_override_app(
+        MethodOverride::new().form_field(false),
+    )?)?;
+    let response = server.perform(form("_method=DELETE"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // only the first field is inspected.
+    let response = server.perform(form("confirm=yes&_method=DELETE"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
+
+#[test]
+fn method_override_large_form() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(method_override_app(MethodOverride::new())?)?;
+
+    // the large forms are passed to the handler as they are, without being rejected.
+    let data = "x".repeat(256 * 1024);
+    let body = format!("_method=DELETE&data={}", data);
+    let response = server.perform(
+        Request::post("/posts/3")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(body.clone()),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body().to_utf8()?,
+        format!("DELETE 3 (original: POST, body: {})", body)
+    );
+
+    let response = server.perform(
+        Request::post("/posts/3")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(format!("data={}&_method=DELETE", data)),
+    )?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    // the field split into multiple chunks.
+    let chunks = vec!["_me", "thod=DEL", "ETE", "&data=", "foo"];
+    let response = server.perform(
+        Request::post("/posts/3")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(hyper::Body::wrap_stream(futures01::stream::iter_ok::<
+                _,
+                std::io::Error,
+            >(chunks))),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "DELETE 3 (original: POST, body: _method=DELETE&data=foo)"
+    );
+
+    // the too long value of the first field is not inspected.
+    let response = server.perform(
+        Request::post("/posts/3")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(format!("_method={}", data)),
+    )?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+    Ok(())
+}
+
+#[test]
+fn method_override_outside_root() {
+    let app = App::create(mount("/api").with(MethodOverride::new()));
+    assert!(app.is_err());
+}
+
+#[test]
+fn host_scopes() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        mount("/").with(chain![
+            host("api.example.com"),
+            path!("/").to(endpoint::get().reply("api index")),
+            path!("/users").to(endpoint::get().reply("api users")),
+            path!("*").to(endpoint::call(|| "api fallback")),
+        ]),
+        mount("/").with(chain![
+            host("www.example.com"),
+            path!("/").to(endpoint::get().reply("www index")),
+        ]),
+        mount("/").with(chain![
+            host("*.users.example.com"),
+            path!("/profile").to(endpoint::get()
+                .extract(extractor::subdomain())
+                .call(|user: String| format!("profile of {}", user))),
+        ]),
+        path!("/users").to(endpoint::get().reply("default users")),
+        path!("*").to(endpoint::call(|| "default fallback")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let get = |host: &str, path: &str| {
+        let mut request = Request::get(path);
+        request.header(header::HOST, host);
+        request
+    };
+
+    let response = server.perform(get("api.example.com", "/"))?;
+    assert_eq!(response.body().to_utf8()?, "api index");
+
+    let response = server.perform(get("WWW.example.com:8080", "/"))?;
+    assert_eq!(response.body().to_utf8()?, "www index");
+
+    let response = server.perform(get("api.example.com", "/users"))?;
+    assert_eq!(response.body().to_utf8()?, "api users");
+
+    // the fallback of the scope is selected only for the matched host.
+    let response = server.perform(get("api.example.com", "/missing"))?;
+    assert_eq!(response.body().to_utf8()?, "api fallback");
+
+    // the request for another host continues to the other scopes.
+    let response = server.perform(get("www.example.com", "/users"))?;
+    assert_eq!(response.body().to_utf8()?, "default users");
+
+    let response = server.perform(get("example.org", "/"))?;
+    assert_eq!(response.body().to_utf8()?, "default fallback");
+
+    let response = server.perform(get("alice.users.example.com", "/profile"))?;
+    assert_eq!(response.body().to_utf8()?, "profile of alice");
+
+    let response = server.perform(get("users.example.com", "/profile"))?;
+    assert_eq!(response.body().to_utf8()?, "default fallback");
+
+    // the authority in the request URI takes precedence over `Host`.
+    let response = server.perform(
+        Request::get("http://bob.users.example.com/profile")
+            .header(header::HOST, "api.example.com"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "profile of bob");
+
+    Ok(())
+}
+
+#[test]
+fn host_scopes_conflict() {
+    let duplicated = App::create(chain![
+        mount("/").with(chain![
+            host("api.example.com"),
+            path!("/").to(endpoint::get().reply("")),
+        ]),
+        mount("/").with(chain![
+            host("API.example.com"),
+            path!("/").to(endpoint::get().reply("")),
+        ]),
+    ]);
+    assert!(duplicated.is_err());
+
+    let nested = App::create(mount("/").with(chain![
+        host("*.example.com"),
+        mount("/api").with(chain![
+            host("api.example.com"),
+            path!("/").to(endpoint::get().reply("")),
+        ]),
+    ]));
+    assert!(nested.is_err());
+
+    let invalid = App::create(mount("/").with(host("api.*.com")));
+    assert!(invalid.is_err());
+
+    // the same path without the hosts conflicts as before.
+    let unconstrained = App::create(chain![
+        path!("/").to(endpoint::get().reply("")),
+        path!("/").to(endpoint::call(|| "")),
+    ]);
+    assert!(unconstrained.is_err());
+}
+
+#[test]
+fn split_methods() -> tsukuyomi_server::Result<()> {
+    let app = App::create(mount("/api/v1/posts").with(chain![
+        path!("/").to(endpoint::get().reply("list")),
+        path!("/").to(endpoint::post().reply("create")),
+        path!("/:id").to(chain![
+            endpoint::get().call(|id: u32| format!("get {}", id)),
+            endpoint::delete().call(|id: u32| format!("delete {}", id)),
+        ]),
+        path!("/:id").to(endpoint::put().call(|id: u32| format!("put {}", id))),
+    ]))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/api/v1/posts")?;
+    assert_eq!(response.body().to_utf8()?, "list");
+    let response = server.perform(Request::post("/api/v1/posts"))?;
+    assert_eq!(response.body().to_utf8()?, "create");
+    let response = server.perform(Request::delete("/api/v1/posts"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header(header::ALLOW)?, "GET, POST");
+
+    let response = server.perform(Request::put("/api/v1/posts/1"))?;
+    assert_eq!(response.body().to_utf8()?, "put 1");
+    let response = server.perform(Request::delete("/api/v1/posts/1"))?;
+    assert_eq!(response.body().to_utf8()?, "delete 1");
+    let response = server.perform(Request::patch("/api/v1/posts/1"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header(header::ALLOW)?, "GET, DELETE, PUT");
+
+    Ok(())
+}
+
+#[test]
+fn optional_params() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/archive/:year/:month?/:day?") //
+            .to(
+                endpoint::get().call(|year: u32, month: Option<u32>, day: Option<u32>| {
+                    match (month, day) {
+                        (Some(month), Some(day)) => format!("{}-{:02}-{:02}", year, month, day),
+                        (Some(month), None) => format!("{}-{:02}", year, month),
+                        _ => format!("{}", year),
+                    }
+                })
+            )
+            .fallback(endpoint::call(|| "archive fallback")),
+        mount("/posts").with(
+            path!("/:page?") //
+                .to(endpoint::get()
+                    .call(|page: Option<u32>| { format!("page {}", page.unwrap_or(1)) }))
+        ),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/archive/2018")?;
+    assert_eq!(response.body().to_utf8()?, "2018");
+    let response = server.perform("/archive/2018/12")?;
+    assert_eq!(response.body().to_utf8()?, "2018-12");
+    let response = server.perform("/archive/2018/12/24")?;
+    assert_eq!(response.body().to_utf8()?, "2018-12-24");
+    let response = server.perform("/archive/2018/12/24/more")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = server.perform("/archive/2018/dec")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = server.perform(Request::post("/archive/2018/12"))?;
+    assert_eq!(response.body().to_utf8()?, "archive fallback");
+
+    let response = server.perform("/posts")?;
+    assert_eq!(response.body().to_utf8()?, "page 1");
+    let response = server.perform("/posts/3")?;
+    assert_eq!(response.body().to_utf8()?, "page 3");
+
+    Ok(())
+}
+
+#[test]
+fn optional_params_conflict() {
+    let app = App::create(chain![
+        path!("/archive/:year/:month?").to(endpoint::get().call(|_: u32, _: Option<u32>| "")),
+        path!("/archive/:year").to(endpoint::get().call(|_: u32| "")),
+    ]);
+    let message = app.err().expect("should be failed").to_string();
+    assert!(
+        message.contains(
+            "`/archive/:year` (expanded from `/archive/:year/:month?`, in the root scope"
+        ),
+        "{}",
+        message
+    );
+
+    let app = App::create(chain![
+        path!("/archive/:year").to(endpoint::get().call(|_: u32| "")),
+        path!("/archive/:year/:month?").to(endpoint::post().call(|_: u32, _: Option<u32>| "")),
+    ]);
+    assert!(app.is_ok());
+}
+
+#[test]
+fn catch_all_in_middle() -> tsukuyomi_server::Result<()> {
+    let app = App::create(|scope: &mut Scope<'_, _, _>| {
+        let refs = scope.route_ref("refs");
+        chain![
+            path!("/repos/*path/info/refs")
+                .to(endpoint::get().call(|repo: String| format!("refs of {}", repo)))
+                .name("refs"),
+            path!("/repos/*path/git-upload-pack")
+                .to(endpoint::post().call(|repo: String| format!("upload-pack of {}", repo))),
+            path!("/repos/*path").to(endpoint::get().call(|path: String| format!("file {}", path))),
+            path!("/clone/*path")
+                .to(endpoint::get()
+                    .call(move |repo: String| { redirect::to_route(&refs, vec![repo]) })),
+        ]
+        .configure(scope)
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/repos/alice/tools.git/info/refs")?;
+    assert_eq!(response.body().to_utf8()?, "refs of alice/tools.git");
+    let response = server.perform(Request::post("/repos/alice/tools.git/git-upload-pack"))?;
+    assert_eq!(response.body().to_utf8()?, "upload-pack of alice/tools.git");
+
+    // the trailing segments are matched at the end of path.
+    let response = server.perform("/repos/info/refs/info/refs")?;
+    assert_eq!(response.body().to_utf8()?, "refs of info/refs");
+
+    // the route ending with the catch-all parameter handles the others.
+    let response = server.perform("/repos/alice/tools.git/info")?;
+    assert_eq!(response.body().to_utf8()?, "file alice/tools.git/info");
+    let response = server.perform("/repos/info/refs")?;
+    assert_eq!(response.body().to_utf8()?, "file info/refs");
+
+    let response = server.perform("/clone/alice/tools.git")?;
+    assert_eq!(
+        response.header(header::LOCATION)?,
+        "/repos/alice/tools.git/info/refs"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn catch_all_in_middle_conflict() {
+    let app = App::create(chain![
+        path!("/repos/*path/info/refs").to(endpoint::get().call(|_: String| "")),
+        path!("/repos/*path/refs").to(endpoint::get().call(|_: String| "")),
+    ]);
+    let message = app.err().expect("should be failed").to_string();
+    assert!(
+        message.contains("cannot end with those of another route"),
+        "{}",
+        message
+    );
+
+    let app = App::create(chain![
+        path!("/repos/:name/info/refs").to(endpoint::get().call(|_: String| "")),
+        path!("/repos/*path/info/refs").to(endpoint::get().call(|_: String| "")),
+    ]);
+    assert!(app.is_err());
+}
+
+#[test]
+fn route_conflicts() {
+    let message =
+        |app: tsukuyomi::app::Result<App>| app.err().expect("should be failed").to_string();
+
+    let line = line!() + 2;
+    let err = message(App::create(chain![
+        path!("/users/:id").to(endpoint::get().call(|_: u32| "")),
+        path!("/users/:name").to(endpoint::get().call(|_: String| "")),
+    ]));
+    assert!(
+        err.contains(&format!(
+            "the route `/users/:name` (in the root scope, at {}:{}) conflicts with \
+             the route `/users/:id` (in the root scope, at {}:{})",
+            file!(),
+            line + 1,
+            file!(),
+            line
+        )),
+        "{}",
+        err
+    );
+
+    let err = message(App::create(chain![
+        path!("/api/users").to(endpoint::get().reply("")),
+        mount("/api").with(mount("/").with(path!("/users").to(endpoint::get().reply("")))),
+    ]));
+    assert!(
+        err.contains("the route `/api/users` (in the scope `/api`, at "),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("the route `/api/users` (in the root scope, at "),
+        "{}",
+        err
+    );
+
+    // the static segment would never match after the parameter at the same position.
+    let err = message(App::create(mount("/users").with(chain![
+        path!("/:id").to(endpoint::get().call(|_: u32| "")),
+        path!("/new").to(endpoint::get().reply("")),
+    ])));
+    assert!(
+        err.contains("the route `/users/new` (in the scope `/users`, at "),
+        "{}",
+        err
+    );
+    assert!(
+        err.contains("conflicts with the route `/users/:id` (in the scope `/users`, at "),
+        "{}",
+        err
+    );
+
+    let err = message(App::create(chain![
+        path!("/files/*path").to(endpoint::get().call(|_: String| "")),
+        path!("/files/").to(endpoint::get().reply("")),
+    ]));
+    assert!(
+        err.contains("conflicts with the route `/files/*path`"),
+        "{}",
+        err
+    );
+}
+
+#[test]
+fn split_methods_conflict() {
+    let app = App::create(chain![
+        path!("/posts").to(endpoint::get().reply("")),
+        path!("/posts").to(endpoint::call(|| "")),
+    ]);
+    assert!(app.is_err());
+}
+
+#[test]
+fn disallowed_method_not_found() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/posts").to(endpoint::get().reply("posts")),
+        path!("/posts").to(endpoint::post().reply("created")),
+        mount("/admin").with(chain![
+            scope_config(DisallowedMethod::NotFound),
+            path!("/users").to(endpoint::get().reply("users")),
+            path!("/users").to(endpoint::post().reply("created")),
+            path!("/settings")
+                .to(endpoint::get().reply("settings"))
+                .fallback(endpoint::call(|| "settings fallback")),
+            path!("*").to(endpoint::call(|| "admin fallback")),
+        ]),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::delete("/posts"))?;
+    assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    assert_eq!(response.header(header::ALLOW)?, "GET, POST");
+
+    let response = server.perform(Request::post("/admin/users"))?;
+    assert_eq!(response.body().to_utf8()?, "created");
+    let response = server.perform(Request::delete("/admin/users"))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "admin fallback");
+    assert!(response.headers().get(header::ALLOW).is_none());
+
+    let response = server.perform(Request::delete("/admin/settings"))?;
+    assert_eq!(response.body().to_utf8()?, "settings fallback");
+
+    Ok(())
+}
+
+fn mount_point_app(mount_point: Option<MountPoint>) -> tsukuyomi::app::Result<App> {
+    App::create(|scope: &mut Scope<'_, _, _>| {
+        let login = scope.route_ref("login");
+        chain![
+            mount_point,
+            TrailingSlash::Redirect,
+            path!("/login")
+                .to(endpoint::get().reply("login form"))
+                .name("login"),
+            path!("/admin").to(endpoint::get().call(move || redirect::to_route(&login, ()))),
+            path!("/posts/:id").to(endpoint::get()
+                .extract(extractor::ready(|input| {
+                    Ok::<_, tsukuyomi::error::Error>((
+                        input.raw_path().to_owned(),
+                        input.request.uri().path().to_owned(),
+                    ))
+                }))
+                .call(|id: u32, raw: String, path: String| format!(
+                    "post {} (raw: {}, effective: {})",
+                    id, raw, path
+                ))),
+        ]
+        .configure(scope)
+    })
+}
+
+#[test]
+fn mount_point() -> tsukuyomi_server::Result<()> {
+    for &(mount_point, prefix) in &[(None, ""), (Some("/service-a/"), "/service-a")] {
+        let mut server =
+            tsukuyomi_server::test::server(mount_point_app(mount_point.map(MountPoint::new))?)?;
+
+        let response = server.perform(format!("{}/login", prefix))?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().to_utf8()?, "login form");
+
+        // the locations generated by the application include the mount point.
+        let response = server.perform(format!("{}/admin", prefix))?;
+        assert_eq!(response.status(), StatusCode::FOUND);
+        assert_eq!(
+            response.header(header::LOCATION)?,
+            format!("{}/login", prefix).as_str()
+        );
+
+        let response = server.perform(format!("{}/login/", prefix))?;
+        assert_eq!(response.status(), StatusCode::MOVED_PERMANENTLY);
+        assert_eq!(
+            response.header(header::LOCATION)?,
+            format!("{}/login", prefix).as_str()
+        );
+
+        let response = server.perform(format!("{}/posts/42?page=1", prefix))?;
+        assert_eq!(
+            response.body().to_utf8()?,
+            format!("post 42 (raw: {}/posts/42, effective: /posts/42)", prefix)
+        );
+    }
+
+    let mut server =
+        tsukuyomi_server::test::server(mount_point_app(Some(MountPoint::new("/service-a")))?)?;
+
+    // the requests outside of the mount point are never routed.
+    let response = server.perform("/login")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = server.perform("/service-ab/login")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
+
+#[test]
+fn mount_point_forwarded_prefix() -> tsukuyomi_server::Result<()> {
+    let request = |path: &str| {
+        Request::get(path)
+            .header("x-forwarded-prefix", "/service-a")
+            .body("")
+    };
+
+    let mut server =
+        tsukuyomi_server::test::server(mount_point_app(Some(MountPoint::new("/v1")))?)?;
+    let response = server.perform(Request::get("/v1/admin"))?;
+    assert_eq!(response.header(header::LOCATION)?, "/v1/login");
+    // the header field is ignored unless trusted.
+    let response = server.perform(request("/v1/admin"))?;
+    assert_eq!(response.header(header::LOCATION)?, "/v1/login");
+
+    let mut server = tsukuyomi_server::test::server(mount_point_app(Some(
+        MountPoint::new("/").forwarded_prefix(true),
+    ))?)?;
+    let response = server.perform(request("/admin"))?;
+    assert_eq!(response.status(), StatusCode::FOUND);
+    assert_eq!(response.header(header::LOCATION)?, "/service-a/login");
+
+    Ok(())
+}
+
+#[test]
+fn mount_point_invalid() {
+    assert!(mount_point_app(Some(MountPoint::new("service-a"))).is_err());
+    assert!(mount_point_app(Some(MountPoint::new("/:tenant"))).is_err());
+    assert!(App::create(mount("/api").with(MountPoint::new("/service-a"))).is_err());
+}
+
+fn path_normalization_app(policy: Option<PathNormalization>) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        policy,
+        MountPoint::new("/service-a"),
+        path!("/a/b").to(endpoint::get().reply("a/b")),
+        path!("/static/*path").to(endpoint::get()
+            .extract(extractor::ready(|input| {
+                Ok::<_, tsukuyomi::error::Error>((input.raw_path().to_owned(),))
+            }))
+            .call(|path: std::path::PathBuf, raw: String| format!(
+                "static {} (raw: {})",
+                path.display(),
+                raw
+            ))),
+    ])
+}
+
+#[test]
+fn path_normalization() -> tsukuyomi_server::Result<()> {
+    let mut server =
+        tsukuyomi_server::test::server(path_normalization_app(Some(PathNormalization::new()))?)?;
+
+    for path in &[
+        "/service-a/a/b",
+        "/service-a//a//b",
+        "/service-a/a/./b",
+        "/service-a/a/c/../b",
+        "/service-a/a/%2e/b",
+        "/service-a/a/c/%2E%2e/b",
+        "//service-a/a/b",
+        "/service-a/x/../a/b",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        assert_eq!(response.body().to_utf8()?, "a/b", "{}", path);
+    }
+
+    // the wildcard captures the normalized remainder, and the raw path is kept.
+    let response = server.perform("/service-a/static//css/./site.css?v=1")?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "static css/site.css (raw: /service-a/static//css/./site.css)"
+    );
+
+    // the paths going up beyond the root are rejected.
+    for path in &[
+        "/..",
+        "/service-a/../../etc/passwd",
+        "/service-a/static/%2e%2e/%2e%2e/%2e%2e/etc/passwd",
+        "/service-a/static/.%2e/.%2E/%2E./etc/passwd",
+        "/service-a//..//..//..//etc/passwd",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST, "{}", path);
+    }
+
+    // the traversal out of the mount point is resolved before stripping it.
+    let response = server.perform("/service-a/../a/b")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    // the encoded slashes are not segment separators, and rejected by the extractor.
+    for path in &[
+        "/service-a/static/..%2f..%2fetc%2fpasswd",
+        "/service-a/static/%2e%2e%2fsecret",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND, "{}", path);
+    }
+
+    // the double-encoded dots are decoded only once, as a literal name.
+    let response = server.perform("/service-a/static/%252e%252e/secret")?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "static %2e%2e/secret (raw: /service-a/static/%252e%252e/secret)"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn path_normalization_disabled() -> tsukuyomi_server::Result<()> {
+    let mut server = tsukuyomi_server::test::server(path_normalization_app(None)?)?;
+
+    let response = server.perform("/service-a/a/b")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = server.perform("/service-a//a//b")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    let response = server.perform("/service-a/a/./b")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let mut server = tsukuyomi_server::test::server(path_normalization_app(Some(
+        PathNormalization::new().dot_segments(false),
+    ))?)?;
+    let response = server.perform("/service-a//a//b")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    let response = server.perform("/service-a/a/./b")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    Ok(())
+}
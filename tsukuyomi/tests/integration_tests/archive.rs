@@ -0,0 +1,179 @@
+use {
+    http::header::{CONTENT_DISPOSITION, CONTENT_TYPE},
+    std::{
+        collections::BTreeMap,
+        path::{Path, PathBuf},
+    },
+    tsukuyomi::{config::prelude::*, fs::ArchiveDir, App},
+    tsukuyomi_server::test::ResponseExt,
+};
+
+/// Creates a tree of files in a temporary directory, removed when dropped.
+struct Fixture(PathBuf);
+
+impl Fixture {
+    fn new(name: &str) -> std::io::Result<Self> {
+        let root =
+            std::env::temp_dir().join(format!("tsukuyomi-archive-{}-{}", name, std::process::id()));
+        let tree = root.join("tree");
+        std::fs::create_dir_all(tree.join("logs/old"))?;
+        std::fs::create_dir_all(tree.join("empty"))?;
+        std::fs::write(tree.join("README.txt"), "readme")?;
+        std::fs::write(
+            tree.join("logs/app.log"),
+            (0..50_000)
+                .map(|i| format!("line {}\n", i))
+                .collect::<String>(),
+        )?;
+        std::fs::write(tree.join("logs/old/app.log.1"), "rotated")?;
+        std::fs::write(tree.join("logs/old/app.1.log"), "rotated")?;
+
+        // a path longer than the name field of tar.
+        let long = tree.join("x".repeat(60)).join("y".repeat(60));
+        std::fs::create_dir_all(&long)?;
+        std::fs::write(long.join("long.txt"), "long")?;
+
+        Ok(Fixture(root))
+    }
+
+    fn tree(&self) -> PathBuf {
+        self.0.join("tree")
+    }
+
+    fn unpacked(&self) -> PathBuf {
+        self.0.join("unpacked")
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+/// Collects the relative paths in the directory, with the contents of the files.
+fn snapshot(root: &Path) -> std::io::Result<BTreeMap<String, Option<Vec<u8>>>> {
+    fn walk(
+        root: &Path,
+        dir: &Path,
+        entries: &mut BTreeMap<String, Option<Vec<u8>>>,
+    ) -> std::io::Result<()> {
+        for entry in std::fs::read_dir(dir)? {
+            let path = entry?.path();
+            let name = path
+                .strip_prefix(root)
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            if path.is_dir() {
+                entries.insert(name, None);
+                walk(root, &path, entries)?;
+            } else {
+                entries.insert(name, Some(std::fs::read(&path)?));
+            }
+        }
+        Ok(())
+    }
+    let mut entries = BTreeMap::new();
+    walk(root, root, &mut entries)?;
+    Ok(entries)
+}
+
+#[test]
+fn archive_dir_tar() -> tsukuyomi_server::Result<()> {
+    let fixture = Fixture::new("tar")?;
+    let tree = fixture.tree();
+
+    let app = App::create(chain![
+        path!("/tree.tar").to(endpoint::get().call({
+            let tree = tree.clone();
+            move || ArchiveDir::new(&tree).chunk_size(4096)
+        })),
+        path!("/logs.tar").to(endpoint::get().call({
+            let tree = tree.clone();
+            move || {
+                ArchiveDir::new(&tree)
+                    .include("*.log")
+                    .exclude("logs/old")
+                    .filename("logs.tar")
+            }
+        })),
+        path!("/missing.tar").to(endpoint::get().call({
+            let missing = tree.join("missing");
+            move || ArchiveDir::new(&missing)
+        })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/tree.tar")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/x-tar");
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"tree.tar\""
+    );
+    let body = response.body().to_bytes();
+    tar::Archive::new(&*body).unpack(fixture.unpacked())?;
+    assert_eq!(snapshot(&fixture.unpacked())?, snapshot(&tree)?);
+
+    let response = server.perform("/logs.tar")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"logs.tar\""
+    );
+    let body = response.body().to_bytes();
+    let mut archive = tar::Archive::new(&*body);
+    let names = archive
+        .entries()?
+        .map(|entry| Ok(entry?.path()?.to_string_lossy().into_owned()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    assert_eq!(names, vec!["logs/app.log"]);
+
+    let response = server.perform("/missing.tar")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[cfg(feature = "archive-zip")]
+#[test]
+fn archive_dir_zip() -> tsukuyomi_server::Result<()> {
+    use {std::io::Read, tsukuyomi::fs::ArchiveFormat};
+
+    let fixture = Fixture::new("zip")?;
+    let tree = fixture.tree();
+
+    let app = App::create(path!("/tree.zip").to(endpoint::get().call({
+        let tree = tree.clone();
+        move || ArchiveDir::new(&tree).format(ArchiveFormat::Zip)
+    })))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/tree.zip")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/zip");
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"tree.zip\""
+    );
+    let body = response.body().to_bytes();
+
+    let mut archive = zip::ZipArchive::new(std::io::Cursor::new(&*body))?;
+    let unpacked = fixture.unpacked();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i)?;
+        let path = unpacked.join(file.name());
+        if file.name().ends_with('/') {
+            std::fs::create_dir_all(&path)?;
+        } else {
+            let mut content = vec![];
+            file.read_to_end(&mut content)?;
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(&path, content)?;
+        }
+    }
+    assert_eq!(snapshot(&unpacked)?, snapshot(&tree)?);
+
+    Ok(())
+}
@@ -0,0 +1,100 @@
+use {
+    http::{header::ALLOW, Method, Request, Response},
+    tsukuyomi::{
+        config::prelude::*, //
+        guard::Decision,
+        modifiers,
+        test::conformance::{Conformance, Invariant},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn conforming_app() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::reply("index")),
+        path!("/posts")
+            .to(endpoint::allow_only("GET, POST")?.call(|| "posts"))
+            .modify(modifiers::default_options()),
+        path!("/posts/:id") //
+            .to(endpoint::get().call(|id: u32| format!("post {}", id))),
+        path!("/static/*path") //
+            .to(endpoint::get().call(|path: String| path)),
+    ])?;
+
+    let report = Conformance::new().run(&app);
+    report.assert();
+    assert!(report.requests() > 0);
+
+    Ok(())
+}
+
+#[test]
+fn method_not_allowed_with_allow() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/posts") //
+            .to(chain![
+                endpoint::get().reply("list"),
+                endpoint::post().reply("create"),
+            ]),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::delete("/posts"))?;
+    assert_eq!(response.status(), 405);
+    let allow = response.header(ALLOW)?.to_str()?;
+    let mut methods: Vec<_> = allow.split(',').map(str::trim).collect();
+    methods.sort();
+    assert_eq!(methods, vec!["GET", "POST"]);
+
+    Ok(())
+}
+
+#[test]
+fn preflight_rejected_by_guard() -> tsukuyomi::app::Result<()> {
+    let app = App::create(
+        path!("/admin")
+            .to(endpoint::get().reply("admin"))
+            .modify(tsukuyomi::guard::client_cert(|_| Decision::Allow))
+            .modify(modifiers::default_options()),
+    )?;
+
+    let report = Conformance::new().run(&app);
+    assert!(!report.is_ok());
+    assert!(report.violations().iter().any(|violation| {
+        violation.invariant() == Invariant::PreflightBypassesAuth
+            && *violation.method() == Method::OPTIONS
+            && violation.path() == "/admin"
+    }));
+
+    assert!(Conformance::new().skip("/admin").run(&app).is_ok());
+
+    Ok(())
+}
+
+#[test]
+fn cors_headers_without_origin() -> tsukuyomi::app::Result<()> {
+    let app = App::create(
+        path!("/data") //
+            .to(endpoint::get().call(|| {
+                Response::builder()
+                    .header("access-control-allow-origin", "https://example.com")
+                    .body("data")
+                    .unwrap()
+            })),
+    )?;
+
+    let report = Conformance::new().run(&app);
+    let invariants: Vec<_> = report
+        .violations()
+        .iter()
+        .map(|violation| violation.invariant())
+        .collect();
+    assert!(invariants.contains(&Invariant::NoCorsWithoutOrigin));
+    assert!(invariants.contains(&Invariant::VaryOrigin));
+    assert!(!invariants.contains(&Invariant::AllowAccuracy));
+
+    Ok(())
+}
@@ -48,3 +48,80 @@ fn disable_manage_cookies() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn responder_with_cookie() -> tsukuyomi_server::Result<()> {
+    use {cookie::SameSite, tsukuyomi::responder::ResponderExt};
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().call(|| {
+                "hello"
+                    .with_cookie(
+                        Cookie::build("theme", "dark mode")
+                            .path("/app")
+                            .domain("example.com")
+                            .same_site(SameSite::Strict)
+                            .secure(true)
+                            .http_only(true)
+                            .max_age(time::Duration::hours(1))
+                            .finish(),
+                    )
+                    .with_cookie(Cookie::new("lang", "ja"))
+                    .remove_cookie("visited")
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    let cookies = response
+        .headers()
+        .get_all(http::header::SET_COOKIE)
+        .iter()
+        .map(|value| Cookie::parse_encoded(value.to_str().unwrap().to_owned()).unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(cookies.len(), 3);
+
+    let theme = cookies.iter().find(|c| c.name() == "theme").unwrap();
+    assert_eq!(theme.value(), "dark mode");
+    assert_eq!(theme.path(), Some("/app"));
+    assert_eq!(theme.domain(), Some("example.com"));
+    assert_eq!(theme.same_site(), Some(SameSite::Strict));
+    assert_eq!(theme.secure(), Some(true));
+    assert_eq!(theme.http_only(), Some(true));
+    assert_eq!(theme.max_age(), Some(time::Duration::hours(1)));
+
+    let lang = cookies.iter().find(|c| c.name() == "lang").unwrap();
+    assert_eq!(lang.value(), "ja");
+
+    let visited = cookies.iter().find(|c| c.name() == "visited").unwrap();
+    assert_eq!(visited.value(), "");
+    assert_eq!(visited.path(), Some("/"));
+    assert_eq!(visited.max_age(), Some(time::Duration::seconds(0)));
+
+    Ok(())
+}
+
+#[test]
+fn responder_with_cookie_keeps_jar_cookies() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::responder::{oneshot, ResponderExt};
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().call(|| {
+                oneshot(|input| {
+                    input.cookies.jar()?.add(Cookie::new("session", "xxxx"));
+                    Ok::<_, tsukuyomi::Error>("")
+                })
+                .with_cookie(Cookie::new("theme", "dark"))
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let mut session = server.new_session()?.save_cookies(true);
+    let _ = session.perform("/")?;
+    assert_eq!(session.cookie("session"), Some("xxxx"));
+    assert_eq!(session.cookie("theme"), Some("dark"));
+
+    Ok(())
+}
@@ -0,0 +1,139 @@
+use {
+    http::{
+        header::{
+            ACCEPT_ENCODING, CACHE_CONTROL, CONTENT_ENCODING, CONTENT_LENGTH, CONTENT_TYPE, ETAG,
+            IF_NONE_MATCH, VARY,
+        },
+        Request,
+    },
+    include_dir::{include_dir, Dir},
+    std::time::Duration,
+    tsukuyomi::{
+        config::prelude::*, //
+        fs::{CachePolicy, Embedded, OpenConfig, Precompressed},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+static ASSETS: Dir<'static> = include_dir!("$CARGO_MANIFEST_DIR/tests/fixtures/embedded");
+
+#[test]
+fn embedded_assets() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        mount("/assets").with(Embedded::new(&ASSETS).index_file("index.html")),
+        mount("/compressed").with(Embedded::new(&ASSETS).open_config(OpenConfig {
+            max_age: Some(Duration::from_secs(3600)),
+            precompressed: Some(Precompressed::default()),
+            ..Default::default()
+        })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/assets/css/app.css")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/css");
+    assert_eq!(response.body().to_utf8()?, "body { color: #333; }\n");
+    let etag = response.header(ETAG)?.clone();
+    assert!(
+        etag.to_str()?.starts_with('"'),
+        "the entity tag should be strong"
+    );
+
+    // the re-request with the entity tag is answered with `304 Not Modified`.
+    let response =
+        server.perform(Request::get("/assets/css/app.css").header(IF_NONE_MATCH, etag))?;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.body().to_utf8()?, "");
+
+    let head = server.perform(Request::head("/assets/css/app.css"))?;
+    assert_eq!(head.status(), 200);
+    assert_eq!(head.header(CONTENT_LENGTH)?, "22");
+    assert_eq!(head.body().to_utf8()?, "");
+
+    // the directories are served with the index file.
+    for &(path, body) in &[
+        ("/assets", "<h1>Embedded</h1>\n"),
+        ("/assets/", "<h1>Embedded</h1>\n"),
+        ("/assets/docs", "<h1>Docs</h1>\n"),
+        ("/assets//docs/./index.html", "<h1>Docs</h1>\n"),
+    ] {
+        let response = server.perform(path)?;
+        assert_eq!(response.status(), 200, "{}", path);
+        assert_eq!(response.body().to_utf8()?, body, "{}", path);
+    }
+    for path in &[
+        "/assets/missing.css",
+        "/assets/css",
+        "/assets/../Cargo.toml",
+        "/assets/%2e%2e/Cargo.toml",
+        "/compressed/docs",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 404, "{}", path);
+    }
+
+    // the gzipped file prepared at build time is served if acceptable.
+    let response =
+        server.perform(Request::get("/compressed/css/app.css").header(ACCEPT_ENCODING, "gzip"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_ENCODING)?, "gzip");
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/css");
+    assert_eq!(response.header(VARY)?, "accept-encoding");
+    assert_eq!(response.header(CACHE_CONTROL)?, "public, max-age=3600");
+    assert_eq!(
+        &*response.body().to_bytes(),
+        &*std::fs::read("tests/fixtures/embedded/css/app.css.gz")?
+    );
+
+    let response = server.perform("/compressed/css/app.css")?;
+    assert_eq!(response.status(), 200);
+    assert!(!response.headers().contains_key(CONTENT_ENCODING));
+    assert_eq!(response.body().to_utf8()?, "body { color: #333; }\n");
+
+    Ok(())
+}
+
+#[test]
+fn embedded_not_found_and_cache_policy() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        mount("/assets").with(
+            Embedded::new(&ASSETS)
+                .cache_policy(CachePolicy::no_cache().prefix("/css/", CachePolicy::immutable()))
+                .not_found_file("docs/index.html"),
+        ),
+        mount("/custom").with(Embedded::new(&ASSETS).not_found(|path| {
+            tsukuyomi::error::custom(
+                tsukuyomi::vendor::http::StatusCode::GONE,
+                format!("gone: {}", path),
+            )
+        })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/assets/css/app.css")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.header(CACHE_CONTROL)?,
+        "public, max-age=31536000, immutable"
+    );
+
+    let response = server.perform("/assets/missing.css")?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html");
+    assert_eq!(response.body().to_utf8()?, "<h1>Docs</h1>\n");
+
+    let response = server.perform("/assets/index.html")?;
+    assert_eq!(response.header(CACHE_CONTROL)?, "no-cache");
+
+    let response = server.perform("/custom/missing.css")?;
+    assert_eq!(response.status(), 410);
+    assert_eq!(response.body().to_utf8()?, "gone: /custom/missing.css");
+
+    // the not-found file must be embedded.
+    assert!(
+        App::create(mount("/").with(Embedded::new(&ASSETS).not_found_file("404.html"))).is_err()
+    );
+
+    Ok(())
+}
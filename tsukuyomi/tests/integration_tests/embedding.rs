@@ -0,0 +1,94 @@
+use {
+    futures01::Future,
+    http::{Response, StatusCode},
+    std::{
+        io::{Read, Write},
+        net::{SocketAddr, TcpStream},
+    },
+    tsukuyomi::{app::HyperService, config::prelude::*, extractor, input::body::RequestBody, App},
+};
+
+fn app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/") //
+            .to(endpoint::get().reply("Hello")),
+        path!("/upgrade") //
+            .to(endpoint::get()
+                .extract(extractor::body::stream())
+                .call(|body: RequestBody| {
+                    let task = body
+                        .on_upgrade()
+                        .map_err(|e| panic!("upgrade error: {}", e))
+                        .and_then(|io| tokio::io::write_all(io, b"upgraded"))
+                        .map(|_| ())
+                        .map_err(|e| panic!("I/O error: {}", e));
+                    tokio::spawn(task);
+                    Response::builder()
+                        .status(StatusCode::SWITCHING_PROTOCOLS)
+                        .header("upgrade", "echo")
+                        .header("connection", "upgrade")
+                        .body(())
+                        .unwrap()
+                })),
+    ])
+}
+
+fn send(addr: SocketAddr, input: &[u8]) -> String {
+    let mut stream = TcpStream::connect(addr).unwrap();
+    stream.write_all(input).unwrap();
+    let mut response = String::new();
+    stream.read_to_string(&mut response).unwrap();
+    response
+}
+
+#[test]
+fn hyper_server() -> tsukuyomi::app::Result<()> {
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(app()?.into_make_service());
+    let addr = server.local_addr();
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(server.map_err(|e| panic!("server error: {}", e)));
+
+    let response = send(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.starts_with("HTTP/1.1 200 OK"), "{}", response);
+    assert!(response.ends_with("Hello"), "{}", response);
+
+    // the upgrade is supported by `hyper::Server`.
+    let response = send(
+        addr,
+        b"GET /upgrade HTTP/1.1\r\nHost: localhost\r\nConnection: upgrade\r\nUpgrade: echo\r\n\r\n",
+    );
+    assert!(
+        response.starts_with("HTTP/1.1 101 Switching Protocols"),
+        "{}",
+        response
+    );
+    assert!(response.ends_with("upgraded"), "{}", response);
+
+    runtime.shutdown_now().wait().unwrap();
+    Ok(())
+}
+
+#[test]
+fn hyper_service_adapter() -> tsukuyomi::app::Result<()> {
+    // the tower `Service` can be wrapped with middlewares before passing to hyper.
+    let service = app()?.into_service();
+    let make_service = hyper::service::make_service_fn(move |_| {
+        Ok::<_, tsukuyomi::util::Never>(HyperService::new(service.clone()))
+    });
+    let server = hyper::Server::bind(&([127, 0, 0, 1], 0).into()).serve(make_service);
+    let addr = server.local_addr();
+    let mut runtime = tokio::runtime::Runtime::new().unwrap();
+    runtime.spawn(server.map_err(|e| panic!("server error: {}", e)));
+
+    let response = send(
+        addr,
+        b"GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+    );
+    assert!(response.ends_with("Hello"), "{}", response);
+
+    runtime.shutdown_now().wait().unwrap();
+    Ok(())
+}
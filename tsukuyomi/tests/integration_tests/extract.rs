@@ -6,6 +6,7 @@ use {
         extractor::ExtractorExt,
         App,
     },
+    tsukuyomi_server::test::ResponseExt,
 };
 
 #[test]
@@ -39,6 +40,54 @@ fn params() -> tsukuyomi_server::Result<()> {
     Ok(())
 }
 
+#[test]
+fn many_arguments() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/many/:a/:b/:c/:d") //
+            .to(endpoint::get()
+                .extract(extractor::value(5u32))
+                .extract(extractor::value(6u32))
+                .extract(extractor::value(7u32))
+                .extract(extractor::value(8u32))
+                .extract(extractor::value(9u32))
+                .extract(extractor::value(10u32))
+                .extract(extractor::value(11u32))
+                .extract(extractor::value(12u32))
+                .call(
+                    |a: u32,
+                     b: u32,
+                     c: u32,
+                     d: u32,
+                     e: u32,
+                     f: u32,
+                     g: u32,
+                     h: u32,
+                     i: u32,
+                     j: u32,
+                     k: u32,
+                     l: u32| {
+                        (a + b + c + d + e + f + g + h + i + j + k + l).to_string()
+                    },
+                )),
+        path!("/tupled/:a/:b") //
+            .to(endpoint::get()
+                .extract(extractor::value("c"))
+                .call(endpoint::tupled(|args: (u32, String, &str)| {
+                    format!("{:?}", args)
+                }))),
+    ])?;
+
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/many/1/2/3/4")?;
+    assert_eq!(response.body().to_utf8()?, "78");
+
+    let response = server.perform("/tupled/1/b")?;
+    assert_eq!(response.body().to_utf8()?, "(1, \"b\", \"c\")");
+
+    Ok(())
+}
+
 #[test]
 fn route_macros() -> tsukuyomi_server::Result<()> {
     let app = App::create(chain![
@@ -113,17 +162,86 @@ fn plain_body() -> tsukuyomi_server::Result<()> {
     )?;
     assert_eq!(response.status(), 400);
 
-    // invalid charset
+    // unknown charset
     let response = server.perform(
         Request::post("/")
-            .header("content-type", "text/plain; charset=euc-jp")
+            .header("content-type", "text/plain; charset=x-unknown")
             .body(BODY),
     )?;
+    assert_eq!(response.status(), 415);
+
+    // invalid UTF-8 sequence
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "text/plain")
+            .body(&b"caf\xe9"[..]),
+    )?;
     assert_eq!(response.status(), 400);
 
     Ok(())
 }
 
+#[test]
+fn plain_body_lossy() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::config::TextDecoding;
+
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::plain())
+                .call(|body: String| body)),
+        scope_config(TextDecoding::Lossy),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "text/plain; charset=utf-8")
+            .body(&b"caf\xe9"[..]),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "caf\u{fffd}");
+
+    Ok(())
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn plain_body_charset() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::plain())
+                .call(|body: String| body)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "text/plain; charset=ISO-8859-1")
+            .body(&b"Cr\xe8me br\xfbl\xe9e \xe0 la fran\xe7aise"[..]),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "Crème brûlée à la française");
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "text/plain; charset=utf-16le")
+            .body(&b"c\x00a\x00f\x00\xe9\x00"[..]),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "café");
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "text/plain; charset=x-unknown")
+            .body("café"),
+    )?;
+    assert_eq!(response.status(), 415);
+
+    Ok(())
+}
+
 #[test]
 fn json_body() -> tsukuyomi_server::Result<()> {
     #[derive(Debug, serde::Deserialize)]
@@ -170,9 +288,10 @@ fn json_body() -> tsukuyomi_server::Result<()> {
     Ok(())
 }
 
+#[cfg(feature = "msgpack")]
 #[test]
-fn urlencoded_body() -> tsukuyomi_server::Result<()> {
-    #[derive(Debug, serde::Deserialize)]
+fn msgpack_body() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Serialize, serde::Deserialize)]
     struct Params {
         id: u32,
         name: String,
@@ -181,224 +300,757 @@ fn urlencoded_body() -> tsukuyomi_server::Result<()> {
     let app = App::create(
         path!("/") //
             .to(endpoint::post()
-                .extract(extractor::body::urlencoded())
+                .extract(extractor::body::msgpack())
                 .call(|params: Params| format!("{},{}", params.id, params.name))),
     )?;
     let mut server = tsukuyomi_server::test::server(app)?;
 
-    const BODY: &[u8] = b"id=23&name=bob";
+    let body = rmp_serde::to_vec_named(&Params {
+        id: 23,
+        name: "bob".into(),
+    })?;
 
     let response = server.perform(
         Request::post("/")
-            .header("content-type", "application/x-www-form-urlencoded")
-            .body(BODY),
+            .header("content-type", "application/msgpack")
+            .body(body.clone()),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "23,bob");
+
+    // the legacy media type
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/x-msgpack")
+            .body(body.clone()),
     )?;
     assert_eq!(response.body().to_utf8()?, "23,bob");
 
     // missing content-type
-    let response = server.perform(Request::post("/").body(BODY))?;
+    let response = server.perform(Request::post("/").body(body.clone()))?;
     assert_eq!(response.status(), 400);
 
     // invalid content-type
     let response = server.perform(
         Request::post("/")
-            .header("content-type", "application/graphql")
-            .body(BODY),
+            .header("content-type", "application/json")
+            .body(body),
     )?;
     assert_eq!(response.status(), 400);
 
     // invalid data
     let response = server.perform(
         Request::post("/")
-            .header("content-type", "application/x-www-form-urlencoded")
-            .body(&br#"THIS_IS_INVALID_FORM_DATA"#[..]),
+            .header("content-type", "application/msgpack")
+            .body(&b"\xc1INVALID"[..]),
     )?;
     assert_eq!(response.status(), 400);
 
     Ok(())
 }
 
+#[cfg(feature = "xml")]
 #[test]
-fn local_data() -> tsukuyomi_server::Result<()> {
-    use {
-        futures01::Poll,
-        tsukuyomi::{
-            future::TryFuture,
-            handler::{AllowedMethods, Handler, ModifyHandler},
-            input::{localmap::local_key, Input},
-        },
-    };
-
-    #[derive(Clone)]
-    struct MyData(String);
-
-    impl MyData {
-        local_key! {
-            const KEY: Self;
-        }
-    }
-
-    #[derive(Clone, Default)]
-    struct InsertMyData(());
-
-    impl<H: Handler> ModifyHandler<H> for InsertMyData {
-        type Output = H::Output;
-        type Handler = InsertMyDataHandler<H>;
-
-        fn modify(&self, inner: H) -> Self::Handler {
-            InsertMyDataHandler(inner)
-        }
-    }
-
-    struct InsertMyDataHandler<H>(H);
-
-    impl<H: Handler> Handler for InsertMyDataHandler<H> {
-        type Output = H::Output;
-        type Error = H::Error;
-        type Handle = InsertMyDataHandle<H::Handle>;
-
-        fn allowed_methods(&self) -> Option<&AllowedMethods> {
-            self.0.allowed_methods()
-        }
-
-        fn handle(&self) -> Self::Handle {
-            InsertMyDataHandle {
-                handle: self.0.handle(),
-            }
-        }
-    }
-
-    #[allow(missing_debug_implementations)]
-    struct InsertMyDataHandle<H> {
-        handle: H,
-    }
-
-    impl<H: TryFuture> TryFuture for InsertMyDataHandle<H> {
-        type Ok = H::Ok;
-        type Error = H::Error;
-
-        fn poll_ready(&mut self, input: &mut Input) -> Poll<Self::Ok, Self::Error> {
-            input
-                .locals
-                .entry(&MyData::KEY)
-                .or_insert_with(|| MyData("dummy".into()));
-            self.handle.poll_ready(input)
-        }
+fn xml_body() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        id: u32,
+        name: String,
     }
 
     let app = App::create(
         path!("/") //
-            .to({
-                endpoint::any()
-                    .extract(extractor::local::remove(&MyData::KEY))
-                    .call(|x: MyData| x.0)
-            })
-            .modify(InsertMyData::default()),
+            .to(endpoint::post()
+                .extract(extractor::body::xml())
+                .call(|params: Params| format!("{},{}", params.id, params.name))),
     )?;
     let mut server = tsukuyomi_server::test::server(app)?;
 
-    let response = server.perform("/")?;
-    assert_eq!(response.status(), 200);
-
-    Ok(())
-}
+    let body = "<Params><id>23</id><name>bob</name></Params>";
 
-#[test]
-fn missing_local_data() -> tsukuyomi_server::Result<()> {
-    use tsukuyomi::input::localmap::local_key;
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/xml")
+            .body(body),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "23,bob");
 
-    #[derive(Clone)]
-    struct MyData(String);
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "text/xml; charset=utf-8")
+            .body(body),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "23,bob");
 
-    impl MyData {
-        local_key! {
-            const KEY: Self;
-        }
-    }
+    // missing content-type
+    let response = server.perform(Request::post("/").body(body))?;
+    assert_eq!(response.status(), 400);
 
-    let app = App::create({
-        path!("/") //
-            .to(endpoint::any()
-                .extract(extractor::local::remove(&MyData::KEY))
-                .call(|x: MyData| x.0))
-    })?;
-    let mut server = tsukuyomi_server::test::server(app)?;
+    // invalid content-type
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body(body),
+    )?;
+    assert_eq!(response.status(), 400);
 
-    let response = server.perform("/")?;
-    assert_eq!(response.status(), 500);
+    // malformed document
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/xml")
+            .body("<Params>\n  <id>23</i>\n</Params>"),
+    )?;
+    assert_eq!(response.status(), 400);
+    let message = response.body().to_utf8()?.into_owned();
+    assert!(
+        message.contains("(line 2, column 12)"),
+        "unexpected message: {}",
+        message
+    );
+
+    // invalid value
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/xml")
+            .body("<Params><id>x</id><name>bob</name></Params>"),
+    )?;
+    assert_eq!(response.status(), 400);
 
     Ok(())
 }
 
 #[test]
-fn optional() -> tsukuyomi_server::Result<()> {
+fn request_parts_with_webhook_signature() -> tsukuyomi_server::Result<()> {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    const SECRET: &[u8] = b"whsec_test";
+
     #[derive(Debug, serde::Deserialize)]
-    struct Params {
-        id: u32,
-        name: String,
+    struct Event {
+        id: String,
+        #[serde(rename = "type")]
+        kind: String,
     }
 
-    let extractor = extractor::body::json().optional();
+    fn sign(timestamp: &str, payload: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_varkey(SECRET).unwrap();
+        mac.input(timestamp.as_bytes());
+        mac.input(b".");
+        mac.input(payload);
+        mac.result()
+            .code()
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect()
+    }
+
+    fn verify(parts: &http::request::Parts, payload: &[u8]) -> tsukuyomi::Result<()> {
+        let header = parts
+            .headers
+            .get("stripe-signature")
+            .and_then(|h| h.to_str().ok())
+            .ok_or_else(|| tsukuyomi::error::bad_request("missing signature"))?;
+        let mut timestamp = None;
+        let mut signature = None;
+        for pair in header.split(',') {
+            match pair.split_at(pair.find('=').unwrap_or(0)) {
+                ("t", t) => timestamp = Some(&t[1..]),
+                ("v1", v) => signature = Some(&v[1..]),
+                _ => {}
+            }
+        }
+        match (timestamp, signature) {
+            (Some(t), Some(v)) if sign(t, payload) == v => Ok(()),
+            _ => Err(tsukuyomi::error::bad_request("invalid signature")),
+        }
+    }
 
     let app = App::create(
-        path!("/") //
-            .to({
-                endpoint::post() //
-                    .extract(extractor)
-                    .call(|params: Option<Params>| {
-                        if let Some(params) = params {
-                            Ok(format!("{},{}", params.id, params.name))
-                        } else {
-                            Err(tsukuyomi::error::internal_server_error("####none####"))
-                        }
-                    })
-            }),
+        path!("/webhook") //
+            .to(endpoint::post()
+                .extract(extractor::request_parts())
+                .extract(extractor::body::json())
+                .call(
+                    |parts: http::request::Parts, payload: bytes::Bytes, event: Event| {
+                        verify(&parts, &payload)
+                            .map(|()| format!("{}:{}:{}", parts.uri, event.id, event.kind))
+                    },
+                )),
     )?;
     let mut server = tsukuyomi_server::test::server(app)?;
 
+    let payload = &br#"{"id":"evt_1","type":"charge.succeeded"}"#[..];
     let response = server.perform(
-        Request::post("/")
+        Request::post("/webhook")
             .header("content-type", "application/json")
-            .body(&br#"{"id":23, "name":"bob"}"#[..]),
+            .header(
+                "stripe-signature",
+                format!("t=1546300800,v1={}", sign("1546300800", payload)),
+            )
+            .body(payload),
     )?;
     assert_eq!(response.status(), 200);
-    assert_eq!(response.body().to_utf8()?, "23,bob");
+    assert_eq!(
+        response.body().to_utf8()?,
+        "/webhook:evt_1:charge.succeeded"
+    );
 
+    // tampered payload
     let response = server.perform(
-        Request::post("/")
-            .header("content-type", "application/x-www-form-urlencoded")
-            .body(&b"id=23&name=bob"[..]),
+        Request::post("/webhook")
+            .header("content-type", "application/json")
+            .header(
+                "stripe-signature",
+                format!("t=1546300800,v1={}", sign("1546300800", payload)),
+            )
+            .body(&br#"{"id":"evt_2","type":"charge.succeeded"}"#[..]),
     )?;
-    assert_eq!(response.status(), 500);
-    assert_eq!(response.body().to_utf8()?, "####none####");
+    assert_eq!(response.status(), 400);
 
     Ok(())
 }
 
 #[test]
-fn either_or() -> tsukuyomi_server::Result<()> {
+fn urlencoded_body() -> tsukuyomi_server::Result<()> {
     #[derive(Debug, serde::Deserialize)]
     struct Params {
         id: u32,
         name: String,
     }
 
-    let params_extractor = (extractor::method::get().and(extractor::query()))
-        .or(extractor::method::post().and(extractor::body::json()))
-        .or(extractor::method::post().and(extractor::body::urlencoded()));
-
     let app = App::create(
         path!("/") //
-            .to({
-                endpoint::allow_only("GET, POST")?
-                    .extract(params_extractor)
-                    .call(|params: Params| format!("{},{}", params.id, params.name))
-            }),
+            .to(endpoint::post()
+                .extract(extractor::body::urlencoded())
+                .call(|params: Params| format!("{},{}", params.id, params.name))),
     )?;
     let mut server = tsukuyomi_server::test::server(app)?;
 
-    let response = server.perform("/?id=23&name=bob")?;
-    assert_eq!(response.status(), 200);
+    const BODY: &[u8] = b"id=23&name=bob";
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(BODY),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "23,bob");
+
+    #[cfg(feature = "encoding")]
+    {
+        let response = server.perform(
+            Request::post("/")
+                .header(
+                    "content-type",
+                    "application/x-www-form-urlencoded; charset=iso-8859-1",
+                )
+                .body("id=23&name=Ren%E9e"),
+        )?;
+        assert_eq!(response.body().to_utf8()?, "23,Renée");
+    }
+
+    // unknown charset
+    let response = server.perform(
+        Request::post("/")
+            .header(
+                "content-type",
+                "application/x-www-form-urlencoded; charset=x-unknown",
+            )
+            .body(BODY),
+    )?;
+    assert_eq!(response.status(), 415);
+
+    // missing content-type
+    let response = server.perform(Request::post("/").body(BODY))?;
+    assert_eq!(response.status(), 400);
+
+    // invalid content-type
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/graphql")
+            .body(BODY),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    // invalid data
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(&br#"THIS_IS_INVALID_FORM_DATA"#[..]),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn urlencoded_nested_body() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Deserialize)]
+    struct Order {
+        customer: Customer,
+        items: Vec<Item>,
+        tags: Vec<String>,
+        note: Option<String>,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Customer {
+        name: String,
+        address: Address,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Address {
+        city: String,
+    }
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Item {
+        sku: String,
+        qty: u32,
+    }
+
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::urlencoded_nested())
+                .call(|order: Order| {
+                    let items: Vec<_> = order
+                        .items
+                        .iter()
+                        .map(|item| format!("{}x{}", item.sku, item.qty))
+                        .collect();
+                    format!(
+                        "{}@{}:{}:{}:{}",
+                        order.customer.name,
+                        order.customer.address.city,
+                        items.join(","),
+                        order.tags.join(","),
+                        order.note.unwrap_or_default(),
+                    )
+                })),
+        path!("/shallow") //
+            .to(endpoint::post()
+                .extract(extractor::body::urlencoded_nested_with_depth(1))
+                .call(|order: Order| order.customer.name)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let post = |uri: &str, body: &'static str| {
+        Request::post(uri.to_owned())
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(body)
+    };
+
+    // the indices are not required to be ordered.
+    let response = server.perform(post(
+        "/",
+        "customer[name]=alice&customer[address][city]=Tokyo\
+         &items[1][sku]=B2&items[1][qty]=3&items[0][sku]=A1&items[0][qty]=1\
+         &tags[]=gift&tags[]=express",
+    ))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "alice@Tokyo:A1x1,B2x3:gift,express:"
+    );
+
+    // the repeated keys and the percent-encoded brackets.
+    let response = server.perform(post(
+        "/",
+        "customer%5Bname%5D=bob&customer%5Baddress%5D%5Bcity%5D=Osaka\
+         &items[0][sku]=C3&items[0][qty]=2&tags=a&tags=b&note=fragile",
+    ))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "bob@Osaka:C3x2:a,b:fragile");
+
+    // invalid value: the error points to the key.
+    let response = server.perform(post(
+        "/",
+        "customer[name]=alice&customer[address][city]=Tokyo&items[0][sku]=A1&items[0][qty]=many&tags[]=x",
+    ))?;
+    assert_eq!(response.status(), 400);
+    assert!(response.body().to_utf8()?.contains("`items[0][qty]`"));
+
+    // missing field in a nested struct.
+    let response = server.perform(post(
+        "/",
+        "customer[name]=alice&items[0][sku]=A1&items[0][qty]=1&tags[]=x",
+    ))?;
+    assert_eq!(response.status(), 400);
+    assert!(response.body().to_utf8()?.contains("`customer`"));
+
+    // conflicting keys.
+    let response = server.perform(post("/", "customer=alice&customer[name]=bob"))?;
+    assert_eq!(response.status(), 400);
+    assert!(response.body().to_utf8()?.contains("`customer[name]`"));
+
+    // too deep.
+    let response = server.perform(post(
+        "/shallow",
+        "customer[name]=alice&customer[address][city]=Tokyo",
+    ))?;
+    assert_eq!(response.status(), 400);
+    assert!(response
+        .body()
+        .to_utf8()?
+        .contains("the key `customer[address][city]` exceeds the maximum nesting depth (1)"));
+
+    let response = server.perform(post("/", "a[b][c][d][e][f][g]=1&customer[name]=alice"))?;
+    assert_eq!(response.status(), 400);
+    assert!(response
+        .body()
+        .to_utf8()?
+        .contains("exceeds the maximum nesting depth"));
+
+    // invalid content-type
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body("{}"),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn form_body() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::extractor::body::Files;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        id: u32,
+        name: String,
+    }
+
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::form())
+                .call(|params: Params| format!("{},{}", params.id, params.name))),
+        path!("/files") //
+            .to(endpoint::post()
+                .extract(extractor::body::form_with_files())
+                .call(|params: Params, files: Files| {
+                    let file = files.get("avatar").expect("missing file");
+                    format!(
+                        "{},{},{},{},{},{}",
+                        params.id,
+                        params.name,
+                        files.len(),
+                        file.filename().unwrap_or("-"),
+                        file.content_type().map_or("-", |mime| mime.as_ref()),
+                        String::from_utf8_lossy(file.data()),
+                    )
+                })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    const MULTIPART_BODY: &str = "preamble\r\n\
+         --BOUNDARY\r\n\
+         Content-Disposition: form-data; name=\"id\"\r\n\
+         \r\n\
+         23\r\n\
+         --BOUNDARY\r\n\
+         Content-Disposition: form-data; name=\"avatar\"; filename=\"a;b.txt\"\r\n\
+         Content-Type: text/plain\r\n\
+         \r\n\
+         --hello--\r\n\
+         --BOUNDARY\r\n\
+         content-disposition: form-data; name=\"name\"\r\n\
+         \r\n\
+         bob\r\n\
+         --BOUNDARY--\r\n";
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body("id=23&name=bob"),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "23,bob");
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(MULTIPART_BODY),
+    )?;
+    assert_eq!(response.body().to_utf8()?, "23,bob");
+
+    let response = server.perform(
+        Request::post("/files")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(MULTIPART_BODY),
+    )?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "23,bob,1,a;b.txt,text/plain,--hello--"
+    );
+
+    // missing content-type
+    let response = server.perform(Request::post("/").body("id=23&name=bob"))?;
+    assert_eq!(response.status(), 415);
+
+    // unsupported content-type
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body(r#"{"id":23,"name":"bob"}"#),
+    )?;
+    assert_eq!(response.status(), 415);
+
+    // missing boundary
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "multipart/form-data")
+            .body(MULTIPART_BODY),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    // truncated multipart data
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(&MULTIPART_BODY[..60]),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn local_data() -> tsukuyomi_server::Result<()> {
+    use {
+        futures01::Poll,
+        tsukuyomi::{
+            future::TryFuture,
+            handler::{AllowedMethods, Handler, ModifyHandler},
+            input::{localmap::local_key, Input},
+        },
+    };
+
+    #[derive(Clone)]
+    struct MyData(String);
+
+    impl MyData {
+        local_key! {
+            const KEY: Self;
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct InsertMyData(());
+
+    impl<H: Handler> ModifyHandler<H> for InsertMyData {
+        type Output = H::Output;
+        type Handler = InsertMyDataHandler<H>;
+
+        fn modify(&self, inner: H) -> Self::Handler {
+            InsertMyDataHandler(inner)
+        }
+    }
+
+    struct InsertMyDataHandler<H>(H);
+
+    impl<H: Handler> Handler for InsertMyDataHandler<H> {
+        type Output = H::Output;
+        type Error = H::Error;
+        type Handle = InsertMyDataHandle<H::Handle>;
+
+        fn allowed_methods(&self) -> Option<&AllowedMethods> {
+            self.0.allowed_methods()
+        }
+
+        fn handle(&self) -> Self::Handle {
+            InsertMyDataHandle {
+                handle: self.0.handle(),
+            }
+        }
+    }
+
+    #[allow(missing_debug_implementations)]
+    struct InsertMyDataHandle<H> {
+        handle: H,
+    }
+
+    impl<H: TryFuture> TryFuture for InsertMyDataHandle<H> {
+        type Ok = H::Ok;
+        type Error = H::Error;
+
+        fn poll_ready(&mut self, input: &mut Input) -> Poll<Self::Ok, Self::Error> {
+            input
+                .locals
+                .entry(&MyData::KEY)
+                .or_insert_with(|| MyData("dummy".into()));
+            self.handle.poll_ready(input)
+        }
+    }
+
+    let app = App::create(
+        path!("/") //
+            .to({
+                endpoint::any()
+                    .extract(extractor::local::remove(&MyData::KEY))
+                    .call(|x: MyData| x.0)
+            })
+            .modify(InsertMyData::default()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn missing_local_data() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::input::localmap::local_key;
+
+    #[derive(Clone)]
+    struct MyData(String);
+
+    impl MyData {
+        local_key! {
+            const KEY: Self;
+        }
+    }
+
+    let app = App::create({
+        path!("/") //
+            .to(endpoint::any()
+                .extract(extractor::local::remove(&MyData::KEY))
+                .call(|x: MyData| x.0))
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 500);
+
+    Ok(())
+}
+
+#[test]
+fn local_extractor() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::{
+        extractor::ExtractorExt,
+        input::localmap::{local_key, LocalData},
+    };
+
+    #[derive(Clone)]
+    struct CurrentUser(String);
+
+    impl LocalData for CurrentUser {
+        local_key! {
+            const KEY: Self;
+        }
+    }
+
+    let authenticate = extractor::ready(|input| {
+        CurrentUser("alice".into()).insert_into(input.locals);
+        Ok::<_, tsukuyomi::Error>(())
+    });
+
+    let app = App::create(chain![
+        path!("/me") //
+            .to(endpoint::get()
+                .extract(authenticate.and(extractor::local::<CurrentUser>()))
+                .call(|user: CurrentUser| user.0)),
+        path!("/maybe") //
+            .to(endpoint::get()
+                .extract(extractor::local::<CurrentUser>().optional())
+                .call(|user: Option<CurrentUser>| {
+                    user.map_or_else(|| "anonymous".into(), |user| user.0)
+                })),
+        path!("/missing") //
+            .to(endpoint::get()
+                .extract(extractor::local::<CurrentUser>())
+                .call(|user: CurrentUser| user.0)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/me")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "alice");
+
+    let response = server.perform("/maybe")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "anonymous");
+
+    let response = server.perform("/missing")?;
+    assert_eq!(response.status(), 500);
+
+    Ok(())
+}
+
+#[test]
+fn optional() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        id: u32,
+        name: String,
+    }
+
+    let extractor = extractor::body::json().optional();
+
+    let app = App::create(
+        path!("/") //
+            .to({
+                endpoint::post() //
+                    .extract(extractor)
+                    .call(|params: Option<Params>| {
+                        if let Some(params) = params {
+                            Ok(format!("{},{}", params.id, params.name))
+                        } else {
+                            Err(tsukuyomi::error::internal_server_error("####none####"))
+                        }
+                    })
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body(&br#"{"id":23, "name":"bob"}"#[..]),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "23,bob");
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/x-www-form-urlencoded")
+            .body(&b"id=23&name=bob"[..]),
+    )?;
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.body().to_utf8()?, "####none####");
+
+    Ok(())
+}
+
+#[test]
+fn either_or() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        id: u32,
+        name: String,
+    }
+
+    let params_extractor = (extractor::method::get().and(extractor::query()))
+        .or(extractor::method::post().and(extractor::body::json()))
+        .or(extractor::method::post().and(extractor::body::urlencoded()));
+
+    let app = App::create(
+        path!("/") //
+            .to({
+                endpoint::allow_only("GET, POST")?
+                    .extract(params_extractor)
+                    .call(|params: Params| format!("{},{}", params.id, params.name))
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/?id=23&name=bob")?;
+    assert_eq!(response.status(), 200);
     assert_eq!(response.body().to_utf8()?, "23,bob");
 
     let response = server.perform(
@@ -426,3 +1078,669 @@ fn either_or() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn validated() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::extractor::validate::{Validate, ValidationErrors, ValidationRenderer};
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        password: String,
+        confirm_password: String,
+    }
+
+    impl Validate for Params {
+        fn validate(&self) -> Result<(), ValidationErrors> {
+            let mut errors = ValidationErrors::new();
+            if self.password != self.confirm_password {
+                errors.add("confirm_password", "not matched to password");
+            }
+            errors.into_result()
+        }
+    }
+
+    let app = App::create(chain![
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::json().validate())
+                .call(|params: Params| params.password)),
+        path!("/custom") //
+            .to(endpoint::post()
+                .extract(extractor::body::json().validate().render_with(|errors, _| {
+                    http::Response::builder()
+                        .status(400)
+                        .body(errors.to_string())
+                        .unwrap()
+                }))
+                .call(|params: Params| params.password)),
+        mount("/scoped").with(chain![
+            state(ValidationRenderer::new(|errors, _| {
+                http::Response::builder()
+                    .status(400)
+                    .body(format!("scoped: {}", errors))
+                    .unwrap()
+            })),
+            path!("/") //
+                .to(endpoint::post()
+                    .extract(extractor::body::json().validate())
+                    .call(|params: Params| params.password)),
+            path!("/custom") //
+                .to(endpoint::post()
+                    .extract(extractor::body::json().validate().render_with(|_, _| {
+                        http::Response::builder().status(409).body("").unwrap()
+                    }))
+                    .call(|params: Params| params.password)),
+        ]),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body(&br#"{"password":"foo", "confirm_password":"foo"}"#[..]),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "foo");
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body(&br#"{"password":"foo", "confirm_password":"bar"}"#[..]),
+    )?;
+    assert_eq!(response.status(), 422);
+    assert_eq!(response.header("content-type")?, "application/json");
+    assert_eq!(
+        response.body().to_utf8()?,
+        r#"{"errors":[{"field":"confirm_password","message":"not matched to password"}]}"#
+    );
+
+    let response = server.perform(
+        Request::post("/custom")
+            .header("content-type", "application/json")
+            .body(&br#"{"password":"foo", "confirm_password":"bar"}"#[..]),
+    )?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "confirm_password: not matched to password"
+    );
+
+    // the renderer registered in the scope.
+    let response = server.perform(
+        Request::post("/scoped")
+            .header("content-type", "application/json")
+            .body(&br#"{"password":"foo", "confirm_password":"bar"}"#[..]),
+    )?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "scoped: confirm_password: not matched to password"
+    );
+
+    // `render_with` takes precedence over the scope.
+    let response = server.perform(
+        Request::post("/scoped/custom")
+            .header("content-type", "application/json")
+            .body(&br#"{"password":"foo", "confirm_password":"bar"}"#[..]),
+    )?;
+    assert_eq!(response.status(), 409);
+
+    Ok(())
+}
+
+#[test]
+fn method_switch() -> tsukuyomi_server::Result<()> {
+    #[derive(Debug, serde::Deserialize)]
+    struct Params {
+        id: u32,
+    }
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::any()
+                .extract(
+                    extractor::method_switch()
+                        .on(http::Method::GET, extractor::query())
+                        .on(http::Method::POST, extractor::body::json()),
+                )
+                .call(|params: Params| format!("id = {}", params.id))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/?id=42")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "id = 42");
+
+    let response = server.perform(
+        Request::post("/")
+            .header("content-type", "application/json")
+            .body(&br#"{"id":7}"#[..]),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "id = 7");
+
+    let response = server.perform(Request::put("/").body(&br#"{"id":7}"#[..]))?;
+    assert_eq!(response.status(), 405);
+
+    Ok(())
+}
+
+#[test]
+fn cached() -> tsukuyomi_server::Result<()> {
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    let count = Arc::new(AtomicUsize::new(0));
+    let extractor = Arc::new({
+        let count = count.clone();
+        extractor::ready(move |_| {
+            Ok::<_, tsukuyomi::util::Never>((count.fetch_add(1, Ordering::SeqCst),))
+        })
+        .cached()
+    });
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor.clone())
+                .extract(extractor)
+                .call(|a: usize, b: usize| format!("{}, {}", a, b))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "0, 0");
+    assert_eq!(count.load(Ordering::SeqCst), 1);
+
+    // the cache is cleared at the end of request.
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "1, 1");
+    assert_eq!(count.load(Ordering::SeqCst), 2);
+
+    Ok(())
+}
+
+#[test]
+fn timeout() -> tsukuyomi_server::Result<()> {
+    use {
+        std::time::Duration,
+        tsukuyomi::future::{poll_fn, Async},
+    };
+
+    // an extractor that never completes.
+    let pending = || {
+        extractor::extract(|| {
+            poll_fn(|_| Ok::<Async<(String,)>, tsukuyomi::util::Never>(Async::NotReady))
+        })
+    };
+
+    let app = App::create(chain![
+        path!("/pending") //
+            .to(endpoint::get()
+                .extract(pending().timeout(Duration::from_millis(10)))
+                .call(|s: String| s)),
+        path!("/custom") //
+            .to(endpoint::get()
+                .extract(
+                    pending()
+                        .timeout(Duration::from_millis(10))
+                        .on_timeout(|_| tsukuyomi::error::custom(
+                            http::StatusCode::SERVICE_UNAVAILABLE,
+                            "busy"
+                        )),
+                )
+                .call(|s: String| s)),
+        path!("/ready") //
+            .to(endpoint::get()
+                .extract(
+                    extractor::ready(|_| Ok::<_, tsukuyomi::util::Never>(("ready".to_owned(),)))
+                        .timeout(Duration::from_millis(10)),
+                )
+                .call(|s: String| s)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/pending")?;
+    assert_eq!(response.status(), 408);
+
+    let response = server.perform("/custom")?;
+    assert_eq!(response.status(), 503);
+
+    let response = server.perform("/ready")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "ready");
+
+    Ok(())
+}
+
+#[test]
+fn scoped_timeout() -> tsukuyomi_server::Result<()> {
+    use {
+        std::time::Duration,
+        tsukuyomi::{
+            config::ExtractTimeout,
+            future::{poll_fn, Async},
+        },
+    };
+
+    let pending = || {
+        endpoint::get()
+            .extract(
+                extractor::extract(|| {
+                    poll_fn(|_| Ok::<Async<(String,)>, tsukuyomi::util::Never>(Async::NotReady))
+                })
+                .scoped_timeout(),
+            )
+            .call(|s: String| s)
+    };
+    let ready = || {
+        endpoint::get()
+            .extract(
+                extractor::ready(|_| Ok::<_, tsukuyomi::util::Never>(("ready".to_owned(),)))
+                    .scoped_timeout(),
+            )
+            .call(|s: String| s)
+    };
+
+    let app = App::create(chain![
+        mount("/limited").with(chain![
+            path!("/pending").to(pending()),
+            path!("/ready").to(ready()),
+            scope_config(ExtractTimeout(Duration::from_millis(10))),
+        ]),
+        path!("/unlimited").to(ready()),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/limited/pending")?;
+    assert_eq!(response.status(), 408);
+
+    let response = server.perform("/limited/ready")?;
+    assert_eq!(response.body().to_utf8()?, "ready");
+
+    let response = server.perform("/unlimited")?;
+    assert_eq!(response.body().to_utf8()?, "ready");
+
+    Ok(())
+}
+
+#[test]
+fn accept() -> tsukuyomi_server::Result<()> {
+    use {either::Either, tsukuyomi::extractor::accept::Accept};
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().extract(extractor::accept()).call(
+                |accept: Accept| -> tsukuyomi::Result<_> {
+                    let supported = [mime::APPLICATION_JSON, mime::TEXT_HTML];
+                    let mime = accept.negotiate(&supported)?;
+                    if *mime == mime::TEXT_HTML {
+                        Ok(Either::Left(tsukuyomi::output::html("<p>hello</p>")))
+                    } else {
+                        Ok(Either::Right(tsukuyomi::output::json("hello")))
+                    }
+                },
+            )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::get("/").header("accept", "text/html, */*;q=0.1"))?;
+    assert_eq!(response.header("content-type")?, "text/html; charset=utf-8");
+
+    let response = server
+        .perform(Request::get("/").header("accept", "text/*;q=0.3, application/json;q=0.5"))?;
+    assert_eq!(response.header("content-type")?, "application/json");
+
+    // the first supported type is chosen if `Accept` is missing or the qualities are tied.
+    let response = server.perform("/")?;
+    assert_eq!(response.header("content-type")?, "application/json");
+    let response = server.perform(Request::get("/").header("accept", "*/*"))?;
+    assert_eq!(response.header("content-type")?, "application/json");
+
+    let response =
+        server.perform(Request::get("/").header("accept", "image/png, application/json;q=0"))?;
+    assert_eq!(response.status(), 406);
+
+    Ok(())
+}
+
+#[test]
+fn accept_parse() {
+    use tsukuyomi::extractor::accept::Accept;
+
+    let accept = Accept::parse(
+        "text/*;q=0.3, text/html;q=0.7, text/html;level=1, \
+         text/html;level=2;q=0.4, */*;q=0.5",
+    );
+    let ranges: Vec<_> = accept
+        .iter()
+        .map(|range| (range.mime().to_string(), range.quality()))
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![
+            ("text/html; level=1".to_owned(), 1.0),
+            ("text/html".to_owned(), 0.7),
+            ("*/*".to_owned(), 0.5),
+            ("text/html; level=2".to_owned(), 0.4),
+            ("text/*".to_owned(), 0.3),
+        ]
+    );
+
+    // the quality is taken from the most specific range (RFC 7231, section 5.3.2).
+    let quality = |s: &str| accept.quality(&s.parse().unwrap());
+    assert_eq!(quality("text/html;level=1"), 1.0);
+    assert_eq!(quality("text/html"), 0.7);
+    assert_eq!(quality("text/plain"), 0.3);
+    assert_eq!(quality("image/jpeg"), 0.5);
+    assert_eq!(quality("text/html;level=2"), 0.4);
+    assert_eq!(quality("text/html;level=3"), 0.7);
+
+    // the parameters in the range must match.
+    let accept = Accept::parse("application/vnd.api+json; ext=\"bulk\", application/json;q=0.1");
+    let jsonapi: mime::Mime = "application/vnd.api+json".parse().unwrap();
+    let jsonapi_bulk: mime::Mime = "application/vnd.api+json; ext=bulk".parse().unwrap();
+    assert_eq!(
+        accept
+            .negotiate(&[jsonapi.clone(), jsonapi_bulk.clone()])
+            .ok(),
+        Some(&jsonapi_bulk)
+    );
+    assert!(accept.negotiate(&[jsonapi]).is_err());
+    assert_eq!(
+        accept.negotiate(&[mime::APPLICATION_JSON]).ok(),
+        Some(&mime::APPLICATION_JSON)
+    );
+
+    // the parameters after `q` are the extension parameters.
+    let accept = Accept::parse("text/plain; q=0.5; charset=utf-8");
+    assert_eq!(accept.quality(&mime::TEXT_PLAIN), 0.5);
+
+    // malformed elements are ignored.
+    let accept = Accept::parse("text/html;q=2, text/plain;q=0.1234, */html, ,, application/json");
+    let ranges: Vec<_> = accept
+        .iter()
+        .map(|range| range.mime().to_string())
+        .collect();
+    assert_eq!(ranges, vec!["application/json"]);
+}
+
+#[test]
+fn accept_malformed_never_panics() {
+    use tsukuyomi::extractor::accept::Accept;
+
+    const ALPHABET: &[&str] = &[
+        "text", "/", "*", ";", ",", "=", "q", "0", "1", ".", "\"", " ", "\\", "html", "+json",
+        "level", "\u{3042}", "\t", "9", "-",
+    ];
+    let supported = [
+        mime::TEXT_HTML,
+        mime::TEXT_PLAIN_UTF_8,
+        mime::APPLICATION_JSON,
+        mime::STAR_STAR,
+    ];
+
+    // a simple linear congruential generator, to keep the inputs reproducible.
+    let mut seed: u32 = 0x2545_f491;
+    let mut next = move || {
+        seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+        (seed >> 16) as usize
+    };
+
+    for _ in 0..5000 {
+        let len = next() % 24;
+        let input: String = (0..len)
+            .map(|_| ALPHABET[next() % ALPHABET.len()])
+            .collect();
+        let accept = Accept::parse(&input);
+        for range in &accept {
+            assert!(
+                range.quality() >= 0.0 && range.quality() <= 1.0,
+                "{:?}",
+                input
+            );
+        }
+        let _ = accept.negotiate(&supported);
+    }
+}
+
+#[test]
+fn percent_encoded_params() -> tsukuyomi_server::Result<()> {
+    use {
+        std::path::PathBuf,
+        tsukuyomi::input::param::{FromPercentEncoded, PercentEncoded},
+    };
+
+    #[derive(Debug)]
+    struct Lowercase(String);
+
+    impl FromPercentEncoded for Lowercase {
+        type Error = tsukuyomi::Error;
+
+        fn from_percent_encoded(s: &PercentEncoded) -> Result<Self, Self::Error> {
+            let s = s.decode_utf8().map_err(tsukuyomi::error::not_found)?;
+            if s.chars().all(|c| c.is_ascii_lowercase()) {
+                Ok(Lowercase(s.into_owned()))
+            } else {
+                Err(tsukuyomi::error::not_found("not lowercase"))
+            }
+        }
+    }
+
+    let app = App::create(chain![
+        path!("/num/:id") //
+            .to(endpoint::call(|id: u32| format!("num({})", id))),
+        path!("/name/:name") //
+            .to(endpoint::call(|name: String| format!("name({})", name))),
+        path!("/raw/:name") //
+            .to(endpoint::call(|name: bytes::Bytes| format!(
+                "raw({})",
+                std::str::from_utf8(&name).unwrap()
+            ))),
+        path!("/lower/:name") //
+            .to(endpoint::call(|name: Lowercase| format!(
+                "lower({})",
+                name.0
+            ))),
+        path!("/files/*path") //
+            .to(endpoint::call(|path: PathBuf| format!(
+                "files({})",
+                path.display()
+            ))),
+        path!("/pos/:a/:b") //
+            .to(endpoint::get()
+                .extract(extractor::param::pos::<String>(1))
+                .extract(extractor::param::named::<u32>("a"))
+                .call(|_: u32, _: String, b: String, a: u32| format!("pos({}, {})", a, b))),
+        path!("/wildcard/*path") //
+            .to(endpoint::get()
+                .extract(extractor::param::wildcard::<PathBuf>())
+                .call(|_: PathBuf, path: PathBuf| format!("wildcard({})", path.display()))),
+        path!("/repos/*path/info/refs") //
+            .to(endpoint::call(|path: PathBuf| format!(
+                "refs({})",
+                path.display()
+            ))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/num/42")?;
+    assert_eq!(response.body().to_utf8()?, "num(42)");
+    let response = server.perform("/num/forty-two")?;
+    assert_eq!(response.status(), 404);
+
+    // "%2F" in a normal segment does not split the segment, and is decoded.
+    let response = server.perform("/name/a%2Fb")?;
+    assert_eq!(response.body().to_utf8()?, "name(a/b)");
+    let response = server.perform("/raw/a%2Fb")?;
+    assert_eq!(response.body().to_utf8()?, "raw(a%2Fb)");
+
+    let response = server.perform("/lower/alice")?;
+    assert_eq!(response.body().to_utf8()?, "lower(alice)");
+    let response = server.perform("/lower/Alice")?;
+    assert_eq!(response.status(), 404);
+
+    // "%2F" in a wildcard segment is decoded and the traversal is rejected.
+    let response = server.perform("/files/a%2Fb/c.txt")?;
+    assert_eq!(response.body().to_utf8()?, "files(a/b/c.txt)");
+    let response = server.perform("/files/a/..%2F..%2Fsecret")?;
+    assert_eq!(response.status(), 404);
+    let response = server.perform("/files/%2Fetc%2Fpasswd")?;
+    assert_eq!(response.status(), 404);
+    for path in &[
+        "/files/%2e%2e%2fsecret",
+        "/files/a%5c..%5csecret",
+        "/files/C:%5csecret",
+        "/files/%c0%ae%c0%ae/secret",
+        "/files/a%00b",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 404, "{}", path);
+    }
+
+    let response = server.perform("/pos/7/x%20y")?;
+    assert_eq!(response.body().to_utf8()?, "pos(7, x y)");
+    let response = server.perform("/wildcard/a%2Fb")?;
+    assert_eq!(response.body().to_utf8()?, "wildcard(a/b)");
+    let response = server.perform("/wildcard/..%2Fb")?;
+    assert_eq!(response.status(), 404);
+
+    // the catch-all parameter in the middle is decoded in the same way.
+    let response = server.perform("/repos/a%2Fb/c.git/info/refs")?;
+    assert_eq!(response.body().to_utf8()?, "refs(a/b/c.git)");
+    let response = server.perform("/repos/..%2Fsecret/info/refs")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn range_header() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::extractor::range::Range;
+
+    let app = App::create(chain![
+        path!("/data").to(endpoint::get().extract(extractor::range(Some(100))).call(
+            |range: Option<Range>| -> tsukuyomi::Result<_> {
+                Ok(match range {
+                    Some(range) => range
+                        .resolve(100)?
+                        .iter()
+                        .map(|r| format!("{}-{}", r.first(), r.last()))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    None => "full".into(),
+                })
+            }
+        )),
+        path!("/single").to(endpoint::get()
+            .extract(extractor::range::single(None))
+            .call(|range: Option<Range>| format!("{:?}", range.map(|r| r.specs().len())))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/data")?;
+    assert_eq!(response.body().to_utf8()?, "full");
+
+    let response = server.perform(Request::get("/data").header("range", "bytes=0-9,5-19,-10"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "0-19,90-99");
+
+    let response = server.perform(Request::get("/data").header("range", "items=0-9"))?;
+    assert_eq!(response.body().to_utf8()?, "full");
+
+    let response = server.perform(Request::get("/data").header("range", "bytes=100-"))?;
+    assert_eq!(response.status(), 416);
+    assert_eq!(response.header("content-range")?, "bytes */100");
+
+    let response = server.perform(Request::get("/single").header("range", "bytes=0-9"))?;
+    assert_eq!(response.body().to_utf8()?, "Some(1)");
+
+    let response = server.perform(Request::get("/single").header("range", "bytes=0-9,20-29"))?;
+    assert_eq!(response.status(), 416);
+    assert!(!response.headers().contains_key("content-range"));
+
+    Ok(())
+}
+
+#[test]
+fn guard_body_framing() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/empty").to(endpoint::post()
+            .extract(extractor::guard::no_body())
+            .call(|| "ok")),
+        path!("/limited").to(endpoint::post()
+            .extract(extractor::guard::content_length_max(5).and(extractor::body::plain()))
+            .call(|body: String| body)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/empty"))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(Request::post("/empty").header("content-length", "0"))?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(
+        Request::post("/empty")
+            .header("content-length", "3")
+            .body("fox"),
+    )?;
+    assert_eq!(response.status(), 413);
+
+    let response = server.perform(
+        Request::post("/empty")
+            .header("transfer-encoding", "chunked")
+            .body("fox"),
+    )?;
+    assert_eq!(response.status(), 413);
+
+    let response = server.perform(
+        Request::post("/limited")
+            .header("content-length", "3")
+            .body("fox"),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "fox");
+
+    let response = server.perform(Request::post("/limited").body("fox"))?;
+    assert_eq!(response.status(), 411);
+
+    let response = server.perform(
+        Request::post("/limited")
+            .header("content-length", "9")
+            .body("quick fox"),
+    )?;
+    assert_eq!(response.status(), 413);
+
+    let response = server.perform(
+        Request::post("/limited")
+            .header("content-length", "three")
+            .body("fox"),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn cached_per_instance() -> tsukuyomi_server::Result<()> {
+    // both extractors have the same type, but extract the different values.
+    let value =
+        |n: usize| extractor::ready(move |_| Ok::<_, tsukuyomi::util::Never>((n,))).cached();
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(value(1))
+                .extract(value(2))
+                .call(|a: usize, b: usize| format!("{}, {}", a, b))),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "1, 2");
+
+    Ok(())
+}
@@ -1,7 +1,12 @@
-use tsukuyomi::{
-    config::prelude::*, //
-    fs::{NamedFile, Staticfiles},
-    App,
+use {
+    http::Request,
+    std::path::{Component, Path},
+    tsukuyomi::{
+        config::prelude::*, //
+        fs::{self, NamedFile, SafePath, Staticfiles, SymlinkPolicy},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
 };
 
 #[test]
@@ -20,3 +25,1214 @@ fn compiletest() -> tsukuyomi::app::Result<()> {
 fn compiletest_staticfiles() -> tsukuyomi::app::Result<()> {
     App::create(Staticfiles::new("./public")).map(drop)
 }
+
+#[test]
+fn resolve_path_canonicalization() {
+    let root = Path::new("/srv/public");
+    let resolve = |path: &str| fs::resolve_path(root, path).ok();
+
+    assert_eq!(resolve(""), Some(root.into()));
+    for path in &[
+        "app.css",
+        "/app.css",
+        "//app.css///",
+        "./app.css",
+        "%2e/app.css",
+        "%2E/./%2e//app.css",
+    ] {
+        assert_eq!(resolve(path), Some(root.join("app.css")), "{}", path);
+    }
+    assert_eq!(
+        resolve("css/app.css"),
+        Some(root.join("css").join("app.css"))
+    );
+    assert_eq!(resolve("app%20copy.css"), Some(root.join("app copy.css")));
+
+    for path in &[
+        "..",
+        "../etc/passwd",
+        "css/../app.css",
+        "%2e%2e/etc/passwd",
+        "%2e%2e%2fetc%2fpasswd",
+        "css%2fapp.css",
+        "..%5cetc",
+        "C:%5cWindows",
+        "app.css%00.png",
+        "%c0%ae%c0%ae/etc",
+        "%ff",
+    ] {
+        assert_eq!(resolve(path), None, "{}", path);
+    }
+}
+
+#[test]
+fn resolve_path_never_escapes_root() {
+    let root = Path::new("/srv/public");
+
+    for path in &[
+        "",
+        "/",
+        "//",
+        ".",
+        "./.",
+        "%2e",
+        "%2E/%2e",
+        ".%2e",
+        "%2e.",
+        "...",
+        "a/./b.css",
+        "a//b.css",
+        "a/%2e/b.css",
+        "~",
+        "%25",
+        "%252e%252e",
+        "%2e%2e%2e",
+        "..",
+        "a/..",
+        "a/../..",
+        "%2e%2e",
+        "%2E%2E",
+        ".%2e/a",
+        "%2e./a",
+        "a/%2e%2e/%2e%2e",
+        "..%2f",
+        "%2e%2e%2f",
+        "a%2f..%2f..",
+        "%5c",
+        "..%5c..%5c",
+        "a\\..\\..",
+        "%00",
+        "a%00/b.css",
+        ":",
+        "C:",
+        "C:%5c",
+        "a/C:/b.css",
+        "%c0%ae%c0%ae",
+        "%c0%af",
+        "%e0%80%ae",
+        "%",
+        "%2",
+        "%zz",
+        "%ff%fe",
+    ] {
+        match fs::resolve_path(root, path) {
+            Ok(resolved) => {
+                let rest = resolved.strip_prefix(root).unwrap_or_else(|_| {
+                    panic!("{:?} is resolved outside of the root: {:?}", path, resolved)
+                });
+                assert!(
+                    rest.components()
+                        .all(|component| component != Component::ParentDir
+                            && component != Component::CurDir
+                            && component != Component::RootDir),
+                    "{:?} is resolved to a non-canonical path: {:?}",
+                    path,
+                    resolved
+                );
+            }
+            Err(err) => assert_eq!(
+                err.into_response(&Request::new(())).status(),
+                404,
+                "{:?}",
+                path
+            ),
+        }
+    }
+}
+
+#[test]
+fn staticfiles_resolution() -> tsukuyomi_server::Result<()> {
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-{}", std::process::id()));
+    let public = root.join("public");
+    std::fs::create_dir_all(public.join("static"))?;
+    std::fs::write(public.join("index.html"), "index")?;
+    std::fs::write(public.join("static").join("app.css"), "body {}")?;
+    std::fs::write(root.join("secret.txt"), "secret")?;
+
+    let app = App::create(chain![
+        mount("/eager").with(Staticfiles::new(&public).eager(true)),
+        Staticfiles::new(&public),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the top-level entries are resolved in the same way in both modes.
+    for prefix in &["", "/eager"] {
+        for path in &["/index.html", "//index.html", "/./index.html", "/index.html/"] {
+            let response = server.perform(format!("{}{}", prefix, path))?;
+            assert_eq!(response.status(), 200, "{}{}", prefix, path);
+            assert_eq!(response.body().to_utf8()?, "index", "{}{}", prefix, path);
+        }
+    }
+
+    for prefix in &["", "/eager"] {
+        for path in &[
+            "/static/app.css",
+            "/static//app.css",
+            "/static///app.css",
+            "/static/./app.css",
+            "/static/%2e/app.css",
+        ] {
+            let response = server.perform(format!("{}{}", prefix, path))?;
+            assert_eq!(response.status(), 200, "{}{}", prefix, path);
+            assert_eq!(response.body().to_utf8()?, "body {}", "{}{}", prefix, path);
+        }
+
+        for path in &[
+            "/static/../index.html",
+            "/static/%2e%2e/index.html",
+            "/static/%2e%2e%2f%2e%2e%2fsecret.txt",
+            "/static/..%2f..%2fsecret.txt",
+            "/static/%2e%2e/%2e%2e/secret.txt",
+            "/static/app.css%00",
+            "/static/..%5c..%5csecret.txt",
+            "/static/%5c..%5csecret.txt",
+            "/static/C:%5csecret.txt",
+            "/static/%c0%ae%c0%ae/secret.txt",
+            "/static/%c0%af..%c0%afsecret.txt",
+            "/static/%e0%80%ae%e0%80%ae/secret.txt",
+            "/static/%252e%252e/secret.txt",
+        ] {
+            let response = server.perform(format!("{}{}", prefix, path))?;
+            assert_eq!(response.status(), 404, "{}{}", prefix, path);
+        }
+    }
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn named_file_not_modified() -> tsukuyomi_server::Result<()> {
+    use http::header::{CACHE_CONTROL, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-304-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(root.join("index.html"), "index")?;
+
+    let app = App::create(Staticfiles::new(&root))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/index.html")?;
+    assert_eq!(response.status(), 200);
+    let etag = response.headers()[ETAG].clone();
+    let last_modified = response.headers()[LAST_MODIFIED].clone();
+
+    let response =
+        server.perform(Request::get("/index.html").header(IF_NONE_MATCH, etag.clone()))?;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.headers()[ETAG], etag);
+    assert!(response.headers().contains_key(CACHE_CONTROL));
+    assert!(response.body().to_bytes().is_empty());
+
+    let response = server
+        .perform(Request::get("/index.html").header(IF_MODIFIED_SINCE, last_modified.clone()))?;
+    assert_eq!(response.status(), 304);
+
+    let response =
+        server.perform(Request::get("/index.html").header(IF_NONE_MATCH, "\"other\""))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "index");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn named_file_range() -> tsukuyomi_server::Result<()> {
+    use http::header::{
+        ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG, IF_RANGE, LAST_MODIFIED, RANGE,
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-range-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8).collect();
+    std::fs::write(root.join("video.bin"), &content)?;
+
+    let app = App::create(Staticfiles::new(&root).open_config(fs::OpenConfig {
+        chunk_size: Some(4096),
+        ..Default::default()
+    }))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/video.bin")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(ACCEPT_RANGES)?, "bytes");
+    assert_eq!(response.header(CONTENT_LENGTH)?, "100000");
+    assert_eq!(response.body().to_bytes(), &content[..]);
+    let etag = response.headers()[ETAG].clone();
+    let last_modified = response.headers()[LAST_MODIFIED].clone();
+
+    // download the file in two ranges.
+    let first = server.perform(Request::get("/video.bin").header(RANGE, "bytes=0-49999"))?;
+    assert_eq!(first.status(), 206);
+    assert_eq!(first.header(CONTENT_RANGE)?, "bytes 0-49999/100000");
+    assert_eq!(first.header(CONTENT_LENGTH)?, "50000");
+    let second = server.perform(Request::get("/video.bin").header(RANGE, "bytes=50000-"))?;
+    assert_eq!(second.status(), 206);
+    assert_eq!(second.header(CONTENT_RANGE)?, "bytes 50000-99999/100000");
+    let mut downloaded = first.body().to_bytes().to_vec();
+    downloaded.extend_from_slice(&second.body().to_bytes());
+    assert_eq!(downloaded, content);
+
+    let response = server.perform(Request::get("/video.bin").header(RANGE, "bytes=-10"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.body().to_bytes(), &content[99_990..]);
+
+    let response = server.perform(Request::get("/video.bin").header(RANGE, "bytes=200000-"))?;
+    assert_eq!(response.status(), 416);
+    assert_eq!(response.header(CONTENT_RANGE)?, "bytes */100000");
+
+    // `If-Range` is compared with the last modification date exactly.
+    let response = server.perform(
+        Request::get("/video.bin")
+            .header(RANGE, "bytes=10-19")
+            .header(IF_RANGE, last_modified),
+    )?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.body().to_bytes(), &content[10..20]);
+
+    let response = server.perform(
+        Request::get("/video.bin")
+            .header(RANGE, "bytes=10-19")
+            .header(IF_RANGE, "Thu, 01 Jan 1970 00:00:00 GMT"),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_bytes().len(), content.len());
+
+    // the weak entity tag never matches `If-Range`.
+    let response = server.perform(
+        Request::get("/video.bin")
+            .header(RANGE, "bytes=10-19")
+            .header(IF_RANGE, etag),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn named_file_etag_strategies() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+        std::time::Duration,
+        tsukuyomi::fs::{ETagStrategy, OpenConfig},
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-etag-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(root.join("a.txt"), "same content")?;
+    std::fs::write(root.join("b.txt"), "same content")?;
+
+    let serve = |etag| {
+        Staticfiles::new(&root).open_config(OpenConfig {
+            max_age: Some(Duration::from_secs(60)),
+            etag,
+            ..Default::default()
+        })
+    };
+    let app = App::create(chain![
+        mount("/metadata").with(serve(ETagStrategy::Metadata)),
+        mount("/strong").with(serve(ETagStrategy::StrongMetadata)),
+        mount("/hash").with(serve(ETagStrategy::content_hash())),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let weak = server.perform("/metadata/a.txt")?.headers()[ETAG].clone();
+    assert!(weak.to_str()?.starts_with("W/\""));
+    let strong = server.perform("/strong/a.txt")?.headers()[ETAG].clone();
+    assert_eq!(weak.to_str()?[2..], *strong.to_str()?);
+
+    // the hash of the content does not depend on the file.
+    let hash_a = server.perform("/hash/a.txt")?.headers()[ETAG].clone();
+    let hash_b = server.perform("/hash/b.txt")?.headers()[ETAG].clone();
+    assert!(hash_a.to_str()?.starts_with('"'));
+    assert_eq!(hash_a, hash_b);
+
+    // the 304 response keeps the validators and Cache-Control.
+    for &(path, etag) in &[
+        ("/metadata/a.txt", &weak),
+        ("/strong/a.txt", &strong),
+        ("/hash/a.txt", &hash_a),
+    ] {
+        let response = server.perform(Request::get(path).header(IF_NONE_MATCH, etag.clone()))?;
+        assert_eq!(response.status(), 304, "{}", path);
+        assert_eq!(response.headers()[ETAG], *etag, "{}", path);
+        assert_eq!(response.headers()[CACHE_CONTROL], "public, max-age=60");
+        assert!(response.body().to_bytes().is_empty());
+    }
+
+    // the cached hash is recomputed after the file is modified.
+    std::fs::write(root.join("a.txt"), "modified content")?;
+    let response =
+        server.perform(Request::get("/hash/a.txt").header(IF_NONE_MATCH, hash_a.clone()))?;
+    assert_eq!(response.status(), 200);
+    assert_ne!(response.headers()[ETAG], hash_a);
+    assert_eq!(response.body().to_utf8()?, "modified content");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn content_hash_cache_is_bounded() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::ETAG,
+        tsukuyomi::fs::{ContentHashCache, ETagStrategy, OpenConfig},
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-hash-cache-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    for name in &["a.txt", "b.txt", "c.txt"] {
+        std::fs::write(root.join(name), *name)?;
+    }
+
+    let cache = ContentHashCache::with_capacity(2);
+    let app = App::create(Staticfiles::new(&root).open_config(OpenConfig {
+        etag: ETagStrategy::ContentHash(cache.clone()),
+        ..Default::default()
+    }))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let first = server.perform("/a.txt")?.headers()[ETAG].clone();
+    server.perform("/b.txt")?;
+    assert_eq!(cache.len(), 2);
+
+    // the oldest entry is evicted, and the tag is computed again.
+    server.perform("/c.txt")?;
+    assert_eq!(cache.len(), 2);
+    assert_eq!(server.perform("/a.txt")?.headers()[ETAG], first);
+    assert_eq!(cache.len(), 2);
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_directory_index_and_listing() -> tsukuyomi_server::Result<()> {
+    use http::header::CONTENT_TYPE;
+
+    let base = std::env::temp_dir().join(format!("tsukuyomi-fs-index-{}", std::process::id()));
+    let root = base.join("public");
+    std::fs::create_dir_all(root.join("docs"))?;
+    std::fs::create_dir_all(root.join("files").join("sub"))?;
+    std::fs::create_dir_all(base.join("outside"))?;
+    std::fs::write(root.join("index.html"), "home")?;
+    std::fs::write(root.join("docs").join("index.html"), "docs")?;
+    std::fs::write(root.join("files").join("a.txt"), "aaa")?;
+    std::fs::write(root.join("files").join("<b>&.txt"), "b")?;
+    std::fs::write(base.join("outside").join("secret.txt"), "secret")?;
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(base.join("outside"), root.join("files").join("link"))?;
+
+    let app = App::create(chain![
+        mount("/site").with(
+            Staticfiles::new(&root)
+                .index_file("index.html")
+                .listing(true)
+        ),
+        mount("/plain").with(Staticfiles::new(&root)),
+        mount("/spa").with(Staticfiles::new(&root).fallback_file("index.html")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/site")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "home");
+    for path in &["/site/docs", "/site/docs/"] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 200, "{}", path);
+        assert_eq!(response.body().to_utf8()?, "docs", "{}", path);
+    }
+
+    // the directory without the index file is listed, with the names escaped.
+    let response = server.perform("/site/files/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+    let body = response.body().to_utf8()?;
+    assert!(body.contains("<a href=\"/site/files/a.txt\">a.txt</a></td><td>3</td>"));
+    assert!(body.contains(
+        "<a href=\"/site/files/%3Cb%3E&amp;.txt\">&lt;b&gt;&amp;.txt</a></td><td>1</td>"
+    ));
+    assert!(body.contains("<a href=\"/site/files/sub/\">sub/</a></td><td>-</td>"));
+    assert!(!body.contains("<b>"));
+
+    let response = server.perform("/site/files/sub")?;
+    assert_eq!(response.status(), 200);
+    assert!(response
+        .body()
+        .to_utf8()?
+        .contains("Index of /site/files/sub"));
+
+    // the directories outside of the root are never listed.
+    #[cfg(unix)]
+    {
+        let response = server.perform("/site/files/link/")?;
+        assert_eq!(response.status(), 404);
+    }
+
+    // without the options, the directories are not served.
+    for path in &["/plain", "/plain/docs", "/plain/files/"] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 404, "{}", path);
+    }
+
+    // the fallback file is served for the unresolved paths.
+    for path in &["/spa/users/42", "/spa/files/missing.txt", "/spa/unknown"] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 200, "{}", path);
+        assert_eq!(response.body().to_utf8()?, "home", "{}", path);
+    }
+    let response = server.perform("/spa/files/a.txt")?;
+    assert_eq!(response.body().to_utf8()?, "aaa");
+    let response = server.perform("/spa/files/%2e%2e/%2e%2e/outside/secret.txt")?;
+    assert_eq!(response.status(), 404);
+
+    std::fs::remove_dir_all(&base)?;
+    Ok(())
+}
+
+#[test]
+fn named_file_precompressed() -> tsukuyomi_server::Result<()> {
+    use {
+        filetime::FileTime,
+        http::header::{
+            ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, ETAG, IF_NONE_MATCH, VARY,
+        },
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-precomp-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(root.join("app.js"), "console.log('original');")?;
+    std::fs::write(root.join("app.js.br"), "brotli")?;
+    std::fs::write(root.join("app.js.gz"), "gzip")?;
+    std::fs::write(root.join("old.js"), "original")?;
+    std::fs::write(root.join("old.js.gz"), "stale gzip")?;
+    filetime::set_file_mtime(
+        root.join("old.js.gz"),
+        FileTime::from_unix_time(1_000_000, 0),
+    )?;
+
+    let precompressed = |precompressed| fs::OpenConfig {
+        precompressed: Some(precompressed),
+        ..Default::default()
+    };
+    let app = App::create(chain![
+        mount("/default")
+            .with(Staticfiles::new(&root).open_config(precompressed(fs::Precompressed::default()))),
+        mount("/gzip-first").with(Staticfiles::new(&root).open_config(precompressed(
+            fs::Precompressed {
+                encodings: vec![fs::Encoding::Gzip, fs::Encoding::Brotli],
+                check_modified: false,
+            }
+        ))),
+        mount("/disabled").with(Staticfiles::new(&root)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let cases: &[(&str, Option<&str>, Option<&str>, &str)] = &[
+        ("/default/app.js", None, None, "console.log('original');"),
+        ("/default/app.js", Some("gzip, br"), Some("br"), "brotli"),
+        (
+            "/default/app.js",
+            Some("br;q=0, gzip"),
+            Some("gzip"),
+            "gzip",
+        ),
+        ("/default/app.js", Some("*"), Some("br"), "brotli"),
+        ("/default/app.js", Some("*, br;q=0"), Some("gzip"), "gzip"),
+        (
+            "/default/app.js",
+            Some("identity"),
+            None,
+            "console.log('original');",
+        ),
+        ("/default/old.js", Some("gzip"), None, "original"),
+        ("/gzip-first/app.js", Some("gzip, br"), Some("gzip"), "gzip"),
+        (
+            "/gzip-first/old.js",
+            Some("gzip"),
+            Some("gzip"),
+            "stale gzip",
+        ),
+        (
+            "/disabled/app.js",
+            Some("gzip, br"),
+            None,
+            "console.log('original');",
+        ),
+    ];
+    for &(path, accept_encoding, encoding, body) in cases {
+        let mut request = Request::get(path);
+        if let Some(accept_encoding) = accept_encoding {
+            request.header(ACCEPT_ENCODING, accept_encoding);
+        }
+        let response = server.perform(request)?;
+        let case = format!("{} ({:?})", path, accept_encoding);
+        assert_eq!(response.status(), 200, "{}", case);
+        assert_eq!(
+            response
+                .headers()
+                .get(CONTENT_ENCODING)
+                .map(|v| v.to_str().unwrap()),
+            encoding,
+            "{}",
+            case
+        );
+        assert_eq!(
+            response.header(CONTENT_TYPE)?,
+            "text/javascript",
+            "{}",
+            case
+        );
+        assert_eq!(response.body().to_utf8()?, body, "{}", case);
+        if path.starts_with("/disabled") {
+            assert!(!response.headers().contains_key(VARY), "{}", case);
+        } else {
+            assert_eq!(response.header(VARY)?, "accept-encoding", "{}", case);
+        }
+    }
+
+    // the variants have the distinct entity tags.
+    let identity = server.perform("/default/app.js")?;
+    let brotli = server.perform(Request::get("/default/app.js").header(ACCEPT_ENCODING, "br"))?;
+    assert_ne!(identity.headers()[ETAG], brotli.headers()[ETAG]);
+    let response = server.perform(
+        Request::get("/default/app.js")
+            .header(ACCEPT_ENCODING, "br")
+            .header(IF_NONE_MATCH, brotli.headers()[ETAG].clone()),
+    )?;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.header(VARY)?, "accept-encoding");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn safe_path_resolution() -> tsukuyomi_server::Result<()> {
+    let base = std::env::temp_dir().join(format!("tsukuyomi-fs-safe-{}", std::process::id()));
+    let root = base.join("public");
+    std::fs::create_dir_all(root.join("css"))?;
+    std::fs::write(root.join("css").join("app.css"), "body {}")?;
+    std::fs::write(base.join("secret.txt"), "secret")?;
+
+    let safe_path = SafePath::new(&root)?;
+    let expected = root.canonicalize()?.join("css").join("app.css");
+    assert_eq!(
+        safe_path.resolve("css/app.css").ok(),
+        Some(expected.clone())
+    );
+    assert_eq!(
+        safe_path.resolve("css/%2e/app.css").ok(),
+        Some(expected.clone())
+    );
+    assert_eq!(safe_path.join("css/app.css").ok(), Some(expected));
+
+    for path in &[
+        "css/missing.css",
+        "../secret.txt",
+        "%2e%2e/secret.txt",
+        "css%2f..%2f..%2fsecret.txt",
+        "..%5csecret.txt",
+        "%c0%ae%c0%ae/secret.txt",
+    ] {
+        assert!(safe_path.resolve(path).is_err(), "{}", path);
+    }
+    assert!(safe_path.join("../secret.txt").is_err());
+    assert!(safe_path.join(base.join("secret.txt")).is_err());
+
+    std::fs::remove_dir_all(&base)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+#[test]
+fn staticfiles_symlink_policies() -> tsukuyomi_server::Result<()> {
+    use std::os::unix::fs::symlink;
+
+    let base = std::env::temp_dir().join(format!("tsukuyomi-fs-symlink-{}", std::process::id()));
+    let root = base.join("public");
+    std::fs::create_dir_all(root.join("files"))?;
+    std::fs::create_dir_all(base.join("outside"))?;
+    std::fs::write(root.join("files").join("a.txt"), "aaa")?;
+    std::fs::write(base.join("outside").join("secret.txt"), "secret")?;
+    symlink(
+        root.join("files").join("a.txt"),
+        root.join("files").join("internal.txt"),
+    )?;
+    symlink(base.join("outside"), root.join("files").join("external"))?;
+    symlink(
+        base.join("outside").join("secret.txt"),
+        root.join("secret.txt"),
+    )?;
+
+    let app = App::create(chain![
+        mount("/default").with(Staticfiles::new(&root)),
+        mount("/follow").with(Staticfiles::new(&root).symlinks(SymlinkPolicy::Follow)),
+        mount("/deny").with(Staticfiles::new(&root).symlinks(SymlinkPolicy::Deny)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let cases: &[(&str, Option<&str>)] = &[
+        ("/default/files/a.txt", Some("aaa")),
+        ("/default/files/internal.txt", Some("aaa")),
+        ("/default/files/external/secret.txt", None),
+        ("/default/secret.txt", None),
+        ("/follow/files/internal.txt", Some("aaa")),
+        ("/follow/files/external/secret.txt", Some("secret")),
+        ("/follow/secret.txt", Some("secret")),
+        ("/deny/files/a.txt", Some("aaa")),
+        ("/deny/files/internal.txt", None),
+        ("/deny/files/external/secret.txt", None),
+        ("/deny/secret.txt", None),
+    ];
+    for &(path, expected) in cases {
+        let response = server.perform(path)?;
+        match expected {
+            Some(body) => {
+                assert_eq!(response.status(), 200, "{}", path);
+                assert_eq!(response.body().to_utf8()?, body, "{}", path);
+            }
+            None => assert_eq!(response.status(), 404, "{}", path),
+        }
+    }
+
+    std::fs::remove_dir_all(&base)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_cache() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{CONTENT_RANGE, ETAG, IF_NONE_MATCH, LAST_MODIFIED, RANGE},
+        std::time::Duration,
+        tsukuyomi::fs::{CacheConfig, CacheMetrics, FileCache},
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-cache-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("dir"))?;
+    std::fs::write(root.join("a.txt"), "aaaaaaaaaa")?;
+    std::fs::write(root.join("b.txt"), "bbbbbbbbbb")?;
+    std::fs::write(root.join("c.txt"), "cccccccccc")?;
+    std::fs::write(root.join("large.txt"), "0123456789abcdefghij")?;
+    std::fs::write(root.join("dir").join("nested.txt"), "nested")?;
+
+    let cache = FileCache::new(CacheConfig {
+        max_file_size: 16,
+        max_total_size: 25,
+        ttl: None,
+    });
+    let ttl_cache = FileCache::new(CacheConfig {
+        ttl: Some(Duration::from_secs(3600)),
+        ..Default::default()
+    });
+    let app = App::create(chain![
+        mount("/cached").with(Staticfiles::new(&root).cache(cache.clone())),
+        mount("/ttl").with(Staticfiles::new(&root).cache(ttl_cache.clone())),
+        mount("/plain").with(Staticfiles::new(&root)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+    let metrics = |hits, misses, entries, size| CacheMetrics {
+        hits,
+        misses,
+        entries,
+        size,
+    };
+
+    let plain = server.perform("/plain/a.txt")?;
+    let response = server.perform("/cached/a.txt")?;
+    assert_eq!(response.body().to_utf8()?, "aaaaaaaaaa");
+    assert_eq!(cache.metrics(), metrics(0, 1, 1, 10));
+    let response = server.perform("/cached/a.txt")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "aaaaaaaaaa");
+    assert_eq!(response.headers()[ETAG], plain.headers()[ETAG]);
+    assert_eq!(
+        response.headers()[LAST_MODIFIED],
+        plain.headers()[LAST_MODIFIED]
+    );
+    assert_eq!(cache.metrics(), metrics(1, 1, 1, 10));
+
+    // the cached responses support the conditional and range requests.
+    let response = server.perform(
+        Request::get("/cached/a.txt").header(IF_NONE_MATCH, plain.headers()[ETAG].clone()),
+    )?;
+    assert_eq!(response.status(), 304);
+    let response =
+        server.perform(Request::get("/cached/dir/nested.txt").header(RANGE, "bytes=1-3"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.header(CONTENT_RANGE)?, "bytes 1-3/6");
+    assert_eq!(response.body().to_utf8()?, "est");
+    assert_eq!(cache.metrics(), metrics(2, 2, 2, 16));
+
+    // the files larger than the limit are not cached.
+    let response = server.perform("/cached/large.txt")?;
+    assert_eq!(response.body().to_utf8()?, "0123456789abcdefghij");
+    assert_eq!(cache.metrics(), metrics(2, 3, 2, 16));
+
+    // the least recently used file is evicted when the total size exceeds the limit.
+    server.perform("/cached/a.txt")?;
+    server.perform("/cached/b.txt")?;
+    assert_eq!(cache.metrics(), metrics(3, 4, 2, 20));
+    server.perform("/cached/a.txt")?;
+    server.perform("/cached/c.txt")?;
+    assert_eq!(cache.metrics(), metrics(4, 5, 2, 20));
+    server.perform("/cached/a.txt")?;
+    server.perform("/cached/b.txt")?;
+    assert_eq!(cache.metrics(), metrics(5, 6, 2, 20));
+
+    // the modified file is reloaded.
+    std::fs::write(root.join("a.txt"), "modified")?;
+    let response = server.perform("/cached/a.txt")?;
+    assert_eq!(response.body().to_utf8()?, "modified");
+    assert_eq!(cache.metrics(), metrics(5, 7, 2, 18));
+
+    // with TTL, the cached content is served without accessing the file system.
+    let response = server.perform("/ttl/c.txt")?;
+    assert_eq!(response.body().to_utf8()?, "cccccccccc");
+    std::fs::remove_file(root.join("c.txt"))?;
+    let response = server.perform("/ttl/c.txt")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "cccccccccc");
+    assert_eq!(ttl_cache.metrics(), metrics(1, 1, 1, 10));
+    let response = server.perform("/cached/c.txt")?;
+    assert_eq!(response.status(), 404);
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_resolves_paths_at_request_time() -> tsukuyomi_server::Result<()> {
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-lazy-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(root.join("index.html"), "index")?;
+
+    let app = App::create(chain![
+        path!("/").to(endpoint::get().reply("home")),
+        mount("/api").with(path!("/users").to(endpoint::get().reply("users"))),
+        mount("/public").with(Staticfiles::new(&root).index_file("index.html")),
+        mount("/eager").with(Staticfiles::new(&root).eager(true)),
+        Staticfiles::new(&root),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // the other routes are not affected by the handler of `Staticfiles`.
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "home");
+    let response = server.perform("/api/users")?;
+    assert_eq!(response.body().to_utf8()?, "users");
+    let response = server.perform("/public")?;
+    assert_eq!(response.body().to_utf8()?, "index");
+
+    // the files and directories created after startup are served.
+    std::fs::create_dir_all(root.join("assets").join("js"))?;
+    std::fs::write(root.join("new.txt"), "new")?;
+    std::fs::write(root.join("assets").join("js").join("app.js"), "app")?;
+    for &(path, body) in &[
+        ("/new.txt", "new"),
+        ("/assets/js/app.js", "app"),
+        ("/public/new.txt", "new"),
+        ("/public/assets/js/app.js", "app"),
+    ] {
+        let response = server.perform(path)?;
+        assert_eq!(response.status(), 200, "{}", path);
+        assert_eq!(response.body().to_utf8()?, body, "{}", path);
+    }
+
+    // the entries outside of the prefix are not resolved.
+    for path in &["/publicnew.txt", "/api/new.txt", "/missing.txt"] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 404, "{}", path);
+    }
+
+    // in the eager mode, the top-level entries are fixed at startup.
+    let response = server.perform("/eager/index.html")?;
+    assert_eq!(response.status(), 200);
+    let response = server.perform("/eager/new.txt")?;
+    assert_eq!(response.status(), 404);
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_not_found() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{CONTENT_TYPE, SERVER},
+        tsukuyomi::output::headers::SetHeaders,
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-not-found-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(root.join("index.html"), "index")?;
+    std::fs::write(root.join("404.html"), "<h1>missing</h1>")?;
+
+    let app = App::create(chain![
+        mount("/page")
+            .with(Staticfiles::new(&root).not_found_file("404.html"))
+            .modify(SetHeaders::new().insert(SERVER, "tsukuyomi".parse().unwrap())),
+        mount("/custom").with(Staticfiles::new(&root).not_found(|path: &str| {
+            tsukuyomi::error::custom(http::StatusCode::NOT_FOUND, format!("missing: {}", path))
+        })),
+        mount("/plain").with(Staticfiles::new(&root)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/page/index.html")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "index");
+
+    for path in &[
+        "/page/missing.html",
+        "/page/a/b/c",
+        "/page/%2e%2e/index.html",
+    ] {
+        let response = server.perform(*path)?;
+        assert_eq!(response.status(), 404, "{}", path);
+        assert_eq!(response.header(CONTENT_TYPE)?, "text/html", "{}", path);
+        assert_eq!(response.body().to_utf8()?, "<h1>missing</h1>", "{}", path);
+        // the modifiers of the scope still apply to the page.
+        assert_eq!(response.header(SERVER)?, "tsukuyomi", "{}", path);
+    }
+
+    let response = server.perform("/custom/missing.html")?;
+    assert_eq!(response.status(), 404);
+    assert_eq!(response.body().to_utf8()?, "missing: /custom/missing.html");
+
+    let response = server.perform("/plain/missing.html")?;
+    assert_eq!(response.status(), 404);
+
+    // the missing page is reported when the application is built.
+    assert!(App::create(Staticfiles::new(&root).not_found_file("missing.html")).is_err());
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn file_error_hides_io_details() -> tsukuyomi_server::Result<()> {
+    use {fs::FileError, std::io};
+
+    let app = App::create(chain![
+        path!("/forbidden").to(endpoint::get().call(|| {
+            Err::<&str, _>(FileError::from(io::Error::new(
+                io::ErrorKind::PermissionDenied,
+                "/srv/secret.txt",
+            )))
+        })),
+        path!("/broken").to(endpoint::get().call(|| {
+            Err::<&str, _>(FileError::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "/srv/broken.txt",
+            )))
+        })),
+        path!("/missing").to(endpoint::get().reply(NamedFile::open("/path/to/missing.txt"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for &(path, status, body) in &[
+        ("/forbidden", 403, "Forbidden"),
+        ("/broken", 500, "Internal Server Error"),
+        ("/missing", 404, "Not Found"),
+    ] {
+        let response = server.perform(path)?;
+        assert_eq!(response.status(), status, "{}", path);
+        assert_eq!(response.body().to_utf8()?, body, "{}", path);
+    }
+
+    Ok(())
+}
+
+#[test]
+fn staticfiles_methods() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{ALLOW, CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED},
+        tsukuyomi::fs::{CacheConfig, FileCache},
+    };
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-methods-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    std::fs::write(root.join("hello.txt"), "Hello, world!")?;
+
+    let app = App::create(chain![
+        mount("/eager").with(Staticfiles::new(&root).eager(true)),
+        mount("/cached")
+            .with(Staticfiles::new(&root).cache(FileCache::new(CacheConfig::default()))),
+        Staticfiles::new(&root),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for path in &["/hello.txt", "/eager/hello.txt", "/cached/hello.txt"] {
+        let get = server.perform(*path)?;
+        assert_eq!(get.status(), 200, "{}", path);
+        assert_eq!(get.body().to_utf8()?, "Hello, world!", "{}", path);
+
+        // HEAD has the same header fields as GET, without the body.
+        let head = server.perform(Request::head(*path))?;
+        assert_eq!(head.status(), 200, "{}", path);
+        for name in &[CONTENT_LENGTH, CONTENT_TYPE, ETAG, LAST_MODIFIED] {
+            assert_eq!(head.header(name)?, get.header(name)?, "{}: {}", path, name);
+        }
+        assert_eq!(head.body().to_utf8()?, "", "{}", path);
+
+        let options = server.perform(Request::options(*path))?;
+        assert_eq!(options.status(), 204, "{}", path);
+        assert_eq!(options.header(ALLOW)?, "GET, HEAD, OPTIONS", "{}", path);
+
+        let post = server.perform(Request::post(*path))?;
+        assert_eq!(post.status(), 405, "{}", path);
+        assert_eq!(post.header(ALLOW)?, "GET, HEAD, OPTIONS", "{}", path);
+    }
+
+    // the methods are not answered for the paths that do not resolve to any file.
+    for path in &["/missing.txt", "/cached/missing.txt", "/api/users"] {
+        let options = server.perform(Request::options(*path))?;
+        assert_eq!(options.status(), 404, "{}", path);
+        assert!(!options.headers().contains_key(ALLOW), "{}", path);
+
+        let post = server.perform(Request::post(*path))?;
+        assert_eq!(post.status(), 404, "{}", path);
+    }
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_conflicts_with_default_handler() -> tsukuyomi_server::Result<()> {
+    let root = std::env::temp_dir().join(format!(
+        "tsukuyomi-fs-default-handler-{}",
+        std::process::id()
+    ));
+    std::fs::create_dir_all(&root)?;
+
+    // the default handler registered either before or after `Staticfiles` is rejected.
+    assert!(App::create(chain![
+        path!("*").to(endpoint::reply("fallback")),
+        Staticfiles::new(&root),
+    ])
+    .is_err());
+    assert!(App::create(mount("/public").with(chain![
+        Staticfiles::new(&root),
+        path!("*").to(endpoint::reply("fallback")),
+    ]))
+    .is_err());
+    assert!(App::create(chain![
+        Staticfiles::new(&root)
+            .eager(true)
+            .fallback_file("index.html"),
+        path!("*").to(endpoint::reply("fallback")),
+    ])
+    .is_err());
+
+    // the default handlers in the other scopes are not affected.
+    assert!(App::create(chain![
+        mount("/public").with(Staticfiles::new(&root)),
+        path!("*").to(endpoint::reply("fallback")),
+    ])
+    .is_ok());
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_cache_policy() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{HeaderValue, CACHE_CONTROL, ETAG, IF_NONE_MATCH},
+        std::time::Duration,
+        tsukuyomi::{
+            fs::{CacheConfig, CachePolicy, FileCache},
+            output::headers::SetHeaders,
+        },
+    };
+
+    let root =
+        std::env::temp_dir().join(format!("tsukuyomi-fs-cache-policy-{}", std::process::id()));
+    std::fs::create_dir_all(root.join("assets"))?;
+    std::fs::write(root.join("index.html"), "index")?;
+    std::fs::write(root.join("robots.txt"), "robots")?;
+    std::fs::write(root.join("secret.txt"), "secret")?;
+    std::fs::write(root.join("assets").join("app.3f2a.js"), "app")?;
+
+    let policy = || {
+        CachePolicy::public(Duration::from_secs(600))
+            .prefix("/assets/", CachePolicy::immutable())
+            .extension("HTML", CachePolicy::no_cache())
+            .matches(
+                |_: &str, path: &std::path::Path| path.ends_with("secret.txt"),
+                CachePolicy::no_store(),
+            )
+    };
+    let app = App::create(chain![
+        mount("/explicit")
+            .with(Staticfiles::new(&root).cache_policy(policy()))
+            .modify(SetHeaders::new().insert(CACHE_CONTROL, HeaderValue::from_static("private"))),
+        mount("/cached").with(
+            Staticfiles::new(&root)
+                .cache_policy(policy())
+                .cache(FileCache::new(CacheConfig::default())),
+        ),
+        mount("/default").with(Staticfiles::new(&root)),
+        mount("/eager").with(Staticfiles::new(&root).eager(true).cache_policy(policy())),
+        Staticfiles::new(&root).cache_policy(policy()),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for &(path, expected) in &[
+        ("/assets/app.3f2a.js", "public, max-age=31536000, immutable"),
+        ("/index.html", "no-cache"),
+        ("/secret.txt", "no-store"),
+        ("/robots.txt", "public, max-age=600"),
+        (
+            "/cached/assets/app.3f2a.js",
+            "public, max-age=31536000, immutable",
+        ),
+        ("/cached/index.html", "no-cache"),
+        ("/cached/robots.txt", "public, max-age=600"),
+        // the prefix is compared with the path relative to the root directory.
+        (
+            "/eager/assets/app.3f2a.js",
+            "public, max-age=31536000, immutable",
+        ),
+        ("/default/index.html", "public"),
+        // the explicit value of the wrapping modifier is not overridden.
+        ("/explicit/index.html", "private"),
+        ("/explicit/robots.txt", "private"),
+    ] {
+        // the second request to `/cached` is served from the cache.
+        for _ in 0..2 {
+            let response = server.perform(path)?;
+            assert_eq!(response.status(), 200, "{}", path);
+            assert_eq!(response.header(CACHE_CONTROL)?, expected, "{}", path);
+            assert_eq!(
+                response.headers().get_all(CACHE_CONTROL).iter().count(),
+                1,
+                "{}",
+                path
+            );
+        }
+    }
+
+    // `304 Not Modified` carries the same value.
+    let etag = server.perform("/index.html")?.headers()[ETAG].clone();
+    let response = server.perform(Request::get("/index.html").header(IF_NONE_MATCH, etag))?;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.header(CACHE_CONTROL)?, "no-cache");
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn background_io_on_current_thread() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::fs::{IoStrategy, OpenConfig};
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-background-{}", std::process::id()));
+    std::fs::create_dir_all(&root)?;
+    let content: Vec<u8> = (0..100_000u32).map(|i| (i % 251) as u8).collect();
+    std::fs::write(root.join("large.bin"), &content)?;
+    std::fs::write(root.join("hello.txt"), "Hello")?;
+
+    let background = OpenConfig {
+        io: IoStrategy::Background,
+        chunk_size: Some(4096),
+        ..Default::default()
+    };
+    let app = App::create(chain![
+        mount("/background").with(Staticfiles::new(&root).open_config(background.clone())),
+        path!("/named") //
+            .to(endpoint::get().reply(NamedFile::open_async(root.join("hello.txt")))),
+        mount("/blocking").with(Staticfiles::new(&root)),
+    ])?;
+    let mut server = tsukuyomi_server::test::local_server(app)?;
+
+    let response = server.perform("/background/large.bin")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_bytes(), &content[..]);
+    assert!(response.body().chunks().len() > 1);
+
+    let response =
+        server.perform(Request::get("/background/large.bin").header("range", "bytes=5000-"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.body().to_bytes(), &content[5000..]);
+
+    let response = server.perform("/background/missing.txt")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/named")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "Hello");
+
+    // the blocking pool is not available on the current-thread runtime.
+    let response = server.perform("/blocking/hello.txt")?;
+    assert_eq!(response.status(), 500);
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
+
+#[test]
+fn staticfiles_overlay() -> tsukuyomi_server::Result<()> {
+    use http::header::ETAG;
+
+    let root = std::env::temp_dir().join(format!("tsukuyomi-fs-overlay-{}", std::process::id()));
+    let theme = root.join("theme");
+    let base = root.join("base");
+    std::fs::create_dir_all(theme.join("docs"))?;
+    std::fs::create_dir_all(base.join("docs"))?;
+    std::fs::write(theme.join("style.css"), "theme")?;
+    std::fs::write(theme.join("docs/extra.html"), "extra")?;
+    std::fs::write(base.join("style.css"), "base")?;
+    std::fs::write(base.join("app.js"), "app")?;
+    std::fs::write(base.join("docs/index.html"), "docs")?;
+
+    let app = App::create(chain![
+        mount("/lazy").with(
+            Staticfiles::new(&theme)
+                .with_fallback(&base)
+                .index_file("index.html")
+        ),
+        mount("/eager").with(Staticfiles::new(&theme).with_fallback(&base).eager(true)),
+        mount("/listing").with(Staticfiles::new(&theme).with_fallback(&base).listing(true)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for prefix in &["/lazy", "/eager"] {
+        // the preceding root takes precedence.
+        let response = server.perform(format!("{}/style.css", prefix).as_str())?;
+        assert_eq!(response.status(), 200, "{}", prefix);
+        assert_eq!(response.body().to_utf8()?, "theme", "{}", prefix);
+        let theme_etag = response.header(ETAG)?.to_str()?.to_owned();
+
+        // the files missing in the preceding root are taken from the fallback.
+        let response = server.perform(format!("{}/app.js", prefix).as_str())?;
+        assert_eq!(response.status(), 200, "{}", prefix);
+        assert_eq!(response.body().to_utf8()?, "app", "{}", prefix);
+        assert_ne!(response.header(ETAG)?.to_str()?, theme_etag, "{}", prefix);
+
+        let response = server.perform(format!("{}/docs/extra.html", prefix).as_str())?;
+        assert_eq!(response.status(), 200, "{}", prefix);
+        assert_eq!(response.body().to_utf8()?, "extra", "{}", prefix);
+
+        let response = server.perform(format!("{}/missing.js", prefix).as_str())?;
+        assert_eq!(response.status(), 404, "{}", prefix);
+    }
+
+    // the index file is looked up in the merged directory.
+    let response = server.perform("/lazy/docs/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "docs");
+
+    // the listing shows the entries of all roots.
+    let response = server.perform("/listing/docs/")?;
+    assert_eq!(response.status(), 200);
+    let body = response.body().to_utf8()?;
+    assert!(body.contains("extra.html"), "{}", body);
+    assert!(body.contains("index.html"), "{}", body);
+
+    std::fs::remove_dir_all(&root)?;
+    Ok(())
+}
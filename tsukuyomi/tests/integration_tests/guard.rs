@@ -0,0 +1,105 @@
+use {
+    http::{Request, StatusCode},
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    tsukuyomi::{
+        config::prelude::*, //
+        guard::{Decision, PeerCertificates},
+        App,
+    },
+};
+
+const CLIENT_CERT: &[u8] = include_bytes!("../fixtures/client_cert/client.der");
+const UNTRUSTED_CERT: &[u8] = include_bytes!("../fixtures/client_cert/untrusted.der");
+const CA_CERT: &[u8] = include_bytes!("../fixtures/client_cert/ca.der");
+
+#[test]
+fn client_cert_guard() -> tsukuyomi_server::Result<()> {
+    let count = Arc::new(AtomicUsize::new(0));
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply("authorized"))
+            .modify(tsukuyomi::guard::client_cert({
+                let count = count.clone();
+                move |chain| {
+                    count.fetch_add(1, Ordering::SeqCst);
+                    if chain[0] == CLIENT_CERT {
+                        Decision::Allow
+                    } else {
+                        Decision::deny("unknown certificate")
+                    }
+                }
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // no certificate
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // allowed certificate, with the keep-alive connection.
+    // the guard is re-evaluated for every request.
+    let certs = PeerCertificates::new(vec![CLIENT_CERT.into(), CA_CERT.into()]);
+    for _ in 0..3 {
+        let response = server.perform(Request::get("/").extension(certs.clone()))?;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().to_utf8()?, "authorized");
+    }
+    assert_eq!(count.load(Ordering::SeqCst), 3);
+
+    // rejected certificate
+    let certs = PeerCertificates::new(vec![UNTRUSTED_CERT.into()]);
+    let response = server.perform(Request::get("/").extension(certs))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    assert_eq!(
+        response.body().to_utf8()?,
+        "the client certificate is rejected: unknown certificate"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "x509")]
+#[test]
+fn client_cert_guard_x509() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::guard::x509;
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply("authorized"))
+            .modify(tsukuyomi::guard::client_cert(|chain| {
+                x509::issuer_is(chain, "CN=Tsukuyomi Test CA")
+                    .and_then(|| x509::subject_name_in(chain, &["client.example"]))
+                    .and_then(|| x509::within_validity(chain))
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // allowed CN
+    let certs = PeerCertificates::new(vec![CLIENT_CERT.into(), CA_CERT.into()]);
+    let response = server.perform(Request::get("/").extension(certs))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    // wrong issuer
+    let certs = PeerCertificates::new(vec![UNTRUSTED_CERT.into()]);
+    let response = server.perform(Request::get("/").extension(certs))?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // no certificate
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // the names are compared attribute by attribute.
+    let chain = vec![CLIENT_CERT.to_vec()];
+    assert!(x509::issuer_is(&chain, "cn = tsukuyomi  test ca").is_allowed());
+    assert!(x509::issuer_is(&chain, "2.5.4.3=Tsukuyomi Test CA").is_allowed());
+    assert!(!x509::issuer_is(&chain, "CN=Tsukuyomi Test CA, O=Tsukuyomi").is_allowed());
+    assert!(!x509::issuer_is(&chain, "CN=Tsukuyomi Test").is_allowed());
+    assert!(!x509::issuer_is(&chain, "O=Tsukuyomi Test CA").is_allowed());
+    assert!(!x509::issuer_is(&chain, "XX=Tsukuyomi Test CA").is_allowed());
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use {
+    http::{
+        header::{CONTENT_LENGTH, CONTENT_TYPE},
+        Request,
+    },
+    serde_json::json,
+    tsukuyomi::{
+        config::prelude::*, //
+        contrib::json_schema::{ResponseMode, Schema, ValidateSchemaExt},
+        extractor,
+        output::json,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn user_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "name": { "type": "string" },
+            "age": { "type": "integer", "minimum": 0 },
+        },
+        "required": ["name"],
+    })
+}
+
+#[test]
+fn validate_request_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/users")
+            .to(endpoint::post()
+                .extract(extractor::body::json())
+                .call(|user: serde_json::Value| json(user)))
+            .validate_request(user_schema()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/users")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"name":"alice","age":20}"#),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"{"age":20,"name":"alice"}"#);
+
+    let response = server.perform(
+        Request::post("/users")
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"name":"alice","age":-1}"#),
+    )?;
+    assert_eq!(response.status(), 400);
+    let body: serde_json::Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    let errors = body["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["pointer"], "/age");
+
+    let response = server.perform(
+        Request::post("/users")
+            .header(CONTENT_TYPE, "application/json")
+            .body("{"),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn non_json_request_body_is_not_validated() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/upload")
+            .to(endpoint::post().reply("uploaded"))
+            .validate_request(user_schema()),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header(CONTENT_TYPE, "text/plain")
+            .body("hello"),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "uploaded");
+
+    let response = server.perform(
+        Request::post("/upload")
+            .header(CONTENT_TYPE, "application/merge-patch+json")
+            .body(r#"{"age":20}"#),
+    )?;
+    assert_eq!(response.status(), 400);
+
+    Ok(())
+}
+
+#[test]
+fn validate_response_body() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/log-only")
+            .to(endpoint::get().call(|| json(json!({ "age": 20 }))))
+            .validate_response(user_schema())
+            .response_mode(ResponseMode::LogOnly),
+        path!("/enforce")
+            .to(endpoint::get().call(|| json(json!({ "age": 20 }))))
+            .validate_response(user_schema())
+            .response_mode(ResponseMode::Enforce),
+        path!("/valid")
+            .to(endpoint::get().call(|| json(json!({ "name": "alice" }))))
+            .validate_response(user_schema())
+            .response_mode(ResponseMode::Enforce),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/log-only")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, r#"{"age":20}"#);
+
+    let response = server.perform("/enforce")?;
+    assert_eq!(response.status(), 500);
+
+    let response = server.perform("/valid")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_LENGTH)?, "16");
+    assert_eq!(response.body().to_utf8()?, r#"{"name":"alice"}"#);
+
+    Ok(())
+}
+
+#[test]
+fn invalid_schema_fails_at_startup() {
+    let result = App::create(
+        path!("/")
+            .to(endpoint::post().reply("ok"))
+            .validate_request(json!({ "type": "no-such-type" })),
+    );
+    assert!(result.is_err());
+
+    let result = App::create(
+        path!("/")
+            .to(endpoint::post().reply("ok"))
+            .validate_request(Schema::file("tests/fixtures/no-such-schema.json")),
+    );
+    assert!(result.is_err());
+}
@@ -214,4 +214,116 @@ mod responder {
 
         Ok(())
     }
+
+    #[test]
+    fn test_into_response_preset_envelope() -> tsukuyomi_server::Result<()> {
+        use {
+            http::Request,
+            serde::Serialize,
+            tsukuyomi::{
+                extractor,
+                input::{localmap::local_key, Input},
+                output::preset::{Envelope, Json, Wrap},
+            },
+        };
+
+        struct RequestId(String);
+
+        impl RequestId {
+            local_key! {
+                const KEY: Self;
+            }
+        }
+
+        struct WithRequestId;
+
+        impl Wrap for WithRequestId {
+            fn wrap(value: serde_json::Value, input: &Input<'_>) -> serde_json::Value {
+                let request_id = input.locals.get(&RequestId::KEY).map(|id| &id.0);
+                serde_json::json!({
+                    "data": value,
+                    "meta": { "request_id": request_id },
+                })
+            }
+        }
+
+        #[derive(Serialize, tsukuyomi::output::IntoResponse)]
+        #[response(preset = "Envelope<Json>")]
+        struct Plain {
+            id: u32,
+        }
+
+        #[derive(Serialize, tsukuyomi::output::IntoResponse)]
+        #[response(preset = "Envelope<Json, WithRequestId>")]
+        struct Custom {
+            id: u32,
+        }
+
+        let request_id = extractor::ready(|input| {
+            if let Some(id) = input.request.headers().get("x-request-id") {
+                let id = id.to_str().map_err(tsukuyomi::error::bad_request)?;
+                input.locals.insert(&RequestId::KEY, RequestId(id.into()));
+            }
+            Ok::<_, tsukuyomi::Error>(())
+        });
+
+        let app = App::create(chain! {
+            path!("/plain") //
+                .to(endpoint::call(|| Plain { id: 1 })),
+            path!("/custom") //
+                .to(endpoint::get()
+                    .extract(request_id)
+                    .call(|| Custom { id: 2 })),
+        })?;
+        let mut server = tsukuyomi_server::test::server(app)?;
+
+        let response = server.perform("/plain")?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.header("content-type")?, "application/json");
+        assert_eq!(response.body().to_utf8()?, r#"{"data":{"id":1},"meta":{}}"#);
+
+        let response = server.perform(Request::get("/custom").header("x-request-id", "abc123"))?;
+        assert_eq!(
+            response.body().to_utf8()?,
+            r#"{"data":{"id":2},"meta":{"request_id":"abc123"}}"#
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_response_preset_pretty() -> tsukuyomi_server::Result<()> {
+        use serde::Serialize;
+
+        #[derive(Serialize, tsukuyomi::output::IntoResponse)]
+        #[response(preset = "tsukuyomi::output::preset::Json")]
+        struct Compact {
+            id: u32,
+        }
+
+        #[derive(Serialize, tsukuyomi::output::IntoResponse)]
+        #[response(preset = "tsukuyomi::output::preset::Json", pretty)]
+        struct Pretty {
+            id: u32,
+        }
+
+        let app = App::create(chain! {
+            path!("/compact") //
+                .to(endpoint::call(|| Compact { id: 1 })),
+            path!("/pretty") //
+                .to(endpoint::call(|| Pretty { id: 1 })),
+        })?;
+
+        let mut server = tsukuyomi_server::test::server(app)?;
+
+        let response = server.perform("/compact")?;
+        assert_eq!(response.header("content-type")?, "application/json");
+        assert_eq!(response.body().to_utf8()?, r#"{"id":1}"#);
+
+        let response = server.perform("/pretty")?;
+        assert_eq!(response.header("content-type")?, "application/json");
+        assert_eq!(response.body().to_utf8()?, "{\n  \"id\": 1\n}");
+
+        Ok(())
+    }
 }
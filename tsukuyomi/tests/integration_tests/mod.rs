@@ -1,6 +1,23 @@
 mod app;
+#[cfg(feature = "archive")]
+mod archive;
+mod conformance;
 mod cookie;
+#[cfg(feature = "embed")]
+mod embedded;
+mod embedding;
 mod extract;
 mod fs;
+mod guard;
+#[cfg(feature = "json-schema")]
+mod json_schema;
 mod macros;
 mod modifier;
+mod output;
+mod preflight;
+mod recent_errors;
+mod shard;
+mod tls;
+mod upload;
+#[cfg(feature = "webhook")]
+mod webhook;
@@ -1,8 +1,13 @@
 use {
+    http::Request,
     std::sync::{Arc, Mutex},
     tsukuyomi::{
         config::prelude::*, //
-        handler::{AllowedMethods, Handler, ModifyHandler},
+        extractor,
+        future::{Async, Poll, TryFuture},
+        handler::{AllowedMethods, Handler, Metadata, ModifyHandler},
+        input::{localmap::LocalData, Input},
+        modifiers::{Diagnostics, SampleRate},
         App,
     },
 };
@@ -44,6 +49,10 @@ where
         self.inner.allowed_methods()
     }
 
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
     fn handle(&self) -> Self::Handle {
         self.marker.lock().unwrap().push(self.name);
         self.inner.handle()
@@ -175,3 +184,545 @@ fn nested_modifiers() -> tsukuyomi_server::Result<()> {
 
     Ok(())
 }
+
+#[test]
+fn sampling_always() -> tsukuyomi_server::Result<()> {
+    let reports = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::body::plain::<String>())
+                .extract(extractor::ready(|input| {
+                    Ok::<_, tsukuyomi::util::Never>((Diagnostics::get(input.locals)
+                        .map(|diagnostics| diagnostics.captured_body()),))
+                }))
+                .call(|body: String, captured: Option<bytes::Bytes>| {
+                    let captured = captured.expect("the request should be sampled");
+                    assert!(body.as_bytes().starts_with(&captured));
+                    body
+                }))
+            .modify({
+                let reports = reports.clone();
+                tsukuyomi::modifiers::sampling(SampleRate::always())
+                    .capture_body(4)
+                    .report(move |report| reports.lock().unwrap().push(report.clone()))
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/") //
+            .header("x-request-id", "abc")
+            .body("foo"),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    {
+        let reports = reports.lock().unwrap();
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].request_id, Some("abc".into()));
+        assert_eq!(reports[0].body, "foo");
+        assert!(!reports[0].body_truncated);
+        assert!(reports[0].succeeded);
+    }
+
+    reports.lock().unwrap().clear();
+    let _ = server.perform(Request::post("/").body("foobar"))?;
+    let reports = reports.lock().unwrap();
+    assert_eq!(reports.len(), 1);
+    assert_eq!(reports[0].request_id, None);
+    assert_eq!(reports[0].body, "foob");
+    assert!(reports[0].body_truncated);
+
+    Ok(())
+}
+
+#[test]
+fn sampling_never() -> tsukuyomi_server::Result<()> {
+    let reports = Arc::new(Mutex::new(vec![]));
+    let rate = SampleRate::never();
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::post()
+                .extract(extractor::ready(|input| {
+                    Ok::<_, tsukuyomi::util::Never>((Diagnostics::get(input.locals).is_some(),))
+                }))
+                .call(|sampled: bool| if sampled { "sampled" } else { "" }))
+            .modify({
+                let reports = reports.clone();
+                tsukuyomi::modifiers::sampling(rate.clone())
+                    .report(move |report| reports.lock().unwrap().push(report.clone()))
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/").body("foo"))?;
+    assert_eq!(response.body().to_utf8()?, "");
+    assert!(reports.lock().unwrap().is_empty());
+
+    // the rate can be changed after building the application.
+    rate.set_one_in(1);
+    let response = server.perform(Request::post("/").body("foo"))?;
+    assert_eq!(response.body().to_utf8()?, "sampled");
+    assert_eq!(reports.lock().unwrap().len(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn sampling_is_deterministic_by_request_id() -> tsukuyomi_server::Result<()> {
+    let reports = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply(""))
+            .modify({
+                let reports = reports.clone();
+                tsukuyomi::modifiers::sampling(SampleRate::one_in(2))
+                    .report(move |report| reports.lock().unwrap().push(report.clone()))
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for id in 0..16 {
+        let id = id.to_string();
+        let _ = server.perform(Request::get("/").header("x-request-id", &*id))?;
+        let sampled = reports.lock().unwrap().drain(..).count();
+        for _ in 0..3 {
+            let _ = server.perform(Request::get("/").header("x-request-id", &*id))?;
+            assert_eq!(reports.lock().unwrap().drain(..).count(), sampled);
+        }
+    }
+
+    Ok(())
+}
+
+#[test]
+fn sampling_decision_is_pinned() -> tsukuyomi_server::Result<()> {
+    let reports = Arc::new(Mutex::new(vec![]));
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::reply(""))
+            .modify({
+                let reports = reports.clone();
+                tsukuyomi::modifiers::sampling(SampleRate::one_in(2))
+                    .report(move |report| reports.lock().unwrap().push(report.clone()))
+            }),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    // FNV-1a("request-1") % 1_000_000 == 158_356 < 500_000
+    let _ = server.perform(Request::get("/").header("x-request-id", "request-1"))?;
+    assert_eq!(reports.lock().unwrap().drain(..).count(), 1);
+
+    // FNV-1a("foo") % 1_000_000 == 996_407 >= 500_000
+    let _ = server.perform(Request::get("/").header("x-request-id", "foo"))?;
+    assert_eq!(reports.lock().unwrap().drain(..).count(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn tagged_modifiers() -> tsukuyomi_server::Result<()> {
+    let marker = Arc::new(Mutex::new(vec![]));
+    let modifier = |name| MockModifier {
+        marker: marker.clone(),
+        name,
+    };
+
+    let app = App::create(chain![
+        with_tagged("admin", modifier("admin")),
+        with_tagged("public", modifier("public")),
+        path!("/") //
+            .to(endpoint::reply(""))
+            .tag("public"),
+        mount("/users").with(chain![
+            path!("/") //
+                .to(endpoint::reply(""))
+                .tag("public"),
+            path!("/edit") //
+                .to(endpoint::reply(""))
+                .tag("admin"),
+        ]),
+        mount("/admin")
+            .with(chain![
+                path!("/dashboard") //
+                    .to(endpoint::reply(""))
+                    .tag("public")
+                    .tag("admin"),
+                path!("/health") //
+                    .to(endpoint::reply("")),
+            ])
+            .modify(modifier("scope")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let mut assert_marker = |uri: &str, expected: Vec<&str>| -> tsukuyomi_server::Result<()> {
+        marker.lock().unwrap().clear();
+        let response = server.perform(uri)?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(*marker.lock().unwrap(), expected, "uri = {}", uri);
+        Ok(())
+    };
+    assert_marker("/", vec!["public"])?;
+    assert_marker("/users", vec!["public"])?;
+    assert_marker("/users/edit", vec!["admin"])?;
+    assert_marker("/admin/dashboard", vec!["admin", "public", "scope"])?;
+    assert_marker("/admin/health", vec!["scope"])?;
+
+    Ok(())
+}
+
+#[test]
+fn tagged_modifiers_unknown_tag() {
+    let result = App::create(chain![
+        with_tagged(
+            "admin",
+            MockModifier {
+                marker: Arc::new(Mutex::new(vec![])),
+                name: "admin",
+            }
+        ),
+        mount("/users").with(
+            path!("/edit") //
+                .to(endpoint::reply(""))
+                .tag("amdin"),
+        ),
+    ]);
+    let err = result.err().expect("should be an error").to_string();
+    assert!(err.contains("/users/edit"), "{}", err);
+    assert!(err.contains("amdin"), "{}", err);
+}
+
+/// A modifier that records when the handle of route is entered and exited.
+#[derive(Clone)]
+struct Recorder {
+    events: Arc<Mutex<Vec<String>>>,
+    name: &'static str,
+}
+
+impl<H: Handler> ModifyHandler<H> for Recorder {
+    type Output = H::Output;
+    type Handler = RecorderHandler<H>;
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        RecorderHandler {
+            inner,
+            recorder: self.clone(),
+        }
+    }
+}
+
+impl Recorder {
+    fn record(&self, event: String) {
+        self.events.lock().unwrap().push(event);
+    }
+}
+
+struct RecorderHandler<H> {
+    inner: H,
+    recorder: Recorder,
+}
+
+impl<H> Handler for RecorderHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = H::Error;
+    type Handle = RecorderHandle<H::Handle>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        RecorderHandle {
+            inner: self.inner.handle(),
+            recorder: self.recorder.clone(),
+            entered: false,
+        }
+    }
+}
+
+struct RecorderHandle<T> {
+    inner: T,
+    recorder: Recorder,
+    entered: bool,
+}
+
+impl<T> TryFuture for RecorderHandle<T>
+where
+    T: TryFuture,
+{
+    type Ok = T::Ok;
+    type Error = T::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        let name = self.recorder.name;
+        if !self.entered {
+            self.entered = true;
+            self.recorder.record(format!("enter {}", name));
+        }
+        let polled = self.inner.poll_ready(input);
+        match polled {
+            Ok(Async::Ready(..)) => self.recorder.record(format!("exit {}", name)),
+            Err(..) => self.recorder.record(format!("error {}", name)),
+            Ok(Async::NotReady) => {}
+        }
+        polled
+    }
+}
+
+#[test]
+fn modifier_ordering() -> tsukuyomi_server::Result<()> {
+    let events = Arc::new(Mutex::new(vec![]));
+    let recorder = |name| Recorder {
+        events: events.clone(),
+        name,
+    };
+
+    let app = App::create(chain![
+        with_outer(recorder("logging")),
+        mount("/api")
+            .with(chain![
+                path!("/ok").to(endpoint::call(|| "ok")),
+                path!("/err").to(endpoint::call_async(|| {
+                    Err::<&'static str, _>(tsukuyomi::error::unauthorized("unauthorized"))
+                })),
+            ])
+            .modify(recorder("cors"))
+            .modify(recorder("auth")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/api/ok")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "enter logging",
+            "enter cors",
+            "enter auth",
+            "exit auth",
+            "exit cors",
+            "exit logging",
+        ]
+    );
+
+    events.lock().unwrap().clear();
+    let response = server.perform("/api/err")?;
+    assert_eq!(response.status(), 401);
+    assert_eq!(
+        *events.lock().unwrap(),
+        vec![
+            "enter logging",
+            "enter cors",
+            "enter auth",
+            "error auth",
+            "error cors",
+            "error logging",
+        ]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn outer_modifiers_ordering() -> tsukuyomi_server::Result<()> {
+    let events = Arc::new(Mutex::new(vec![]));
+    let recorder = |name| Recorder {
+        events: events.clone(),
+        name,
+    };
+
+    let app = App::create(chain![
+        with_outer(recorder("outer1")),
+        mount("/").with(chain![
+            with_outer(recorder("outer2")),
+            with_outer(recorder("outer3")),
+            path!("/")
+                .to(endpoint::call(|| "ok"))
+                .modify(chain![recorder("m1"), recorder("m2")]),
+        ]),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let _ = server.perform("/")?;
+    let events = events.lock().unwrap();
+    assert_eq!(
+        events[..5],
+        [
+            "enter outer1",
+            "enter outer3",
+            "enter outer2",
+            "enter m2",
+            "enter m1"
+        ]
+    );
+    assert_eq!(
+        events[5..],
+        [
+            "exit m1",
+            "exit m2",
+            "exit outer2",
+            "exit outer3",
+            "exit outer1"
+        ]
+    );
+
+    Ok(())
+}
+
+/// The marker of the routes that skip the authentication.
+struct Public;
+
+/// The rate limit of route, read by the handlers.
+struct RateLimit(u32);
+
+#[derive(Clone)]
+struct RequireAuth;
+
+impl<H: Handler> ModifyHandler<H> for RequireAuth {
+    type Output = H::Output;
+    type Handler = RequireAuthHandler<H>;
+
+    fn modify(&self, inner: H) -> Self::Handler {
+        RequireAuthHandler {
+            public: inner.metadata().contains::<Public>(),
+            inner,
+        }
+    }
+}
+
+struct RequireAuthHandler<H> {
+    inner: H,
+    public: bool,
+}
+
+impl<H> Handler for RequireAuthHandler<H>
+where
+    H: Handler,
+{
+    type Output = H::Output;
+    type Error = tsukuyomi::error::Error;
+    type Handle = RequireAuthHandle<H::Handle>;
+
+    fn allowed_methods(&self) -> Option<&AllowedMethods> {
+        self.inner.allowed_methods()
+    }
+
+    fn metadata(&self) -> &Metadata {
+        self.inner.metadata()
+    }
+
+    fn handle(&self) -> Self::Handle {
+        RequireAuthHandle {
+            inner: self.inner.handle(),
+            public: self.public,
+        }
+    }
+}
+
+struct RequireAuthHandle<T> {
+    inner: T,
+    public: bool,
+}
+
+impl<T> TryFuture for RequireAuthHandle<T>
+where
+    T: TryFuture,
+{
+    type Ok = T::Ok;
+    type Error = tsukuyomi::error::Error;
+
+    fn poll_ready(&mut self, input: &mut Input<'_>) -> Poll<Self::Ok, Self::Error> {
+        if !self.public
+            && !input
+                .request
+                .headers()
+                .contains_key(http::header::AUTHORIZATION)
+        {
+            return Err(http::StatusCode::UNAUTHORIZED.into());
+        }
+        self.inner.poll_ready(input).map_err(Into::into)
+    }
+}
+
+#[test]
+fn route_extensions() -> tsukuyomi_server::Result<()> {
+    let rate_limit = || {
+        endpoint::get()
+            .extract(extractor::ready(|input| {
+                let limit = input.metadata().get::<RateLimit>().map(|limit| limit.0);
+                Ok::<_, tsukuyomi::error::Error>((limit,))
+            }))
+            .call(|limit: Option<u32>| format!("{:?}", limit))
+    };
+
+    let app = App::create(
+        chain![
+            path!("/login")
+                .to(endpoint::get().reply("login"))
+                .extension(Public),
+            path!("/search")
+                .to(rate_limit())
+                .extension(Public)
+                .extension(RateLimit(100)),
+            path!("/dashboard").to(rate_limit()),
+        ]
+        .modify(RequireAuth),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/login")?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform("/search")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "Some(100)");
+
+    let response = server.perform("/dashboard")?;
+    assert_eq!(response.status(), 401);
+
+    let response = server
+        .perform(Request::get("/dashboard").header(http::header::AUTHORIZATION, "Bearer xxx"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "None");
+
+    Ok(())
+}
+
+#[test]
+fn route_extensions_tagged_and_outer_modifiers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        with_tagged("auth", RequireAuth),
+        mount("/tagged").with(chain![
+            path!("/public")
+                .to(endpoint::get().reply("public"))
+                .tag("auth")
+                .extension(Public),
+            path!("/private")
+                .to(endpoint::get().reply("private"))
+                .tag("auth"),
+        ]),
+        mount("/outer").with(chain![
+            with_outer(RequireAuth),
+            path!("/public")
+                .to(endpoint::get().reply("public"))
+                .extension(Public),
+            path!("/private").to(endpoint::get().reply("private")),
+        ]),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    assert_eq!(server.perform("/tagged/public")?.status(), 200);
+    assert_eq!(server.perform("/tagged/private")?.status(), 401);
+    assert_eq!(server.perform("/outer/public")?.status(), 200);
+    assert_eq!(server.perform("/outer/private")?.status(), 401);
+
+    Ok(())
+}
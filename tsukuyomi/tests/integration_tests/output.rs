@@ -0,0 +1,1522 @@
+use {
+    bytes::Bytes,
+    http::{
+        header::{HeaderValue, CACHE_CONTROL, CONTENT_LENGTH, CONTENT_TYPE},
+        Request, Response,
+    },
+    tsukuyomi::{
+        config::{prelude::*, Scope},
+        modifiers,
+        output::{
+            self,
+            transform::{self, ChunkTransformer},
+            IntoResponse, NoTransform, ResponseBody,
+        },
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn no_transform() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/signed") //
+            .to(endpoint::call(|| output::no_transform("signed payload"))),
+        path!("/plain") //
+            .to(endpoint::reply("plain payload")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/signed")?;
+    assert_eq!(response.header(CACHE_CONTROL)?, "no-transform");
+    assert_eq!(response.body().to_utf8()?, "signed payload");
+
+    let response = server.perform("/plain")?;
+    assert!(!response.headers().contains_key(CACHE_CONTROL));
+
+    Ok(())
+}
+
+#[test]
+fn html_charset() -> tsukuyomi_server::Result<()> {
+    let app = App::create(chain![
+        path!("/utf8").to(endpoint::call(|| output::html("<p>café</p>"))),
+        path!("/latin1").to(endpoint::call(|| {
+            output::with_charset(output::html(&b"<p>caf\xe9</p>"[..]), "iso-8859-1")
+        })),
+        path!("/plain").to(endpoint::call(|| output::with_charset("hello", "us-ascii"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/utf8")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+
+    let response = server.perform("/latin1")?;
+    assert_eq!(
+        response.header(CONTENT_TYPE)?,
+        "text/html; charset=iso-8859-1"
+    );
+    assert_eq!(response.body().to_bytes(), &b"<p>caf\xe9</p>"[..]);
+
+    let response = server.perform("/plain")?;
+    assert_eq!(
+        response.header(CONTENT_TYPE)?,
+        "text/plain; charset=us-ascii"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn is_transformable() {
+    let request = Request::new(());
+    let with_cache_control = |value: &'static str| {
+        output::into_response(move |_| {
+            let mut response = Response::new("payload");
+            response
+                .headers_mut()
+                .insert(CACHE_CONTROL, HeaderValue::from_static(value));
+            Ok::<_, tsukuyomi::Error>(response)
+        })
+    };
+
+    let response = output::no_transform(with_cache_control("private"))
+        .into_response(&request)
+        .unwrap();
+    assert_eq!(response.headers()[CACHE_CONTROL], "private, no-transform");
+    assert!(response.extensions().get::<NoTransform>().is_some());
+    assert!(!output::is_transformable(&response));
+
+    // the directive set by the handler is not duplicated.
+    let response = output::no_transform(with_cache_control("No-Transform"))
+        .into_response(&request)
+        .unwrap();
+    assert_eq!(response.headers()[CACHE_CONTROL], "No-Transform");
+
+    // the responses that only have the directive are also skipped.
+    let response = with_cache_control("max-age=60, no-transform")
+        .into_response(&request)
+        .unwrap();
+    assert!(response.extensions().get::<NoTransform>().is_none());
+    assert!(!output::is_transformable(&response));
+
+    let response = "payload".into_response(&request).unwrap();
+    assert!(output::is_transformable(&response));
+}
+
+fn transform_all(transformer: &mut impl ChunkTransformer, chunks: &[&'static str]) -> Vec<Bytes> {
+    let mut output: Vec<_> = chunks
+        .iter()
+        .map(|chunk| transformer.transform(Bytes::from_static(chunk.as_bytes())))
+        .collect();
+    output.push(transformer.finish());
+    output
+}
+
+#[test]
+fn replace_within_chunk() {
+    let mut transformer = transform::replace("<body>", "<body>[banner]");
+    let output = transform_all(&mut transformer, &["<html><body>Hello</body></html>"]);
+    assert_eq!(
+        output,
+        vec![
+            Bytes::from_static(b"<html><body>[banner]Hello</body></html>"),
+            Bytes::new()
+        ]
+    );
+}
+
+#[test]
+fn replace_split_across_chunk_boundary() {
+    let mut transformer = transform::replace("<body>", "<body>[banner]");
+    let output = transform_all(&mut transformer, &["<html><bo", "dy>Hello</body></html>"]);
+    assert_eq!(
+        output,
+        vec![
+            Bytes::from_static(b"<html>"),
+            Bytes::from_static(b"<body>[banner]Hello</body></html>"),
+            Bytes::new()
+        ]
+    );
+}
+
+#[test]
+fn replace_split_into_many_chunks() {
+    let mut transformer = transform::replace("abc", "X");
+    let output = transform_all(&mut transformer, &["a", "b", "c", "ab", "abc", "a"]);
+    assert_eq!(output.concat(), b"XabXa".to_vec());
+}
+
+#[test]
+fn replace_false_prefix_is_released() {
+    let mut transformer = transform::replace("<body>", "<body>[banner]");
+    let output = transform_all(&mut transformer, &["<b", "r><bod", "y>"]);
+    assert_eq!(
+        output,
+        vec![
+            Bytes::new(),
+            Bytes::from_static(b"<br>"),
+            Bytes::from_static(b"<body>[banner]"),
+            Bytes::new()
+        ]
+    );
+
+    // the held back data is flushed at the end of stream.
+    let mut transformer = transform::replace("<body>", "<body>[banner]");
+    let output = transform_all(&mut transformer, &["<p>end</p><bo"]);
+    assert_eq!(output.concat(), b"<p>end</p><bo".to_vec());
+}
+
+#[test]
+fn transform_body_modifier() -> tsukuyomi_server::Result<()> {
+    fn streamed(content_type: &'static str) -> Response<ResponseBody> {
+        let chunks = vec!["<html><bo", "dy><p>Hello</p></body></html>"];
+        Response::builder()
+            .header(CONTENT_TYPE, content_type)
+            .body(ResponseBody::wrap_stream(futures01::stream::iter_ok::<
+                _,
+                std::io::Error,
+            >(chunks)))
+            .unwrap()
+    }
+
+    let app = App::create(
+        chain![
+            path!("/html").to(endpoint::call(|| streamed("text/html; charset=utf-8"))),
+            path!("/text").to(endpoint::call(|| streamed("text/plain"))),
+            path!("/fixed").to(endpoint::call(|| output::html("<body>fixed</body>"))),
+            path!("/no-transform").to(endpoint::call(|| output::no_transform(output::html(
+                "<body>signed</body>"
+            )))),
+        ]
+        .modify(modifiers::transform_body(mime::TEXT_HTML, || {
+            transform::replace("<body>", "<body><div>banner</div>")
+        })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/html")?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "<html><body><div>banner</div><p>Hello</p></body></html>"
+    );
+
+    let response = server.perform("/text")?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "<html><body><p>Hello</p></body></html>"
+    );
+
+    let response = server.perform("/fixed")?;
+    assert!(!response.headers().contains_key(CONTENT_LENGTH));
+    assert_eq!(
+        response.body().to_utf8()?,
+        "<body><div>banner</div>fixed</body>"
+    );
+
+    let response = server.perform("/no-transform")?;
+    assert_eq!(response.header(CONTENT_LENGTH)?, "19");
+    assert_eq!(response.body().to_utf8()?, "<body>signed</body>");
+
+    Ok(())
+}
+
+#[test]
+fn sse_event_encoding() {
+    use {std::time::Duration, tsukuyomi::output::sse::Event};
+
+    assert_eq!(
+        Event::new()
+            .event("update")
+            .id("4\r\n2")
+            .retry(Duration::from_millis(1500))
+            .data("line1\nline2\r\n")
+            .to_bytes(),
+        "event: update\nid: 42\nretry: 1500\ndata: line1\ndata: line2\ndata:\n\n"
+    );
+    assert_eq!(Event::new().comment("a\nb").to_bytes(), ": a\n: b\n\n");
+    assert_eq!(
+        Event::new().data("a\rb\r\n\nc").to_bytes(),
+        "data: a\ndata: b\ndata:\ndata: c\n\n"
+    );
+}
+
+#[test]
+fn sse_responder() -> tsukuyomi_server::Result<()> {
+    use {
+        futures01::{Async, Poll},
+        std::time::Duration,
+        tsukuyomi::output::sse::{Event, Sse},
+    };
+
+    let app = App::create(chain![
+        path!("/events").to(endpoint::call(|| {
+            Sse::new(futures01::stream::iter_ok::<_, std::io::Error>(vec![
+                Event::new().data("first"),
+                Event::new().event("ping").data("second"),
+            ]))
+        })),
+        path!("/slow").to(endpoint::call(|| {
+            // the stream is not ready until the task is woken up by the keep-alive timer,
+            // which is the only source of notifications here.
+            let mut polled = 0;
+            let events = futures01::stream::poll_fn(move || -> Poll<_, std::io::Error> {
+                polled += 1;
+                match polled {
+                    1 | 2 => Ok(Async::NotReady),
+                    3 => Ok(Async::Ready(Some(Event::new().data("done")))),
+                    _ => Ok(Async::Ready(None)),
+                }
+            });
+            Sse::new(events).keep_alive(Duration::from_millis(10))
+        })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/events")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/event-stream");
+    assert_eq!(response.header(CACHE_CONTROL)?, "no-cache");
+    assert_eq!(
+        *response.body().chunks(),
+        vec![
+            Bytes::from("data: first\n\n"),
+            Bytes::from("event: ping\ndata: second\n\n"),
+        ]
+    );
+
+    let response = server.perform("/slow")?;
+    assert_eq!(
+        *response.body().chunks(),
+        vec![Bytes::from(":\n\n"), Bytes::from("data: done\n\n")]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn negotiate_by_accept() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{ACCEPT, VARY},
+        serde::Serialize,
+        tsukuyomi::output::negotiate::Renderers,
+    };
+
+    #[derive(Debug, Serialize)]
+    struct User {
+        name: &'static str,
+    }
+
+    let user = || {
+        endpoint::call(|| {
+            output::negotiate(User { name: "alice" })
+                .html(|user| Ok::<_, tsukuyomi::Error>(format!("<p>{}</p>", user.name)))
+                .plain(|user| Ok::<_, tsukuyomi::Error>(user.name))
+        })
+    };
+
+    let app = App::create(chain![
+        path!("/user").to(user()),
+        mount("/custom").with(chain![
+            path!("/user").to(user()),
+            scope_config(Renderers::new().register(
+                "application/x-custom".parse().unwrap(),
+                |value| Ok::<_, tsukuyomi::Error>(format!("custom:{}", value["name"])),
+            )),
+        ]),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/user")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.header(VARY)?, "accept");
+    assert_eq!(response.body().to_utf8()?, r#"{"name":"alice"}"#);
+
+    let response = server.perform(Request::get("/user").header(ACCEPT, "text/html"))?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+    assert_eq!(response.header(VARY)?, "accept");
+    assert_eq!(response.body().to_utf8()?, "<p>alice</p>");
+
+    let response = server
+        .perform(Request::get("/user").header(ACCEPT, "text/html;q=0.5, text/plain, */*;q=0.1"))?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/plain; charset=utf-8");
+    assert_eq!(response.body().to_utf8()?, "alice");
+
+    let response = server.perform(Request::get("/user").header(ACCEPT, "application/x-custom"))?;
+    assert_eq!(response.status(), 406);
+
+    let response =
+        server.perform(Request::get("/custom/user").header(ACCEPT, "application/x-custom"))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/x-custom");
+    assert_eq!(response.header(VARY)?, "accept");
+    assert_eq!(response.body().to_utf8()?, r#"custom:"alice""#);
+
+    Ok(())
+}
+
+#[test]
+fn resumable_download() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{ACCEPT_RANGES, CONTENT_RANGE, ETAG, IF_RANGE, RANGE},
+        std::sync::{Arc, Mutex},
+    };
+
+    const DATA: &str = "0123456789abcdefghij";
+
+    let generated = Arc::new(Mutex::new(vec![]));
+    let app = App::create(path!("/export").to(endpoint::get().call({
+        let generated = generated.clone();
+        move || {
+            let generated = generated.clone();
+            output::resumable("v2", DATA.len() as u64, move |range| {
+                generated.lock().unwrap().push(range.clone());
+                let chunk = &DATA[range.start as usize..range.end as usize];
+                futures01::stream::once::<_, std::io::Error>(Ok(chunk))
+            })
+        }
+    })))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/export")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(ACCEPT_RANGES)?, "bytes");
+    assert_eq!(response.header(ETAG)?, "\"v2\"");
+    assert_eq!(response.header(CONTENT_LENGTH)?, "20");
+    assert_eq!(response.body().to_utf8()?, DATA);
+
+    // resume with the current ETag.
+    let response = server.perform(
+        Request::get("/export")
+            .header(RANGE, "bytes=15-")
+            .header(IF_RANGE, "\"v2\""),
+    )?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.header(CONTENT_RANGE)?, "bytes 15-19/20");
+    assert_eq!(response.header(CONTENT_LENGTH)?, "5");
+    assert_eq!(response.body().to_utf8()?, "fghij");
+
+    // resume after the content has changed.
+    let response = server.perform(
+        Request::get("/export")
+            .header(RANGE, "bytes=15-")
+            .header(IF_RANGE, "\"v1\""),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().get(CONTENT_RANGE).is_none());
+    assert_eq!(response.body().to_utf8()?, DATA);
+
+    // the weak validators never match.
+    let response = server.perform(
+        Request::get("/export")
+            .header(RANGE, "bytes=15-")
+            .header(IF_RANGE, "W/\"v2\""),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    let response = server.perform(Request::get("/export").header(RANGE, "bytes=-3"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.body().to_utf8()?, "hij");
+
+    let response = server.perform(Request::get("/export").header(RANGE, "bytes=20-"))?;
+    assert_eq!(response.status(), 416);
+    assert_eq!(response.header(CONTENT_RANGE)?, "bytes */20");
+
+    assert_eq!(
+        *generated.lock().unwrap(),
+        vec![0..20, 15..20, 0..20, 0..20, 17..20]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn json_output() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::output::{Json, JsonConfig};
+
+    let config = JsonConfig::new().pretty_when(|request| match request.uri().query() {
+        Some(query) => query.split('&').any(|pair| pair == "pretty=1"),
+        None => false,
+    });
+
+    let app = App::create(chain![
+        path!("/compact") //
+            .to(endpoint::call(|| Json::new(vec![1, 2]))),
+        path!("/pretty") //
+            .to(endpoint::call(|| Json::new(vec![1, 2]).pretty())),
+        path!("/config") //
+            .to(endpoint::call(move || Json::new(vec![1, 2]).config(&config))),
+        path!("/vendor") //
+            .to(endpoint::call(|| {
+                Json::new(vec![1, 2]).content_type("application/vnd.api+json".parse().unwrap())
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/compact")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.header(CONTENT_LENGTH)?, "5");
+    assert_eq!(response.body().to_utf8()?, "[1,2]");
+
+    let response = server.perform("/pretty")?;
+    assert_eq!(response.header(CONTENT_LENGTH)?, "12");
+    assert_eq!(response.body().to_utf8()?, "[\n  1,\n  2\n]");
+
+    let response = server.perform("/config")?;
+    assert_eq!(response.body().to_utf8()?, "[1,2]");
+    let response = server.perform("/config?pretty=1")?;
+    assert_eq!(response.body().to_utf8()?, "[\n  1,\n  2\n]");
+
+    let response = server.perform("/vendor")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/vnd.api+json");
+    assert_eq!(response.body().to_utf8()?, "[1,2]");
+
+    Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_preset() -> tsukuyomi_server::Result<()> {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, IntoResponse)]
+    #[response(preset = "tsukuyomi::output::preset::Msgpack")]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    let app = App::create(
+        path!("/") //
+            .to(chain![
+                endpoint::get().call(|| User {
+                    name: "Sakura Kinomoto".into(),
+                    age: 13,
+                }),
+                endpoint::post()
+                    .extract(tsukuyomi::extractor::body::msgpack())
+                    .call(|user: User| user),
+            ]),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/msgpack");
+    let body = response.body().to_bytes();
+    assert_eq!(
+        response.header(CONTENT_LENGTH)?,
+        body.len().to_string().as_str()
+    );
+    let user: User = rmp_serde::from_slice(&body)?;
+    assert_eq!(
+        user,
+        User {
+            name: "Sakura Kinomoto".into(),
+            age: 13,
+        }
+    );
+
+    let response = server.perform(
+        Request::post("/")
+            .header(CONTENT_TYPE, "application/msgpack")
+            .body(body.to_vec()),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        rmp_serde::from_slice::<User>(&response.body().to_bytes())?,
+        user
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "msgpack")]
+#[test]
+fn msgpack_preset_error() {
+    use {
+        serde::{ser::Error as _SerError, Serialize, Serializer},
+        tsukuyomi::output::preset::{Msgpack, Preset},
+    };
+
+    struct Broken;
+
+    impl Serialize for Broken {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("broken value"))
+        }
+    }
+
+    let err = match <Msgpack as Preset<Broken>>::into_response(Broken, &Request::new(())) {
+        Ok(..) => panic!("the serialization should fail"),
+        Err(err) => err,
+    };
+    // the underlying error is kept in the error value for logging.
+    assert!(err.to_string().contains("broken value"));
+    let response = err.into_response(&Request::new(()));
+    assert_eq!(response.status(), 500);
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn xml_preset() -> tsukuyomi_server::Result<()> {
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize, IntoResponse)]
+    #[response(preset = "tsukuyomi::output::preset::Xml")]
+    struct User {
+        name: String,
+        age: u32,
+    }
+
+    let app = App::create(
+        path!("/") //
+            .to(chain![
+                endpoint::get().call(|| User {
+                    name: "Sakura Kinomoto".into(),
+                    age: 13,
+                }),
+                endpoint::post()
+                    .extract(tsukuyomi::extractor::body::xml())
+                    .call(|user: User| user),
+            ]),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/xml");
+    let body = response.body().to_utf8()?.into_owned();
+    assert_eq!(
+        response.header(CONTENT_LENGTH)?,
+        body.len().to_string().as_str()
+    );
+    let user: User = serde_xml_rs::from_str(&body)?;
+    assert_eq!(
+        user,
+        User {
+            name: "Sakura Kinomoto".into(),
+            age: 13,
+        }
+    );
+
+    let response = server.perform(
+        Request::post("/")
+            .header(CONTENT_TYPE, "text/xml")
+            .body(body),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        serde_xml_rs::from_str::<User>(&*response.body().to_utf8()?)?,
+        user
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "xml")]
+#[test]
+fn xml_preset_error() {
+    use {
+        serde::{ser::Error as _SerError, Serialize, Serializer},
+        tsukuyomi::output::preset::{Preset, Xml},
+    };
+
+    struct Broken;
+
+    impl Serialize for Broken {
+        fn serialize<S: Serializer>(&self, _: S) -> Result<S::Ok, S::Error> {
+            Err(S::Error::custom("broken value"))
+        }
+    }
+
+    let err = match <Xml as Preset<Broken>>::into_response(Broken, &Request::new(())) {
+        Ok(..) => panic!("the serialization should fail"),
+        Err(err) => err,
+    };
+    assert!(err.to_string().contains("broken value"));
+    let response = err.into_response(&Request::new(()));
+    assert_eq!(response.status(), 500);
+}
+
+#[cfg(feature = "csv")]
+fn csv_chunks<S>(csv: tsukuyomi::output::csv::Csv<S>) -> Result<Vec<String>, String>
+where
+    S: futures01::Stream + Send + 'static,
+    S::Item: serde::Serialize,
+    S::Error: Into<Box<dyn std::error::Error + Send + Sync + 'static>>,
+{
+    use {futures01::Async, hyper::body::Payload};
+
+    let mut body = csv
+        .into_response(&Request::new(()))
+        .map_err(|err| err.to_string())?
+        .into_body();
+    let mut chunks = vec![];
+    loop {
+        match body.poll_data().map_err(|err| err.to_string())? {
+            Async::Ready(Some(chunk)) => {
+                chunks.push(String::from_utf8(chunk.into_bytes().to_vec()).unwrap())
+            }
+            Async::Ready(None) => return Ok(chunks),
+            Async::NotReady => panic!("the records should be ready"),
+        }
+    }
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn csv_streaming() -> tsukuyomi_server::Result<()> {
+    use {serde::Serialize, tsukuyomi::output::csv::Csv};
+
+    #[derive(Serialize)]
+    struct Row {
+        id: u32,
+        name: &'static str,
+    }
+
+    let rows = || {
+        vec![
+            Row {
+                id: 1,
+                name: "alice",
+            },
+            Row {
+                id: 2,
+                name: "bob, jr.",
+            },
+            Row {
+                id: 3,
+                name: "say \"hi\"",
+            },
+            Row {
+                id: 4,
+                name: "multi\nline",
+            },
+            Row { id: 5, name: "" },
+        ]
+    };
+
+    // the records are flushed in batches.
+    let chunks = csv_chunks(Csv::from_records(rows()).batch_size(2)).map_err(failure::err_msg)?;
+    assert_eq!(
+        chunks,
+        vec![
+            "id,name\n1,alice\n2,\"bob, jr.\"\n",
+            "3,\"say \"\"hi\"\"\"\n4,\"multi\nline\"\n",
+            "5,\n",
+        ]
+    );
+
+    let chunks = csv_chunks(
+        Csv::from_records(rows())
+            .delimiter(b';')
+            .has_headers(false)
+            .batch_size(100),
+    )
+    .map_err(failure::err_msg)?;
+    assert_eq!(
+        chunks,
+        vec!["1;alice\n2;bob, jr.\n3;\"say \"\"hi\"\"\"\n4;\"multi\nline\"\n5;\n"]
+    );
+
+    // no chunks for the empty input.
+    let chunks = csv_chunks(Csv::from_records(Vec::<Row>::new())).map_err(failure::err_msg)?;
+    assert!(chunks.is_empty());
+
+    let app = App::create(chain![
+        path!("/export.csv") //
+            .to(endpoint::get().call(move || Csv::from_records(rows()).attachment("rows.csv"))),
+        path!("/export-ja.csv") //
+            .to(endpoint::get().call(|| {
+                Csv::from_records(vec![Row { id: 1, name: "a" }]).attachment("一覧 \"1\".csv")
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/export.csv")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/csv; charset=utf-8");
+    assert_eq!(
+        response.header("content-disposition")?,
+        "attachment; filename=\"rows.csv\""
+    );
+    assert_eq!(
+        response.body().to_utf8()?,
+        "id,name\n1,alice\n2,\"bob, jr.\"\n3,\"say \"\"hi\"\"\"\n4,\"multi\nline\"\n5,\n"
+    );
+
+    let response = server.perform("/export-ja.csv")?;
+    assert_eq!(
+        response.header("content-disposition")?,
+        "attachment; filename=\"__ _1_.csv\"; \
+         filename*=UTF-8''%E4%B8%80%E8%A6%A7%20%221%22.csv"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "csv")]
+#[test]
+fn csv_rejects_invalid_utf8() {
+    use {
+        serde::{Serialize, Serializer},
+        tsukuyomi::output::csv::Csv,
+    };
+
+    struct Raw(&'static [u8]);
+
+    impl Serialize for Raw {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_bytes(self.0)
+        }
+    }
+
+    let chunks = csv_chunks(Csv::from_records(vec![(1, Raw(b"ok"))]).has_headers(false));
+    assert_eq!(chunks, Ok(vec!["1,ok\n".to_owned()]));
+
+    let err = csv_chunks(Csv::from_records(vec![
+        (1, Raw(b"ok")),
+        (2, Raw(b"\xff\xfe")),
+    ]))
+    .unwrap_err();
+    assert!(err.contains("invalid UTF-8"), "{}", err);
+}
+
+#[test]
+fn conditional_etag() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{ETAG, IF_NONE_MATCH, VARY},
+        tsukuyomi::output::{with_etag, ETag},
+    };
+
+    let app = App::create(chain![
+        path!("/page") //
+            .to(endpoint::call(|| {
+                let page = "<h1>Hello</h1>".to_owned();
+                let etag = ETag::from_body(&page);
+                let response = Response::builder()
+                    .header(CONTENT_TYPE, "text/html; charset=utf-8")
+                    .header(CACHE_CONTROL, "max-age=60")
+                    .header(VARY, "accept-language")
+                    .body(page)
+                    .unwrap();
+                with_etag(response, etag)
+            })),
+        path!("/missing") //
+            .to(endpoint::call(|| {
+                let response = Response::builder().status(404).body("missing").unwrap();
+                with_etag(response, ETag::strong("missing"))
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/page")?;
+    assert_eq!(response.status(), 200);
+    let etag = response.header(ETAG)?.clone();
+    assert_eq!(etag, ETag::from_body("<h1>Hello</h1>").to_string().as_str());
+    assert_eq!(response.body().to_utf8()?, "<h1>Hello</h1>");
+
+    let response = server.perform(Request::get("/page").header(IF_NONE_MATCH, etag.clone()))?;
+    assert_eq!(response.status(), 304);
+    assert_eq!(response.header(ETAG)?, etag);
+    assert_eq!(response.header(CACHE_CONTROL)?, "max-age=60");
+    assert_eq!(response.header(VARY)?, "accept-language");
+    assert!(!response.headers().contains_key(CONTENT_TYPE));
+    assert!(response.body().to_bytes().is_empty());
+
+    // any of the listed tags matches by the weak comparison.
+    let response = server.perform(
+        Request::get("/page").header(IF_NONE_MATCH, format!("\"foo\", {}", etag.to_str()?)),
+    )?;
+    assert_eq!(response.status(), 304);
+    let response = server.perform(Request::get("/page").header(IF_NONE_MATCH, "*"))?;
+    assert_eq!(response.status(), 304);
+
+    let response = server.perform(Request::get("/page").header(IF_NONE_MATCH, "\"foo\""))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "<h1>Hello</h1>");
+
+    // the unsafe methods are not evaluated.
+    let response = server.perform(Request::post("/page").header(IF_NONE_MATCH, etag.clone()))?;
+    assert_eq!(response.status(), 200);
+
+    // the validators are not attached to the non-200 responses.
+    let response = server.perform(Request::get("/missing").header(IF_NONE_MATCH, "*"))?;
+    assert_eq!(response.status(), 404);
+    assert!(!response.headers().contains_key(ETAG));
+
+    Ok(())
+}
+
+#[test]
+fn conditional_last_modified() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED},
+        std::time::{Duration, UNIX_EPOCH},
+        tsukuyomi::output::{with_last_modified, ETag},
+    };
+
+    // Sun, 06 Nov 1994 08:49:37 GMT (+ 0.5 sec)
+    let last_modified = UNIX_EPOCH + Duration::from_millis(784_111_777_500);
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::call(move || {
+                with_last_modified("content", last_modified).etag(ETag::strong("v1"))
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.header(LAST_MODIFIED)?,
+        "Sun, 06 Nov 1994 08:49:37 GMT"
+    );
+    assert_eq!(response.header(ETAG)?, "\"v1\"");
+
+    for date in &[
+        "Sun, 06 Nov 1994 08:49:37 GMT",
+        "Sunday, 06-Nov-94 08:49:37 GMT",
+        "Mon, 07 Nov 1994 00:00:00 GMT",
+    ] {
+        let response = server.perform(Request::get("/").header(IF_MODIFIED_SINCE, *date))?;
+        assert_eq!(response.status(), 304, "{}", date);
+        assert_eq!(
+            response.header(LAST_MODIFIED)?,
+            "Sun, 06 Nov 1994 08:49:37 GMT"
+        );
+    }
+
+    for date in &["Sun, 06 Nov 1994 08:49:36 GMT", "invalid date"] {
+        let response = server.perform(Request::get("/").header(IF_MODIFIED_SINCE, *date))?;
+        assert_eq!(response.status(), 200, "{}", date);
+        assert_eq!(response.body().to_utf8()?, "content");
+    }
+
+    // `If-Modified-Since` is ignored if `If-None-Match` is present.
+    let response = server.perform(
+        Request::get("/")
+            .header(IF_NONE_MATCH, "\"v0\"")
+            .header(IF_MODIFIED_SINCE, "Mon, 07 Nov 1994 00:00:00 GMT"),
+    )?;
+    assert_eq!(response.status(), 200);
+
+    Ok(())
+}
+
+#[test]
+fn redirect_to_route() -> tsukuyomi_server::Result<()> {
+    use {http::header::LOCATION, tsukuyomi::output::redirect};
+
+    #[derive(serde::Serialize)]
+    struct PostParams {
+        id: u32,
+        page: Option<u32>,
+    }
+
+    let app = App::create(|scope: &mut Scope<'_, _, _>| {
+        let login = scope.route_ref("login");
+        let post = scope.route_ref("post");
+        let comments = scope.route_ref("comments");
+        chain![
+            path!("/login").to(endpoint::reply("login")).name("login"),
+            mount("/posts").with(
+                path!("/:id/comments/*path")
+                    .to(endpoint::call(|_: u32, _: String| "comments"))
+                    .name("comments")
+            ),
+            path!("/posts/:id")
+                .to(endpoint::call(|_: u32| "post"))
+                .name("post"),
+            path!("/a").to(endpoint::call(move || redirect::to_route(&login, ()))),
+            path!("/b").to(endpoint::call({
+                let post = post.clone();
+                move || {
+                    redirect::to_route(
+                        &post,
+                        PostParams {
+                            id: 42,
+                            page: Some(2),
+                        },
+                    )
+                    .status(http::StatusCode::SEE_OTHER)
+                }
+            })),
+            path!("/c").to(endpoint::call(move || {
+                redirect::to_route(&comments, (1, "a b/c"))
+            })),
+            path!("/e").to(endpoint::call(move || redirect::to_route(&post, ()))),
+        ]
+        .configure(scope)
+    })?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/a")?;
+    assert_eq!(response.status(), 302);
+    assert_eq!(response.header(LOCATION)?, "/login");
+
+    let response = server.perform("/b")?;
+    assert_eq!(response.status(), 303);
+    assert_eq!(response.header(LOCATION)?, "/posts/42?page=2");
+
+    let response = server.perform("/c")?;
+    assert_eq!(response.header(LOCATION)?, "/posts/1/comments/a%20b/c");
+
+    let response = server.perform("/e")?;
+    assert_eq!(response.status(), 500);
+
+    Ok(())
+}
+
+#[test]
+fn redirect_to_missing_route_fails_at_startup() {
+    use tsukuyomi::output::redirect;
+
+    let result = App::create(chain![
+        path!("/login").to(endpoint::reply("login")).name("login"),
+        require_route("login"),
+        require_route("logout"),
+    ]);
+    assert!(result.is_err());
+
+    let result = App::create(|scope: &mut Scope<'_, _, _>| {
+        let logout = scope.route_ref("logout");
+        chain![
+            path!("/login").to(endpoint::reply("login")).name("login"),
+            path!("/a").to(endpoint::call(move || redirect::to_route(&logout, ()))),
+        ]
+        .configure(scope)
+    });
+    assert!(result.is_err());
+
+    let result = App::create(chain![
+        path!("/a").to(endpoint::reply("a")).name("dup"),
+        path!("/b").to(endpoint::reply("b")).name("dup"),
+    ]);
+    assert!(result.is_err());
+}
+
+#[test]
+fn redirect_responders() -> tsukuyomi_server::Result<()> {
+    use {
+        http::header::{HOST, LOCATION, REFERER},
+        tsukuyomi::output::redirect,
+    };
+
+    let app = App::create(chain![
+        path!("/temporary").to(endpoint::call(|| redirect::temporary("/a"))),
+        path!("/permanent").to(endpoint::call(|| redirect::permanent("/a"))),
+        path!("/see_other").to(endpoint::call(|| redirect::see_other("/a"))),
+        path!("/back").to(endpoint::call(|| redirect::back("/home"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/temporary")?;
+    assert_eq!(response.status(), 307);
+    let response = server.perform("/permanent")?;
+    assert_eq!(response.status(), 308);
+    let response = server.perform("/see_other")?;
+    assert_eq!(response.status(), 303);
+
+    let response = server.perform(Request::get("/back"))?;
+    assert_eq!(response.status(), 303);
+    assert_eq!(response.header(LOCATION)?, "/home");
+
+    let response = server.perform(
+        Request::get("/back")
+            .header(HOST, "example.com")
+            .header(REFERER, "http://example.com/posts?page=2"),
+    )?;
+    assert_eq!(response.header(LOCATION)?, "/posts?page=2");
+
+    let response = server.perform(Request::get("/back").header(REFERER, "/posts"))?;
+    assert_eq!(response.header(LOCATION)?, "/posts");
+
+    // cross-origin
+    let response = server.perform(
+        Request::get("/back")
+            .header(HOST, "example.com")
+            .header(REFERER, "http://evil.example.org/"),
+    )?;
+    assert_eq!(response.header(LOCATION)?, "/home");
+
+    Ok(())
+}
+
+#[test]
+fn attachment_filename() -> tsukuyomi_server::Result<()> {
+    use http::header::CONTENT_DISPOSITION;
+
+    let app = App::create(chain![
+        path!("/spaces") //
+            .to(endpoint::call(|| output::attachment(
+                "data",
+                "my report.txt"
+            ))),
+        path!("/quotes") //
+            .to(endpoint::call(|| output::attachment(
+                "data",
+                "say \"hi\".json"
+            ))),
+        path!("/japanese") //
+            .to(endpoint::call(|| output::attachment(
+                Bytes::from_static(b"data"),
+                "報告書 2019.csv"
+            ))),
+        path!("/unknown") //
+            .to(endpoint::call(|| output::attachment(
+                vec![0u8; 4],
+                "data.unknownext"
+            ))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/spaces")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"my report.txt\""
+    );
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/plain");
+    assert_eq!(response.body().to_utf8()?, "data");
+
+    let response = server.perform("/quotes")?;
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"say _hi_.json\"; filename*=UTF-8''say%20%22hi%22.json"
+    );
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+
+    let response = server.perform("/japanese")?;
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"___ 2019.csv\"; \
+         filename*=UTF-8''%E5%A0%B1%E5%91%8A%E6%9B%B8%202019.csv"
+    );
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/csv");
+
+    let response = server.perform("/unknown")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/octet-stream");
+
+    Ok(())
+}
+
+#[test]
+fn attachment_with_validators_and_range() -> tsukuyomi_server::Result<()> {
+    use http::header::{CONTENT_DISPOSITION, CONTENT_RANGE, IF_NONE_MATCH, RANGE};
+
+    const DATA: &[u8] = b"The quick brown fox jumps over the lazy dog";
+
+    let app = App::create(chain![
+        path!("/etag") //
+            .to(endpoint::call(|| output::attachment(
+                output::with_etag("data", output::ETag::strong("v1")),
+                "data.txt",
+            ))),
+        path!("/range") //
+            .to(endpoint::call(|| output::attachment(
+                output::resumable("v1", DATA.len() as u64, |range| {
+                    let chunk = &DATA[range.start as usize..range.end as usize];
+                    futures01::stream::once::<_, std::io::Error>(Ok(chunk))
+                }),
+                "fox.txt",
+            ))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/etag")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("etag")?, "\"v1\"");
+    assert!(response.headers().contains_key(CONTENT_DISPOSITION));
+
+    let response = server.perform(Request::get("/etag").header(IF_NONE_MATCH, "\"v1\""))?;
+    assert_eq!(response.status(), 304);
+    assert!(!response.headers().contains_key(CONTENT_DISPOSITION));
+
+    let response = server.perform(Request::get("/range").header(RANGE, "bytes=4-8"))?;
+    assert_eq!(response.status(), 206);
+    assert_eq!(response.header(CONTENT_RANGE)?, "bytes 4-8/43");
+    assert_eq!(
+        response.header(CONTENT_DISPOSITION)?,
+        "attachment; filename=\"fox.txt\""
+    );
+    assert_eq!(response.body().to_utf8()?, "quick");
+
+    Ok(())
+}
+
+#[test]
+fn tuple_with_status() -> tsukuyomi_server::Result<()> {
+    use {http::StatusCode, serde::Serialize};
+
+    #[derive(Debug, Serialize, IntoResponse)]
+    #[response(preset = "tsukuyomi::output::preset::Json")]
+    struct Post {
+        id: u32,
+    }
+
+    let app = App::create(chain![
+        path!("/text") //
+            .to(endpoint::call(|| (StatusCode::ACCEPTED, "accepted"))),
+        path!("/post") //
+            .to(endpoint::call(|| (StatusCode::CREATED, Post { id: 42 }))),
+        path!("/missing") //
+            .to(endpoint::call(|| (StatusCode::CREATED, None::<&str>))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/text")?;
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/plain; charset=utf-8");
+    assert_eq!(response.body().to_utf8()?, "accepted");
+
+    let response = server.perform("/post")?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"{"id":42}"#);
+
+    // the error from the inner response is not overwritten.
+    let response = server.perform("/missing")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn tuple_with_status_and_headers() -> tsukuyomi_server::Result<()> {
+    use {
+        http::{
+            header::{HeaderMap, LOCATION, SET_COOKIE},
+            StatusCode,
+        },
+        serde::Serialize,
+    };
+
+    #[derive(Debug, Serialize, IntoResponse)]
+    #[response(preset = "tsukuyomi::output::preset::Json")]
+    struct Post {
+        id: u32,
+    }
+
+    let app = App::create(chain![
+        path!("/created") //
+            .to(endpoint::call(|| {
+                let mut headers = HeaderMap::new();
+                headers.insert(LOCATION, HeaderValue::from_static("/posts/42"));
+                (StatusCode::CREATED, headers, Post { id: 42 })
+            })),
+        path!("/content-type") //
+            .to(endpoint::call(|| {
+                let mut headers = HeaderMap::new();
+                headers.insert(
+                    CONTENT_TYPE,
+                    HeaderValue::from_static("application/vnd.api+json"),
+                );
+                (StatusCode::OK, headers, Post { id: 1 })
+            })),
+        path!("/cookies") //
+            .to(endpoint::call(|| {
+                let mut inner = Response::new("cookies");
+                inner
+                    .headers_mut()
+                    .insert(SET_COOKIE, HeaderValue::from_static("a=1"));
+                inner
+                    .headers_mut()
+                    .insert(CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+
+                let mut headers = HeaderMap::new();
+                headers.append(SET_COOKIE, HeaderValue::from_static("b=2"));
+                headers.append(CACHE_CONTROL, HeaderValue::from_static("private"));
+                headers.append(CACHE_CONTROL, HeaderValue::from_static("max-age=60"));
+                (StatusCode::OK, headers, inner)
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/created")?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.header(LOCATION)?, "/posts/42");
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"{"id":42}"#);
+
+    // the header fields in the map take precedence over the ones from the preset.
+    let response = server.perform("/content-type")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/vnd.api+json");
+    assert_eq!(response.headers().get_all(CONTENT_TYPE).iter().count(), 1);
+
+    // `Set-Cookie` is appended, and the others are replaced.
+    let response = server.perform("/cookies")?;
+    let cookies: Vec<_> = response.headers().get_all(SET_COOKIE).iter().collect();
+    assert_eq!(cookies, vec!["a=1", "b=2"]);
+    let cache_control: Vec<_> = response.headers().get_all(CACHE_CONTROL).iter().collect();
+    assert_eq!(cache_control, vec!["private", "max-age=60"]);
+
+    Ok(())
+}
+
+#[test]
+fn result_with_response_as_error() -> tsukuyomi_server::Result<()> {
+    use http::StatusCode;
+
+    let app = App::create(chain![
+        path!("/response/:ok") //
+            .to(endpoint::call(|ok: bool| if ok {
+                Ok("ok")
+            } else {
+                Err((StatusCode::BAD_REQUEST, "bad request"))
+            })),
+        path!("/status") //
+            .to(endpoint::call(|| Err::<&str, _>(StatusCode::CONFLICT))),
+        path!("/error") //
+            .to(endpoint::call(|| {
+                Err::<&str, _>(tsukuyomi::error::custom(StatusCode::GONE, "gone"))
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/response/true")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "ok");
+
+    let response = server.perform("/response/false")?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/plain; charset=utf-8");
+    assert_eq!(response.body().to_utf8()?, "bad request");
+
+    let response = server.perform("/status")?;
+    assert_eq!(response.status(), 409);
+
+    let response = server.perform("/error")?;
+    assert_eq!(response.status(), 410);
+    assert_eq!(response.body().to_utf8()?, "gone");
+
+    Ok(())
+}
+
+#[test]
+fn result_with_json_as_error() -> tsukuyomi_server::Result<()> {
+    use http::StatusCode;
+
+    let app = App::create(chain![
+        path!("/json/:ok") //
+            .to(endpoint::call(|ok: bool| if ok {
+                Ok("ok")
+            } else {
+                Err(output::json(vec!["invalid name"]))
+            })),
+        path!("/status") //
+            .to(endpoint::call(|| {
+                Err::<&str, _>((
+                    StatusCode::UNPROCESSABLE_ENTITY,
+                    output::json(vec!["invalid name"]),
+                ))
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/json/true")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "ok");
+
+    let response = server.perform("/json/false")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"["invalid name"]"#);
+
+    let response = server.perform("/status")?;
+    assert_eq!(response.status(), 422);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    assert_eq!(response.body().to_utf8()?, r#"["invalid name"]"#);
+
+    Ok(())
+}
+
+#[test]
+fn either_responders() -> tsukuyomi_server::Result<()> {
+    use {
+        http::StatusCode,
+        tsukuyomi::util::{Either, Either3, Either4},
+    };
+
+    let app = App::create(chain![
+        path!("/either3/:n") //
+            .to(endpoint::call(|n: u32| match n {
+                0 => Either3::A("zero"),
+                1 => Either3::B(output::html("<p>one</p>")),
+                _ => Either3::C(Err::<(), _>(StatusCode::NOT_FOUND)),
+            })),
+        path!("/either4/:n") //
+            .to(endpoint::call(|n: u32| match n {
+                0 => Either4::A("zero"),
+                1 => Either4::B(output::json(vec![1])),
+                2 => Either4::C((StatusCode::ACCEPTED, "two")),
+                _ => Either4::D(Err::<(), _>(StatusCode::CONFLICT)),
+            })),
+        path!("/nested") //
+            .to(endpoint::call(|| {
+                Either::Right::<&str, _>(Either::Left::<_, &str>(None::<&str>))
+            })),
+        path!("/status/:n") //
+            .to(endpoint::call(|n: u32| {
+                let inner = match n {
+                    0 => Either3::A("zero"),
+                    1 => Either3::B(output::html("<p>one</p>")),
+                    _ => Either3::C(output::json(vec![2])),
+                };
+                (StatusCode::CREATED, inner)
+            })),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/either3/0")?;
+    assert_eq!(response.body().to_utf8()?, "zero");
+    let response = server.perform("/either3/1")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+    let response = server.perform("/either3/2")?;
+    assert_eq!(response.status(), 404);
+
+    let response = server.perform("/either4/1")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    let response = server.perform("/either4/2")?;
+    assert_eq!(response.status(), 202);
+    assert_eq!(response.body().to_utf8()?, "two");
+    let response = server.perform("/either4/3")?;
+    assert_eq!(response.status(), 409);
+
+    let response = server.perform("/status/2")?;
+    assert_eq!(response.status(), 201);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+
+    // the status of the error in the nested responders is preserved.
+    let response = server.perform("/nested")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn set_default_headers() -> tsukuyomi_server::Result<()> {
+    use {
+        http::{
+            header::{
+                LINK, SERVER, STRICT_TRANSPORT_SECURITY, X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+            },
+            StatusCode,
+        },
+        std::time::Duration,
+        tsukuyomi::output::headers::{FrameOptions, SetHeaders},
+    };
+
+    let app = App::create(chain![
+        mount("/secure")
+            .with(chain![
+                path!("/") //
+                    .to(endpoint::get().reply("secure")),
+                path!("/framed") //
+                    .to(endpoint::get().call(|| {
+                        Response::builder()
+                            .header(X_FRAME_OPTIONS, "SAMEORIGIN")
+                            .body("framed")
+                            .unwrap()
+                    })),
+                path!("/forbidden") //
+                    .to(endpoint::get().call(|| Err::<&str, _>(StatusCode::FORBIDDEN))),
+                mount("/inner")
+                    .with(path!("/").to(endpoint::get().reply("inner")))
+                    .modify(SetHeaders::new().insert(SERVER, HeaderValue::from_static("inner"))),
+            ])
+            .modify(
+                SetHeaders::new()
+                    .hsts(Duration::from_secs(3600))
+                    .nosniff()
+                    .frame_options(FrameOptions::Deny)
+                    .insert(SERVER, HeaderValue::from_static("tsukuyomi"))
+                    .append(LINK, HeaderValue::from_static("</a.css>; rel=preload"))
+                    .append(LINK, HeaderValue::from_static("</b.js>; rel=preload")),
+            ),
+        path!("/plain") //
+            .to(endpoint::get().reply("plain")),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/secure")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(STRICT_TRANSPORT_SECURITY)?, "max-age=3600");
+    assert_eq!(response.header(X_CONTENT_TYPE_OPTIONS)?, "nosniff");
+    assert_eq!(response.header(X_FRAME_OPTIONS)?, "DENY");
+    assert_eq!(response.header(SERVER)?, "tsukuyomi");
+    assert_eq!(response.headers().get_all(LINK).iter().count(), 2);
+
+    // the values set by the handler take precedence.
+    let response = server.perform("/secure/framed")?;
+    assert_eq!(response.header(X_FRAME_OPTIONS)?, "SAMEORIGIN");
+    assert_eq!(
+        response.headers().get_all(X_FRAME_OPTIONS).iter().count(),
+        1
+    );
+    assert_eq!(response.header(X_CONTENT_TYPE_OPTIONS)?, "nosniff");
+
+    // the error responses within the scope also receive the headers.
+    let response = server.perform("/secure/forbidden")?;
+    assert_eq!(response.status(), 403);
+    assert_eq!(response.header(X_FRAME_OPTIONS)?, "DENY");
+    assert_eq!(response.header(STRICT_TRANSPORT_SECURITY)?, "max-age=3600");
+
+    // the inner scope overrides the defaults with the same name.
+    let response = server.perform("/secure/inner")?;
+    assert_eq!(response.header(SERVER)?, "inner");
+    assert_eq!(response.header(X_FRAME_OPTIONS)?, "DENY");
+
+    let response = server.perform("/plain")?;
+    assert!(!response.headers().contains_key(X_FRAME_OPTIONS));
+    assert!(!response.headers().contains_key(SERVER));
+
+    Ok(())
+}
+
+#[test]
+fn html_builder() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::output::html;
+
+    assert_eq!(html::escape("plain text"), "plain text");
+    assert_eq!(
+        html::escape(r#"<a href="x">Tom & 'Jerry'</a>"#),
+        "&lt;a href=&quot;x&quot;&gt;Tom &amp; &#x27;Jerry&#x27;&lt;/a&gt;"
+    );
+
+    let app = App::create(chain![
+        path!("/document/:name") //
+            .to(endpoint::get().call(|name: String| {
+                html::document()
+                    .lang("en")
+                    .title("<Greeting>")
+                    .head(html::raw("<meta name=\"robots\" content=\"noindex\">"))
+                    .body(html::Markup::new().element("p", html::raw("Hello, ").text(&name)))
+            })),
+        path!("/fragment") //
+            .to(endpoint::get().call(|| html::text("1 < 2").raw("<br>"))),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/document/%3Cscript%3E")?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+    let body = response.body().to_utf8()?;
+    assert_eq!(
+        body,
+        "<!DOCTYPE html>\n\
+         <html lang=\"en\"><head><meta charset=\"utf-8\">\
+         <title>&lt;Greeting&gt;</title>\
+         <meta name=\"robots\" content=\"noindex\">\
+         </head><body><p>Hello, &lt;script&gt;</p></body></html>"
+    );
+    assert_eq!(
+        response.header(CONTENT_LENGTH)?,
+        body.len().to_string().as_str()
+    );
+
+    let response = server.perform("/fragment")?;
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+    assert_eq!(response.body().to_utf8()?, "1 &lt; 2<br>");
+
+    Ok(())
+}
@@ -0,0 +1,237 @@
+use {
+    futures01::Future,
+    http::{Request, StatusCode},
+    std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    tsukuyomi::{
+        app::{Layer, Outcome},
+        config::prelude::*, //
+        guard::Decision,
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn app(called: Arc<AtomicUsize>) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/public") //
+            .to(endpoint::get().call({
+                let called = called.clone();
+                move || {
+                    called.fetch_add(1, Ordering::SeqCst);
+                    "public"
+                }
+            }))
+            .preflight(),
+        path!("/admin") //
+            .to(endpoint::get().call({
+                let called = called.clone();
+                move || {
+                    called.fetch_add(1, Ordering::SeqCst);
+                    "admin"
+                }
+            }))
+            .preflight()
+            .modify(tsukuyomi::guard::client_cert(|_| Decision::Allow).label("mtls")),
+        path!("/unmarked") //
+            .to(endpoint::get().call(move || {
+                called.fetch_add(1, Ordering::SeqCst);
+                "unmarked"
+            })),
+        preflight_endpoint("/_preflight"),
+    ])
+}
+
+#[test]
+fn authorize() -> tsukuyomi::app::Result<()> {
+    let called = Arc::new(AtomicUsize::new(0));
+    let app = app(called.clone())?;
+
+    let authorize = |method: &str, path: &str| {
+        let request = Request::builder()
+            .method(method)
+            .uri(path)
+            .body(())
+            .unwrap();
+        app.authorize(request).wait().unwrap()
+    };
+
+    assert_eq!(authorize("GET", "/public"), Outcome::Allowed);
+
+    // the client certificate is missing.
+    match authorize("GET", "/admin") {
+        Outcome::Denied {
+            layer: Layer::Modifier(name),
+            status,
+        } => {
+            assert_eq!(name, "mtls");
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+        outcome => panic!("unexpected outcome: {:?}", outcome),
+    }
+
+    assert_eq!(
+        authorize("GET", "/unknown"),
+        Outcome::Denied {
+            layer: Layer::Routing,
+            status: StatusCode::NOT_FOUND,
+        }
+    );
+    assert_eq!(
+        authorize("DELETE", "/public"),
+        Outcome::Denied {
+            layer: Layer::Routing,
+            status: StatusCode::METHOD_NOT_ALLOWED,
+        }
+    );
+
+    // the handlers in the routes not marked by `Route::preflight` are never executed.
+    assert_eq!(authorize("GET", "/unmarked"), Outcome::NotEvaluated);
+
+    assert_eq!(called.load(Ordering::SeqCst), 0);
+    Ok(())
+}
+
+#[test]
+fn marked_route_rejects_non_participating_modifier() {
+    struct Logging;
+    impl<H> tsukuyomi::handler::ModifyHandler<H> for Logging
+    where
+        H: tsukuyomi::handler::Handler,
+    {
+        type Output = H::Output;
+        type Handler = H;
+
+        fn modify(&self, inner: H) -> Self::Handler {
+            inner
+        }
+    }
+
+    let result = App::create(
+        path!("/") //
+            .to(endpoint::get().reply("index"))
+            .preflight()
+            .modify(Logging),
+    );
+    assert!(result.is_err());
+
+    let result = App::create(
+        path!("/") //
+            .to(endpoint::get().reply("index"))
+            .preflight()
+            .modify(tsukuyomi::guard::participate(Logging)),
+    );
+    assert!(result.is_ok());
+}
+
+#[test]
+fn batch_endpoint() -> tsukuyomi_server::Result<()> {
+    let called = Arc::new(AtomicUsize::new(0));
+    let mut server = tsukuyomi_server::test::server(app(called.clone())?)?;
+
+    let response = server.perform(Request::post("/_preflight").body(
+        r#"[
+                {"method": "GET", "path": "/public"},
+                {"method": "GET", "path": "/admin"},
+                {"method": "GET", "path": "/unknown"},
+                {"method": "GET", "path": "/unmarked"}
+            ]"#,
+    ))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.header("content-type")?, "application/json");
+
+    let results: serde_json::Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    assert_eq!(results[0]["allowed"], true);
+    assert_eq!(results[1]["allowed"], false);
+    assert_eq!(results[1]["status"], 403);
+    assert_eq!(results[1]["layer"], "mtls");
+    assert_eq!(results[2]["allowed"], false);
+    assert_eq!(results[2]["status"], 404);
+    assert_eq!(results[2]["layer"], "routing");
+    assert_eq!(results[3]["allowed"], false);
+    assert_eq!(results[3]["evaluated"], false);
+
+    // the requests with the other methods are routed as usual.
+    let response = server.perform("/_preflight")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    assert_eq!(called.load(Ordering::SeqCst), 0);
+    Ok(())
+}
+
+#[test]
+fn batch_endpoint_rewrites_paths() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::config::{MountPoint, PathNormalization};
+
+    let app = App::create(chain![
+        PathNormalization::new(),
+        MountPoint::new("/app"),
+        path!("/public") //
+            .to(endpoint::get().reply("public"))
+            .preflight(),
+        path!("/_preflight") //
+            .to(endpoint::get().reply("preflight page")),
+        preflight_endpoint("/_preflight"),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/app/_preflight").body(
+        r#"[
+                {"method": "GET", "path": "/app/public"},
+                {"method": "GET", "path": "/app//public"},
+                {"method": "GET", "path": "/app/./public"},
+                {"method": "GET", "path": "/public"}
+            ]"#,
+    ))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let results: serde_json::Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    assert_eq!(results[0]["allowed"], true);
+    assert_eq!(results[1]["allowed"], true);
+    assert_eq!(results[2]["allowed"], true);
+    assert_eq!(results[3]["allowed"], false);
+    assert_eq!(results[3]["status"], 404);
+    assert_eq!(results[3]["layer"], "routing");
+
+    // the route registered with the same path still serves the other methods.
+    let response = server.perform("/app/_preflight")?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "preflight page");
+
+    Ok(())
+}
+
+#[test]
+fn batch_endpoint_limits() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::config::PreflightLimits;
+
+    let app = App::create(chain![
+        path!("/public") //
+            .to(endpoint::get().reply("public"))
+            .preflight(),
+        preflight_endpoint("/_preflight")
+            .limits(PreflightLimits::new().max_body_size(128).max_items(2)),
+    ])?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let item = r#"{"method": "GET", "path": "/public"}"#;
+    let response = server.perform(Request::post("/_preflight").body(format!("[{}]", item)))?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let response = server.perform(
+        Request::post("/_preflight")
+            .body(format!("[{0},{0},{0}]", r#"{"method":"GET","path":"/"}"#)),
+    )?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    let long_path = format!("/{}", "x".repeat(128));
+    let response = server.perform(
+        Request::post("/_preflight")
+            .body(format!(r#"[{{"method": "GET", "path": "{}"}}]"#, long_path)),
+    )?;
+    assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+    Ok(())
+}
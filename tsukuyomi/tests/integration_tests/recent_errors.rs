@@ -0,0 +1,170 @@
+use {
+    http::{
+        header::{ACCEPT, CONTENT_TYPE},
+        Request, StatusCode,
+    },
+    std::sync::Arc,
+    tsukuyomi::{
+        config::prelude::*, //
+        contrib::recent_errors::RecentErrors,
+        guard::{Decision, PeerCertificates},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn app(recent: &RecentErrors) -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        observe_errors({
+            let recent = recent.clone();
+            move |event| recent.record(event)
+        }),
+        mount("/api").with(chain![
+            path!("/fail/:n") //
+                .to(endpoint::call(|n: u32| -> tsukuyomi::Result<String> {
+                    Err(tsukuyomi::error::internal_server_error(format!(
+                        "failure #{}",
+                        n
+                    )))
+                })),
+            path!("/chain") //
+                .to(endpoint::call(|| -> tsukuyomi::Result<String> {
+                    let cause = failure::err_msg("disk is full");
+                    Err(failure::Error::from(cause.context("failed to save")).into())
+                })),
+            path!("/missing") //
+                .to(endpoint::call(|| -> tsukuyomi::Result<String> {
+                    Err(tsukuyomi::error::not_found("no such item"))
+                })),
+        ]),
+        mount("/admin/recent-errors").with(recent.admin(tsukuyomi::guard::client_cert(|chain| {
+            if chain[0] == b"admin" {
+                Decision::Allow
+            } else {
+                Decision::deny("not an operator")
+            }
+        }))),
+    ])
+}
+
+#[test]
+fn records_server_errors() -> tsukuyomi_server::Result<()> {
+    let recent = RecentErrors::new(3).redact("token");
+    let mut server = tsukuyomi_server::test::server(app(&recent)?)?;
+
+    let response = server
+        .perform(Request::get("/api/fail/1?token=secret&page=2").header("x-request-id", "req-1"))?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(response.body().to_utf8()?, "failure #1");
+
+    // the client errors are not recorded.
+    let response = server.perform("/api/missing")?;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let response = server.perform("/api/chain")?;
+    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+
+    let entries = recent.entries();
+    assert_eq!(entries.len(), 2);
+    assert_eq!(entries[0].method, "GET");
+    assert_eq!(entries[0].uri, "/api/fail/1?token=%5BREDACTED%5D&page=2");
+    assert_eq!(entries[0].status, 500);
+    assert_eq!(
+        entries[0].request_id.as_ref().map(AsRef::as_ref),
+        Some("req-1")
+    );
+    assert_eq!(entries[0].chain, vec!["failure #1"]);
+    assert_eq!(entries[1].uri, "/api/chain");
+    assert_eq!(entries[1].request_id, None);
+    assert_eq!(entries[1].chain, vec!["failed to save", "disk is full"]);
+
+    Ok(())
+}
+
+#[test]
+fn buffer_is_bounded() -> tsukuyomi_server::Result<()> {
+    let recent = RecentErrors::new(3);
+    let mut server = tsukuyomi_server::test::server(app(&recent)?)?;
+
+    for n in 0..10 {
+        let response = server.perform(&*format!("/api/fail/{}", n))?;
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    assert_eq!(recent.total(), 10);
+    let entries = recent.entries();
+    assert_eq!(
+        entries
+            .iter()
+            .map(|entry| entry.chain[0].as_str())
+            .collect::<Vec<_>>(),
+        vec!["failure #7", "failure #8", "failure #9"]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_records() {
+    let recent = RecentErrors::new(16);
+    let app = app(&recent).unwrap();
+
+    let threads: Vec<_> = (0..4)
+        .map(|i| {
+            let app = app.clone();
+            std::thread::spawn(move || {
+                let mut server = tsukuyomi_server::test::server(app).unwrap();
+                for n in 0..25 {
+                    let response = server
+                        .perform(&*format!("/api/fail/{}", i * 100 + n))
+                        .unwrap();
+                    assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(recent.total(), 100);
+    let entries = recent.entries();
+    assert_eq!(entries.len(), 16);
+    let seqs: Vec<_> = entries.iter().map(|entry| entry.seq).collect();
+    assert_eq!(seqs, (84..100).collect::<Vec<_>>());
+}
+
+#[test]
+fn admin_endpoint() -> tsukuyomi_server::Result<()> {
+    let recent = RecentErrors::new(3);
+    let mut server = tsukuyomi_server::test::server(app(&recent)?)?;
+
+    let _ = server.perform(Request::get("/api/fail/1").header("x-request-id", "<script>"))?;
+    let admin = Arc::new(PeerCertificates::new(vec![b"admin".to_vec()]));
+
+    let response = server.perform("/admin/recent-errors")?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    let response = server
+        .perform(Request::get("/admin/recent-errors").extension(PeerCertificates::clone(&admin)))?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.header(CONTENT_TYPE)?, "application/json");
+    let entries: serde_json::Value = serde_json::from_str(&response.body().to_utf8()?)?;
+    assert_eq!(entries[0]["uri"], "/api/fail/1");
+    assert_eq!(entries[0]["request_id"], "<script>");
+    assert_eq!(entries[0]["chain"][0], "failure #1");
+
+    let response = server.perform(
+        Request::get("/admin/recent-errors")
+            .header(ACCEPT, "text/html")
+            .extension(PeerCertificates::clone(&admin)),
+    )?;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(response.header(CONTENT_TYPE)?, "text/html; charset=utf-8");
+    let body = response.body().to_utf8()?;
+    assert!(body.contains("failure #1"), "{}", body);
+    assert!(body.contains("&lt;script&gt;"), "{}", body);
+    assert!(!body.contains("<script>"), "{}", body);
+
+    Ok(())
+}
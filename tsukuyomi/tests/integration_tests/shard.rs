@@ -0,0 +1,107 @@
+use tsukuyomi::{
+    config::prelude::*, //
+    extractor,
+    App,
+};
+
+fn new_app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/").to(endpoint::reply("new root")),
+        path!("/users").to(endpoint::reply("new users")),
+        path!("/uri").to(endpoint::get()
+            .extract(extractor::uri())
+            .call(|uri: http::Uri| uri.to_string())),
+    ])
+}
+
+fn old_app() -> tsukuyomi::app::Result<App> {
+    App::create(chain![
+        path!("/users").to(endpoint::reply("old users")),
+        path!("/legacy/users").to(endpoint::reply("legacy users")),
+    ])
+}
+
+#[test]
+fn dispatch_by_prefix() -> tsukuyomi_server::Result<()> {
+    let service = tsukuyomi_server::shard()
+        .mount_stripped("/v2", new_app()?)
+        .mount("/legacy", old_app()?)
+        .fallback(old_app()?);
+    let mut server = tsukuyomi_server::test::server(service)?;
+
+    let response = server.perform("/v2/users")?;
+    assert_eq!(response.body().to_utf8()?, "new users");
+
+    let response = server.perform("/v2")?;
+    assert_eq!(response.body().to_utf8()?, "new root");
+
+    let response = server.perform("/v2/uri?page=2")?;
+    assert_eq!(response.body().to_utf8()?, "/uri?page=2");
+
+    let response = server.perform("/legacy/users")?;
+    assert_eq!(response.body().to_utf8()?, "legacy users");
+
+    let response = server.perform("/users")?;
+    assert_eq!(response.body().to_utf8()?, "old users");
+
+    // the prefix matches only at the segment boundary.
+    let response = server.perform("/v20/users")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn longest_prefix_wins() -> tsukuyomi_server::Result<()> {
+    let service = tsukuyomi_server::shard()
+        .mount_stripped("/api/v2", new_app()?)
+        .mount_stripped("/api", old_app()?);
+    let mut server = tsukuyomi_server::test::server(service)?;
+
+    let response = server.perform("/api/v2/users")?;
+    assert_eq!(response.body().to_utf8()?, "new users");
+
+    let response = server.perform("/api/users")?;
+    assert_eq!(response.body().to_utf8()?, "old users");
+
+    // without fallback
+    let response = server.perform("/users")?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn keep_alive_across_shards() -> tsukuyomi_server::Result<()> {
+    let service = tsukuyomi_server::shard()
+        .mount_stripped("/v2", new_app()?)
+        .fallback(old_app()?);
+    let mut server = tsukuyomi_server::test::server(service)?;
+
+    let output = server.perform_raw(
+        &b"GET /v2/users HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           \r\n\
+           GET /users HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           \r\n\
+           GET /v2 HTTP/1.1\r\n\
+           Host: localhost\r\n\
+           \r\n"[..],
+    )?;
+    assert!(output.connection_error().is_none());
+    let bodies = output
+        .responses()
+        .iter()
+        .map(|response| response.body().to_utf8().map(|body| body.into_owned()))
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(bodies, vec!["new users", "old users", "new root"]);
+
+    Ok(())
+}
+
+#[test]
+#[should_panic(expected = "already been mounted")]
+fn duplicate_prefix() {
+    let _ = tsukuyomi_server::shard().mount("/v2", ()).mount("/v2/", ());
+}
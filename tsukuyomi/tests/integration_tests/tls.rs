@@ -0,0 +1,314 @@
+use {
+    http::{Request, Version},
+    tsukuyomi::{
+        config::prelude::*, //
+        extractor,
+        extractor::{connection::ConnectionInfo, tls::TlsInfo},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+#[test]
+fn plaintext_listener() -> tsukuyomi_server::Result<()> {
+    let app =
+        App::create(
+            path!("/") //
+                .to(endpoint::get().extract(extractor::tls::info()).call(
+                    |info: Option<TlsInfo>| {
+                        if info.is_some() {
+                            "tls"
+                        } else {
+                            "plaintext"
+                        }
+                    },
+                )),
+        )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "plaintext");
+    assert_eq!(
+        response.header("content-type")?,
+        "text/plain; charset=utf-8"
+    );
+
+    Ok(())
+}
+
+#[test]
+fn connection_info() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().extract(extractor::connection_info()).call(
+                |info: ConnectionInfo| {
+                    format!(
+                        "{:?},{},{:?},{:?}",
+                        info.version(),
+                        info.is_tls(),
+                        info.alpn_protocol(),
+                        info.server_name()
+                    )
+                },
+            )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.body().to_utf8()?, "HTTP/1.1,false,None,None");
+
+    let response = server.perform(Request::get("/").version(Version::HTTP_2))?;
+    assert_eq!(response.body().to_utf8()?, "HTTP/2.0,false,None,None");
+
+    let info = TlsInfo::new(
+        "TLSv1.3",
+        "TLS13_AES_128_GCM_SHA256",
+        Some("localhost".into()),
+    )
+    .with_alpn_protocol("h2");
+    let response = server.perform({
+        let mut request = Request::get("/");
+        request.version(Version::HTTP_2).extension(info);
+        request
+    })?;
+    assert_eq!(
+        response.body().to_utf8()?,
+        "HTTP/2.0,true,Some(\"h2\"),Some(\"localhost\")"
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "use-rustls")]
+#[test]
+fn captured_from_rustls_session() -> tsukuyomi_server::Result<()> {
+    use {
+        futures01::{Future, Stream},
+        http::StatusCode,
+        std::sync::{Arc, Mutex},
+        tokio::net::{TcpListener, TcpStream},
+        tokio_rustls::{
+            rustls::{
+                Certificate, CipherSuite, ClientConfig, NoClientAuth, PrivateKey, ProtocolVersion,
+                ServerConfig, ALL_CIPHERSUITES,
+            },
+            webpki::DNSNameRef,
+            TlsAcceptor, TlsConnector,
+        },
+        tsukuyomi_service::{MakeService, Service},
+    };
+
+    const CA_CERT: &[u8] = include_bytes!("../fixtures/tls/ca.der");
+    const SERVER_CERT: &[u8] = include_bytes!("../fixtures/tls/server.der");
+    const SERVER_KEY: &[u8] = include_bytes!("../fixtures/tls/server-key.der");
+
+    let acceptor = {
+        let mut config = ServerConfig::new(NoClientAuth::new());
+        config.set_single_cert(
+            vec![Certificate(SERVER_CERT.to_vec())],
+            PrivateKey(SERVER_KEY.to_vec()),
+        )?;
+        TlsAcceptor::from(Arc::new(config))
+    };
+
+    let connector = {
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add(&Certificate(CA_CERT.to_vec()))
+            .expect("invalid CA certificate");
+        config.versions = vec![ProtocolVersion::TLSv1_2];
+        config.ciphersuites = ALL_CIPHERSUITES
+            .iter()
+            .cloned()
+            .filter(|s| s.suite == CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256)
+            .collect();
+        TlsConnector::from(Arc::new(config))
+    };
+
+    let listener = TcpListener::bind(&"127.0.0.1:0".parse()?)?;
+    let addr = listener.local_addr()?;
+    let accept = listener
+        .incoming()
+        .into_future()
+        .map_err(|(err, _)| err)
+        .and_then(move |(stream, _)| acceptor.accept(stream.expect("no incoming connection")));
+    let connect = TcpStream::connect(&addr).and_then(move |stream| {
+        let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+        connector.connect(domain, stream)
+    });
+    let (stream, _client) = tokio::runtime::current_thread::block_on_all(accept.join(connect))?;
+
+    let captured = Arc::new(Mutex::new(None));
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get().extract(extractor::tls::info()).call({
+                let captured = captured.clone();
+                move |info: Option<TlsInfo>| {
+                    *captured.lock().unwrap() = info;
+                    "captured"
+                }
+            })),
+    )?;
+
+    let make_service = app.with_modify_service(extractor::tls::with_tls_info());
+    let mut service = MakeService::<_, Request<hyper::Body>>::make_service(&make_service, &stream)
+        .wait()
+        .unwrap();
+    let response = service
+        .call(Request::get("/").body(hyper::Body::empty())?)
+        .wait()?;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let info = captured.lock().unwrap().take().expect("missing TLS info");
+    assert_eq!(info.protocol_version(), "TLSv1.2");
+    assert_eq!(info.cipher_suite(), "TLS_ECDHE_RSA_WITH_AES_128_GCM_SHA256");
+    assert_eq!(info.server_name(), Some("localhost"));
+    assert_eq!(info.alpn_protocol(), None);
+
+    Ok(())
+}
+
+#[test]
+fn peer_certificates_absent_on_plaintext() -> tsukuyomi_server::Result<()> {
+    use tsukuyomi::guard::PeerCertificates;
+
+    let app = App::create(
+        path!("/") //
+            .to(endpoint::get()
+                .extract(extractor::tls::peer_certificates())
+                .call(
+                    |certs: Option<PeerCertificates>| {
+                        if certs.is_some() {
+                            "present"
+                        } else {
+                            "absent"
+                        }
+                    },
+                )),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), http::StatusCode::OK);
+    assert_eq!(response.body().to_utf8()?, "absent");
+
+    let response = server.perform(Request::get("/").extension(PeerCertificates::new(vec![])))?;
+    assert_eq!(response.body().to_utf8()?, "absent");
+
+    Ok(())
+}
+
+#[cfg(feature = "x509")]
+#[test]
+fn peer_certificates_x509_helpers() {
+    use tsukuyomi::guard::x509;
+
+    const CLIENT_CERT: &[u8] = include_bytes!("../fixtures/client_cert/self-signed.der");
+
+    let chain = vec![CLIENT_CERT.to_vec()];
+    assert_eq!(
+        x509::subject(&chain).as_ref().map(String::as_str),
+        Some("CN=client.example, O=Tsukuyomi Test")
+    );
+    assert_eq!(x509::dns_names(&chain), vec!["client.example".to_owned()]);
+
+    assert_eq!(x509::subject(&[]), None);
+    assert!(x509::dns_names(&[b"garbage".to_vec()]).is_empty());
+}
+
+#[cfg(feature = "use-rustls")]
+#[test]
+fn peer_certificates_from_rustls_session() -> tsukuyomi_server::Result<()> {
+    use {
+        futures01::Future,
+        http::StatusCode,
+        std::sync::Arc,
+        tokio::net::TcpStream,
+        tokio_rustls::{
+            rustls::{
+                AllowAnyAuthenticatedClient, Certificate, ClientConfig, PrivateKey, RootCertStore,
+                ServerConfig,
+            },
+            webpki::DNSNameRef,
+            TlsAcceptor, TlsConnector,
+        },
+        tsukuyomi::guard::PeerCertificates,
+        tsukuyomi_server::Server,
+    };
+
+    const CA_CERT: &[u8] = include_bytes!("../fixtures/tls/ca.der");
+    const SERVER_CERT: &[u8] = include_bytes!("../fixtures/tls/server.der");
+    const SERVER_KEY: &[u8] = include_bytes!("../fixtures/tls/server-key.der");
+    const CLIENT_CERT: &[u8] = include_bytes!("../fixtures/client_cert/self-signed.der");
+    const CLIENT_KEY: &[u8] = include_bytes!("../fixtures/client_cert/self-signed-key.der");
+
+    let acceptor = {
+        let mut client_roots = RootCertStore::empty();
+        client_roots
+            .add(&Certificate(CLIENT_CERT.to_vec()))
+            .expect("invalid client certificate");
+        let mut config = ServerConfig::new(AllowAnyAuthenticatedClient::new(client_roots));
+        config.set_single_cert(
+            vec![Certificate(SERVER_CERT.to_vec())],
+            PrivateKey(SERVER_KEY.to_vec()),
+        )?;
+        TlsAcceptor::from(Arc::new(config))
+    };
+
+    let connector = {
+        let mut config = ClientConfig::new();
+        config
+            .root_store
+            .add(&Certificate(CA_CERT.to_vec()))
+            .expect("invalid CA certificate");
+        config.set_single_client_cert(
+            vec![Certificate(CLIENT_CERT.to_vec())],
+            PrivateKey(CLIENT_KEY.to_vec()),
+        );
+        TlsConnector::from(Arc::new(config))
+    };
+
+    // the handler authorizes the client by the identity of its certificate.
+    let app = || {
+        App::create(
+            path!("/") //
+                .to(endpoint::get()
+                    .extract(extractor::tls::peer_certificates())
+                    .call(|certs: Option<PeerCertificates>| match certs {
+                        Some(ref certs) if certs.chain()[0] == CLIENT_CERT => Ok("authorized"),
+                        _ => Err(tsukuyomi::Error::from(StatusCode::FORBIDDEN)),
+                    })),
+        )
+    };
+
+    // the certificate chain is attached by the acceptor of the server.
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    let addr = listener.local_addr()?;
+    let server = Server::new(app()?).bind(listener).acceptor(acceptor);
+    std::thread::spawn(move || server.run().unwrap());
+
+    let request = TcpStream::connect(&addr)
+        .and_then(move |stream| {
+            let domain = DNSNameRef::try_from_ascii_str("localhost").unwrap();
+            connector.connect(domain, stream)
+        })
+        .and_then(|stream| {
+            tokio::io::write_all(
+                stream,
+                "GET / HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+            )
+        })
+        .and_then(|(stream, _)| tokio::io::read_to_end(stream, vec![]));
+    let (_, response) = tokio::runtime::current_thread::block_on_all(request)?;
+    let response = String::from_utf8(response)?;
+    assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+    assert!(response.ends_with("\r\n\r\nauthorized"), "{}", response);
+
+    // the request without the client certificate is forbidden.
+    let mut server = tsukuyomi_server::test::server(app()?)?;
+    let response = server.perform("/")?;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    Ok(())
+}
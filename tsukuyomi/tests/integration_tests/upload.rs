@@ -0,0 +1,362 @@
+use {
+    http::Request,
+    hyper::Body,
+    std::{
+        io::{self, Read},
+        path::PathBuf,
+        sync::{Arc, Mutex},
+    },
+    tsukuyomi::{
+        config::prelude::*, //
+        contrib::upload::{Completed, FsStore, Uploads},
+        extractor,
+        fs::{Spool, TempUploads},
+        App,
+    },
+    tsukuyomi_server::test::ResponseExt,
+};
+
+fn store_dir(name: &str) -> PathBuf {
+    let dir =
+        std::env::temp_dir().join(format!("tsukuyomi-upload-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    dir
+}
+
+#[test]
+fn resume_after_disconnect() -> tsukuyomi_server::Result<()> {
+    let completed = Arc::new(Mutex::new(None));
+    let app = App::create(mount("/uploads").with(Uploads::new(
+        FsStore::new(store_dir("resume"))?,
+        {
+            let completed = completed.clone();
+            move |mut upload: Completed| {
+                let mut content = String::new();
+                upload.read_to_string(&mut content)?;
+                *completed.lock().unwrap() = Some(content);
+                Ok(())
+            }
+        },
+    )))?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/uploads").header("upload-length", "11"))?;
+    assert_eq!(response.status(), 201);
+    let location = response.header("location")?.to_str()?.to_owned();
+    assert!(location.starts_with("/uploads/"));
+
+    // the connection is lost after sending the first chunk.
+    let body = Body::wrap_stream(futures01::stream::iter_result(vec![
+        Ok(hyper::Chunk::from("hello")),
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "disconnected",
+        )),
+    ]));
+    let response = server.perform(
+        Request::patch(&*location)
+            .header("upload-offset", "0")
+            .body(body),
+    )?;
+    assert!(!response.status().is_success());
+    assert!(completed.lock().unwrap().is_none());
+
+    let response = server.perform(Request::head(&*location))?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.header("upload-offset")?, "5");
+    assert_eq!(response.header("upload-length")?, "11");
+
+    // a chunk which is not contiguous to the stored data is rejected.
+    let response = server.perform(
+        Request::patch(&*location)
+            .header("upload-offset", "3")
+            .body(" world"),
+    )?;
+    assert_eq!(response.status(), 409);
+
+    let response = server.perform(
+        Request::put(&*location)
+            .header("content-range", "bytes 5-10/11")
+            .body(" world"),
+    )?;
+    assert_eq!(response.status(), 204);
+    assert_eq!(response.header("upload-offset")?, "11");
+    assert_eq!(
+        completed.lock().unwrap().as_ref().map(|s| s.as_str()),
+        Some("hello world")
+    );
+
+    // the completed upload is removed from the store.
+    let response = server.perform(Request::head(&*location))?;
+    assert_eq!(response.status(), 404);
+
+    Ok(())
+}
+
+#[test]
+fn max_size() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        mount("/uploads")
+            .with(Uploads::new(FsStore::new(store_dir("max-size"))?, |_| Ok(())).max_size(4)),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(Request::post("/uploads").header("upload-length", "5"))?;
+    assert_eq!(response.status(), 413);
+
+    let response = server.perform(Request::post("/uploads").header("upload-length", "4"))?;
+    assert_eq!(response.status(), 201);
+    let location = response.header("location")?.to_str()?.to_owned();
+
+    // the body exceeding the declared length is rejected.
+    let response = server.perform(
+        Request::patch(&*location)
+            .header("upload-offset", "0")
+            .body("hello"),
+    )?;
+    assert_eq!(response.status(), 413);
+
+    Ok(())
+}
+
+#[test]
+fn concurrent_writers() -> tsukuyomi_server::Result<()> {
+    let app = App::create(
+        mount("/uploads").with(Uploads::new(FsStore::new(store_dir("concurrent"))?, |_| {
+            Ok(())
+        })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app.clone())?;
+
+    let response = server.perform(Request::post("/uploads").header("upload-length", "10"))?;
+    let location = response.header("location")?.to_str()?.to_owned();
+
+    // the first writer keeps the upload locked until the request body is closed.
+    let (mut sender, body) = Body::channel();
+    let first = std::thread::spawn({
+        let location = location.clone();
+        move || -> tsukuyomi_server::Result<_> {
+            let mut server = tsukuyomi_server::test::server(app)?;
+            let response = server.perform(
+                Request::patch(&*location)
+                    .header("upload-offset", "0")
+                    .body(body),
+            )?;
+            Ok((response.status(), response.header("upload-offset")?.clone()))
+        }
+    });
+
+    let mut locked = false;
+    for _ in 0..100 {
+        // an empty chunk does not change the progress of the upload.
+        let response = server.perform(
+            Request::patch(&*location)
+                .header("upload-offset", "0")
+                .body(""),
+        )?;
+        if response.status() == 423 {
+            locked = true;
+            break;
+        }
+        std::thread::sleep(std::time::Duration::from_millis(10));
+    }
+    assert!(locked);
+
+    sender
+        .send_data("hello".into())
+        .map_err(|_| failure::format_err!("the receiver has been dropped"))?;
+    drop(sender);
+
+    let (status, offset) = first.join().expect("the writer thread panicked")?;
+    assert_eq!(status, 204);
+    assert_eq!(offset, "5");
+
+    Ok(())
+}
+
+const SPOOL_FORM: &str = "--BOUNDARY\r\n\
+     Content-Disposition: form-data; name=\"user\"\r\n\
+     \r\n\
+     alice\r\n\
+     --BOUNDARY\r\n\
+     Content-Disposition: form-data; name=\"avatar\"; filename=\"me.png\"\r\n\
+     Content-Type: image/png\r\n\
+     \r\n\
+     \r\n--BOUNDAR\r\n\
+     --BOUNDARY--\r\n";
+
+#[derive(Debug, serde::Deserialize)]
+struct Profile {
+    user: String,
+}
+
+fn spool_files(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.count())
+        .unwrap_or(0)
+}
+
+/// Splits the body into the small chunks, so that the delimiters span them.
+fn chunked(data: &'static str, size: usize) -> Body {
+    Body::wrap_stream(futures01::stream::iter_ok::<_, io::Error>(
+        data.as_bytes()
+            .chunks(size)
+            .map(hyper::Chunk::from)
+            .collect::<Vec<_>>(),
+    ))
+}
+
+#[test]
+fn spool_persist() -> tsukuyomi_server::Result<()> {
+    let spool = store_dir("spool-persist");
+    let dest = store_dir("spool-persist-dest");
+    std::fs::create_dir_all(&dest)?;
+
+    let app = App::create(
+        path!("/avatar").to(endpoint::post()
+            .extract(extractor::body::form_with_uploads(Spool::new(&spool)))
+            .call({
+                let dest = dest.clone();
+                move |profile: Profile, mut uploads: TempUploads| -> tsukuyomi::Result<_> {
+                    let upload = uploads.take("avatar").expect("missing upload");
+                    let info = format!(
+                        "{},{},{},{}",
+                        profile.user,
+                        upload.filename().unwrap_or("-"),
+                        upload.content_type().map_or("-", |mime| mime.as_ref()),
+                        upload.len(),
+                    );
+                    upload.persist(dest.join(&profile.user))?;
+                    Ok(info)
+                }
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    for &size in &[SPOOL_FORM.len(), 7, 1] {
+        let response = server.perform(
+            Request::post("/avatar")
+                .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+                .body(chunked(SPOOL_FORM, size)),
+        )?;
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.body().to_utf8()?, "alice,me.png,image/png,11");
+        assert_eq!(
+            std::fs::read(dest.join("alice"))?,
+            b"\r\n--BOUNDAR".to_vec()
+        );
+        assert_eq!(spool_files(&spool), 0);
+    }
+
+    let _ = std::fs::remove_dir_all(&spool);
+    let _ = std::fs::remove_dir_all(&dest);
+    Ok(())
+}
+
+#[test]
+fn spool_persist_failure() -> tsukuyomi_server::Result<()> {
+    let spool = store_dir("spool-persist-failure");
+    let dest = store_dir("spool-persist-failure-dest");
+    std::fs::create_dir_all(&dest)?;
+
+    let app = App::create(
+        path!("/avatar").to(endpoint::post()
+            .extract(extractor::body::form_with_uploads(Spool::new(&spool)))
+            .call({
+                let dest = dest.clone();
+                move |_: Profile, mut uploads: TempUploads| -> tsukuyomi::Result<_> {
+                    let upload = uploads.take("avatar").expect("missing upload");
+                    // the upload is returned back when the destination is not available.
+                    let err = upload
+                        .persist(dest.join("missing").join("avatar"))
+                        .unwrap_err();
+                    assert_eq!(err.error.kind(), io::ErrorKind::NotFound);
+                    assert!(err.upload.path().exists());
+                    err.upload.persist(dest.join("avatar"))?;
+                    Ok("persisted")
+                }
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/avatar")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(SPOOL_FORM),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(
+        std::fs::read(dest.join("avatar"))?,
+        b"\r\n--BOUNDAR".to_vec()
+    );
+    assert_eq!(spool_files(&spool), 0);
+
+    let _ = std::fs::remove_dir_all(&spool);
+    let _ = std::fs::remove_dir_all(&dest);
+    Ok(())
+}
+
+#[test]
+fn spool_cleanup() -> tsukuyomi_server::Result<()> {
+    let spool = store_dir("spool-cleanup");
+
+    let app = App::create(
+        path!("/avatar").to(endpoint::post()
+            .extract(extractor::body::form_with_uploads(
+                Spool::new(&spool).max_file_size(8),
+            ))
+            .call(|_: Profile, uploads: TempUploads| {
+                // the files are removed after the handler unless persisted.
+                let upload = uploads.get("avatar").expect("missing upload");
+                assert!(upload.path().exists());
+                format!("{}", uploads.len())
+            })),
+    )?;
+    let mut server = tsukuyomi_server::test::server(app)?;
+
+    let response = server.perform(
+        Request::post("/avatar")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(SPOOL_FORM.replace("\r\n--BOUNDAR\r\n", "small\r\n")),
+    )?;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.body().to_utf8()?, "1");
+    assert_eq!(spool_files(&spool), 0);
+
+    // the file exceeding the limit.
+    let response = server.perform(
+        Request::post("/avatar")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(chunked(SPOOL_FORM, 4)),
+    )?;
+    assert_eq!(response.status(), 413);
+    assert_eq!(spool_files(&spool), 0);
+
+    // the connection is lost in the middle of the file.
+    let body = Body::wrap_stream(futures01::stream::iter_result(vec![
+        Ok(hyper::Chunk::from(&SPOOL_FORM[..SPOOL_FORM.len() - 24])),
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionReset,
+            "disconnected",
+        )),
+    ]));
+    let response = server.perform(
+        Request::post("/avatar")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(body),
+    )?;
+    assert!(!response.status().is_success());
+    assert_eq!(spool_files(&spool), 0);
+
+    // the closing boundary is missing.
+    let response = server.perform(
+        Request::post("/avatar")
+            .header("content-type", "multipart/form-data; boundary=BOUNDARY")
+            .body(&SPOOL_FORM[..SPOOL_FORM.len() - 16]),
+    )?;
+    assert_eq!(response.status(), 400);
+    assert_eq!(spool_files(&spool), 0);
+
+    let _ = std::fs::remove_dir_all(&spool);
+    Ok(())
+}
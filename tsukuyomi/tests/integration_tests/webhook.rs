@@ -0,0 +1,356 @@
+use {
+    futures01::{future, Future, Stream},
+    hmac::{Hmac, Mac},
+    http::{Response, StatusCode},
+    hyper::{service::service_fn, Body, Server},
+    sha2::Sha256,
+    std::{
+        net::SocketAddr,
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        time::{Duration, Instant},
+    },
+    tokio::runtime::Runtime,
+    tsukuyomi::contrib::webhook::{self, Delivery, DeliveryState, Dispatcher, Metrics},
+};
+
+const SECRET: &[u8] = b"whsec_test";
+
+#[derive(Debug, serde::Serialize)]
+struct Event {
+    id: &'static str,
+    #[serde(rename = "type")]
+    kind: &'static str,
+}
+
+#[derive(Debug, Default)]
+struct Received {
+    requests: Vec<(String, Vec<u8>)>,
+    active: usize,
+    max_active: usize,
+}
+
+/// Starts a stub endpoint that responds with the status codes in the order of `statuses`,
+/// and then with `200 OK`.
+fn start_stub(
+    rt: &mut Runtime,
+    statuses: Vec<StatusCode>,
+    latency: Duration,
+) -> (SocketAddr, Arc<Mutex<Received>>) {
+    let received = Arc::new(Mutex::new(Received::default()));
+    let count = Arc::new(AtomicUsize::new(0));
+    let statuses = Arc::new(statuses);
+
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve({
+        let received = received.clone();
+        move || {
+            let received = received.clone();
+            let count = count.clone();
+            let statuses = statuses.clone();
+            service_fn(move |request: http::Request<Body>| {
+                let received = received.clone();
+                let signature = request
+                    .headers()
+                    .get("webhook-signature")
+                    .map(|h| h.to_str().unwrap().to_owned())
+                    .unwrap_or_default();
+                let status = statuses
+                    .get(count.fetch_add(1, Ordering::SeqCst))
+                    .cloned()
+                    .unwrap_or(StatusCode::OK);
+                {
+                    let mut received = received.lock().unwrap();
+                    received.active += 1;
+                    received.max_active = std::cmp::max(received.max_active, received.active);
+                }
+                request
+                    .into_body()
+                    .concat2()
+                    .join(
+                        tokio_timer::Delay::new(Instant::now() + latency)
+                            .map_err(|_| unreachable!()),
+                    )
+                    .map(move |(body, ())| {
+                        let mut received = received.lock().unwrap();
+                        received.active -= 1;
+                        received.requests.push((signature, body.to_vec()));
+                        let mut response = Response::new(Body::empty());
+                        *response.status_mut() = status;
+                        response
+                    })
+            })
+        }
+    });
+    let addr = server.local_addr();
+    rt.spawn(server.map_err(|_| ()));
+    (addr, received)
+}
+
+fn wait_for(dispatcher: &Dispatcher, ids: &[usize]) -> Vec<Delivery> {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    loop {
+        let deliveries: Vec<_> = ids
+            .iter()
+            .map(|&id| dispatcher.delivery(id).expect("should be in the history"))
+            .collect();
+        if deliveries.iter().all(|delivery| match delivery.state {
+            DeliveryState::Delivered | DeliveryState::DeadLettered => true,
+            DeliveryState::Pending | DeliveryState::Retrying => false,
+        }) {
+            return deliveries;
+        }
+        assert!(Instant::now() < deadline, "timed out: {:?}", deliveries);
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+fn dispatch(rt: &mut Runtime, dispatcher: &Dispatcher, event: Event) -> Vec<usize> {
+    let dispatcher = dispatcher.clone();
+    rt.block_on(future::lazy(move || dispatcher.dispatch(&event)))
+        .expect("the event should be serialized")
+}
+
+fn verify(signature: &str, payload: &[u8]) {
+    let mut timestamp = None;
+    let mut v1 = None;
+    for pair in signature.split(',') {
+        match pair.split_at(pair.find('=').unwrap_or(0)) {
+            ("t", t) => timestamp = Some(t[1..].parse::<i64>().unwrap()),
+            ("v1", v) => v1 = Some(&v[1..]),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.expect("missing timestamp");
+    assert!((time::get_time().sec - timestamp).abs() < 60);
+
+    let mut mac = Hmac::<Sha256>::new_varkey(SECRET).unwrap();
+    mac.input(format!("{}.", timestamp).as_bytes());
+    mac.input(payload);
+    let expected: String = mac
+        .result()
+        .code()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    assert_eq!(v1, Some(&*expected));
+    assert_eq!(webhook::sign(SECRET, timestamp, payload), expected);
+}
+
+#[test]
+fn retries_until_success() {
+    let mut rt = Runtime::new().unwrap();
+    let (addr, received) = start_stub(
+        &mut rt,
+        vec![
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::INTERNAL_SERVER_ERROR,
+        ],
+        Duration::from_millis(0),
+    );
+
+    let dispatcher = Dispatcher::new()
+        .max_attempts(5)
+        .backoff(Duration::from_millis(10), Duration::from_millis(100));
+    dispatcher.subscribe(format!("http://{}/hooks", addr).parse().unwrap(), SECRET);
+
+    let ids = dispatch(
+        &mut rt,
+        &dispatcher,
+        Event {
+            id: "evt_1",
+            kind: "charge.succeeded",
+        },
+    );
+    assert_eq!(ids.len(), 1);
+
+    let deliveries = wait_for(&dispatcher, &ids);
+    assert_eq!(deliveries[0].state, DeliveryState::Delivered);
+    assert_eq!(deliveries[0].attempts, 3);
+    assert_eq!(
+        deliveries[0].last_error.as_ref().map(String::as_str),
+        Some("the subscriber responded with 500 Internal Server Error")
+    );
+    assert_eq!(
+        dispatcher.metrics(),
+        Metrics {
+            dispatched: 1,
+            attempts: 3,
+            retries: 2,
+            delivered: 1,
+            dead_lettered: 0,
+            in_flight: 0,
+        }
+    );
+
+    let received = received.lock().unwrap();
+    assert_eq!(received.requests.len(), 3);
+    for (signature, payload) in &received.requests {
+        assert_eq!(
+            &payload[..],
+            &br#"{"id":"evt_1","type":"charge.succeeded"}"#[..]
+        );
+        verify(signature, payload);
+    }
+}
+
+#[test]
+fn dead_letter_after_max_attempts() {
+    let mut rt = Runtime::new().unwrap();
+    let (addr, received) = start_stub(
+        &mut rt,
+        vec![StatusCode::BAD_GATEWAY; 10],
+        Duration::from_millis(0),
+    );
+
+    let dead_letters = Arc::new(Mutex::new(vec![]));
+    let dispatcher = Dispatcher::new()
+        .max_attempts(3)
+        .backoff(Duration::from_millis(5), Duration::from_millis(20))
+        .on_dead_letter({
+            let dead_letters = dead_letters.clone();
+            move |dead| dead_letters.lock().unwrap().push(dead.clone())
+        });
+    dispatcher.subscribe(format!("http://{}/hooks", addr).parse().unwrap(), SECRET);
+    // nobody listens on the port 9 (discard).
+    dispatcher.subscribe("http://127.0.0.1:9/hooks".parse().unwrap(), SECRET);
+
+    let event = Event {
+        id: "evt_2",
+        kind: "charge.failed",
+    };
+    let ids = dispatch(&mut rt, &dispatcher, event);
+    let deliveries = wait_for(&dispatcher, &ids);
+    assert!(deliveries
+        .iter()
+        .all(|delivery| delivery.state == DeliveryState::DeadLettered && delivery.attempts == 3));
+    assert_eq!(received.lock().unwrap().requests.len(), 3);
+
+    let dead_letters = dead_letters.lock().unwrap();
+    assert_eq!(dead_letters.len(), 2);
+    for dead in dead_letters.iter() {
+        assert_eq!(dead.attempts, 3);
+        assert_eq!(
+            &dead.payload[..],
+            &br#"{"id":"evt_2","type":"charge.failed"}"#[..]
+        );
+    }
+    let metrics = dispatcher.metrics();
+    assert_eq!(metrics.attempts, 6);
+    assert_eq!(metrics.retries, 4);
+    assert_eq!(metrics.dead_lettered, 2);
+    assert_eq!(metrics.delivered, 0);
+}
+
+#[test]
+fn bounded_concurrency() {
+    let mut rt = Runtime::new().unwrap();
+    let (addr, received) = start_stub(&mut rt, vec![], Duration::from_millis(50));
+
+    let dispatcher = Dispatcher::new().max_concurrency(2).history(3);
+    for i in 0..6 {
+        dispatcher.subscribe(
+            format!("http://{}/hooks/{}", addr, i).parse().unwrap(),
+            SECRET,
+        );
+    }
+
+    let ids = dispatch(
+        &mut rt,
+        &dispatcher,
+        Event {
+            id: "evt_3",
+            kind: "charge.refunded",
+        },
+    );
+    assert_eq!(ids.len(), 6);
+
+    // only the last three deliveries are kept in the history.
+    assert!(dispatcher.delivery(ids[0]).is_none());
+    let deliveries = wait_for(&dispatcher, &ids[3..]);
+    assert!(deliveries
+        .iter()
+        .all(|delivery| delivery.state == DeliveryState::Delivered));
+    assert_eq!(dispatcher.deliveries().len(), 3);
+
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while dispatcher.metrics().delivered < 6 {
+        assert!(Instant::now() < deadline, "timed out");
+        std::thread::sleep(Duration::from_millis(10));
+    }
+    let received = received.lock().unwrap();
+    assert_eq!(received.requests.len(), 6);
+    assert_eq!(received.max_active, 2);
+}
+
+#[test]
+fn close_shuts_down_the_worker() {
+    let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    let dead_letters = Arc::new(Mutex::new(vec![]));
+    let dispatcher = Dispatcher::new()
+        .max_attempts(5)
+        .backoff(Duration::from_secs(60), Duration::from_secs(60))
+        .on_dead_letter({
+            let dead_letters = dead_letters.clone();
+            move |dead| dead_letters.lock().unwrap().push(dead.clone())
+        });
+    // nobody listens on the port 9 (discard).
+    dispatcher.subscribe("http://127.0.0.1:9/hooks".parse().unwrap(), SECRET);
+
+    let ids = rt
+        .block_on(future::lazy(|| dispatcher.dispatch(&"evt_4")))
+        .unwrap();
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while dispatcher.delivery(ids[0]).unwrap().state != DeliveryState::Retrying {
+        assert!(Instant::now() < deadline, "timed out");
+        rt.block_on(tokio_timer::Delay::new(
+            Instant::now() + Duration::from_millis(10),
+        ))
+        .unwrap();
+    }
+
+    // the retry waiting for the backoff is given up, and the runtime becomes idle.
+    dispatcher.close();
+    rt.run().unwrap();
+
+    let delivery = dispatcher.delivery(ids[0]).unwrap();
+    assert_eq!(delivery.state, DeliveryState::DeadLettered);
+    assert_eq!(delivery.attempts, 1);
+    assert_eq!(dead_letters.lock().unwrap().len(), 1);
+    assert!(rt
+        .block_on(future::lazy(|| dispatcher.dispatch(&"evt_5")))
+        .is_err());
+}
+
+#[test]
+fn dead_letter_on_full_queue() {
+    let mut rt = tokio::runtime::current_thread::Runtime::new().unwrap();
+
+    let dispatcher = Dispatcher::new().max_attempts(1).queue_capacity(2);
+    for i in 0..4 {
+        dispatcher.subscribe(
+            format!("http://127.0.0.1:9/hooks/{}", i).parse().unwrap(),
+            SECRET,
+        );
+    }
+
+    // the worker does not run until the dispatch completes.
+    let ids = rt
+        .block_on(future::lazy(|| dispatcher.dispatch(&"evt_6")))
+        .unwrap();
+    for &id in &ids[2..] {
+        let delivery = dispatcher.delivery(id).unwrap();
+        assert_eq!(delivery.state, DeliveryState::DeadLettered);
+        assert_eq!(delivery.attempts, 0);
+        assert_eq!(
+            delivery.last_error.as_ref().map(String::as_str),
+            Some("the delivery queue is full")
+        );
+    }
+
+    // dropping the dispatcher also shuts down the worker.
+    drop(dispatcher);
+    rt.run().unwrap();
+}